@@ -422,6 +422,82 @@ impl Detection {
     pub fn center(&self) -> &[f64] {
         unsafe { &(*self.0).c }
     }
+    /// The corners of the tag in image pixel coordinates.
+    ///
+    /// The corners wrap counter-clockwise around the tag.
+    pub fn corners(&self) -> &[[f64; 2]; 4] {
+        unsafe { &(*self.0).p }
+    }
+
+    /// Estimate the 6-DOF pose of the tag relative to the camera.
+    ///
+    /// `tag_size` is the edge length of the tag's black border (in the units
+    /// desired for the translation, e.g. meters). The camera is assumed to be
+    /// an undistorted pinhole camera described by `intrinsics`.
+    ///
+    /// This calls `estimate_tag_pose()` from the April Tag library, which
+    /// returns the best of the two candidate poses with orthogonal iteration.
+    pub fn estimate_pose(&self, tag_size: f64, intrinsics: &CameraIntrinsics) -> Pose {
+        let mut info = apriltag_sys::apriltag_detection_info_t {
+            det: self.0,
+            tagsize: tag_size,
+            fx: intrinsics.fx,
+            fy: intrinsics.fy,
+            cx: intrinsics.cx,
+            cy: intrinsics.cy,
+        };
+        let mut raw_pose = apriltag_sys::apriltag_pose_t {
+            R: std::ptr::null_mut(),
+            t: std::ptr::null_mut(),
+        };
+        unsafe {
+            let error = apriltag_sys::estimate_tag_pose(&mut info, &mut raw_pose);
+            assert!(!raw_pose.R.is_null());
+            assert!(!raw_pose.t.is_null());
+
+            let mut rotation = [0.0; 9];
+            rotation.copy_from_slice((*raw_pose.R).data.as_slice(9));
+            let mut translation = [0.0; 3];
+            translation.copy_from_slice((*raw_pose.t).data.as_slice(3));
+
+            apriltag_sys::matd_destroy(raw_pose.R);
+            apriltag_sys::matd_destroy(raw_pose.t);
+
+            Pose {
+                rotation,
+                translation,
+                error,
+            }
+        }
+    }
+}
+
+/// Pinhole camera intrinsic parameters used for tag pose estimation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraIntrinsics {
+    /// Focal length in x direction (pixels)
+    pub fx: f64,
+    /// Focal length in y direction (pixels)
+    pub fy: f64,
+    /// Principal point x coordinate (pixels)
+    pub cx: f64,
+    /// Principal point y coordinate (pixels)
+    pub cy: f64,
+}
+
+/// The pose of a tag in the camera coordinate frame.
+///
+/// The tag coordinate frame has its origin at the tag center with the z axis
+/// pointing into the tag. A point `X` in tag coordinates is at `R*X + t` in
+/// camera coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose {
+    /// Rotation matrix `R` in row-major order.
+    pub rotation: [f64; 9],
+    /// Translation vector `t`, in the units of the tag size.
+    pub translation: [f64; 3],
+    /// Object-space error of the pose estimate.
+    pub error: f64,
 }
 
 impl std::fmt::Debug for Detection {
@@ -60,3 +60,62 @@ fn test_detect_standard_36h11() {
     let detections = td.detect(apriltag::ImageU8::inner(&im));
     assert!(detections.is_empty());
 }
+
+#[test]
+fn test_estimate_pose() {
+    let mut td = apriltag::Detector::new();
+    let tf = apriltag::Family::new_tag_standard_41h12();
+    td.add_family(tf);
+
+    let raw_td = td.as_mut();
+    raw_td.quad_decimate = 2.0;
+    raw_td.quad_sigma = 0.0;
+    raw_td.refine_edges = 1;
+    raw_td.decode_sharpening = 0.25;
+
+    let file_buf = include_bytes!("frame1.jpg");
+    let image = image::load_from_memory(file_buf).unwrap();
+    let rgb = convert_image::piston_to_frame(image).unwrap();
+
+    let dest = convert_image::convert::<_, Mono8>(&rgb).unwrap();
+    let im = apriltag::ImageU8Borrowed::view(&dest);
+    let detections = td.detect(apriltag::ImageU8::inner(&im));
+    assert_eq!(detections.len(), 1);
+    let det = &detections.as_slice()[0];
+
+    // The corners surround the center.
+    let corners = det.corners();
+    let mean_x = corners.iter().map(|c| c[0]).sum::<f64>() / 4.0;
+    let mean_y = corners.iter().map(|c| c[1]).sum::<f64>() / 4.0;
+    assert!((mean_x - det.center()[0]).abs() < 2.0);
+    assert!((mean_y - det.center()[1]).abs() < 2.0);
+
+    // Nominal intrinsics. The image is not calibrated, but the pose must still
+    // be a valid rigid transformation placing the tag in front of the camera.
+    let intrinsics = apriltag::CameraIntrinsics {
+        fx: 500.0,
+        fy: 500.0,
+        cx: 320.0,
+        cy: 240.0,
+    };
+    let tag_size = 0.05;
+    let pose = det.estimate_pose(tag_size, &intrinsics);
+    println!("pose: {:?}", pose);
+
+    let r = &pose.rotation;
+    for i in 0..3 {
+        for j in 0..3 {
+            let dot: f64 = (0..3).map(|k| r[i * 3 + k] * r[j * 3 + k]).sum();
+            let expected = if i == j { 1.0 } else { 0.0 };
+            assert!((dot - expected).abs() < 1e-6);
+        }
+    }
+    assert!(pose.translation[2] > 0.0);
+    assert!(pose.error >= 0.0);
+
+    // The translation scales linearly with the tag size.
+    let pose2 = det.estimate_pose(2.0 * tag_size, &intrinsics);
+    for i in 0..3 {
+        assert!((pose2.translation[i] - 2.0 * pose.translation[i]).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,160 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Context;
+use clap::{Parser, ValueEnum};
+
+use braid_april_cal::{
+    do_calibrate_system_from_board, BoardCalData, BoardCalOptions, BoardCameraData, BoardDetection,
+    BoardLayout, Fiducial3DCoords, FrameSync, WorldFrame,
+};
+use opencv_ros_camera::NamedIntrinsicParameters;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum SyncMode {
+    /// Detections with the same frame number are simultaneous.
+    FrameNumber,
+    /// Detections with the same timestamp are simultaneous.
+    Timestamp,
+}
+
+/// Calibrate the extrinsic parameters of multiple cameras from a moving
+/// board of April tags.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// YAML file with the board layout (`tag_size` and a list of `tags`
+    /// with `id`, `x` and `y`).
+    #[arg(long)]
+    board: PathBuf,
+
+    /// April tag detections CSV and camera intrinsics YAML for one camera.
+    ///
+    /// The detections CSV is as saved by Strand Camera or by
+    /// `apriltag-track-movie`. The camera name is taken from the intrinsics
+    /// YAML. Give this once per camera.
+    #[arg(long, num_args = 2, value_names = ["DETECTIONS_CSV", "INTRINSICS_YAML"], required = true)]
+    camera: Vec<PathBuf>,
+
+    /// How detections from different cameras are matched.
+    #[arg(long, value_enum, default_value_t = SyncMode::FrameNumber)]
+    sync: SyncMode,
+
+    /// Tolerance (in seconds) when matching detections by timestamp.
+    #[arg(long, default_value_t = 0.005)]
+    timestamp_tolerance: f64,
+
+    /// Use the coordinate frame of this camera as the world frame.
+    #[arg(long, conflicts_with = "fiducials")]
+    reference_camera: Option<String>,
+
+    /// CSV file with 3D coordinates of static fiducial tags defining the
+    /// world frame.
+    ///
+    /// If neither this nor `--reference-camera` is given, the first board
+    /// pose defines the world frame.
+    #[arg(long)]
+    fiducials: Option<PathBuf>,
+
+    /// Output filename. Saved as pymvg JSON if the extension is `.json`,
+    /// otherwise as flydra XML.
+    #[arg(long)]
+    output: PathBuf,
+}
+
+fn read_csv<T>(path: &PathBuf) -> anyhow::Result<Vec<T>>
+where
+    for<'de> T: serde::Deserialize<'de>,
+{
+    let rdr = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_path(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    let mut rows = Vec::new();
+    for row in rdr.into_deserialize() {
+        rows.push(row.with_context(|| format!("while parsing {}", path.display()))?);
+    }
+    Ok(rows)
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    let board_buf = std::fs::read_to_string(&cli.board)
+        .with_context(|| format!("opening {}", cli.board.display()))?;
+    let board: BoardLayout = serde_yaml::from_str(&board_buf)
+        .with_context(|| format!("while parsing {}", cli.board.display()))?;
+
+    let mut per_camera_2d = BTreeMap::new();
+    let mut intrinsics = BTreeMap::new();
+    for pair in cli.camera.chunks(2) {
+        let (csv_path, yaml_path) = (&pair[0], &pair[1]);
+
+        let yaml_buf =
+            std::fs::read(yaml_path).with_context(|| format!("opening {}", yaml_path.display()))?;
+        let named: NamedIntrinsicParameters<f64> =
+            opencv_ros_camera::from_ros_yaml(yaml_buf.as_slice())
+                .map_err(|e| anyhow::anyhow!("{e}"))
+                .with_context(|| format!("while parsing {}", yaml_path.display()))?;
+
+        let detections: Vec<BoardDetection> = read_csv(csv_path)?;
+        // Only Strand Camera saves the recording start time.
+        let created_at = std::fs::File::open(csv_path)
+            .ok()
+            .and_then(|fd| braid_april_cal::get_apriltag_cfg(fd).ok())
+            .map(|cfg| cfg.created_at);
+
+        log::info!("camera {}: {} detections", named.name, detections.len());
+        per_camera_2d.insert(
+            named.name.clone(),
+            BoardCameraData {
+                created_at,
+                detections,
+            },
+        );
+        intrinsics.insert(named.name.clone(), named);
+    }
+
+    let sync = match cli.sync {
+        SyncMode::FrameNumber => FrameSync::FrameNumber,
+        SyncMode::Timestamp => FrameSync::Timestamp {
+            tolerance_seconds: cli.timestamp_tolerance,
+        },
+    };
+
+    let world_frame = if let Some(name) = cli.reference_camera {
+        WorldFrame::Camera(name)
+    } else if let Some(path) = &cli.fiducials {
+        WorldFrame::Fiducials(read_csv::<Fiducial3DCoords>(path)?)
+    } else {
+        WorldFrame::FirstBoardPose
+    };
+
+    let src_data = BoardCalData {
+        board,
+        per_camera_2d,
+        intrinsics,
+        sync,
+        world_frame,
+    };
+
+    let cal = do_calibrate_system_from_board(&src_data, &BoardCalOptions::default())?;
+
+    println!("{} board poses", cal.num_board_poses);
+    for (cam_name, dist) in cal.mean_reproj_dist.iter() {
+        println!(
+            "{cam_name}: {} views, mean reprojection distance {dist:.3} pixels",
+            cal.num_views[cam_name]
+        );
+    }
+
+    let buf = if cli.output.extension().and_then(|e| e.to_str()) == Some("json") {
+        cal.to_pymvg_json()
+    } else {
+        cal.to_flydra_xml()
+    }?;
+    std::fs::write(&cli.output, buf)
+        .with_context(|| format!("writing {}", cli.output.display()))?;
+
+    Ok(())
+}
@@ -0,0 +1,999 @@
+//! Multi-camera extrinsic calibration from a moving board of April tags.
+//!
+//! A rigid board carrying April tags at known locations is moved through the
+//! tracking volume while all cameras record. Each camera's view of the board
+//! at one instant gives an initial board pose relative to that camera (from the
+//! homography between the board plane and the undistorted image). Instants
+//! seen by several cameras link the cameras together, giving initial camera
+//! extrinsics. Finally, all camera extrinsics and board poses are jointly
+//! refined by minimizing the reprojection error of the tag corners (a bundle
+//! adjustment with a robust Huber loss).
+//!
+//! Camera intrinsics are not estimated and must be known (e.g. from
+//! checkerboard calibration).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use camcal::homography::{find_homography, pose_from_homography};
+use nalgebra::{
+    DMatrix, DVector, Matrix3, Matrix6, Point2, Point3, Rotation3, UnitQuaternion, Vector2,
+    Vector3, Vector6,
+};
+use opencv_ros_camera::{NamedIntrinsicParameters, RosOpenCvIntrinsics};
+use serde::{Deserialize, Serialize};
+
+use crate::{Fiducial3DCoords, MyError};
+
+/// The location of one tag on a calibration board.
+///
+/// Board coordinates follow the April tag library convention: looking at the
+/// printed board, x points right, y points down and z points into the board.
+/// Tags must be printed upright, with their edges parallel to the board axes.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BoardTag {
+    pub id: u32,
+    /// x coordinate of the tag center
+    pub x: f64,
+    /// y coordinate of the tag center
+    pub y: f64,
+}
+
+/// The layout of tags on a calibration board.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BoardLayout {
+    /// Edge length of the black border of each tag.
+    ///
+    /// This sets the units (e.g. meters) of the resulting calibration.
+    pub tag_size: f64,
+    pub tags: Vec<BoardTag>,
+}
+
+impl BoardLayout {
+    /// A grid of `cols` by `rows` tags with consecutive IDs in row-major order.
+    ///
+    /// The tag centers are `spacing` apart and the first tag is centered at
+    /// the origin.
+    pub fn grid(first_id: u32, cols: u32, rows: u32, tag_size: f64, spacing: f64) -> Self {
+        let tags = (0..rows)
+            .flat_map(|row| {
+                (0..cols).map(move |col| BoardTag {
+                    id: first_id + row * cols + col,
+                    x: col as f64 * spacing,
+                    y: row as f64 * spacing,
+                })
+            })
+            .collect();
+        Self { tag_size, tags }
+    }
+
+    /// The four corners of a tag in board coordinates, in the order of
+    /// [BoardDetection::corners].
+    fn tag_corners(&self, tag: &BoardTag) -> [Vector3<f64>; 4] {
+        let s = self.tag_size / 2.0;
+        TAG_CORNERS.map(|(u, v)| Vector3::new(tag.x + u * s, tag.y + v * s, 0.0))
+    }
+}
+
+/// Tag coordinates of the corners, in the order of `apriltag_detection_t::p`.
+const TAG_CORNERS: [(f64, f64); 4] = [(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)];
+
+/// One April tag detection, as saved by Strand Camera or `apriltag-track-movie`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BoardDetection {
+    pub frame: usize,
+    /// Time since the start of the recording. Not saved by
+    /// `apriltag-track-movie`.
+    #[serde(default)]
+    pub time_microseconds: Option<i64>,
+    pub id: i32,
+    pub h00: f64,
+    pub h01: f64,
+    pub h02: f64,
+    pub h10: f64,
+    pub h11: f64,
+    pub h12: f64,
+    pub h20: f64,
+    pub h21: f64,
+}
+
+impl BoardDetection {
+    /// The four tag corners in pixel coordinates.
+    ///
+    /// These are computed from the homography in the same order as the April
+    /// tag library does.
+    pub fn corners(&self) -> [[f64; 2]; 4] {
+        TAG_CORNERS.map(|(u, v)| {
+            let x = self.h00 * u + self.h01 * v + self.h02;
+            let y = self.h10 * u + self.h11 * v + self.h12;
+            let w = self.h20 * u + self.h21 * v + 1.0;
+            [x / w, y / w]
+        })
+    }
+}
+
+/// The detections from one camera.
+#[derive(Debug, Clone)]
+pub struct BoardCameraData {
+    /// The start time of the recording. Required for [FrameSync::Timestamp].
+    pub created_at: Option<chrono::DateTime<chrono::Local>>,
+    pub detections: Vec<BoardDetection>,
+}
+
+/// How detections from different cameras are matched to the same instant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameSync {
+    /// Detections with the same frame number were taken at the same instant.
+    ///
+    /// This is appropriate for videos from synchronized cameras.
+    FrameNumber,
+    /// Detections whose timestamps (the recording start time plus the
+    /// detection time) are within the given tolerance were taken at the same
+    /// instant.
+    Timestamp { tolerance_seconds: f64 },
+}
+
+/// The coordinate frame of the resulting calibration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldFrame {
+    /// The coordinate frame of the named camera.
+    Camera(String),
+    /// The board coordinate frame at the first instant the board was seen by
+    /// at least two cameras.
+    FirstBoardPose,
+    /// Static tags (not on the board) with known 3D coordinates. These must be
+    /// seen by at least two cameras each, and at least three are required.
+    Fiducials(Vec<Fiducial3DCoords>),
+}
+
+pub struct BoardCalData {
+    pub board: BoardLayout,
+    pub per_camera_2d: BTreeMap<String, BoardCameraData>,
+    pub intrinsics: BTreeMap<String, NamedIntrinsicParameters<f64>>,
+    pub sync: FrameSync,
+    pub world_frame: WorldFrame,
+}
+
+/// Options for [do_calibrate_system_from_board].
+#[derive(Debug, Clone)]
+pub struct BoardCalOptions {
+    /// Views of the board with fewer tags than this are not used.
+    pub min_tags_per_view: usize,
+    /// Reprojection errors larger than this (in pixels) are down-weighted
+    /// with the Huber loss.
+    pub huber_threshold_pixels: f64,
+    /// Maximum number of Levenberg-Marquardt iterations.
+    pub max_iterations: usize,
+}
+
+impl Default for BoardCalOptions {
+    fn default() -> Self {
+        Self {
+            min_tags_per_view: 2,
+            huber_threshold_pixels: 2.0,
+            max_iterations: 100,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BoardCalibrationResult {
+    pub cam_system: mvg::MultiCameraSystem<f64>,
+    /// mean reprojection distance of the tag corners, per camera
+    pub mean_reproj_dist: BTreeMap<String, f64>,
+    /// number of board views used, per camera
+    pub num_views: BTreeMap<String, usize>,
+    /// number of board poses used
+    pub num_board_poses: usize,
+}
+
+impl BoardCalibrationResult {
+    pub fn to_flydra_xml(&self) -> Result<Vec<u8>, MyError> {
+        crate::to_flydra_xml(&self.cam_system)
+    }
+
+    pub fn to_pymvg_json(&self) -> Result<Vec<u8>, MyError> {
+        crate::to_pymvg_json(&self.cam_system)
+    }
+}
+
+/// A rigid transformation `x -> rot * x + t`.
+#[derive(Debug, Clone, Copy)]
+struct Rigid {
+    rot: Rotation3<f64>,
+    t: Vector3<f64>,
+}
+
+impl Rigid {
+    fn identity() -> Self {
+        Self {
+            rot: Rotation3::identity(),
+            t: Vector3::zeros(),
+        }
+    }
+
+    fn apply(&self, x: &Vector3<f64>) -> Vector3<f64> {
+        self.rot * x + self.t
+    }
+
+    /// The transformation applying `other` first, then `self`.
+    fn compose(&self, other: &Rigid) -> Rigid {
+        Rigid {
+            rot: self.rot * other.rot,
+            t: self.rot * other.t + self.t,
+        }
+    }
+
+    fn inverse(&self) -> Rigid {
+        let rot = self.rot.inverse();
+        Rigid {
+            rot,
+            t: -(rot * self.t),
+        }
+    }
+
+    /// Apply a small update: a rotation vector followed by a translation.
+    fn perturbed(&self, delta: &Vector6<f64>) -> Rigid {
+        let omega = Vector3::new(delta[0], delta[1], delta[2]);
+        Rigid {
+            rot: Rotation3::new(omega) * self.rot,
+            t: self.t + Vector3::new(delta[3], delta[4], delta[5]),
+        }
+    }
+
+    /// The position of the destination frame origin in source coordinates.
+    ///
+    /// For a camera pose, this is the camera center.
+    fn center(&self) -> Vector3<f64> {
+        -(self.rot.inverse() * self.t)
+    }
+}
+
+/// One camera's view of the board at one instant.
+struct View {
+    cam: usize,
+    instant: usize,
+    object_points: Vec<Vector3<f64>>,
+    image_points: Vec<Vector2<f64>>,
+    /// Board pose in this camera's frame, estimated from this view alone.
+    cam_from_board: Rigid,
+}
+
+/// Calibrate camera extrinsics from views of a moving board of April tags.
+pub fn do_calibrate_system_from_board(
+    src_data: &BoardCalData,
+    opts: &BoardCalOptions,
+) -> Result<BoardCalibrationResult, MyError> {
+    let board = &src_data.board;
+    if !(board.tag_size > 0.0) {
+        return Err(MyError {
+            msg: "board tag size must be positive".into(),
+        });
+    }
+    let mut board_tags = BTreeMap::new();
+    for tag in board.tags.iter() {
+        if board_tags.insert(tag.id, board.tag_corners(tag)).is_some() {
+            return Err(MyError {
+                msg: format!("multiple entries for ID {} in board layout", tag.id),
+            });
+        }
+    }
+
+    let cam_names: Vec<String> = src_data.per_camera_2d.keys().cloned().collect();
+    if cam_names.len() < 2 {
+        return Err(MyError {
+            msg: "at least two cameras are required".into(),
+        });
+    }
+    let mut intrinsics = Vec::with_capacity(cam_names.len());
+    for cam_name in cam_names.iter() {
+        let named = src_data.intrinsics.get(cam_name).ok_or_else(|| MyError {
+            msg: format!("Camera {}: no intrinsics", cam_name),
+        })?;
+        intrinsics.push(named);
+    }
+
+    // Assign each (camera, frame) to an instant.
+    let instant_of = assign_instants(src_data, &cam_names)?;
+
+    // Estimate the board pose in each view independently.
+    let mut views = Vec::new();
+    for (cam, cam_name) in cam_names.iter().enumerate() {
+        let cam_data = &src_data.per_camera_2d[cam_name];
+        let mut by_frame: BTreeMap<usize, Vec<&BoardDetection>> = BTreeMap::new();
+        for det in cam_data.detections.iter() {
+            by_frame.entry(det.frame).or_default().push(det);
+        }
+        let cam_intrinsics = &intrinsics[cam].intrinsics;
+        for (frame, dets) in by_frame.iter() {
+            let mut count_per_id: BTreeMap<i32, usize> = BTreeMap::new();
+            for det in dets.iter() {
+                *count_per_id.entry(det.id).or_default() += 1;
+            }
+            let mut object_points = Vec::new();
+            let mut image_points = Vec::new();
+            let mut num_tags = 0;
+            for det in dets.iter() {
+                // Ignore tags not on the board and ambiguous duplicates.
+                if count_per_id[&det.id] != 1 {
+                    continue;
+                }
+                let Some(corners) = u32::try_from(det.id)
+                    .ok()
+                    .and_then(|id| board_tags.get(&id))
+                else {
+                    continue;
+                };
+                object_points.extend_from_slice(corners);
+                image_points.extend(det.corners().iter().map(|c| Vector2::new(c[0], c[1])));
+                num_tags += 1;
+            }
+            if num_tags == 0 || num_tags < opts.min_tags_per_view {
+                continue;
+            }
+            let Some(cam_from_board) =
+                initial_view_pose(cam_intrinsics, &object_points, &image_points, opts)
+            else {
+                log::warn!(
+                    "Camera {}, frame {}: could not estimate board pose",
+                    cam_name,
+                    frame
+                );
+                continue;
+            };
+            views.push(View {
+                cam,
+                instant: instant_of[&(cam, *frame)],
+                object_points,
+                image_points,
+                cam_from_board,
+            });
+        }
+    }
+
+    // Only instants seen by at least two cameras constrain the extrinsics.
+    let mut cams_per_instant: BTreeMap<usize, usize> = BTreeMap::new();
+    for view in views.iter() {
+        *cams_per_instant.entry(view.instant).or_default() += 1;
+    }
+    views.retain(|view| cams_per_instant[&view.instant] >= 2);
+    let instants: BTreeSet<usize> = views.iter().map(|v| v.instant).collect();
+    let new_index: BTreeMap<usize, usize> = instants
+        .iter()
+        .enumerate()
+        .map(|(idx, instant)| (*instant, idx))
+        .collect();
+    for view in views.iter_mut() {
+        view.instant = new_index[&view.instant];
+    }
+    let num_instants = instants.len();
+    if num_instants == 0 {
+        return Err(MyError {
+            msg: "the board was never seen by two cameras at the same time".into(),
+        });
+    }
+
+    let mut num_views = vec![0; cam_names.len()];
+    for view in views.iter() {
+        num_views[view.cam] += 1;
+    }
+    let ref_cam = match &src_data.world_frame {
+        WorldFrame::Camera(name) => {
+            cam_names
+                .iter()
+                .position(|n| n == name)
+                .ok_or_else(|| MyError {
+                    msg: format!("world frame camera {} not found", name),
+                })?
+        }
+        _ => (0..cam_names.len()).max_by_key(|c| num_views[*c]).unwrap(),
+    };
+
+    let mut cam_from_ref = initial_extrinsics(&views, &cam_names, ref_cam, num_instants)?;
+    let mut ref_from_board = initial_board_poses(&views, &cam_from_ref, num_instants);
+
+    let all_intrinsics: Vec<&RosOpenCvIntrinsics<f64>> =
+        intrinsics.iter().map(|i| &i.intrinsics).collect();
+    let view_refs: Vec<&View> = views.iter().collect();
+    bundle_adjust(
+        &all_intrinsics,
+        &mut cam_from_ref,
+        ref_cam,
+        &mut ref_from_board,
+        &view_refs,
+        opts,
+    )?;
+
+    let mut mean_reproj_dist = BTreeMap::new();
+    for (cam, cam_name) in cam_names.iter().enumerate() {
+        let mut sum = 0.0;
+        let mut n = 0;
+        for view in views.iter().filter(|v| v.cam == cam) {
+            let pose = cam_from_ref[cam].compose(&ref_from_board[view.instant]);
+            for (x, px) in view.object_points.iter().zip(view.image_points.iter()) {
+                sum += (project(all_intrinsics[cam], &pose.apply(x)) - px).norm();
+                n += 1;
+            }
+        }
+        if n == 0 {
+            return Err(MyError {
+                msg: format!("Camera {}: no views of the board", cam_name),
+            });
+        }
+        mean_reproj_dist.insert(cam_name.clone(), sum / n as f64);
+    }
+
+    // Transform from the reference camera frame to the world frame.
+    let world_from_ref = match &src_data.world_frame {
+        WorldFrame::Camera(_) => Rigid::identity(),
+        WorldFrame::FirstBoardPose => ref_from_board[0].inverse(),
+        WorldFrame::Fiducials(coords) => {
+            let cams = build_cams(&cam_names, &intrinsics, &cam_from_ref, &Rigid::identity())?;
+            align_to_fiducials(src_data, &cams, coords)?
+        }
+    };
+
+    let cams = build_cams(&cam_names, &intrinsics, &cam_from_ref, &world_from_ref)?;
+    let cam_system = mvg::MultiCameraSystem::new(cams);
+
+    Ok(BoardCalibrationResult {
+        cam_system,
+        mean_reproj_dist,
+        num_views: cam_names.iter().cloned().zip(num_views).collect(),
+        num_board_poses: num_instants,
+    })
+}
+
+/// Map each (camera index, frame number) with detections to an instant index.
+fn assign_instants(
+    src_data: &BoardCalData,
+    cam_names: &[String],
+) -> Result<BTreeMap<(usize, usize), usize>, MyError> {
+    let mut result = BTreeMap::new();
+    match &src_data.sync {
+        FrameSync::FrameNumber => {
+            for (cam, cam_name) in cam_names.iter().enumerate() {
+                for det in src_data.per_camera_2d[cam_name].detections.iter() {
+                    result.insert((cam, det.frame), det.frame);
+                }
+            }
+        }
+        FrameSync::Timestamp { tolerance_seconds } => {
+            let mut stamped = Vec::new();
+            for (cam, cam_name) in cam_names.iter().enumerate() {
+                let cam_data = &src_data.per_camera_2d[cam_name];
+                let created_at = cam_data.created_at.ok_or_else(|| MyError {
+                    msg: format!("Camera {}: recording start time unknown", cam_name),
+                })?;
+                let t0 = created_at.timestamp() as f64
+                    + created_at.timestamp_subsec_nanos() as f64 * 1e-9;
+                let mut frames = BTreeMap::new();
+                for det in cam_data.detections.iter() {
+                    let us = det.time_microseconds.ok_or_else(|| MyError {
+                        msg: format!("Camera {}: detection time unknown", cam_name),
+                    })?;
+                    frames.insert(det.frame, t0 + us as f64 * 1e-6);
+                }
+                stamped.extend(frames.into_iter().map(|(frame, t)| (t, cam, frame)));
+            }
+            stamped.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            // Greedily group frames starting within the tolerance of the
+            // first frame of the group, with at most one frame per camera.
+            let mut instant = 0;
+            let mut group_start = f64::NEG_INFINITY;
+            let mut group_cams = BTreeSet::new();
+            for (t, cam, frame) in stamped {
+                if t - group_start > *tolerance_seconds || group_cams.contains(&cam) {
+                    instant += 1;
+                    group_start = t;
+                    group_cams.clear();
+                }
+                group_cams.insert(cam);
+                result.insert((cam, frame), instant);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Estimate the board pose from a single view and refine it.
+fn initial_view_pose(
+    intrinsics: &RosOpenCvIntrinsics<f64>,
+    object_points: &[Vector3<f64>],
+    image_points: &[Vector2<f64>],
+    opts: &BoardCalOptions,
+) -> Option<Rigid> {
+    let board_xy: Vec<Vector2<f64>> = object_points.iter().map(|p| p.xy()).collect();
+    let normalized: Vec<Vector2<f64>> = image_points
+        .iter()
+        .map(|p| normalized_coords(intrinsics, p))
+        .collect();
+    let h = find_homography(&board_xy, &normalized)?;
+    let (rot, t) = pose_from_homography(&h)?;
+    let pose = Rigid { rot, t };
+
+    let view = View {
+        cam: 0,
+        instant: 0,
+        object_points: object_points.to_vec(),
+        image_points: image_points.to_vec(),
+        cam_from_board: pose,
+    };
+    let mut cams = [Rigid::identity()];
+    let mut boards = [pose];
+    bundle_adjust(&[intrinsics], &mut cams, 0, &mut boards, &[&view], opts).ok()?;
+    let pose = boards[0];
+    object_points
+        .iter()
+        .all(|x| pose.apply(x).z > 0.0)
+        .then_some(pose)
+}
+
+/// Initial pose of each camera relative to the reference camera.
+///
+/// Starting from the reference camera, cameras are added one at a time. Each
+/// new camera's pose is taken from the instant, shared with an already posed
+/// camera, whose implied camera center is the median-like (medoid) of all
+/// such estimates.
+fn initial_extrinsics(
+    views: &[View],
+    cam_names: &[String],
+    ref_cam: usize,
+    num_instants: usize,
+) -> Result<Vec<Rigid>, MyError> {
+    let mut views_per_instant: Vec<Vec<&View>> = vec![Vec::new(); num_instants];
+    for view in views.iter() {
+        views_per_instant[view.instant].push(view);
+    }
+
+    let mut cam_from_ref: Vec<Option<Rigid>> = vec![None; cam_names.len()];
+    cam_from_ref[ref_cam] = Some(Rigid::identity());
+
+    loop {
+        let mut candidates: BTreeMap<usize, Vec<Rigid>> = BTreeMap::new();
+        for instant_views in views_per_instant.iter() {
+            for known in instant_views.iter() {
+                let Some(known_from_ref) = cam_from_ref[known.cam] else {
+                    continue;
+                };
+                let board_from_ref = known.cam_from_board.inverse().compose(&known_from_ref);
+                for other in instant_views.iter() {
+                    if cam_from_ref[other.cam].is_none() {
+                        candidates
+                            .entry(other.cam)
+                            .or_default()
+                            .push(other.cam_from_board.compose(&board_from_ref));
+                    }
+                }
+            }
+        }
+
+        let Some((cam, cands)) = candidates.into_iter().max_by_key(|(_, c)| c.len()) else {
+            break;
+        };
+        let centers: Vec<Vector3<f64>> = cands.iter().map(|c| c.center()).collect();
+        let medoid = (0..cands.len())
+            .min_by(|a, b| {
+                let cost =
+                    |i: usize| -> f64 { centers.iter().map(|c| (c - centers[i]).norm()).sum() };
+                cost(*a).total_cmp(&cost(*b))
+            })
+            .unwrap();
+        cam_from_ref[cam] = Some(cands[medoid]);
+    }
+
+    cam_from_ref
+        .into_iter()
+        .enumerate()
+        .map(|(cam, pose)| {
+            pose.ok_or_else(|| MyError {
+                msg: format!(
+                    "Camera {}: never saw the board at the same time as the other cameras",
+                    cam_names[cam]
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Initial board pose at each instant, from the view with the most points.
+fn initial_board_poses(views: &[View], cam_from_ref: &[Rigid], num_instants: usize) -> Vec<Rigid> {
+    let mut best: Vec<Option<&View>> = vec![None; num_instants];
+    for view in views.iter() {
+        let entry = &mut best[view.instant];
+        if entry.map_or(true, |b| b.object_points.len() < view.object_points.len()) {
+            *entry = Some(view);
+        }
+    }
+    best.into_iter()
+        .map(|view| {
+            let view = view.unwrap();
+            cam_from_ref[view.cam]
+                .inverse()
+                .compose(&view.cam_from_board)
+        })
+        .collect()
+}
+
+/// Compute the world frame from static fiducial tags with known coordinates.
+fn align_to_fiducials(
+    src_data: &BoardCalData,
+    cams: &BTreeMap<String, mvg::Camera<f64>>,
+    coords: &[Fiducial3DCoords],
+) -> Result<Rigid, MyError> {
+    let system = mvg::MultiCameraSystem::new(cams.clone());
+    let mut ref_points = Vec::new();
+    let mut world_points = Vec::new();
+    for fiducial in coords.iter() {
+        let mut upoints = Vec::new();
+        for (cam_name, cam_data) in src_data.per_camera_2d.iter() {
+            let (mut sumu, mut sumv, mut n) = (0.0, 0.0, 0);
+            for det in cam_data.detections.iter() {
+                if det.id as i64 == fiducial.id as i64 {
+                    sumu += det.h02;
+                    sumv += det.h12;
+                    n += 1;
+                }
+            }
+            if n == 0 {
+                continue;
+            }
+            let distorted = mvg::DistortedPixel {
+                coords: Point2::new(sumu / n as f64, sumv / n as f64),
+            };
+            let undistorted: mvg::UndistortedPixel<f64> = cams[cam_name]
+                .intrinsics()
+                .undistort(&(&distorted).into())
+                .into();
+            upoints.push((cam_name.clone(), undistorted));
+        }
+        if upoints.len() < 2 {
+            log::warn!(
+                "Fiducial {} seen by fewer than two cameras, ignoring",
+                fiducial.id
+            );
+            continue;
+        }
+        let pt = system.find3d(&upoints)?;
+        ref_points.push(pt.coords.coords);
+        world_points.push(Vector3::new(fiducial.x, fiducial.y, fiducial.z));
+    }
+    if ref_points.len() < 3 {
+        return Err(MyError {
+            msg: "at least three fiducials seen by two or more cameras are required".into(),
+        });
+    }
+    rigid_alignment(&ref_points, &world_points).ok_or_else(|| MyError {
+        msg: "could not align to fiducials (are they collinear?)".into(),
+    })
+}
+
+/// Least squares rigid transformation mapping `from` onto `to` (Kabsch).
+fn rigid_alignment(from: &[Vector3<f64>], to: &[Vector3<f64>]) -> Option<Rigid> {
+    let n = from.len() as f64;
+    let c_from = from.iter().fold(Vector3::zeros(), |acc, p| acc + p) / n;
+    let c_to = to.iter().fold(Vector3::zeros(), |acc, p| acc + p) / n;
+    let mut cov = Matrix3::zeros();
+    for (a, b) in from.iter().zip(to.iter()) {
+        cov += (b - c_to) * (a - c_from).transpose();
+    }
+    let svd = cov.svd(true, true);
+    if svd.singular_values[1] < 1e-12 * svd.singular_values[0].max(1e-300) {
+        return None;
+    }
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    let d = (u * v_t).determinant().signum();
+    let rot = u * Matrix3::from_diagonal(&Vector3::new(1.0, 1.0, d)) * v_t;
+    let rot = Rotation3::from_matrix_unchecked(rot);
+    Some(Rigid {
+        rot,
+        t: c_to - rot * c_from,
+    })
+}
+
+fn build_cams(
+    cam_names: &[String],
+    intrinsics: &[&NamedIntrinsicParameters<f64>],
+    cam_from_ref: &[Rigid],
+    world_from_ref: &Rigid,
+) -> Result<BTreeMap<String, mvg::Camera<f64>>, MyError> {
+    let ref_from_world = world_from_ref.inverse();
+    let mut cams = BTreeMap::new();
+    for (cam, cam_name) in cam_names.iter().enumerate() {
+        let cam_from_world = cam_from_ref[cam].compose(&ref_from_world);
+        let rquat = UnitQuaternion::from_rotation_matrix(&cam_from_world.rot);
+        let camcenter = Point3::from(cam_from_world.center());
+        let extrinsics =
+            cam_geom::ExtrinsicParameters::from_rotation_and_camcenter(rquat, camcenter);
+        let named = intrinsics[cam];
+        let final_cam = mvg::Camera::new(
+            named.width,
+            named.height,
+            extrinsics,
+            named.intrinsics.clone(),
+        )?;
+        cams.insert(cam_name.clone(), final_cam);
+    }
+    Ok(cams)
+}
+
+/// Project a point in camera coordinates to a (distorted) pixel.
+fn project(intrinsics: &RosOpenCvIntrinsics<f64>, pt: &Vector3<f64>) -> Vector2<f64> {
+    use cam_geom::IntrinsicParameters;
+    let pt = mvg::PointCameraFrame {
+        coords: Point3::from(*pt),
+    };
+    let px: mvg::DistortedPixel<f64> = intrinsics.camera_to_pixel(&(&pt).into()).into();
+    px.coords.coords
+}
+
+/// Undistort a pixel to normalized image coordinates.
+fn normalized_coords(intrinsics: &RosOpenCvIntrinsics<f64>, px: &Vector2<f64>) -> Vector2<f64> {
+    use cam_geom::IntrinsicParameters;
+    let distorted = mvg::DistortedPixel {
+        coords: Point2::from(*px),
+    };
+    let ray = intrinsics.pixel_to_camera(&(&distorted).into());
+    let pt = ray.point_on_ray_at_distance(1.0);
+    Vector2::new(
+        pt.data[(0, 0)] / pt.data[(0, 2)],
+        pt.data[(0, 1)] / pt.data[(0, 2)],
+    )
+}
+
+const DIFF_STEP: f64 = 1e-6;
+
+/// Reprojection residuals (predicted minus observed pixel coordinates).
+fn view_residuals(
+    intrinsics: &RosOpenCvIntrinsics<f64>,
+    cam_from_ref: &Rigid,
+    ref_from_board: &Rigid,
+    view: &View,
+    out: &mut Vec<f64>,
+) {
+    out.clear();
+    let pose = cam_from_ref.compose(ref_from_board);
+    for (x, px) in view.object_points.iter().zip(view.image_points.iter()) {
+        let r = project(intrinsics, &pose.apply(x)) - px;
+        out.push(r.x);
+        out.push(r.y);
+    }
+}
+
+/// Huber loss of a reprojection distance and the corresponding IRLS weight.
+fn huber(dist: f64, threshold: f64) -> (f64, f64) {
+    if dist <= threshold {
+        (0.5 * dist * dist, 1.0)
+    } else {
+        (threshold * (dist - 0.5 * threshold), threshold / dist)
+    }
+}
+
+fn total_cost(
+    intrinsics: &[&RosOpenCvIntrinsics<f64>],
+    cam_from_ref: &[Rigid],
+    ref_from_board: &[Rigid],
+    views: &[&View],
+    threshold: f64,
+) -> f64 {
+    let mut r = Vec::new();
+    let mut cost = 0.0;
+    for view in views.iter() {
+        view_residuals(
+            intrinsics[view.cam],
+            &cam_from_ref[view.cam],
+            &ref_from_board[view.instant],
+            view,
+            &mut r,
+        );
+        for xy in r.chunks_exact(2) {
+            cost += huber(xy[0].hypot(xy[1]), threshold).0;
+        }
+    }
+    cost
+}
+
+/// Numerical Jacobian of the view residuals with respect to a pose update.
+fn pose_jacobian(
+    residuals: impl Fn(&Rigid, &mut Vec<f64>),
+    pose: &Rigid,
+    n_rows: usize,
+) -> DMatrix<f64> {
+    let mut jac = DMatrix::<f64>::zeros(n_rows, 6);
+    let mut rp = Vec::new();
+    let mut rm = Vec::new();
+    for k in 0..6 {
+        let mut delta = Vector6::zeros();
+        delta[k] = DIFF_STEP;
+        residuals(&pose.perturbed(&delta), &mut rp);
+        delta[k] = -DIFF_STEP;
+        residuals(&pose.perturbed(&delta), &mut rm);
+        for row in 0..n_rows {
+            jac[(row, k)] = (rp[row] - rm[row]) / (2.0 * DIFF_STEP);
+        }
+    }
+    jac
+}
+
+/// Jointly refine camera and board poses with Levenberg-Marquardt.
+///
+/// The camera `fixed_cam` defines the reference frame and is not changed.
+/// Each board pose only affects the views at its instant, so the board poses
+/// are eliminated with the Schur complement and only a system the size of the
+/// camera parameters is solved at each step.
+fn bundle_adjust(
+    intrinsics: &[&RosOpenCvIntrinsics<f64>],
+    cam_from_ref: &mut [Rigid],
+    fixed_cam: usize,
+    ref_from_board: &mut [Rigid],
+    views: &[&View],
+    opts: &BoardCalOptions,
+) -> Result<(), MyError> {
+    let threshold = opts.huber_threshold_pixels;
+    let free_cams: Vec<usize> = (0..cam_from_ref.len())
+        .filter(|c| *c != fixed_cam)
+        .collect();
+    let cam_block: BTreeMap<usize, usize> = free_cams
+        .iter()
+        .enumerate()
+        .map(|(block, cam)| (*cam, block))
+        .collect();
+    let n_cam_params = 6 * free_cams.len();
+    let n_instants = ref_from_board.len();
+
+    let mut cost = total_cost(intrinsics, cam_from_ref, ref_from_board, views, threshold);
+    if !cost.is_finite() {
+        return Err(MyError {
+            msg: "bundle adjustment: initial cost is not finite".into(),
+        });
+    }
+    let mut lambda = 1e-3;
+    let mut r0 = Vec::new();
+
+    for _ in 0..opts.max_iterations {
+        let mut u = vec![Matrix6::<f64>::zeros(); free_cams.len()];
+        let mut g_cam = vec![Vector6::<f64>::zeros(); free_cams.len()];
+        let mut v = vec![Matrix6::<f64>::zeros(); n_instants];
+        let mut g_board = vec![Vector6::<f64>::zeros(); n_instants];
+        // Cross terms, per instant: (camera block, J_cam^T W J_board).
+        let mut w: Vec<Vec<(usize, Matrix6<f64>)>> = vec![Vec::new(); n_instants];
+
+        for view in views.iter() {
+            let cam_intrinsics = intrinsics[view.cam];
+            let cam_pose = cam_from_ref[view.cam];
+            let board_pose = ref_from_board[view.instant];
+            view_residuals(cam_intrinsics, &cam_pose, &board_pose, view, &mut r0);
+            let n_rows = r0.len();
+
+            // IRLS weights for the Huber loss.
+            let mut weights = DVector::<f64>::zeros(n_rows);
+            for (i, xy) in r0.chunks_exact(2).enumerate() {
+                let wt = huber(xy[0].hypot(xy[1]), threshold).1;
+                weights[2 * i] = wt;
+                weights[2 * i + 1] = wt;
+            }
+            let r = DVector::from_column_slice(&r0).component_mul(&weights);
+
+            let j_board = pose_jacobian(
+                |p, out| view_residuals(cam_intrinsics, &cam_pose, p, view, out),
+                &board_pose,
+                n_rows,
+            );
+            let j_board_w = weight_rows(&j_board, &weights);
+            v[view.instant] += to_matrix6(&(j_board.transpose() * &j_board_w));
+            g_board[view.instant] += to_vector6(&(j_board.transpose() * &r));
+
+            if let Some(&block) = cam_block.get(&view.cam) {
+                let j_cam = pose_jacobian(
+                    |p, out| view_residuals(cam_intrinsics, p, &board_pose, view, out),
+                    &cam_pose,
+                    n_rows,
+                );
+                u[block] += to_matrix6(&(j_cam.transpose() * weight_rows(&j_cam, &weights)));
+                g_cam[block] += to_vector6(&(j_cam.transpose() * &r));
+                w[view.instant].push((block, to_matrix6(&(j_cam.transpose() * &j_board_w))));
+            }
+        }
+
+        let mut improved = false;
+        while lambda < 1e12 {
+            let damp = |m: &Matrix6<f64>| {
+                let mut m = *m;
+                for d in 0..6 {
+                    m[(d, d)] += lambda * m[(d, d)].max(1e-12);
+                }
+                m
+            };
+
+            // Reduced camera system.
+            let mut s = DMatrix::<f64>::zeros(n_cam_params, n_cam_params);
+            let mut rhs = DVector::<f64>::zeros(n_cam_params);
+            for (block, (u_c, g_c)) in u.iter().zip(g_cam.iter()).enumerate() {
+                s.fixed_view_mut::<6, 6>(6 * block, 6 * block)
+                    .copy_from(&damp(u_c));
+                rhs.fixed_rows_mut::<6>(6 * block).copy_from(&(-g_c));
+            }
+            let mut v_inv = Vec::with_capacity(n_instants);
+            let mut singular = false;
+            for (i, v_i) in v.iter().enumerate() {
+                let Some(inv) = damp(v_i).try_inverse() else {
+                    singular = true;
+                    break;
+                };
+                for (ca, w_a) in w[i].iter() {
+                    let w_a_vinv = w_a * inv;
+                    let mut rhs_a = rhs.fixed_rows_mut::<6>(6 * ca);
+                    rhs_a += w_a_vinv * g_board[i];
+                    for (cb, w_b) in w[i].iter() {
+                        let mut s_ab = s.fixed_view_mut::<6, 6>(6 * ca, 6 * cb);
+                        s_ab -= w_a_vinv * w_b.transpose();
+                    }
+                }
+                v_inv.push(inv);
+            }
+            if singular {
+                lambda *= 10.0;
+                continue;
+            }
+            let delta_cams = if n_cam_params > 0 {
+                match s.cholesky() {
+                    Some(chol) => chol.solve(&rhs),
+                    None => {
+                        lambda *= 10.0;
+                        continue;
+                    }
+                }
+            } else {
+                rhs
+            };
+
+            let mut new_cams = cam_from_ref.to_vec();
+            for (block, cam) in free_cams.iter().enumerate() {
+                let d = delta_cams.fixed_rows::<6>(6 * block).into_owned();
+                new_cams[*cam] = cam_from_ref[*cam].perturbed(&d);
+            }
+            let mut new_boards = ref_from_board.to_vec();
+            for i in 0..n_instants {
+                let mut b = -g_board[i];
+                for (c, w_c) in w[i].iter() {
+                    b -= w_c.transpose() * delta_cams.fixed_rows::<6>(6 * c);
+                }
+                let d = v_inv[i] * b;
+                new_boards[i] = ref_from_board[i].perturbed(&d);
+            }
+
+            let new_cost = total_cost(intrinsics, &new_cams, &new_boards, views, threshold);
+            if new_cost.is_finite() && new_cost < cost {
+                let relative_decrease = (cost - new_cost) / cost;
+                cam_from_ref.copy_from_slice(&new_cams);
+                ref_from_board.copy_from_slice(&new_boards);
+                cost = new_cost;
+                lambda = (lambda * 0.1).max(1e-12);
+                improved = relative_decrease > 1e-12;
+                break;
+            }
+            lambda *= 10.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn weight_rows(jac: &DMatrix<f64>, weights: &DVector<f64>) -> DMatrix<f64> {
+    let mut out = jac.clone();
+    for (row, wt) in weights.iter().enumerate() {
+        out.row_mut(row).scale_mut(*wt);
+    }
+    out
+}
+
+fn to_matrix6(m: &DMatrix<f64>) -> Matrix6<f64> {
+    m.fixed_view::<6, 6>(0, 0).into_owned()
+}
+
+fn to_vector6(m: &DVector<f64>) -> Vector6<f64> {
+    m.fixed_rows::<6>(0).into_owned()
+}
@@ -10,6 +10,12 @@ use nalgebra::{
 
 use argmin::core::{CostFunction, Error as ArgminError};
 
+mod board;
+pub use board::{
+    do_calibrate_system_from_board, BoardCalData, BoardCalOptions, BoardCalibrationResult,
+    BoardCameraData, BoardDetection, BoardLayout, BoardTag, FrameSync, WorldFrame,
+};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AprilTagCorrespondingPoint<R: RealField> {
     pub id: i32,
@@ -212,22 +218,30 @@ pub struct CalibrationResult {
 
 impl CalibrationResult {
     pub fn to_flydra_xml(&self) -> Result<Vec<u8>, MyError> {
-        let flydra_cal =
-            flydra_mvg::FlydraMultiCameraSystem::<f64>::from_system(self.cam_system.clone(), None);
-
-        let mut xml_buf: Vec<u8> = Vec::new();
-        flydra_cal
-            .to_flydra_xml(&mut xml_buf)
-            .expect("to_flydra_xml");
-        Ok(xml_buf)
+        to_flydra_xml(&self.cam_system)
     }
 
     pub fn to_pymvg_json(&self) -> Result<Vec<u8>, MyError> {
-        let sys = self.cam_system.to_pymvg().unwrap();
-        Ok(serde_json::to_vec_pretty(&sys)?)
+        to_pymvg_json(&self.cam_system)
     }
 }
 
+fn to_flydra_xml(cam_system: &mvg::MultiCameraSystem<f64>) -> Result<Vec<u8>, MyError> {
+    let flydra_cal =
+        flydra_mvg::FlydraMultiCameraSystem::<f64>::from_system(cam_system.clone(), None);
+
+    let mut xml_buf: Vec<u8> = Vec::new();
+    flydra_cal
+        .to_flydra_xml(&mut xml_buf)
+        .expect("to_flydra_xml");
+    Ok(xml_buf)
+}
+
+fn to_pymvg_json(cam_system: &mvg::MultiCameraSystem<f64>) -> Result<Vec<u8>, MyError> {
+    let sys = cam_system.to_pymvg().unwrap();
+    Ok(serde_json::to_vec_pretty(&sys)?)
+}
+
 fn gather_points_per_cam(
     object_points: &BTreeMap<u32, [f64; 3]>,
     cam_data: &[DetectionSerializer],
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use braid_april_cal::*;
+use nalgebra::{Matrix3, Rotation3, Vector3};
+use opencv_ros_camera::{NamedIntrinsicParameters, RosOpenCvIntrinsics};
+
+/// Simple deterministic pseudo-random numbers in [-1,1).
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> f64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 11) as f64) / ((1u64 << 53) as f64) * 2.0 - 1.0
+    }
+}
+
+/// Generate detections of a board moving in front of three cameras.
+///
+/// Returns the calibration input and the camera centers in the coordinate
+/// frame of the first camera.
+fn gen_board_data(noise_pixels: f64) -> (BoardCalData, Vec<Vector3<f64>>) {
+    let (fx, fy, cx, cy) = (800.0, 800.0, 320.0, 240.0);
+    let k = Matrix3::new(fx, 0.0, cx, 0.0, fy, cy, 0.0, 0.0, 1.0);
+    let centers = [
+        Vector3::new(-0.8, 0.1, -2.0),
+        Vector3::new(0.9, -0.2, -2.2),
+        Vector3::new(0.0, 0.7, -1.8),
+    ];
+    // Each camera looks at the origin. Stored as (rotation, translation) of
+    // the camera-from-world transformation.
+    let cams: Vec<(Rotation3<f64>, Vector3<f64>)> = centers
+        .iter()
+        .map(|c| {
+            let rot = Rotation3::face_towards(&(-c), &Vector3::y()).inverse();
+            (rot, -(rot * c))
+        })
+        .collect();
+
+    let board = BoardLayout::grid(0, 3, 3, 0.1, 0.15);
+    let mut rng = Lcg(42);
+    let mut per_camera_2d = BTreeMap::new();
+    let mut intrinsics = BTreeMap::new();
+    for cam in 0..cams.len() {
+        let name = format!("cam{cam}");
+        per_camera_2d.insert(
+            name.clone(),
+            BoardCameraData {
+                created_at: None,
+                detections: Vec::new(),
+            },
+        );
+        intrinsics.insert(
+            name.clone(),
+            NamedIntrinsicParameters {
+                intrinsics: RosOpenCvIntrinsics::from_params(fx, 0.0, fy, cx, cy),
+                width: 640,
+                height: 480,
+                name,
+            },
+        );
+    }
+
+    for frame in 0..30 {
+        let board_rot =
+            Rotation3::from_scaled_axis(Vector3::new(rng.next(), rng.next(), rng.next()) * 0.35);
+        let board_t = Vector3::new(rng.next() * 0.15, rng.next() * 0.15, rng.next() * 0.3);
+        for (cam, (cam_rot, cam_t)) in cams.iter().enumerate() {
+            let rot = cam_rot * board_rot;
+            let t = cam_rot * board_t + cam_t;
+            let plane_to_cam = Matrix3::from_columns(&[
+                rot.matrix().column(0).into_owned(),
+                rot.matrix().column(1).into_owned(),
+                t,
+            ]);
+            let dets = &mut per_camera_2d
+                .get_mut(&format!("cam{cam}"))
+                .unwrap()
+                .detections;
+            for tag in board.tags.iter() {
+                let s = board.tag_size / 2.0;
+                let tag_to_plane = Matrix3::new(s, 0.0, tag.x, 0.0, s, tag.y, 0.0, 0.0, 1.0);
+                let h = k * plane_to_cam * tag_to_plane;
+                let h = h / h[(2, 2)];
+                dets.push(BoardDetection {
+                    frame,
+                    time_microseconds: None,
+                    id: tag.id as i32,
+                    h00: h[(0, 0)],
+                    h01: h[(0, 1)],
+                    h02: h[(0, 2)] + rng.next() * noise_pixels,
+                    h10: h[(1, 0)],
+                    h11: h[(1, 1)],
+                    h12: h[(1, 2)] + rng.next() * noise_pixels,
+                    h20: h[(2, 0)],
+                    h21: h[(2, 1)],
+                });
+            }
+        }
+    }
+
+    let (rot0, t0) = &cams[0];
+    let expected_centers = centers.iter().map(|c| rot0 * c + t0).collect();
+
+    let src_data = BoardCalData {
+        board,
+        per_camera_2d,
+        intrinsics,
+        sync: FrameSync::FrameNumber,
+        world_frame: WorldFrame::Camera("cam0".into()),
+    };
+    (src_data, expected_centers)
+}
+
+#[test]
+fn test_board_calibration() {
+    for (noise_pixels, tolerance) in [(0.0, 1e-6), (0.5, 0.01)] {
+        let (src_data, expected_centers) = gen_board_data(noise_pixels);
+        let cal_result =
+            do_calibrate_system_from_board(&src_data, &BoardCalOptions::default()).unwrap();
+        assert_eq!(cal_result.num_board_poses, 30);
+
+        for (cam, expected) in expected_centers.iter().enumerate() {
+            let cam_name = format!("cam{cam}");
+            let reproj_dist = cal_result.mean_reproj_dist[&cam_name];
+            println!("Camera {}: mean reproj dist: {}", cam_name, reproj_dist);
+            assert!(reproj_dist < noise_pixels + 1e-6);
+
+            let cam = cal_result.cam_system.cam_by_name(&cam_name).unwrap();
+            let actual = cam.extrinsics().camcenter().coords;
+            assert!(
+                (actual - expected).norm() < tolerance,
+                "camera {cam_name} at {actual:?}, expected {expected:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_board_calibration_xml() {
+    let (src_data, _) = gen_board_data(0.0);
+    let cal_result =
+        do_calibrate_system_from_board(&src_data, &BoardCalOptions::default()).unwrap();
+    let xml_buf = cal_result.to_flydra_xml().unwrap();
+
+    use flydra_mvg::FlydraMultiCameraSystem;
+    let loaded: FlydraMultiCameraSystem<f64> =
+        FlydraMultiCameraSystem::from_flydra_xml(xml_buf.as_slice()).unwrap();
+    assert_eq!(loaded.system().cams_by_name().len(), 3);
+}
@@ -158,6 +158,25 @@ pub struct MainbrainConfig {
     /// sending data to disk.
     #[serde(default = "default_write_buffer_size_num_messages")]
     pub write_buffer_size_num_messages: usize,
+    /// Duration without frames from a camera after which it is considered
+    /// disconnected (sec).
+    ///
+    /// A disconnected camera is removed from tracking, which continues with
+    /// the remaining cameras. When the camera connects again, it rejoins the
+    /// running synchronization. Set to `0` to disable detection of stalled
+    /// cameras.
+    #[serde(default = "default_camera_stale_timeout_secs")]
+    pub camera_stale_timeout_secs: f64,
+    /// Relaunch Strand Camera processes started by Braid if they crash or
+    /// stall.
+    #[serde(default = "default_true")]
+    pub relaunch_strand_cam: bool,
+    /// Bearer token required by the REST API at `/api/v1`.
+    ///
+    /// If not set, the token of the web UI is used. (If the web UI does not
+    /// require a token because it listens only on the loopback interface, the
+    /// REST API does not require one either.)
+    pub api_token: Option<String>,
 }
 
 impl std::default::Default for MainbrainConfig {
@@ -178,6 +197,9 @@ impl std::default::Default for MainbrainConfig {
             acquisition_duration_allowed_imprecision_msec:
                 flydra_types::DEFAULT_ACQUISITION_DURATION_ALLOWED_IMPRECISION_MSEC,
             write_buffer_size_num_messages: default_write_buffer_size_num_messages(),
+            camera_stale_timeout_secs: default_camera_stale_timeout_secs(),
+            relaunch_strand_cam: true,
+            api_token: None,
         }
     }
 }
@@ -186,6 +208,10 @@ pub const fn default_write_buffer_size_num_messages() -> usize {
     10000
 }
 
+const fn default_camera_stale_timeout_secs() -> f64 {
+    5.0
+}
+
 /// The Braid configuration format used in [the Braid configuration `TOML`
 /// file](https://strawlab.github.io/strand-braid/braid_configuration_and_launching.html).
 ///
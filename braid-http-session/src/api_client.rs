@@ -0,0 +1,189 @@
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{header, Method, StatusCode};
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use serde::de::DeserializeOwned;
+use tracing::debug;
+
+use flydra_types::braid_api_v1::{
+    AnnotationResponse, CameraInfo, ErrorResponse, ExperimentUuidRequest, NewAnnotation,
+    PostTriggerBufferRequest, RecordingRequest, StatusResponse, TrackingParams,
+    TrackingParamsUpdate, ANNOTATIONS_PATH, API_V1_PREFIX, CAMERAS_PATH, EXPERIMENT_UUID_PATH,
+    POST_TRIGGER_BUFFER_PATH, POST_TRIGGER_MP4_PATH, RECORDING_BRAIDZ_PATH, RECORDING_MP4_PATH,
+    STATUS_PATH, TRACKING_PARAMS_PATH,
+};
+
+use crate::Error;
+
+/// Client for version 1 of the Braid REST API.
+///
+/// See [flydra_types::braid_api_v1] for a description of the API.
+#[derive(Clone, Debug)]
+pub struct BraidApiClient {
+    /// The URL of the Braid HTTP server, ending with `/`.
+    base_url: String,
+    token: Option<String>,
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl BraidApiClient {
+    /// Create a client for Braid running at `braid_url`.
+    ///
+    /// `braid_url` is the URL printed by Braid at startup, e.g.
+    /// `http://127.0.0.1:33333/?token=abc`. If `token` is `None`, the `token`
+    /// query parameter of this URL is used as the bearer token, if present.
+    pub fn new(braid_url: &str, token: Option<String>) -> Result<Self, Error> {
+        let uri: hyper::Uri = braid_url.parse()?;
+        let authority = uri
+            .authority()
+            .ok_or_else(|| Error::InvalidUrl(braid_url.to_string()))?;
+        let scheme = uri.scheme_str().unwrap_or("http");
+        let token = token.or_else(|| {
+            uri.query().and_then(|query| {
+                query
+                    .split('&')
+                    .find_map(|kv| kv.strip_prefix("token=").map(String::from))
+            })
+        });
+        let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build_http();
+        Ok(Self {
+            base_url: format!("{scheme}://{authority}/"),
+            token,
+            client,
+        })
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<Bytes, Error> {
+        let url = format!("{}{}/{}", self.base_url, API_V1_PREFIX, path);
+        let mut builder = hyper::Request::builder().method(method).uri(&url);
+        if let Some(token) = &self.token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let body = match body {
+            Some(body) => {
+                builder = builder.header(header::CONTENT_TYPE, "application/json");
+                Full::new(Bytes::from(body))
+            }
+            None => Full::new(Bytes::new()),
+        };
+        let req = builder.body(body)?;
+
+        debug!("API request {} {}", req.method(), url);
+        let resp = self.client.request(req).await?;
+        let status = resp.status();
+        let data = resp.into_body().collect().await?.to_bytes();
+
+        if !status.is_success() {
+            // Use the message of the error response if possible.
+            let message = serde_json::from_slice::<ErrorResponse>(&data)
+                .map(|e| e.message)
+                .unwrap_or_else(|_| String::from_utf8_lossy(&data).into_owned());
+            return Err(Error::ApiError { status, message });
+        }
+        Ok(data)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let data = self.request(Method::GET, path, None).await?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    async fn put<T: serde::Serialize>(&self, path: &str, value: &T) -> Result<(), Error> {
+        let body = serde_json::to_vec(value)?;
+        self.request(Method::PUT, path, Some(body)).await?;
+        Ok(())
+    }
+
+    /// Get the recording state of Braid.
+    pub async fn status(&self) -> Result<StatusResponse, Error> {
+        self.get(STATUS_PATH).await
+    }
+
+    /// List the connected cameras.
+    pub async fn cameras(&self) -> Result<Vec<CameraInfo>, Error> {
+        self.get(CAMERAS_PATH).await
+    }
+
+    /// Start or stop recording a `.braidz` file.
+    pub async fn set_braidz_recording(&self, recording: bool) -> Result<(), Error> {
+        self.put(RECORDING_BRAIDZ_PATH, &RecordingRequest { recording })
+            .await
+    }
+
+    /// Start or stop recording MP4 files on all cameras.
+    pub async fn set_mp4_recording(&self, recording: bool) -> Result<(), Error> {
+        self.put(RECORDING_MP4_PATH, &RecordingRequest { recording })
+            .await
+    }
+
+    /// Set the UUID in the `experiment_info` table of the current recording.
+    ///
+    /// This fails with status `409 Conflict` if no `.braidz` file is being
+    /// recorded.
+    pub async fn set_experiment_uuid(&self, uuid: &str) -> Result<(), Error> {
+        let req = ExperimentUuidRequest {
+            uuid: uuid.to_string(),
+        };
+        self.put(EXPERIMENT_UUID_PATH, &req).await
+    }
+
+    /// Set the number of frames buffered in each camera for post-triggering.
+    pub async fn set_post_trigger_buffer_size(&self, num_frames: usize) -> Result<(), Error> {
+        self.put(
+            POST_TRIGGER_BUFFER_PATH,
+            &PostTriggerBufferRequest { num_frames },
+        )
+        .await
+    }
+
+    /// Start MP4 recording on all cameras, including the buffered frames.
+    pub async fn post_trigger_mp4(&self) -> Result<(), Error> {
+        self.request(Method::POST, POST_TRIGGER_MP4_PATH, None)
+            .await?;
+        Ok(())
+    }
+
+    /// Save an annotation in the `annotations` table of the current recording.
+    ///
+    /// This fails with status `409 Conflict` if no `.braidz` file is being
+    /// recorded.
+    pub async fn add_annotation(
+        &self,
+        annotation: &NewAnnotation,
+    ) -> Result<AnnotationResponse, Error> {
+        let body = serde_json::to_vec(annotation)?;
+        let data = self
+            .request(Method::POST, ANNOTATIONS_PATH, Some(body))
+            .await?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Get the tracking parameters currently used for new objects.
+    pub async fn tracking_params(&self) -> Result<TrackingParams, Error> {
+        self.get(TRACKING_PARAMS_PATH).await
+    }
+
+    /// Change the tracking parameters while tracking.
+    ///
+    /// This fails with status `400 Bad Request` if the change is not possible
+    /// while tracking.
+    pub async fn set_tracking_params(&self, update: &TrackingParamsUpdate) -> Result<(), Error> {
+        self.put(TRACKING_PARAMS_PATH, update).await
+    }
+}
+
+impl Error {
+    /// The HTTP status code, if the error is a response from the API.
+    pub fn api_status(&self) -> Option<StatusCode> {
+        match self {
+            Error::ApiError { status, .. } => Some(*status),
+            Error::HttpError(status, _) => Some(*status),
+            _ => None,
+        }
+    }
+}
@@ -15,8 +15,24 @@ pub enum Error {
     BuiBackendSession(#[from] bui_backend_session::Error),
     #[error("HTTP error {0} when calling {1}")]
     HttpError(hyper::StatusCode, String),
+    #[error("{0}")]
+    HyperUtilError(#[from] hyper_util::client::legacy::Error),
+    #[error("{0}")]
+    HttpRequestError(#[from] http::Error),
+    #[error("{0}")]
+    InvalidUri(#[from] http::uri::InvalidUri),
+    #[error("invalid URL \"{0}\"")]
+    InvalidUrl(String),
+    #[error("API error {status}: {message}")]
+    ApiError {
+        status: hyper::StatusCode,
+        message: String,
+    },
 }
 
+mod api_client;
+pub use api_client::BraidApiClient;
+
 /// Create a `MainbrainSession` which has already made a request
 #[tracing::instrument(level = "info")]
 pub async fn create_mainbrain_session(
@@ -89,6 +105,39 @@ impl MainbrainSession {
         >(&data)?)
     }
 
+    /// Wait for commands for the `braid-agent` named `agent_name`.
+    ///
+    /// Braid holds this request open until commands are available or a timeout
+    /// elapses, in which case the returned `Vec` is empty.
+    pub async fn get_agent_commands(
+        &mut self,
+        agent_name: &str,
+    ) -> Result<Vec<flydra_types::AgentCommand>, Error> {
+        let path = format!(
+            "{}/{}",
+            flydra_types::braid_http::AGENT_COMMANDS_PATH,
+            flydra_types::braid_http::encode_agent_name(agent_name)
+        );
+
+        let resp = self.inner.get(&path).await?;
+
+        if !resp.status().is_success() {
+            error!("error: GET was not a success {}:{}", file!(), line!());
+            return Err(Error::HttpError(resp.status(), path));
+        };
+
+        let body = resp.into_body();
+        let chunks: Result<http_body_util::Collected<bytes::Bytes>, hyper::Error> = {
+            use http_body_util::BodyExt;
+            body.collect().await
+        };
+        let data = chunks?.to_bytes();
+
+        Ok(serde_json::from_slice::<Vec<flydra_types::AgentCommand>>(
+            &data,
+        )?)
+    }
+
     #[tracing::instrument(skip_all)]
     pub async fn post_callback_message(
         &mut self,
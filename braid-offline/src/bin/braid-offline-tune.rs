@@ -0,0 +1,25 @@
+use clap::Parser;
+use color_eyre::eyre::{self as anyhow};
+use tracing_futures::Instrument;
+
+fn main() -> anyhow::Result<()> {
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "braid_offline=info,flydra2=warn,warn");
+    }
+
+    let _tracing_guard = env_tracing_logger::init();
+
+    let opt = braid_offline::tune::TuneCli::parse();
+
+    let future = async { braid_offline::tune::braid_offline_tune(opt).await };
+    let instrumented = future.instrument(tracing::info_span!("braid-offline-tune"));
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .worker_threads(2)
+        .thread_name("braid-offline-tune")
+        .build()?;
+
+    rt.block_on(instrumented)?;
+    Ok(())
+}
@@ -19,7 +19,7 @@ use flydra2::{
     FrameDataAndPoints, NumberedRawUdpPoint, StreamItem,
 };
 use flydra_types::{
-    CamInfoRow, PerCamSaveData, RawCamName, SyncFno, TrackingParams,
+    CamInfoRow, PerCamSaveData, RawCamName, SyncFno, TrackingParams, TrackingParamsUpdate,
     FEATURE_DETECT_SETTINGS_DIRNAME, IMAGES_DIRNAME,
 };
 use groupby::{AscendingGroupIter, BufferedSortIter};
@@ -27,6 +27,11 @@ use groupby::{AscendingGroupIter, BufferedSortIter};
 #[cfg(feature = "backtrace")]
 use std::backtrace::Backtrace;
 
+#[doc(hidden)]
+pub mod rng;
+pub mod tracking_metrics;
+pub mod tune;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("{source}")]
@@ -84,6 +89,13 @@ pub enum Error {
         #[cfg(feature = "backtrace")]
         backtrace: Backtrace,
     },
+    #[error("cannot change tracking parameters at frame {frame}: {msg}")]
+    InvalidTrackingParamsUpdate {
+        frame: u64,
+        msg: &'static str,
+        #[cfg(feature = "backtrace")]
+        backtrace: Backtrace,
+    },
     #[error("{source}")]
     JoinError {
         #[from]
@@ -208,6 +220,10 @@ pub struct KalmanizeOptions {
     pub start_frame: Option<u64>,
     pub stop_frame: Option<u64>,
     pub model_server_addr: Option<String>,
+    /// Changes of the tracking parameters to apply prior to tracking the given
+    /// frames, e.g. from the `tracking_params_updates` table of the original
+    /// recording.
+    pub tracking_params_updates: Vec<(SyncFno, TrackingParamsUpdate)>,
 }
 
 /// Perform offline tracking on the data
@@ -374,12 +390,24 @@ where
             mini_arena_debug_image_dir,
             write_buffer_size_num_messages:
                 braid_config_data::default_write_buffer_size_num_messages(),
+            per_observation_timestamps: false,
         },
         cam_manager.clone(),
         Some(recon.clone()),
         metadata_builder.clone(),
     )?;
 
+    for (frame, update) in opt2.tracking_params_updates.iter() {
+        coord_processor
+            .schedule_tracking_params_update(*frame, update.clone())
+            .map_err(|msg| Error::InvalidTrackingParamsUpdate {
+                frame: frame.0,
+                msg,
+                #[cfg(feature = "backtrace")]
+                backtrace: std::backtrace::Backtrace::capture(),
+            })?;
+    }
+
     let images_dirname = data_src.path_starter().join(IMAGES_DIRNAME);
     let mut found_image_paths: Vec<_> = match images_dirname.list_paths() {
         Ok(paths) => paths,
@@ -783,29 +811,17 @@ pub fn pick_csvgz_or_csv(csv_path: &Path) -> flydra2::Result<Box<dyn Read>> {
     }
 }
 
-/// This is our "real" main top-level function but we have some decoration we
-/// need to do in [main], so we name this differently.
-#[tracing::instrument(level = "debug", skip_all)]
-pub async fn braid_offline_retrack(opt: Cli) -> anyhow::Result<()> {
-    let data_src =
-        braidz_parser::incremental_parser::IncrementalParser::open(opt.data_src.as_path())
-            .with_context(|| {
-                format!(
-                    "while opening file \"{}\"",
-                    opt.data_src.as_path().display()
-                )
-            })?;
-    let data_src = data_src.parse_basics().with_context(|| {
-        format!(
-            "when parsing braidz file \"{}\"",
-            opt.data_src.as_path().display()
-        )
-    })?;
-
-    let cam_info = &data_src.basic_info().cam_info;
-
-    let tracking_params: flydra_types::TrackingParams = match opt.tracking_params {
-        Some(ref fname) => {
+/// Get the tracking parameters from the file `fname`, if given, or otherwise
+/// from the data source. If the data source has none, use default parameters
+/// for the number of cameras.
+pub(crate) fn get_tracking_params(
+    fname: Option<&Path>,
+    basic_info: &braidz_parser::incremental_parser::BasicInfoParsed,
+) -> anyhow::Result<TrackingParams> {
+    let cam_info = &basic_info.cam_info;
+
+    let tracking_params: flydra_types::TrackingParams = match fname {
+        Some(fname) => {
             info!("reading tracking parameters from file {}", fname.display());
             // read the traking parameters
             let buf = std::fs::read_to_string(fname)
@@ -822,29 +838,89 @@ pub async fn braid_offline_retrack(opt: Cli) -> anyhow::Result<()> {
             }
             tracking_params
         }
-        None => {
-            let parsed = data_src.basic_info();
-            match parsed.tracking_params.clone() {
-                Some(tp) => tp,
-                None => {
-                    let num_cams = data_src.basic_info().cam_info.camid2camn.len();
-                    match num_cams {
-                        0 => {
-                            anyhow::bail!(
-                                "No tracking parameters specified, none found in \
+        None => match basic_info.tracking_params.clone() {
+            Some(tp) => tp,
+            None => {
+                let num_cams = cam_info.camid2camn.len();
+                match num_cams {
+                    0 => {
+                        anyhow::bail!(
+                            "No tracking parameters specified, none found in \
                             data_src, and no default is reasonable because zero cameras present."
-                            )
-                        }
-                        1 => flydra_types::default_tracking_params_flat_3d(),
-                        _ => flydra_types::default_tracking_params_full_3d(),
+                        )
                     }
+                    1 => flydra_types::default_tracking_params_flat_3d(),
+                    _ => flydra_types::default_tracking_params_full_3d(),
                 }
             }
-        }
+        },
     };
+    Ok(tracking_params)
+}
+
+/// Get the changes of the tracking parameters recorded in the data source.
+///
+/// If tracking parameters are given in the file `fname`, they are used for the
+/// entire recording and recorded changes are ignored.
+pub(crate) fn get_tracking_params_updates(
+    fname: Option<&Path>,
+    basic_info: &braidz_parser::incremental_parser::BasicInfoParsed,
+) -> anyhow::Result<Vec<(SyncFno, TrackingParamsUpdate)>> {
+    let rows = &basic_info.tracking_params_updates;
+    if fname.is_some() {
+        if !rows.is_empty() {
+            warn!(
+                "Ignoring {} recorded change(s) of tracking parameters because \
+                tracking parameters were given.",
+                rows.len()
+            );
+        }
+        return Ok(vec![]);
+    }
+    rows.iter()
+        .map(|row| {
+            let update = row.update().with_context(|| {
+                format!(
+                    "decoding tracking parameters changed at frame {}",
+                    row.frame
+                )
+            })?;
+            info!(
+                "Will change tracking parameters at frame {} for {:?}",
+                row.frame, update.scope
+            );
+            Ok((row.frame, update))
+        })
+        .collect()
+}
+
+/// This is our "real" main top-level function but we have some decoration we
+/// need to do in [main], so we name this differently.
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn braid_offline_retrack(opt: Cli) -> anyhow::Result<()> {
+    let data_src =
+        braidz_parser::incremental_parser::IncrementalParser::open(opt.data_src.as_path())
+            .with_context(|| {
+                format!(
+                    "while opening file \"{}\"",
+                    opt.data_src.as_path().display()
+                )
+            })?;
+    let data_src = data_src.parse_basics().with_context(|| {
+        format!(
+            "when parsing braidz file \"{}\"",
+            opt.data_src.as_path().display()
+        )
+    })?;
+
+    let tracking_params =
+        get_tracking_params(opt.tracking_params.as_deref(), data_src.basic_info())?;
+    let tracking_params_updates =
+        get_tracking_params_updates(opt.tracking_params.as_deref(), data_src.basic_info())?;
     let opts = KalmanizeOptions {
         start_frame: opt.start_frame,
         stop_frame: opt.stop_frame,
+        tracking_params_updates,
         ..Default::default()
     };
 
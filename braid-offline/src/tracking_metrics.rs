@@ -0,0 +1,382 @@
+//! Evaluate tracking results against known ground truth.
+//!
+//! The metrics follow the CLEAR MOT metrics (MOTA, Bernardin and Stiefelhagen,
+//! 2008) and the identity metrics (IDF1, Ristani et al., 2016). A ground truth
+//! position and an estimated position can only correspond if they are within
+//! a maximum distance of each other.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+/// The position of an object in a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectPosition {
+    pub frame: u64,
+    /// The identity of the object (ground truth ID or tracker `obj_id`).
+    pub id: u32,
+    /// The 3D position, in meters.
+    pub pos: [f64; 3],
+}
+
+impl From<&flydra_types::KalmanEstimatesRow> for ObjectPosition {
+    fn from(row: &flydra_types::KalmanEstimatesRow) -> Self {
+        Self {
+            frame: row.frame.0,
+            id: row.obj_id,
+            pos: [row.x, row.y, row.z],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrackingMetrics {
+    /// The number of ground truth positions.
+    pub num_ground_truth: usize,
+    /// The number of estimated positions.
+    pub num_estimates: usize,
+    /// The number of ground truth positions with a corresponding estimate.
+    pub num_matches: usize,
+    /// The number of ground truth positions without a corresponding estimate.
+    pub num_misses: usize,
+    /// The number of estimated positions without corresponding ground truth.
+    pub num_false_positives: usize,
+    /// The number of times a ground truth object corresponds to a different
+    /// estimated object than previously.
+    pub num_id_switches: usize,
+    /// Multiple object tracking accuracy.
+    pub mota: f64,
+    /// F1 score of the correctly identified positions.
+    pub idf1: f64,
+    /// The root mean square distance between corresponding ground truth and
+    /// estimated positions, in meters.
+    pub rms_error: f64,
+}
+
+impl std::fmt::Display for TrackingMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "MOTA {:.4}, IDF1 {:.4}, RMS error {:.5} m, {} ID switches, \
+            {} misses, {} false positives ({} ground truth, {} estimates)",
+            self.mota,
+            self.idf1,
+            self.rms_error,
+            self.num_id_switches,
+            self.num_misses,
+            self.num_false_positives,
+            self.num_ground_truth,
+            self.num_estimates,
+        )
+    }
+}
+
+/// Cost of an assignment which is not allowed.
+const FORBIDDEN_COST: f64 = 1e12;
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| (a - b).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+fn by_frame(positions: &[ObjectPosition]) -> BTreeMap<u64, Vec<&ObjectPosition>> {
+    let mut result: BTreeMap<u64, Vec<&ObjectPosition>> = BTreeMap::new();
+    for pos in positions.iter() {
+        result.entry(pos.frame).or_default().push(pos);
+    }
+    result
+}
+
+/// Compute tracking metrics of `estimates` given the `ground_truth`.
+///
+/// `max_distance` is the largest distance, in meters, at which an estimate can
+/// correspond to a ground truth position.
+pub fn compute_tracking_metrics(
+    ground_truth: &[ObjectPosition],
+    estimates: &[ObjectPosition],
+    max_distance: f64,
+) -> TrackingMetrics {
+    let gt_by_frame = by_frame(ground_truth);
+    let est_by_frame = by_frame(estimates);
+    let frames: BTreeSet<u64> = gt_by_frame
+        .keys()
+        .chain(est_by_frame.keys())
+        .copied()
+        .collect();
+
+    let no_positions = Vec::new();
+    let mut num_matches = 0;
+    let mut num_id_switches = 0;
+    let mut sum_sq_error = 0.0;
+    // The estimated ID last corresponding to each ground truth ID.
+    let mut last_match: BTreeMap<u32, u32> = BTreeMap::new();
+    // For each pair of ground truth ID and estimated ID, the number of frames
+    // in which they are close enough to correspond.
+    let mut pair_counts: BTreeMap<(u32, u32), usize> = BTreeMap::new();
+
+    for frame in frames.iter() {
+        let gts = gt_by_frame.get(frame).unwrap_or(&no_positions);
+        let ests = est_by_frame.get(frame).unwrap_or(&no_positions);
+
+        for gt in gts.iter() {
+            for est in ests.iter() {
+                if distance(&gt.pos, &est.pos) <= max_distance {
+                    *pair_counts.entry((gt.id, est.id)).or_default() += 1;
+                }
+            }
+        }
+
+        // Correspondences from the previous frames are kept if still valid.
+        let mut matches: Vec<(usize, usize)> = Vec::new();
+        for (i, gt) in gts.iter().enumerate() {
+            if let Some(prev_est_id) = last_match.get(&gt.id) {
+                if let Some(j) = ests.iter().position(|est| est.id == *prev_est_id) {
+                    if distance(&gt.pos, &ests[j].pos) <= max_distance
+                        && !matches.iter().any(|m| m.1 == j)
+                    {
+                        matches.push((i, j));
+                    }
+                }
+            }
+        }
+
+        // Find the remaining correspondences with minimal total distance.
+        let free_gts: Vec<usize> = (0..gts.len())
+            .filter(|i| !matches.iter().any(|m| m.0 == *i))
+            .collect();
+        let free_ests: Vec<usize> = (0..ests.len())
+            .filter(|j| !matches.iter().any(|m| m.1 == *j))
+            .collect();
+        let cost: Vec<Vec<f64>> = free_gts
+            .iter()
+            .map(|i| {
+                free_ests
+                    .iter()
+                    .map(|j| {
+                        let dist = distance(&gts[*i].pos, &ests[*j].pos);
+                        if dist <= max_distance {
+                            dist
+                        } else {
+                            FORBIDDEN_COST
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        for (i, j) in min_cost_assignment(&cost) {
+            if cost[i][j] < FORBIDDEN_COST {
+                matches.push((free_gts[i], free_ests[j]));
+            }
+        }
+
+        for (i, j) in matches {
+            let (gt, est) = (gts[i], ests[j]);
+            if let Some(prev_est_id) = last_match.insert(gt.id, est.id) {
+                if prev_est_id != est.id {
+                    num_id_switches += 1;
+                }
+            }
+            sum_sq_error += distance(&gt.pos, &est.pos).powi(2);
+            num_matches += 1;
+        }
+    }
+
+    // Assign each ground truth ID to at most one estimated ID such that the
+    // number of corresponding positions is maximal.
+    let gt_ids: Vec<u32> = ground_truth
+        .iter()
+        .map(|p| p.id)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let est_ids: Vec<u32> = estimates
+        .iter()
+        .map(|p| p.id)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let count_cost: Vec<Vec<f64>> = gt_ids
+        .iter()
+        .map(|gt_id| {
+            est_ids
+                .iter()
+                .map(|est_id| -(*pair_counts.get(&(*gt_id, *est_id)).unwrap_or(&0) as f64))
+                .collect()
+        })
+        .collect();
+    let id_true_positives: f64 = -min_cost_assignment(&count_cost)
+        .into_iter()
+        .map(|(i, j)| count_cost[i][j])
+        .sum::<f64>();
+
+    let num_ground_truth = ground_truth.len();
+    let num_estimates = estimates.len();
+    let num_misses = num_ground_truth - num_matches;
+    let num_false_positives = num_estimates - num_matches;
+
+    TrackingMetrics {
+        num_ground_truth,
+        num_estimates,
+        num_matches,
+        num_misses,
+        num_false_positives,
+        num_id_switches,
+        mota: 1.0
+            - (num_misses + num_false_positives + num_id_switches) as f64 / num_ground_truth as f64,
+        idf1: 2.0 * id_true_positives / (num_ground_truth + num_estimates) as f64,
+        rms_error: (sum_sq_error / num_matches as f64).sqrt(),
+    }
+}
+
+/// Solve the assignment problem with the Hungarian algorithm.
+///
+/// `cost[i][j]` is the cost of assigning row `i` to column `j`. Returns the
+/// pairs `(i, j)` of an assignment with minimal total cost in which every row
+/// (if there are at most as many rows as columns) or every column (otherwise)
+/// is assigned.
+fn min_cost_assignment(cost: &[Vec<f64>]) -> Vec<(usize, usize)> {
+    let n_rows = cost.len();
+    let n_cols = cost.first().map(|row| row.len()).unwrap_or(0);
+    if n_rows == 0 || n_cols == 0 {
+        return Vec::new();
+    }
+    if n_rows > n_cols {
+        let transposed: Vec<Vec<f64>> = (0..n_cols)
+            .map(|j| cost.iter().map(|row| row[j]).collect())
+            .collect();
+        return min_cost_assignment(&transposed)
+            .into_iter()
+            .map(|(j, i)| (i, j))
+            .collect();
+    }
+
+    // Potentials `u` (rows) and `v` (columns). Index 0 is a virtual column and
+    // row indices stored in `assigned_row` are 1-based, 0 meaning unassigned.
+    let mut u = vec![0.0; n_rows + 1];
+    let mut v = vec![0.0; n_cols + 1];
+    let mut assigned_row = vec![0usize; n_cols + 1];
+    let mut way = vec![0usize; n_cols + 1];
+    for row in 1..=n_rows {
+        assigned_row[0] = row;
+        let mut col0 = 0;
+        let mut min_v = vec![f64::INFINITY; n_cols + 1];
+        let mut used = vec![false; n_cols + 1];
+        loop {
+            used[col0] = true;
+            let row0 = assigned_row[col0];
+            let mut delta = f64::INFINITY;
+            let mut col1 = 0;
+            for col in 1..=n_cols {
+                if used[col] {
+                    continue;
+                }
+                let cur = cost[row0 - 1][col - 1] - u[row0] - v[col];
+                if cur < min_v[col] {
+                    min_v[col] = cur;
+                    way[col] = col0;
+                }
+                if min_v[col] < delta {
+                    delta = min_v[col];
+                    col1 = col;
+                }
+            }
+            for col in 0..=n_cols {
+                if used[col] {
+                    u[assigned_row[col]] += delta;
+                    v[col] -= delta;
+                } else {
+                    min_v[col] -= delta;
+                }
+            }
+            col0 = col1;
+            if assigned_row[col0] == 0 {
+                break;
+            }
+        }
+        // Augment along the found path.
+        loop {
+            let col1 = way[col0];
+            assigned_row[col0] = assigned_row[col1];
+            col0 = col1;
+            if col0 == 0 {
+                break;
+            }
+        }
+    }
+
+    assigned_row
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(_col, row)| **row != 0)
+        .map(|(col, row)| (row - 1, col - 1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(frame: u64, id: u32, x: f64) -> ObjectPosition {
+        ObjectPosition {
+            frame,
+            id,
+            pos: [x, 0.0, 0.0],
+        }
+    }
+
+    #[test]
+    fn test_assignment() {
+        let cost = vec![
+            vec![4.0, 1.0, 3.0],
+            vec![2.0, 0.0, 5.0],
+            vec![3.0, 2.0, 2.0],
+        ];
+        let mut result = min_cost_assignment(&cost);
+        result.sort();
+        assert_eq!(result, vec![(0, 1), (1, 0), (2, 2)]);
+
+        let cost = vec![vec![1.0, 5.0], vec![0.0, 9.0], vec![3.0, 3.0]];
+        let mut result = min_cost_assignment(&cost);
+        result.sort();
+        assert_eq!(result, vec![(1, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn test_perfect_tracking() {
+        let gt: Vec<_> = (0..10)
+            .flat_map(|f| [pos(f, 1, 0.0), pos(f, 2, 1.0)])
+            .collect();
+        let est: Vec<_> = (0..10)
+            .flat_map(|f| [pos(f, 7, 0.001), pos(f, 8, 1.0)])
+            .collect();
+        let m = compute_tracking_metrics(&gt, &est, 0.01);
+        assert_eq!(m.num_matches, 20);
+        assert_eq!(m.num_id_switches, 0);
+        assert_eq!(m.mota, 1.0);
+        assert_eq!(m.idf1, 1.0);
+        approx::assert_relative_eq!(m.rms_error, (0.001f64.powi(2) / 2.0).sqrt());
+    }
+
+    #[test]
+    fn test_id_switch_miss_and_false_positive() {
+        // Ground truth object 1 is tracked as 7 for frames 0-4 and as 8 for
+        // frames 5-9. Frame 9 is missed. An extra estimate is far away.
+        let gt: Vec<_> = (0..10).map(|f| pos(f, 1, 0.0)).collect();
+        let mut est: Vec<_> = (0..9)
+            .map(|f| pos(f, if f < 5 { 7 } else { 8 }, 0.0))
+            .collect();
+        est.push(pos(3, 9, 5.0));
+        let m = compute_tracking_metrics(&gt, &est, 0.01);
+        assert_eq!(m.num_matches, 9);
+        assert_eq!(m.num_misses, 1);
+        assert_eq!(m.num_false_positives, 1);
+        assert_eq!(m.num_id_switches, 1);
+        approx::assert_relative_eq!(m.mota, 1.0 - 3.0 / 10.0);
+        // Best identity assignment 1->7 gives 5 correctly identified positions.
+        approx::assert_relative_eq!(m.idf1, 2.0 * 5.0 / 20.0);
+    }
+}
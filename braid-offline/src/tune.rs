@@ -0,0 +1,759 @@
+//! Automatic tuning of the tracking parameters against ground truth.
+//!
+//! The data is retracked repeatedly with different [TrackingParams]. Each
+//! result is compared with ground truth trajectories using
+//! [crate::tracking_metrics] and the parameters with the best objective are
+//! kept.
+//!
+//! The search is done in a unit hypercube with one dimension per tuned
+//! parameter. Each dimension is mapped linearly or logarithmically to the
+//! range of its parameter as given in the search space.
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, ValueEnum};
+use color_eyre::eyre::{self as anyhow, WrapErr};
+use ordered_float::NotNan;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use flydra_types::TrackingParams;
+
+use crate::rng::Rng;
+use crate::tracking_metrics::{compute_tracking_metrics, ObjectPosition, TrackingMetrics};
+
+/// A tracking parameter which can be tuned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TunableParam {
+    MotionNoiseScale,
+    InitialPositionStdMeters,
+    InitialVelStdMetersPerSec,
+    EkfObservationCovariancePixels,
+    AcceptObservationMinLikelihood,
+    MaxPositionStdMeters,
+    NumObservationsToVisibility,
+    /// `hypothesis_test_params.minimum_number_of_cameras`
+    MinimumNumberOfCameras,
+    /// `hypothesis_test_params.hypothesis_test_max_acceptable_error`
+    HypothesisTestMaxAcceptableError,
+    /// `hypothesis_test_params.minimum_pixel_abs_zscore`
+    MinimumPixelAbsZscore,
+}
+
+impl TunableParam {
+    /// The name as used in the search space file and the report.
+    pub fn name(&self) -> &'static str {
+        use TunableParam::*;
+        match self {
+            MotionNoiseScale => "motion_noise_scale",
+            InitialPositionStdMeters => "initial_position_std_meters",
+            InitialVelStdMetersPerSec => "initial_vel_std_meters_per_sec",
+            EkfObservationCovariancePixels => "ekf_observation_covariance_pixels",
+            AcceptObservationMinLikelihood => "accept_observation_min_likelihood",
+            MaxPositionStdMeters => "max_position_std_meters",
+            NumObservationsToVisibility => "num_observations_to_visibility",
+            MinimumNumberOfCameras => "minimum_number_of_cameras",
+            HypothesisTestMaxAcceptableError => "hypothesis_test_max_acceptable_error",
+            MinimumPixelAbsZscore => "minimum_pixel_abs_zscore",
+        }
+    }
+
+    /// Get the value of this parameter.
+    ///
+    /// Returns `None` for hypothesis test parameters if there are none (i.e.
+    /// with flat 3D tracking).
+    pub fn get(&self, tp: &TrackingParams) -> Option<f64> {
+        use TunableParam::*;
+        let htp = tp.hypothesis_test_params.as_ref();
+        Some(match self {
+            MotionNoiseScale => tp.motion_noise_scale,
+            InitialPositionStdMeters => tp.initial_position_std_meters,
+            InitialVelStdMetersPerSec => tp.initial_vel_std_meters_per_sec,
+            EkfObservationCovariancePixels => tp.ekf_observation_covariance_pixels,
+            AcceptObservationMinLikelihood => tp.accept_observation_min_likelihood,
+            MaxPositionStdMeters => tp.max_position_std_meters.into(),
+            NumObservationsToVisibility => tp.num_observations_to_visibility.into(),
+            MinimumNumberOfCameras => htp?.minimum_number_of_cameras.into(),
+            HypothesisTestMaxAcceptableError => htp?.hypothesis_test_max_acceptable_error,
+            MinimumPixelAbsZscore => htp?.minimum_pixel_abs_zscore,
+        })
+    }
+
+    /// Set the value of this parameter. Integer parameters are rounded.
+    ///
+    /// Hypothesis test parameters are ignored if there are none.
+    pub fn set(&self, tp: &mut TrackingParams, value: f64) {
+        use TunableParam::*;
+        let as_u8 = || value.round().clamp(0.0, u8::MAX.into()) as u8;
+        match self {
+            MotionNoiseScale => tp.motion_noise_scale = value,
+            InitialPositionStdMeters => tp.initial_position_std_meters = value,
+            InitialVelStdMetersPerSec => tp.initial_vel_std_meters_per_sec = value,
+            EkfObservationCovariancePixels => tp.ekf_observation_covariance_pixels = value,
+            AcceptObservationMinLikelihood => tp.accept_observation_min_likelihood = value,
+            MaxPositionStdMeters => tp.max_position_std_meters = value as f32,
+            NumObservationsToVisibility => tp.num_observations_to_visibility = as_u8(),
+            MinimumNumberOfCameras => {
+                if let Some(htp) = tp.hypothesis_test_params.as_mut() {
+                    htp.minimum_number_of_cameras = as_u8();
+                }
+            }
+            HypothesisTestMaxAcceptableError => {
+                if let Some(htp) = tp.hypothesis_test_params.as_mut() {
+                    htp.hypothesis_test_max_acceptable_error = value;
+                }
+            }
+            MinimumPixelAbsZscore => {
+                if let Some(htp) = tp.hypothesis_test_params.as_mut() {
+                    htp.minimum_pixel_abs_zscore = value;
+                }
+            }
+        }
+    }
+}
+
+/// The range in which a parameter is searched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParamRange {
+    pub min: f64,
+    pub max: f64,
+    /// Search logarithmically spaced values. Requires `min > 0`.
+    #[serde(default)]
+    pub log: bool,
+}
+
+impl ParamRange {
+    /// Map `u` in `[0, 1]` to a value in the range.
+    fn value(&self, u: f64) -> f64 {
+        let u = u.clamp(0.0, 1.0);
+        if self.log {
+            (self.min.ln() + u * (self.max.ln() - self.min.ln())).exp()
+        } else {
+            self.min + u * (self.max - self.min)
+        }
+    }
+
+    /// Map a value in the range to `[0, 1]`.
+    fn unit(&self, value: f64) -> f64 {
+        let u = if self.log {
+            (value.ln() - self.min.ln()) / (self.max.ln() - self.min.ln())
+        } else {
+            (value - self.min) / (self.max - self.min)
+        };
+        if u.is_nan() {
+            0.5
+        } else {
+            u.clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// The parameters to tune and the range of each.
+///
+/// In TOML, this is a table per parameter, e.g.
+///
+/// ```toml
+/// [motion_noise_scale]
+/// min = 0.01
+/// max = 1.0
+/// log = true
+///
+/// [hypothesis_test_max_acceptable_error]
+/// min = 1.0
+/// max = 20.0
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SearchSpace(pub BTreeMap<TunableParam, ParamRange>);
+
+impl SearchSpace {
+    /// The default search space around the parameters `tp`.
+    pub fn default_for(tp: &TrackingParams) -> Self {
+        let mut space = BTreeMap::new();
+        for param in [
+            TunableParam::MotionNoiseScale,
+            TunableParam::AcceptObservationMinLikelihood,
+            TunableParam::MaxPositionStdMeters,
+        ] {
+            let value = param.get(tp).unwrap();
+            space.insert(
+                param,
+                ParamRange {
+                    min: value / 10.0,
+                    max: value * 10.0,
+                    log: true,
+                },
+            );
+        }
+        if tp.hypothesis_test_params.is_some() {
+            space.insert(
+                TunableParam::HypothesisTestMaxAcceptableError,
+                ParamRange {
+                    min: 1.0,
+                    max: 20.0,
+                    log: false,
+                },
+            );
+        }
+        Self(space)
+    }
+
+    fn validate(&self, tp: &TrackingParams) -> anyhow::Result<()> {
+        if self.0.is_empty() {
+            anyhow::bail!("search space has no parameters");
+        }
+        for (param, range) in self.0.iter() {
+            let name = param.name();
+            if param.get(tp).is_none() {
+                anyhow::bail!(
+                    "parameter \"{name}\" cannot be tuned because the tracking \
+                    parameters have no `hypothesis_test_params`"
+                );
+            }
+            if range.min.partial_cmp(&range.max) != Some(std::cmp::Ordering::Less) {
+                anyhow::bail!("parameter \"{name}\": `min` must be less than `max`");
+            }
+            if range.log && range.min <= 0.0 {
+                anyhow::bail!("parameter \"{name}\": `min` must be positive with `log = true`");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SearchMethod {
+    /// Evaluate all combinations of `--grid-steps` values per parameter.
+    Grid,
+    /// Evaluate `--num-trials` uniformly random points.
+    Random,
+    /// Nelder-Mead simplex search starting at the initial parameters, with at
+    /// most `--num-trials` trials.
+    #[default]
+    NelderMead,
+}
+
+/// The value to maximize.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Objective {
+    /// Multiple object tracking accuracy
+    #[default]
+    Mota,
+    /// F1 score of correctly identified positions
+    Idf1,
+}
+
+impl Objective {
+    fn eval(&self, metrics: &TrackingMetrics) -> f64 {
+        let value = match self {
+            Objective::Mota => metrics.mota,
+            Objective::Idf1 => metrics.idf1,
+        };
+        if value.is_nan() {
+            f64::NEG_INFINITY
+        } else {
+            value
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about = "Tune tracking parameters against ground truth"
+)]
+pub struct TuneCli {
+    /// Input .braidz file
+    #[arg(short = 'd', long)]
+    pub data_src: PathBuf,
+    /// Ground truth trajectories.
+    ///
+    /// Either a CSV file with the columns `obj_id`, `frame`, `x`, `y` and `z`
+    /// (e.g. a manually curated `kalman_estimates.csv`) or a .braidz file
+    /// whose tracked trajectories are used.
+    #[arg(short = 'g', long)]
+    pub ground_truth: PathBuf,
+    /// Output tracking parameters TOML file with the best parameters
+    #[arg(short = 'o', long)]
+    pub output: PathBuf,
+    /// Output CSV file with the parameters and result of each trial. Defaults
+    /// to the output file with the extension `.csv`.
+    #[arg(long)]
+    pub report: Option<PathBuf>,
+    /// Initial tracking parameters TOML file. Parameters which are not tuned
+    /// keep these values.
+    #[arg(long)]
+    pub tracking_params: Option<PathBuf>,
+    /// Search space TOML file. Defaults to searching some parameters around
+    /// their initial values.
+    #[arg(long)]
+    pub search_space: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t)]
+    pub method: SearchMethod,
+    #[arg(long, value_enum, default_value_t)]
+    pub objective: Objective,
+    /// Maximum number of trials (for random and Nelder-Mead search)
+    #[arg(long, default_value_t = 50)]
+    pub num_trials: usize,
+    /// Number of values per parameter (for grid search)
+    #[arg(long, default_value_t = 3)]
+    pub grid_steps: usize,
+    /// Seed of the random search
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    /// Maximum distance, in meters, between ground truth and tracked positions
+    /// to count as the same object
+    #[arg(long, default_value_t = 0.02)]
+    pub max_distance: f64,
+    /// Set frames per second
+    #[arg(long)]
+    pub fps: Option<f64>,
+    /// Retrack all frames, not only those with ground truth
+    #[arg(long)]
+    pub all_frames: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroundTruthRow {
+    obj_id: u32,
+    frame: u64,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+fn load_ground_truth(path: &Path) -> anyhow::Result<Vec<ObjectPosition>> {
+    let is_braidz = path.is_dir() || path.extension() == Some(std::ffi::OsStr::new("braidz"));
+    let positions: Vec<ObjectPosition> = if is_braidz {
+        let archive = braidz_parser::braidz_parse_path(path)?;
+        let rows = archive
+            .kalman_estimates_table
+            .ok_or_else(|| anyhow::anyhow!("no trajectories in \"{}\"", path.display()))?;
+        rows.iter().map(Into::into).collect()
+    } else {
+        let mut rows = Vec::new();
+        for row in csv::Reader::from_path(path)?.into_deserialize() {
+            let row: GroundTruthRow = row?;
+            rows.push(ObjectPosition {
+                frame: row.frame,
+                id: row.obj_id,
+                pos: [row.x, row.y, row.z],
+            });
+        }
+        rows
+    };
+    let positions: Vec<_> = positions
+        .into_iter()
+        .filter(|p| p.pos.iter().all(|v| v.is_finite()))
+        .collect();
+    if positions.is_empty() {
+        anyhow::bail!("no ground truth positions in \"{}\"", path.display());
+    }
+    Ok(positions)
+}
+
+/// The result of retracking with one set of parameters.
+#[derive(Debug, Clone)]
+pub struct Trial {
+    pub tracking_params: TrackingParams,
+    pub metrics: TrackingMetrics,
+    pub objective: f64,
+}
+
+struct Tuner {
+    opt: TuneCli,
+    base_params: TrackingParams,
+    params: Vec<(TunableParam, ParamRange)>,
+    ground_truth: Vec<ObjectPosition>,
+    frame_range: (u64, u64),
+    tmpdir: tempfile::TempDir,
+    report: csv::Writer<std::fs::File>,
+    trials: Vec<Trial>,
+}
+
+impl Tuner {
+    fn tracking_params(&self, x: &[f64]) -> TrackingParams {
+        let mut tp = self.base_params.clone();
+        for ((param, range), u) in self.params.iter().zip(x.iter()) {
+            param.set(&mut tp, range.value(*u));
+        }
+        tp
+    }
+
+    /// Retrack with the parameters at `x` and return the objective.
+    async fn evaluate(&mut self, x: &[f64]) -> anyhow::Result<f64> {
+        let tracking_params = self.tracking_params(x);
+        let trial_num = self.trials.len();
+
+        let data_src =
+            braidz_parser::incremental_parser::IncrementalParser::open(&self.opt.data_src)?
+                .parse_basics()?;
+        let output = self.tmpdir.path().join(format!("trial-{trial_num}.braidz"));
+        let (start_frame, stop_frame) = if self.opt.all_frames {
+            (None, None)
+        } else {
+            (Some(self.frame_range.0), Some(self.frame_range.1))
+        };
+        crate::kalmanize(
+            data_src,
+            &output,
+            self.opt.fps.map(|v| NotNan::new(v).unwrap()),
+            tracking_params.clone(),
+            crate::KalmanizeOptions {
+                start_frame,
+                stop_frame,
+                ..Default::default()
+            },
+            false,
+            "braid-offline-tune",
+            true,
+            None,
+        )
+        .await?;
+
+        let archive = braidz_parser::braidz_parse_path(&output)?;
+        let (first, last) = self.frame_range;
+        let estimates: Vec<ObjectPosition> = archive
+            .kalman_estimates_table
+            .as_ref()
+            .map(|rows| rows.iter().map(ObjectPosition::from).collect())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|p: &ObjectPosition| first <= p.frame && p.frame <= last)
+            .collect();
+        drop(archive);
+        remove_path(&output)?;
+
+        let metrics =
+            compute_tracking_metrics(&self.ground_truth, &estimates, self.opt.max_distance);
+        let objective = self.opt.objective.eval(&metrics);
+        info!("trial {trial_num}: objective {objective:.4}, {metrics}");
+
+        let mut record = vec![trial_num.to_string()];
+        for (param, _) in self.params.iter() {
+            record.push(param.get(&tracking_params).unwrap().to_string());
+        }
+        record.extend([
+            metrics.mota.to_string(),
+            metrics.idf1.to_string(),
+            metrics.rms_error.to_string(),
+            metrics.num_id_switches.to_string(),
+            metrics.num_misses.to_string(),
+            metrics.num_false_positives.to_string(),
+            objective.to_string(),
+        ]);
+        self.report.write_record(&record)?;
+        self.report.flush()?;
+
+        self.trials.push(Trial {
+            tracking_params,
+            metrics,
+            objective,
+        });
+        Ok(objective)
+    }
+
+    async fn grid_search(&mut self) -> anyhow::Result<()> {
+        let steps = self.opt.grid_steps.max(1);
+        let dims = self.params.len();
+        let num_points = u32::try_from(dims)
+            .ok()
+            .and_then(|d| steps.checked_pow(d))
+            .ok_or_else(|| anyhow::anyhow!("grid too large"))?;
+        info!("grid search with {num_points} trials");
+        let unit = |i: usize| {
+            if steps == 1 {
+                0.5
+            } else {
+                i as f64 / (steps - 1) as f64
+            }
+        };
+        for mut idx in 0..num_points {
+            let mut x = Vec::with_capacity(dims);
+            for _ in 0..dims {
+                x.push(unit(idx % steps));
+                idx /= steps;
+            }
+            self.evaluate(&x).await?;
+        }
+        Ok(())
+    }
+
+    async fn random_search(&mut self) -> anyhow::Result<()> {
+        let mut rng = Rng::new(self.opt.seed);
+        for _ in 0..self.opt.num_trials {
+            let x: Vec<f64> = (0..self.params.len()).map(|_| rng.uniform()).collect();
+            self.evaluate(&x).await?;
+        }
+        Ok(())
+    }
+
+    /// Nelder-Mead simplex search (maximizing the objective).
+    async fn nelder_mead(&mut self) -> anyhow::Result<()> {
+        const INITIAL_STEP: f64 = 0.25;
+        let dims = self.params.len();
+        let budget = self.opt.num_trials;
+
+        let x0: Vec<f64> = self
+            .params
+            .iter()
+            .map(|(param, range)| range.unit(param.get(&self.base_params).unwrap()))
+            .collect();
+        let mut simplex = vec![x0.clone()];
+        for i in 0..dims {
+            let mut x = x0.clone();
+            x[i] = if x[i] + INITIAL_STEP <= 1.0 {
+                x[i] + INITIAL_STEP
+            } else {
+                x[i] - INITIAL_STEP
+            };
+            simplex.push(x);
+        }
+
+        let mut values = Vec::with_capacity(simplex.len());
+        for x in simplex.iter() {
+            if self.trials.len() >= budget {
+                return Ok(());
+            }
+            values.push(self.evaluate(x).await?);
+        }
+
+        // Points outside the unit hypercube are clamped.
+        let along = |a: &[f64], b: &[f64], t: f64| -> Vec<f64> {
+            a.iter()
+                .zip(b.iter())
+                .map(|(a, b)| (a + t * (b - a)).clamp(0.0, 1.0))
+                .collect()
+        };
+
+        while self.trials.len() < budget {
+            // Sort best (highest) first.
+            let mut order: Vec<usize> = (0..simplex.len()).collect();
+            order.sort_by(|a, b| values[*b].total_cmp(&values[*a]));
+            simplex = order.iter().map(|i| simplex[*i].clone()).collect();
+            values = order.iter().map(|i| values[*i]).collect();
+
+            let worst = dims;
+            let centroid: Vec<f64> = (0..dims)
+                .map(|j| simplex[..worst].iter().map(|x| x[j]).sum::<f64>() / dims as f64)
+                .collect();
+
+            let reflected = along(&centroid, &simplex[worst], -1.0);
+            let f_reflected = self.evaluate(&reflected).await?;
+            if f_reflected > values[0] {
+                if self.trials.len() >= budget {
+                    simplex[worst] = reflected;
+                    values[worst] = f_reflected;
+                    break;
+                }
+                let expanded = along(&centroid, &simplex[worst], -2.0);
+                let f_expanded = self.evaluate(&expanded).await?;
+                if f_expanded > f_reflected {
+                    simplex[worst] = expanded;
+                    values[worst] = f_expanded;
+                } else {
+                    simplex[worst] = reflected;
+                    values[worst] = f_reflected;
+                }
+                continue;
+            }
+            if f_reflected > values[worst - 1] {
+                simplex[worst] = reflected;
+                values[worst] = f_reflected;
+                continue;
+            }
+            if self.trials.len() >= budget {
+                break;
+            }
+            let contracted = if f_reflected > values[worst] {
+                along(&centroid, &reflected, 0.5)
+            } else {
+                along(&centroid, &simplex[worst], 0.5)
+            };
+            let f_contracted = self.evaluate(&contracted).await?;
+            if f_contracted > values[worst].max(f_reflected) {
+                simplex[worst] = contracted;
+                values[worst] = f_contracted;
+                continue;
+            }
+            // Shrink towards the best point.
+            for i in 1..simplex.len() {
+                if self.trials.len() >= budget {
+                    break;
+                }
+                simplex[i] = along(&simplex[0], &simplex[i], 0.5);
+                values[i] = self.evaluate(&simplex[i]).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else if path.exists() {
+        std::fs::remove_file(path)
+    } else {
+        Ok(())
+    }
+}
+
+/// Search tracking parameters which best reproduce the ground truth.
+///
+/// The best parameters are saved to `opt.output` and all trials to the
+/// report. Returns all trials in the order they were done.
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn braid_offline_tune(opt: TuneCli) -> anyhow::Result<Vec<Trial>> {
+    if opt.output.exists() {
+        anyhow::bail!("Path {} exists. Will not overwrite.", opt.output.display());
+    }
+    let report_path = opt
+        .report
+        .clone()
+        .unwrap_or_else(|| opt.output.with_extension("csv"));
+
+    let data_src = braidz_parser::incremental_parser::IncrementalParser::open(&opt.data_src)
+        .with_context(|| format!("while opening file \"{}\"", opt.data_src.display()))?
+        .parse_basics()
+        .with_context(|| format!("when parsing braidz file \"{}\"", opt.data_src.display()))?;
+    let base_params =
+        crate::get_tracking_params(opt.tracking_params.as_deref(), data_src.basic_info())?;
+    drop(data_src);
+
+    let search_space = match &opt.search_space {
+        Some(fname) => {
+            let buf = std::fs::read_to_string(fname)
+                .with_context(|| format!("loading search space {}", fname.display()))?;
+            toml::from_str(&buf)?
+        }
+        None => SearchSpace::default_for(&base_params),
+    };
+    search_space.validate(&base_params)?;
+    let params: Vec<(TunableParam, ParamRange)> = search_space.0.into_iter().collect();
+
+    let ground_truth = load_ground_truth(&opt.ground_truth)?;
+    let frame_range = (
+        ground_truth.iter().map(|p| p.frame).min().unwrap(),
+        ground_truth.iter().map(|p| p.frame).max().unwrap(),
+    );
+    info!(
+        "{} ground truth positions in frames {}-{}",
+        ground_truth.len(),
+        frame_range.0,
+        frame_range.1
+    );
+
+    let mut report = csv::Writer::from_path(&report_path)?;
+    let mut header = vec!["trial"];
+    header.extend(params.iter().map(|(param, _)| param.name()));
+    header.extend([
+        "mota",
+        "idf1",
+        "rms_error",
+        "num_id_switches",
+        "num_misses",
+        "num_false_positives",
+        "objective",
+    ]);
+    report.write_record(&header)?;
+
+    let mut tuner = Tuner {
+        tmpdir: tempfile::tempdir()?,
+        opt,
+        base_params,
+        params,
+        ground_truth,
+        frame_range,
+        report,
+        trials: Vec::new(),
+    };
+
+    match tuner.opt.method {
+        SearchMethod::Grid => tuner.grid_search().await?,
+        SearchMethod::Random => tuner.random_search().await?,
+        SearchMethod::NelderMead => tuner.nelder_mead().await?,
+    }
+
+    let best = tuner
+        .trials
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.objective.total_cmp(&b.objective))
+        .ok_or_else(|| anyhow::anyhow!("no trials done"))?;
+    info!(
+        "best trial {} of {}: objective {:.4}, {}",
+        best.0,
+        tuner.trials.len(),
+        best.1.objective,
+        best.1.metrics
+    );
+    if best.1.objective.is_infinite() {
+        warn!("No trial tracked any ground truth object.");
+    }
+
+    let value = toml::Value::try_from(&best.1.tracking_params)?;
+    let mut fd = std::fs::File::create(&tuner.opt.output)?;
+    fd.write_all(toml::to_string(&value)?.as_bytes())?;
+    info!(
+        "saved best tracking parameters to \"{}\" and trials to \"{}\"",
+        tuner.opt.output.display(),
+        report_path.display()
+    );
+
+    Ok(tuner.trials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_param_range() {
+        let range = ParamRange {
+            min: 0.01,
+            max: 1.0,
+            log: true,
+        };
+        approx::assert_relative_eq!(range.value(0.5), 0.1, epsilon = 1e-12);
+        approx::assert_relative_eq!(range.unit(0.1), 0.5, epsilon = 1e-12);
+        assert_eq!(range.unit(100.0), 1.0);
+    }
+
+    #[test]
+    fn test_search_space_toml() {
+        let buf = r#"
+            [motion_noise_scale]
+            min = 0.01
+            max = 1.0
+            log = true
+
+            [minimum_number_of_cameras]
+            min = 2
+            max = 4
+        "#;
+        let space: SearchSpace = toml::from_str(buf).unwrap();
+        assert_eq!(space.0.len(), 2);
+        let tp = flydra_types::default_tracking_params_full_3d();
+        space.validate(&tp).unwrap();
+        assert!(space
+            .validate(&flydra_types::default_tracking_params_flat_3d())
+            .is_err());
+
+        let mut tp2 = tp.clone();
+        TunableParam::MinimumNumberOfCameras.set(&mut tp2, 2.6);
+        assert_eq!(
+            tp2.hypothesis_test_params
+                .unwrap()
+                .minimum_number_of_cameras,
+            3
+        );
+    }
+}
@@ -0,0 +1,418 @@
+//! Tracking regression tests with synthetic multi-camera data.
+//!
+//! Each scene has known 3D trajectories which are projected into several
+//! cameras (with pixel noise, occlusions and false positive detections) and
+//! saved as a `.braid` directory. This is tracked by `braid_offline_retrack`
+//! and the result is compared with the ground truth. The metrics must meet the
+//! thresholds of each scene.
+//!
+//! All data is generated from a fixed seed, so the results are deterministic.
+//! To evaluate other tracking parameters, set the environment variable
+//! `BRAID_REGRESSION_TRACKING_PARAMS` to a tracking parameters TOML file and
+//! run with `--nocapture` to see the metrics. To keep the generated input and
+//! tracked output, set `BRAID_REGRESSION_OUTPUT_DIR`.
+
+use std::{collections::BTreeMap, path::Path};
+
+use color_eyre::eyre::{self as anyhow};
+use nalgebra::{Point3, Unit, Vector3};
+
+use braid_offline::rng::Rng;
+use braid_offline::tracking_metrics::{compute_tracking_metrics, ObjectPosition, TrackingMetrics};
+use flydra_types::{CamInfoRow, CamNum, Data2dDistortedRow, FlydraFloatTimestampLocal, HostClock};
+
+const FPS: f64 = 100.0;
+const NUM_FRAMES: u64 = 300;
+const WIDTH: usize = 640;
+const HEIGHT: usize = 480;
+/// Standard deviation of the detected pixel location.
+const PIXEL_NOISE_STD: f64 = 0.5;
+/// Maximum distance between ground truth and tracked position to be considered
+/// the same object.
+const MAX_MATCH_DISTANCE: f64 = 0.02;
+
+enum Motion {
+    Circle {
+        center: [f64; 3],
+        radius: f64,
+        /// Angular velocity, in radians per second.
+        omega: f64,
+        phase: f64,
+    },
+    Line {
+        start: [f64; 3],
+        /// Velocity, in meters per second.
+        velocity: [f64; 3],
+    },
+}
+
+impl Motion {
+    fn position(&self, t: f64) -> [f64; 3] {
+        match self {
+            Motion::Circle {
+                center,
+                radius,
+                omega,
+                phase,
+            } => {
+                let angle = omega * t + phase;
+                [
+                    center[0] + radius * angle.cos(),
+                    center[1] + radius * angle.sin(),
+                    center[2],
+                ]
+            }
+            Motion::Line { start, velocity } => [
+                start[0] + velocity[0] * t,
+                start[1] + velocity[1] * t,
+                start[2] + velocity[2] * t,
+            ],
+        }
+    }
+}
+
+/// Frames in which a camera does not detect a given object.
+struct Occlusion {
+    cam_idx: usize,
+    object_idx: usize,
+    frames: std::ops::Range<u64>,
+}
+
+struct Thresholds {
+    min_mota: f64,
+    min_idf1: f64,
+    max_rms_error: f64,
+    max_id_switches: usize,
+}
+
+struct Scene {
+    name: &'static str,
+    objects: Vec<Motion>,
+    occlusions: Vec<Occlusion>,
+    /// Expected number of false positive detections per camera per frame.
+    false_positive_rate: f64,
+    thresholds: Thresholds,
+}
+
+fn cameras() -> flydra_mvg::FlydraMultiCameraSystem<f64> {
+    let mut cams = BTreeMap::new();
+    let lookat = Vector3::new(0.0, 0.0, 0.0);
+    let up = Unit::new_normalize(Vector3::new(0.0, 0.0, 1.0));
+    for i in 0..4 {
+        let angle = std::f64::consts::FRAC_PI_4 + i as f64 * std::f64::consts::FRAC_PI_2;
+        let camcenter = Vector3::new(1.5 * angle.cos(), 1.5 * angle.sin(), 0.8);
+        let extrinsics = cam_geom::ExtrinsicParameters::from_view(&camcenter, &lookat, &up);
+        let intrinsics =
+            opencv_ros_camera::RosOpenCvIntrinsics::from_params(600.0, 0.0, 600.0, 320.0, 240.0);
+        let cam = mvg::Camera::new(WIDTH, HEIGHT, extrinsics, intrinsics).unwrap();
+        cams.insert(format!("cam{}", i + 1), cam);
+    }
+    flydra_mvg::FlydraMultiCameraSystem::new(cams, None)
+}
+
+/// Save the scene as `.braid` directory and return the ground truth.
+fn write_scene(
+    scene: &Scene,
+    system: &flydra_mvg::FlydraMultiCameraSystem<f64>,
+    braid_dir: &Path,
+) -> anyhow::Result<Vec<ObjectPosition>> {
+    std::fs::create_dir_all(braid_dir)?;
+    let mut rng = Rng::new(0x5eed);
+
+    let recording_time = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let metadata = braidz_types::BraidMetadata {
+        schema: flydra_types::BRAID_SCHEMA,
+        git_revision: "synthetic".to_string(),
+        original_recording_time: Some(recording_time.with_timezone(&chrono::Local)),
+        save_empty_data2d: true,
+        saving_program_name: "test-tracking-regression".to_string(),
+    };
+    std::fs::write(
+        braid_dir.join(flydra_types::BRAID_METADATA_YML_FNAME),
+        serde_yaml::to_string(&metadata)?,
+    )?;
+
+    system.to_flydra_xml(std::fs::File::create(
+        braid_dir.join(flydra_types::CALIBRATION_XML_FNAME),
+    )?)?;
+
+    let cam_names: Vec<String> = system.system().cams_by_name().keys().cloned().collect();
+    {
+        let mut wtr = csv::Writer::from_path(braid_dir.join(flydra_types::CAM_INFO_CSV_FNAME))?;
+        for (idx, cam_name) in cam_names.iter().enumerate() {
+            wtr.serialize(CamInfoRow {
+                camn: CamNum(idx.try_into().unwrap()),
+                cam_id: cam_name.clone(),
+            })?;
+        }
+    }
+
+    let mut ground_truth = Vec::new();
+    let mut wtr = csv::Writer::from_path(braid_dir.join(flydra_types::DATA2D_DISTORTED_CSV_FNAME))?;
+    for frame in 0..NUM_FRAMES {
+        let t = frame as f64 / FPS;
+        let positions: Vec<[f64; 3]> = scene.objects.iter().map(|m| m.position(t)).collect();
+        for (object_idx, pos) in positions.iter().enumerate() {
+            ground_truth.push(ObjectPosition {
+                frame,
+                id: object_idx.try_into().unwrap(),
+                pos: *pos,
+            });
+        }
+
+        for (cam_idx, cam_name) in cam_names.iter().enumerate() {
+            let cam = system.system().cam_by_name(cam_name).unwrap();
+            let mut detections = Vec::new();
+            for (object_idx, pos) in positions.iter().enumerate() {
+                let occluded = scene.occlusions.iter().any(|o| {
+                    o.cam_idx == cam_idx && o.object_idx == object_idx && o.frames.contains(&frame)
+                });
+                if occluded {
+                    continue;
+                }
+                let pt = mvg::PointWorldFrame {
+                    coords: Point3::new(pos[0], pos[1], pos[2]),
+                };
+                let px = cam.project_3d_to_distorted_pixel(&pt).coords;
+                detections.push((
+                    px.x + PIXEL_NOISE_STD * rng.normal(),
+                    px.y + PIXEL_NOISE_STD * rng.normal(),
+                ));
+            }
+            if rng.uniform() < scene.false_positive_rate {
+                detections.push((rng.uniform() * WIDTH as f64, rng.uniform() * HEIGHT as f64));
+            }
+            detections.retain(|(x, y)| {
+                (0.0..WIDTH as f64).contains(x) && (0.0..HEIGHT as f64).contains(y)
+            });
+
+            let row = Data2dDistortedRow {
+                camn: CamNum(cam_idx.try_into().unwrap()),
+                frame: frame.try_into().unwrap(),
+                timestamp: None,
+                cam_received_timestamp: FlydraFloatTimestampLocal::<HostClock>::from_f64(
+                    recording_time.timestamp() as f64 + t,
+                ),
+                device_timestamp: None,
+                block_id: std::num::NonZeroU64::new(frame + 1),
+                x: f64::NAN,
+                y: f64::NAN,
+                area: f64::NAN,
+                slope: f64::NAN,
+                eccentricity: f64::NAN,
+                frame_pt_idx: 0,
+                cur_val: 0,
+                mean_val: f64::NAN,
+                sumsqf_val: f64::NAN,
+            };
+            if detections.is_empty() {
+                wtr.serialize(&row)?;
+            }
+            for (idx, (x, y)) in detections.into_iter().enumerate() {
+                wtr.serialize(Data2dDistortedRow {
+                    x,
+                    y,
+                    area: 10.0,
+                    frame_pt_idx: idx.try_into().unwrap(),
+                    cur_val: 255,
+                    mean_val: 0.0,
+                    sumsqf_val: 0.0,
+                    ..row.clone()
+                })?;
+            }
+        }
+    }
+    wtr.flush()?;
+    Ok(ground_truth)
+}
+
+async fn run_scene(scene: &Scene) -> anyhow::Result<TrackingMetrics> {
+    let tmpdir = tempfile::tempdir()?; // cleanup on drop
+    let output_root = match std::env::var_os("BRAID_REGRESSION_OUTPUT_DIR") {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => tmpdir.path().to_path_buf(),
+    };
+    let braid_dir = output_root.join(format!("{}-input.braid", scene.name));
+    let output = output_root.join(format!("{}-tracked.braidz", scene.name));
+    for path in [&braid_dir, &output] {
+        if path.is_dir() {
+            std::fs::remove_dir_all(path)?;
+        } else if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    let ground_truth = write_scene(scene, &cameras(), &braid_dir)?;
+
+    let opt = braid_offline::Cli {
+        data_src: braid_dir,
+        output: output.clone(),
+        fps: Some(FPS),
+        tracking_params: std::env::var_os("BRAID_REGRESSION_TRACKING_PARAMS").map(Into::into),
+        no_progress: true,
+        ..Default::default()
+    };
+    braid_offline::braid_offline_retrack(opt).await?;
+
+    let archive = braidz_parser::braidz_parse_path(&output)?;
+    let estimates: Vec<ObjectPosition> = archive
+        .kalman_estimates_table
+        .as_ref()
+        .map(|rows| rows.iter().map(Into::into).collect())
+        .unwrap_or_default();
+
+    Ok(compute_tracking_metrics(
+        &ground_truth,
+        &estimates,
+        MAX_MATCH_DISTANCE,
+    ))
+}
+
+fn check(scene: &Scene, metrics: &TrackingMetrics) {
+    println!("{}: {metrics}", scene.name);
+    let th = &scene.thresholds;
+    assert!(
+        metrics.mota >= th.min_mota,
+        "{}: MOTA {} below {}",
+        scene.name,
+        metrics.mota,
+        th.min_mota
+    );
+    assert!(
+        metrics.idf1 >= th.min_idf1,
+        "{}: IDF1 {} below {}",
+        scene.name,
+        metrics.idf1,
+        th.min_idf1
+    );
+    assert!(
+        metrics.rms_error <= th.max_rms_error,
+        "{}: RMS error {} above {}",
+        scene.name,
+        metrics.rms_error,
+        th.max_rms_error
+    );
+    assert!(
+        metrics.num_id_switches <= th.max_id_switches,
+        "{}: {} ID switches, more than {}",
+        scene.name,
+        metrics.num_id_switches,
+        th.max_id_switches
+    );
+}
+
+#[tokio::test]
+async fn test_single_circle() -> anyhow::Result<()> {
+    let scene = Scene {
+        name: "single-circle",
+        objects: vec![Motion::Circle {
+            center: [0.0, 0.0, 0.1],
+            radius: 0.1,
+            omega: std::f64::consts::PI,
+            phase: 0.0,
+        }],
+        occlusions: vec![],
+        false_positive_rate: 0.0,
+        thresholds: Thresholds {
+            min_mota: 0.95,
+            min_idf1: 0.95,
+            max_rms_error: 0.005,
+            max_id_switches: 0,
+        },
+    };
+    check(&scene, &run_scene(&scene).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_crossing() -> anyhow::Result<()> {
+    // Two objects fly past each other at 3 cm distance at t=1.5 seconds.
+    let scene = Scene {
+        name: "crossing",
+        objects: vec![
+            Motion::Line {
+                start: [-0.3, 0.0, 0.1],
+                velocity: [0.2, 0.0, 0.0],
+            },
+            Motion::Line {
+                start: [0.0, -0.3, 0.13],
+                velocity: [0.0, 0.2, 0.0],
+            },
+        ],
+        occlusions: vec![],
+        false_positive_rate: 0.0,
+        thresholds: Thresholds {
+            min_mota: 0.9,
+            min_idf1: 0.8,
+            max_rms_error: 0.005,
+            max_id_switches: 2,
+        },
+    };
+    check(&scene, &run_scene(&scene).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_occlusions() -> anyhow::Result<()> {
+    // Two cameras lose the object in partially overlapping intervals. At least
+    // two cameras always see the object.
+    let scene = Scene {
+        name: "occlusions",
+        objects: vec![Motion::Circle {
+            center: [0.0, 0.0, 0.0],
+            radius: 0.15,
+            omega: 2.0,
+            phase: 1.0,
+        }],
+        occlusions: vec![
+            Occlusion {
+                cam_idx: 0,
+                object_idx: 0,
+                frames: 100..150,
+            },
+            Occlusion {
+                cam_idx: 1,
+                object_idx: 0,
+                frames: 120..200,
+            },
+        ],
+        false_positive_rate: 0.0,
+        thresholds: Thresholds {
+            min_mota: 0.95,
+            min_idf1: 0.95,
+            max_rms_error: 0.01,
+            max_id_switches: 0,
+        },
+    };
+    check(&scene, &run_scene(&scene).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_false_positives() -> anyhow::Result<()> {
+    let scene = Scene {
+        name: "false-positives",
+        objects: vec![
+            Motion::Circle {
+                center: [0.0, 0.0, 0.1],
+                radius: 0.1,
+                omega: 2.0,
+                phase: 0.0,
+            },
+            Motion::Line {
+                start: [-0.2, 0.2, -0.1],
+                velocity: [0.1, -0.1, 0.05],
+            },
+        ],
+        occlusions: vec![],
+        false_positive_rate: 0.2,
+        thresholds: Thresholds {
+            min_mota: 0.85,
+            min_idf1: 0.85,
+            max_rms_error: 0.005,
+            max_id_switches: 1,
+        },
+    };
+    check(&scene, &run_scene(&scene).await?);
+    Ok(())
+}
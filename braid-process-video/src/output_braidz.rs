@@ -122,6 +122,7 @@ impl BraidStorage {
                 mini_arena_debug_image_dir: None,
                 write_buffer_size_num_messages:
                     braid_config_data::default_write_buffer_size_num_messages(),
+                per_observation_timestamps: false,
             },
             cam_manager.clone(),
             recon.clone(),
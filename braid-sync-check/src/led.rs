@@ -0,0 +1,207 @@
+//! Control of the LED box which produces the flashes.
+use std::{
+    io::{BufRead, BufReader, Write},
+    time::{Duration, Instant, SystemTime},
+};
+
+use led_box_comms::{DeviceState, FromDevice, OnState, ToDevice};
+use serde::{Deserialize, Serialize};
+
+use crate::{sequence::FlashSequence, Error, Result};
+
+/// Timeout for replies of the LED box.
+const SERIAL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Something which can switch an LED on and off.
+pub trait LedBox {
+    /// Switch LED `channel` (1 to 4) on or off.
+    fn set_channel(&mut self, channel: u8, on: bool) -> Result<()>;
+}
+
+fn channel_mut(state: &mut DeviceState, channel: u8) -> Result<&mut led_box_comms::ChannelState> {
+    match channel {
+        1 => Ok(&mut state.ch1),
+        2 => Ok(&mut state.ch2),
+        3 => Ok(&mut state.ch3),
+        4 => Ok(&mut state.ch4),
+        _ => Err(Error::InvalidChannel(channel)),
+    }
+}
+
+/// An LED box connected to a serial port.
+pub struct SerialLedBox {
+    port: Box<dyn serialport::SerialPort>,
+    state: DeviceState,
+}
+
+impl SerialLedBox {
+    /// Open the LED box at `device` and check its communication version.
+    ///
+    /// All channels are initially switched off.
+    pub fn open(device: &str) -> Result<Self> {
+        let port = serialport::new(device, led_box_comms::BAUD_RATE)
+            .timeout(SERIAL_TIMEOUT)
+            .open()?;
+        let mut result = Self {
+            port,
+            state: DeviceState::default(),
+        };
+        result.send(&ToDevice::VersionRequest)?;
+        let mut reader = BufReader::new(result.port.try_clone()?);
+        let found = loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            if let Ok(FromDevice::VersionResponse(found)) = serde_json::from_str(&line) {
+                break found;
+            }
+        };
+        if !led_box_comms::is_compatible_version(found) {
+            return Err(Error::CommVersionMismatch {
+                expected: led_box_comms::COMM_VERSION,
+                found,
+            });
+        }
+        result.send(&ToDevice::DeviceState(result.state))?;
+        Ok(result)
+    }
+
+    fn send(&mut self, msg: &ToDevice) -> Result<()> {
+        let mut buf = serde_json::to_vec(msg)?;
+        buf.push(b'\n');
+        self.port.write_all(&buf)?;
+        self.port.flush()?;
+        Ok(())
+    }
+}
+
+impl LedBox for SerialLedBox {
+    fn set_channel(&mut self, channel: u8, on: bool) -> Result<()> {
+        let ch = channel_mut(&mut self.state, channel)?;
+        ch.on_state = if on {
+            OnState::ConstantOn
+        } else {
+            OnState::Off
+        };
+        self.send(&ToDevice::DeviceState(self.state))
+    }
+}
+
+/// A simulated LED box which records when each channel was switched.
+///
+/// This is used to test the analysis without hardware. The light of a
+/// channel changes `latency` seconds after the command.
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedLedBox {
+    latency: f64,
+    changes: Vec<LedEvent>,
+}
+
+impl SimulatedLedBox {
+    pub fn new(latency: f64) -> Self {
+        Self {
+            latency,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Whether the LED of `channel` was on at time `t`.
+    pub fn is_on(&self, channel: u8, t: f64) -> bool {
+        self.changes
+            .iter()
+            .rev()
+            .find(|ev| ev.channel == channel && ev.time <= t)
+            .is_some_and(|ev| ev.on)
+    }
+}
+
+impl LedBox for SimulatedLedBox {
+    fn set_channel(&mut self, channel: u8, on: bool) -> Result<()> {
+        if !(1..=4).contains(&channel) {
+            return Err(Error::InvalidChannel(channel));
+        }
+        self.changes.push(LedEvent {
+            channel,
+            time: now_f64() + self.latency,
+            on,
+        });
+        Ok(())
+    }
+}
+
+/// A command to the LED box.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LedEvent {
+    pub channel: u8,
+    /// Time of the command, in seconds since the Unix epoch.
+    pub time: f64,
+    pub on: bool,
+}
+
+/// The record of a flash sequence as it was sent to the LED box.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlashLog {
+    pub sequence: FlashSequence,
+    /// Duration of each state of the sequence, in seconds.
+    pub flash_duration: f64,
+    pub events: Vec<LedEvent>,
+}
+
+impl FlashLog {
+    /// The LED state at time `t`, or `None` outside of the sequence.
+    pub fn state_at(&self, t: f64) -> Option<bool> {
+        let last = self.events.last()?;
+        if t >= last.time {
+            return None;
+        }
+        let idx = self.events.partition_point(|ev| ev.time <= t);
+        idx.checked_sub(1).map(|idx| self.events[idx].on)
+    }
+
+    /// The times at which the LED changed state, with the new state.
+    pub fn changes(&self) -> impl Iterator<Item = &LedEvent> {
+        self.events
+            .windows(2)
+            .filter(|w| w[0].on != w[1].on)
+            .map(|w| &w[1])
+    }
+}
+
+fn now_f64() -> f64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+}
+
+/// Flash the LED of `channel` with `sequence`, holding each state for
+/// `flash_duration`.
+///
+/// The LED is switched off before and after the sequence. The host time of
+/// each command is recorded in the returned log.
+pub fn run_flash_sequence<L: LedBox>(
+    led_box: &mut L,
+    channel: u8,
+    sequence: &FlashSequence,
+    flash_duration: Duration,
+) -> Result<FlashLog> {
+    let states = std::iter::once(false)
+        .chain(sequence.states().iter().copied())
+        .chain(std::iter::once(false));
+    let start = Instant::now();
+    let mut events = Vec::new();
+    for (i, on) in states.enumerate() {
+        // Schedule relative to the start so that delays do not accumulate.
+        let scheduled = start + flash_duration * i as u32;
+        if let Some(wait) = scheduled.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+        let time = now_f64();
+        led_box.set_channel(channel, on)?;
+        events.push(LedEvent { channel, time, on });
+    }
+    Ok(FlashLog {
+        sequence: sequence.clone(),
+        flash_duration: flash_duration.as_secs_f64(),
+        events,
+    })
+}
@@ -0,0 +1,390 @@
+//! Verify the synchronization of Braid cameras by flashing an LED.
+//!
+//! An LED of the LED box which is visible to all cameras is flashed with a
+//! pseudo-random [FlashSequence]. The brightness of the LED in each camera,
+//! either measured in the camera frames with [roi_brightness] or taken from
+//! the `cur_val` column of `data2d_distorted`, is then compared with the
+//! flashes. This gives the frame offset and jitter of each camera relative to
+//! the synchronized frame numbers of Braid. A camera which is out of sync by
+//! one frame otherwise only shows up as degraded 3D reconstruction.
+use std::collections::BTreeMap;
+
+use braidz_types::{CamNum, Data2dDistortedRow};
+
+pub mod led;
+pub mod sequence;
+
+pub use led::{run_flash_sequence, FlashLog, LedBox, LedEvent, SerialLedBox, SimulatedLedBox};
+pub use sequence::FlashSequence;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("invalid LED channel {0}, must be 1 to 4")]
+    InvalidChannel(u8),
+    #[error("LED box has communication version {found}, expected {expected}")]
+    CommVersionMismatch { expected: u16, found: u16 },
+    #[error("need trigger timestamps of at least two frames")]
+    NoTimestamps,
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Serial(#[from] serialport::Error),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The brightness of the LED seen by a camera on one frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BrightnessSample {
+    /// The synchronized frame number.
+    pub frame: u64,
+    /// The trigger timestamp of the frame, in seconds since the Unix epoch.
+    pub timestamp: Option<f64>,
+    pub brightness: f64,
+}
+
+/// A rectangular region of an image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Roi {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// The mean intensity of `roi` in a monochrome 8 bit image.
+pub fn roi_brightness(image: &[u8], stride: usize, roi: &Roi) -> f64 {
+    let mut sum = 0u64;
+    for row in image.chunks(stride).skip(roi.y).take(roi.height) {
+        sum += row[roi.x..roi.x + roi.width]
+            .iter()
+            .map(|v| *v as u64)
+            .sum::<u64>();
+    }
+    sum as f64 / (roi.width * roi.height) as f64
+}
+
+/// Collect the LED brightness per camera from `data2d_distorted` rows.
+///
+/// The brightest detection (`cur_val`) of each frame is used. To also have
+/// samples when the LED is off, the data should be saved with empty frames.
+pub fn brightness_from_data2d<I>(
+    rows: I,
+    camn2camid: &BTreeMap<CamNum, String>,
+) -> BTreeMap<String, Vec<BrightnessSample>>
+where
+    I: IntoIterator<Item = Data2dDistortedRow>,
+{
+    let mut per_cam: BTreeMap<&String, BTreeMap<u64, BrightnessSample>> = BTreeMap::new();
+    for row in rows {
+        let (Some(cam_name), Ok(frame)) = (camn2camid.get(&row.camn), u64::try_from(row.frame))
+        else {
+            continue;
+        };
+        let brightness = row.cur_val as f64;
+        let sample =
+            per_cam
+                .entry(cam_name)
+                .or_default()
+                .entry(frame)
+                .or_insert(BrightnessSample {
+                    frame,
+                    timestamp: row.timestamp.as_ref().map(|t| t.as_f64()),
+                    brightness,
+                });
+        sample.brightness = sample.brightness.max(brightness);
+    }
+    per_cam
+        .into_iter()
+        .map(|(cam_name, samples)| (cam_name.clone(), samples.into_values().collect()))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncCheckConfig {
+    /// Largest frame offset which is searched.
+    pub max_lag: u32,
+    /// Minimum brightness difference between the LED on and off.
+    pub min_contrast: f64,
+    /// Minimum fraction of frames showing the expected LED state.
+    pub min_agreement: f64,
+}
+
+impl Default for SyncCheckConfig {
+    fn default() -> Self {
+        Self {
+            max_lag: 10,
+            min_contrast: 10.0,
+            min_agreement: 0.9,
+        }
+    }
+}
+
+/// The measured synchronization of one camera.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameOffset {
+    /// Number of frames during the flash sequence.
+    pub n_frames: usize,
+    /// Frame `n` of this camera shows the LED at the time of frame `n + offset`.
+    pub offset: i64,
+    /// The offset relative to the median offset of all cameras.
+    ///
+    /// Unlike `offset`, this does not depend on the latency of the LED box.
+    pub relative_offset: i64,
+    /// Fraction of frames showing the expected LED state at `offset`.
+    pub agreement: f64,
+    /// Number of LED changes seen.
+    pub n_edges: usize,
+    /// Root mean square error, in frames, of the frames on which the LED
+    /// changes were seen.
+    pub jitter: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CameraSync {
+    /// The brightness of the LED did not vary enough.
+    NoFlashes {
+        contrast: f64,
+    },
+    Measured(FrameOffset),
+}
+
+impl CameraSync {
+    fn is_synchronized(&self, min_agreement: f64) -> bool {
+        match self {
+            CameraSync::NoFlashes { .. } => false,
+            CameraSync::Measured(m) => m.relative_offset == 0 && m.agreement >= min_agreement,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncReport {
+    /// Duration of a frame, in seconds.
+    pub frame_period: f64,
+    pub min_agreement: f64,
+    pub cameras: BTreeMap<String, CameraSync>,
+}
+
+impl SyncReport {
+    /// Names of the cameras which could not be verified to be in sync.
+    pub fn out_of_sync(&self) -> Vec<&str> {
+        self.cameras
+            .iter()
+            .filter(|(_, cs)| !cs.is_synchronized(self.min_agreement))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+impl std::fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Frame period: {:.3} ms", self.frame_period * 1000.0)?;
+        writeln!(
+            f,
+            "{:<20} {:>7} {:>9} {:>10} {:>6} {:>7}  status",
+            "camera", "offset", "relative", "agreement", "edges", "jitter"
+        )?;
+        for (name, cs) in self.cameras.iter() {
+            let status = if cs.is_synchronized(self.min_agreement) {
+                "ok"
+            } else {
+                "OUT OF SYNC"
+            };
+            match cs {
+                CameraSync::NoFlashes { contrast } => writeln!(
+                    f,
+                    "{name:<20} no flashes detected (contrast {contrast:.1})  {status}"
+                )?,
+                CameraSync::Measured(m) => writeln!(
+                    f,
+                    "{:<20} {:>7} {:>9} {:>9.1}% {:>6} {:>7.2}  {}",
+                    name,
+                    m.offset,
+                    m.relative_offset,
+                    m.agreement * 100.0,
+                    m.n_edges,
+                    m.jitter,
+                    status
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Linear model of the trigger timestamp of each synchronized frame.
+struct FrameTimes {
+    frame0: u64,
+    mean_frame: f64,
+    mean_time: f64,
+    period: f64,
+}
+
+impl FrameTimes {
+    /// Least squares fit of the trigger timestamps of all samples.
+    fn fit(cameras: &BTreeMap<String, Vec<BrightnessSample>>) -> Result<Self> {
+        let stamped: Vec<(u64, f64)> = cameras
+            .values()
+            .flatten()
+            .filter_map(|s| s.timestamp.map(|t| (s.frame, t)))
+            .collect();
+        let frame0 = stamped
+            .iter()
+            .map(|x| x.0)
+            .min()
+            .ok_or(Error::NoTimestamps)?;
+        let n = stamped.len() as f64;
+        let mean_frame = stamped.iter().map(|x| (x.0 - frame0) as f64).sum::<f64>() / n;
+        let mean_time = stamped.iter().map(|x| x.1).sum::<f64>() / n;
+        let (mut cov, mut var) = (0.0, 0.0);
+        for (frame, t) in stamped.iter() {
+            let df = (frame - frame0) as f64 - mean_frame;
+            cov += df * (t - mean_time);
+            var += df * df;
+        }
+        if var == 0.0 {
+            return Err(Error::NoTimestamps);
+        }
+        Ok(Self {
+            frame0,
+            mean_frame,
+            mean_time,
+            period: cov / var,
+        })
+    }
+
+    /// The time of (possibly fractional) frame `frame0 + offset`.
+    fn time(&self, offset: f64) -> f64 {
+        self.mean_time + self.period * (offset - self.mean_frame)
+    }
+
+    /// The frame, relative to `frame0`, at time `t`.
+    fn frame_offset(&self, t: f64) -> f64 {
+        self.mean_frame + (t - self.mean_time) / self.period
+    }
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let idx = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[idx]
+}
+
+/// Compare the brightness of the LED in each camera with the flashes.
+pub fn check_sync(
+    log: &FlashLog,
+    cameras: &BTreeMap<String, Vec<BrightnessSample>>,
+    cfg: &SyncCheckConfig,
+) -> Result<SyncReport> {
+    let times = FrameTimes::fit(cameras)?;
+    let max_lag = cfg.max_lag as i64;
+    // Search small offsets first so that ties resolve to the smallest offset.
+    let lags: Vec<i64> = (0..=max_lag).flat_map(|lag| [-lag, lag]).skip(1).collect();
+
+    let mut results: BTreeMap<String, CameraSync> = BTreeMap::new();
+    for (cam_name, samples) in cameras.iter() {
+        // Frames during the flash sequence, relative to `frame0`.
+        let during: BTreeMap<i64, f64> = samples
+            .iter()
+            .map(|s| ((s.frame - times.frame0) as i64, s.brightness))
+            .filter(|(frame, _)| log.state_at(times.time(*frame as f64)).is_some())
+            .collect();
+
+        let mut sorted: Vec<f64> = during.values().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let (low, high) = if sorted.is_empty() {
+            (0.0, 0.0)
+        } else {
+            (percentile(&sorted, 0.1), percentile(&sorted, 0.9))
+        };
+        let contrast = high - low;
+        if contrast < cfg.min_contrast {
+            results.insert(cam_name.clone(), CameraSync::NoFlashes { contrast });
+            continue;
+        }
+        let threshold = (low + high) / 2.0;
+        let observed: BTreeMap<i64, bool> = during
+            .iter()
+            .map(|(frame, brightness)| (*frame, *brightness > threshold))
+            .collect();
+
+        let mut best: Option<(i64, f64)> = None;
+        for lag in lags.iter() {
+            let (mut n, mut n_match) = (0usize, 0usize);
+            for (frame, on) in observed.iter() {
+                if let Some(expected) = log.state_at(times.time((frame + lag) as f64)) {
+                    n += 1;
+                    if expected == *on {
+                        n_match += 1;
+                    }
+                }
+            }
+            if n > 0 {
+                let agreement = n_match as f64 / n as f64;
+                if !matches!(best, Some((_, b)) if b >= agreement) {
+                    best = Some((*lag, agreement));
+                }
+            }
+        }
+        let Some((offset, agreement)) = best else {
+            results.insert(cam_name.clone(), CameraSync::NoFlashes { contrast });
+            continue;
+        };
+
+        // Compare the first frame showing each change of the LED with the
+        // first frame after the change.
+        let errors: Vec<f64> = observed
+            .iter()
+            .zip(observed.iter().skip(1))
+            .filter(|((f0, on0), (f1, on1))| *f1 - *f0 == 1 && on0 != on1)
+            .filter_map(|(_, (frame, on))| {
+                let seen = (frame + offset) as f64;
+                log.changes()
+                    .filter(|ev| ev.on == *on)
+                    .map(|ev| seen - times.frame_offset(ev.time).ceil())
+                    .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+            })
+            .collect();
+        let jitter = if errors.is_empty() {
+            0.0
+        } else {
+            (errors.iter().map(|e| e * e).sum::<f64>() / errors.len() as f64).sqrt()
+        };
+
+        results.insert(
+            cam_name.clone(),
+            CameraSync::Measured(FrameOffset {
+                n_frames: observed.len(),
+                offset,
+                relative_offset: 0,
+                agreement,
+                n_edges: errors.len(),
+                jitter,
+            }),
+        );
+    }
+
+    let mut offsets: Vec<i64> = results
+        .values()
+        .filter_map(|cs| match cs {
+            CameraSync::Measured(m) => Some(m.offset),
+            CameraSync::NoFlashes { .. } => None,
+        })
+        .collect();
+    offsets.sort();
+    if let Some(median) = offsets.get(offsets.len() / 2).copied() {
+        for cs in results.values_mut() {
+            if let CameraSync::Measured(m) = cs {
+                m.relative_offset = m.offset - median;
+            }
+        }
+    }
+
+    Ok(SyncReport {
+        frame_period: times.period,
+        min_agreement: cfg.min_agreement,
+        cameras: results,
+    })
+}
@@ -0,0 +1,132 @@
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{self, Result, WrapErr};
+use std::{path::PathBuf, time::Duration};
+
+use braid_sync_check::{FlashLog, FlashSequence, SerialLedBox, SyncCheckConfig};
+
+#[derive(Debug, Parser)]
+#[command(author, version, about)]
+struct Opt {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Flash an LED of the LED box while Braid is recording
+    Flash {
+        /// Serial device of the LED box
+        #[arg(short, long)]
+        device: String,
+
+        /// LED channel (1 to 4)
+        #[arg(short, long, default_value_t = 1)]
+        channel: u8,
+
+        /// Seed of the pseudo-random flash sequence
+        #[arg(long, default_value_t = 1)]
+        seed: u16,
+
+        /// Number of states in the flash sequence
+        #[arg(short, long, default_value_t = 255)]
+        n_flashes: usize,
+
+        /// Duration of each state, in milliseconds
+        #[arg(long, default_value_t = 50)]
+        flash_duration_ms: u64,
+
+        /// Output filename of the flash log
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Check the synchronization of the cameras in a .braidz file
+    ///
+    /// The LED must be the brightest point in the view of each camera and
+    /// the data must have been saved with empty 2D detections.
+    Check {
+        /// Input .braidz file
+        input: PathBuf,
+
+        /// Flash log saved by the `flash` command
+        #[arg(short, long)]
+        flash_log: PathBuf,
+
+        /// Largest frame offset which is searched
+        #[arg(long, default_value_t = SyncCheckConfig::default().max_lag)]
+        max_lag: u32,
+
+        /// Minimum brightness difference between the LED on and off
+        #[arg(long, default_value_t = SyncCheckConfig::default().min_contrast)]
+        min_contrast: f64,
+
+        /// Minimum fraction of frames showing the expected LED state
+        #[arg(long, default_value_t = SyncCheckConfig::default().min_agreement)]
+        min_agreement: f64,
+    },
+}
+
+fn main() -> Result<()> {
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    env_tracing_logger::init();
+    let opt = Opt::parse();
+
+    match opt.command {
+        Command::Flash {
+            device,
+            channel,
+            seed,
+            n_flashes,
+            flash_duration_ms,
+            output,
+        } => {
+            let mut led_box = SerialLedBox::open(&device)
+                .with_context(|| format!("Opening LED box at {device}"))?;
+            let sequence = FlashSequence::new(seed, n_flashes);
+            tracing::info!("Flashing LED channel {channel} {n_flashes} times");
+            let log = braid_sync_check::run_flash_sequence(
+                &mut led_box,
+                channel,
+                &sequence,
+                Duration::from_millis(flash_duration_ms),
+            )?;
+            let buf = serde_json::to_vec_pretty(&log)?;
+            std::fs::write(&output, buf)
+                .with_context(|| format!("Writing file {}", output.display()))?;
+            tracing::info!("Saved flash log to {}", output.display());
+        }
+        Command::Check {
+            input,
+            flash_log,
+            max_lag,
+            min_contrast,
+            min_agreement,
+        } => {
+            let log: FlashLog = serde_json::from_slice(
+                &std::fs::read(&flash_log)
+                    .with_context(|| format!("Reading file {}", flash_log.display()))?,
+            )?;
+            let mut archive = braidz_parser::braidz_parse_path(&input)
+                .with_context(|| format!("Parsing file {}", input.display()))?;
+            let camn2camid = archive.cam_info.camn2camid.clone();
+            let rows = archive
+                .iter_data2d_distorted()?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let cameras = braid_sync_check::brightness_from_data2d(rows, &camn2camid);
+            let cfg = SyncCheckConfig {
+                max_lag,
+                min_contrast,
+                min_agreement,
+            };
+            let report = braid_sync_check::check_sync(&log, &cameras, &cfg)?;
+            print!("{report}");
+
+            let out_of_sync = report.out_of_sync();
+            if !out_of_sync.is_empty() {
+                eyre::bail!("Cameras not in sync: {}", out_of_sync.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
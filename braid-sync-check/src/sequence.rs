@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+/// Feedback taps of a maximal length 16 bit Galois LFSR.
+const LFSR_TAPS: u16 = 0xB400;
+
+/// Seed used in place of zero, for which the LFSR would be stuck.
+const DEFAULT_SEED: u16 = 0xACE1;
+
+/// A pseudo-random sequence of LED states.
+///
+/// The states are generated with a linear feedback shift register. Unlike a
+/// periodic blinking pattern, the sequence has a single sharp correlation peak
+/// so that a frame offset between the flashes and a camera is unambiguous.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlashSequence {
+    seed: u16,
+    states: Vec<bool>,
+}
+
+impl FlashSequence {
+    /// Create a sequence of `len` states starting from `seed`.
+    pub fn new(seed: u16, len: usize) -> Self {
+        let seed = if seed == 0 { DEFAULT_SEED } else { seed };
+        let mut lfsr = seed;
+        let states = (0..len)
+            .map(|_| {
+                let bit = lfsr & 1 == 1;
+                lfsr >>= 1;
+                if bit {
+                    lfsr ^= LFSR_TAPS;
+                }
+                bit
+            })
+            .collect();
+        Self { seed, states }
+    }
+
+    pub fn seed(&self) -> u16 {
+        self.seed
+    }
+
+    /// The LED states, `true` meaning on.
+    pub fn states(&self) -> &[bool] {
+        &self.states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence() {
+        let seq = FlashSequence::new(1, 1000);
+        assert_eq!(seq, FlashSequence::new(1, 1000));
+        assert_ne!(seq.states(), FlashSequence::new(2, 1000).states());
+        assert_eq!(FlashSequence::new(0, 10).seed(), DEFAULT_SEED);
+
+        // The sequence is balanced and changes often.
+        let n_on = seq.states().iter().filter(|x| **x).count();
+        assert!((400..600).contains(&n_on), "{n_on} of 1000 on");
+        let n_changes = seq.states().windows(2).filter(|w| w[0] != w[1]).count();
+        assert!((400..600).contains(&n_changes), "{n_changes} changes");
+    }
+}
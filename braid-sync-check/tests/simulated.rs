@@ -0,0 +1,80 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use braid_sync_check::{
+    check_sync, roi_brightness, run_flash_sequence, BrightnessSample, CameraSync, FlashSequence,
+    Roi, SimulatedLedBox, SyncCheckConfig,
+};
+
+const WIDTH: usize = 32;
+const FRAME_PERIOD: f64 = 0.005;
+const ROI: Roi = Roi {
+    x: 12,
+    y: 12,
+    width: 8,
+    height: 8,
+};
+
+/// Render a frame with the LED, if visible, in the ROI.
+fn render(led_on: bool, noise: u8) -> Vec<u8> {
+    let mut image = vec![20 + noise; WIDTH * WIDTH];
+    if led_on {
+        for row in image.chunks_mut(WIDTH).skip(ROI.y).take(ROI.height) {
+            row[ROI.x..ROI.x + ROI.width].fill(200 + noise);
+        }
+    }
+    image
+}
+
+#[test]
+fn test_simulated_cameras() {
+    let mut led_box = SimulatedLedBox::new(0.0);
+    let sequence = FlashSequence::new(1, 63);
+    let log = run_flash_sequence(&mut led_box, 1, &sequence, Duration::from_millis(20)).unwrap();
+
+    let frame0 = 1000;
+    let t0 = log.events[0].time - 0.1;
+    let n_frames = ((log.events.last().unwrap().time - t0 + 0.1) / FRAME_PERIOD) as u64;
+
+    // Camera name, frame offset and whether the LED is visible.
+    let cams = [
+        ("cam1", 0, true),
+        ("cam2", 0, true),
+        ("cam3", 1, true),
+        ("cam4", 0, false),
+    ];
+    let mut cameras = BTreeMap::new();
+    for (cam_name, offset, visible) in cams {
+        let samples: Vec<BrightnessSample> = (0..n_frames)
+            .map(|i| {
+                let t = t0 + (i as i64 + offset) as f64 * FRAME_PERIOD;
+                let noise = ((i * 7919) % 9) as u8;
+                let image = render(visible && led_box.is_on(1, t), noise);
+                BrightnessSample {
+                    frame: frame0 + i,
+                    timestamp: Some(t0 + i as f64 * FRAME_PERIOD),
+                    brightness: roi_brightness(&image, WIDTH, &ROI),
+                }
+            })
+            .collect();
+        cameras.insert(cam_name.to_string(), samples);
+    }
+
+    let report = check_sync(&log, &cameras, &SyncCheckConfig::default()).unwrap();
+    assert!((report.frame_period - FRAME_PERIOD).abs() < 1e-9);
+    assert_eq!(report.out_of_sync(), vec!["cam3", "cam4"]);
+
+    for (cam_name, offset) in [("cam1", 0), ("cam2", 0), ("cam3", 1)] {
+        let CameraSync::Measured(m) = &report.cameras[cam_name] else {
+            panic!("no flashes detected for {cam_name}");
+        };
+        assert_eq!(m.offset, offset);
+        assert_eq!(m.relative_offset, offset);
+        assert!(m.agreement > 0.99, "{cam_name}: {m:?}");
+        assert!(m.n_edges > 20, "{cam_name}: {m:?}");
+        assert!(m.jitter < 0.5, "{cam_name}: {m:?}");
+    }
+    assert!(matches!(
+        report.cameras["cam4"],
+        CameraSync::NoFlashes { .. }
+    ));
+}
@@ -0,0 +1,177 @@
+//! Model of the pulse timer of the triggerbox.
+
+use std::time::Instant;
+
+use crate::protocol::{Prescaler, BASE_CLOCK_HZ};
+
+/// Pulse timer of the emulated triggerbox.
+///
+/// The timer runs from the device clock, which deviates from the host clock
+/// by `clock_drift_ppm`. Pulses are counted as a real number so that changes
+/// of the rate or the drift take effect from the time of the change.
+#[derive(Debug, Clone)]
+pub(crate) struct Timer {
+    top: u16,
+    prescaler: Prescaler,
+    clock_drift_ppm: f64,
+    /// Pulses counted up to `since`.
+    pulses: f64,
+    /// Time of the last update while running, `None` if stopped.
+    since: Option<Instant>,
+}
+
+impl Timer {
+    /// A stopped timer at 100 pulses per second.
+    pub(crate) fn new(clock_drift_ppm: f64) -> Self {
+        Self {
+            top: 19999,
+            prescaler: Prescaler::Scale8,
+            clock_drift_ppm,
+            pulses: 0.0,
+            since: None,
+        }
+    }
+
+    /// Pulses per second of host time.
+    pub(crate) fn pulse_rate(&self) -> f64 {
+        let device_clock = BASE_CLOCK_HZ * (1.0 + self.clock_drift_ppm * 1e-6);
+        device_clock / (f64::from(self.prescaler.divisor()) * (f64::from(self.top) + 1.0))
+    }
+
+    fn pulses_at(&self, now: Instant) -> f64 {
+        match self.since {
+            Some(since) => {
+                let dt = now.saturating_duration_since(since).as_secs_f64();
+                self.pulses + dt * self.pulse_rate()
+            }
+            None => self.pulses,
+        }
+    }
+
+    fn update(&mut self, now: Instant) {
+        self.pulses = self.pulses_at(now);
+        if self.since.is_some() {
+            self.since = Some(now);
+        }
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.since.is_some()
+    }
+
+    pub(crate) fn start(&mut self, now: Instant) {
+        if self.since.is_none() {
+            self.since = Some(now);
+        }
+    }
+
+    pub(crate) fn stop(&mut self, now: Instant) {
+        self.update(now);
+        self.since = None;
+    }
+
+    pub(crate) fn stop_and_reset(&mut self) {
+        self.pulses = 0.0;
+        self.since = None;
+    }
+
+    pub(crate) fn set_rate(&mut self, now: Instant, top: u16, prescaler: Prescaler) {
+        self.update(now);
+        self.top = top;
+        self.prescaler = prescaler;
+    }
+
+    pub(crate) fn set_clock_drift(&mut self, now: Instant, clock_drift_ppm: f64) {
+        self.update(now);
+        self.clock_drift_ppm = clock_drift_ppm;
+    }
+
+    /// The pulse number and timer count at `now`, as read by the firmware.
+    pub(crate) fn sample(&self, now: Instant) -> (u32, u16) {
+        let pulses = self.pulses_at(now);
+        let pulsenumber = pulses.floor();
+        let count = ((pulses - pulsenumber) * (f64::from(self.top) + 1.0)) as u16;
+        // The pulse number wraps like the 32 bit counter of the firmware.
+        ((pulsenumber as u64) as u32, count.min(self.top))
+    }
+
+    /// While running, the host time of a reference pulse and the pulse rate.
+    pub(crate) fn reference(&self) -> Option<(Instant, f64, f64)> {
+        self.since
+            .map(|since| (since, self.pulses, self.pulse_rate()))
+    }
+}
+
+/// Pseudo-random number generator (SplitMix64) for reproducible jitter and
+/// dropouts.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed in `[0, 1)`.
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn count_pulses_with_drift() {
+        let t0 = Instant::now();
+        let mut timer = Timer::new(100.0);
+        assert_eq!(timer.sample(t0), (0, 0));
+        timer.start(t0);
+        // 100 pulses per second on the nominal clock and 100 ppm fast.
+        let (pulsenumber, count) = timer.sample(t0 + Duration::from_secs(100));
+        assert_eq!(pulsenumber, 10_001);
+        assert!(count < 10, "count {count}");
+
+        // Stopping keeps the count, resetting clears it.
+        let t1 = t0 + Duration::from_millis(100_005);
+        timer.stop(t1);
+        let stopped = timer.sample(t1);
+        assert_eq!(stopped.0, 10_001);
+        assert_eq!(timer.sample(t1 + Duration::from_secs(1)), stopped);
+        timer.stop_and_reset();
+        assert_eq!(timer.sample(t1), (0, 0));
+    }
+
+    #[test]
+    fn change_rate_while_running() {
+        let t0 = Instant::now();
+        let mut timer = Timer::new(0.0);
+        timer.start(t0);
+        let t1 = t0 + Duration::from_secs(1);
+        // 2 MHz / 40000 = 50 pulses per second
+        timer.set_rate(t1, 39999, Prescaler::Scale8);
+        let (pulsenumber, _) = timer.sample(t1 + Duration::from_millis(1001));
+        assert_eq!(pulsenumber, 150);
+    }
+
+    #[test]
+    fn rng_is_uniform() {
+        let mut rng = Rng::new(1);
+        let n = 10_000;
+        let samples: Vec<f64> = (0..n).map(|_| rng.next_f64()).collect();
+        assert!(samples.iter().all(|x| (0.0..1.0).contains(x)));
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        assert!((mean - 0.5).abs() < 0.02, "mean {mean}");
+    }
+}
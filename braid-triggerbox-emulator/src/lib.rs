@@ -0,0 +1,299 @@
+//! Software emulation of the Straw Lab triggerbox.
+//!
+//! The [Emulator] opens a pseudo-terminal and answers the serial
+//! [protocol] of the triggerbox firmware on it. Braid (or any other host)
+//! uses the path from [Emulator::device_path] in place of the real device,
+//! for example as `device_fname` of the `TriggerboxV1` trigger configuration.
+//!
+//! The clock of the emulated device runs with a configurable drift relative
+//! to the host clock, and clock queries are answered with a configurable
+//! latency, random jitter and random dropouts. Because the emulator knows the
+//! true relation between pulse numbers and host time
+//! ([Emulator::true_clock_model]), the clock model fitted by the host can be
+//! checked automatically.
+//!
+//! Only Unix is supported.
+use std::{
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serialport::{SerialPort, TTYPort};
+use tracing::{debug, trace};
+
+mod device;
+pub mod protocol;
+
+use device::{Rng, Timer};
+use protocol::{Command, Parsed};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("pseudo-terminal has no name")]
+    NoDevicePath,
+    #[error("emulator thread panicked")]
+    ThreadPanicked,
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Serial(#[from] serialport::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// How the emulated triggerbox deviates from an ideal one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmulatorConfig {
+    /// Deviation of the device clock from the host clock, in parts per
+    /// million. Positive values make the pulses faster than requested.
+    pub clock_drift_ppm: f64,
+    /// Delay between receiving a clock query and sampling the clock, and
+    /// again between sampling and sending the reply.
+    pub latency: Duration,
+    /// Maximum random delay added to each of the two latencies. Unequal
+    /// delays before and after sampling cause an error in the host's estimate
+    /// of the sampling time.
+    pub jitter: Duration,
+    /// Probability that a clock query is not answered.
+    pub dropout_probability: f64,
+    /// Seed of the pseudo-random jitter and dropouts.
+    pub seed: u64,
+}
+
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        Self {
+            clock_drift_ppm: 0.0,
+            latency: Duration::from_millis(1),
+            jitter: Duration::ZERO,
+            dropout_probability: 0.0,
+            seed: 1,
+        }
+    }
+}
+
+/// Counts of the clock queries received by the emulator.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub queries: u64,
+    /// Queries left unanswered due to `dropout_probability`.
+    pub dropped: u64,
+}
+
+/// The true relation `time = gain * pulsenumber + offset` between the pulse
+/// number and the host time, in the form of Braid's clock model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrueClockModel {
+    /// Seconds of host time per pulse.
+    pub gain: f64,
+    /// Host time of pulse number zero, in seconds since the Unix epoch.
+    pub offset: f64,
+}
+
+struct Shared {
+    config: EmulatorConfig,
+    timer: Timer,
+    stats: Stats,
+}
+
+/// Relates [Instant] to the host time in seconds since the Unix epoch.
+#[derive(Debug, Clone, Copy)]
+struct HostClock {
+    instant: Instant,
+    unix_secs: f64,
+}
+
+impl HostClock {
+    fn now() -> Self {
+        let instant = Instant::now();
+        let unix_secs = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+        Self { instant, unix_secs }
+    }
+
+    fn unix_secs(&self, instant: Instant) -> f64 {
+        if instant >= self.instant {
+            self.unix_secs + (instant - self.instant).as_secs_f64()
+        } else {
+            self.unix_secs - (self.instant - instant).as_secs_f64()
+        }
+    }
+}
+
+/// An emulated triggerbox, running until dropped.
+pub struct Emulator {
+    device_path: String,
+    shared: Arc<Mutex<Shared>>,
+    host_clock: HostClock,
+    quit: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<()>>>,
+    /// Keeps the pseudo-terminal open while no host is connected.
+    _slave: TTYPort,
+}
+
+impl Emulator {
+    /// Open a pseudo-terminal and start answering on it.
+    pub fn spawn(config: EmulatorConfig) -> Result<Self> {
+        let (mut master, slave) = TTYPort::pair()?;
+        let device_path = slave.name().ok_or(Error::NoDevicePath)?;
+        master.set_timeout(Duration::from_millis(50))?;
+
+        let shared = Arc::new(Mutex::new(Shared {
+            timer: Timer::new(config.clock_drift_ppm),
+            stats: Stats::default(),
+            config,
+        }));
+        let quit = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let shared = shared.clone();
+            let quit = quit.clone();
+            std::thread::Builder::new()
+                .name("triggerbox-emulator".to_string())
+                .spawn(move || run(&mut master, &shared, &quit))?
+        };
+        debug!("emulated triggerbox at {device_path}");
+        Ok(Self {
+            device_path,
+            shared,
+            host_clock: HostClock::now(),
+            quit,
+            thread: Some(thread),
+            _slave: slave,
+        })
+    }
+
+    /// Path of the pseudo-terminal to which the host connects.
+    pub fn device_path(&self) -> &str {
+        &self.device_path
+    }
+
+    pub fn config(&self) -> EmulatorConfig {
+        self.shared.lock().unwrap().config.clone()
+    }
+
+    /// Change the behavior of the running emulator, for example to simulate
+    /// a period of dropouts.
+    pub fn set_config(&self, config: EmulatorConfig) {
+        let mut shared = self.shared.lock().unwrap();
+        if config.clock_drift_ppm != shared.config.clock_drift_ppm {
+            shared
+                .timer
+                .set_clock_drift(Instant::now(), config.clock_drift_ppm);
+        }
+        shared.config = config;
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.shared.lock().unwrap().stats
+    }
+
+    /// Whether the host started the pulses.
+    pub fn is_pulsing(&self) -> bool {
+        self.shared.lock().unwrap().timer.is_running()
+    }
+
+    /// The current relation of pulse numbers to host time, `None` while the
+    /// pulses are stopped.
+    pub fn true_clock_model(&self) -> Option<TrueClockModel> {
+        let (instant, pulses, rate) = self.shared.lock().unwrap().timer.reference()?;
+        let gain = 1.0 / rate;
+        Some(TrueClockModel {
+            gain,
+            offset: self.host_clock.unix_secs(instant) - pulses * gain,
+        })
+    }
+
+    /// Block until the emulator fails.
+    pub fn join(mut self) -> Result<()> {
+        let thread = self.thread.take().unwrap();
+        thread.join().map_err(|_| Error::ThreadPanicked)?
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        self.quit.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+fn run(port: &mut TTYPort, shared: &Mutex<Shared>, quit: &AtomicBool) -> Result<()> {
+    let mut rng = Rng::new(shared.lock().unwrap().config.seed);
+    let mut buf = Vec::new();
+    let mut read_buf = [0u8; 64];
+    while !quit.load(Ordering::Relaxed) {
+        match port.read(&mut read_buf) {
+            Ok(n) => buf.extend_from_slice(&read_buf[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(e) => return Err(e.into()),
+        }
+        loop {
+            match protocol::parse_command(&buf) {
+                Parsed::Incomplete => break,
+                Parsed::Invalid(n) => {
+                    debug!("ignoring invalid bytes {:?}", &buf[..n]);
+                    buf.drain(..n);
+                }
+                Parsed::Command(cmd, n) => {
+                    buf.drain(..n);
+                    trace!("received {cmd:?}");
+                    handle_command(port, shared, &mut rng, cmd)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_command(
+    port: &mut TTYPort,
+    shared: &Mutex<Shared>,
+    rng: &mut Rng,
+    cmd: Command,
+) -> Result<()> {
+    let now = Instant::now();
+    match cmd {
+        Command::Version => {
+            port.write_all(&protocol::encode_version())?;
+        }
+        Command::Query(qi) => {
+            let (before, after) = {
+                let mut shared = shared.lock().unwrap();
+                shared.stats.queries += 1;
+                let config = &shared.config;
+                if rng.next_f64() < config.dropout_probability {
+                    shared.stats.dropped += 1;
+                    debug!("dropping clock query {qi}");
+                    return Ok(());
+                }
+                let (latency, jitter) = (config.latency, config.jitter);
+                (
+                    latency + jitter.mul_f64(rng.next_f64()),
+                    latency + jitter.mul_f64(rng.next_f64()),
+                )
+            };
+            std::thread::sleep(before);
+            let (pulsenumber, count) = shared.lock().unwrap().timer.sample(Instant::now());
+            std::thread::sleep(after);
+            port.write_all(&protocol::encode_sample(qi, pulsenumber, count))?;
+        }
+        Command::StopPulses => shared.lock().unwrap().timer.stop(now),
+        Command::StartPulses => shared.lock().unwrap().timer.start(now),
+        Command::StopPulsesAndReset => shared.lock().unwrap().timer.stop_and_reset(),
+        Command::SetRate { top, prescaler } => {
+            shared.lock().unwrap().timer.set_rate(now, top, prescaler);
+        }
+        Command::SetAOut(..) => {}
+    }
+    port.flush()?;
+    Ok(())
+}
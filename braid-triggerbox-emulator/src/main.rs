@@ -0,0 +1,64 @@
+use clap::Parser;
+use color_eyre::eyre::{Result, WrapErr};
+use std::{path::PathBuf, time::Duration};
+
+use braid_triggerbox_emulator::{Emulator, EmulatorConfig};
+
+#[derive(Debug, Parser)]
+#[command(author, version, about)]
+struct Opt {
+    /// Deviation of the device clock from the host clock, in parts per million
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    clock_drift_ppm: f64,
+
+    /// Delay before sampling the clock and again before replying, in
+    /// milliseconds
+    #[arg(long, default_value_t = 1.0)]
+    latency_ms: f64,
+
+    /// Maximum random delay added to each latency, in milliseconds
+    #[arg(long, default_value_t = 0.0)]
+    jitter_ms: f64,
+
+    /// Probability that a clock query is not answered
+    #[arg(long, default_value_t = 0.0)]
+    dropout_probability: f64,
+
+    /// Seed of the pseudo-random jitter and dropouts
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+
+    /// Create a symbolic link to the pseudo-terminal at this path (e.g.
+    /// `/tmp/trig1`), for use as `device_fname` in the Braid configuration
+    #[arg(long)]
+    link: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    env_tracing_logger::init();
+    let opt = Opt::parse();
+
+    let emulator = Emulator::spawn(EmulatorConfig {
+        clock_drift_ppm: opt.clock_drift_ppm,
+        latency: Duration::from_secs_f64(opt.latency_ms * 1e-3),
+        jitter: Duration::from_secs_f64(opt.jitter_ms * 1e-3),
+        dropout_probability: opt.dropout_probability,
+        seed: opt.seed,
+    })?;
+    tracing::info!("Emulated triggerbox at {}", emulator.device_path());
+
+    if let Some(link) = &opt.link {
+        if link.symlink_metadata().is_ok_and(|m| m.is_symlink()) {
+            std::fs::remove_file(link)?;
+        }
+        std::os::unix::fs::symlink(emulator.device_path(), link)
+            .with_context(|| format!("Creating symbolic link {}", link.display()))?;
+        tracing::info!("Linked from {}", link.display());
+    }
+
+    emulator.join()?;
+    Ok(())
+}
@@ -0,0 +1,203 @@
+//! The serial protocol of the triggerbox firmware.
+//!
+//! Commands from the host are two ASCII bytes, some followed by binary
+//! arguments:
+//!
+//! - `V?` requests the firmware version, answered with `V=<version>\r\n`.
+//! - `P<qi>` samples the clock. The reply is `P`, the query index `qi`, the
+//!   pulse number (`u32`), the timer count within the current pulse (`u16`)
+//!   and a CRC-8/MAXIM-DOW checksum of the preceding eight bytes.
+//! - `S0` stops the pulses, `S1` starts them and `S2` stops them and resets
+//!   the pulse number to zero.
+//! - `T=` followed by the timer top value (`u16`) and the prescaler key
+//!   (`1` for 8, `2` for 64) sets the pulse rate.
+//! - `O=` followed by two `u16` values sets the analog outputs.
+//!
+//! All integers are little endian.
+
+/// Clock frequency of the triggerbox microcontroller.
+pub const BASE_CLOCK_HZ: f64 = 16e6;
+
+/// Firmware version reported by the emulator.
+pub const FIRMWARE_VERSION: u8 = 15;
+
+/// Length of the reply to a clock query.
+pub const SAMPLE_REPLY_LEN: usize = 9;
+
+/// Divisor of the base clock which drives the pulse timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prescaler {
+    Scale8,
+    Scale64,
+}
+
+impl Prescaler {
+    pub fn divisor(&self) -> u16 {
+        match self {
+            Prescaler::Scale8 => 8,
+            Prescaler::Scale64 => 64,
+        }
+    }
+
+    fn from_key(key: u8) -> Option<Self> {
+        match key {
+            b'1' => Some(Prescaler::Scale8),
+            b'2' => Some(Prescaler::Scale64),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Version,
+    /// Sample the clock, with the query index echoed in the reply.
+    Query(u8),
+    StopPulses,
+    StartPulses,
+    StopPulsesAndReset,
+    /// The pulse period is `prescaler * (top + 1)` ticks of the base clock.
+    SetRate {
+        top: u16,
+        prescaler: Prescaler,
+    },
+    SetAOut(u16, u16),
+}
+
+/// Result of [parse_command].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parsed {
+    /// A command and the number of bytes it used.
+    Command(Command, usize),
+    /// The buffer ends within a command.
+    Incomplete,
+    /// This number of bytes does not start a valid command and is skipped.
+    Invalid(usize),
+}
+
+/// Parse the command at the start of `buf`.
+pub fn parse_command(buf: &[u8]) -> Parsed {
+    let (cmd, value) = match buf {
+        [] | [_] => return Parsed::Incomplete,
+        [cmd, value, ..] => (*cmd, *value),
+    };
+    let args = &buf[2..];
+    let simple = |cmd| Parsed::Command(cmd, 2);
+    match (cmd, value) {
+        (b'V', b'?') => simple(Command::Version),
+        (b'P', qi) => simple(Command::Query(qi)),
+        (b'S', b'0') => simple(Command::StopPulses),
+        (b'S', b'1') => simple(Command::StartPulses),
+        (b'S', b'2') => simple(Command::StopPulsesAndReset),
+        (b'T', b'=') => match args {
+            [lo, hi, key, ..] => match Prescaler::from_key(*key) {
+                Some(prescaler) => Parsed::Command(
+                    Command::SetRate {
+                        top: u16::from_le_bytes([*lo, *hi]),
+                        prescaler,
+                    },
+                    5,
+                ),
+                None => Parsed::Invalid(5),
+            },
+            _ => Parsed::Incomplete,
+        },
+        (b'O', b'=') => match args {
+            [a0, a1, b0, b1, ..] => Parsed::Command(
+                Command::SetAOut(
+                    u16::from_le_bytes([*a0, *a1]),
+                    u16::from_le_bytes([*b0, *b1]),
+                ),
+                6,
+            ),
+            _ => Parsed::Incomplete,
+        },
+        _ => Parsed::Invalid(1),
+    }
+}
+
+/// Reply to [Command::Version].
+pub fn encode_version() -> Vec<u8> {
+    format!("V={FIRMWARE_VERSION}\r\n").into_bytes()
+}
+
+/// Reply to [Command::Query].
+pub fn encode_sample(qi: u8, pulsenumber: u32, count: u16) -> [u8; SAMPLE_REPLY_LEN] {
+    let mut buf = [0u8; SAMPLE_REPLY_LEN];
+    buf[0] = b'P';
+    buf[1] = qi;
+    buf[2..6].copy_from_slice(&pulsenumber.to_le_bytes());
+    buf[6..8].copy_from_slice(&count.to_le_bytes());
+    buf[8] = crc8_maxim(&buf[..8]);
+    buf
+}
+
+/// CRC-8/MAXIM-DOW, as used by the 1-Wire bus.
+pub fn crc8_maxim(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x8C
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc8_maxim(b"123456789"), 0xA1);
+    }
+
+    #[test]
+    fn parse_commands() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"V?P\x07S2T=");
+        buf.extend_from_slice(&19999u16.to_le_bytes());
+        buf.extend_from_slice(b"1S1");
+
+        let mut cmds = Vec::new();
+        let mut rest = &buf[..];
+        while let Parsed::Command(cmd, n) = parse_command(rest) {
+            cmds.push(cmd);
+            rest = &rest[n..];
+        }
+        assert!(rest.is_empty());
+        assert_eq!(
+            cmds,
+            vec![
+                Command::Version,
+                Command::Query(7),
+                Command::StopPulsesAndReset,
+                Command::SetRate {
+                    top: 19999,
+                    prescaler: Prescaler::Scale8
+                },
+                Command::StartPulses,
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_partial_and_invalid() {
+        assert_eq!(parse_command(b"V"), Parsed::Incomplete);
+        assert_eq!(parse_command(b"T=\x20"), Parsed::Incomplete);
+        assert_eq!(parse_command(b"xV?"), Parsed::Invalid(1));
+        assert_eq!(parse_command(b"T=\x20\x4e9"), Parsed::Invalid(5));
+    }
+
+    #[test]
+    fn sample_checksum() {
+        let buf = encode_sample(3, 123_456, 789);
+        assert_eq!(crc8_maxim(&buf), 0, "checksum over data and CRC is zero");
+        assert_eq!(u32::from_le_bytes(buf[2..6].try_into().unwrap()), 123_456);
+    }
+}
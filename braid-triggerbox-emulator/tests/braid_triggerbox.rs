@@ -0,0 +1,106 @@
+//! Run the triggerbox host code of Braid against the emulator.
+use std::time::Duration;
+
+use braid_triggerbox::{make_trig_fps_cmd, ClockModel, Cmd, TriggerClockInfoRow, TriggerboxDevice};
+use tokio::time::timeout;
+
+use braid_triggerbox_emulator::{Emulator, EmulatorConfig};
+
+const QUERY_DT: Duration = Duration::from_millis(50);
+
+/// Start the host for the emulator as Braid does and return the receivers of
+/// the clock models and the clock info rows.
+async fn start_host(
+    emulator: &Emulator,
+    max_triggerbox_measurement_error: Duration,
+) -> (
+    std::sync::mpsc::Receiver<Option<ClockModel>>,
+    tokio::sync::mpsc::Receiver<TriggerClockInfoRow>,
+) {
+    let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(20);
+    let (row_tx, row_rx) = tokio::sync::mpsc::channel(100);
+    let (model_tx, model_rx) = std::sync::mpsc::channel();
+    let on_new_clock_model = Box::new(move |tm: Option<ClockModel>| {
+        model_tx.send(tm).ok();
+    });
+
+    let (rate_cmd, _rate_actual) = make_trig_fps_cmd(100.0);
+    cmd_tx.send(Cmd::StopPulsesAndReset).await.unwrap();
+    cmd_tx.send(rate_cmd).await.unwrap();
+    cmd_tx.send(Cmd::StartPulses).await.unwrap();
+
+    let triggerbox = TriggerboxDevice::new(
+        on_new_clock_model,
+        emulator.device_path().to_string(),
+        cmd_rx,
+        Some(row_tx),
+        None,
+        max_triggerbox_measurement_error,
+        Duration::from_millis(100),
+    )
+    .await
+    .unwrap();
+    tokio::spawn(async move {
+        // Keep the command sender alive while the host runs.
+        let _cmd_tx = cmd_tx;
+        triggerbox.run_forever(QUERY_DT).await
+    });
+    (model_rx, row_rx)
+}
+
+#[tokio::test]
+async fn clock_model_follows_drifting_device() {
+    let emulator = Emulator::spawn(EmulatorConfig {
+        clock_drift_ppm: 200.0,
+        jitter: Duration::from_millis(2),
+        dropout_probability: 0.1,
+        ..Default::default()
+    })
+    .unwrap();
+    let (model_rx, mut row_rx) = start_host(&emulator, Duration::from_millis(20)).await;
+
+    let mut rows = Vec::new();
+    while rows.len() < 40 {
+        let row = timeout(Duration::from_secs(10), row_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        rows.push(row);
+    }
+    for row in rows.iter() {
+        assert!(row.start_timestamp <= row.stop_timestamp);
+    }
+    for pair in rows.windows(2) {
+        assert!(pair[0].framecount <= pair[1].framecount);
+    }
+    assert!(emulator.stats().dropped > 0);
+
+    let model = model_rx
+        .try_iter()
+        .flatten()
+        .last()
+        .expect("no clock model");
+    let truth = emulator.true_clock_model().unwrap();
+    assert!((model.gain - truth.gain).abs() / truth.gain < 1e-2);
+    let framecount = rows.last().unwrap().framecount as f64;
+    let predicted = model.gain * framecount + model.offset;
+    let expected = truth.gain * framecount + truth.offset;
+    assert!(
+        (predicted - expected).abs() < 0.01,
+        "predicted {predicted}, expected {expected}"
+    );
+}
+
+#[tokio::test]
+async fn slow_replies_exceed_max_measurement_error() {
+    let emulator = Emulator::spawn(EmulatorConfig {
+        latency: Duration::from_millis(25),
+        ..Default::default()
+    })
+    .unwrap();
+    let (model_rx, _row_rx) = start_host(&emulator, Duration::from_millis(20)).await;
+
+    tokio::time::sleep(QUERY_DT * 40).await;
+    assert!(emulator.stats().queries > 10);
+    assert!(model_rx.try_iter().flatten().next().is_none());
+}
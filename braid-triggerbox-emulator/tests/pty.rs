@@ -0,0 +1,104 @@
+use std::{
+    io::{Read, Write},
+    time::{Duration, SystemTime},
+};
+
+use braid_triggerbox_emulator::{
+    protocol::{self, SAMPLE_REPLY_LEN},
+    Emulator, EmulatorConfig,
+};
+
+fn now_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+}
+
+fn open(emulator: &Emulator) -> Box<dyn serialport::SerialPort> {
+    serialport::new(emulator.device_path(), 9600)
+        .timeout(Duration::from_millis(500))
+        .open()
+        .unwrap()
+}
+
+/// Query the clock and return the pulse number with fraction and the host
+/// time halfway between query and reply.
+fn query(port: &mut Box<dyn serialport::SerialPort>, qi: u8, top: u16) -> (f64, f64) {
+    let start = now_secs();
+    port.write_all(&[b'P', qi]).unwrap();
+    let mut buf = [0u8; SAMPLE_REPLY_LEN];
+    port.read_exact(&mut buf).unwrap();
+    let stop = now_secs();
+    assert_eq!(buf[..2], [b'P', qi]);
+    assert_eq!(protocol::crc8_maxim(&buf), 0);
+    let pulsenumber = u32::from_le_bytes(buf[2..6].try_into().unwrap());
+    let count = u16::from_le_bytes(buf[6..8].try_into().unwrap());
+    let pulses = f64::from(pulsenumber) + f64::from(count) / (f64::from(top) + 1.0);
+    (pulses, (start + stop) / 2.0)
+}
+
+#[test]
+fn clock_queries_follow_true_model() {
+    let drift_ppm = 500.0;
+    let emulator = Emulator::spawn(EmulatorConfig {
+        clock_drift_ppm: drift_ppm,
+        ..Default::default()
+    })
+    .unwrap();
+    let mut port = open(&emulator);
+
+    port.write_all(b"V?").unwrap();
+    let mut version = [0u8; 6];
+    port.read_exact(&mut version).unwrap();
+    assert_eq!(&version, b"V=15\r\n");
+
+    // 2 MHz / 10000 = 200 pulses per second
+    let top = 9999u16;
+    port.write_all(b"S2T=").unwrap();
+    port.write_all(&top.to_le_bytes()).unwrap();
+    port.write_all(b"1S1").unwrap();
+    let (pulses, _) = query(&mut port, 0, top);
+    assert!(pulses < 20.0, "pulses {pulses}");
+    assert!(emulator.is_pulsing());
+
+    let model = emulator.true_clock_model().unwrap();
+    let expected_gain = 1.0 / 200.0 / (1.0 + drift_ppm * 1e-6);
+    assert!((model.gain - expected_gain).abs() < 1e-12);
+
+    for qi in 1..10 {
+        std::thread::sleep(Duration::from_millis(20));
+        let (pulses, t) = query(&mut port, qi, top);
+        let expected = (t - model.offset) / model.gain;
+        // Allow for the scheduling of the emulator thread.
+        assert!((pulses - expected).abs() < 2.0, "{pulses} != {expected}");
+    }
+
+    // Stopping freezes the pulse number.
+    port.write_all(b"S0").unwrap();
+    let (stopped, _) = query(&mut port, 10, top);
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(query(&mut port, 11, top).0, stopped);
+    assert!(emulator.true_clock_model().is_none());
+}
+
+#[test]
+fn dropouts_leave_queries_unanswered() {
+    let emulator = Emulator::spawn(EmulatorConfig {
+        dropout_probability: 1.0,
+        ..Default::default()
+    })
+    .unwrap();
+    let mut port = open(&emulator);
+    port.write_all(&[b'P', 1]).unwrap();
+    let mut buf = [0u8; SAMPLE_REPLY_LEN];
+    let err = port.read_exact(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+    emulator.set_config(EmulatorConfig::default());
+    port.write_all(&[b'P', 2]).unwrap();
+    port.read_exact(&mut buf).unwrap();
+    assert_eq!(buf[1], 2);
+    let stats = emulator.stats();
+    assert_eq!((stats.queries, stats.dropped), (2, 1));
+}
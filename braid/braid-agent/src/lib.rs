@@ -0,0 +1,198 @@
+//! Start Strand Camera on a camera computer on behalf of Braid.
+//!
+//! Cameras in the Braid configuration with `agent = "<name>"` are started by
+//! the `braid-agent` with that name. The agent registers with Braid, waits for
+//! commands to launch or stop Strand Camera and reports when a Strand Camera
+//! process ends. If Braid is not (or no longer) reachable, the agent keeps
+//! trying to connect, so it can be left running between Braid sessions.
+//!
+//! Several agents may run on one computer as long as they have distinct names.
+use std::{path::PathBuf, sync::Arc};
+
+use color_eyre::{eyre, Result};
+use tracing::{debug, error, info, warn};
+
+use braid::strand_cam_process::StrandCamProcesses;
+use braid_http_session::MainbrainSession;
+use flydra_types::{
+    AgentCameraExited, AgentCommand, BraidCameraConfig, BraidHttpApiCallback, BuiServerAddrInfo,
+    RawCamName, RegisterAgent,
+};
+
+/// How long to wait before trying to connect to Braid again.
+const RECONNECT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// The Strand Camera processes started by this agent.
+struct Agent {
+    agent_name: String,
+    braid_url: String,
+    strand_cam_dir: PathBuf,
+    processes: StrandCamProcesses,
+}
+
+impl Agent {
+    fn handle_command(&mut self, cmd: AgentCommand) {
+        debug!("got command {cmd:?}");
+        match cmd {
+            AgentCommand::Launch(camera) => {
+                if let Err(e) = self.launch(&camera) {
+                    error!(
+                        "Could not launch Strand Camera for \"{}\": {e:?}",
+                        camera.name
+                    );
+                }
+            }
+            AgentCommand::Stop(cam_name) => {
+                if self.processes.kill(&cam_name) {
+                    info!("Stopping Strand Camera for \"{cam_name}\".");
+                } else {
+                    warn!("Cannot stop \"{cam_name}\": Strand Camera not running.");
+                }
+            }
+        }
+    }
+
+    fn launch(&mut self, camera: &BraidCameraConfig) -> Result<()> {
+        let cam_name = RawCamName::new(camera.name.clone());
+        if self.processes.is_running(&cam_name) {
+            warn!("Strand Camera for \"{cam_name}\" already running. Not launching again.");
+            return Ok(());
+        }
+
+        let exe_name = camera.start_backend.strand_cam_exe_name().ok_or_else(|| {
+            eyre::eyre!("camera \"{cam_name}\" does not specify a backend to start")
+        })?;
+        let exe = self
+            .strand_cam_dir
+            .join(format!("{exe_name}{}", std::env::consts::EXE_SUFFIX));
+
+        let args = [
+            "--camera-name".to_string(),
+            camera.name.clone(),
+            "--braid-url".to_string(),
+            self.braid_url.clone(),
+        ];
+        self.processes.spawn(&cam_name, &exe, &args)?;
+        info!("Launched Strand Camera for \"{cam_name}\".");
+        Ok(())
+    }
+
+    /// Register with Braid and process its commands until the connection
+    /// fails.
+    async fn run_session(&mut self) -> Result<()> {
+        let addr_info = BuiServerAddrInfo::parse_url_with_token(&self.braid_url)?;
+        let jar = Arc::new(parking_lot::RwLock::new(cookie_store::CookieStore::new(
+            None,
+        )));
+        let mut session = braid_http_session::create_mainbrain_session(addr_info, jar).await?;
+
+        let hostname = gethostname::gethostname().to_string_lossy().to_string();
+        session
+            .post_callback_message(BraidHttpApiCallback::RegisterAgent(RegisterAgent {
+                agent_name: self.agent_name.clone(),
+                hostname,
+            }))
+            .await?;
+        info!("Registered with Braid as agent \"{}\".", self.agent_name);
+
+        // Poll for commands in a separate task so that a request is never
+        // dropped after Braid has removed the commands from its queue.
+        let (cmd_tx, mut cmd_rx) = tokio::sync::mpsc::channel(10);
+        let poll_jh = tokio::spawn(poll_commands(
+            session.clone(),
+            self.agent_name.clone(),
+            cmd_tx,
+        ));
+        let _abort_on_drop = AbortOnDrop(poll_jh.abort_handle());
+
+        loop {
+            tokio::select! {
+                cmds = cmd_rx.recv() => {
+                    match cmds {
+                        Some(cmds) => {
+                            for cmd in cmds {
+                                self.handle_command(cmd);
+                            }
+                        }
+                        None => {
+                            return poll_jh.await?;
+                        }
+                    }
+                }
+                Some(exit) = self.processes.join_next() => {
+                    session
+                        .post_callback_message(BraidHttpApiCallback::AgentCameraExited(
+                            AgentCameraExited {
+                                agent_name: self.agent_name.clone(),
+                                raw_cam_name: exit.cam_name,
+                                success: exit.success,
+                            },
+                        ))
+                        .await?;
+                }
+            }
+        }
+    }
+}
+
+struct AbortOnDrop(tokio::task::AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn poll_commands(
+    mut session: MainbrainSession,
+    agent_name: String,
+    cmd_tx: tokio::sync::mpsc::Sender<Vec<AgentCommand>>,
+) -> Result<()> {
+    loop {
+        let cmds = session.get_agent_commands(&agent_name).await?;
+        if !cmds.is_empty() && cmd_tx.send(cmds).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Run the agent `agent_name` for the Braid at `braid_url`.
+///
+/// Strand Camera is started from the executables in `strand_cam_dir`. This
+/// runs forever, reconnecting to Braid whenever the connection fails.
+pub async fn run_agent(
+    agent_name: String,
+    braid_url: String,
+    strand_cam_dir: PathBuf,
+) -> Result<()> {
+    let mut agent = Agent {
+        agent_name,
+        braid_url,
+        strand_cam_dir,
+        processes: StrandCamProcesses::new(),
+    };
+
+    loop {
+        if let Err(e) = agent.run_session().await {
+            warn!(
+                "Connection to Braid failed: {e}. Retrying in {} seconds.",
+                RECONNECT_INTERVAL.as_secs()
+            );
+        }
+        // Processes ending now were started for a previous Braid session, so
+        // they are not reported.
+        let sleep = tokio::time::sleep(RECONNECT_INTERVAL);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                Some(exit) = agent.processes.join_next() => {
+                    info!(
+                        "Strand Camera for \"{}\" ended while not connected to Braid.",
+                        exit.cam_name
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Start Strand Camera on a camera computer on behalf of Braid.
+use std::path::PathBuf;
+
+use clap::Parser;
+use color_eyre::Result;
+
+use braid::braid_start;
+
+#[derive(Debug, Parser)]
+#[command(author, version, about)]
+struct BraidAgentCliArgs {
+    /// URL of Braid (the same URL given to Strand Camera with `--braid-url`)
+    #[arg(long)]
+    braid_url: String,
+    /// Name of this agent as used in the `agent` field of the camera
+    /// configuration. Defaults to the hostname.
+    #[arg(long)]
+    name: Option<String>,
+    /// Directory with the Strand Camera executables. Defaults to the directory
+    /// of this executable.
+    #[arg(long)]
+    strand_cam_dir: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    braid_start("agent")?;
+    let _guard = env_tracing_logger::init();
+
+    let args = BraidAgentCliArgs::parse();
+
+    let agent_name = match args.name {
+        Some(name) => name,
+        None => gethostname::gethostname().to_string_lossy().to_string(),
+    };
+
+    let strand_cam_dir = match args.strand_cam_dir {
+        Some(dir) => dir,
+        None => std::env::current_exe()?
+            .parent()
+            .expect("Executable must be in some directory")
+            .to_path_buf(),
+    };
+
+    braid_agent::run_agent(agent_name, args.braid_url, strand_cam_dir).await
+}
@@ -8,18 +8,22 @@ use serde::{Deserialize, Serialize};
 use gloo_events::EventListener;
 use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue, UnwrapThrowExt};
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{EventSource, MessageEvent};
+use web_sys::{EventSource, HtmlInputElement, HtmlTextAreaElement, KeyboardEvent, MessageEvent};
 
 use flydra_types::{
-    BraidHttpApiCallback, BraidHttpApiSharedState, BuiServerInfo, CamInfo, TriggerType,
+    BraidHttpApiCallback, BraidHttpApiSharedState, BuiServerInfo, CamInfo, NewAnnotation,
+    TrackingParams, TrackingParamsScope, TrackingParamsUpdate, TriggerType,
 };
 use rust_cam_bui_types::RecordingPath;
 
-use yew::{html, Component, Context, Event, Html};
+use yew::{html, Component, Context, Event, Html, InputEvent, TargetCast};
 use yew_tincture::components::{Button, CheckboxLabel, TypedInput, TypedInputStorage};
 
 use ads_webasm::components::{RecordingPathWidget, ReloadButton};
 
+mod live_view;
+use live_view::LiveView;
+
 // -----------------------------------------------------------------------------
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -49,9 +53,18 @@ struct Model {
     recording_path: Option<RecordingPath>,
     fake_mp4_recording_path: Option<RecordingPath>,
     post_trigger_buffer_size_local: TypedInputStorage<usize>,
+    annotation_message_local: TypedInputStorage<String>,
+    annotation_message: String,
+    /// Tracking parameters (as YAML) being edited. `None` if not edited.
+    tracking_params_text: Option<String>,
+    tracking_params_all_objects: bool,
+    tracking_params_error: Option<String>,
     _listeners: Vec<EventListener>,
 }
 
+const ANNOTATION_KIND_NOTE: &str = "note";
+const ANNOTATION_KIND_STIMULUS_ONSET: &str = "stimulus_onset";
+
 // -----------------------------------------------------------------------------
 
 enum Msg {
@@ -62,6 +75,12 @@ enum Msg {
     SendMessageFetchState(FetchState),
     SetPostTriggerBufferSize(usize),
     PostTriggerMp4Recording,
+    SetAnnotationMessage(String),
+    AddAnnotation(&'static str),
+    EditTrackingParams(String),
+    SetTrackingParamsAllObjects(bool),
+    ApplyTrackingParams,
+    ResetTrackingParams,
     RenderView,
 }
 
@@ -129,6 +148,29 @@ impl Component for Model {
             link.send_message(Msg::RenderView);
         }));
 
+        // Keyboard shortcuts to add annotations: Alt+A for a note, Alt+S for a
+        // stimulus onset. Use the physical key because Alt changes the
+        // character on some keyboard layouts.
+        let link = ctx.link().clone();
+        let window = web_sys::window().unwrap_throw();
+        _listeners.push(EventListener::new(
+            &window,
+            "keydown",
+            move |event: &Event| {
+                let event = event.dyn_ref::<KeyboardEvent>().unwrap_throw();
+                if !event.alt_key() || event.ctrl_key() || event.meta_key() {
+                    return;
+                }
+                let kind = match event.code().as_str() {
+                    "KeyA" => ANNOTATION_KIND_NOTE,
+                    "KeyS" => ANNOTATION_KIND_STIMULUS_ONSET,
+                    _ => return,
+                };
+                event.prevent_default();
+                link.send_message(Msg::AddAnnotation(kind));
+            },
+        ));
+
         Self {
             shared: None,
             es,
@@ -137,6 +179,11 @@ impl Component for Model {
             recording_path: None,
             fake_mp4_recording_path: None,
             post_trigger_buffer_size_local: TypedInputStorage::empty(),
+            annotation_message_local: TypedInputStorage::empty(),
+            annotation_message: String::new(),
+            tracking_params_text: None,
+            tracking_params_all_objects: false,
+            tracking_params_error: None,
             _listeners,
         }
     }
@@ -197,6 +244,59 @@ impl Component for Model {
             Msg::PostTriggerMp4Recording => {
                 return self.send_to_all_cams(ctx, BraidHttpApiCallback::PostTriggerMp4Recording);
             }
+            Msg::SetAnnotationMessage(message) => {
+                self.annotation_message = message;
+                return false;
+            }
+            Msg::AddAnnotation(kind) => {
+                if self.recording_path.is_none() {
+                    // Annotations are only saved in .braidz files.
+                    return false;
+                }
+                let annotation = NewAnnotation {
+                    kind: kind.to_string(),
+                    message: self.annotation_message.clone(),
+                    payload: None,
+                };
+                return self.send_to_all_cams(ctx, BraidHttpApiCallback::AddAnnotation(annotation));
+            }
+            Msg::EditTrackingParams(text) => {
+                self.tracking_params_text = Some(text);
+                return false;
+            }
+            Msg::SetTrackingParamsAllObjects(val) => {
+                self.tracking_params_all_objects = val;
+                return false;
+            }
+            Msg::ApplyTrackingParams => {
+                let text = match &self.tracking_params_text {
+                    Some(text) => text,
+                    None => return false, // Nothing edited.
+                };
+                match serde_yaml::from_str::<TrackingParams>(text) {
+                    Ok(tracking_params) => {
+                        self.tracking_params_text = None;
+                        self.tracking_params_error = None;
+                        let scope = if self.tracking_params_all_objects {
+                            TrackingParamsScope::AllObjects
+                        } else {
+                            TrackingParamsScope::NewObjects
+                        };
+                        let update = TrackingParamsUpdate {
+                            tracking_params,
+                            scope,
+                        };
+                        self.send_to_all_cams(ctx, BraidHttpApiCallback::SetTrackingParams(update));
+                    }
+                    Err(e) => {
+                        self.tracking_params_error = Some(format!("{e}"));
+                    }
+                }
+            }
+            Msg::ResetTrackingParams => {
+                self.tracking_params_text = None;
+                self.tracking_params_error = None;
+            }
         }
         true
     }
@@ -264,6 +364,76 @@ impl Model {
         }
     }
 
+    fn view_annotations(&self, ctx: &Context<Self>) -> Html {
+        if self.recording_path.is_none() {
+            return html! {};
+        }
+        html! {
+            <div class="wrap-collapsible">
+                <CheckboxLabel label="Annotations" initially_checked=true />
+                <div>
+                    <p>{"Save a note in the .braidz file, stamped with the current frame number. \
+                    Keyboard shortcuts: Alt+A adds a note, Alt+S marks a stimulus onset."}</p>
+                </div>
+                <div>
+                    <label>{"message "}
+                        <TypedInput<String>
+                            storage={self.annotation_message_local.clone()}
+                            on_send_valid={ctx.link().callback(Msg::SetAnnotationMessage)}
+                            />
+                    </label>
+
+                    <Button title={"Add note"} onsignal={ctx.link().callback(|_| Msg::AddAnnotation(ANNOTATION_KIND_NOTE))}/>
+                    <Button title={"Mark stimulus onset"} onsignal={ctx.link().callback(|_| Msg::AddAnnotation(ANNOTATION_KIND_STIMULUS_ONSET))}/>
+                </div>
+            </div>
+        }
+    }
+
+    fn view_tracking_params(&self, ctx: &Context<Self>, current: &TrackingParams) -> Html {
+        let text = match &self.tracking_params_text {
+            Some(text) => text.clone(),
+            None => serde_yaml::to_string(current).unwrap_throw(),
+        };
+        let error = match &self.tracking_params_error {
+            Some(e) => html! { <div>{format!("❌ Error: {e}")}</div> },
+            None => html! {},
+        };
+        html! {
+            <div class="wrap-collapsible">
+                <CheckboxLabel label="Tracking Parameters" initially_checked=false />
+                <div>
+                    <p>{"Change the tracking parameters while tracking. Changes are saved in the .braidz file. \
+                    The mini arena configuration and the choice between 3D and flat 3D tracking cannot be changed."}</p>
+                </div>
+                <div>
+                    <textarea rows="20" cols="60"
+                        value={text}
+                        oninput={ctx.link().callback(|e: InputEvent| {
+                            let input: HtmlTextAreaElement = e.target_unchecked_into();
+                            Msg::EditTrackingParams(input.value())
+                        })}
+                        />
+                    {error}
+                    <div>
+                        <label>
+                            <input type="checkbox"
+                                checked={self.tracking_params_all_objects}
+                                onchange={ctx.link().callback(|e: Event| {
+                                    let input: HtmlInputElement = e.target_unchecked_into();
+                                    Msg::SetTrackingParamsAllObjects(input.checked())
+                                })}
+                                />
+                            {"Also apply to objects already being tracked"}
+                        </label>
+                    </div>
+                    <Button title={"Apply"} onsignal={ctx.link().callback(|_| Msg::ApplyTrackingParams)}/>
+                    <Button title={"Reset"} onsignal={ctx.link().callback(|_| Msg::ResetTrackingParams)}/>
+                </div>
+            </div>
+        }
+    }
+
     fn view_shared(&self, ctx: &Context<Self>) -> Html {
         if let Some(ref value) = self.shared {
             let clock_model_ready = if value.needs_clock_model {
@@ -291,6 +461,8 @@ impl Model {
 
                         { self.view_post_trigger_options(ctx) }
 
+                        { self.view_annotations(ctx) }
+
                     </div>
                 }
             } else {
@@ -317,6 +489,8 @@ impl Model {
                         {view_clock_model(&value)}
                         {view_calibration(&value.calibration_filename)}
                         {view_cam_list(&value.connected_cameras)}
+                        {self.view_tracking_params(ctx, &value.tracking_params)}
+                        <LiveView mini_arena_config={value.mini_arena_config.clone()}/>
                         {view_model_server_link(&value.model_server_addr)}
                     </div>
                 </div>
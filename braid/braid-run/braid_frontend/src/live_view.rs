@@ -0,0 +1,482 @@
+//! Live view of the objects currently being tracked.
+//!
+//! The tracking results are received from Braid in the format of the model
+//! server (the "Braid pose API") and drawn on a canvas together with the
+//! cameras of the calibration and the mini arenas.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use gloo_events::EventListener;
+use serde::Deserialize;
+use wasm_bindgen::{JsCast, JsValue, UnwrapThrowExt};
+use web_sys::{CanvasRenderingContext2d, EventSource, HtmlCanvasElement, MessageEvent};
+use yew::{html, Component, Context, Event, Html, NodeRef, Properties};
+
+use flydra_types::MiniArenaConfig;
+
+/// Number of recent positions drawn for each object.
+const TRAIL_LENGTH: usize = 100;
+/// Minimum time between redraws, in milliseconds.
+const MIN_REDRAW_INTERVAL_MSEC: f64 = 50.0;
+const CANVAS_WIDTH: u32 = 600;
+const CANVAS_HEIGHT: u32 = 400;
+/// Version of the Braid pose API which is understood here.
+const POSE_API_VERSION: u16 = 3;
+
+// The parts of the Braid pose API (`flydra2::model_server::ToListener`) which
+// are used here.
+
+#[derive(Deserialize)]
+struct ToListener {
+    v: u16,
+    msg: SendType,
+}
+
+#[derive(Deserialize)]
+enum SendType {
+    Birth(KalmanEstimate),
+    Update(KalmanEstimate),
+    Death(u32),
+    EndOfFrame(serde::de::IgnoredAny),
+    CalibrationFlydraXml(String),
+}
+
+#[derive(Deserialize)]
+struct KalmanEstimate {
+    obj_id: u32,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ViewKind {
+    /// Looking down the Z axis.
+    Top,
+    /// Looking along the Y axis.
+    Side,
+    /// Perspective view from above the scene.
+    Perspective,
+}
+
+impl ViewKind {
+    fn label(&self) -> &'static str {
+        match self {
+            ViewKind::Top => "Top",
+            ViewKind::Side => "Side",
+            ViewKind::Perspective => "Perspective",
+        }
+    }
+}
+
+/// A camera drawn as its center and the corners of its image at some
+/// distance.
+struct CameraFrustum {
+    name: String,
+    center: [f64; 3],
+    corners: [[f64; 3]; 4],
+}
+
+fn frusta_from_flydra_xml(xml: &str) -> Result<Vec<CameraFrustum>, String> {
+    let system = flydra_mvg::FlydraMultiCameraSystem::<f64>::from_flydra_xml(xml.as_bytes())
+        .map_err(|e| format!("{e}"))?;
+    let cams = system.system().cams_by_name();
+    let centers: Vec<[f64; 3]> = cams
+        .values()
+        .map(|cam| {
+            let c = cam.extrinsics().camcenter();
+            [c.x, c.y, c.z]
+        })
+        .collect();
+
+    // Scale the frusta to the size of the camera arrangement.
+    let mean = mean_point(&centers);
+    let mean_dist =
+        centers.iter().map(|c| distance(c, &mean)).sum::<f64>() / centers.len().max(1) as f64;
+    let depth = if mean_dist > 0.0 {
+        0.3 * mean_dist
+    } else {
+        0.1
+    };
+
+    Ok(cams
+        .iter()
+        .zip(centers)
+        .map(|((name, cam), center)| {
+            let (w, h) = (cam.width() as f64, cam.height() as f64);
+            let corners = [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)].map(|(x, y)| {
+                let px = mvg::UndistortedPixel {
+                    coords: nalgebra::Point2::new(x, y),
+                };
+                let pt = cam.project_pixel_to_3d_with_dist(&px, depth).coords;
+                [pt.x, pt.y, pt.z]
+            });
+            CameraFrustum {
+                name: name.clone(),
+                center,
+                corners,
+            }
+        })
+        .collect())
+}
+
+fn mean_point(points: &[[f64; 3]]) -> [f64; 3] {
+    let n = points.len().max(1) as f64;
+    let mut sum = [0.0; 3];
+    for p in points {
+        for (s, v) in sum.iter_mut().zip(p) {
+            *s += v;
+        }
+    }
+    sum.map(|s| s / n)
+}
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// Projects 3D points into the 2D coordinates of a view.
+struct Projection {
+    kind: ViewKind,
+    eye: [f64; 3],
+    forward: [f64; 3],
+    right: [f64; 3],
+    up: [f64; 3],
+}
+
+impl Projection {
+    /// Create a projection. For the perspective view, the eye is placed so
+    /// that the points `scene` are visible.
+    fn new(kind: ViewKind, scene: &[[f64; 3]]) -> Self {
+        let center = mean_point(scene);
+        let extent = scene
+            .iter()
+            .map(|p| distance(p, &center))
+            .fold(0.0, f64::max)
+            .max(0.5);
+        let (azimuth, elevation) = ((-60f64).to_radians(), 30f64.to_radians());
+        let dir = [
+            elevation.cos() * azimuth.cos(),
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+        ];
+        let eye = [0, 1, 2].map(|i| center[i] + 3.0 * extent * dir[i]);
+        let forward = dir.map(|v| -v);
+        // right = forward x (0,0,1), up = right x forward
+        let right = normalize([forward[1], -forward[0], 0.0]);
+        let up = [
+            right[1] * forward[2] - right[2] * forward[1],
+            right[2] * forward[0] - right[0] * forward[2],
+            right[0] * forward[1] - right[1] * forward[0],
+        ];
+        Self {
+            kind,
+            eye,
+            forward,
+            right,
+            up,
+        }
+    }
+
+    /// Returns `None` if the point is behind the eye of the perspective view.
+    fn project(&self, p: &[f64; 3]) -> Option<(f64, f64)> {
+        match self.kind {
+            ViewKind::Top => Some((p[0], p[1])),
+            ViewKind::Side => Some((p[0], p[2])),
+            ViewKind::Perspective => {
+                let q = [0, 1, 2].map(|i| p[i] - self.eye[i]);
+                let depth = dot(&q, &self.forward);
+                if depth <= 1e-6 {
+                    return None;
+                }
+                Some((dot(&q, &self.right) / depth, dot(&q, &self.up) / depth))
+            }
+        }
+    }
+}
+
+fn dot(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(v: [f64; 3]) -> [f64; 3] {
+    let n = dot(&v, &v).sqrt();
+    v.map(|x| x / n)
+}
+
+/// Maps view coordinates to canvas pixels, keeping the aspect ratio.
+struct ToCanvas {
+    scale: f64,
+    offset: (f64, f64),
+}
+
+impl ToCanvas {
+    fn new(points: &[(f64, f64)]) -> Self {
+        let (mut xmin, mut xmax, mut ymin, mut ymax) = (-1.0, 1.0, -1.0, 1.0);
+        if !points.is_empty() {
+            xmin = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+            xmax = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+            ymin = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+            ymax = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+        }
+        let margin = 0.1 * (xmax - xmin).max(ymax - ymin).max(1e-3);
+        let (xmin, xmax, ymin, ymax) = (xmin - margin, xmax + margin, ymin - margin, ymax + margin);
+        let (w, h) = (CANVAS_WIDTH as f64, CANVAS_HEIGHT as f64);
+        let scale = (w / (xmax - xmin)).min(h / (ymax - ymin));
+        // Center the drawing. The canvas y axis points down.
+        let offset = (
+            w / 2.0 - scale * (xmin + xmax) / 2.0,
+            h / 2.0 + scale * (ymin + ymax) / 2.0,
+        );
+        Self { scale, offset }
+    }
+
+    fn px(&self, p: (f64, f64)) -> (f64, f64) {
+        (
+            self.offset.0 + self.scale * p.0,
+            self.offset.1 - self.scale * p.1,
+        )
+    }
+}
+
+fn obj_color(obj_id: u32) -> JsValue {
+    JsValue::from_str(&format!("hsl({}, 80%, 50%)", (obj_id * 137) % 360))
+}
+
+pub(crate) struct LiveView {
+    _es: EventSource,
+    _listeners: Vec<EventListener>,
+    canvas: NodeRef,
+    view: ViewKind,
+    trails: BTreeMap<u32, VecDeque<[f64; 3]>>,
+    cameras: Vec<CameraFrustum>,
+    last_draw: f64,
+    fail_msg: Option<String>,
+}
+
+pub(crate) enum Msg {
+    ModelEvent(String),
+    SetView(ViewKind),
+}
+
+#[derive(PartialEq, Properties)]
+pub(crate) struct Props {
+    pub(crate) mini_arena_config: MiniArenaConfig,
+}
+
+impl Component for LiveView {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let es = EventSource::new(flydra_types::MODEL_EVENTS_URL_PATH)
+            .map_err(|js_value: JsValue| {
+                let err: js_sys::Error = js_value.dyn_into().unwrap_throw();
+                err
+            })
+            .unwrap_throw();
+        let cb = ctx.link().callback(Msg::ModelEvent);
+        let _listeners = vec![EventListener::new(
+            &es,
+            flydra_types::BRAID_EVENT_NAME,
+            move |event: &Event| {
+                let event = event.dyn_ref::<MessageEvent>().unwrap_throw();
+                let text = event.data().as_string().unwrap_throw();
+                cb.emit(text);
+            },
+        )];
+
+        Self {
+            _es: es,
+            _listeners,
+            canvas: NodeRef::default(),
+            view: ViewKind::Top,
+            trails: BTreeMap::new(),
+            cameras: Vec::new(),
+            last_draw: 0.0,
+            fail_msg: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::SetView(view) => {
+                self.view = view;
+                true
+            }
+            Msg::ModelEvent(buf) => {
+                let msg: ToListener = match serde_json::from_str(&buf) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        self.fail_msg = Some(format!("Could not decode tracking data: {e}"));
+                        return true;
+                    }
+                };
+                if msg.v != POSE_API_VERSION {
+                    self.fail_msg = Some(format!(
+                        "Unsupported tracking data version {} (expected {POSE_API_VERSION}).",
+                        msg.v
+                    ));
+                    return true;
+                }
+                match msg.msg {
+                    SendType::Birth(est) | SendType::Update(est) => {
+                        let trail = self.trails.entry(est.obj_id).or_default();
+                        trail.push_back([est.x, est.y, est.z]);
+                        while trail.len() > TRAIL_LENGTH {
+                            trail.pop_front();
+                        }
+                    }
+                    SendType::Death(obj_id) => {
+                        self.trails.remove(&obj_id);
+                    }
+                    SendType::EndOfFrame(_) => {
+                        let now = js_sys::Date::now();
+                        if now - self.last_draw >= MIN_REDRAW_INTERVAL_MSEC {
+                            self.draw(ctx);
+                        }
+                    }
+                    SendType::CalibrationFlydraXml(xml) => match frusta_from_flydra_xml(&xml) {
+                        Ok(cameras) => {
+                            self.cameras = cameras;
+                            self.draw(ctx);
+                        }
+                        Err(e) => {
+                            self.fail_msg = Some(format!("Could not parse calibration: {e}"));
+                            return true;
+                        }
+                    },
+                }
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let buttons: Vec<Html> = [ViewKind::Top, ViewKind::Side, ViewKind::Perspective]
+            .into_iter()
+            .map(|kind| {
+                let onclick = ctx.link().callback(move |_| Msg::SetView(kind));
+                html! {
+                    <button onclick={onclick} disabled={kind == self.view}>{kind.label()}</button>
+                }
+            })
+            .collect();
+        let fail_msg = match &self.fail_msg {
+            Some(msg) => html! { <p>{msg}</p> },
+            None => html! {},
+        };
+        html! {
+            <div class="live-view">
+                <div>{"Live view: "}{buttons}</div>
+                {fail_msg}
+                <canvas ref={self.canvas.clone()} width={CANVAS_WIDTH.to_string()} height={CANVAS_HEIGHT.to_string()}/>
+            </div>
+        }
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, _first_render: bool) {
+        self.draw(ctx);
+    }
+}
+
+impl LiveView {
+    fn draw(&mut self, ctx: &Context<Self>) {
+        self.last_draw = js_sys::Date::now();
+        let Some(canvas) = self.canvas.cast::<HtmlCanvasElement>() else {
+            return;
+        };
+        let c2d: CanvasRenderingContext2d = canvas
+            .get_context("2d")
+            .unwrap_throw()
+            .unwrap_throw()
+            .dyn_into()
+            .unwrap_throw();
+
+        let arenas: Vec<Vec<[f64; 3]>> = match &ctx.props().mini_arena_config {
+            MiniArenaConfig::NoMiniArena => vec![],
+            MiniArenaConfig::XYGrid(grid) => grid
+                .iter_centers()
+                .map(|(x, y)| {
+                    (0..=32)
+                        .map(|i| {
+                            let angle = i as f64 / 32.0 * std::f64::consts::TAU;
+                            let r = grid.radius();
+                            [x + r * angle.cos(), y + r * angle.sin(), 0.0]
+                        })
+                        .collect()
+                })
+                .collect(),
+        };
+
+        // Fit the view to the static parts of the scene, if any, so that it
+        // does not jump around as objects move.
+        let mut scene: Vec<[f64; 3]> = self
+            .cameras
+            .iter()
+            .flat_map(|cam| std::iter::once(cam.center).chain(cam.corners))
+            .chain(arenas.iter().flatten().copied())
+            .collect();
+        if scene.is_empty() {
+            scene = self.trails.values().flatten().copied().collect();
+        }
+        let projection = Projection::new(self.view, &scene);
+        let projected: Vec<(f64, f64)> =
+            scene.iter().filter_map(|p| projection.project(p)).collect();
+        let to_canvas = ToCanvas::new(&projected);
+        let px = |p: &[f64; 3]| projection.project(p).map(|p| to_canvas.px(p));
+
+        let polyline = |points: &mut dyn Iterator<Item = Option<(f64, f64)>>| {
+            c2d.begin_path();
+            let mut pen_down = false;
+            for p in points {
+                match p {
+                    Some((x, y)) if pen_down => c2d.line_to(x, y),
+                    Some((x, y)) => {
+                        c2d.move_to(x, y);
+                        pen_down = true;
+                    }
+                    None => pen_down = false,
+                }
+            }
+            c2d.stroke();
+        };
+
+        c2d.clear_rect(0.0, 0.0, CANVAS_WIDTH as f64, CANVAS_HEIGHT as f64);
+        c2d.set_line_width(1.0);
+        c2d.set_font("12px sans-serif");
+
+        c2d.set_stroke_style(&JsValue::from_str("gray"));
+        for arena in arenas.iter() {
+            polyline(&mut arena.iter().map(px));
+        }
+
+        c2d.set_fill_style(&JsValue::from_str("gray"));
+        for cam in self.cameras.iter() {
+            let corners = cam.corners.iter().map(px);
+            polyline(&mut corners.clone().chain(std::iter::once(px(&cam.corners[0]))));
+            for corner in cam.corners.iter() {
+                polyline(&mut [px(&cam.center), px(corner)].into_iter());
+            }
+            if let Some((x, y)) = px(&cam.center) {
+                c2d.fill_text(&cam.name, x + 3.0, y - 3.0).unwrap_throw();
+            }
+        }
+
+        c2d.set_line_width(2.0);
+        for (obj_id, trail) in self.trails.iter() {
+            let color = obj_color(*obj_id);
+            c2d.set_stroke_style(&color);
+            c2d.set_fill_style(&color);
+            polyline(&mut trail.iter().map(px));
+            if let Some((x, y)) = trail.back().and_then(px) {
+                c2d.begin_path();
+                c2d.arc(x, y, 4.0, 0.0, std::f64::consts::TAU)
+                    .unwrap_throw();
+                c2d.fill();
+                c2d.fill_text(&format!("{obj_id}"), x + 6.0, y - 6.0)
+                    .unwrap_throw();
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::{collections::BTreeMap, collections::VecDeque, sync::Arc};
+
+use parking_lot::Mutex;
+use tracing::{debug, info, warn};
+
+use flydra_types::{AgentCommand, BraidCameraConfig};
+
+use braid::strand_cam_process::StrandCamExit;
+
+/// How long a `braid-agent` waits for commands before it polls again.
+pub(crate) const AGENT_POLL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[derive(Default)]
+struct AgentQueue {
+    commands: VecDeque<AgentCommand>,
+    /// Notified when commands are added to the queue.
+    notify: Arc<tokio::sync::Notify>,
+    /// Whether the agent has registered with Braid.
+    registered: bool,
+}
+
+/// Command queues for `braid-agent` processes, which start Strand Camera on
+/// (potentially) other computers.
+///
+/// Agents fetch their commands by long-polling
+/// [flydra_types::braid_http::AGENT_COMMANDS_PATH] and report ended processes
+/// with [flydra_types::BraidHttpApiCallback::AgentCameraExited].
+#[derive(Clone)]
+pub(crate) struct AgentRegistry {
+    queues: Arc<Mutex<BTreeMap<String, AgentQueue>>>,
+    exit_tx: tokio::sync::mpsc::UnboundedSender<StrandCamExit>,
+}
+
+impl AgentRegistry {
+    /// Create a new registry. The returned receiver gets the Strand Camera
+    /// processes which ended as reported by the agents.
+    pub(crate) fn new() -> (Self, tokio::sync::mpsc::UnboundedReceiver<StrandCamExit>) {
+        let (exit_tx, exit_rx) = tokio::sync::mpsc::unbounded_channel();
+        let queues = Arc::new(Mutex::new(BTreeMap::new()));
+        (Self { queues, exit_tx }, exit_rx)
+    }
+
+    /// Queue a command for an agent.
+    ///
+    /// If the agent has not (yet) registered, the command is kept until it
+    /// does so.
+    pub(crate) fn send(&self, agent_name: &str, cmd: AgentCommand) {
+        let mut queues = self.queues.lock();
+        let queue = queues.entry(agent_name.to_string()).or_default();
+        if !queue.registered {
+            debug!("Agent \"{agent_name}\" not registered. Queueing command {cmd:?}.");
+        }
+        queue.commands.push_back(cmd);
+        queue.notify.notify_one();
+    }
+
+    /// Register an agent and queue launching the cameras in `to_launch`.
+    ///
+    /// Commands queued before the registration are discarded because they
+    /// were meant for a previous instance of the agent.
+    pub(crate) fn register(&self, agent_name: &str, to_launch: Vec<BraidCameraConfig>) {
+        let mut queues = self.queues.lock();
+        let queue = queues.entry(agent_name.to_string()).or_default();
+        if queue.registered {
+            info!("Agent \"{agent_name}\" registered again.");
+        }
+        queue.registered = true;
+        queue.commands.clear();
+        for camera in to_launch {
+            info!(
+                "Agent \"{agent_name}\" will launch Strand Camera for \"{}\".",
+                camera.name
+            );
+            queue.commands.push_back(AgentCommand::Launch(camera));
+        }
+        queue.notify.notify_one();
+    }
+
+    /// Wait up to `timeout` for commands for an agent and return them.
+    ///
+    /// Returns an empty `Vec` if no commands arrived in time.
+    pub(crate) async fn next_commands(
+        &self,
+        agent_name: &str,
+        timeout: std::time::Duration,
+    ) -> Vec<AgentCommand> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let notify = {
+                let mut queues = self.queues.lock();
+                let queue = queues.entry(agent_name.to_string()).or_default();
+                if !queue.commands.is_empty() {
+                    return queue.commands.drain(..).collect();
+                }
+                queue.notify.clone()
+            };
+            // `notify_one` stores a permit if nobody is waiting, so a command
+            // queued between releasing the lock and here is not missed.
+            if tokio::time::timeout_at(deadline, notify.notified())
+                .await
+                .is_err()
+            {
+                return Vec::new();
+            }
+        }
+    }
+
+    /// Pass on the report of an agent that a Strand Camera process ended.
+    pub(crate) fn camera_exited(&self, exit: StrandCamExit) {
+        if self.exit_tx.send(exit).is_err() {
+            warn!("Strand Camera process ended but no longer watching processes.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flydra_types::RawCamName;
+
+    #[tokio::test]
+    async fn test_commands_per_agent() {
+        let (registry, _exit_rx) = AgentRegistry::new();
+        let timeout = std::time::Duration::from_millis(10);
+
+        // Commands sent before registration are replaced on registration.
+        registry.send("a", AgentCommand::Stop(RawCamName::new("cam0".into())));
+        let cam1 = BraidCameraConfig::default_absdiff_config("cam1".into());
+        registry.register("a", vec![cam1.clone()]);
+        registry.register("b", vec![]);
+
+        assert_eq!(
+            registry.next_commands("a", timeout).await,
+            vec![AgentCommand::Launch(cam1)]
+        );
+        assert!(registry.next_commands("a", timeout).await.is_empty());
+        assert!(registry.next_commands("b", timeout).await.is_empty());
+
+        // A waiting agent is woken by a new command.
+        let waiter = {
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                registry
+                    .next_commands("b", std::time::Duration::from_secs(10))
+                    .await
+            })
+        };
+        let stop = AgentCommand::Stop(RawCamName::new("cam2".into()));
+        registry.send("b", stop.clone());
+        assert_eq!(waiter.await.unwrap(), vec![stop]);
+    }
+}
@@ -0,0 +1,363 @@
+//! Version 1 of the Braid REST API for scripted control.
+//!
+//! See [flydra_types::braid_api_v1] for the request and response types. In
+//! contrast to the web UI, requests are authorized with a bearer token rather
+//! than a session cookie.
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json,
+};
+use http::{header::AUTHORIZATION, StatusCode};
+use tracing::{debug, warn};
+
+use bui_backend_session_types::AccessToken;
+use flydra_types::braid_api_v1::{
+    AnnotationResponse, CameraInfo, ErrorResponse, ExperimentUuidRequest, NewAnnotation,
+    PostTriggerBufferRequest, RecordingRequest, StatusResponse, TrackingParams,
+    TrackingParamsUpdate, ANNOTATIONS_PATH, API_VERSION, CAMERAS_PATH, EXPERIMENT_UUID_PATH,
+    OPENAPI_PATH, POST_TRIGGER_BUFFER_PATH, POST_TRIGGER_MP4_PATH, RECORDING_BRAIDZ_PATH,
+    RECORDING_MP4_PATH, STATUS_PATH, TRACKING_PARAMS_PATH,
+};
+
+use crate::{
+    callback_handling::{
+        add_annotation, initiate_post_trigger_mp4, set_experiment_uuid, set_mp4_recording,
+        set_post_trigger_buffer_size, set_tracking_params,
+    },
+    mainbrain::{toggle_saving_csv_tables, BraidAppState},
+};
+
+const OPENAPI_YAML: &str = include_str!("../braid-api-v1.openapi.yaml");
+
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorResponse {
+            message: self.message,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<(StatusCode, &'static str)> for ApiError {
+    fn from((status, message): (StatusCode, &'static str)) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Compare tokens in time independent of where they differ.
+fn tokens_equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn require_bearer_token(
+    expected: Option<String>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if let Some(expected) = expected {
+        let given = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let authorized = given
+            .map(|given| tokens_equal(given.trim().as_bytes(), expected.as_bytes()))
+            .unwrap_or(false);
+        if !authorized {
+            warn!("Unauthorized API request to {}", req.uri());
+            return Err(ApiError {
+                status: StatusCode::UNAUTHORIZED,
+                message: "missing or invalid bearer token".to_string(),
+            });
+        }
+    }
+    Ok(next.run(req).await)
+}
+
+/// The bearer token for the API: `configured` (the `api_token` value of the
+/// configuration) or, if that is not set, the token of the web UI.
+pub(crate) fn api_token(configured: Option<String>, ui_token: &AccessToken) -> Option<String> {
+    configured.or_else(|| match ui_token {
+        AccessToken::PreSharedToken(value) => Some(value.clone()),
+        AccessToken::NoToken => None,
+    })
+}
+
+/// Create the router for the API. This is nested at
+/// [flydra_types::braid_api_v1::API_V1_PREFIX].
+///
+/// If `token` is `None`, requests are not checked for authorization.
+pub(crate) fn router(token: Option<String>) -> axum::Router<BraidAppState> {
+    assert_eq!(STATUS_PATH, "status");
+    assert_eq!(CAMERAS_PATH, "cameras");
+    assert_eq!(RECORDING_BRAIDZ_PATH, "recording/braidz");
+    assert_eq!(RECORDING_MP4_PATH, "recording/mp4");
+    assert_eq!(EXPERIMENT_UUID_PATH, "experiment-uuid");
+    assert_eq!(POST_TRIGGER_BUFFER_PATH, "post-trigger/buffer-size");
+    assert_eq!(POST_TRIGGER_MP4_PATH, "post-trigger/mp4");
+    assert_eq!(ANNOTATIONS_PATH, "annotations");
+    assert_eq!(TRACKING_PARAMS_PATH, "tracking-params");
+    assert_eq!(OPENAPI_PATH, "openapi.yaml");
+
+    axum::Router::new()
+        .route("/status", get(status_handler))
+        .route("/cameras", get(cameras_handler))
+        .route("/recording/braidz", put(recording_braidz_handler))
+        .route("/recording/mp4", put(recording_mp4_handler))
+        .route("/experiment-uuid", put(experiment_uuid_handler))
+        .route(
+            "/post-trigger/buffer-size",
+            put(post_trigger_buffer_handler),
+        )
+        .route("/post-trigger/mp4", post(post_trigger_mp4_handler))
+        .route("/annotations", post(annotations_handler))
+        .route(
+            "/tracking-params",
+            get(get_tracking_params_handler).put(put_tracking_params_handler),
+        )
+        .route_layer(axum::middleware::from_fn(
+            move |req: Request, next: Next| require_bearer_token(token.clone(), req, next),
+        ))
+        // The API description is available without authorization.
+        .route("/openapi.yaml", get(openapi_handler))
+}
+
+async fn openapi_handler() -> impl IntoResponse {
+    (
+        [(http::header::CONTENT_TYPE, "application/yaml")],
+        OPENAPI_YAML,
+    )
+}
+
+async fn status_handler(State(app_state): State<BraidAppState>) -> Json<StatusResponse> {
+    let tracker = app_state.shared_store.read();
+    let shared = tracker.as_ref();
+    Json(StatusResponse {
+        api_version: API_VERSION,
+        braidz_recording: shared.csv_tables_dirname.as_ref().map(|p| p.path()),
+        mp4_recording: shared.fake_mp4_recording_path.is_some(),
+        post_trigger_buffer_size: shared.post_trigger_buffer_size,
+        calibration_filename: shared.calibration_filename.clone(),
+        all_expected_cameras_are_synced: shared.all_expected_cameras_are_synced,
+    })
+}
+
+async fn cameras_handler(State(app_state): State<BraidAppState>) -> Json<Vec<CameraInfo>> {
+    let tracker = app_state.shared_store.read();
+    let cams = tracker
+        .as_ref()
+        .connected_cameras
+        .iter()
+        .map(|cam| CameraInfo {
+            name: cam.name.as_str().to_string(),
+            sync_state: cam.state.clone(),
+            recent_stats: cam.recent_stats.clone(),
+        })
+        .collect();
+    Json(cams)
+}
+
+async fn recording_braidz_handler(
+    State(app_state): State<BraidAppState>,
+    Json(req): Json<RecordingRequest>,
+) -> StatusCode {
+    debug!("API request to set .braidz recording to {}", req.recording);
+    toggle_saving_csv_tables(
+        req.recording,
+        app_state.expected_framerate_arc.clone(),
+        app_state.output_base_dirname.clone(),
+        app_state.braidz_write_tx_weak.clone(),
+        app_state.per_cam_data_arc.clone(),
+        app_state.shared_store.clone(),
+    )
+    .await;
+    StatusCode::NO_CONTENT
+}
+
+async fn recording_mp4_handler(
+    State(app_state): State<BraidAppState>,
+    Json(req): Json<RecordingRequest>,
+) -> Result<StatusCode, ApiError> {
+    debug!("API request to set MP4 recording to {}", req.recording);
+    set_mp4_recording(&app_state, req.recording).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn experiment_uuid_handler(
+    State(app_state): State<BraidAppState>,
+    Json(req): Json<ExperimentUuidRequest>,
+) -> Result<StatusCode, ApiError> {
+    debug!("API request to set experiment UUID to {}", req.uuid);
+    require_braidz_recording(&app_state, "experiment UUID")?;
+    set_experiment_uuid(&app_state, req.uuid).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn require_braidz_recording(app_state: &BraidAppState, what: &str) -> Result<(), ApiError> {
+    if app_state
+        .shared_store
+        .read()
+        .as_ref()
+        .csv_tables_dirname
+        .is_none()
+    {
+        return Err(ApiError {
+            status: StatusCode::CONFLICT,
+            message: format!("{what} can only be set while recording .braidz"),
+        });
+    }
+    Ok(())
+}
+
+async fn annotations_handler(
+    State(app_state): State<BraidAppState>,
+    Json(req): Json<NewAnnotation>,
+) -> Result<(StatusCode, Json<AnnotationResponse>), ApiError> {
+    debug!("API request to add annotation of kind {:?}", req.kind);
+    require_braidz_recording(&app_state, "annotations")?;
+    let row = add_annotation(&app_state, req).await;
+    let response = AnnotationResponse {
+        frame: row.frame.map(|f| f.0),
+        timestamp: row.timestamp.as_ref().map(|t| t.as_f64()),
+    };
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+async fn get_tracking_params_handler(
+    State(app_state): State<BraidAppState>,
+) -> Json<TrackingParams> {
+    let tracker = app_state.shared_store.read();
+    Json(tracker.as_ref().tracking_params.clone())
+}
+
+async fn put_tracking_params_handler(
+    State(app_state): State<BraidAppState>,
+    Json(req): Json<TrackingParamsUpdate>,
+) -> Result<StatusCode, ApiError> {
+    debug!("API request to set tracking parameters for {:?}", req.scope);
+    set_tracking_params(&app_state, req).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn post_trigger_buffer_handler(
+    State(app_state): State<BraidAppState>,
+    Json(req): Json<PostTriggerBufferRequest>,
+) -> Result<StatusCode, ApiError> {
+    debug!(
+        "API request to set post trigger buffer size to {}",
+        req.num_frames
+    );
+    set_post_trigger_buffer_size(&app_state, req.num_frames).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn post_trigger_mp4_handler(
+    State(app_state): State<BraidAppState>,
+) -> Result<StatusCode, ApiError> {
+    debug!("API request to initiate post trigger MP4 recording");
+    initiate_post_trigger_mp4(&app_state).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use braid_http_session::BraidApiClient;
+    use flydra_types::braid_api_v1::API_V1_PREFIX;
+
+    const TOKEN: &str = "secret-token";
+
+    /// Serve the API of a Braid without cameras on a local port and return
+    /// the URL of the server.
+    async fn serve(token: Option<String>) -> String {
+        let (app_state, coord_processor) = crate::mainbrain::tests::app_state(std::env::temp_dir());
+        let router = axum::Router::new()
+            .nest(&format!("/{API_V1_PREFIX}"), router(token))
+            .with_state(app_state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            // Keep the `.braidz` writer alive while serving.
+            let _coord_processor = coord_processor;
+            axum::serve(listener, router).await
+        });
+        format!("http://{addr}/")
+    }
+
+    #[test]
+    fn test_api_token_defaults_to_ui_token() {
+        let ui_token = AccessToken::PreSharedToken(TOKEN.to_string());
+        assert_eq!(api_token(None, &ui_token), Some(TOKEN.to_string()));
+        assert_eq!(
+            api_token(Some("api".to_string()), &ui_token),
+            Some("api".to_string())
+        );
+        assert_eq!(api_token(None, &AccessToken::NoToken), None);
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token_required() {
+        let url = serve(Some(TOKEN.to_string())).await;
+
+        for token in [None, Some("wrong".to_string())] {
+            let client = BraidApiClient::new(&url, token).unwrap();
+            let err = client.status().await.unwrap_err();
+            assert_eq!(err.api_status(), Some(StatusCode::UNAUTHORIZED));
+        }
+
+        // The token is taken from the URL printed by Braid.
+        let client = BraidApiClient::new(&format!("{url}?token={TOKEN}"), None).unwrap();
+        let status = client.status().await.unwrap();
+        assert_eq!(status.api_version, API_VERSION);
+        assert_eq!(status.braidz_recording, None);
+        assert_eq!(
+            client.tracking_params().await.unwrap(),
+            flydra_types::default_tracking_params_full_3d()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_tracking_params() {
+        let url = serve(None).await;
+        let client = BraidApiClient::new(&url, None).unwrap();
+        let mut tracking_params = client.tracking_params().await.unwrap();
+
+        tracking_params.motion_noise_scale *= 2.0;
+        let update = TrackingParamsUpdate {
+            tracking_params: tracking_params.clone(),
+            scope: Default::default(),
+        };
+        client.set_tracking_params(&update).await.unwrap();
+        assert_eq!(client.tracking_params().await.unwrap(), tracking_params);
+
+        // Switching to flat 3D tracking is rejected and not applied.
+        let mut flat = tracking_params.clone();
+        flat.hypothesis_test_params = None;
+        let update = TrackingParamsUpdate {
+            tracking_params: flat,
+            scope: Default::default(),
+        };
+        let err = client.set_tracking_params(&update).await.unwrap_err();
+        assert_eq!(err.api_status(), Some(StatusCode::BAD_REQUEST));
+        assert_eq!(client.tracking_params().await.unwrap(), tracking_params);
+    }
+
+    #[tokio::test]
+    async fn test_no_token() {
+        let url = serve(None).await;
+        let client = BraidApiClient::new(&url, None).unwrap();
+        assert!(client.cameras().await.unwrap().is_empty());
+    }
+}
@@ -1,8 +1,10 @@
 use axum::response::IntoResponse;
-use tracing::debug;
+use tracing::{debug, info, warn};
 
 use event_stream_types::TolerantJson;
-use flydra_types::{BraidHttpApiCallback, PerCamSaveData};
+use flydra_types::{
+    AnnotationRow, BraidHttpApiCallback, NewAnnotation, PerCamSaveData, TrackingParamsUpdate,
+};
 use http::StatusCode;
 use rust_cam_bui_types::RecordingPath;
 
@@ -19,6 +21,159 @@ fn start_saving_mp4s_all_cams(app_state: &BraidAppState, start_saving: bool) {
     });
 }
 
+/// Start or stop recording MP4 files on all cameras.
+pub(crate) async fn set_mp4_recording(
+    app_state: &BraidAppState,
+    start_saving: bool,
+) -> Result<(), (StatusCode, &'static str)> {
+    app_state
+        .strand_cam_http_session_handler
+        .toggle_saving_mp4_files_all(start_saving)
+        .await
+        .map_err(|_e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "toggle_saving_mp4_files_all failed",
+            )
+        })?;
+
+    start_saving_mp4s_all_cams(app_state, start_saving);
+    Ok(())
+}
+
+/// Set the UUID in the experiment_info table of the current recording.
+pub(crate) async fn set_experiment_uuid(app_state: &BraidAppState, value: String) {
+    if let Some(braidz_write_tx) = app_state.braidz_write_tx_weak.upgrade() {
+        // `braidz_write_tx` will be dropped after this scope.
+        braidz_write_tx
+            .send(flydra2::SaveToDiskMsg::SetExperimentUuid(value))
+            .await
+            .unwrap();
+    }
+}
+
+/// Save an annotation stamped with the most recent synchronized frame.
+///
+/// The annotation is dropped if no `.braidz` file is being recorded. Returns
+/// the row which was saved.
+pub(crate) async fn add_annotation(
+    app_state: &BraidAppState,
+    annotation: NewAnnotation,
+) -> AnnotationRow {
+    let latest = app_state.latest_frame_arc.read().clone();
+    let (frame, timestamp) = match latest {
+        Some(latest) => (Some(latest.frame), latest.trigger_timestamp),
+        None => (None, None),
+    };
+    let payload = annotation
+        .payload
+        .map(|value| value.to_string())
+        .unwrap_or_default();
+    let row = AnnotationRow {
+        frame,
+        timestamp,
+        host_timestamp: chrono::Utc::now().into(),
+        kind: annotation.kind,
+        message: annotation.message,
+        payload,
+    };
+    if let Some(braidz_write_tx) = app_state.braidz_write_tx_weak.upgrade() {
+        // `braidz_write_tx` will be dropped after this scope.
+        braidz_write_tx
+            .send(flydra2::SaveToDiskMsg::Annotation(row.clone()))
+            .await
+            .unwrap();
+    }
+    row
+}
+
+/// Change the tracking parameters of the running tracker.
+pub(crate) async fn set_tracking_params(
+    app_state: &BraidAppState,
+    update: TrackingParamsUpdate,
+) -> Result<(), (StatusCode, &'static str)> {
+    {
+        // Check and update while holding the lock so that concurrent changes
+        // are checked against each other. The tracker applies the same check
+        // to the same sequence of changes, so it accepts this one, too.
+        let mut tracker = app_state.shared_store.write();
+        flydra2::check_tracking_params_update(
+            &tracker.as_ref().tracking_params,
+            &update.tracking_params,
+        )
+        .map_err(|msg| (StatusCode::BAD_REQUEST, msg))?;
+        tracker.modify(|store| {
+            store.mini_arena_config = update.tracking_params.mini_arena_config.clone();
+            store.tracking_params = update.tracking_params.clone();
+        });
+    }
+
+    app_state
+        .tracking_params_tx
+        .send(update)
+        .await
+        .map_err(|_e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "sending tracking parameters failed",
+            )
+        })?;
+    Ok(())
+}
+
+/// Set the number of frames to buffer in each camera.
+pub(crate) async fn set_post_trigger_buffer_size(
+    app_state: &BraidAppState,
+    val: usize,
+) -> Result<(), (StatusCode, &'static str)> {
+    app_state
+        .strand_cam_http_session_handler
+        .set_post_trigger_buffer_all(val)
+        .await
+        .map_err(|_e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "set_post_trigger_buffer_all failed",
+            )
+        })?;
+
+    let mut tracker = app_state.shared_store.write();
+    tracker.modify(|store| {
+        store.post_trigger_buffer_size = val;
+    });
+    Ok(())
+}
+
+/// Initiate MP4 recording using the post trigger buffer on all cameras.
+///
+/// Does nothing if MP4 files are already being recorded.
+pub(crate) async fn initiate_post_trigger_mp4(
+    app_state: &BraidAppState,
+) -> Result<(), (StatusCode, &'static str)> {
+    let is_saving = {
+        let tracker = app_state.shared_store.read();
+        (*tracker).as_ref().fake_mp4_recording_path.is_some()
+    };
+
+    if !is_saving {
+        app_state
+            .strand_cam_http_session_handler
+            .initiate_post_trigger_mp4_all()
+            .await
+            .map_err(|_e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "initiate_post_trigger_mp4_all failed",
+                )
+            })?;
+
+        start_saving_mp4s_all_cams(app_state, true);
+    } else {
+        debug!("Already saving, not initiating again.");
+    }
+    Ok(())
+}
+
 pub(crate) async fn callback_handler(
     axum::extract::State(app_state): axum::extract::State<crate::mainbrain::BraidAppState>,
     _session_key: axum_token_auth::SessionKey,
@@ -64,24 +219,26 @@ pub(crate) async fn callback_handler(
                     image_info.raw_cam_name.as_str()
                 );
                 let mut current_cam_data = app_state.per_cam_data_arc.write();
-                current_cam_data
-                    .get_mut(&image_info.raw_cam_name)
-                    .unwrap()
-                    .current_image_png = image_info.inner.current_image_png;
+                if let Some(cam_data) = current_cam_data.get_mut(&image_info.raw_cam_name) {
+                    cam_data.current_image_png = image_info.inner.current_image_png;
+                } else {
+                    // The camera was removed (e.g. it stopped sending frames).
+                    debug!("ignoring image from removed camera");
+                }
             }
             UpdateCamSettings(cam_settings) => {
                 let mut current_cam_data = app_state.per_cam_data_arc.write();
-                current_cam_data
-                    .get_mut(&cam_settings.raw_cam_name)
-                    .unwrap()
-                    .cam_settings_data = Some(cam_settings.inner);
+                if let Some(cam_data) = current_cam_data.get_mut(&cam_settings.raw_cam_name) {
+                    cam_data.cam_settings_data = Some(cam_settings.inner);
+                }
             }
             UpdateFeatureDetectSettings(feature_detect_settings) => {
                 let mut current_cam_data = app_state.per_cam_data_arc.write();
-                current_cam_data
-                    .get_mut(&feature_detect_settings.raw_cam_name)
-                    .unwrap()
-                    .feature_detect_settings = Some(feature_detect_settings.inner);
+                if let Some(cam_data) =
+                    current_cam_data.get_mut(&feature_detect_settings.raw_cam_name)
+                {
+                    cam_data.feature_detect_settings = Some(feature_detect_settings.inner);
+                }
             }
             DoRecordCsvTables(value) => {
                 debug!("got DoRecordCsvTables({})", value);
@@ -97,78 +254,131 @@ pub(crate) async fn callback_handler(
             }
             DoRecordMp4Files(start_saving) => {
                 debug!("got DoRecordMp4Files({start_saving})");
-
-                app_state
-                    .strand_cam_http_session_handler
-                    .toggle_saving_mp4_files_all(start_saving)
-                    .await
-                    .map_err(|_e| {
-                        (
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            "toggle_saving_mp4_files_all failed",
-                        )
-                    })?;
-
-                start_saving_mp4s_all_cams(&app_state, start_saving);
+                set_mp4_recording(&app_state, start_saving).await?;
             }
             SetExperimentUuid(value) => {
                 debug!("got SetExperimentUuid({})", value);
-                if let Some(braidz_write_tx) = app_state.braidz_write_tx_weak.upgrade() {
-                    // `braidz_write_tx` will be dropped after this scope.
-                    braidz_write_tx
-                        .send(flydra2::SaveToDiskMsg::SetExperimentUuid(value))
-                        .await
-                        .unwrap();
-                }
+                set_experiment_uuid(&app_state, value).await;
+            }
+            AddAnnotation(annotation) => {
+                debug!("got AddAnnotation({:?})", annotation.kind);
+                add_annotation(&app_state, annotation).await;
+            }
+            SetTrackingParams(update) => {
+                debug!("got SetTrackingParams for {:?}", update.scope);
+                set_tracking_params(&app_state, update).await?;
             }
             SetPostTriggerBufferSize(val) => {
                 debug!("got SetPostTriggerBufferSize({val})");
-
-                app_state
-                    .strand_cam_http_session_handler
-                    .set_post_trigger_buffer_all(val)
-                    .await
-                    .map_err(|_e| {
-                        (
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            "set_post_trigger_buffer_all failed",
-                        )
-                    })?;
-
+                set_post_trigger_buffer_size(&app_state, val).await?;
+            }
+            RegisterAgent(agent_info) => {
+                let agent_name = agent_info.agent_name;
+                info!(
+                    "Agent \"{agent_name}\" on host \"{}\" registered.",
+                    agent_info.hostname
+                );
+                // Launch the agent's cameras unless they are already connected
+                // (e.g. if the agent itself was restarted).
+                let connected = app_state.cam_manager.all_raw_cam_names();
+                let to_launch: Vec<_> = app_state
+                    .camera_configs
+                    .iter()
+                    .filter(|(cam_name, camera)| {
+                        camera.agent.as_ref() == Some(&agent_name) && !connected.contains(*cam_name)
+                    })
+                    .map(|(_cam_name, camera)| camera.clone())
+                    .collect();
+                if !app_state
+                    .camera_configs
+                    .values()
+                    .any(|camera| camera.agent.as_ref() == Some(&agent_name))
                 {
-                    let mut tracker = app_state.shared_store.write();
-                    tracker.modify(|store| {
-                        store.post_trigger_buffer_size = val;
-                    });
+                    warn!("No cameras configured for agent \"{agent_name}\".");
                 }
+                app_state.agent_registry.register(&agent_name, to_launch);
+            }
+            AgentCameraExited(exit) => {
+                debug!(
+                    "agent \"{}\" reported exit of Strand Camera for \"{}\" (success: {})",
+                    exit.agent_name,
+                    exit.raw_cam_name.as_str(),
+                    exit.success
+                );
+                app_state
+                    .agent_registry
+                    .camera_exited(braid::strand_cam_process::StrandCamExit {
+                        cam_name: exit.raw_cam_name,
+                        success: exit.success,
+                    });
             }
             PostTriggerMp4Recording => {
                 debug!("got PostTriggerMp4Recording");
-
-                let is_saving = {
-                    let tracker = app_state.shared_store.read();
-                    (*tracker).as_ref().fake_mp4_recording_path.is_some()
-                };
-
-                if !is_saving {
-                    app_state
-                        .strand_cam_http_session_handler
-                        .initiate_post_trigger_mp4_all()
-                        .await
-                        .map_err(|_e| {
-                            (
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                "initiate_post_trigger_mp4_all failed",
-                            )
-                        })?;
-
-                    start_saving_mp4s_all_cams(&app_state, true);
-                } else {
-                    debug!("Already saving, not initiating again.");
-                }
+                initiate_post_trigger_mp4(&app_state).await?;
             }
         }
         Ok::<_, (StatusCode, &'static str)>(())
     };
     fut.await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flydra_types::{FlydraFloatTimestampLocal, SyncFno};
+
+    #[tokio::test]
+    async fn test_annotation_saved_with_current_frame() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let (app_state, coord_processor) =
+            crate::mainbrain::tests::app_state(output_dir.path().to_path_buf());
+        let trigger_timestamp = FlydraFloatTimestampLocal::from_f64(1_700_000_000.5);
+        *app_state.latest_frame_arc.write() = Some(LatestFrame {
+            frame: SyncFno(123),
+            trigger_timestamp: Some(trigger_timestamp.clone()),
+        });
+
+        let toggle_saving = |start_saving| {
+            toggle_saving_csv_tables(
+                start_saving,
+                app_state.expected_framerate_arc.clone(),
+                app_state.output_base_dirname.clone(),
+                app_state.braidz_write_tx_weak.clone(),
+                app_state.per_cam_data_arc.clone(),
+                app_state.shared_store.clone(),
+            )
+        };
+        toggle_saving(true).await;
+        let annotation = NewAnnotation {
+            kind: "stimulus_onset".to_string(),
+            message: "light on".to_string(),
+            payload: Some(serde_json::json!({"intensity": 0.5})),
+        };
+        let row = add_annotation(&app_state, annotation).await;
+        toggle_saving(false).await;
+
+        // Dropping the sender ends the writer once the `.braidz` file is
+        // complete.
+        let flydra2::CoordProcessor {
+            writer_join_handle, ..
+        } = coord_processor;
+        writer_join_handle.await.unwrap().unwrap();
+
+        let braidz_path = std::fs::read_dir(output_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "braidz"))
+            .expect("no .braidz file written");
+        let archive = braidz_parser::braidz_parse_path(&braidz_path).unwrap();
+        assert_eq!(archive.annotations.len(), 1);
+        let saved = &archive.annotations[0];
+        assert_eq!(row.frame, Some(SyncFno(123)));
+        assert_eq!(saved.frame, row.frame);
+        assert_eq!(
+            saved.timestamp.as_ref().map(|t| t.as_f64()),
+            Some(trigger_timestamp.as_f64())
+        );
+        assert_eq!(saved.kind, "stimulus_onset");
+        assert_eq!(saved.payload, r#"{"intensity":0.5}"#);
+    }
+}
@@ -9,13 +9,14 @@ use tracing::debug;
 
 use braid::braid_start;
 use braid_config_data::parse_config_file;
-use flydra_types::{
-    BraidCameraConfig, BuiServerAddrInfo, RawCamName, StartCameraBackend, TriggerType,
-};
+use flydra_types::{BraidCameraConfig, RawCamName, TriggerType};
 
+mod agents;
+mod api_v1;
 mod callback_handling;
 mod mainbrain;
 mod multicam_http_session_handler;
+mod strand_cam_launcher;
 
 #[derive(Debug, Parser)]
 #[command(author, version, about)]
@@ -27,72 +28,6 @@ struct BraidRunCliArgs {
     disable_console: bool,
 }
 
-fn compute_strand_cam_args(
-    camera: &BraidCameraConfig,
-    mainbrain_internal_addr: &BuiServerAddrInfo,
-) -> Result<Vec<String>> {
-    let urls = mainbrain_internal_addr.build_urls()?;
-    let url = urls
-        .first()
-        .ok_or_else(|| eyre::eyre!("need at least one URL"))?;
-    let url_string = format!("{url}");
-    Ok(vec![
-        "--camera-name".into(),
-        camera.name.clone(),
-        "--braid-url".into(),
-        url_string,
-    ])
-}
-
-fn launch_strand_cam(
-    strand_cam_set: &mut tokio::task::JoinSet<()>,
-    camera: &BraidCameraConfig,
-    mainbrain_internal_addr: &BuiServerAddrInfo,
-) -> Result<()> {
-    // On initial startup strand cam queries for
-    // [flydra_types::RemoteCameraInfoResponse] and thus we do not need to
-    // provide much info.
-
-    let braid_run_exe = std::env::current_exe().unwrap();
-    let exe_dir = braid_run_exe
-        .parent()
-        .expect("Executable must be in some directory");
-    #[cfg(target_os = "windows")]
-    let ext = ".exe";
-    #[cfg(not(target_os = "windows"))]
-    let ext = "";
-    let exe = exe_dir.join(format!(
-        "{}{}",
-        camera.start_backend.strand_cam_exe_name().unwrap(),
-        ext
-    ));
-    debug!("strand cam executable name: \"{}\"", exe.display());
-
-    let cam_name = camera.name.clone();
-
-    let mut exec = std::process::Command::new(&exe);
-    let args = compute_strand_cam_args(&camera, mainbrain_internal_addr)?;
-    exec.args(&args);
-    debug!("exec: {:?}", exec);
-    let mut obj = exec.spawn().context(format!(
-        "Starting Strand Cam executable \"{}\"",
-        exe.display()
-    ))?;
-
-    let _abort_handle = strand_cam_set.spawn_blocking(move || {
-        let exit_code = obj.wait().unwrap();
-        if !exit_code.success() {
-            tracing::error!(
-                "Strand Cam executable for {cam_name} exited with exit code {:?}",
-                exit_code.code()
-            );
-        } else {
-            debug!("Strand Cam executable done.");
-        }
-    });
-    Ok(())
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
     std::panic::set_hook(Box::new(tracing_panic::panic_hook));
@@ -120,7 +55,7 @@ async fn main() -> Result<()> {
     tracing::info!("{} {}", "run", version);
     tracing::debug!("{:?}", cfg);
 
-    let camera_configs = cfg
+    let camera_configs: std::collections::BTreeMap<RawCamName, BraidCameraConfig> = cfg
         .cameras
         .iter()
         .map(|cfg| {
@@ -137,7 +72,7 @@ async fn main() -> Result<()> {
             false,
             flydra_types::StartSoftwareFrameRateLimit::Enable(cfg.framerate),
         ),
-        TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp => {
+        TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp(_) => {
             (false, flydra_types::StartSoftwareFrameRateLimit::NoChange)
         }
     };
@@ -154,24 +89,11 @@ async fn main() -> Result<()> {
     let (listener, mainbrain_server_info) = flydra_types::start_listener(&address_string).await?;
     let mainbrain_internal_addr = mainbrain_server_info.clone();
 
-    let cfg_cameras = cfg.cameras;
-    let mut strand_cam_set = tokio::task::JoinSet::new();
-    for camera in cfg_cameras.into_iter() {
-        if camera.start_backend != StartCameraBackend::Remote {
-            launch_strand_cam(&mut strand_cam_set, &camera, &mainbrain_internal_addr)?;
-        } else {
-            tracing::info!(
-                "Not starting remote camera \"{}\". Use args: {}",
-                camera.name,
-                compute_strand_cam_args(&camera, &mainbrain_internal_addr)
-                    .unwrap()
-                    .join(" ")
-            );
-            // Insert dummy future that never completes so that the JoinSet does
-            // not complete.
-            strand_cam_set.spawn(std::future::pending());
-        }
-    }
+    let mut strand_cam_launcher = strand_cam_launcher::StrandCamLauncher::new(
+        camera_configs.clone(),
+        mainbrain_internal_addr,
+    );
+    strand_cam_launcher.launch_all()?;
 
     debug!("done launching cameras");
 
@@ -192,7 +114,7 @@ async fn main() -> Result<()> {
         "braid",
         listener,
         mainbrain_server_info,
-        strand_cam_set,
+        strand_cam_launcher,
     )
     .await?;
 
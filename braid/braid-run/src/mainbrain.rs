@@ -1,5 +1,5 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     net::SocketAddr,
     path::PathBuf,
     sync::{
@@ -20,16 +20,18 @@ use preferences_serde1::{AppInfo, Preferences};
 use serde::Serialize;
 use tokio::net::UdpSocket;
 use tower_http::trace::TraceLayer;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use bui_backend_session_types::AccessToken;
 use event_stream_types::{AcceptsEventStream, EventBroadcaster};
 use flydra2::{CoordProcessor, CoordProcessorConfig, FrameDataAndPoints, StreamItem};
 use flydra_types::{
-    braid_http::{CAM_PROXY_PATH, REMOTE_CAMERA_INFO_PATH},
-    BraidHttpApiSharedState, BuiServerAddrInfo, CamInfo, CborPacketCodec, FakeSyncConfig,
-    FlydraFloatTimestampLocal, HostClock, PerCamSaveData, RawCamName, SyncFno, TriggerType,
-    Triggerbox, BRAID_EVENTS_URL_PATH, BRAID_EVENT_NAME, TRIGGERBOX_SYNC_SECONDS,
+    braid_api_v1::API_V1_PREFIX,
+    braid_http::{AGENT_COMMANDS_PATH, CAM_PROXY_PATH, REMOTE_CAMERA_INFO_PATH},
+    BraidHttpApiSharedState, BuiServerAddrInfo, CamInfo, CborPacketCodec, ConnectedCameraSyncState,
+    FakeSyncConfig, FlydraFloatTimestampLocal, HostClock, PerCamSaveData, RawCamName, SyncFno,
+    TextlogRow, TriggerType, Triggerbox, BRAID_EVENTS_URL_PATH, BRAID_EVENT_NAME,
+    MODEL_EVENTS_URL_PATH, TRIGGERBOX_SYNC_SECONDS,
 };
 use rust_cam_bui_types::{ClockModel, RecordingPath};
 
@@ -38,7 +40,11 @@ use color_eyre::{
     Result,
 };
 
-use crate::multicam_http_session_handler::{MaybeSession, StrandCamHttpSessionHandler};
+use crate::{
+    agents::{AgentRegistry, AGENT_POLL_TIMEOUT},
+    multicam_http_session_handler::{MaybeSession, StrandCamHttpSessionHandler},
+    strand_cam_launcher::StrandCamLauncher,
+};
 
 #[cfg(feature = "bundle_files")]
 static ASSETS_DIR: include_dir::Dir<'static> =
@@ -98,14 +104,30 @@ pub(crate) struct BraidAppState {
     force_camera_sync_mode: bool,
     software_limit_framerate: flydra_types::StartSoftwareFrameRateLimit,
     event_broadcaster: EventBroadcaster<usize>,
+    /// Broadcasts the events of the model server to the web UI.
+    model_event_broadcaster: EventBroadcaster<usize>,
+    /// The most recent calibration event of the model server.
+    model_calibration_frame: Arc<RwLock<Option<String>>>,
     pub(crate) per_cam_data_arc: Arc<RwLock<BTreeMap<RawCamName, PerCamSaveData>>>,
     pub(crate) expected_framerate_arc: Arc<RwLock<Option<f32>>>,
-    camera_configs: BTreeMap<RawCamName, flydra_types::BraidCameraConfig>,
+    pub(crate) camera_configs: BTreeMap<RawCamName, flydra_types::BraidCameraConfig>,
     next_connection_id: Arc<RwLock<usize>>,
     pub(crate) strand_cam_http_session_handler: StrandCamHttpSessionHandler,
     pub(crate) cam_manager: flydra2::ConnectedCamerasManager,
     pub(crate) output_base_dirname: PathBuf,
     pub(crate) braidz_write_tx_weak: tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
+    pub(crate) agent_registry: AgentRegistry,
+    /// The most recent synchronized frame, used to stamp annotations.
+    pub(crate) latest_frame_arc: Arc<RwLock<Option<LatestFrame>>>,
+    /// Changes the tracking parameters of the running tracker.
+    pub(crate) tracking_params_tx: tokio::sync::mpsc::Sender<flydra_types::TrackingParamsUpdate>,
+}
+
+/// The most recent synchronized frame received from any camera.
+#[derive(Debug, Clone)]
+pub(crate) struct LatestFrame {
+    pub(crate) frame: SyncFno,
+    pub(crate) trigger_timestamp: Option<FlydraFloatTimestampLocal<Triggerbox>>,
 }
 
 async fn events_handler(
@@ -140,6 +162,38 @@ async fn events_handler(
     body
 }
 
+/// Stream the tracking results in the format of the model server.
+///
+/// This allows the web UI to show the tracked objects without connecting to
+/// the model server, which listens on another port.
+async fn model_events_handler(
+    State(app_state): State<BraidAppState>,
+    _session_key: axum_token_auth::SessionKey,
+    _: AcceptsEventStream,
+) -> impl axum::response::IntoResponse {
+    let key = {
+        let mut next_connection_id = app_state.next_connection_id.write();
+        let key = *next_connection_id;
+        *next_connection_id += 1;
+        key
+    };
+    let (tx, body) = app_state.model_event_broadcaster.new_connection(key);
+
+    // Send the calibration, if any, so the client can draw the cameras.
+    let cal_frame = app_state.model_calibration_frame.read().clone();
+    if let Some(frame_string) = cal_frame {
+        if tx
+            .send(Ok(http_body::Frame::data(frame_string.into())))
+            .await
+            .is_err()
+        {
+            tracing::debug!("initial send error");
+        }
+    }
+
+    body
+}
+
 async fn handle_auth_error(err: tower::BoxError) -> (StatusCode, &'static str) {
     match err.downcast::<axum_token_auth::ValidationErrors>() {
         Ok(err) => {
@@ -193,6 +247,22 @@ async fn remote_camera_info_handler(
     }
 }
 
+/// Wait for commands for a `braid-agent` and return them.
+///
+/// Agents call this repeatedly. If no commands arrive within
+/// [AGENT_POLL_TIMEOUT], an empty list is returned.
+async fn agent_commands_handler(
+    State(app_state): State<BraidAppState>,
+    _session_key: axum_token_auth::SessionKey,
+    Path(agent_name): Path<String>,
+) -> impl axum::response::IntoResponse {
+    let commands = app_state
+        .agent_registry
+        .next_commands(&agent_name, AGENT_POLL_TIMEOUT)
+        .await;
+    axum::Json(commands)
+}
+
 async fn cam_proxy_handler_inner(
     app_state: BraidAppState,
     _session_key: axum_token_auth::SessionKey,
@@ -268,6 +338,7 @@ async fn cam_proxy_handler(
 
 async fn launch_braid_http_backend(
     secret_base64: Option<String>,
+    api_token: Option<String>,
     listener: tokio::net::TcpListener,
     mainbrain_server_info: BuiServerAddrInfo,
     app_state: BraidAppState,
@@ -318,17 +389,27 @@ async fn launch_braid_http_backend(
 
     let auth_layer = cfg.into_layer();
 
+    let api_token = crate::api_v1::api_token(api_token, mainbrain_server_info.token());
+
     assert_eq!(BRAID_EVENTS_URL_PATH, "braid-events");
+    assert_eq!(MODEL_EVENTS_URL_PATH, "model-events");
     assert_eq!(REMOTE_CAMERA_INFO_PATH, "remote-camera-info");
     assert_eq!(CAM_PROXY_PATH, "cam-proxy");
+    assert_eq!(AGENT_COMMANDS_PATH, "agent-commands");
+    assert_eq!(API_V1_PREFIX, "api/v1");
 
     // Create axum router.
     let router = axum::Router::new()
         .route("/braid-events", get(events_handler))
+        .route("/model-events", get(model_events_handler))
         .route(
             "/remote-camera-info/:encoded_cam_name",
             get(remote_camera_info_handler),
         )
+        .route(
+            "/agent-commands/:encoded_agent_name",
+            get(agent_commands_handler),
+        )
         // .route("/cam-proxy/:encoded_cam_name", get(slash_redirect_handler))
         .route(
             "/cam-proxy/:encoded_cam_name/",
@@ -354,6 +435,12 @@ async fn launch_braid_http_backend(
                 ))
                 .layer(auth_layer),
         )
+        // Added after the cookie and token auth layer, which thus does not
+        // apply to the REST API.
+        .nest(
+            "/api/v1",
+            crate::api_v1::router(api_token).layer(TraceLayer::new_for_http()),
+        )
         .with_state(app_state);
 
     // create future for our app
@@ -507,7 +594,7 @@ pub(crate) async fn do_run_forever(
     saving_program_name: &str,
     listener: tokio::net::TcpListener,
     mainbrain_server_info: BuiServerAddrInfo,
-    mut strand_cam_set: tokio::task::JoinSet<()>,
+    mut strand_cam_launcher: StrandCamLauncher,
 ) -> Result<()> {
     let cal_fname: Option<std::path::PathBuf> = mainbrain_config.cal_fname.clone();
     let output_base_dirname: std::path::PathBuf = mainbrain_config.output_base_dirname.clone();
@@ -526,6 +613,9 @@ pub(crate) async fn do_run_forever(
 
     let save_empty_data2d: bool = mainbrain_config.save_empty_data2d;
     let write_buffer_size_num_messages = mainbrain_config.write_buffer_size_num_messages;
+    let camera_stale_timeout_secs = mainbrain_config.camera_stale_timeout_secs;
+    let relaunch_strand_cam = mainbrain_config.relaunch_strand_cam;
+    let api_token = mainbrain_config.api_token.clone();
 
     info!("saving to directory: {}", output_base_dirname.display());
 
@@ -591,11 +681,13 @@ pub(crate) async fn do_run_forever(
             ignore_latency,
             mini_arena_debug_image_dir: None,
             write_buffer_size_num_messages,
+            per_observation_timestamps: matches!(trigger_cfg, TriggerType::DeviceTimestamp(_)),
         },
         cam_manager.clone(),
         recon.clone(),
         flydra2::BraidMetadataBuilder::saving_program_name(saving_program_name),
     )?;
+    let tracking_params_tx = coord_processor.tracking_params_sender();
 
     // Here is what we do on quit:
     // 1) Stop saving data, convert .braid dir to .braidz, close files.
@@ -604,9 +696,13 @@ pub(crate) async fn do_run_forever(
     let mut quit_trigger_container = Some(quit_trigger);
     let mut strand_cam_http_session_handler2 = strand_cam_http_session_handler.clone();
     let braidz_write_tx_weak = coord_processor.braidz_write_tx.downgrade();
+    let shutting_down = Arc::new(AtomicBool::new(false));
+    let shutting_down2 = shutting_down.clone();
     tokio::spawn(async move {
         while let Some(()) = shtdwn_q_rx.recv().await {
             debug!("got shutdown command {}:{}", file!(), line!());
+            // Cameras quitting from now on should not be relaunched.
+            shutting_down2.store(true, Ordering::SeqCst);
 
             if let Some(braidz_write_tx) = braidz_write_tx_weak.upgrade() {
                 // `braidz_write_tx` will be dropped after this scope.
@@ -642,14 +738,14 @@ pub(crate) async fn do_run_forever(
             let (tx, rx) = tokio::sync::mpsc::channel(20);
             (Some(tx), Some(rx))
         }
-        TriggerType::FakeSync(_) | TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp => {
+        TriggerType::FakeSync(_) | TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp(_) => {
             (None, None)
         }
     };
 
     let needs_clock_model = match &trigger_cfg {
         TriggerType::TriggerboxV1(_) | TriggerType::FakeSync(_) => true,
-        TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp => false,
+        TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp(_) => false,
     };
 
     let sync_pulse_pause_started: Option<std::time::Instant> = None;
@@ -657,6 +753,8 @@ pub(crate) async fn do_run_forever(
 
     let flydra_app_name = "Braid".to_string();
 
+    let mini_arena_config = mainbrain_config.tracking_params.mini_arena_config.clone();
+
     let shared = BraidHttpApiSharedState {
         trigger_type: trigger_cfg.clone(),
         csv_tables_dirname: None,
@@ -669,6 +767,8 @@ pub(crate) async fn do_run_forever(
         flydra_app_name,
         all_expected_cameras_are_synced: false,
         needs_clock_model,
+        mini_arena_config,
+        tracking_params: mainbrain_config.tracking_params.clone(),
     };
     let shared_store = ChangeTracker::new(shared);
     let mut shared_store_changes_rx = shared_store.get_changes(1);
@@ -722,6 +822,7 @@ pub(crate) async fn do_run_forever(
     let braidz_write_tx_weak = coord_processor.braidz_write_tx.downgrade();
 
     let time_model_arc = Arc::new(RwLock::new(None));
+    let latest_frame_arc = Arc::new(RwLock::new(None));
 
     // Create our app state.
     let app_state = BraidAppState {
@@ -730,6 +831,8 @@ pub(crate) async fn do_run_forever(
         force_camera_sync_mode,
         software_limit_framerate,
         event_broadcaster: Default::default(),
+        model_event_broadcaster: Default::default(),
+        model_calibration_frame: Arc::new(RwLock::new(None)),
         per_cam_data_arc: per_cam_data_arc.clone(),
         camera_configs,
         next_connection_id: Arc::new(RwLock::new(0)),
@@ -738,8 +841,14 @@ pub(crate) async fn do_run_forever(
         cam_manager: cam_manager.clone(),
         output_base_dirname,
         strand_cam_http_session_handler: strand_cam_http_session_handler.clone(),
+        agent_registry: strand_cam_launcher.agent_registry().clone(),
+        latest_frame_arc: latest_frame_arc.clone(),
+        tracking_params_tx,
     };
 
+    let model_event_broadcaster = app_state.model_event_broadcaster.clone();
+    let model_calibration_frame = app_state.model_calibration_frame.clone();
+
     // This future will send state updates to all connected event listeners.
     let event_broadcaster = app_state.event_broadcaster.clone();
     let event_broadcast_fut = async move {
@@ -749,9 +858,14 @@ pub(crate) async fn do_run_forever(
         }
     };
 
-    let http_serve_future =
-        launch_braid_http_backend(secret_base64, listener, mainbrain_server_info, app_state)
-            .await?;
+    let http_serve_future = launch_braid_http_backend(
+        secret_base64,
+        api_token,
+        listener,
+        mainbrain_server_info,
+        app_state,
+    )
+    .await?;
 
     let signal_triggerbox_connected = Arc::new(AtomicBool::new(false));
 
@@ -763,51 +877,6 @@ pub(crate) async fn do_run_forever(
         assert!(old_callback.is_none());
     }
 
-    let (triggerbox_data_tx, mut triggerbox_data_rx) =
-        tokio::sync::mpsc::channel::<braid_triggerbox::TriggerClockInfoRow>(20);
-
-    match &trigger_cfg {
-        TriggerType::TriggerboxV1(_) | TriggerType::FakeSync(_) => {
-            let braidz_write_tx_weak = coord_processor.braidz_write_tx.downgrade();
-            let signal_triggerbox_connected = signal_triggerbox_connected.clone();
-
-            let mut has_triggerbox_connected = false;
-            let triggerbox_future = async move {
-                debug!(
-                    "starting triggerbox listener future {}:{}",
-                    file!(),
-                    line!()
-                );
-                while let Some(msg) = triggerbox_data_rx.recv().await {
-                    if !has_triggerbox_connected {
-                        has_triggerbox_connected = true;
-                        info!("triggerbox is connected.");
-                        signal_triggerbox_connected.store(true, Ordering::SeqCst);
-                    }
-                    let msg2 = flydra_types::TriggerClockInfoRow {
-                        start_timestamp: msg.start_timestamp.into(),
-                        framecount: msg.framecount,
-                        tcnt: msg.tcnt,
-                        stop_timestamp: msg.stop_timestamp.into(),
-                    };
-
-                    if let Some(braidz_write_tx) = braidz_write_tx_weak.upgrade() {
-                        // `braidz_write_tx` will be dropped after this scope.
-                        braidz_write_tx
-                            .send(flydra2::SaveToDiskMsg::TriggerClockInfo(msg2))
-                            .await
-                            .unwrap();
-                    }
-                }
-                debug!("triggerbox listener future done {}:{}", file!(), line!());
-            };
-            tokio::spawn(triggerbox_future);
-        }
-        _ => {
-            debug!("not listening to triggerbox");
-        }
-    }
-
     let tracker = shared_store.clone();
 
     let on_new_clock_model = {
@@ -815,15 +884,9 @@ pub(crate) async fn do_run_forever(
         let strand_cam_http_session_handler = strand_cam_http_session_handler.clone();
         let tracker = tracker.clone();
         let trigger_cfg = trigger_cfg.clone();
-        Box::new(move |tm1: Option<braid_triggerbox::ClockModel>| {
+        Box::new(move |tm: Option<ClockModel>| {
             match &trigger_cfg {
                 TriggerType::FakeSync(_) | TriggerType::TriggerboxV1(_) => {
-                    let tm = tm1.map(|x| rust_cam_bui_types::ClockModel {
-                        gain: x.gain,
-                        offset: x.offset,
-                        n_measurements: x.n_measurements,
-                        residuals: x.residuals,
-                    });
                     let cm = tm.clone();
                     {
                         let mut guard = time_model_arc.write();
@@ -847,7 +910,7 @@ pub(crate) async fn do_run_forever(
                         };
                     });
                 }
-                TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp => {
+                TriggerType::PtpSync(_) | TriggerType::DeviceTimestamp(_) => {
                     // no central clock model
                     panic!("No need for clock model.");
                 }
@@ -893,11 +956,25 @@ pub(crate) async fn do_run_forever(
                 *expected_framerate = Some(rate_actual as f32);
             }
 
+            // The clock model is fit here from the measurements of the
+            // triggerbox rather than by `braid_triggerbox`.
+            let (triggerbox_data_tx, triggerbox_data_rx) =
+                tokio::sync::mpsc::channel::<braid_triggerbox::TriggerClockInfoRow>(20);
+            tokio::spawn(fit_triggerbox_clock_model(
+                triggerbox_data_rx,
+                on_new_clock_model,
+                max_triggerbox_measurement_error,
+                signal_triggerbox_connected.clone(),
+                coord_processor.braidz_write_tx.downgrade(),
+            ));
+
             // Emperically, an Arduino Nano requires 7 seconds to wake up.
             let sleep_dur = std::time::Duration::from_secs_f32(7.0);
 
             let triggerbox = braid_triggerbox::TriggerboxDevice::new(
-                on_new_clock_model,
+                Box::new(|tm: Option<braid_triggerbox::ClockModel>| {
+                    debug!("braid_triggerbox clock model: {tm:?}");
+                }),
                 device_fname,
                 cmd_rx,
                 Some(triggerbox_data_tx),
@@ -931,11 +1008,14 @@ pub(crate) async fn do_run_forever(
             let now: chrono::DateTime<chrono::Utc> = chrono::Utc::now();
             let offset = datetime_conversion::datetime_to_f64(&now);
 
-            (on_new_clock_model)(Some(braid_triggerbox::ClockModel {
+            (on_new_clock_model)(Some(ClockModel {
                 gain,
                 n_measurements: 0,
                 offset,
                 residuals: 0.0,
+                n_inliers: None,
+                residual_std: None,
+                prediction_std: None,
             }));
         }
         TriggerType::PtpSync(ptpcfg) => {
@@ -947,8 +1027,12 @@ pub(crate) async fn do_run_forever(
                 *expected_framerate = Some(framerate as f32);
             }
         }
-        TriggerType::DeviceTimestamp => {
+        TriggerType::DeviceTimestamp(cfg) => {
             signal_triggerbox_connected.store(true, Ordering::SeqCst);
+
+            // Tracking results are output at this rate.
+            let mut expected_framerate = expected_framerate_arc.write();
+            *expected_framerate = Some(cfg.output_framerate as f32);
         }
     };
 
@@ -997,6 +1081,7 @@ pub(crate) async fn do_run_forever(
     // Signal cameras are synchronized
 
     let valve2 = valve.clone();
+    let signal_all_cams_synced2 = signal_all_cams_synced.clone();
     let _sync_done_jh = tokio::spawn(async move {
         let interval_stream = tokio_stream::wrappers::IntervalStream::new(tokio::time::interval(
             std::time::Duration::from_secs(1),
@@ -1015,9 +1100,103 @@ pub(crate) async fn do_run_forever(
         }
     });
 
+    // Detect cameras which stopped sending frames. These are removed so
+    // tracking continues with the remaining cameras and, if Braid launched
+    // them, they are relaunched.
+    let (relaunch_tx, mut relaunch_rx) = tokio::sync::mpsc::channel::<RawCamName>(10);
+    if camera_stale_timeout_secs > 0.0 {
+        let timeout = std::time::Duration::from_secs_f64(camera_stale_timeout_secs);
+        let valve2 = valve.clone();
+        let signal_all_cams_synced = signal_all_cams_synced2.clone();
+        let cam_manager = cam_manager.clone();
+        let mut strand_cam_http_session_handler = strand_cam_http_session_handler.clone();
+        let per_cam_data_arc = per_cam_data_arc.clone();
+        let braidz_write_tx_weak = coord_processor.braidz_write_tx.downgrade();
+        let _stale_cam_jh = tokio::spawn(async move {
+            let interval_stream = tokio_stream::wrappers::IntervalStream::new(
+                tokio::time::interval(std::time::Duration::from_secs(1)),
+            );
+            let mut interval_stream = valve2.wrap(interval_stream);
+            while let Some(_now) = interval_stream.next().await {
+                if !signal_all_cams_synced.load(Ordering::SeqCst) {
+                    // Frames are paused during the initial synchronization.
+                    continue;
+                }
+                for cam_name in cam_manager.stale_cameras(timeout) {
+                    forget_camera(
+                        &mut strand_cam_http_session_handler,
+                        &per_cam_data_arc,
+                        &braidz_write_tx_weak,
+                        &cam_name,
+                        &format!("no frames received for {camera_stale_timeout_secs} seconds"),
+                    );
+                    if relaunch_strand_cam {
+                        relaunch_tx.send(cam_name).await.unwrap_or(());
+                    }
+                }
+            }
+        });
+    }
+
+    // Watch the Strand Camera processes and relaunch them when needed.
+    let strand_cam_supervisor_fut = {
+        let mut strand_cam_http_session_handler = strand_cam_http_session_handler.clone();
+        let per_cam_data_arc = per_cam_data_arc.clone();
+        let braidz_write_tx_weak = coord_processor.braidz_write_tx.downgrade();
+        async move {
+            let mut relaunch_requested = BTreeSet::new();
+            loop {
+                tokio::select! {
+                    exit = strand_cam_launcher.join_next() => {
+                        let Some(exit) = exit else {
+                            // No processes are left to watch, but tracking
+                            // continues with any remote cameras.
+                            return std::future::pending::<()>().await;
+                        };
+                        let cam_name = exit.cam_name;
+                        let killed_as_stale = relaunch_requested.remove(&cam_name);
+                        if shutting_down.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        // A camera killed as stale was already removed.
+                        if !killed_as_stale {
+                            let reason = if exit.success {
+                                "Strand Camera exited"
+                            } else {
+                                "Strand Camera exited with an error"
+                            };
+                            forget_camera(
+                                &mut strand_cam_http_session_handler,
+                                &per_cam_data_arc,
+                                &braidz_write_tx_weak,
+                                &cam_name,
+                                reason,
+                            );
+                        }
+                        let relaunch_needed = killed_as_stale || !exit.success;
+                        if !relaunch_strand_cam || !relaunch_needed {
+                            continue;
+                        }
+                        info!("Relaunching Strand Camera for \"{cam_name}\".");
+                        log_camera_event(&braidz_write_tx_weak, &cam_name, "relaunching Strand Camera");
+                        if let Err(e) = strand_cam_launcher.launch(&cam_name) {
+                            error!("Could not relaunch Strand Camera for \"{cam_name}\": {e}");
+                        }
+                    }
+                    Some(cam_name) = relaunch_rx.recv() => {
+                        if strand_cam_launcher.kill(&cam_name) {
+                            relaunch_requested.insert(cam_name);
+                        }
+                    }
+                }
+            }
+        }
+    };
+
     let strand_cam_http_session_handler2 = strand_cam_http_session_handler.clone();
     let cam_manager2 = cam_manager.clone();
     let live_stats_collector2 = live_stats_collector.clone();
+    let braidz_write_tx_weak = coord_processor.braidz_write_tx.downgrade();
 
     let packet_filter = move |r| {
         let live_stats_collector2 = live_stats_collector2.clone();
@@ -1026,11 +1205,14 @@ pub(crate) async fn do_run_forever(
         let cam_manager2 = cam_manager2.clone();
         let sync_pulse_pause_started_arc = sync_pulse_pause_started_arc.clone();
         let cam_manager = cam_manager.clone();
+        let signal_all_cams_synced = signal_all_cams_synced2.clone();
+        let braidz_write_tx_weak = braidz_write_tx_weak.clone();
         // This creates a debug logger when `packet_capture_dump_fname` is not
         // `None`.
         let mut raw_packet_logger =
             RawPacketLogger::new(mainbrain_config.packet_capture_dump_fname.as_deref()).unwrap();
         let time_model_arc = time_model_arc.clone();
+        let latest_frame_arc = latest_frame_arc.clone();
         async move {
             // vvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvv
             // Start of closure for on each incoming packet.
@@ -1054,9 +1236,32 @@ pub(crate) async fn do_run_forever(
             let raw_cam_name = RawCamName::new(packet.cam_name.clone());
             live_stats_collector2.register_new_frame_data(&raw_cam_name, packet.points.len());
 
-            // Create closure which is called only if there is a new frame offset
+            // Create closure which is called only if there is a new sync state
             // (which occurs upon synchronization).
-            let send_new_frame_offset = |frame| {
+            let on_new_sync_state = |sync_state: &ConnectedCameraSyncState| {
+                if signal_all_cams_synced.load(Ordering::SeqCst) {
+                    // The other cameras are already running, so this camera
+                    // (re)joined late.
+                    log_camera_event(
+                        &braidz_write_tx_weak,
+                        &raw_cam_name,
+                        &format!("rejoined synchronization: {sync_state:?}"),
+                    );
+                }
+                let frame = match sync_state {
+                    ConnectedCameraSyncState::Synchronized(frame) => *frame,
+                    ConnectedCameraSyncState::Rejoined(_) => {
+                        // The frame numbers of a rejoined camera are behind
+                        // the synchronized frame numbers, an offset which
+                        // `SetFrameOffset` cannot express. Tracking does not
+                        // need it because Braid computes the synchronized
+                        // frame and its trigger timestamp itself. Strand
+                        // Camera saves its own recordings without trigger
+                        // timestamps, as it does before synchronization.
+                        return;
+                    }
+                    ConnectedCameraSyncState::Unsynchronized => return,
+                };
                 let strand_cam_http_session_handler = strand_cam_http_session_handler2.clone();
                 let cam_name = raw_cam_name.clone();
                 let fut_no_err = async move {
@@ -1073,12 +1278,16 @@ pub(crate) async fn do_run_forever(
                 tokio::spawn(fut_no_err);
             };
 
-            let synced_frame = cam_manager2.got_new_frame_live(
-                &packet,
-                &sync_pulse_pause_started_arc,
-                send_new_frame_offset,
-                &trigger_cfg,
-            );
+            let synced_frame = {
+                let time_model = time_model_arc.read();
+                cam_manager2.got_new_frame_live(
+                    &packet,
+                    &sync_pulse_pause_started_arc,
+                    time_model.as_ref(),
+                    on_new_sync_state,
+                    &trigger_cfg,
+                )
+            };
 
             let cam_num = cam_manager.cam_num(&raw_cam_name);
 
@@ -1126,10 +1335,21 @@ pub(crate) async fn do_run_forever(
                                 device_timestamp_chrono.into()
                             })
                         }
-                        TriggerType::DeviceTimestamp => {
-                            todo!();
+                        TriggerType::DeviceTimestamp(_) => {
+                            // Each camera has its own timestamp, corrected to
+                            // our clock by the camera.
+                            packet.timestamp.clone()
                         }
                     };
+                    {
+                        let mut latest = latest_frame_arc.write();
+                        if latest.as_ref().map_or(true, |l| l.frame < synced_frame) {
+                            *latest = Some(LatestFrame {
+                                frame: synced_frame,
+                                trigger_timestamp: trigger_timestamp.clone(),
+                            });
+                        }
+                    }
                     (synced_frame, trigger_timestamp)
                 }
                 None => {
@@ -1182,6 +1402,37 @@ pub(crate) async fn do_run_forever(
     info!("expected_framerate: {:?}", expected_framerate);
 
     coord_processor.add_listener(data_tx);
+
+    // Forward the tracking results also to the web UI. Slow viewers must not
+    // hold up tracking, so frames are dropped for viewers which lag behind.
+    let (model_event_tx, mut model_event_rx) = tokio::sync::mpsc::channel(50);
+    coord_processor.add_listener(model_event_tx);
+    let (model_frame_tx, mut model_frame_rx) = tokio::sync::broadcast::channel(50);
+    tokio::spawn(async move {
+        while let Some(data) = model_event_rx.recv().await {
+            let frame_string = flydra2::model_event_frame(&data);
+            if let flydra2::SendType::CalibrationFlydraXml(_) = &data.0 {
+                *model_calibration_frame.write() = Some(frame_string.clone());
+            }
+            // This does not wait and only fails if the receiver is gone.
+            let _ = model_frame_tx.send(frame_string);
+        }
+    });
+    tokio::spawn(async move {
+        use tokio::sync::broadcast::error::RecvError;
+        loop {
+            match model_frame_rx.recv().await {
+                Ok(frame_string) => {
+                    model_event_broadcaster.broadcast_frame(frame_string).await;
+                }
+                Err(RecvError::Lagged(n_dropped)) => {
+                    debug!("Dropped {n_dropped} model frames for slow event listeners.");
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
     let coord_proc_fut = coord_processor.consume_stream(flydra2_stream, expected_framerate);
 
     // We "block" (in an async way) here for the entire runtime of the program.
@@ -1194,7 +1445,7 @@ pub(crate) async fn do_run_forever(
         _ = http_serve_future => {
             info!("HTTP Server finished.");
         },
-        _ = strand_cam_set.join_next() => {
+        _ = strand_cam_supervisor_fut => {
             info!("Strand Camera future set finished.");
         },
         res_writer_jh = coord_proc_fut => {
@@ -1354,6 +1605,94 @@ pub(crate) async fn toggle_saving_csv_tables(
     }
 }
 
+/// Fit the clock model of the triggerbox to its clock measurements.
+///
+/// Each measurement is saved to `trigger_clock_info` together with the
+/// updated clock model, so that the accuracy of the trigger timestamps can be
+/// checked later.
+async fn fit_triggerbox_clock_model<F>(
+    mut triggerbox_data_rx: tokio::sync::mpsc::Receiver<braid_triggerbox::TriggerClockInfoRow>,
+    on_new_clock_model: F,
+    max_triggerbox_measurement_error: std::time::Duration,
+    signal_triggerbox_connected: Arc<AtomicBool>,
+    braidz_write_tx_weak: tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
+) where
+    F: Fn(Option<ClockModel>),
+{
+    debug!(
+        "starting triggerbox listener future {}:{}",
+        file!(),
+        line!()
+    );
+    let mut estimator = clock_model::ClockModelEstimator::new(Default::default());
+    let mut has_triggerbox_connected = false;
+    while let Some(msg) = triggerbox_data_rx.recv().await {
+        if !has_triggerbox_connected {
+            has_triggerbox_connected = true;
+            info!("triggerbox is connected.");
+            signal_triggerbox_connected.store(true, Ordering::SeqCst);
+        }
+        let start_timestamp: FlydraFloatTimestampLocal<HostClock> = msg.start_timestamp.into();
+        let stop_timestamp: FlydraFloatTimestampLocal<HostClock> = msg.stop_timestamp.into();
+        let (start, stop) = (start_timestamp.as_f64(), stop_timestamp.as_f64());
+
+        // The triggerbox was sampled at an unknown time between sending the
+        // query and receiving the reply.
+        let measurement_error = (stop - start) / 2.0;
+        let update = if measurement_error <= max_triggerbox_measurement_error.as_secs_f64() {
+            let frame = msg.framecount as f64 + f64::from(msg.tcnt) / 255.0;
+            estimator.push(frame, (start + stop) / 2.0)
+        } else {
+            debug!(
+                "triggerbox measurement error {} microseconds too large",
+                (measurement_error * 1e6).round()
+            );
+            None
+        };
+
+        if let Some(update) = update.as_ref() {
+            let fit = &update.fit;
+            if update.is_outlier {
+                warn!(
+                    "triggerbox measurement rejected, residual {} microseconds",
+                    (update.residual * 1e6).round()
+                );
+            }
+            on_new_clock_model(Some(ClockModel {
+                gain: fit.gain,
+                offset: fit.offset,
+                residuals: fit.sum_sq_residuals,
+                n_measurements: fit.n_measurements.try_into().unwrap(),
+                n_inliers: Some(fit.n_inliers.try_into().unwrap()),
+                residual_std: Some(fit.residual_std),
+                prediction_std: Some(fit.prediction_std),
+            }));
+        }
+
+        let msg2 = flydra_types::TriggerClockInfoRow {
+            start_timestamp,
+            framecount: msg.framecount,
+            tcnt: msg.tcnt,
+            stop_timestamp,
+            residual: update.map(|u| u.residual),
+            outlier: update.map(|u| u.is_outlier),
+            model_gain: update.map(|u| u.fit.gain),
+            model_offset: update.map(|u| u.fit.offset),
+            model_residual_std: update.map(|u| u.fit.residual_std),
+            model_prediction_std: update.map(|u| u.fit.prediction_std),
+        };
+
+        if let Some(braidz_write_tx) = braidz_write_tx_weak.upgrade() {
+            // `braidz_write_tx` will be dropped after this scope.
+            braidz_write_tx
+                .send(flydra2::SaveToDiskMsg::TriggerClockInfo(msg2))
+                .await
+                .unwrap();
+        }
+    }
+    debug!("triggerbox listener future done {}:{}", file!(), line!());
+}
+
 async fn synchronize_cameras(
     triggerbox_cmd: Option<tokio::sync::mpsc::Sender<braid_triggerbox::Cmd>>,
     fake_sync: bool,
@@ -1402,8 +1741,260 @@ async fn begin_cam_sync_triggerbox_in_process(
     Ok(())
 }
 
+/// Record an event about a camera connection in the textlog.
+fn log_camera_event(
+    braidz_write_tx_weak: &tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
+    cam_name: &RawCamName,
+    event: &str,
+) {
+    if let Some(braidz_write_tx) = braidz_write_tx_weak.upgrade() {
+        let timestamp = datetime_conversion::datetime_to_f64(&chrono::Local::now());
+        let row = TextlogRow {
+            mainbrain_timestamp: timestamp,
+            cam_id: cam_name.as_str().to_string(),
+            host_timestamp: timestamp,
+            message: serde_json::json!({ "camera_event": event }).to_string(),
+        };
+        if let Err(e) = braidz_write_tx.try_send(flydra2::SaveToDiskMsg::Textlog(row)) {
+            error!("Could not log camera event: {e}");
+        }
+    }
+}
+
+/// Stop using a camera which quit or stopped sending frames.
+///
+/// Tracking continues with the remaining cameras. If the camera connects
+/// again, it is registered anew.
+fn forget_camera(
+    strand_cam_http_session_handler: &mut StrandCamHttpSessionHandler,
+    per_cam_data_arc: &Arc<RwLock<BTreeMap<RawCamName, PerCamSaveData>>>,
+    braidz_write_tx_weak: &tokio::sync::mpsc::WeakSender<flydra2::SaveToDiskMsg>,
+    cam_name: &RawCamName,
+    reason: &str,
+) {
+    warn!("Removing camera \"{cam_name}\": {reason}");
+    strand_cam_http_session_handler.forget_camera(cam_name);
+    per_cam_data_arc.write().remove(cam_name);
+    log_camera_event(
+        braidz_write_tx_weak,
+        cam_name,
+        &format!("removed: {reason}"),
+    );
+}
+
 fn to_event_frame(state: &BraidHttpApiSharedState) -> String {
     let buf = serde_json::to_string(&state).unwrap();
     let frame_string = format!("event: {BRAID_EVENT_NAME}\ndata: {buf}\n\n");
     frame_string
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Create the state of a Braid without cameras which saves data to
+    /// `output_base_dirname`.
+    ///
+    /// The returned [CoordProcessor] owns the sender to the `.braidz` writer,
+    /// so it must be kept alive while the state is used.
+    pub(crate) fn app_state(output_base_dirname: PathBuf) -> (BraidAppState, CoordProcessor) {
+        let tracking_params = flydra_types::default_tracking_params_full_3d();
+        let cam_manager = flydra2::ConnectedCamerasManager::new(
+            &None,
+            BTreeSet::new(),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+            None,
+        );
+        let mut coord_processor = CoordProcessor::new(
+            CoordProcessorConfig {
+                tracking_params: tracking_params.clone(),
+                save_empty_data2d: false,
+                ignore_latency: false,
+                mini_arena_debug_image_dir: None,
+                write_buffer_size_num_messages:
+                    braid_config_data::default_write_buffer_size_num_messages(),
+                per_observation_timestamps: false,
+            },
+            cam_manager.clone(),
+            None,
+            flydra2::BraidMetadataBuilder::saving_program_name("braid"),
+        )
+        .unwrap();
+
+        let shared = BraidHttpApiSharedState {
+            trigger_type: TriggerType::FakeSync(FakeSyncConfig::default()),
+            csv_tables_dirname: None,
+            fake_mp4_recording_path: None,
+            post_trigger_buffer_size: 0,
+            clock_model: None,
+            calibration_filename: None,
+            connected_cameras: Vec::new(),
+            model_server_addr: None,
+            flydra_app_name: "Braid".to_string(),
+            all_expected_cameras_are_synced: false,
+            needs_clock_model: true,
+            mini_arena_config: tracking_params.mini_arena_config.clone(),
+            tracking_params,
+        };
+        let jar = Arc::new(RwLock::new(cookie_store::CookieStore::new(None)));
+        let app_state = BraidAppState {
+            shared_store: Arc::new(RwLock::new(ChangeTracker::new(shared))),
+            lowlatency_camdata_udp_addr: "127.0.0.1:0".parse().unwrap(),
+            force_camera_sync_mode: false,
+            software_limit_framerate: flydra_types::StartSoftwareFrameRateLimit::NoChange,
+            event_broadcaster: Default::default(),
+            model_event_broadcaster: Default::default(),
+            model_calibration_frame: Arc::new(RwLock::new(None)),
+            per_cam_data_arc: Default::default(),
+            expected_framerate_arc: Arc::new(RwLock::new(None)),
+            camera_configs: BTreeMap::new(),
+            next_connection_id: Arc::new(RwLock::new(0)),
+            strand_cam_http_session_handler: StrandCamHttpSessionHandler::new(
+                cam_manager.clone(),
+                jar,
+            ),
+            cam_manager,
+            output_base_dirname,
+            braidz_write_tx_weak: coord_processor.braidz_write_tx.downgrade(),
+            agent_registry: AgentRegistry::new().0,
+            latest_frame_arc: Arc::new(RwLock::new(None)),
+            tracking_params_tx: coord_processor.tracking_params_sender(),
+        };
+        (app_state, coord_processor)
+    }
+
+    /// Run [fit_triggerbox_clock_model] on the measurements of a triggerbox
+    /// emulator until `n_rows` rows for `trigger_clock_info` are saved.
+    ///
+    /// Returns the saved rows and the clock models passed to the callback.
+    #[cfg(unix)]
+    async fn fit_emulator_clock(
+        emulator: &braid_triggerbox_emulator::Emulator,
+        max_triggerbox_measurement_error: std::time::Duration,
+        n_rows: usize,
+    ) -> (
+        Vec<flydra_types::TriggerClockInfoRow>,
+        Vec<Option<ClockModel>>,
+    ) {
+        use braid_triggerbox::{make_trig_fps_cmd, Cmd, TriggerboxDevice};
+
+        let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(20);
+        let (rate_cmd, _rate_actual) = make_trig_fps_cmd(100.0);
+        cmd_tx.send(Cmd::StopPulsesAndReset).await.unwrap();
+        cmd_tx.send(rate_cmd).await.unwrap();
+        cmd_tx.send(Cmd::StartPulses).await.unwrap();
+
+        // As in Braid, measurements are rejected when fitting the clock model
+        // rather than by `braid_triggerbox`, so a large limit is used here.
+        let (triggerbox_data_tx, triggerbox_data_rx) = tokio::sync::mpsc::channel(20);
+        let triggerbox = TriggerboxDevice::new(
+            Box::new(|_tm: Option<braid_triggerbox::ClockModel>| {}),
+            emulator.device_path().to_string(),
+            cmd_rx,
+            Some(triggerbox_data_tx),
+            None,
+            std::time::Duration::from_secs(1),
+            std::time::Duration::from_millis(100),
+        )
+        .await
+        .unwrap();
+        let triggerbox_jh = tokio::spawn(async move {
+            // Keep the command sender alive while the host runs.
+            let _cmd_tx = cmd_tx;
+            triggerbox
+                .run_forever(std::time::Duration::from_millis(50))
+                .await
+        });
+
+        let (model_tx, model_rx) = std::sync::mpsc::channel();
+        let (braidz_write_tx, mut braidz_write_rx) = tokio::sync::mpsc::channel(100);
+        let fit_jh = tokio::spawn(fit_triggerbox_clock_model(
+            triggerbox_data_rx,
+            move |tm| {
+                model_tx.send(tm).ok();
+            },
+            max_triggerbox_measurement_error,
+            Arc::new(AtomicBool::new(false)),
+            braidz_write_tx.downgrade(),
+        ));
+
+        let mut rows = Vec::new();
+        while rows.len() < n_rows {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(10), braidz_write_rx.recv())
+                    .await
+                    .expect("no triggerbox measurement saved")
+                    .unwrap();
+            if let flydra2::SaveToDiskMsg::TriggerClockInfo(row) = msg {
+                rows.push(row);
+            }
+        }
+        fit_jh.abort();
+        triggerbox_jh.abort();
+        (rows, model_rx.try_iter().collect())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_triggerbox_clock_model_follows_emulator() {
+        let emulator =
+            braid_triggerbox_emulator::Emulator::spawn(braid_triggerbox_emulator::EmulatorConfig {
+                clock_drift_ppm: 200.0,
+                jitter: std::time::Duration::from_millis(1),
+                ..Default::default()
+            })
+            .unwrap();
+        let (rows, models) =
+            fit_emulator_clock(&emulator, std::time::Duration::from_millis(20), 40).await;
+
+        for row in rows.iter() {
+            assert!(row.start_timestamp.as_f64() <= row.stop_timestamp.as_f64());
+        }
+        for pair in rows.windows(2) {
+            assert!(pair[0].framecount <= pair[1].framecount);
+        }
+
+        // The saved clock model predicts the true time of the pulses.
+        let last = rows.last().unwrap();
+        let gain = last.model_gain.expect("no clock model saved");
+        let offset = last.model_offset.unwrap();
+        assert!(last.residual.is_some());
+        let truth = emulator.true_clock_model().unwrap();
+        assert!((gain - truth.gain).abs() / truth.gain < 1e-2);
+        let framecount = last.framecount as f64;
+        let predicted = gain * framecount + offset;
+        let expected = truth.gain * framecount + truth.offset;
+        assert!(
+            (predicted - expected).abs() < 0.01,
+            "predicted {predicted}, expected {expected}"
+        );
+
+        let model = models.into_iter().flatten().last().expect("no clock model");
+        assert!((model.gain - truth.gain).abs() / truth.gain < 1e-2);
+        assert!(model.n_inliers.unwrap() <= model.n_measurements);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_triggerbox_slow_replies_rejected() {
+        // Replies take at least 50 msec, so the measurement error is at least
+        // 25 msec.
+        let emulator =
+            braid_triggerbox_emulator::Emulator::spawn(braid_triggerbox_emulator::EmulatorConfig {
+                latency: std::time::Duration::from_millis(25),
+                ..Default::default()
+            })
+            .unwrap();
+        let (rows, models) =
+            fit_emulator_clock(&emulator, std::time::Duration::from_millis(20), 10).await;
+
+        // The measurements are saved, but without a clock model.
+        for row in rows.iter() {
+            assert_eq!(row.residual, None);
+            assert_eq!(row.outlier, None);
+            assert_eq!(row.model_gain, None);
+        }
+        assert!(models.is_empty());
+    }
+}
@@ -162,6 +162,13 @@ impl StrandCamHttpSessionHandler {
         self.post(cam_name, args).await
     }
 
+    /// Drop the session to a camera and remove it from the camera manager.
+    pub(crate) fn forget_camera(&mut self, cam_name: &RawCamName) {
+        let mut name_to_session = self.name_to_session.write();
+        name_to_session.remove(cam_name);
+        self.cam_manager.remove(cam_name);
+    }
+
     async fn send_quit(&mut self, cam_name: &RawCamName) -> Result<(), MainbrainError> {
         info!("for cam {}, sending quit", cam_name.as_str());
         let args = ci2_remote_control::CamArg::DoQuit;
@@ -169,9 +176,7 @@ impl StrandCamHttpSessionHandler {
         let cam_result = self.post(cam_name, args).await;
 
         // If we are telling the camera to quit, we don't want to keep its session around
-        let mut name_to_session = self.name_to_session.write();
-        name_to_session.remove(cam_name);
-        self.cam_manager.remove(cam_name);
+        self.forget_camera(cam_name);
         // TODO: we should cancel the stream of incoming frames so that they
         // don't get processed after we have removed this camera
         // information.
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use color_eyre::{eyre, Result};
+use tracing::{debug, info};
+
+use braid::strand_cam_process::{StrandCamExit, StrandCamProcesses};
+use flydra_types::{
+    AgentCommand, BraidCameraConfig, BuiServerAddrInfo, RawCamName, StartCameraBackend,
+};
+
+use crate::agents::AgentRegistry;
+
+/// Launches Strand Camera processes for cameras configured to be started by
+/// Braid and keeps track of them so they can be relaunched.
+///
+/// Cameras with an `agent` configured are launched by sending commands to that
+/// `braid-agent`.
+pub(crate) struct StrandCamLauncher {
+    camera_configs: BTreeMap<RawCamName, BraidCameraConfig>,
+    mainbrain_internal_addr: BuiServerAddrInfo,
+    processes: StrandCamProcesses,
+    agent_registry: AgentRegistry,
+    agent_exit_rx: tokio::sync::mpsc::UnboundedReceiver<StrandCamExit>,
+}
+
+fn compute_strand_cam_args(
+    camera: &BraidCameraConfig,
+    mainbrain_internal_addr: &BuiServerAddrInfo,
+) -> Result<Vec<String>> {
+    let urls = mainbrain_internal_addr.build_urls()?;
+    let url = urls
+        .first()
+        .ok_or_else(|| eyre::eyre!("need at least one URL"))?;
+    let url_string = format!("{url}");
+    Ok(vec![
+        "--camera-name".into(),
+        camera.name.clone(),
+        "--braid-url".into(),
+        url_string,
+    ])
+}
+
+impl StrandCamLauncher {
+    pub(crate) fn new(
+        camera_configs: BTreeMap<RawCamName, BraidCameraConfig>,
+        mainbrain_internal_addr: BuiServerAddrInfo,
+    ) -> Self {
+        let (agent_registry, agent_exit_rx) = AgentRegistry::new();
+        Self {
+            camera_configs,
+            mainbrain_internal_addr,
+            processes: StrandCamProcesses::new(),
+            agent_registry,
+            agent_exit_rx,
+        }
+    }
+
+    /// The registry of `braid-agent` processes used to launch cameras.
+    pub(crate) fn agent_registry(&self) -> &AgentRegistry {
+        &self.agent_registry
+    }
+
+    /// Start all cameras which are not remote cameras.
+    ///
+    /// Cameras started by an agent are launched once the agent registers.
+    pub(crate) fn launch_all(&mut self) -> Result<()> {
+        let cam_names: Vec<RawCamName> = self.camera_configs.keys().cloned().collect();
+        for cam_name in cam_names.iter() {
+            let camera = &self.camera_configs[cam_name];
+            if let Some(agent_name) = &camera.agent {
+                if camera.start_backend == StartCameraBackend::Remote {
+                    eyre::bail!(
+                        "Camera \"{}\" has agent \"{agent_name}\" but the \"remote\" \
+                        start_backend. Set the backend which the agent should start.",
+                        camera.name
+                    );
+                }
+                info!(
+                    "Camera \"{}\" will be launched by agent \"{agent_name}\".",
+                    camera.name
+                );
+            } else if self.is_launched_by_braid(cam_name) {
+                self.launch(cam_name)?;
+            } else {
+                let camera = &self.camera_configs[cam_name];
+                info!(
+                    "Not starting remote camera \"{}\". Use args: {}",
+                    camera.name,
+                    compute_strand_cam_args(camera, &self.mainbrain_internal_addr)
+                        .unwrap()
+                        .join(" ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the Strand Camera process for this camera is started by Braid.
+    pub(crate) fn is_launched_by_braid(&self, cam_name: &RawCamName) -> bool {
+        self.camera_configs
+            .get(cam_name)
+            .map(|camera| camera.start_backend != StartCameraBackend::Remote)
+            .unwrap_or(false)
+    }
+
+    /// Start the Strand Camera process for a camera.
+    pub(crate) fn launch(&mut self, cam_name: &RawCamName) -> Result<()> {
+        // On initial startup strand cam queries for
+        // [flydra_types::RemoteCameraInfoResponse] and thus we do not need to
+        // provide much info.
+        let camera = self
+            .camera_configs
+            .get(cam_name)
+            .ok_or_else(|| eyre::eyre!("no configuration for camera \"{cam_name}\""))?;
+
+        if let Some(agent_name) = &camera.agent {
+            self.agent_registry
+                .send(agent_name, AgentCommand::Launch(camera.clone()));
+            return Ok(());
+        }
+
+        let braid_run_exe = std::env::current_exe().unwrap();
+        let exe_dir = braid_run_exe
+            .parent()
+            .expect("Executable must be in some directory");
+        #[cfg(target_os = "windows")]
+        let ext = ".exe";
+        #[cfg(not(target_os = "windows"))]
+        let ext = "";
+        let exe = exe_dir.join(format!(
+            "{}{}",
+            camera.start_backend.strand_cam_exe_name().unwrap(),
+            ext
+        ));
+        debug!("strand cam executable name: \"{}\"", exe.display());
+
+        let args = compute_strand_cam_args(camera, &self.mainbrain_internal_addr)?;
+        self.processes.spawn(cam_name, &exe, &args)
+    }
+
+    /// Kill the Strand Camera process for a camera.
+    ///
+    /// Returns `false` if there is no such process.
+    pub(crate) fn kill(&mut self, cam_name: &RawCamName) -> bool {
+        if let Some(agent_name) = self
+            .camera_configs
+            .get(cam_name)
+            .and_then(|camera| camera.agent.as_ref())
+        {
+            self.agent_registry
+                .send(agent_name, AgentCommand::Stop(cam_name.clone()));
+            return true;
+        }
+        self.processes.kill(cam_name)
+    }
+
+    /// Wait for the next Strand Camera process to end, whether started by
+    /// Braid or by an agent.
+    ///
+    /// Returns `None` if there are no more processes.
+    pub(crate) async fn join_next(&mut self) -> Option<StrandCamExit> {
+        tokio::select! {
+            Some(exit) = self.processes.join_next() => Some(exit),
+            Some(exit) = self.agent_exit_rx.recv() => Some(exit),
+            else => None,
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Start Braid with cameras assigned to two `braid-agent` instances.
+#![cfg(unix)]
+
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Strand Camera stand-in which records the camera it was started for and
+/// fails, so that Braid relaunches it.
+const FAKE_STRAND_CAM: &str = r#"#!/bin/sh
+echo "$2" >> "$(dirname "$0")/launched.txt"
+exit 1
+"#;
+
+fn free_local_addr() -> std::net::SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap()
+}
+
+/// Create a directory for an agent with the fake `strand-cam-pylon`.
+fn make_strand_cam_dir(root: &Path, agent_name: &str) -> PathBuf {
+    let dir = root.join(agent_name);
+    std::fs::create_dir(&dir).unwrap();
+    let exe = dir.join("strand-cam-pylon");
+    std::fs::write(&exe, FAKE_STRAND_CAM).unwrap();
+    std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+    dir
+}
+
+/// The cameras launched from `dir` so far.
+fn launched(dir: &Path) -> Vec<String> {
+    match std::fs::read_to_string(dir.join("launched.txt")) {
+        Ok(contents) => contents.lines().map(String::from).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cameras_launched_by_their_agents() {
+    let root = tempfile::tempdir().unwrap();
+    let addr = free_local_addr();
+
+    let config_path = root.path().join("braid-config.toml");
+    let config = format!(
+        r#"[mainbrain]
+output_base_dirname = "{}"
+http_api_server_addr = "{addr}"
+
+[[cameras]]
+name = "cam-a"
+agent = "agent-a"
+
+[[cameras]]
+name = "cam-b"
+agent = "agent-b"
+
+[trigger]
+framerate = 50.0
+trigger_type = "FakeSync"
+"#,
+        root.path().join("DATA").display()
+    );
+    std::fs::write(&config_path, config).unwrap();
+
+    let _braid = tokio::process::Command::new(env!("CARGO_BIN_EXE_braid-run"))
+        .arg(&config_path)
+        .arg("--disable-console")
+        // Keep the log file out of the real home directory.
+        .env("HOME", root.path())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+
+    let braid_url = format!("http://{addr}");
+    let dir_a = make_strand_cam_dir(root.path(), "agent-a");
+    let dir_b = make_strand_cam_dir(root.path(), "agent-b");
+    for (agent_name, dir) in [("agent-a", &dir_a), ("agent-b", &dir_b)] {
+        tokio::spawn(braid_agent::run_agent(
+            agent_name.to_string(),
+            braid_url.clone(),
+            dir.clone(),
+        ));
+    }
+
+    // Each fake Strand Camera fails, so a second launch shows that the agent
+    // reported the exit status back to Braid.
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(30);
+    while launched(&dir_a).len() < 2 || launched(&dir_b).len() < 2 {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "cameras not relaunched: agent-a {:?}, agent-b {:?}",
+            launched(&dir_a),
+            launched(&dir_b)
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert!(launched(&dir_a).iter().all(|name| name == "cam-a"));
+    assert!(launched(&dir_b).iter().all(|name| name == "cam-b"));
+}
@@ -0,0 +1,108 @@
+//! Running Strand Camera processes, shared by `braid-run` and `braid-agent`.
+
+use std::{collections::BTreeMap, path::Path};
+
+use eyre::{Result, WrapErr};
+use tracing::{debug, error, info};
+
+use flydra_types::RawCamName;
+
+/// Information about a Strand Camera process which ended.
+#[derive(Debug)]
+pub struct StrandCamExit {
+    pub cam_name: RawCamName,
+    /// Whether the process exited successfully.
+    pub success: bool,
+}
+
+/// Strand Camera processes, each of which can be killed and whose exit is
+/// reported by [StrandCamProcesses::join_next].
+#[derive(Default)]
+pub struct StrandCamProcesses {
+    strand_cam_set: tokio::task::JoinSet<StrandCamExit>,
+    kill_switches: BTreeMap<RawCamName, tokio::sync::oneshot::Sender<()>>,
+}
+
+impl StrandCamProcesses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start the Strand Camera executable `exe` with `args` for a camera.
+    pub fn spawn(&mut self, cam_name: &RawCamName, exe: &Path, args: &[String]) -> Result<()> {
+        let mut exec = tokio::process::Command::new(exe);
+        exec.args(args);
+        debug!("exec: {:?}", exec);
+        let mut child = exec
+            .spawn()
+            .with_context(|| format!("Starting Strand Cam executable \"{}\"", exe.display()))?;
+
+        let (kill_tx, mut kill_rx) = tokio::sync::oneshot::channel();
+        self.kill_switches.insert(cam_name.clone(), kill_tx);
+
+        let cam_name = cam_name.clone();
+        self.strand_cam_set.spawn(async move {
+            let exit_status = tokio::select! {
+                exit_status = child.wait() => exit_status,
+                // If the sender is dropped, this branch is disabled and we
+                // keep waiting for the process.
+                Ok(()) = &mut kill_rx => {
+                    info!("Killing Strand Cam executable for {cam_name}.");
+                    child.kill().await.unwrap_or(());
+                    child.wait().await
+                }
+            };
+            let success = match exit_status {
+                Ok(exit_status) if exit_status.success() => {
+                    debug!("Strand Cam executable done.");
+                    true
+                }
+                Ok(exit_status) => {
+                    error!(
+                        "Strand Cam executable for {cam_name} exited with exit code {:?}",
+                        exit_status.code()
+                    );
+                    false
+                }
+                Err(e) => {
+                    error!("Waiting for Strand Cam executable for {cam_name}: {e}");
+                    false
+                }
+            };
+            StrandCamExit { cam_name, success }
+        });
+        Ok(())
+    }
+
+    /// Whether a Strand Camera process for this camera is running.
+    pub fn is_running(&self, cam_name: &RawCamName) -> bool {
+        self.kill_switches.contains_key(cam_name)
+    }
+
+    /// Kill the Strand Camera process for a camera.
+    ///
+    /// Returns `false` if there is no such process.
+    pub fn kill(&mut self, cam_name: &RawCamName) -> bool {
+        match self.kill_switches.remove(cam_name) {
+            Some(kill_tx) => kill_tx.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Wait for the next Strand Camera process to end.
+    ///
+    /// Returns `None` if no processes are running.
+    pub async fn join_next(&mut self) -> Option<StrandCamExit> {
+        loop {
+            match self.strand_cam_set.join_next().await? {
+                Ok(exit) => {
+                    self.kill_switches.remove(&exit.cam_name);
+                    return Some(exit);
+                }
+                Err(e) => {
+                    error!("Strand Cam task failed: {e}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,465 @@
+//! Calibration quality report from a `.braidz` file.
+//!
+//! The 2D detections associated with each tracked object are triangulated
+//! with the calibration saved in the archive. The reprojection errors of these
+//! detections show whether the calibration still fits the cameras, for
+//! example after a camera has been bumped.
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io::{Read, Seek},
+};
+
+use color_eyre::eyre::{self, Result};
+
+use braidz_parser::BraidzArchive;
+use braidz_types::CamNum;
+use flydra_mvg::{FlydraMultiCameraSystem, MultiCamera};
+use mvg::{DistortedPixel, UndistortedPixel};
+
+mod plots;
+
+/// Number of time bins in the drift-over-time plot.
+const N_TIME_BINS: usize = 50;
+
+/// Magnification of the bias lines in the spatial error maps.
+const BIAS_SCALE: f64 = 20.0;
+
+/// A single associated 2D detection and its reprojection error.
+#[derive(Debug, Clone)]
+pub struct ReprojSample {
+    /// Time since the first detection, in seconds.
+    pub t: f64,
+    /// Detected (distorted) pixel coordinates.
+    pub pixel: [f64; 2],
+    /// Reprojected minus detected position, in undistorted pixels.
+    pub residual: [f64; 2],
+    /// Reprojection distance, in undistorted pixels.
+    pub dist: f64,
+}
+
+/// Summary statistics of the reprojection errors of one camera.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorStats {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+    /// Mean reprojected minus detected position.
+    pub bias: [f64; 2],
+}
+
+#[derive(Debug, Clone)]
+pub struct CameraReport {
+    pub width: usize,
+    pub height: usize,
+    pub samples: Vec<ReprojSample>,
+}
+
+impl CameraReport {
+    /// Return `None` if there are no samples.
+    pub fn stats(&self) -> Option<ErrorStats> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let count = self.samples.len();
+        let n = count as f64;
+        let mut dists: Vec<f64> = self.samples.iter().map(|s| s.dist).collect();
+        dists.sort_by(|a, b| a.total_cmp(b));
+        let bias = [
+            self.samples.iter().map(|s| s.residual[0]).sum::<f64>() / n,
+            self.samples.iter().map(|s| s.residual[1]).sum::<f64>() / n,
+        ];
+        Some(ErrorStats {
+            count,
+            mean: dists.iter().sum::<f64>() / n,
+            median: dists[count / 2],
+            p95: dists[(count * 95) / 100],
+            max: dists[count - 1],
+            bias,
+        })
+    }
+
+    /// The (bin center time, mean error) of each non-empty time bin.
+    pub fn binned_mean_error(&self, duration: f64, n_bins: usize) -> Vec<(f64, f64)> {
+        let bin_width = duration.max(f64::EPSILON) / n_bins as f64;
+        let mut bins = vec![(0usize, 0.0); n_bins];
+        for s in self.samples.iter() {
+            let idx = ((s.t / bin_width) as usize).min(n_bins - 1);
+            bins[idx].0 += 1;
+            bins[idx].1 += s.dist;
+        }
+        bins.iter()
+            .enumerate()
+            .filter(|(_, (count, _))| *count > 0)
+            .map(|(i, (count, sum))| ((i as f64 + 0.5) * bin_width, sum / *count as f64))
+            .collect()
+    }
+}
+
+/// Reprojection errors of all associated detections in a recording.
+#[derive(Debug, Clone)]
+pub struct CalReport {
+    /// The cameras in the calibration, by name.
+    pub cameras: BTreeMap<String, CameraReport>,
+    /// Cameras which recorded data but are not in the calibration.
+    pub uncalibrated: Vec<String>,
+    /// Triangulated 3D points.
+    pub points3d: Vec<[f64; 3]>,
+    /// Time from first to last detection, in seconds.
+    pub duration: f64,
+}
+
+/// A detection associated with an object.
+struct Detection {
+    cam_name: String,
+    pixel: DistortedPixel<f64>,
+    timestamp: f64,
+}
+
+/// Compute the reprojection error of every associated 2D detection.
+///
+/// For each object and frame, the associated detections are triangulated and
+/// the distances between the reprojected 3D point and the detections are
+/// computed with
+/// [FlydraMultiCameraSystem::get_reprojection_undistorted_dists].
+pub fn compute_report<R: Read + Seek>(archive: &mut BraidzArchive<R>) -> Result<CalReport> {
+    let cal = archive
+        .calibration_info
+        .as_ref()
+        .ok_or_else(|| eyre::eyre!("no calibration in {}", archive.display()))?;
+    let system = FlydraMultiCameraSystem::from_system(cal.cameras.clone(), cal.water);
+    let cams: BTreeMap<String, MultiCamera<f64>> = system
+        .cam_names()
+        .map(|name| (name.to_string(), system.cam_by_name(name).unwrap()))
+        .collect();
+
+    let camn2camid = archive.cam_info.camn2camid.clone();
+    let uncalibrated = camn2camid
+        .values()
+        .filter(|name| !cams.contains_key(*name))
+        .cloned()
+        .collect();
+
+    let mut assoc: BTreeMap<(u64, CamNum, u8), u32> = BTreeMap::new();
+    for row in archive.iter_data_association()? {
+        let row = row?;
+        assoc.insert((row.frame.0, row.cam_num, row.pt_idx), row.obj_id);
+    }
+    if assoc.is_empty() {
+        eyre::bail!(
+            "no data association in {}. Was 3D tracking enabled?",
+            archive.display()
+        );
+    }
+
+    let mut detections: BTreeMap<(u32, u64), Vec<Detection>> = BTreeMap::new();
+    for row in archive.iter_data2d_distorted()? {
+        let row = row?;
+        if row.x.is_nan() || row.frame < 0 {
+            continue;
+        }
+        let frame = row.frame as u64;
+        let Some(obj_id) = assoc.get(&(frame, row.camn, row.frame_pt_idx)) else {
+            continue;
+        };
+        let Some(cam_name) = camn2camid.get(&row.camn) else {
+            continue;
+        };
+        if !cams.contains_key(cam_name) {
+            continue;
+        }
+        let timestamp = row
+            .timestamp
+            .as_ref()
+            .map(|t| t.as_f64())
+            .unwrap_or_else(|| row.cam_received_timestamp.as_f64());
+        detections
+            .entry((*obj_id, frame))
+            .or_default()
+            .push(Detection {
+                cam_name: cam_name.clone(),
+                pixel: DistortedPixel {
+                    coords: nalgebra::Point2::new(row.x, row.y),
+                },
+                timestamp,
+            });
+    }
+
+    let t0 = detections
+        .values()
+        .flatten()
+        .map(|d| d.timestamp)
+        .fold(f64::INFINITY, f64::min);
+    let mut cameras: BTreeMap<String, CameraReport> = cams
+        .iter()
+        .map(|(name, cam)| {
+            (
+                name.clone(),
+                CameraReport {
+                    width: cam.width(),
+                    height: cam.height(),
+                    samples: Vec::new(),
+                },
+            )
+        })
+        .collect();
+    let mut points3d = Vec::new();
+    let mut duration: f64 = 0.0;
+    for dets in detections.values() {
+        if dets.len() < 2 {
+            continue;
+        }
+        let upoints: Vec<(String, UndistortedPixel<f64>)> = dets
+            .iter()
+            .map(|d| (d.cam_name.clone(), cams[&d.cam_name].undistort(&d.pixel)))
+            .collect();
+        let Ok(pt) = system.find3d(&upoints) else {
+            continue;
+        };
+        let pt = pt.point();
+        let dists = system.get_reprojection_undistorted_dists(&upoints, &pt)?;
+        for ((det, (cam_name, upoint)), dist) in dets.iter().zip(upoints.iter()).zip(dists) {
+            let projected = cams[cam_name].project_3d_to_pixel(&pt);
+            let t = det.timestamp - t0;
+            duration = duration.max(t);
+            cameras
+                .get_mut(cam_name)
+                .unwrap()
+                .samples
+                .push(ReprojSample {
+                    t,
+                    pixel: [det.pixel.coords.x, det.pixel.coords.y],
+                    residual: [
+                        projected.coords.x - upoint.coords.x,
+                        projected.coords.y - upoint.coords.y,
+                    ],
+                    dist,
+                });
+        }
+        points3d.push([pt.coords.x, pt.coords.y, pt.coords.z]);
+    }
+
+    Ok(CalReport {
+        cameras,
+        uncalibrated,
+        points3d,
+        duration,
+    })
+}
+
+/// Why a camera was flagged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Flag {
+    NoDetections,
+    /// The mean error over the whole recording exceeds the threshold.
+    MeanError,
+    /// The mean error exceeds the threshold starting at this time (seconds).
+    ErrorFrom(f64),
+}
+
+impl std::fmt::Display for Flag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Flag::NoDetections => write!(f, "no associated detections"),
+            Flag::MeanError => write!(f, "mean error exceeds threshold"),
+            Flag::ErrorFrom(t) => write!(f, "error exceeds threshold from {t:.1} s"),
+        }
+    }
+}
+
+impl CalReport {
+    /// Return the cameras whose error exceeds `threshold` (in pixels).
+    pub fn flagged(&self, threshold: f64) -> BTreeMap<String, Flag> {
+        let mut flagged = BTreeMap::new();
+        for (cam_name, cam) in self.cameras.iter() {
+            let flag = match cam.stats() {
+                None => Some(Flag::NoDetections),
+                Some(stats) if stats.mean > threshold => Some(Flag::MeanError),
+                Some(_) => cam
+                    .binned_mean_error(self.duration, N_TIME_BINS)
+                    .iter()
+                    .find(|(_, err)| *err > threshold)
+                    .map(|(t, _)| Flag::ErrorFrom(*t)),
+            };
+            if let Some(flag) = flag {
+                flagged.insert(cam_name.clone(), flag);
+            }
+        }
+        flagged
+    }
+
+    /// Render the report as a self-contained HTML page with SVG plots.
+    pub fn to_html(&self, title: &str, threshold: f64) -> Result<String> {
+        let flagged = self.flagged(threshold);
+        let mut html = String::new();
+        writeln!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>Calibration report: {title}</title>\n<style>\n\
+            body {{ font-family: sans-serif; }}\n\
+            table {{ border-collapse: collapse; }}\n\
+            td, th {{ border: 1px solid #ccc; padding: 0.2em 0.5em; text-align: right; }}\n\
+            .flagged {{ background-color: #fcc; }}\n\
+            </style>\n</head>\n<body>",
+            title = escape(title)
+        )?;
+        writeln!(html, "<h1>Calibration report: {}</h1>", escape(title))?;
+        writeln!(
+            html,
+            "<p>Reprojection errors of {} triangulated points over {:.1} seconds. \
+            Cameras are flagged when the reprojection error exceeds {threshold} pixels.</p>",
+            self.points3d.len(),
+            self.duration,
+        )?;
+
+        writeln!(html, "<h2>Summary</h2>\n<table>")?;
+        writeln!(
+            html,
+            "<tr><th>camera</th><th>detections</th><th>mean</th><th>median</th>\
+            <th>95th percentile</th><th>max</th><th>bias x</th><th>bias y</th><th>status</th></tr>"
+        )?;
+        for (cam_name, cam) in self.cameras.iter() {
+            let flag = flagged.get(cam_name);
+            let class = if flag.is_some() {
+                " class=\"flagged\""
+            } else {
+                ""
+            };
+            let status = flag.map(|f| f.to_string()).unwrap_or_else(|| "OK".into());
+            write!(html, "<tr{class}><td>{}</td>", escape(cam_name))?;
+            match cam.stats() {
+                Some(s) => write!(
+                    html,
+                    "<td>{}</td><td>{:.3}</td><td>{:.3}</td><td>{:.3}</td><td>{:.3}</td>\
+                    <td>{:+.3}</td><td>{:+.3}</td>",
+                    s.count, s.mean, s.median, s.p95, s.max, s.bias[0], s.bias[1]
+                )?,
+                None => write!(html, "<td>0</td>{}", "<td></td>".repeat(6))?,
+            }
+            writeln!(html, "<td>{status}</td></tr>")?;
+        }
+        writeln!(html, "</table>")?;
+        if !self.uncalibrated.is_empty() {
+            let names: Vec<String> = self.uncalibrated.iter().map(|n| escape(n)).collect();
+            writeln!(
+                html,
+                "<p>Cameras not in the calibration: {}</p>",
+                names.join(", ")
+            )?;
+        }
+
+        writeln!(html, "<h2>Reprojection error over time</h2>")?;
+        let series: Vec<(String, Vec<(f64, f64)>)> = self
+            .cameras
+            .iter()
+            .map(|(name, cam)| {
+                (
+                    name.clone(),
+                    cam.binned_mean_error(self.duration, N_TIME_BINS),
+                )
+            })
+            .collect();
+        writeln!(html, "{}", plots::drift_svg(&series, threshold)?)?;
+
+        writeln!(html, "<h2>3D coverage</h2>")?;
+        writeln!(
+            html,
+            "<p>Number of triangulated points seen from above (x-y) and from the side (x-z).</p>"
+        )?;
+        writeln!(html, "{}", plots::coverage_svg(&self.points3d)?)?;
+
+        for (cam_name, cam) in self.cameras.iter() {
+            writeln!(html, "<h2>{}</h2>", escape(cam_name))?;
+            if cam.samples.is_empty() {
+                writeln!(html, "<p>No associated detections.</p>")?;
+                continue;
+            }
+            let dists: Vec<f64> = cam.samples.iter().map(|s| s.dist).collect();
+            writeln!(html, "{}", plots::histogram_svg(&dists, threshold)?)?;
+            writeln!(
+                html,
+                "{}",
+                plots::error_map_svg(cam.width, cam.height, &cam.samples, threshold, BIAS_SCALE)?
+            )?;
+            writeln!(
+                html,
+                "<p>Left: histogram of reprojection errors. Right: mean reprojection \
+                error in image space. Lines show the mean bias (reprojected minus \
+                detected position) magnified {BIAS_SCALE} times.</p>"
+            )?;
+        }
+        writeln!(html, "</body>\n</html>")?;
+        Ok(html)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cam_with_errors(errors: &[(f64, f64)]) -> CameraReport {
+        CameraReport {
+            width: 640,
+            height: 480,
+            samples: errors
+                .iter()
+                .map(|(t, dist)| ReprojSample {
+                    t: *t,
+                    pixel: [320.0, 240.0],
+                    residual: [*dist, 0.0],
+                    dist: *dist,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_flagged() {
+        let good: Vec<(f64, f64)> = (0..100).map(|i| (i as f64, 0.2)).collect();
+        // Error jumps after the camera was bumped at t=80.
+        let bumped: Vec<(f64, f64)> = (0..100)
+            .map(|i| (i as f64, if i < 80 { 0.2 } else { 3.0 }))
+            .collect();
+        let report = CalReport {
+            cameras: [
+                (
+                    "bad".to_string(),
+                    cam_with_errors(&[(0.0, 2.0), (99.0, 2.0)]),
+                ),
+                ("bumped".to_string(), cam_with_errors(&bumped)),
+                ("good".to_string(), cam_with_errors(&good)),
+                ("none".to_string(), cam_with_errors(&[])),
+            ]
+            .into_iter()
+            .collect(),
+            uncalibrated: vec![],
+            points3d: vec![[0.0, 0.0, 0.0]],
+            duration: 99.0,
+        };
+        let flagged = report.flagged(1.0);
+        assert_eq!(flagged.get("bad"), Some(&Flag::MeanError));
+        assert!(
+            matches!(flagged.get("bumped"), Some(Flag::ErrorFrom(t)) if (79.0..81.0).contains(t))
+        );
+        assert_eq!(flagged.get("good"), None);
+        assert_eq!(flagged.get("none"), Some(&Flag::NoDetections));
+
+        let stats = report.cameras["good"].stats().unwrap();
+        assert_eq!(stats.count, 100);
+        assert!((stats.mean - 0.2).abs() < 1e-12);
+        assert!((stats.bias[0] - 0.2).abs() < 1e-12);
+
+        let html = report.to_html("test", 1.0).unwrap();
+        assert!(html.contains("<svg"));
+        assert!(html.contains("mean error exceeds threshold"));
+    }
+}
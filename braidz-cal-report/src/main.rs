@@ -0,0 +1,49 @@
+use clap::Parser;
+use color_eyre::eyre::{Result, WrapErr};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(author, version, about)]
+struct Opt {
+    /// Input .braidz file
+    input: PathBuf,
+
+    /// Output HTML filename. Defaults to "<INPUT>.cal-report.html"
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Reprojection error (in pixels) above which a camera is flagged
+    #[arg(short, long, default_value_t = 1.0)]
+    threshold: f64,
+}
+
+fn main() -> Result<()> {
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    env_tracing_logger::init();
+    let opt = Opt::parse();
+
+    let output = opt.output.unwrap_or_else(|| {
+        let mut output = opt.input.as_os_str().to_owned();
+        output.push(".cal-report.html");
+        PathBuf::from(output)
+    });
+
+    let mut archive = braidz_parser::braidz_parse_path(&opt.input)
+        .with_context(|| format!("Parsing file {}", opt.input.display()))?;
+    let report = braidz_cal_report::compute_report(&mut archive)?;
+    let title = opt
+        .input
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let html = report.to_html(&title, opt.threshold)?;
+    std::fs::write(&output, html).with_context(|| format!("Writing file {}", output.display()))?;
+    tracing::info!("Saved report to {}", output.display());
+
+    for (cam_name, flag) in report.flagged(opt.threshold) {
+        tracing::warn!("Camera {cam_name}: {flag}");
+    }
+    Ok(())
+}
@@ -0,0 +1,254 @@
+use color_eyre::eyre::Result;
+use plotters::prelude::*;
+
+use crate::ReprojSample;
+
+const PLOT_WIDTH: u32 = 480;
+const PLOT_HEIGHT: u32 = 320;
+
+/// Number of cells along the longer image axis in the spatial error map.
+const MAP_CELLS: usize = 16;
+
+/// Number of cells along each axis of the 3D coverage heatmaps.
+const COVERAGE_CELLS: usize = 40;
+
+/// Color for an error value, from green (zero) over yellow (threshold) to red
+/// (twice the threshold and above).
+fn error_color(value: f64, threshold: f64) -> HSLColor {
+    let frac = (value / (2.0 * threshold)).clamp(0.0, 1.0);
+    HSLColor((1.0 - frac) / 3.0, 0.9, 0.5)
+}
+
+/// Histogram of reprojection distances with the threshold marked.
+pub(crate) fn histogram_svg(dists: &[f64], threshold: f64) -> Result<String> {
+    const N_BINS: usize = 40;
+    let mut sorted = dists.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let p99 = sorted
+        .get((sorted.len() * 99) / 100)
+        .copied()
+        .unwrap_or_default();
+    let xmax = p99.max(2.0 * threshold);
+    let bin_width = xmax / N_BINS as f64;
+    let mut counts = [0u32; N_BINS];
+    for d in dists {
+        // Values beyond the range are put in the last bin.
+        let idx = ((d / bin_width) as usize).min(N_BINS - 1);
+        counts[idx] += 1;
+    }
+    let ymax = counts.iter().copied().max().unwrap_or_default().max(1);
+
+    let mut buf = String::new();
+    {
+        let root = SVGBackend::with_string(&mut buf, (PLOT_WIDTH, PLOT_HEIGHT)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .margin(10)
+            .x_label_area_size(35)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0.0..xmax, 0u32..ymax + ymax / 10 + 1)?;
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .x_desc("reprojection error (pixels)")
+            .y_desc("count")
+            .draw()?;
+        chart.draw_series(counts.iter().enumerate().map(|(i, count)| {
+            let x0 = i as f64 * bin_width;
+            let color = error_color(x0 + 0.5 * bin_width, threshold);
+            Rectangle::new([(x0, 0), (x0 + bin_width, *count)], color.filled())
+        }))?;
+        chart.draw_series(std::iter::once(PathElement::new(
+            vec![(threshold, 0), (threshold, ymax + ymax / 10 + 1)],
+            RED.stroke_width(2),
+        )))?;
+        root.present()?;
+    }
+    Ok(buf)
+}
+
+/// Mean reprojection error and mean bias in cells over the image.
+///
+/// Each cell is colored by the mean error of the detections within it. The
+/// line from the center of the cell shows the mean of the reprojected minus
+/// detected position, magnified by `bias_scale`.
+pub(crate) fn error_map_svg(
+    width: usize,
+    height: usize,
+    samples: &[ReprojSample],
+    threshold: f64,
+    bias_scale: f64,
+) -> Result<String> {
+    let (w, h) = (width.max(1) as f64, height.max(1) as f64);
+    let cell_size = w.max(h) / MAP_CELLS as f64;
+    let nx = (w / cell_size).ceil() as usize;
+    let ny = (h / cell_size).ceil() as usize;
+
+    // (count, sum of distances, sum of residuals)
+    let mut cells = vec![(0u32, 0.0, [0.0, 0.0]); nx * ny];
+    for s in samples {
+        let ix = ((s.pixel[0] / cell_size) as usize).min(nx - 1);
+        let iy = ((s.pixel[1] / cell_size) as usize).min(ny - 1);
+        let cell = &mut cells[iy * nx + ix];
+        cell.0 += 1;
+        cell.1 += s.dist;
+        cell.2[0] += s.residual[0];
+        cell.2[1] += s.residual[1];
+    }
+
+    let plot_height = ((PLOT_WIDTH as f64) * h / w) as u32 + 50;
+    let mut buf = String::new();
+    {
+        let root = SVGBackend::with_string(&mut buf, (PLOT_WIDTH, plot_height)).into_drawing_area();
+        root.fill(&WHITE)?;
+        // Image coordinates, with y increasing downwards.
+        let mut chart = ChartBuilder::on(&root)
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0.0..w, h..0.0)?;
+        chart.configure_mesh().disable_mesh().draw()?;
+        for iy in 0..ny {
+            for ix in 0..nx {
+                let (count, sum_dist, sum_residual) = cells[iy * nx + ix];
+                if count == 0 {
+                    continue;
+                }
+                let n = count as f64;
+                let x0 = ix as f64 * cell_size;
+                let y0 = iy as f64 * cell_size;
+                let x1 = (x0 + cell_size).min(w);
+                let y1 = (y0 + cell_size).min(h);
+                let color = error_color(sum_dist / n, threshold);
+                chart.draw_series(std::iter::once(Rectangle::new(
+                    [(x0, y0), (x1, y1)],
+                    color.filled(),
+                )))?;
+                let (cx, cy) = (0.5 * (x0 + x1), 0.5 * (y0 + y1));
+                let bias = [sum_residual[0] / n, sum_residual[1] / n];
+                chart.draw_series(std::iter::once(PathElement::new(
+                    vec![
+                        (cx, cy),
+                        (cx + bias_scale * bias[0], cy + bias_scale * bias[1]),
+                    ],
+                    BLACK.stroke_width(1),
+                )))?;
+                chart.draw_series(std::iter::once(Circle::new((cx, cy), 1, BLACK.filled())))?;
+            }
+        }
+        root.present()?;
+    }
+    Ok(buf)
+}
+
+/// Number of triangulated points in cells of the top (x-y) and side (x-z)
+/// views.
+pub(crate) fn coverage_svg(points: &[[f64; 3]]) -> Result<String> {
+    let mut lims = [[f64::INFINITY, f64::NEG_INFINITY]; 3];
+    for p in points {
+        for (lim, v) in lims.iter_mut().zip(p.iter()) {
+            lim[0] = lim[0].min(*v);
+            lim[1] = lim[1].max(*v);
+        }
+    }
+    for lim in lims.iter_mut() {
+        if !lim[0].is_finite() || lim[1] <= lim[0] {
+            // No points or no extent.
+            *lim = [lim[0].min(0.0) - 0.5, lim[1].max(0.0) + 0.5];
+        }
+    }
+
+    let mut buf = String::new();
+    {
+        let root =
+            SVGBackend::with_string(&mut buf, (2 * PLOT_WIDTH, PLOT_HEIGHT)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let (left, right) = root.split_horizontally(PLOT_WIDTH);
+        for (area, (axis, name)) in [left, right].iter().zip([(1, "y"), (2, "z")]) {
+            let xlim = lims[0];
+            let vlim = lims[axis];
+            let dx = (xlim[1] - xlim[0]) / COVERAGE_CELLS as f64;
+            let dv = (vlim[1] - vlim[0]) / COVERAGE_CELLS as f64;
+            let mut counts = vec![0u32; COVERAGE_CELLS * COVERAGE_CELLS];
+            for p in points {
+                let ix = (((p[0] - xlim[0]) / dx) as usize).min(COVERAGE_CELLS - 1);
+                let iv = (((p[axis] - vlim[0]) / dv) as usize).min(COVERAGE_CELLS - 1);
+                counts[iv * COVERAGE_CELLS + ix] += 1;
+            }
+            let max_log = (counts.iter().copied().max().unwrap_or_default() as f64 + 1.0).ln();
+
+            let mut chart = ChartBuilder::on(area)
+                .margin(10)
+                .x_label_area_size(35)
+                .y_label_area_size(50)
+                .build_cartesian_2d(xlim[0]..xlim[1], vlim[0]..vlim[1])?;
+            chart
+                .configure_mesh()
+                .disable_mesh()
+                .x_desc("x")
+                .y_desc(name)
+                .draw()?;
+            chart.draw_series(counts.iter().enumerate().filter(|(_, c)| **c > 0).map(
+                |(i, count)| {
+                    let x0 = xlim[0] + (i % COVERAGE_CELLS) as f64 * dx;
+                    let v0 = vlim[0] + (i / COVERAGE_CELLS) as f64 * dv;
+                    // Logarithmic scale from light to dark blue.
+                    let frac = (*count as f64 + 1.0).ln() / max_log;
+                    let color = HSLColor(0.6, 0.8, 0.9 - 0.6 * frac);
+                    Rectangle::new([(x0, v0), (x0 + dx, v0 + dv)], color.filled())
+                },
+            ))?;
+        }
+        root.present()?;
+    }
+    Ok(buf)
+}
+
+/// Mean reprojection error over time for each camera.
+///
+/// `series` holds, for each camera, the (time, mean error) of each time bin.
+pub(crate) fn drift_svg(series: &[(String, Vec<(f64, f64)>)], threshold: f64) -> Result<String> {
+    let tmax = series
+        .iter()
+        .flat_map(|(_, s)| s.iter().map(|(t, _)| *t))
+        .fold(1.0, f64::max);
+    let emax = series
+        .iter()
+        .flat_map(|(_, s)| s.iter().map(|(_, e)| *e))
+        .fold(2.0 * threshold, f64::max);
+
+    let mut buf = String::new();
+    {
+        let root =
+            SVGBackend::with_string(&mut buf, (2 * PLOT_WIDTH, PLOT_HEIGHT)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .margin(10)
+            .x_label_area_size(35)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0.0..tmax, 0.0..emax * 1.05)?;
+        chart
+            .configure_mesh()
+            .x_desc("time (seconds)")
+            .y_desc("mean reprojection error (pixels)")
+            .draw()?;
+        for (i, (cam_name, s)) in series.iter().enumerate() {
+            let color = Palette99::pick(i).to_rgba();
+            chart
+                .draw_series(LineSeries::new(s.iter().copied(), color.stroke_width(2)))?
+                .label(cam_name.as_str())
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        chart.draw_series(std::iter::once(PathElement::new(
+            vec![(0.0, threshold), (tmax, threshold)],
+            RED.stroke_width(1),
+        )))?;
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+    }
+    Ok(buf)
+}
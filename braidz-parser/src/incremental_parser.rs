@@ -19,6 +19,9 @@ pub struct BasicInfoParsed {
     pub reconstruction_latency_hlog: Option<HistogramLog>,
     pub reprojection_distance_hlog: Option<HistogramLog>,
     pub cam_info: CamInfo,
+    /// Changes of the tracking parameters during the recording, empty if the
+    /// archive has no `tracking_params_updates` table.
+    pub tracking_params_updates: Vec<TrackingParamsUpdateRow>,
 }
 
 /// The archive been completely parsed.
@@ -35,6 +38,8 @@ pub struct FullyParsed {
     pub data2d_distorted: Option<D2DInfo>,
     /// A mapping from camera name to (width, height).
     pub image_sizes: Option<BTreeMap<String, (usize, usize)>>,
+    /// User annotations, empty if the archive has no `annotations` table.
+    pub annotations: Vec<AnnotationRow>,
 }
 
 impl ParseState for ArchiveOpened {}
@@ -306,6 +311,11 @@ impl<R: Read + Seek> IncrementalParser<R, ArchiveOpened> {
             }
         };
 
+        let tracking_params_updates = read_optional_table(
+            &mut self.archive,
+            flydra_types::TRACKING_PARAMS_UPDATES_CSV_FNAME,
+        )?;
+
         let state = BasicInfoParsed {
             metadata,
             expected_fps,
@@ -314,6 +324,7 @@ impl<R: Read + Seek> IncrementalParser<R, ArchiveOpened> {
             reconstruction_latency_hlog,
             reprojection_distance_hlog,
             cam_info,
+            tracking_params_updates,
         };
 
         Ok(IncrementalParser {
@@ -513,6 +524,9 @@ impl<R: Read + Seek> IncrementalParser<R, BasicInfoParsed> {
             }
         };
 
+        let annotations =
+            read_optional_table(&mut self.archive, flydra_types::ANNOTATIONS_CSV_FNAME)?;
+
         let cam_info = basics.cam_info;
 
         Ok(IncrementalParser {
@@ -528,6 +542,7 @@ impl<R: Read + Seek> IncrementalParser<R, BasicInfoParsed> {
                 reconstruction_latency_hlog: basics.reconstruction_latency_hlog,
                 reprojection_distance_hlog: basics.reprojection_distance_hlog,
                 image_sizes,
+                annotations,
             },
         })
     }
@@ -563,3 +578,40 @@ impl<R: Read + Seek, S: ParseState> IncrementalParser<R, S> {
         self.archive.path_starter()
     }
 }
+
+/// Read all rows of a table which is not present in all archives.
+///
+/// Returns an empty `Vec` if the table is not present.
+fn read_optional_table<R, T>(
+    archive: &mut zip_or_dir::ZipDirArchive<R>,
+    fname: &str,
+) -> Result<Vec<T>, Error>
+where
+    R: Read + Seek,
+    T: serde::de::DeserializeOwned,
+{
+    let mut path = archive.path_starter();
+    path.push(fname);
+    match open_maybe_gzipped(path) {
+        Ok(rdr) => {
+            let rdr = csv::Reader::from_reader(rdr);
+            let mut rows = Vec::new();
+            for row in rdr.into_deserialize().early_eof_ok() {
+                rows.push(row?);
+            }
+            Ok(rows)
+        }
+        Err(e) =>
+        {
+            #[allow(unused_variables)]
+            match e {
+                Error::ZipOrDir {
+                    source: zip_or_dir::Error::FileNotFound,
+                    #[cfg(feature = "backtrace")]
+                    backtrace,
+                } => Ok(Vec::new()),
+                _ => Err(e),
+            }
+        }
+    }
+}
@@ -12,11 +12,15 @@ use std::{
 use hdrhistogram::serialization::interval_log;
 use ordered_float::NotNan;
 
-use flydra_types::{FlydraFloatTimestampLocal, HostClock, TextlogRow, TrackingParams, Triggerbox};
+use flydra_types::{
+    FlydraFloatTimestampLocal, HostClock, TextlogRow, TrackingParams, TrackingParamsUpdateRow,
+    Triggerbox,
+};
 
 use braidz_types::{
-    BraidMetadata, BraidzSummary, CalibrationInfo, CamInfo, CamInfoRow, CamNum, Data2dDistortedRow,
-    Data2dSummary, HistogramSummary, KalmanEstimatesRow, KalmanEstimatesSummary,
+    AnnotationRow, BraidMetadata, BraidzSummary, CalibrationInfo, CamInfo, CamInfoRow, CamNum,
+    Data2dDistortedRow, Data2dSummary, DataAssocRow, HistogramSummary, KalmanEstimatesRow,
+    KalmanEstimatesSummary,
 };
 
 use groupby::{AscendingGroupIter, BufferedSortIter, GroupedRows};
@@ -142,6 +146,8 @@ pub struct BraidzArchive<R: Read + Seek> {
     pub data2d_distorted: Option<D2DInfo>,
     /// A mapping from camera name to (width, height).
     pub image_sizes: Option<BTreeMap<String, (usize, usize)>>,
+    /// User annotations, empty if the archive has no `annotations` table.
+    pub annotations: Vec<AnnotationRow>,
 }
 
 #[derive(Debug)]
@@ -349,6 +355,7 @@ pub fn braidz_parse<R: Read + Seek>(
         reconstruction_latency_hlog: state.reconstruction_latency_hlog,
         reprojection_distance_hlog: state.reprojection_distance_hlog,
         image_sizes: state.image_sizes,
+        annotations: state.annotations,
     })
 }
 
@@ -375,6 +382,22 @@ impl<'a, R: Read + Seek> BraidzArchive<R> {
         Ok(rdr2.into_deserialize().early_eof_ok())
     }
 
+    /// Iterate over the rows of the `data_association` table.
+    ///
+    /// This takes a mutable reference because the read location in the archive
+    /// is changed during operation.
+    pub fn iter_data_association(
+        &'a mut self,
+    ) -> Result<impl Iterator<Item = Result<DataAssocRow, csv::Error>> + 'a, Error> {
+        let data_fname = self
+            .archive
+            .path_starter()
+            .join(flydra_types::DATA_ASSOCIATE_CSV_FNAME);
+        let rdr = open_maybe_gzipped(data_fname)?;
+        let rdr2 = csv::Reader::from_reader(rdr);
+        Ok(rdr2.into_deserialize().early_eof_ok())
+    }
+
     /// Iterate over synchronized frames in `data2d_distorted` table.
     ///
     /// This sorts the data by looking ahead up to `bufsize` rows. Furthermore,
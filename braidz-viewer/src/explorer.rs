@@ -0,0 +1,640 @@
+//! Per-trajectory exploration of a loaded BRAIDZ file.
+//!
+//! Everything here is computed client-side from the parsed archive when the
+//! file is loaded. Plots are then drawn for a single selected `obj_id`.
+
+use std::collections::BTreeMap;
+
+use braidz_types::{AnnotationRow, CamNum, KalmanEstimatesRow};
+use plotters::{
+    drawing::IntoDrawingArea,
+    prelude::{
+        ChartBuilder, Circle, LineSeries, Palette, Palette99, PathElement, BLACK, BLUE, GREEN,
+        MAGENTA, RED, WHITE,
+    },
+    style::{Color, HSLColor},
+};
+use plotters_canvas::CanvasBackend;
+use wasm_bindgen::UnwrapThrowExt;
+use yew::prelude::*;
+
+const XY_VIEW: &str = "explorer-xy-canvas";
+const XZ_VIEW: &str = "explorer-xz-canvas";
+const YZ_VIEW: &str = "explorer-yz-canvas";
+const SPEED_VIEW: &str = "explorer-speed-canvas";
+const COVARIANCE_VIEW: &str = "explorer-covariance-canvas";
+const RASTER_VIEW: &str = "explorer-raster-canvas";
+const ASSOC_VIEW: &str = "explorer-assoc-canvas";
+const REPROJECTION_VIEW: &str = "explorer-reprojection-canvas";
+
+/// A single reprojection error measurement.
+struct ReprojectionError {
+    frame: u64,
+    camn: CamNum,
+    distance: f64,
+}
+
+/// All data about a single trajectory.
+struct ObjData {
+    /// Kalman estimates, sorted by frame.
+    rows: Vec<KalmanEstimatesRow>,
+    /// The cameras (and detection indices) associated with this object in
+    /// each frame.
+    assoc: BTreeMap<u64, Vec<(CamNum, u8)>>,
+    reprojection: Vec<ReprojectionError>,
+}
+
+/// Summary of reprojection errors for a single camera over the whole file.
+pub(crate) struct CamReprojectionSummary {
+    pub(crate) count: usize,
+    pub(crate) mean: f64,
+    pub(crate) median: f64,
+    pub(crate) max: f64,
+}
+
+/// Data computed from the archive for the trajectory explorer.
+pub(crate) struct ExplorerData {
+    objs: BTreeMap<u32, ObjData>,
+    /// Per-camera reprojection summary. `None` if there is no calibration or
+    /// no data association table.
+    reprojection: Option<BTreeMap<CamNum, CamReprojectionSummary>>,
+    /// Cameras for which the calibration had no entry.
+    uncalibrated: Vec<String>,
+}
+
+impl ExplorerData {
+    /// Compute explorer data from the archive.
+    ///
+    /// Returns `None` if the archive has no 3D trajectories.
+    pub(crate) fn new<R: std::io::Read + std::io::Seek>(
+        archive: &mut braidz_parser::BraidzArchive<R>,
+    ) -> Option<Self> {
+        let table = archive.kalman_estimates_table.as_ref()?;
+
+        let mut objs: BTreeMap<u32, ObjData> = BTreeMap::new();
+        for row in table.iter() {
+            objs.entry(row.obj_id)
+                .or_insert_with(|| ObjData {
+                    rows: vec![],
+                    assoc: BTreeMap::new(),
+                    reprojection: vec![],
+                })
+                .rows
+                .push(row.clone());
+        }
+        for obj in objs.values_mut() {
+            obj.rows.sort_by_key(|row| row.frame);
+        }
+
+        // Data association. This is absent in files saved without 3D tracking
+        // or by old versions of Braid.
+        let mut assoc_lookup: BTreeMap<(u64, CamNum, u8), u32> = BTreeMap::new();
+        match archive.iter_data_association() {
+            Ok(iter) => {
+                for row in iter {
+                    let row = match row {
+                        Ok(row) => row,
+                        Err(e) => {
+                            log::warn!("error reading data association: {e}");
+                            break;
+                        }
+                    };
+                    if let Some(obj) = objs.get_mut(&row.obj_id) {
+                        obj.assoc
+                            .entry(row.frame.0)
+                            .or_default()
+                            .push((row.cam_num, row.pt_idx));
+                        assoc_lookup.insert((row.frame.0, row.cam_num, row.pt_idx), row.obj_id);
+                    }
+                }
+            }
+            Err(e) => {
+                log::info!("no data association: {e}");
+            }
+        }
+
+        // Reprojection error of each associated 2D detection.
+        let mut reprojection = None;
+        let mut uncalibrated = vec![];
+        if let Some(cal) = archive.calibration_info.as_ref() {
+            let mut cams = BTreeMap::new();
+            for (camid, camn) in archive.cam_info.camid2camn.iter() {
+                match cal.cameras.cam_by_name(camid) {
+                    Some(cam) => {
+                        cams.insert(*camn, cam.clone());
+                    }
+                    None => uncalibrated.push(camid.clone()),
+                }
+            }
+
+            if !assoc_lookup.is_empty() {
+                match archive.iter_data2d_distorted() {
+                    Ok(iter) => {
+                        for row in iter {
+                            let row = match row {
+                                Ok(row) => row,
+                                Err(e) => {
+                                    log::warn!("error reading 2D data: {e}");
+                                    break;
+                                }
+                            };
+                            if row.x.is_nan() || row.frame < 0 {
+                                continue;
+                            }
+                            let frame = row.frame as u64;
+                            let key = (frame, row.camn, row.frame_pt_idx);
+                            let Some(obj_id) = assoc_lookup.get(&key) else {
+                                continue;
+                            };
+                            let Some(cam) = cams.get(&row.camn) else {
+                                continue;
+                            };
+                            let obj = objs.get_mut(obj_id).unwrap_throw();
+                            let Ok(idx) = obj.rows.binary_search_by_key(&frame, |r| r.frame.0)
+                            else {
+                                continue;
+                            };
+                            let est = &obj.rows[idx];
+                            let pt3d = mvg::PointWorldFrame {
+                                coords: nalgebra::Point3::new(est.x, est.y, est.z),
+                            };
+                            let projected = cam.project_3d_to_distorted_pixel(&pt3d);
+                            let dx = projected.coords[0] - row.x;
+                            let dy = projected.coords[1] - row.y;
+                            obj.reprojection.push(ReprojectionError {
+                                frame,
+                                camn: row.camn,
+                                distance: (dx * dx + dy * dy).sqrt(),
+                            });
+                        }
+                    }
+                    Err(e) => {
+                        log::info!("no 2D data: {e}");
+                    }
+                }
+
+                let mut by_cam: BTreeMap<CamNum, Vec<f64>> = BTreeMap::new();
+                for obj in objs.values() {
+                    for r in obj.reprojection.iter() {
+                        by_cam.entry(r.camn).or_default().push(r.distance);
+                    }
+                }
+                reprojection = Some(
+                    by_cam
+                        .into_iter()
+                        .map(|(camn, mut dists)| {
+                            dists.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                            let count = dists.len();
+                            let summary = CamReprojectionSummary {
+                                count,
+                                mean: dists.iter().sum::<f64>() / count as f64,
+                                median: dists[count / 2],
+                                max: dists[count - 1],
+                            };
+                            (camn, summary)
+                        })
+                        .collect(),
+                );
+            }
+        }
+
+        Some(Self {
+            objs,
+            reprojection,
+            uncalibrated,
+        })
+    }
+
+    /// The object with the longest trajectory, used as the initial selection.
+    pub(crate) fn default_obj_id(&self) -> Option<u32> {
+        self.objs
+            .iter()
+            .max_by_key(|(_, obj)| obj.rows.len())
+            .map(|(obj_id, _)| *obj_id)
+    }
+}
+
+/// Create the selector and the canvas elements for the explorer.
+pub(crate) fn view(
+    data: &ExplorerData,
+    cam_info: &braidz_types::CamInfo,
+    selected: Option<u32>,
+    onselect: Callback<u32>,
+) -> Html {
+    let options: Vec<Html> = data
+        .objs
+        .iter()
+        .map(|(obj_id, obj)| {
+            let label = format!("{} ({} frames)", obj_id, obj.rows.len());
+            html! {
+                <option value={obj_id.to_string()} selected={Some(*obj_id) == selected}>
+                    {label}
+                </option>
+            }
+        })
+        .collect();
+
+    let onchange = Callback::from(move |e: Event| {
+        let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+        if let Ok(obj_id) = select.value().parse() {
+            onselect.emit(obj_id);
+        }
+    });
+
+    let obj_summary = match selected.and_then(|obj_id| data.objs.get(&obj_id)) {
+        Some(obj) => {
+            let first = obj.rows.first().unwrap_throw().frame;
+            let last = obj.rows.last().unwrap_throw().frame;
+            format!("Frames {} - {}", first, last)
+        }
+        None => "".to_string(),
+    };
+
+    let reprojection_part = match &data.reprojection {
+        Some(summary) => {
+            let rows: Vec<Html> = summary
+                .iter()
+                .map(|(camn, s)| {
+                    let camid = cam_info
+                        .camn2camid
+                        .get(camn)
+                        .cloned()
+                        .unwrap_or_else(|| camn.to_string());
+                    html! {
+                        <tr>
+                            <td>{camid}</td>
+                            <td>{s.count}</td>
+                            <td>{format!("{:.2}", s.mean)}</td>
+                            <td>{format!("{:.2}", s.median)}</td>
+                            <td>{format!("{:.2}", s.max)}</td>
+                        </tr>
+                    }
+                })
+                .collect();
+            let uncalibrated = if data.uncalibrated.is_empty() {
+                html! {}
+            } else {
+                let msg = format!(
+                    "Cameras missing from calibration: {}",
+                    data.uncalibrated.join(", ")
+                );
+                html! {
+                    <p>{msg}</p>
+                }
+            };
+            html! {
+                <div>
+                    <p>{"Reprojection error (pixels) of all associated detections"}</p>
+                    <table>
+                        <tr>
+                            <th>{"Camera"}</th><th>{"N"}</th><th>{"Mean"}</th>
+                            <th>{"Median"}</th><th>{"Max"}</th>
+                        </tr>
+                        {rows}
+                    </table>
+                    {uncalibrated}
+                    <p>{"Reprojection error of selected trajectory"}</p>
+                    <canvas id={REPROJECTION_VIEW} width="1000" height="200"/>
+                </div>
+            }
+        }
+        None => html! {
+            <p>{"Reprojection error unavailable (requires calibration and data association)."}</p>
+        },
+    };
+
+    html! {
+        <div>
+            <h2>{"Trajectory explorer"}</h2>
+            <label>{"Object: "}
+                <select {onchange}>
+                    {options}
+                </select>
+            </label>
+            <span>{" "}{obj_summary}</span>
+            <div>
+                <p>{"XY, XZ and YZ projections (color indicates time)"}</p>
+                <canvas id={XY_VIEW} width="400" height="400"/>
+                <canvas id={XZ_VIEW} width="400" height="400"/>
+                <canvas id={YZ_VIEW} width="400" height="400"/>
+            </div>
+            <div>
+                <p>{"Speed"}</p>
+                <canvas id={SPEED_VIEW} width="1000" height="200"/>
+            </div>
+            <div>
+                <p>{"Position covariance (P00 red, P11 green, P22 blue)"}</p>
+                <canvas id={COVARIANCE_VIEW} width="1000" height="200"/>
+            </div>
+            <div>
+                <p>{"2D detections (gray: all, red: associated with selected object)"}</p>
+                <canvas id={RASTER_VIEW} width="1000" height="200"/>
+            </div>
+            <div>
+                <p>{"Number of cameras associated per frame"}</p>
+                <canvas id={ASSOC_VIEW} width="1000" height="200"/>
+            </div>
+            {reprojection_part}
+        </div>
+    }
+}
+
+fn time_color(i: usize, n: usize) -> HSLColor {
+    let frac = if n > 1 {
+        i as f64 / (n - 1) as f64
+    } else {
+        0.0
+    };
+    HSLColor(0.8 * frac, 1.0, 0.4)
+}
+
+fn position(row: &KalmanEstimatesRow, dim: usize) -> f64 {
+    match dim {
+        0 => row.x,
+        1 => row.y,
+        _ => row.z,
+    }
+}
+
+fn variance(row: &KalmanEstimatesRow, dim: usize) -> f64 {
+    match dim {
+        0 => row.P00,
+        1 => row.P11,
+        _ => row.P22,
+    }
+}
+
+fn range(iter: impl Iterator<Item = f64>) -> std::ops::Range<f64> {
+    let (mut lo, mut hi) = (f64::INFINITY, f64::NEG_INFINITY);
+    for v in iter.filter(|v| v.is_finite()) {
+        lo = lo.min(v);
+        hi = hi.max(v);
+    }
+    if !lo.is_finite() {
+        return 0.0..1.0;
+    }
+    if hi - lo < 1e-9 {
+        return (lo - 0.5)..(hi + 0.5);
+    }
+    let margin = (hi - lo) * 0.05;
+    (lo - margin)..(hi + margin)
+}
+
+/// Draw all explorer plots for the selected object.
+///
+/// Returns `Err(())` if a canvas was not found in the DOM.
+pub(crate) fn render(
+    data: &ExplorerData,
+    d2d: Option<&braidz_parser::D2DInfo>,
+    cam_info: &braidz_types::CamInfo,
+    annotations: &[AnnotationRow],
+    obj_id: u32,
+) -> Result<(), ()> {
+    let Some(obj) = data.objs.get(&obj_id) else {
+        return Ok(());
+    };
+    let n = obj.rows.len();
+    if n == 0 {
+        return Ok(());
+    }
+    let frame_lim = obj.rows[0].frame.0..obj.rows[n - 1].frame.0 + 1;
+
+    // Spatial projections.
+    let lims: Vec<_> = (0..3)
+        .map(|dim| range(obj.rows.iter().map(|r| position(r, dim))))
+        .collect();
+    let descs = ["x (m)", "y (m)", "z (m)"];
+    for (canv_id, a, b) in [(XY_VIEW, 0, 1), (XZ_VIEW, 0, 2), (YZ_VIEW, 1, 2)] {
+        let root = CanvasBackend::new(canv_id).ok_or(())?.into_drawing_area();
+        root.fill(&WHITE).unwrap_throw();
+        let mut chart = ChartBuilder::on(&root)
+            .x_label_area_size(30)
+            .y_label_area_size(40)
+            .build_cartesian_2d(lims[a].clone(), lims[b].clone())
+            .unwrap_throw();
+        chart
+            .configure_mesh()
+            .x_labels(3)
+            .y_labels(3)
+            .x_desc(descs[a])
+            .y_desc(descs[b])
+            .draw()
+            .unwrap_throw();
+        chart
+            .draw_series(obj.rows.iter().enumerate().map(|(i, r)| {
+                Circle::new(
+                    (position(r, a), position(r, b)),
+                    2,
+                    time_color(i, n).filled(),
+                )
+            }))
+            .unwrap_throw();
+    }
+
+    // Speed.
+    {
+        let speed: Vec<(u64, f64)> = obj
+            .rows
+            .iter()
+            .map(|r| {
+                let s = (r.xvel * r.xvel + r.yvel * r.yvel + r.zvel * r.zvel).sqrt();
+                (r.frame.0, s)
+            })
+            .collect();
+        let slim = range(speed.iter().map(|(_, s)| *s).chain(std::iter::once(0.0)));
+        let (s0, s1) = (slim.start, slim.end);
+        let root = CanvasBackend::new(SPEED_VIEW)
+            .ok_or(())?
+            .into_drawing_area();
+        root.fill(&WHITE).unwrap_throw();
+        let mut chart = ChartBuilder::on(&root)
+            .x_label_area_size(30)
+            .y_label_area_size(40)
+            .build_cartesian_2d(frame_lim.clone(), slim)
+            .unwrap_throw();
+        chart
+            .configure_mesh()
+            .x_labels(5)
+            .y_labels(3)
+            .x_desc("Frame")
+            .y_desc("Speed (m/s)")
+            .draw()
+            .unwrap_throw();
+        chart
+            .draw_series(LineSeries::new(speed, &BLACK))
+            .unwrap_throw();
+        // Mark annotations, e.g. stimulus onsets.
+        chart
+            .draw_series(
+                crate::annotation_frames(annotations, &frame_lim)
+                    .map(|f| PathElement::new(vec![(f, s0), (f, s1)], MAGENTA)),
+            )
+            .unwrap_throw();
+    }
+
+    // Covariance.
+    {
+        let plim = range(obj.rows.iter().flat_map(|r| [r.P00, r.P11, r.P22]));
+        let root = CanvasBackend::new(COVARIANCE_VIEW)
+            .ok_or(())?
+            .into_drawing_area();
+        root.fill(&WHITE).unwrap_throw();
+        let mut chart = ChartBuilder::on(&root)
+            .x_label_area_size(30)
+            .y_label_area_size(60)
+            .build_cartesian_2d(frame_lim.clone(), plim)
+            .unwrap_throw();
+        chart
+            .configure_mesh()
+            .x_labels(5)
+            .y_labels(3)
+            .x_desc("Frame")
+            .y_desc("Variance (m^2)")
+            .draw()
+            .unwrap_throw();
+        for (dim, color) in [(0, &RED), (1, &GREEN), (2, &BLUE)] {
+            chart
+                .draw_series(LineSeries::new(
+                    obj.rows.iter().map(|r| (r.frame.0, variance(r, dim))),
+                    color,
+                ))
+                .unwrap_throw();
+        }
+    }
+
+    // Per-camera 2D detection raster.
+    {
+        let camns: Vec<CamNum> = cam_info.camn2camid.keys().copied().collect();
+        let labels: Vec<String> = cam_info.camn2camid.values().cloned().collect();
+        let ncams = camns.len().max(1);
+        let root = CanvasBackend::new(RASTER_VIEW)
+            .ok_or(())?
+            .into_drawing_area();
+        root.fill(&WHITE).unwrap_throw();
+        let mut chart = ChartBuilder::on(&root)
+            .x_label_area_size(30)
+            .y_label_area_size(100)
+            .build_cartesian_2d(frame_lim.clone(), -0.5..(ncams as f64 - 0.5))
+            .unwrap_throw();
+        chart
+            .configure_mesh()
+            .x_labels(5)
+            .y_labels(ncams)
+            .y_label_formatter(&|v| {
+                let i = v.round();
+                if (v - i).abs() < 1e-6 && i >= 0.0 {
+                    labels.get(i as usize).cloned().unwrap_or_default()
+                } else {
+                    "".to_string()
+                }
+            })
+            .x_desc("Frame")
+            .draw()
+            .unwrap_throw();
+        if let Some(d2d) = d2d {
+            for (row_idx, camn) in camns.iter().enumerate() {
+                let Some(seq) = d2d.qz.get(camn) else {
+                    continue;
+                };
+                chart
+                    .draw_series(
+                        seq.frame
+                            .iter()
+                            .filter(|f| **f >= 0 && frame_lim.contains(&(**f as u64)))
+                            .map(|f| {
+                                Circle::new((*f as u64, row_idx as f64), 2, BLACK.mix(0.3).filled())
+                            }),
+                    )
+                    .unwrap_throw();
+            }
+        }
+        let row_of = |camn: &CamNum| camns.iter().position(|c| c == camn);
+        chart
+            .draw_series(obj.assoc.iter().flat_map(|(frame, cams)| {
+                cams.iter().filter_map(move |(camn, _)| {
+                    row_of(camn)
+                        .map(|row_idx| Circle::new((*frame, row_idx as f64), 2, RED.filled()))
+                })
+            }))
+            .unwrap_throw();
+    }
+
+    // Data association counts.
+    {
+        let counts: Vec<(u64, usize)> = obj
+            .rows
+            .iter()
+            .map(|r| {
+                (
+                    r.frame.0,
+                    obj.assoc.get(&r.frame.0).map(Vec::len).unwrap_or(0),
+                )
+            })
+            .collect();
+        let max_count = counts.iter().map(|(_, c)| *c).max().unwrap_or(0).max(1);
+        let root = CanvasBackend::new(ASSOC_VIEW)
+            .ok_or(())?
+            .into_drawing_area();
+        root.fill(&WHITE).unwrap_throw();
+        let mut chart = ChartBuilder::on(&root)
+            .x_label_area_size(30)
+            .y_label_area_size(40)
+            .build_cartesian_2d(frame_lim.clone(), 0..max_count + 1)
+            .unwrap_throw();
+        chart
+            .configure_mesh()
+            .x_labels(5)
+            .y_labels(max_count + 2)
+            .x_desc("Frame")
+            .y_desc("Cameras")
+            .draw()
+            .unwrap_throw();
+        chart
+            .draw_series(
+                counts
+                    .into_iter()
+                    .map(|(frame, count)| Circle::new((frame, count), 2, BLUE.filled())),
+            )
+            .unwrap_throw();
+    }
+
+    // Reprojection errors.
+    if data.reprojection.is_some() {
+        let dlim = range(
+            obj.reprojection
+                .iter()
+                .map(|r| r.distance)
+                .chain(std::iter::once(0.0)),
+        );
+        let root = CanvasBackend::new(REPROJECTION_VIEW)
+            .ok_or(())?
+            .into_drawing_area();
+        root.fill(&WHITE).unwrap_throw();
+        let mut chart = ChartBuilder::on(&root)
+            .x_label_area_size(30)
+            .y_label_area_size(40)
+            .build_cartesian_2d(frame_lim, dlim)
+            .unwrap_throw();
+        chart
+            .configure_mesh()
+            .x_labels(5)
+            .y_labels(3)
+            .x_desc("Frame")
+            .y_desc("Pixels")
+            .draw()
+            .unwrap_throw();
+        for (i, camn) in cam_info.camn2camid.keys().enumerate() {
+            let color = Palette99::pick(i);
+            chart
+                .draw_series(
+                    obj.reprojection
+                        .iter()
+                        .filter(|r| r.camn == *camn)
+                        .map(|r| Circle::new((r.frame, r.distance), 2, color.filled())),
+                )
+                .unwrap_throw();
+        }
+    }
+
+    Ok(())
+}
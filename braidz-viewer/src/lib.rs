@@ -11,7 +11,7 @@ use yew::prelude::*;
 
 use plotters::{
     drawing::IntoDrawingArea,
-    prelude::{ChartBuilder, Circle, FontDesc, LineSeries, GREEN, RED, WHITE},
+    prelude::{ChartBuilder, Circle, FontDesc, LineSeries, PathElement, BLUE, GREEN, RED, WHITE},
     style::Color,
 };
 use plotters_canvas::CanvasBackend;
@@ -20,6 +20,8 @@ use serde::{Deserialize, Serialize};
 
 use web_sys::{self, console::log_1, Event, HtmlInputElement};
 
+mod explorer;
+
 // -----------------------------------------------------------------------------
 
 const TOPVIEW: &str = "3d-topview-canvas";
@@ -37,6 +39,7 @@ pub struct ValidBraidzFile {
     pub filename: String,
     filesize: u64,
     archive: braidz_parser::BraidzArchive<std::io::Cursor<Vec<u8>>>,
+    explorer: Option<explorer::ExplorerData>,
 }
 
 impl Default for MaybeValidBraidzFile {
@@ -71,10 +74,13 @@ struct Model {
     did_error: bool,
     html_page_title: Option<String>,
     why_busy: WhyBusy,
+    selected_obj_id: Option<u32>,
 }
 
 pub enum Msg {
     RenderAll,
+    RenderExplorer,
+    SelectObjId(u32),
     FileChanged(File),
     Loaded(String, Vec<u8>),
     FileDropped(DragEvent),
@@ -99,6 +105,7 @@ impl Component for Model {
             did_error: false,
             html_page_title: None,
             why_busy: WhyBusy::NotBusy,
+            selected_obj_id: None,
         }
     }
 
@@ -107,8 +114,21 @@ impl Component for Model {
             Msg::RenderAll => {
                 update_2d_canvas(self);
                 update_canvas(self);
+                update_explorer(self);
                 self.why_busy = WhyBusy::NotBusy;
             }
+            Msg::RenderExplorer => {
+                update_explorer(self);
+                return false;
+            }
+            Msg::SelectObjId(obj_id) => {
+                self.selected_obj_id = Some(obj_id);
+                // Render after the DOM was updated with the new selection.
+                let link = ctx.link().clone();
+                self.timeout = Some(Timeout::new(3, move || {
+                    link.send_message(Msg::RenderExplorer)
+                }));
+            }
             Msg::Loaded(filename, rbuf) => {
                 self.why_busy = WhyBusy::DrawingPlots;
                 let filesize = rbuf.len() as u64;
@@ -121,13 +141,17 @@ impl Component for Model {
 
                 self.readers.remove(&filename);
                 let file = match braidz_parser::braidz_parse(cur) {
-                    Ok(archive) => {
+                    Ok(mut archive) => {
                         let title = format!("{filename} - BRAIDZ Viewer");
 
+                        let explorer = explorer::ExplorerData::new(&mut archive);
+                        self.selected_obj_id = explorer.as_ref().and_then(|e| e.default_obj_id());
+
                         let v = ValidBraidzFile {
                             filename,
                             filesize,
                             archive,
+                            explorer,
                         };
 
                         web_sys::window()
@@ -239,6 +263,21 @@ impl Component for Model {
             empty()
         };
 
+        let explorer_part = if let Valid(ref fd) = &self.braidz_file {
+            if let Some(data) = &fd.explorer {
+                explorer::view(
+                    data,
+                    &fd.archive.cam_info,
+                    self.selected_obj_id,
+                    ctx.link().callback(Msg::SelectObjId),
+                )
+            } else {
+                empty()
+            }
+        } else {
+            empty()
+        };
+
         let (spinner_div_class, spinner_msg) = match &self.why_busy {
             WhyBusy::NotBusy => ("display-none", "".to_string()),
             WhyBusy::LoadingFile(filename) => {
@@ -299,6 +338,7 @@ impl Component for Model {
                         {braidz_file_part}
                         {did_error_part}
                         {the_3d_part}
+                        {explorer_part}
                     </div>
                     <footer id="footer">{format!("Viewer date: {} (revision {})",
                                         env!("GIT_DATE"),
@@ -371,6 +411,18 @@ fn update_2d_canvas(model: &mut Model) {
                                 .map(|(frame, y)| Circle::new((*frame, **y), 2, GREEN.filled())),
                         )
                         .unwrap_throw();
+
+                    let frame_range = frame_lim[0]..frame_lim[1] + 1;
+                    chart
+                        .draw_series(
+                            annotation_frames(&fd.archive.annotations, &frame_range).map(|f| {
+                                PathElement::new(
+                                    vec![(f as i64, 0.0), (f as i64, *seq.max_pixel)],
+                                    BLUE,
+                                )
+                            }),
+                        )
+                        .unwrap_throw();
                 }
                 &None => {
                     log_1(&("no data2d_distorted - cannot plot".into()));
@@ -493,6 +545,24 @@ fn update_canvas(model: &mut Model) {
     }
 }
 
+fn update_explorer(model: &mut Model) {
+    if let MaybeValidBraidzFile::Valid(fd) = &model.braidz_file {
+        if let (Some(data), Some(obj_id)) = (&fd.explorer, model.selected_obj_id) {
+            if explorer::render(
+                data,
+                fd.archive.data2d_distorted.as_ref(),
+                &fd.archive.cam_info,
+                &fd.archive.annotations,
+                obj_id,
+            )
+            .is_err()
+            {
+                model.did_error = true;
+            }
+        }
+    }
+}
+
 fn add_2d_dom_elements(fd: &ValidBraidzFile) -> Html {
     let divs: Vec<Html> = fd
         .archive
@@ -518,6 +588,43 @@ fn add_2d_dom_elements(fd: &ValidBraidzFile) -> Html {
     }
 }
 
+/// The frames of all annotations within `frame_lim`.
+fn annotation_frames<'a>(
+    annotations: &'a [braidz_types::AnnotationRow],
+    frame_lim: &'a std::ops::Range<u64>,
+) -> impl Iterator<Item = u64> + 'a {
+    annotations
+        .iter()
+        .filter_map(|a| a.frame.map(|f| f.0))
+        .filter(|f| frame_lim.contains(f))
+}
+
+fn annotations_table(annotations: &[braidz_types::AnnotationRow]) -> Html {
+    if annotations.is_empty() {
+        return empty();
+    }
+    let rows = annotations.iter().map(|a| {
+        let frame = a.frame.map(|f| format!("{}", f.0)).unwrap_or_default();
+        html! {
+            <tr>
+                <td>{frame}</td>
+                <td>{&a.kind}</td>
+                <td>{&a.message}</td>
+                <td>{&a.payload}</td>
+            </tr>
+        }
+    });
+    html! {
+        <div>
+            <h2>{"Annotations"}</h2>
+            <table>
+                <tr><th>{"Frame"}</th><th>{"Kind"}</th><th>{"Message"}</th><th>{"Payload"}</th></tr>
+                {for rows}
+            </table>
+        </div>
+    }
+}
+
 fn get_canv_id(camid: &str) -> String {
     format!("canv2d-{}", camid)
 }
@@ -618,6 +725,7 @@ fn detail_table_valid(fd: &ValidBraidzFile) -> Html {
                 <tr><td>{"Y limits:"}</td><td>{by}</td></tr>
                 <tr><td>{"Z limits:"}</td><td>{bz}</td></tr>
             </table>
+            {annotations_table(&fd.archive.annotations)}
         </div>
     }
 }
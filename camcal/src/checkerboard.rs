@@ -0,0 +1,552 @@
+//! Checkerboard inner corner detection.
+//!
+//! Candidate corners are saddle points of the smoothed image intensity, found
+//! as local maxima of `Ixy² - Ixx·Iyy` and checked for the four alternating
+//! light and dark sectors of an X-junction. Starting from a seed candidate and
+//! its nearest neighbors, a grid is grown by predicting the location of each
+//! next corner from the already found ones. A detection succeeds when the grid
+//! has exactly the requested size, after which corner locations are refined to
+//! subpixel accuracy with the gradient orthogonality method of
+//! `cv::cornerSubPix`.
+
+use std::collections::BTreeMap;
+
+use nalgebra::{Matrix2, Vector2};
+
+/// Smoothing scales (in pixels) tried in turn until a board is found.
+const SIGMAS: [f64; 3] = [1.0, 2.0, 3.5];
+/// Candidates weaker than this fraction of the strongest are ignored.
+const MIN_RELATIVE_RESPONSE: f32 = 0.02;
+/// Number of strongest candidates tried as grid seeds.
+const MAX_SEEDS: usize = 50;
+/// Predicted corner positions are accepted within this fraction of the local
+/// corner spacing.
+const PREDICTION_TOLERANCE: f64 = 0.35;
+
+type Point = Vector2<f64>;
+
+struct GrayImage {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl GrayImage {
+    fn from_u8(gray: &[u8], width: usize, height: usize, stride: usize) -> Self {
+        let mut data = Vec::with_capacity(width * height);
+        for row in gray.chunks(stride).take(height) {
+            data.extend(row[..width].iter().map(|&x| x as f32));
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    #[inline]
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.data[y * self.width + x]
+    }
+
+    /// Bilinear interpolation, clamped at the image border.
+    fn sample(&self, x: f64, y: f64) -> f32 {
+        let x = x.clamp(0.0, (self.width - 1) as f64);
+        let y = y.clamp(0.0, (self.height - 1) as f64);
+        let x0 = (x.floor() as usize).min(self.width - 2);
+        let y0 = (y.floor() as usize).min(self.height - 2);
+        let fx = (x - x0 as f64) as f32;
+        let fy = (y - y0 as f64) as f32;
+        let top = self.at(x0, y0) * (1.0 - fx) + self.at(x0 + 1, y0) * fx;
+        let bottom = self.at(x0, y0 + 1) * (1.0 - fx) + self.at(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    /// Separable Gaussian blur with clamped borders.
+    fn blur(&self, sigma: f64) -> Self {
+        let radius = (3.0 * sigma).ceil() as isize;
+        let kernel: Vec<f32> = (-radius..=radius)
+            .map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp() as f32)
+            .collect();
+        let sum: f32 = kernel.iter().sum();
+        let kernel: Vec<f32> = kernel.iter().map(|k| k / sum).collect();
+
+        let (w, h) = (self.width as isize, self.height as isize);
+        let mut tmp = vec![0.0; self.data.len()];
+        for y in 0..h {
+            for x in 0..w {
+                let mut acc = 0.0;
+                for (k, weight) in kernel.iter().enumerate() {
+                    let xx = (x + k as isize - radius).clamp(0, w - 1);
+                    acc += weight * self.data[(y * w + xx) as usize];
+                }
+                tmp[(y * w + x) as usize] = acc;
+            }
+        }
+        let mut data = vec![0.0; self.data.len()];
+        for y in 0..h {
+            for x in 0..w {
+                let mut acc = 0.0;
+                for (k, weight) in kernel.iter().enumerate() {
+                    let yy = (y + k as isize - radius).clamp(0, h - 1);
+                    acc += weight * tmp[(yy * w + x) as usize];
+                }
+                data[(y * w + x) as usize] = acc;
+            }
+        }
+        Self {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+}
+
+/// Find the inner corners of a checkerboard in a grayscale image.
+///
+/// `pattern_width` and `pattern_height` are the number of inner corners per
+/// row and column (e.g. a board of 8x6 squares has 7x5 inner corners). On
+/// success, `pattern_width * pattern_height` corners are returned in row major
+/// order, matching the object points used by [crate::compute_intrinsics]. The
+/// first corner is the one closest to the top left of the image.
+pub fn find_chessboard_corners(
+    gray: &[u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    pattern_width: usize,
+    pattern_height: usize,
+) -> Option<Vec<(f32, f32)>> {
+    let (width, height) = (width as usize, height as usize);
+    if pattern_width < 2 || pattern_height < 2 || width < 16 || height < 16 {
+        return None;
+    }
+    assert!(stride >= width);
+    assert!(gray.len() >= stride * (height - 1) + width);
+
+    let image = GrayImage::from_u8(gray, width, height, stride);
+    for sigma in SIGMAS {
+        let smoothed = image.blur(sigma);
+        let candidates = saddle_candidates(&smoothed, sigma);
+        if candidates.len() < pattern_width * pattern_height {
+            continue;
+        }
+        for seed in 0..candidates.len().min(MAX_SEEDS) {
+            if let Some(grid) = grow_grid(&candidates, seed, pattern_width, pattern_height) {
+                let refine_image = image.blur(1.0);
+                let mut corners = order_grid(&candidates, &grid, pattern_width, pattern_height);
+                refine_corners(&refine_image, &mut corners, pattern_width);
+                return Some(corners.iter().map(|p| (p.x as f32, p.y as f32)).collect());
+            }
+        }
+    }
+    None
+}
+
+/// Return saddle points, strongest first.
+fn saddle_candidates(im: &GrayImage, sigma: f64) -> Vec<Point> {
+    let (w, h) = (im.width, im.height);
+    let mut response = vec![0.0f32; w * h];
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let c = im.at(x, y);
+            let ixx = im.at(x + 1, y) - 2.0 * c + im.at(x - 1, y);
+            let iyy = im.at(x, y + 1) - 2.0 * c + im.at(x, y - 1);
+            let ixy = 0.25
+                * (im.at(x + 1, y + 1) - im.at(x + 1, y - 1) - im.at(x - 1, y + 1)
+                    + im.at(x - 1, y - 1));
+            response[y * w + x] = (ixy * ixy - ixx * iyy).max(0.0);
+        }
+    }
+    let max_response = response.iter().cloned().fold(0.0, f32::max);
+    if max_response <= 0.0 {
+        return vec![];
+    }
+    let threshold = MIN_RELATIVE_RESPONSE * max_response;
+
+    let nms = (2.0 * sigma).round().max(2.0) as usize;
+    let ring_radius = (2.0 * sigma).max(3.0);
+    let border = nms.max(ring_radius.ceil() as usize + 1);
+    let mut found = Vec::new();
+    for y in border..h.saturating_sub(border) {
+        'pixel: for x in border..w.saturating_sub(border) {
+            let r = response[y * w + x];
+            if r < threshold {
+                continue;
+            }
+            for yy in y - nms..=y + nms {
+                for xx in x - nms..=x + nms {
+                    let other = response[yy * w + xx];
+                    // Break ties towards the first pixel in raster order.
+                    if other > r || (other == r && (yy, xx) < (y, x)) {
+                        continue 'pixel;
+                    }
+                }
+            }
+            let p = Point::new(x as f64, y as f64);
+            if is_x_junction(im, &p, ring_radius) {
+                found.push((r, p));
+            }
+        }
+    }
+    found.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    found.into_iter().map(|(_, p)| p).collect()
+}
+
+/// An X-junction has four alternating light and dark sectors on a circle
+/// around it, whereas edges have two and L-junctions (such as the outer
+/// corners of the board) have two unequal ones.
+fn is_x_junction(im: &GrayImage, p: &Point, radius: f64) -> bool {
+    const N: usize = 16;
+    let samples: Vec<f32> = (0..N)
+        .map(|i| {
+            let a = i as f64 * std::f64::consts::TAU / N as f64;
+            im.sample(p.x + radius * a.cos(), p.y + radius * a.sin())
+        })
+        .collect();
+    let mean = samples.iter().sum::<f32>() / N as f32;
+    let above: Vec<bool> = samples.iter().map(|&s| s > mean).collect();
+    let changes = (0..N).filter(|&i| above[i] != above[(i + 1) % N]).count();
+    // Opposite sectors have the same color, up to samples near the sector
+    // boundaries.
+    let mismatches = (0..N / 2).filter(|&i| above[i] != above[i + N / 2]).count();
+    changes == 4 && mismatches <= 3
+}
+
+/// Grid coordinates of candidate indices.
+type Grid = BTreeMap<(i32, i32), usize>;
+
+fn nearest(points: &[Point], target: &Point, max_dist: f64, used: &[bool]) -> Option<usize> {
+    let mut best = None;
+    let mut best_dist = max_dist;
+    for (i, p) in points.iter().enumerate() {
+        if used[i] {
+            continue;
+        }
+        let d = (p - target).norm();
+        if d < best_dist {
+            best = Some(i);
+            best_dist = d;
+        }
+    }
+    best
+}
+
+/// Spacing vector along `axis` (0 for i, 1 for j) near grid cell `(i, j)`.
+fn local_step(points: &[Point], grid: &Grid, i: i32, j: i32, axis: usize) -> Option<Point> {
+    let unit = if axis == 0 { (1, 0) } else { (0, 1) };
+    let across = if axis == 0 { (0, 1) } else { (1, 0) };
+    for offset in [0, 1, -1, 2, -2] {
+        let ci = i + across.0 * offset;
+        let cj = j + across.1 * offset;
+        for (a, b) in [
+            ((ci, cj), (ci + unit.0, cj + unit.1)),
+            ((ci - unit.0, cj - unit.1), (ci, cj)),
+        ] {
+            if let (Some(&pa), Some(&pb)) = (grid.get(&a), grid.get(&b)) {
+                return Some(points[pb] - points[pa]);
+            }
+        }
+    }
+    None
+}
+
+/// Grow a grid of corners from candidate `seed`.
+///
+/// Returns `None` unless the grid has exactly the pattern size (in either
+/// orientation).
+fn grow_grid(
+    points: &[Point],
+    seed: usize,
+    pattern_width: usize,
+    pattern_height: usize,
+) -> Option<Grid> {
+    let p0 = points[seed];
+    let mut neighbors: Vec<usize> = (0..points.len()).filter(|&i| i != seed).collect();
+    neighbors.sort_by(|&a, &b| {
+        (points[a] - p0)
+            .norm()
+            .partial_cmp(&(points[b] - p0).norm())
+            .unwrap()
+    });
+    let first = *neighbors.first()?;
+    let d1 = points[first] - p0;
+    let second = neighbors.iter().take(8).copied().find(|&i| {
+        let d2 = points[i] - p0;
+        (d1.dot(&d2) / (d1.norm() * d2.norm())).abs() < 0.5 && d2.norm() < 2.0 * d1.norm()
+    })?;
+
+    let mut used = vec![false; points.len()];
+    let mut grid = Grid::new();
+    for (cell, idx) in [((0, 0), seed), ((1, 0), first), ((0, 1), second)] {
+        grid.insert(cell, idx);
+        used[idx] = true;
+    }
+
+    let max_extent = pattern_width.max(pattern_height) as i32;
+    let mut bounds = (0, 1, 0, 1);
+    loop {
+        let mut added = false;
+        let cells: Vec<((i32, i32), usize)> = grid.iter().map(|(&c, &i)| (c, i)).collect();
+        for ((i, j), idx) in cells {
+            for (di, dj) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let next = (i + di, j + dj);
+                if grid.contains_key(&next) {
+                    continue;
+                }
+                let p = points[idx];
+                let (predicted, spacing) = if let Some(&opposite) = grid.get(&(i - di, j - dj)) {
+                    let step = p - points[opposite];
+                    (p + step, step.norm())
+                } else {
+                    let axis = if di != 0 { 0 } else { 1 };
+                    match local_step(points, &grid, i, j, axis) {
+                        Some(step) => {
+                            let step = step * (di + dj) as f64;
+                            (p + step, step.norm())
+                        }
+                        None => continue,
+                    }
+                };
+                if let Some(found) =
+                    nearest(points, &predicted, PREDICTION_TOLERANCE * spacing, &used)
+                {
+                    grid.insert(next, found);
+                    used[found] = true;
+                    added = true;
+                    bounds = (
+                        bounds.0.min(next.0),
+                        bounds.1.max(next.0),
+                        bounds.2.min(next.1),
+                        bounds.3.max(next.1),
+                    );
+                    if bounds.1 - bounds.0 + 1 > max_extent || bounds.3 - bounds.2 + 1 > max_extent
+                    {
+                        return None;
+                    }
+                }
+            }
+        }
+        if !added {
+            break;
+        }
+    }
+
+    let n_i = (bounds.1 - bounds.0 + 1) as usize;
+    let n_j = (bounds.3 - bounds.2 + 1) as usize;
+    let size_ok = (n_i, n_j) == (pattern_width, pattern_height)
+        || (n_i, n_j) == (pattern_height, pattern_width);
+    if !size_ok || grid.len() != n_i * n_j {
+        return None;
+    }
+    // Shift so that the grid starts at (0, 0).
+    Some(
+        grid.into_iter()
+            .map(|((i, j), idx)| ((i - bounds.0, j - bounds.2), idx))
+            .collect(),
+    )
+}
+
+/// Order a complete grid into rows of `pattern_width` corners.
+///
+/// Of all orderings compatible with the pattern size, the one starting at the
+/// corner closest to the image origin is used. If two orderings start there
+/// (square patterns), the one whose first row is most horizontal wins.
+fn order_grid(
+    points: &[Point],
+    grid: &Grid,
+    pattern_width: usize,
+    pattern_height: usize,
+) -> Vec<Point> {
+    let n_i = grid.keys().map(|c| c.0).max().unwrap() as usize + 1;
+    let n_j = grid.keys().map(|c| c.1).max().unwrap() as usize + 1;
+    let at = |i: usize, j: usize| points[grid[&(i as i32, j as i32)]];
+
+    let mut best: Option<((f64, f64), Vec<Point>)> = None;
+    for transpose in [false, true] {
+        let (cols, rows) = if transpose { (n_j, n_i) } else { (n_i, n_j) };
+        if (cols, rows) != (pattern_width, pattern_height) {
+            continue;
+        }
+        for flip_col in [false, true] {
+            for flip_row in [false, true] {
+                let ordered: Vec<Point> = (0..rows)
+                    .flat_map(|r| (0..cols).map(move |c| (r, c)))
+                    .map(|(r, c)| {
+                        let r = if flip_row { rows - 1 - r } else { r };
+                        let c = if flip_col { cols - 1 - c } else { c };
+                        if transpose {
+                            at(r, c)
+                        } else {
+                            at(c, r)
+                        }
+                    })
+                    .collect();
+                let first = ordered[0];
+                let row_dir = (ordered[cols - 1] - first).normalize();
+                let score = (first.x + first.y, -row_dir.x);
+                if best.as_ref().map_or(true, |(s, _)| score < *s) {
+                    best = Some((score, ordered));
+                }
+            }
+        }
+    }
+    best.unwrap().1
+}
+
+/// Subpixel refinement of corner locations (as in `cv::cornerSubPix`).
+///
+/// At a corner, the image gradient at every nearby pixel is orthogonal to the
+/// vector from the corner to that pixel. The corner location minimizing the
+/// violation of this constraint is found iteratively.
+fn refine_corners(im: &GrayImage, corners: &mut [Point], pattern_width: usize) {
+    // Keep the window well within a single square.
+    let spacing = corners
+        .windows(2)
+        .enumerate()
+        .filter(|(i, _)| (i + 1) % pattern_width != 0)
+        .map(|(_, w)| (w[1] - w[0]).norm())
+        .fold(f64::INFINITY, f64::min);
+    let half_window = (0.3 * spacing).round().clamp(2.0, 7.0) as i32;
+    let weight_sigma = half_window as f64 * 0.5;
+
+    for corner in corners.iter_mut() {
+        let mut q = *corner;
+        for _ in 0..20 {
+            let mut a = Matrix2::<f64>::zeros();
+            let mut b = Vector2::<f64>::zeros();
+            let cx = q.x.round() as i32;
+            let cy = q.y.round() as i32;
+            for dy in -half_window..=half_window {
+                for dx in -half_window..=half_window {
+                    let x = cx + dx;
+                    let y = cy + dy;
+                    if x < 1 || y < 1 || x >= im.width as i32 - 1 || y >= im.height as i32 - 1 {
+                        continue;
+                    }
+                    let (xu, yu) = (x as usize, y as usize);
+                    let gx = 0.5 * (im.at(xu + 1, yu) - im.at(xu - 1, yu)) as f64;
+                    let gy = 0.5 * (im.at(xu, yu + 1) - im.at(xu, yu - 1)) as f64;
+                    let p = Point::new(x as f64, y as f64);
+                    let d2 = (p - q).norm_squared();
+                    let w = (-d2 / (2.0 * weight_sigma * weight_sigma)).exp();
+                    let gg = Matrix2::new(gx * gx, gx * gy, gx * gy, gy * gy) * w;
+                    a += gg;
+                    b += gg * p;
+                }
+            }
+            let next = match a.try_inverse() {
+                Some(inv) => inv * b,
+                None => break,
+            };
+            // Do not wander off to another corner.
+            if (next - *corner).norm() > half_window as f64 {
+                break;
+            }
+            let moved = (next - q).norm();
+            q = next;
+            if moved < 0.01 {
+                break;
+            }
+        }
+        *corner = q;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Matrix3;
+
+    /// Render a checkerboard with `squares` squares (and a white margin of
+    /// one square) through the homography `h` from board to image
+    /// coordinates, with 4x4 supersampling.
+    fn render(h: &Matrix3<f64>, squares: (usize, usize), width: usize, height: usize) -> Vec<u8> {
+        let h_inv = h.try_inverse().unwrap();
+        let mut im = vec![0u8; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let mut acc = 0.0;
+                for sy in 0..4 {
+                    for sx in 0..4 {
+                        let p = h_inv
+                            * nalgebra::Vector3::new(
+                                x as f64 + (sx as f64 + 0.5) / 4.0 - 0.5,
+                                y as f64 + (sy as f64 + 0.5) / 4.0 - 0.5,
+                                1.0,
+                            );
+                        let (u, v) = (p.x / p.z, p.y / p.z);
+                        let inside =
+                            u >= 0.0 && v >= 0.0 && u < squares.0 as f64 && v < squares.1 as f64;
+                        let dark = inside && (u.floor() as i64 + v.floor() as i64) % 2 == 0;
+                        acc += if dark { 30.0 } else { 220.0 };
+                    }
+                }
+                im[y * width + x] = (acc / 16.0) as u8;
+            }
+        }
+        im
+    }
+
+    #[test]
+    fn test_find_synthetic_board() {
+        let (width, height) = (320, 240);
+        let squares = (8, 6);
+        // Rotated, scaled and slightly perspective-distorted board.
+        let (s, c) = 0.2f64.sin_cos();
+        let h = Matrix3::new(
+            24.0 * c,
+            -24.0 * s,
+            80.0,
+            24.0 * s,
+            24.0 * c,
+            40.0,
+            0.01,
+            0.015,
+            1.0,
+        );
+        let im = render(&h, squares, width, height);
+
+        let corners =
+            find_chessboard_corners(&im, width as u32, height as u32, width, 7, 5).unwrap();
+        assert_eq!(corners.len(), 35);
+
+        let expected: Vec<Point> = (1..squares.1)
+            .flat_map(|v| (1..squares.0).map(move |u| (u, v)))
+            .map(|(u, v)| {
+                let p = h * nalgebra::Vector3::new(u as f64, v as f64, 1.0);
+                Point::new(p.x / p.z, p.y / p.z)
+            })
+            .collect();
+        for e in expected.iter() {
+            let closest = corners
+                .iter()
+                .map(|c| (Point::new(c.0 as f64, c.1 as f64) - e).norm())
+                .fold(f64::INFINITY, f64::min);
+            assert!(closest < 0.2, "corner {e} off by {closest}");
+        }
+
+        // Rows have 7 corners and neighbors within a row are adjacent.
+        for row in corners.chunks(7) {
+            for pair in row.windows(2) {
+                let d = ((pair[1].0 - pair[0].0).powi(2) + (pair[1].1 - pair[0].1).powi(2)).sqrt();
+                assert!(d > 15.0 && d < 35.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_board() {
+        let (width, height) = (160, 120);
+        let mut state = 1234u64;
+        let im: Vec<u8> = (0..width * height)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect();
+        assert!(find_chessboard_corners(&im, width as u32, height as u32, width, 7, 5).is_none());
+    }
+}
@@ -0,0 +1,77 @@
+//! Homographies between a planar target and the image.
+
+use nalgebra::{Matrix3, Rotation3, SMatrix, SVector, Vector2, Vector3};
+
+/// Transform moving the centroid of `points` to the origin and scaling their
+/// mean distance from it to sqrt(2).
+fn normalizing_transform(points: &[Vector2<f64>]) -> Matrix3<f64> {
+    let n = points.len() as f64;
+    let c = points.iter().fold(Vector2::zeros(), |acc, p| acc + p) / n;
+    let mean_dist = points.iter().map(|p| (p - c).norm()).sum::<f64>() / n;
+    let s = if mean_dist > 0.0 {
+        std::f64::consts::SQRT_2 / mean_dist
+    } else {
+        1.0
+    };
+    Matrix3::new(s, 0.0, -s * c.x, 0.0, s, -s * c.y, 0.0, 0.0, 1.0)
+}
+
+/// Estimate the homography mapping `from` to `to` with the normalized DLT.
+///
+/// The result is scaled so that its bottom right element is one. Returns
+/// `None` for degenerate point configurations.
+pub fn find_homography(from: &[Vector2<f64>], to: &[Vector2<f64>]) -> Option<Matrix3<f64>> {
+    let t_from = normalizing_transform(from);
+    let t_to = normalizing_transform(to);
+
+    let mut ata = SMatrix::<f64, 9, 9>::zeros();
+    for (a, b) in from.iter().zip(to.iter()) {
+        let a = t_from.transform_point(&(*a).into());
+        let b = t_to.transform_point(&(*b).into());
+        let (x, y, u, v) = (a.x, a.y, b.x, b.y);
+        let r1 =
+            SVector::<f64, 9>::from_column_slice(&[x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, -u]);
+        let r2 =
+            SVector::<f64, 9>::from_column_slice(&[0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, -v]);
+        ata += r1 * r1.transpose() + r2 * r2.transpose();
+    }
+    let eig = ata.symmetric_eigen();
+    let h = eig.eigenvectors.column(eig.eigenvalues.imin());
+    let hn = Matrix3::new(h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], h[8]);
+    let h = t_to.try_inverse()? * hn * t_from;
+    if h[(2, 2)].abs() < 1e-12 {
+        return None;
+    }
+    let h = h / h[(2, 2)];
+    h.iter().all(|x| x.is_finite()).then_some(h)
+}
+
+/// Pose of the target plane from the homography mapping it to normalized
+/// image coordinates (that is, with the camera matrix already removed).
+///
+/// Returns the rotation and translation taking target coordinates to camera
+/// coordinates, with the target in front of the camera.
+pub fn pose_from_homography(m: &Matrix3<f64>) -> Option<(Rotation3<f64>, Vector3<f64>)> {
+    let norm = 0.5 * (m.column(0).norm() + m.column(1).norm());
+    if norm == 0.0 || !norm.is_finite() {
+        return None;
+    }
+    // The board must be in front of the camera.
+    let sign = if m[(2, 2)] < 0.0 { -1.0 } else { 1.0 };
+    let scale = sign / norm;
+    let r1: Vector3<f64> = m.column(0) * scale;
+    let r2: Vector3<f64> = m.column(1) * scale;
+    let t: Vector3<f64> = m.column(2) * scale;
+    let r = Matrix3::from_columns(&[r1, r2, r1.cross(&r2)]);
+
+    // Closest rotation matrix in the Frobenius norm.
+    let svd = r.svd(true, true);
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    let mut rot = u * v_t;
+    if rot.determinant() < 0.0 {
+        let mut u = u;
+        u.column_mut(2).neg_mut();
+        rot = u * v_t;
+    }
+    Some((Rotation3::from_matrix_unchecked(rot), t))
+}
@@ -1,6 +1,33 @@
 use nalgebra::RealField;
 use serde::{Deserialize, Serialize};
 
+mod checkerboard;
+pub mod homography;
+mod zhang;
+
+pub use checkerboard::find_chessboard_corners;
+pub use zhang::{CalibrationOptions, ImageReprojectionError, IntrinsicsCalibration};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("no checkerboard data")]
+    NoData,
+    #[error("checkerboard {index} has {actual} points, expected {expected}")]
+    WrongNumberOfPoints {
+        index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("checkerboard {index} has fewer than 4 points")]
+    TooFewPoints { index: usize },
+    #[error("degenerate view of checkerboard {0}")]
+    DegenerateView(usize),
+    #[error("could not estimate initial intrinsic parameters")]
+    InitialIntrinsics,
+    #[error("nonlinear optimization failed")]
+    OptimizationFailed,
+}
+
 type Coords3D = (f64, f64, f64);
 type Coords2D = (f64, f64);
 
@@ -39,6 +66,7 @@ impl PixelSize {
 }
 
 /// Given some checkerboard corner locations, compute intrinsics using OpenCV.
+#[cfg(feature = "opencv")]
 pub fn compute_intrinsics_with_raw_opencv<R: RealField>(
     size: PixelSize,
     data: &[CheckerBoardData],
@@ -78,6 +106,7 @@ pub fn compute_intrinsics_with_raw_opencv<R: RealField>(
     )?)
 }
 
+#[cfg(feature = "opencv")]
 pub fn convert_to_cam_geom<R: RealField>(
     opencv_results: &opencv_calibrate::CalibrationResult,
 ) -> opencv_ros_camera::RosOpenCvIntrinsics<R> {
@@ -107,9 +136,22 @@ pub fn convert_to_cam_geom<R: RealField>(
 pub fn compute_intrinsics<R: RealField>(
     size: PixelSize,
     data: &[CheckerBoardData],
-) -> Result<opencv_ros_camera::RosOpenCvIntrinsics<R>, opencv_calibrate::Error> {
-    let opencv_results = compute_intrinsics_with_raw_opencv::<R>(size, data)?;
-    Ok(convert_to_cam_geom(&opencv_results))
+) -> Result<opencv_ros_camera::RosOpenCvIntrinsics<R>, Error> {
+    Ok(compute_intrinsics_with_report(size, data, &CalibrationOptions::default())?.intrinsics)
+}
+
+/// Given some checkerboard corner locations, compute intrinsics and
+/// reprojection errors
+///
+/// Like [compute_intrinsics] but with configurable options and returning the
+/// reprojection error of each image. Images which are outliers are excluded
+/// from the solution (see [CalibrationOptions::outlier_threshold]).
+pub fn compute_intrinsics_with_report<R: RealField>(
+    size: PixelSize,
+    data: &[CheckerBoardData],
+    opts: &CalibrationOptions,
+) -> Result<IntrinsicsCalibration<R>, Error> {
+    zhang::calibrate(size, data, opts)
 }
 
 fn mk_object_points(data: &[CheckerBoardData]) -> Vec<Vec<Coords3D>> {
@@ -0,0 +1,597 @@
+//! Intrinsic calibration from views of a planar target (Zhang's method).
+//!
+//! Each view is first described by a homography from the checkerboard plane to
+//! the image. These give a closed-form estimate of the focal lengths (with the
+//! principal point at the image center, as `cv::initIntrinsicParams2D` does)
+//! and an initial pose for each view. All parameters are then jointly refined
+//! with Levenberg-Marquardt to minimize reprojection error under the plumb bob
+//! distortion model.
+
+use nalgebra::{DMatrix, DVector, Matrix3, RealField, Rotation3, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::homography::find_homography;
+use crate::{mk_object_points, to_image_points, CheckerBoardData, Error, PixelSize};
+
+const FX: usize = 0;
+const FY: usize = 1;
+const CX: usize = 2;
+const CY: usize = 3;
+const K1: usize = 4;
+const K2: usize = 5;
+const P1: usize = 6;
+const P2: usize = 7;
+const K3: usize = 8;
+
+/// Outlier rejection never reduces the number of images below this.
+const MIN_INLIER_IMAGES: usize = 3;
+
+type Intrinsics = [f64; 9];
+/// Rotation vector followed by translation.
+type Pose = [f64; 6];
+
+/// Options for [crate::compute_intrinsics_with_report].
+#[derive(Debug, Clone)]
+pub struct CalibrationOptions {
+    /// Keep the sixth order radial distortion coefficient `k3` at zero.
+    ///
+    /// This matches the ROS `cameracalibrator.py` default.
+    pub fix_k3: bool,
+    /// Maximum number of Levenberg-Marquardt iterations per solve.
+    pub max_iterations: usize,
+    /// Reject images whose RMS reprojection error exceeds this multiple of
+    /// the median RMS reprojection error. `None` disables outlier rejection.
+    pub outlier_threshold: Option<f64>,
+    /// Images with an RMS reprojection error below this value, in pixels, are
+    /// never rejected as outliers.
+    pub min_outlier_error_pixels: f64,
+}
+
+impl Default for CalibrationOptions {
+    fn default() -> Self {
+        Self {
+            fix_k3: true,
+            max_iterations: 100,
+            outlier_threshold: Some(3.0),
+            min_outlier_error_pixels: 1.0,
+        }
+    }
+}
+
+/// Reprojection error of the corners of a single checkerboard image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageReprojectionError {
+    /// Mean distance between detected and reprojected corners, in pixels
+    pub mean_distance_pixels: f64,
+    /// Root mean square distance between detected and reprojected corners, in
+    /// pixels
+    pub rms_distance_pixels: f64,
+    /// Whether this image was rejected as an outlier and thus not used for the
+    /// final solution. The errors of rejected images are computed from the
+    /// final intrinsics with a pose fit to that image alone.
+    pub rejected: bool,
+}
+
+/// The result of [crate::compute_intrinsics_with_report].
+#[derive(Debug, Clone)]
+pub struct IntrinsicsCalibration<R: RealField> {
+    pub intrinsics: opencv_ros_camera::RosOpenCvIntrinsics<R>,
+    /// mean reprojection distance over all corners of non-rejected images, in
+    /// pixels
+    pub mean_reprojection_distance_pixels: f64,
+    /// root mean square reprojection distance over all corners of non-rejected
+    /// images, in pixels
+    pub rms_reprojection_distance_pixels: f64,
+    /// per-image errors, in the order of the input data
+    pub per_image: Vec<ImageReprojectionError>,
+}
+
+struct View {
+    object_points: Vec<Vector2<f64>>,
+    image_points: Vec<Vector2<f64>>,
+}
+
+pub(crate) fn calibrate<R: RealField>(
+    size: PixelSize,
+    data: &[CheckerBoardData],
+    opts: &CalibrationOptions,
+) -> Result<IntrinsicsCalibration<R>, Error> {
+    if data.is_empty() {
+        return Err(Error::NoData);
+    }
+    for (index, board) in data.iter().enumerate() {
+        let expected = board.n_rows * board.n_cols;
+        if board.points.len() != expected {
+            return Err(Error::WrongNumberOfPoints {
+                index,
+                expected,
+                actual: board.points.len(),
+            });
+        }
+        if expected < 4 {
+            return Err(Error::TooFewPoints { index });
+        }
+    }
+
+    let views: Vec<View> = mk_object_points(data)
+        .into_iter()
+        .zip(data.iter().map(to_image_points))
+        .map(|(obj, im)| View {
+            object_points: obj.iter().map(|p| Vector2::new(p.0, p.1)).collect(),
+            image_points: im.iter().map(|p| Vector2::new(p.0, p.1)).collect(),
+        })
+        .collect();
+
+    let free_intrinsics: Vec<usize> = if opts.fix_k3 {
+        (FX..K3).collect()
+    } else {
+        (FX..=K3).collect()
+    };
+
+    let mut inlier = vec![true; views.len()];
+    let (intrinsics, poses) = loop {
+        let active: Vec<&View> = views
+            .iter()
+            .zip(inlier.iter())
+            .filter_map(|(v, &is_inlier)| is_inlier.then_some(v))
+            .collect();
+        let (intrinsics, poses) = solve(&size, &active, &free_intrinsics, opts.max_iterations)?;
+
+        let threshold = match opts.outlier_threshold {
+            Some(threshold) if active.len() > MIN_INLIER_IMAGES => threshold,
+            _ => break (intrinsics, poses),
+        };
+        let rms: Vec<f64> = active
+            .iter()
+            .zip(poses.iter())
+            .map(|(view, pose)| view_errors(&intrinsics, pose, view).1)
+            .collect();
+        let max_rms = (threshold * median(&rms)).max(opts.min_outlier_error_pixels);
+
+        // Reject only the worst image per round because a single bad image
+        // also inflates the errors of the good ones.
+        let (worst, worst_rms) =
+            rms.iter()
+                .enumerate()
+                .fold(
+                    (0, f64::NEG_INFINITY),
+                    |acc, (i, &e)| {
+                        if e > acc.1 {
+                            (i, e)
+                        } else {
+                            acc
+                        }
+                    },
+                );
+        if worst_rms <= max_rms {
+            break (intrinsics, poses);
+        }
+        let worst_index = inlier
+            .iter()
+            .enumerate()
+            .filter(|(_, &is_inlier)| is_inlier)
+            .nth(worst)
+            .unwrap()
+            .0;
+        inlier[worst_index] = false;
+    };
+
+    let mut per_image = Vec::with_capacity(views.len());
+    let mut sum_dist = 0.0;
+    let mut sum_sq_dist = 0.0;
+    let mut n_points = 0;
+    let mut inlier_poses = poses.iter();
+    for (index, (view, &is_inlier)) in views.iter().zip(inlier.iter()).enumerate() {
+        let pose = if is_inlier {
+            *inlier_poses.next().unwrap()
+        } else {
+            let mut poses = [initial_pose(&intrinsics, view, index)?];
+            refine(
+                &mut intrinsics.clone(),
+                &mut poses,
+                &[view],
+                &[],
+                opts.max_iterations,
+            )?;
+            poses[0]
+        };
+        let (mean, rms) = view_errors(&intrinsics, &pose, view);
+        if is_inlier {
+            let n = view.image_points.len();
+            sum_dist += mean * n as f64;
+            sum_sq_dist += rms * rms * n as f64;
+            n_points += n;
+        }
+        per_image.push(ImageReprojectionError {
+            mean_distance_pixels: mean,
+            rms_distance_pixels: rms,
+            rejected: !is_inlier,
+        });
+    }
+
+    let i = &intrinsics;
+    let dist = nalgebra::Vector5::new(
+        nalgebra::convert(i[K1]),
+        nalgebra::convert(i[K2]),
+        nalgebra::convert(i[P1]),
+        nalgebra::convert(i[P2]),
+        nalgebra::convert(i[K3]),
+    );
+    let intrinsics = opencv_ros_camera::RosOpenCvIntrinsics::from_params_with_distortion(
+        nalgebra::convert(i[FX]),
+        R::zero(),
+        nalgebra::convert(i[FY]),
+        nalgebra::convert(i[CX]),
+        nalgebra::convert(i[CY]),
+        opencv_ros_camera::Distortion::from_opencv_vec(dist),
+    );
+
+    Ok(IntrinsicsCalibration {
+        intrinsics,
+        mean_reprojection_distance_pixels: sum_dist / n_points as f64,
+        rms_reprojection_distance_pixels: (sum_sq_dist / n_points as f64).sqrt(),
+        per_image,
+    })
+}
+
+/// Initialize from homographies and refine all parameters.
+fn solve(
+    size: &PixelSize,
+    views: &[&View],
+    free_intrinsics: &[usize],
+    max_iterations: usize,
+) -> Result<(Intrinsics, Vec<Pose>), Error> {
+    let homographies = views
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            find_homography(&v.object_points, &v.image_points).ok_or(Error::DegenerateView(i))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let cx = (size.width as f64 - 1.0) * 0.5;
+    let cy = (size.height as f64 - 1.0) * 0.5;
+    let (fx, fy) = initial_focal_lengths(&homographies, cx, cy).ok_or(Error::InitialIntrinsics)?;
+    let mut intrinsics = [fx, fy, cx, cy, 0.0, 0.0, 0.0, 0.0, 0.0];
+
+    let k_inv = camera_matrix(&intrinsics)
+        .try_inverse()
+        .ok_or(Error::InitialIntrinsics)?;
+    let mut poses = homographies
+        .iter()
+        .enumerate()
+        .map(|(i, h)| pose_from_homography(h, &k_inv).ok_or(Error::DegenerateView(i)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    refine(
+        &mut intrinsics,
+        &mut poses,
+        views,
+        free_intrinsics,
+        max_iterations,
+    )?;
+    Ok((intrinsics, poses))
+}
+
+fn camera_matrix(i: &Intrinsics) -> Matrix3<f64> {
+    Matrix3::new(i[FX], 0.0, i[CX], 0.0, i[FY], i[CY], 0.0, 0.0, 1.0)
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = sorted.len();
+    if n % 2 == 1 {
+        sorted[n / 2]
+    } else {
+        0.5 * (sorted[n / 2 - 1] + sorted[n / 2])
+    }
+}
+
+/// Compute the reprojection residuals (x0, y0, x1, y1, ...) of one view.
+fn view_residuals(i: &Intrinsics, pose: &Pose, view: &View, out: &mut Vec<f64>) {
+    out.clear();
+    let rot = Rotation3::from_scaled_axis(Vector3::new(pose[0], pose[1], pose[2]));
+    let t = Vector3::new(pose[3], pose[4], pose[5]);
+    for (obj, im) in view.object_points.iter().zip(view.image_points.iter()) {
+        let p = rot * Vector3::new(obj.x, obj.y, 0.0) + t;
+        let x = p.x / p.z;
+        let y = p.y / p.z;
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (i[K1] + r2 * (i[K2] + r2 * i[K3]));
+        let xd = x * radial + 2.0 * i[P1] * x * y + i[P2] * (r2 + 2.0 * x * x);
+        let yd = y * radial + i[P1] * (r2 + 2.0 * y * y) + 2.0 * i[P2] * x * y;
+        out.push(i[FX] * xd + i[CX] - im.x);
+        out.push(i[FY] * yd + i[CY] - im.y);
+    }
+}
+
+/// Return the mean and RMS reprojection distance of one view.
+fn view_errors(i: &Intrinsics, pose: &Pose, view: &View) -> (f64, f64) {
+    let mut r = Vec::new();
+    view_residuals(i, pose, view, &mut r);
+    let n = (r.len() / 2) as f64;
+    let (sum, sum_sq) = r.chunks_exact(2).fold((0.0, 0.0), |(s, s2), d| {
+        let sq = d[0] * d[0] + d[1] * d[1];
+        (s + sq.sqrt(), s2 + sq)
+    });
+    (sum / n, (sum_sq / n).sqrt())
+}
+
+fn total_cost(i: &Intrinsics, poses: &[Pose], views: &[&View]) -> f64 {
+    let mut r = Vec::new();
+    views
+        .iter()
+        .zip(poses.iter())
+        .map(|(view, pose)| {
+            view_residuals(i, pose, view, &mut r);
+            r.iter().map(|x| x * x).sum::<f64>()
+        })
+        .sum()
+}
+
+/// Central difference step size for a parameter with value `x`.
+fn diff_step(x: f64) -> f64 {
+    1e-6 * x.abs().max(1.0)
+}
+
+/// Levenberg-Marquardt refinement of the intrinsic parameters listed in
+/// `free_intrinsics` and all poses.
+///
+/// The Jacobian is computed by central differences. Each pose only affects
+/// the residuals of its own view, so the Jacobian is computed view by view
+/// and scattered into the normal equations.
+fn refine(
+    intrinsics: &mut Intrinsics,
+    poses: &mut [Pose],
+    views: &[&View],
+    free_intrinsics: &[usize],
+    max_iterations: usize,
+) -> Result<(), Error> {
+    let ni = free_intrinsics.len();
+    let n_local = ni + 6;
+    let n_params = ni + 6 * views.len();
+
+    let mut cost = total_cost(intrinsics, poses, views);
+    if !cost.is_finite() {
+        return Err(Error::OptimizationFailed);
+    }
+    let mut lambda = 1e-3;
+    let mut r0 = Vec::new();
+    let mut rp = Vec::new();
+    let mut rm = Vec::new();
+
+    for _ in 0..max_iterations {
+        let mut jtj = DMatrix::<f64>::zeros(n_params, n_params);
+        let mut jtr = DVector::<f64>::zeros(n_params);
+
+        for (vi, view) in views.iter().enumerate() {
+            let pose = &poses[vi];
+            view_residuals(intrinsics, pose, view, &mut r0);
+            let mut jac = DMatrix::<f64>::zeros(r0.len(), n_local);
+            for (col, &k) in free_intrinsics.iter().enumerate() {
+                let h = diff_step(intrinsics[k]);
+                let mut ip = *intrinsics;
+                ip[k] += h;
+                let mut im = *intrinsics;
+                im[k] -= h;
+                view_residuals(&ip, pose, view, &mut rp);
+                view_residuals(&im, pose, view, &mut rm);
+                for row in 0..r0.len() {
+                    jac[(row, col)] = (rp[row] - rm[row]) / (2.0 * h);
+                }
+            }
+            for k in 0..6 {
+                let h = diff_step(pose[k]);
+                let mut pp = *pose;
+                pp[k] += h;
+                let mut pm = *pose;
+                pm[k] -= h;
+                view_residuals(intrinsics, &pp, view, &mut rp);
+                view_residuals(intrinsics, &pm, view, &mut rm);
+                for row in 0..r0.len() {
+                    jac[(row, ni + k)] = (rp[row] - rm[row]) / (2.0 * h);
+                }
+            }
+
+            let local_jtj = jac.transpose() * &jac;
+            let local_jtr = jac.transpose() * DVector::from_column_slice(&r0);
+            let global = |l: usize| if l < ni { l } else { ni + 6 * vi + (l - ni) };
+            for a in 0..n_local {
+                jtr[global(a)] += local_jtr[a];
+                for b in 0..n_local {
+                    jtj[(global(a), global(b))] += local_jtj[(a, b)];
+                }
+            }
+        }
+
+        let mut improved = false;
+        while lambda < 1e12 {
+            let mut a = jtj.clone();
+            for d in 0..n_params {
+                a[(d, d)] += lambda * jtj[(d, d)].max(1e-12);
+            }
+            let delta = match a.cholesky() {
+                Some(chol) => chol.solve(&(-&jtr)),
+                None => {
+                    lambda *= 10.0;
+                    continue;
+                }
+            };
+
+            let mut new_intrinsics = *intrinsics;
+            for (col, &k) in free_intrinsics.iter().enumerate() {
+                new_intrinsics[k] += delta[col];
+            }
+            let mut new_poses = poses.to_vec();
+            for (vi, pose) in new_poses.iter_mut().enumerate() {
+                for (k, p) in pose.iter_mut().enumerate() {
+                    *p += delta[ni + 6 * vi + k];
+                }
+            }
+
+            let new_cost = total_cost(&new_intrinsics, &new_poses, views);
+            if new_cost.is_finite() && new_cost < cost {
+                let relative_decrease = (cost - new_cost) / cost;
+                *intrinsics = new_intrinsics;
+                poses.copy_from_slice(&new_poses);
+                cost = new_cost;
+                lambda = (lambda * 0.1).max(1e-12);
+                improved = relative_decrease > 1e-12;
+                break;
+            }
+            lambda *= 10.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Closed form focal lengths given a known principal point.
+///
+/// Each homography contributes the two constraints that its first two columns
+/// are the images of orthogonal unit vectors.
+fn initial_focal_lengths(homographies: &[Matrix3<f64>], cx: f64, cy: f64) -> Option<(f64, f64)> {
+    let shift = Matrix3::new(1.0, 0.0, -cx, 0.0, 1.0, -cy, 0.0, 0.0, 1.0);
+    let n = homographies.len();
+    let mut a = DMatrix::<f64>::zeros(2 * n, 2);
+    let mut b = DVector::<f64>::zeros(2 * n);
+    for (k, h) in homographies.iter().enumerate() {
+        let h = shift * h;
+        let (h1, h2) = (h.column(0), h.column(1));
+        // Scaling any of these vectors does not change the constraints, so
+        // normalize them for better conditioning.
+        let d1 = ((h1 + h2) * 0.5).normalize();
+        let d2 = ((h1 - h2) * 0.5).normalize();
+        let h1 = h1.normalize();
+        let h2 = h2.normalize();
+        a[(2 * k, 0)] = h1.x * h2.x;
+        a[(2 * k, 1)] = h1.y * h2.y;
+        b[2 * k] = -h1.z * h2.z;
+        a[(2 * k + 1, 0)] = d1.x * d2.x;
+        a[(2 * k + 1, 1)] = d1.y * d2.y;
+        b[2 * k + 1] = -d1.z * d2.z;
+    }
+    let f = a.svd(true, true).solve(&b, 1e-15).ok()?;
+    let fx = (1.0 / f[0]).abs().sqrt();
+    let fy = (1.0 / f[1]).abs().sqrt();
+    (fx.is_finite() && fy.is_finite() && fx > 0.0 && fy > 0.0).then_some((fx, fy))
+}
+
+fn pose_from_homography(h: &Matrix3<f64>, k_inv: &Matrix3<f64>) -> Option<Pose> {
+    let (rot, t) = crate::homography::pose_from_homography(&(k_inv * h))?;
+    let rvec = rot.scaled_axis();
+    Some([rvec.x, rvec.y, rvec.z, t.x, t.y, t.z])
+}
+
+/// Initial pose of a view given (fixed) intrinsics.
+fn initial_pose(intrinsics: &Intrinsics, view: &View, index: usize) -> Result<Pose, Error> {
+    // Undistorting is not needed for a starting point.
+    let h = find_homography(&view.object_points, &view.image_points)
+        .ok_or(Error::DegenerateView(index))?;
+    let k_inv = camera_matrix(intrinsics)
+        .try_inverse()
+        .ok_or(Error::OptimizationFailed)?;
+    pose_from_homography(&h, &k_inv).ok_or(Error::DegenerateView(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRUTH: Intrinsics = [800.0, 810.0, 330.0, 245.0, -0.2, 0.05, 0.001, -0.0005, 0.0];
+
+    /// Deterministic pseudo-random numbers in [-1, 1).
+    struct Lcg(u64);
+    impl Lcg {
+        fn next(&mut self) -> f64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 11) as f64 / (1u64 << 53) as f64) * 2.0 - 1.0
+        }
+    }
+
+    fn synthetic_boards(noise: f64) -> Vec<CheckerBoardData> {
+        let (n_rows, n_cols) = (6, 9);
+        let mut rng = Lcg(1234);
+        let mut boards = Vec::new();
+        for (ax, ay, az) in [
+            (0.0, 0.0, 0.1),
+            (0.4, 0.0, 0.0),
+            (-0.4, 0.1, 0.0),
+            (0.0, 0.4, -0.1),
+            (0.1, -0.4, 0.2),
+            (0.3, 0.3, 0.0),
+            (-0.3, -0.3, 0.3),
+        ] {
+            // Center the board in front of the camera.
+            let rot = Rotation3::from_euler_angles(ax, ay, az);
+            let center = rot * Vector3::new(2.5, 4.0, 0.0);
+            let t = Vector3::new(0.0, 0.0, 14.0) - center;
+            let rvec = rot.scaled_axis();
+            let pose = [rvec.x, rvec.y, rvec.z, t.x, t.y, t.z];
+
+            let object_points = mk_object_points(&[CheckerBoardData::new(n_rows, n_cols, &[])])
+                .remove(0)
+                .iter()
+                .map(|p| Vector2::new(p.0, p.1))
+                .collect();
+            let view = View {
+                object_points,
+                image_points: vec![Vector2::zeros(); n_rows * n_cols],
+            };
+            let mut r = Vec::new();
+            view_residuals(&TRUTH, &pose, &view, &mut r);
+            let points: Vec<(f64, f64)> = r
+                .chunks_exact(2)
+                .map(|p| (p[0] + noise * rng.next(), p[1] + noise * rng.next()))
+                .collect();
+            boards.push(CheckerBoardData::new(n_rows, n_cols, &points));
+        }
+        boards
+    }
+
+    #[test]
+    fn test_recover_intrinsics() {
+        let boards = synthetic_boards(0.1);
+        let cal = calibrate::<f64>(
+            PixelSize::new(640, 480),
+            &boards,
+            &CalibrationOptions::default(),
+        )
+        .unwrap();
+        let i = &cal.intrinsics;
+        assert!((i.p[(0, 0)] - TRUTH[FX]).abs() < 0.01 * TRUTH[FX]);
+        assert!((i.p[(1, 1)] - TRUTH[FY]).abs() < 0.01 * TRUTH[FY]);
+        assert!((i.p[(0, 2)] - TRUTH[CX]).abs() < 3.0);
+        assert!((i.p[(1, 2)] - TRUTH[CY]).abs() < 3.0);
+        assert!((i.distortion.radial1() - TRUTH[K1]).abs() < 0.02);
+        assert!(cal.rms_reprojection_distance_pixels < 0.15);
+        assert!(cal.per_image.iter().all(|e| !e.rejected));
+    }
+
+    #[test]
+    fn test_reject_outlier_image() {
+        let mut boards = synthetic_boards(0.05);
+        let mut rng = Lcg(99);
+        for p in boards[3].points.iter_mut() {
+            p.0 += 8.0 * rng.next();
+            p.1 += 8.0 * rng.next();
+        }
+        let cal = calibrate::<f64>(
+            PixelSize::new(640, 480),
+            &boards,
+            &CalibrationOptions::default(),
+        )
+        .unwrap();
+        for (i, e) in cal.per_image.iter().enumerate() {
+            assert_eq!(e.rejected, i == 3);
+        }
+        assert!(cal.per_image[3].rms_distance_pixels > 2.0);
+        assert!((cal.intrinsics.p[(0, 0)] - TRUTH[FX]).abs() < 0.01 * TRUTH[FX]);
+    }
+}
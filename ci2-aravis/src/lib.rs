@@ -0,0 +1,735 @@
+#![cfg_attr(feature = "backtrace", feature(error_generic_member_access))]
+
+//! Camera backend using the open source [Aravis](https://github.com/AravisProject/aravis)
+//! library for GenICam cameras (GigE Vision and USB3 Vision).
+//!
+//! For testing without hardware, set the environment variable
+//! `CI2_ARAVIS_ENABLE_FAKE` to enable Aravis' built-in fake camera. Aravis'
+//! fake GigE Vision camera (`arv-fake-gv-camera-0.8`) is found without this.
+
+use parking_lot::Mutex;
+use std::{convert::TryInto, sync::Arc};
+
+use aravis::{Aravis, BufferStatus, CameraExt, CameraExtManual, StreamExt};
+use chrono::{DateTime, Utc};
+
+use machine_vision_formats as formats;
+
+use ci2::{AcquisitionMode, AutoMode, TriggerMode, TriggerSelector};
+use formats::PixFmt;
+use timestamped_frame::HostTimeData;
+
+use basic_frame::DynamicFrame;
+
+// Number of frame buffers to allocate for the Aravis stream.
+const N_BUFFER_FRAMES: usize = 10;
+
+/// Timeout waiting for a frame, in microseconds.
+const FRAME_TIMEOUT_USEC: u64 = 10_000_000;
+
+/// Environment variable which enables Aravis' fake camera interface.
+const ENABLE_FAKE_ENV_VAR: &str = "CI2_ARAVIS_ENABLE_FAKE";
+
+/// The settings saved by [ci2::Camera::node_map_save].
+///
+/// Aravis has no equivalent of the feature persistence of the vendor SDKs, so
+/// we save the standard (SFNC) features relevant to us. The order matters:
+/// e.g. the region of interest must be set before the offsets.
+const PERSISTED_FEATURES: &[(&str, FeatureType)] = &[
+    ("PixelFormat", FeatureType::Enum),
+    ("Width", FeatureType::Int),
+    ("Height", FeatureType::Int),
+    ("OffsetX", FeatureType::Int),
+    ("OffsetY", FeatureType::Int),
+    ("BinningHorizontal", FeatureType::Int),
+    ("BinningVertical", FeatureType::Int),
+    ("ExposureAuto", FeatureType::Enum),
+    ("ExposureTime", FeatureType::Float),
+    ("GainAuto", FeatureType::Enum),
+    ("Gain", FeatureType::Float),
+    ("AcquisitionFrameRateEnable", FeatureType::Bool),
+    ("AcquisitionFrameRate", FeatureType::Float),
+    ("TriggerSelector", FeatureType::Enum),
+    ("TriggerSource", FeatureType::Enum),
+    ("TriggerActivation", FeatureType::Enum),
+    ("TriggerMode", FeatureType::Enum),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum FeatureType {
+    Bool,
+    Enum,
+    Float,
+    Int,
+}
+
+/// convert an Aravis error to ci2::Error
+fn ae2ce<E: std::fmt::Display>(orig: E) -> ci2::Error {
+    ci2::Error::from(anyhow::anyhow!("aravis error: {orig}"))
+}
+
+trait ExtendedError<T> {
+    fn map_aravis_err(self) -> ci2::Result<T>;
+}
+
+impl<T, E: std::fmt::Display> ExtendedError<T> for std::result::Result<T, E> {
+    fn map_aravis_err(self) -> ci2::Result<T> {
+        self.map_err(ae2ce)
+    }
+}
+
+/// The Aravis objects of an open camera.
+///
+/// Aravis objects are reference counted GObjects and may be used from any
+/// thread, but not concurrently. We ensure this by keeping them behind a
+/// mutex.
+struct ArvHandles {
+    camera: aravis::Camera,
+    stream: Option<aravis::Stream>,
+}
+
+unsafe impl Send for ArvHandles {}
+
+pub struct WrappedModule {
+    aravis: Aravis,
+}
+
+// The `Aravis` token only ensures that the library is initialized.
+unsafe impl Send for WrappedModule {}
+unsafe impl Sync for WrappedModule {}
+
+impl WrappedModule {
+    fn camera_infos(&self) -> ci2::Result<Vec<AravisCameraInfo>> {
+        let infos = self
+            .aravis
+            .get_device_list()
+            .into_iter()
+            .map(|info| AravisCameraInfo {
+                name: info.id.to_string(),
+                serial: info.serial_nbr.to_string(),
+                model: info.model.to_string(),
+                vendor: info.vendor.to_string(),
+            })
+            .collect();
+        Ok(infos)
+    }
+}
+
+pub fn new_module() -> ci2::Result<WrappedModule> {
+    let aravis = Aravis::initialize().map_aravis_err()?;
+    if std::env::var_os(ENABLE_FAKE_ENV_VAR).is_some() {
+        aravis::enable_interface("Fake");
+    }
+    Ok(WrappedModule { aravis })
+}
+
+/// Nothing to do at shutdown. Aravis releases its resources at process exit.
+pub struct AravisTerminateGuard {}
+
+pub fn make_singleton_guard(
+    _aravis_module: &dyn ci2::CameraModule<
+        CameraType = WrappedCamera,
+        Guard = AravisTerminateGuard,
+    >,
+) -> ci2::Result<AravisTerminateGuard> {
+    Ok(AravisTerminateGuard {})
+}
+
+impl<'a> ci2::CameraModule for &'a WrappedModule {
+    type CameraType = WrappedCamera;
+    type Guard = AravisTerminateGuard;
+
+    fn name(self: &&'a WrappedModule) -> &'static str {
+        "aravis"
+    }
+    fn camera_infos(self: &&'a WrappedModule) -> ci2::Result<Vec<Box<dyn ci2::CameraInfo>>> {
+        let vec1 = WrappedModule::camera_infos(self)?;
+        let infos = vec1
+            .into_iter()
+            .map(|aci| {
+                let ci: Box<dyn ci2::CameraInfo> = Box::new(aci); // explicitly perform type erasure
+                ci
+            })
+            .collect();
+        Ok(infos)
+    }
+    fn camera(self: &mut &'a WrappedModule, name: &str) -> ci2::Result<Self::CameraType> {
+        let info = WrappedModule::camera_infos(self)?
+            .into_iter()
+            .find(|ci| ci.name == name)
+            .ok_or_else(|| ci2::Error::from(format!("no camera named \"{name}\"")))?;
+        let camera = aravis::Camera::new(Some(name)).map_aravis_err()?;
+
+        Ok(WrappedCamera {
+            inner: Arc::new(Mutex::new(ArvHandles {
+                camera,
+                stream: None,
+            })),
+            info,
+            pixel_format: PixFmt::Mono8,
+            store_fno: 0,
+        })
+    }
+
+    fn settings_file_extension(&self) -> &str {
+        "txt"
+    }
+
+    fn frame_info_extractor(&self) -> &'static dyn ci2::ExtractFrameInfo {
+        &*FRAME_INFO
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref FRAME_INFO: AravisFrameInfo = AravisFrameInfo {};
+}
+
+struct AravisFrameInfo {}
+
+impl ci2::ExtractFrameInfo for AravisFrameInfo {
+    fn extract_frame_info(&self, frame: &DynamicFrame) -> ci2::FrameInfo {
+        use timestamped_frame::ExtraTimeData;
+        let extra = frame.extra();
+
+        let aravis_extra = extra.as_any().downcast_ref::<AravisExtra>().unwrap();
+        ci2::FrameInfo {
+            device_timestamp: std::num::NonZeroU64::new(aravis_extra.device_timestamp),
+            frame_id: std::num::NonZeroU64::new(aravis_extra.frame_id),
+            host_framenumber: extra.host_framenumber(),
+            host_timestamp: extra.host_timestamp(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AravisCameraInfo {
+    name: String,
+    serial: String,
+    model: String,
+    vendor: String,
+}
+
+impl ci2::CameraInfo for AravisCameraInfo {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn serial(&self) -> &str {
+        &self.serial
+    }
+    fn model(&self) -> &str {
+        &self.model
+    }
+    fn vendor(&self) -> &str {
+        &self.vendor
+    }
+}
+
+pub struct WrappedCamera {
+    inner: Arc<Mutex<ArvHandles>>,
+    pub info: AravisCameraInfo,
+    /// The pixel format at the start of acquisition.
+    pixel_format: PixFmt,
+    store_fno: usize,
+}
+
+fn _test_camera_is_send() {
+    // Compile-time test to ensure WrappedCamera implements Send trait.
+    fn implements<T: Send>() {}
+    implements::<WrappedCamera>();
+}
+
+impl ci2::CameraInfo for WrappedCamera {
+    fn name(&self) -> &str {
+        self.info.name()
+    }
+    fn serial(&self) -> &str {
+        self.info.serial()
+    }
+    fn model(&self) -> &str {
+        self.info.model()
+    }
+    fn vendor(&self) -> &str {
+        self.info.vendor()
+    }
+}
+
+impl WrappedCamera {
+    fn is_acquiring(&self) -> bool {
+        self.inner.lock().stream.is_some()
+    }
+
+    /// Run `f` with acquisition stopped, restarting acquisition afterwards if
+    /// it was running.
+    fn with_acquisition_stopped<F>(&mut self, f: F) -> ci2::Result<()>
+    where
+        F: FnOnce(&mut Self) -> ci2::Result<()>,
+    {
+        let restart = self.is_acquiring();
+        if restart {
+            ci2::Camera::acquisition_stop(self)?;
+        }
+        f(self)?;
+        if restart {
+            ci2::Camera::acquisition_start(self)?;
+        }
+        Ok(())
+    }
+}
+
+impl ci2::Camera for WrappedCamera {
+    // ----- start: weakly typed but easier to implement API -----
+
+    fn command_execute(&self, name: &str, _verify: bool) -> ci2::Result<()> {
+        self.inner
+            .lock()
+            .camera
+            .execute_command(name)
+            .map_aravis_err()
+    }
+
+    fn feature_bool(&self, name: &str) -> ci2::Result<bool> {
+        self.inner.lock().camera.boolean(name).map_aravis_err()
+    }
+
+    fn feature_bool_set(&self, name: &str, value: bool) -> ci2::Result<()> {
+        self.inner
+            .lock()
+            .camera
+            .set_boolean(name, value)
+            .map_aravis_err()
+    }
+
+    fn feature_enum(&self, name: &str) -> ci2::Result<String> {
+        self.inner
+            .lock()
+            .camera
+            .string(name)
+            .map_aravis_err()
+            .map(Into::into)
+    }
+
+    fn feature_enum_set(&self, name: &str, value: &str) -> ci2::Result<()> {
+        self.inner
+            .lock()
+            .camera
+            .set_string(name, value)
+            .map_aravis_err()
+    }
+
+    fn feature_float(&self, name: &str) -> ci2::Result<f64> {
+        self.inner.lock().camera.float(name).map_aravis_err()
+    }
+
+    fn feature_float_set(&self, name: &str, value: f64) -> ci2::Result<()> {
+        self.inner
+            .lock()
+            .camera
+            .set_float(name, value)
+            .map_aravis_err()
+    }
+
+    fn feature_int(&self, name: &str) -> ci2::Result<i64> {
+        self.inner.lock().camera.integer(name).map_aravis_err()
+    }
+
+    fn feature_int_set(&self, name: &str, value: i64) -> ci2::Result<()> {
+        self.inner
+            .lock()
+            .camera
+            .set_integer(name, value)
+            .map_aravis_err()
+    }
+
+    // ----- end: weakly typed but easier to implement API -----
+
+    /// Load settings saved with [ci2::Camera::node_map_save].
+    ///
+    /// Each non-empty line not starting with `#` has the form `Name=value`.
+    fn node_map_load(&self, settings: &str) -> ci2::Result<()> {
+        for line in settings.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| ci2::Error::from(format!("invalid settings line: \"{line}\"")))?;
+            let feature_type = PERSISTED_FEATURES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, t)| *t)
+                .ok_or_else(|| ci2::Error::from(format!("unknown feature \"{name}\"")))?;
+            match feature_type {
+                FeatureType::Bool => self.feature_bool_set(name, parse_value(name, value)?),
+                FeatureType::Enum => self.feature_enum_set(name, value),
+                FeatureType::Float => self.feature_float_set(name, parse_value(name, value)?),
+                FeatureType::Int => self.feature_int_set(name, parse_value(name, value)?),
+            }?;
+        }
+        Ok(())
+    }
+
+    fn node_map_save(&self) -> ci2::Result<String> {
+        let mut result = format!("# Saved by ci2-aravis from camera {}\n", self.info.name);
+        for (name, feature_type) in PERSISTED_FEATURES.iter() {
+            let value = match feature_type {
+                FeatureType::Bool => self.feature_bool(name).map(|v| v.to_string()),
+                FeatureType::Enum => self.feature_enum(name),
+                FeatureType::Float => self.feature_float(name).map(|v| v.to_string()),
+                FeatureType::Int => self.feature_int(name).map(|v| v.to_string()),
+            };
+            match value {
+                Ok(value) => {
+                    result.push_str(&format!("{name}={value}\n"));
+                }
+                Err(e) => {
+                    // Not all cameras implement all features.
+                    log::debug!("not saving feature {name}: {e}");
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn width(&self) -> ci2::Result<u32> {
+        Ok(self.feature_int("Width")?.try_into()?)
+    }
+    fn height(&self) -> ci2::Result<u32> {
+        Ok(self.feature_int("Height")?.try_into()?)
+    }
+    fn pixel_format(&self) -> ci2::Result<PixFmt> {
+        let fmt = self.feature_enum("PixelFormat")?;
+        convert_to_pixel_format(&fmt)
+    }
+    fn possible_pixel_formats(&self) -> ci2::Result<Vec<PixFmt>> {
+        let fmts = self
+            .inner
+            .lock()
+            .camera
+            .dup_available_enumerations_as_strings("PixelFormat")
+            .map_aravis_err()?;
+        Ok(fmts
+            .iter()
+            // This silently drops pixel formats that cannot be converted.
+            .filter_map(|fmt_str| convert_to_pixel_format(fmt_str).ok())
+            .collect())
+    }
+    fn set_pixel_format(&mut self, pixel_format: PixFmt) -> ci2::Result<()> {
+        let s = convert_pixel_format(pixel_format)?;
+        self.with_acquisition_stopped(|cam| cam.feature_enum_set("PixelFormat", s))
+    }
+    fn exposure_time(&self) -> ci2::Result<f64> {
+        self.feature_float("ExposureTime")
+    }
+    fn exposure_time_range(&self) -> ci2::Result<(f64, f64)> {
+        self.inner
+            .lock()
+            .camera
+            .float_bounds("ExposureTime")
+            .map_aravis_err()
+    }
+    fn set_exposure_time(&mut self, value: f64) -> ci2::Result<()> {
+        self.feature_float_set("ExposureTime", value)
+    }
+    fn exposure_auto(&self) -> ci2::Result<AutoMode> {
+        str_to_auto_mode(&self.feature_enum("ExposureAuto")?)
+    }
+    fn set_exposure_auto(&mut self, value: AutoMode) -> ci2::Result<()> {
+        self.feature_enum_set("ExposureAuto", auto_mode_to_str(value))
+    }
+    fn gain(&self) -> ci2::Result<f64> {
+        self.feature_float("Gain")
+    }
+    fn gain_range(&self) -> ci2::Result<(f64, f64)> {
+        self.inner
+            .lock()
+            .camera
+            .float_bounds("Gain")
+            .map_aravis_err()
+    }
+    fn set_gain(&mut self, value: f64) -> ci2::Result<()> {
+        self.feature_float_set("Gain", value)
+    }
+    fn gain_auto(&self) -> ci2::Result<AutoMode> {
+        str_to_auto_mode(&self.feature_enum("GainAuto")?)
+    }
+    fn set_gain_auto(&mut self, value: AutoMode) -> ci2::Result<()> {
+        self.feature_enum_set("GainAuto", auto_mode_to_str(value))
+    }
+
+    fn start_default_external_triggering(&mut self) -> ci2::Result<()> {
+        self.with_acquisition_stopped(|cam| {
+            // The trigger selector must be set before the trigger mode.
+            cam.set_trigger_selector(TriggerSelector::FrameStart)?;
+            cam.feature_enum_set("TriggerSource", "Line0")?;
+            cam.feature_enum_set("TriggerActivation", "RisingEdge")?;
+            cam.set_trigger_mode(TriggerMode::On)
+        })
+    }
+
+    fn set_software_frame_rate_limit(&mut self, fps_limit: f64) -> ci2::Result<()> {
+        self.with_acquisition_stopped(|cam| {
+            cam.set_acquisition_frame_rate_enable(true)?;
+            cam.set_acquisition_frame_rate(fps_limit)
+        })
+    }
+
+    fn trigger_mode(&self) -> ci2::Result<TriggerMode> {
+        match self.feature_enum("TriggerMode")?.as_str() {
+            "Off" => Ok(TriggerMode::Off),
+            "On" => Ok(TriggerMode::On),
+            s => Err(ci2::Error::from(format!(
+                "unexpected TriggerMode enum string: {}",
+                s
+            ))),
+        }
+    }
+    fn set_trigger_mode(&mut self, value: TriggerMode) -> ci2::Result<()> {
+        let valstr = match value {
+            TriggerMode::Off => "Off",
+            TriggerMode::On => "On",
+        };
+        self.feature_enum_set("TriggerMode", valstr)
+    }
+    fn acquisition_frame_rate_enable(&self) -> ci2::Result<bool> {
+        self.feature_bool("AcquisitionFrameRateEnable")
+    }
+    fn set_acquisition_frame_rate_enable(&mut self, value: bool) -> ci2::Result<()> {
+        self.feature_bool_set("AcquisitionFrameRateEnable", value)
+    }
+    fn acquisition_frame_rate(&self) -> ci2::Result<f64> {
+        self.feature_float("AcquisitionFrameRate")
+    }
+    fn acquisition_frame_rate_range(&self) -> ci2::Result<(f64, f64)> {
+        self.inner
+            .lock()
+            .camera
+            .float_bounds("AcquisitionFrameRate")
+            .map_aravis_err()
+    }
+    fn set_acquisition_frame_rate(&mut self, value: f64) -> ci2::Result<()> {
+        self.feature_float_set("AcquisitionFrameRate", value)
+    }
+    fn trigger_selector(&self) -> ci2::Result<TriggerSelector> {
+        match self.feature_enum("TriggerSelector")?.as_str() {
+            "AcquisitionStart" => Ok(TriggerSelector::AcquisitionStart),
+            "FrameBurstStart" => Ok(TriggerSelector::FrameBurstStart),
+            "FrameStart" => Ok(TriggerSelector::FrameStart),
+            "ExposureActive" => Ok(TriggerSelector::ExposureActive),
+            s => Err(ci2::Error::from(format!(
+                "unexpected TriggerSelector enum string: {}",
+                s
+            ))),
+        }
+    }
+    fn set_trigger_selector(&mut self, value: TriggerSelector) -> ci2::Result<()> {
+        let valstr = match value {
+            TriggerSelector::AcquisitionStart => "AcquisitionStart",
+            TriggerSelector::FrameStart => "FrameStart",
+            TriggerSelector::FrameBurstStart => "FrameBurstStart",
+            TriggerSelector::ExposureActive => "ExposureActive",
+            _ => {
+                return Err(ci2::Error::from(format!(
+                    "unknown TriggerSelector mode: {:?}",
+                    value
+                )))
+            }
+        };
+        self.feature_enum_set("TriggerSelector", valstr)
+    }
+    fn acquisition_mode(&self) -> ci2::Result<AcquisitionMode> {
+        Ok(match self.feature_enum("AcquisitionMode")?.as_str() {
+            "Continuous" => AcquisitionMode::Continuous,
+            "SingleFrame" => AcquisitionMode::SingleFrame,
+            "MultiFrame" => AcquisitionMode::MultiFrame,
+            val => {
+                return Err(ci2::Error::from(format!(
+                    "unknown AcquisitionMode: {:?}",
+                    val
+                )))
+            }
+        })
+    }
+    fn set_acquisition_mode(&mut self, value: AcquisitionMode) -> ci2::Result<()> {
+        let sval = match value {
+            AcquisitionMode::Continuous => "Continuous",
+            AcquisitionMode::SingleFrame => "SingleFrame",
+            AcquisitionMode::MultiFrame => "MultiFrame",
+        };
+        self.feature_enum_set("AcquisitionMode", sval)
+    }
+    fn acquisition_start(&mut self) -> ci2::Result<()> {
+        self.pixel_format = self.pixel_format()?;
+
+        let mut handles = self.inner.lock();
+        if handles.stream.is_some() {
+            return Ok(());
+        }
+        let stream = handles.camera.create_stream().map_aravis_err()?;
+        let payload = handles.camera.payload().map_aravis_err()?;
+        for _ in 0..N_BUFFER_FRAMES {
+            stream.push_buffer(aravis::Buffer::new_allocate(payload as usize));
+        }
+        handles.camera.start_acquisition().map_aravis_err()?;
+        handles.stream = Some(stream);
+        Ok(())
+    }
+    fn acquisition_stop(&mut self) -> ci2::Result<()> {
+        let mut handles = self.inner.lock();
+        if handles.stream.is_some() {
+            handles.camera.stop_acquisition().map_aravis_err()?;
+            // Dropping the stream releases its buffers.
+            handles.stream = None;
+        }
+        Ok(())
+    }
+    fn next_frame(&mut self) -> ci2::Result<DynamicFrame> {
+        let handles = self.inner.lock();
+        let stream = handles
+            .stream
+            .as_ref()
+            .ok_or_else(|| ci2::Error::from("acquisition not started"))?;
+
+        let buffer = stream
+            .timeout_pop_buffer(FRAME_TIMEOUT_USEC)
+            .ok_or(ci2::Error::Timeout)?;
+        let now = chrono::Utc::now(); // earliest possible timestamp
+
+        let status = buffer.status();
+        let result = if status == BufferStatus::Success {
+            let width: u32 = buffer.image_width().try_into()?;
+            let height: u32 = buffer.image_height().try_into()?;
+            let image_data = buffer.data().to_vec(); // makes copy
+            let device_timestamp = buffer.timestamp();
+            let frame_id = buffer.frame_id();
+
+            // GigE Vision frame IDs may wrap at 65535, so count frames here.
+            let host_framenumber = self.store_fno;
+            self.store_fno += 1;
+
+            let pixel_format = self.pixel_format;
+            let min_stride = width as usize * pixel_format.bits_per_pixel() as usize / 8;
+            let extra = Box::new(AravisExtra {
+                frame_id,
+                host_timestamp: now,
+                host_framenumber,
+                pixel_format,
+                device_timestamp,
+            });
+            Ok(DynamicFrame::new(
+                width,
+                height,
+                min_stride.try_into()?,
+                extra,
+                image_data,
+                pixel_format,
+            ))
+        } else {
+            Err(ci2::Error::SingleFrameError(format!(
+                "Aravis buffer status {status:?}"
+            )))
+        };
+
+        // Return the buffer to the stream for reuse.
+        stream.push_buffer(buffer);
+        result
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AravisExtra {
+    /// The frame ID (block ID for GigE Vision cameras) from the device.
+    pub frame_id: u64,
+    host_timestamp: DateTime<Utc>,
+    host_framenumber: usize,
+    pub pixel_format: formats::PixFmt,
+    /// The device timestamp, in nanoseconds.
+    pub device_timestamp: u64,
+}
+
+impl HostTimeData for AravisExtra {
+    fn host_framenumber(&self) -> usize {
+        self.host_framenumber
+    }
+    fn host_timestamp(&self) -> DateTime<Utc> {
+        self.host_timestamp
+    }
+}
+
+pub fn convert_pixel_format(pixel_format: formats::PixFmt) -> ci2::Result<&'static str> {
+    use formats::PixFmt::*;
+    let pixfmt = match pixel_format {
+        Mono8 => "Mono8",
+        RGB8 => "RGB8",
+        YUV422 => "YUV422_8_UYVY",
+        BayerGR8 => "BayerGR8",
+        BayerRG8 => "BayerRG8",
+        BayerBG8 => "BayerBG8",
+        BayerGB8 => "BayerGB8",
+        unknown => {
+            return Err(ci2::Error::from(format!("Unsuppored PixFmt {}", unknown)));
+        }
+    };
+    Ok(pixfmt)
+}
+
+pub fn convert_to_pixel_format(orig: &str) -> ci2::Result<formats::PixFmt> {
+    use formats::PixFmt::*;
+    let pixfmt = match orig {
+        "Mono8" => Mono8,
+        // Older cameras use the GenICam names from before PFNC.
+        "RGB8" | "RGB8Packed" => RGB8,
+        "YUV422_8_UYVY" | "YUV422Packed" => YUV422,
+        "BayerGR8" => BayerGR8,
+        "BayerRG8" => BayerRG8,
+        "BayerGB8" => BayerGB8,
+        "BayerBG8" => BayerBG8,
+        e => {
+            return Err(ci2::Error::from(format!(
+                "Unknown pixel format string: {:?}",
+                e
+            )));
+        }
+    };
+    Ok(pixfmt)
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> ci2::Result<T> {
+    value
+        .parse()
+        .map_err(|_| ci2::Error::from(format!("cannot parse \"{value}\" for {name}")))
+}
+
+fn str_to_auto_mode(val: &str) -> ci2::Result<AutoMode> {
+    match val {
+        "Off" => Ok(AutoMode::Off),
+        "Once" => Ok(AutoMode::Once),
+        "Continuous" => Ok(AutoMode::Continuous),
+        s => Err(ci2::Error::from(format!(
+            "unexpected AutoMode enum string: {}",
+            s
+        ))),
+    }
+}
+
+fn auto_mode_to_str(value: AutoMode) -> &'static str {
+    use AutoMode::*;
+    match value {
+        Off => "Off",
+        Once => "Once",
+        Continuous => "Continuous",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_pixel_format_roundtrip() {
+        use crate::{convert_pixel_format, convert_to_pixel_format};
+        use machine_vision_formats::PixFmt::*;
+        for pixfmt in [Mono8, RGB8, YUV422, BayerGR8, BayerRG8, BayerGB8, BayerBG8] {
+            let s = convert_pixel_format(pixfmt).unwrap();
+            assert_eq!(convert_to_pixel_format(s).unwrap(), pixfmt);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Test against the fake camera built into Aravis. This requires only the
+//! Aravis library, no camera hardware.
+
+use ci2::{Camera, CameraInfo, CameraModule};
+
+#[test]
+fn test_fake_camera() -> anyhow::Result<()> {
+    let _ = env_logger::builder().is_test(true).try_init();
+    std::env::set_var("CI2_ARAVIS_ENABLE_FAKE", "1");
+
+    let module = ci2_aravis::new_module()?;
+    let mut mymod = &module;
+    let infos = mymod.camera_infos()?;
+    let info = infos
+        .iter()
+        .find(|info| info.vendor() == "Aravis")
+        .expect("no fake camera found");
+
+    let mut cam = mymod.camera(info.name())?;
+    assert_eq!(cam.pixel_format()?, machine_vision_formats::PixFmt::Mono8);
+
+    // feature access and settings roundtrip
+    cam.feature_int_set("Width", 256)?;
+    let settings = cam.node_map_save()?;
+    assert!(settings.contains("Width=256"));
+    cam.feature_int_set("Width", 512)?;
+    cam.node_map_load(&settings)?;
+    assert_eq!(cam.width()?, 256);
+
+    let frame_info = mymod.frame_info_extractor();
+    cam.acquisition_start()?;
+    let mut prev_device_timestamp = None;
+    for i in 0..5 {
+        let frame = cam.next_frame()?;
+        assert_eq!(frame.width(), cam.width()?);
+        assert_eq!(frame.height(), cam.height()?);
+        let fi = frame_info.extract_frame_info(&frame);
+        assert_eq!(fi.host_framenumber, i);
+        let device_timestamp = fi.device_timestamp.expect("no device timestamp");
+        if let Some(prev) = prev_device_timestamp {
+            assert!(device_timestamp > prev);
+        }
+        prev_device_timestamp = Some(device_timestamp);
+    }
+    cam.acquisition_stop()?;
+
+    // The fake camera supports triggering on Line1 and by software. Here we
+    // only check that the trigger settings are accepted.
+    cam.feature_enum_set("TriggerSource", "Software")?;
+    cam.set_trigger_selector(ci2::TriggerSelector::FrameStart)?;
+    cam.set_trigger_mode(ci2::TriggerMode::On)?;
+    assert_eq!(cam.trigger_mode()?, ci2::TriggerMode::On);
+    cam.acquisition_start()?;
+    cam.command_execute("TriggerSoftware", true)?;
+    let _frame = cam.next_frame()?;
+    cam.acquisition_stop()?;
+    Ok(())
+}
@@ -107,6 +107,40 @@ pub enum Mp4Codec {
     H264LessAvc,
     /// Data is already encoded as a raw H264 stream.
     H264RawStream,
+    /// Encode data losslessly with LessAVC at 12 bits per pixel (monochrome).
+    ///
+    /// `Mono8` frames are scaled to the 12 bit range. `Mono32f` frames are
+    /// interpreted as raw sensor counts in the range 0-4095 and are stored
+    /// without loss.
+    H264LessAvcMono12,
+    /// Encode data as AV1 with the rav1e encoder (CPU only).
+    Av1Rav1e(Rav1eOptions),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Rav1eOptions {
+    /// Speed preset, from 0 (slowest, best compression) to 10 (fastest).
+    pub speed: u8,
+    /// Base quantizer, from 0 (lossless) to 255 (worst quality).
+    ///
+    /// Ignored if `bitrate` is set.
+    pub quantizer: u8,
+    /// Target bitrate in kilobits per second. If `None`, constant quantizer
+    /// mode is used.
+    pub bitrate: Option<u32>,
+    /// The maximum interval between keyframes, in frames.
+    pub max_keyframe_interval: u64,
+}
+
+impl Default for Rav1eOptions {
+    fn default() -> Self {
+        Self {
+            speed: 10,
+            quantizer: 100,
+            bitrate: None,
+            max_keyframe_interval: 240,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
@@ -337,6 +371,8 @@ impl enum_iter::EnumIter for BitrateSelection {
 pub enum CodecSelection {
     H264Nvenc,
     H264OpenH264,
+    H264LessAvcMono12,
+    Av1Rav1e,
 }
 
 impl std::fmt::Display for CodecSelection {
@@ -344,6 +380,8 @@ impl std::fmt::Display for CodecSelection {
         let x = match self {
             CodecSelection::H264Nvenc => "H264Nvenc",
             CodecSelection::H264OpenH264 => "H264OpenH264",
+            CodecSelection::H264LessAvcMono12 => "H264LessAvcMono12",
+            CodecSelection::Av1Rav1e => "Av1Rav1e",
         };
         write!(f, "{}", x)
     }
@@ -351,7 +389,12 @@ impl std::fmt::Display for CodecSelection {
 
 impl enum_iter::EnumIter for CodecSelection {
     fn variants() -> &'static [Self] {
-        &[CodecSelection::H264Nvenc, CodecSelection::H264OpenH264]
+        &[
+            CodecSelection::H264Nvenc,
+            CodecSelection::H264OpenH264,
+            CodecSelection::H264LessAvcMono12,
+            CodecSelection::Av1Rav1e,
+        ]
     }
 }
 
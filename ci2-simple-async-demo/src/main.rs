@@ -41,7 +41,24 @@ pub fn print_backend_specific_data(extra: &dyn HostTimeData) {
     );
 }
 
-#[cfg(not(any(feature = "backend_pyloncxx", feature = "backend_vimba")))]
+#[cfg(feature = "backend_aravis")]
+pub fn print_backend_specific_data(extra: &dyn HostTimeData) {
+    // Downcast to aravis specific type.
+    let aravis_extra = extra
+        .as_any()
+        .downcast_ref::<ci2_aravis::AravisExtra>()
+        .unwrap();
+    println!(
+        "    device_timestamp: {}, frame_id: {}",
+        aravis_extra.device_timestamp, aravis_extra.frame_id
+    );
+}
+
+#[cfg(not(any(
+    feature = "backend_aravis",
+    feature = "backend_pyloncxx",
+    feature = "backend_vimba"
+)))]
 pub fn print_backend_specific_data(_extra: &dyn HostTimeData) {
     // do nothing
 }
@@ -0,0 +1,678 @@
+#![cfg_attr(feature = "backtrace", feature(error_generic_member_access))]
+
+//! Camera backend which plays back recorded video files.
+//!
+//! Any file readable by [frame_source] (MP4, MKV, H264, FMF and TIFF stacks)
+//! can be opened as a camera. The camera name is the path of the file. This
+//! allows running Strand Camera and Braid on previously recorded footage as if
+//! it were live.
+//!
+//! When the module is created with [new_module], the configuration is read
+//! from environment variables:
+//!
+//! - `CI2_VIDEO_FILE_PATHS` lists the files reported by
+//!   [ci2::CameraModule::camera_infos], separated like `PATH` entries. (Other
+//!   files may be opened by name.)
+//! - `CI2_VIDEO_FILE_PACING` is `recorded` (the default) to deliver frames at
+//!   the pace of their recorded timestamps or `fast` to deliver frames as fast
+//!   as possible.
+//! - `CI2_VIDEO_FILE_LOOP`, if set, restarts playback at the end of the file.
+//!   Otherwise [ci2::Camera::next_frame] returns an error at the end of the
+//!   file.
+//!
+//! The original timestamp of each frame is returned as the device timestamp
+//! (in nanoseconds since the UNIX epoch if the file stores the absolute time
+//! of the first frame, otherwise since the first frame) by
+//! [ci2::ExtractFrameInfo].
+
+use parking_lot::Mutex;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use machine_vision_formats as formats;
+
+use ci2::{AcquisitionMode, AutoMode, TriggerMode, TriggerSelector};
+use formats::PixFmt;
+use timestamped_frame::HostTimeData;
+
+use basic_frame::DynamicFrame;
+use channellib::{Receiver, Sender};
+use frame_source::{FrameDataSource, ImageData, Timestamp};
+
+/// Number of decoded frames buffered ahead of the consumer.
+const N_CHANNEL_FRAMES: usize = 10;
+
+/// Frame rate used to pace frames from sources without timestamps.
+const DEFAULT_FRAME_RATE: f64 = 25.0;
+
+const PATHS_ENV_VAR: &str = "CI2_VIDEO_FILE_PATHS";
+const PACING_ENV_VAR: &str = "CI2_VIDEO_FILE_PACING";
+const LOOP_ENV_VAR: &str = "CI2_VIDEO_FILE_LOOP";
+
+/// How quickly frames are delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pacing {
+    /// Deliver frames at the pace given by their recorded timestamps.
+    Recorded,
+    /// Deliver frames as fast as they can be decoded.
+    AsFastAsPossible,
+}
+
+#[derive(Debug, Clone)]
+pub struct VideoFileConfig {
+    /// The files reported by [ci2::CameraModule::camera_infos].
+    pub paths: Vec<PathBuf>,
+    pub pacing: Pacing,
+    /// Restart at the beginning of the file when the end is reached.
+    pub looping: bool,
+}
+
+impl VideoFileConfig {
+    /// Read the configuration from environment variables.
+    pub fn from_env() -> ci2::Result<Self> {
+        let paths = std::env::var_os(PATHS_ENV_VAR)
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        let pacing = match std::env::var(PACING_ENV_VAR).as_deref() {
+            Err(std::env::VarError::NotPresent) | Ok("recorded") => Pacing::Recorded,
+            Ok("fast") => Pacing::AsFastAsPossible,
+            Ok(other) => {
+                return Err(ci2::Error::from(format!(
+                    "unknown value for {PACING_ENV_VAR}: \"{other}\" (expected \"recorded\" or \"fast\")"
+                )));
+            }
+            Err(e) => {
+                return Err(ci2::Error::from(format!("{PACING_ENV_VAR}: {e}")));
+            }
+        };
+        let looping = std::env::var_os(LOOP_ENV_VAR).is_some();
+        Ok(Self {
+            paths,
+            pacing,
+            looping,
+        })
+    }
+}
+
+/// convert a frame_source error to ci2::Error
+fn fe2ce<E: std::fmt::Debug>(orig: E) -> ci2::Error {
+    ci2::Error::from(anyhow::anyhow!("frame source error: {orig:?}"))
+}
+
+pub struct WrappedModule {
+    config: VideoFileConfig,
+}
+
+pub fn new_module() -> ci2::Result<WrappedModule> {
+    Ok(new_module_with_config(VideoFileConfig::from_env()?))
+}
+
+pub fn new_module_with_config(config: VideoFileConfig) -> WrappedModule {
+    WrappedModule { config }
+}
+
+/// Nothing to do at shutdown.
+pub struct VideoFileTerminateGuard {}
+
+pub fn make_singleton_guard(
+    _module: &dyn ci2::CameraModule<CameraType = WrappedCamera, Guard = VideoFileTerminateGuard>,
+) -> ci2::Result<VideoFileTerminateGuard> {
+    Ok(VideoFileTerminateGuard {})
+}
+
+impl<'a> ci2::CameraModule for &'a WrappedModule {
+    type CameraType = WrappedCamera;
+    type Guard = VideoFileTerminateGuard;
+
+    fn name(self: &&'a WrappedModule) -> &'static str {
+        "video-file"
+    }
+    fn camera_infos(self: &&'a WrappedModule) -> ci2::Result<Vec<Box<dyn ci2::CameraInfo>>> {
+        let infos = self
+            .config
+            .paths
+            .iter()
+            .map(|path| {
+                let ci: Box<dyn ci2::CameraInfo> = Box::new(VideoFileCameraInfo::new(path));
+                ci
+            })
+            .collect();
+        Ok(infos)
+    }
+    fn camera(self: &mut &'a WrappedModule, name: &str) -> ci2::Result<Self::CameraType> {
+        WrappedCamera::new(PathBuf::from(name), &self.config)
+    }
+
+    fn settings_file_extension(&self) -> &str {
+        "json"
+    }
+
+    fn frame_info_extractor(&self) -> &'static dyn ci2::ExtractFrameInfo {
+        &*FRAME_INFO
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref FRAME_INFO: VideoFileFrameInfo = VideoFileFrameInfo {};
+}
+
+struct VideoFileFrameInfo {}
+
+impl ci2::ExtractFrameInfo for VideoFileFrameInfo {
+    fn extract_frame_info(&self, frame: &DynamicFrame) -> ci2::FrameInfo {
+        use timestamped_frame::ExtraTimeData;
+        let extra = frame.extra();
+
+        let video_extra = extra.as_any().downcast_ref::<VideoFileExtra>().unwrap();
+        ci2::FrameInfo {
+            device_timestamp: std::num::NonZeroU64::new(video_extra.device_timestamp),
+            frame_id: std::num::NonZeroU64::new(video_extra.frame_id),
+            host_framenumber: extra.host_framenumber(),
+            host_timestamp: extra.host_timestamp(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct VideoFileCameraInfo {
+    name: String,
+    serial: String,
+}
+
+impl VideoFileCameraInfo {
+    fn new(path: &Path) -> Self {
+        let serial = path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Self {
+            name: path.display().to_string(),
+            serial,
+        }
+    }
+}
+
+impl ci2::CameraInfo for VideoFileCameraInfo {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn serial(&self) -> &str {
+        &self.serial
+    }
+    fn model(&self) -> &str {
+        "video file"
+    }
+    fn vendor(&self) -> &str {
+        "ci2-video-file"
+    }
+}
+
+/// Camera settings.
+///
+/// These are stored so that they can be read back, but have no effect on the
+/// frames.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Settings {
+    exposure_time: f64,
+    exposure_auto: AutoMode,
+    gain: f64,
+    gain_auto: AutoMode,
+    trigger_mode: TriggerMode,
+    trigger_selector: TriggerSelector,
+    acquisition_mode: AcquisitionMode,
+    acquisition_frame_rate_enable: bool,
+    acquisition_frame_rate: f64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            exposure_time: 10000.0,
+            exposure_auto: AutoMode::Off,
+            gain: 0.0,
+            gain_auto: AutoMode::Off,
+            trigger_mode: TriggerMode::Off,
+            trigger_selector: TriggerSelector::FrameStart,
+            acquisition_mode: AcquisitionMode::Continuous,
+            acquisition_frame_rate_enable: false,
+            acquisition_frame_rate: DEFAULT_FRAME_RATE,
+        }
+    }
+}
+
+/// A frame sent from the reader thread.
+struct SourceFrame {
+    image: DynamicFrame,
+    /// Time since the first frame of the file, if known.
+    pts: Option<Duration>,
+    /// Index of the frame in the file.
+    idx: usize,
+    /// Number of times the file was restarted.
+    loop_count: usize,
+}
+
+type FrameResult = std::result::Result<SourceFrame, ci2::Error>;
+
+/// The state of the playback clock.
+struct PlaybackClock {
+    /// Host instant at which the frame at `pts0` was delivered.
+    start: Instant,
+    pts0: Duration,
+    loop_count: usize,
+}
+
+pub struct WrappedCamera {
+    info: VideoFileCameraInfo,
+    path: PathBuf,
+    pacing: Pacing,
+    looping: bool,
+    width: u32,
+    height: u32,
+    pixel_format: PixFmt,
+    gamma: Option<f32>,
+    frame0_time: Option<DateTime<Utc>>,
+    settings: Mutex<Settings>,
+    rx: Option<Receiver<FrameResult>>,
+    clock: Option<PlaybackClock>,
+    store_fno: usize,
+}
+
+fn _test_camera_is_send() {
+    // Compile-time test to ensure WrappedCamera implements Send trait.
+    fn implements<T: Send>() {}
+    implements::<WrappedCamera>();
+}
+
+impl WrappedCamera {
+    fn new(path: PathBuf, config: &VideoFileConfig) -> ci2::Result<Self> {
+        // Open the source once to read its properties. Playback reopens it in
+        // the reader thread.
+        let mut src = frame_source::from_path(&path, true).map_err(fe2ce)?;
+        let width = src.width();
+        let height = src.height();
+        let gamma = src.gamma();
+        let frame0_time = src.frame0_time().map(|t| t.with_timezone(&Utc));
+        let first = src
+            .iter()
+            .next()
+            .ok_or_else(|| ci2::Error::from(format!("no frames in {}", path.display())))?
+            .map_err(fe2ce)?;
+        let pixel_format = to_dynamic_frame(first.into_image(), 0)?.pixel_format();
+
+        Ok(Self {
+            info: VideoFileCameraInfo::new(&path),
+            path,
+            pacing: config.pacing,
+            looping: config.looping,
+            width,
+            height,
+            pixel_format,
+            gamma,
+            frame0_time,
+            settings: Mutex::new(Settings::default()),
+            rx: None,
+            clock: None,
+            store_fno: 0,
+        })
+    }
+
+    /// Wait until the frame at `pts` is due.
+    fn wait_for(&mut self, pts: Duration, loop_count: usize) {
+        let now = Instant::now();
+        let clock = match &self.clock {
+            Some(clock) if clock.loop_count == loop_count && pts >= clock.pts0 => clock,
+            _ => {
+                // Start (or restart) the clock with this frame.
+                self.clock = Some(PlaybackClock {
+                    start: now,
+                    pts0: pts,
+                    loop_count,
+                });
+                return;
+            }
+        };
+        let due = clock.start + (pts - clock.pts0);
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+    }
+
+    /// The interval between frames for sources without timestamps.
+    fn nominal_interval(&self) -> Duration {
+        let settings = self.settings.lock();
+        let fps = if settings.acquisition_frame_rate > 0.0 {
+            settings.acquisition_frame_rate
+        } else {
+            DEFAULT_FRAME_RATE
+        };
+        Duration::from_secs_f64(1.0 / fps)
+    }
+}
+
+/// Read all frames from `path`, sending them to `tx`, until the receiver is
+/// dropped.
+fn reader_thread(path: PathBuf, looping: bool, tx: Sender<FrameResult>) {
+    let mut loop_count = 0;
+    loop {
+        let mut src = match frame_source::from_path(&path, true) {
+            Ok(src) => src,
+            Err(e) => {
+                let _ = tx.send(Err(fe2ce(e)));
+                return;
+            }
+        };
+        let has_timestamps = src.has_timestamps();
+        for frame_data in src.iter() {
+            let msg = frame_data.map_err(fe2ce).and_then(|frame_data| {
+                let idx = frame_data.idx();
+                let pts = match frame_data.timestamp() {
+                    Timestamp::Duration(pts) if has_timestamps => Some(pts),
+                    _ => None,
+                };
+                let image = to_dynamic_frame(frame_data.into_image(), idx)?;
+                Ok(SourceFrame {
+                    image,
+                    pts,
+                    idx,
+                    loop_count,
+                })
+            });
+            let is_err = msg.is_err();
+            if tx.send(msg).is_err() || is_err {
+                // Receiver dropped (acquisition stopped) or error sent.
+                return;
+            }
+        }
+        if !looping {
+            let _ = tx.send(Err(ci2::Error::from("end of video file")));
+            return;
+        }
+        loop_count += 1;
+    }
+}
+
+/// Convert the image data from a source to a frame.
+fn to_dynamic_frame(image: ImageData, idx: usize) -> ci2::Result<DynamicFrame> {
+    match image {
+        ImageData::Decoded(frame) => Ok(frame),
+        ImageData::Tiff(tiff_image) => {
+            // TIFF stacks store more than 8 bits per pixel. Use the high bits.
+            let frame0_time = chrono::DateTime::<Utc>::from(std::time::UNIX_EPOCH).into();
+            tiff_decoder::read_tiff_image(
+                &tiff_image,
+                frame0_time,
+                idx,
+                &tiff_decoder::HdrConfig::Downscale_To_8Bit,
+                None,
+                &mut tiff_decoder::ValHistogram::new(),
+            )
+            .map_err(fe2ce)
+        }
+        ImageData::EncodedH264(_) => Err(ci2::Error::from("H264 data was not decoded")),
+        ImageData::EncodedAv1(_) => Err(ci2::Error::from("AV1 data was not decoded")),
+    }
+}
+
+impl ci2::CameraInfo for WrappedCamera {
+    fn name(&self) -> &str {
+        self.info.name()
+    }
+    fn serial(&self) -> &str {
+        self.info.serial()
+    }
+    fn model(&self) -> &str {
+        self.info.model()
+    }
+    fn vendor(&self) -> &str {
+        self.info.vendor()
+    }
+}
+
+fn feature_not_present(name: &str) -> ci2::Error {
+    ci2::Error::from(format!("feature \"{name}\" not present"))
+}
+
+impl ci2::Camera for WrappedCamera {
+    // ----- start: weakly typed but easier to implement API -----
+
+    fn command_execute(&self, name: &str, _verify: bool) -> ci2::Result<()> {
+        Err(feature_not_present(name))
+    }
+    fn feature_bool(&self, name: &str) -> ci2::Result<bool> {
+        Err(feature_not_present(name))
+    }
+    fn feature_bool_set(&self, name: &str, _value: bool) -> ci2::Result<()> {
+        Err(feature_not_present(name))
+    }
+    fn feature_enum(&self, name: &str) -> ci2::Result<String> {
+        Err(feature_not_present(name))
+    }
+    fn feature_enum_set(&self, name: &str, _value: &str) -> ci2::Result<()> {
+        Err(feature_not_present(name))
+    }
+    fn feature_float(&self, name: &str) -> ci2::Result<f64> {
+        match (name, self.gamma) {
+            ("Gamma", Some(gamma)) => Ok(gamma.into()),
+            _ => Err(feature_not_present(name)),
+        }
+    }
+    fn feature_float_set(&self, name: &str, _value: f64) -> ci2::Result<()> {
+        Err(feature_not_present(name))
+    }
+    fn feature_int(&self, name: &str) -> ci2::Result<i64> {
+        Err(feature_not_present(name))
+    }
+    fn feature_int_set(&self, name: &str, _value: i64) -> ci2::Result<()> {
+        Err(feature_not_present(name))
+    }
+
+    // ----- end: weakly typed but easier to implement API -----
+
+    fn node_map_load(&self, settings: &str) -> ci2::Result<()> {
+        let loaded: Settings = serde_json::from_str(settings)
+            .map_err(|e| ci2::Error::from(format!("cannot parse settings: {e}")))?;
+        *self.settings.lock() = loaded;
+        Ok(())
+    }
+    fn node_map_save(&self) -> ci2::Result<String> {
+        serde_json::to_string_pretty(&*self.settings.lock())
+            .map_err(|e| ci2::Error::from(format!("cannot save settings: {e}")))
+    }
+
+    fn width(&self) -> ci2::Result<u32> {
+        Ok(self.width)
+    }
+    fn height(&self) -> ci2::Result<u32> {
+        Ok(self.height)
+    }
+    fn pixel_format(&self) -> ci2::Result<PixFmt> {
+        Ok(self.pixel_format)
+    }
+    fn possible_pixel_formats(&self) -> ci2::Result<Vec<PixFmt>> {
+        Ok(vec![self.pixel_format])
+    }
+    fn set_pixel_format(&mut self, pixel_format: PixFmt) -> ci2::Result<()> {
+        if pixel_format != self.pixel_format {
+            return Err(ci2::Error::from(format!(
+                "cannot change pixel format of video file from {} to {}",
+                self.pixel_format, pixel_format
+            )));
+        }
+        Ok(())
+    }
+    fn exposure_time(&self) -> ci2::Result<f64> {
+        Ok(self.settings.lock().exposure_time)
+    }
+    fn exposure_time_range(&self) -> ci2::Result<(f64, f64)> {
+        Ok((1.0, 1_000_000.0))
+    }
+    fn set_exposure_time(&mut self, value: f64) -> ci2::Result<()> {
+        self.settings.lock().exposure_time = value;
+        Ok(())
+    }
+    fn exposure_auto(&self) -> ci2::Result<AutoMode> {
+        Ok(self.settings.lock().exposure_auto)
+    }
+    fn set_exposure_auto(&mut self, value: AutoMode) -> ci2::Result<()> {
+        self.settings.lock().exposure_auto = value;
+        Ok(())
+    }
+    fn gain(&self) -> ci2::Result<f64> {
+        Ok(self.settings.lock().gain)
+    }
+    fn gain_range(&self) -> ci2::Result<(f64, f64)> {
+        Ok((0.0, 24.0))
+    }
+    fn set_gain(&mut self, value: f64) -> ci2::Result<()> {
+        self.settings.lock().gain = value;
+        Ok(())
+    }
+    fn gain_auto(&self) -> ci2::Result<AutoMode> {
+        Ok(self.settings.lock().gain_auto)
+    }
+    fn set_gain_auto(&mut self, value: AutoMode) -> ci2::Result<()> {
+        self.settings.lock().gain_auto = value;
+        Ok(())
+    }
+    fn trigger_mode(&self) -> ci2::Result<TriggerMode> {
+        Ok(self.settings.lock().trigger_mode)
+    }
+    fn set_trigger_mode(&mut self, value: TriggerMode) -> ci2::Result<()> {
+        if value == TriggerMode::On {
+            log::warn!("video file playback ignores external triggering");
+        }
+        self.settings.lock().trigger_mode = value;
+        Ok(())
+    }
+    fn acquisition_frame_rate_enable(&self) -> ci2::Result<bool> {
+        Ok(self.settings.lock().acquisition_frame_rate_enable)
+    }
+    fn set_acquisition_frame_rate_enable(&mut self, value: bool) -> ci2::Result<()> {
+        self.settings.lock().acquisition_frame_rate_enable = value;
+        Ok(())
+    }
+    fn acquisition_frame_rate(&self) -> ci2::Result<f64> {
+        Ok(self.settings.lock().acquisition_frame_rate)
+    }
+    fn acquisition_frame_rate_range(&self) -> ci2::Result<(f64, f64)> {
+        Ok((0.1, 10000.0))
+    }
+    fn set_acquisition_frame_rate(&mut self, value: f64) -> ci2::Result<()> {
+        self.settings.lock().acquisition_frame_rate = value;
+        Ok(())
+    }
+    fn trigger_selector(&self) -> ci2::Result<TriggerSelector> {
+        Ok(self.settings.lock().trigger_selector)
+    }
+    fn set_trigger_selector(&mut self, value: TriggerSelector) -> ci2::Result<()> {
+        self.settings.lock().trigger_selector = value;
+        Ok(())
+    }
+    fn acquisition_mode(&self) -> ci2::Result<AcquisitionMode> {
+        Ok(self.settings.lock().acquisition_mode)
+    }
+    fn set_acquisition_mode(&mut self, value: AcquisitionMode) -> ci2::Result<()> {
+        self.settings.lock().acquisition_mode = value;
+        Ok(())
+    }
+    fn acquisition_start(&mut self) -> ci2::Result<()> {
+        if self.rx.is_some() {
+            return Ok(());
+        }
+        let (tx, rx) = channellib::bounded(N_CHANNEL_FRAMES);
+        let path = self.path.clone();
+        let looping = self.looping;
+        std::thread::Builder::new()
+            .name(format!("video-file-reader {}", self.path.display()))
+            .spawn(move || reader_thread(path, looping, tx))?;
+        self.rx = Some(rx);
+        self.clock = None;
+        Ok(())
+    }
+    fn acquisition_stop(&mut self) -> ci2::Result<()> {
+        // Dropping the receiver stops the reader thread.
+        self.rx = None;
+        Ok(())
+    }
+    fn next_frame(&mut self) -> ci2::Result<DynamicFrame> {
+        let rx = self
+            .rx
+            .as_ref()
+            .ok_or_else(|| ci2::Error::from("acquisition not started"))?;
+        let msg = rx.recv().map_err(|e| {
+            ci2::Error::BackendError(anyhow::anyhow!("Error receiving frame : {}", e))
+        })?;
+        let SourceFrame {
+            image,
+            pts,
+            idx,
+            loop_count,
+        } = msg?;
+
+        if self.pacing == Pacing::Recorded {
+            let pts = pts.unwrap_or_else(|| self.nominal_interval() * idx as u32);
+            self.wait_for(pts, loop_count);
+        }
+        let now = Utc::now();
+
+        // The original timestamp of the frame.
+        let pts_nanos = pts.unwrap_or_default().as_nanos() as u64;
+        let device_timestamp = match self.frame0_time {
+            Some(t0) => {
+                let t0_nanos: u64 = t0.timestamp_nanos_opt().unwrap_or_default().try_into()?;
+                t0_nanos + pts_nanos
+            }
+            None => pts_nanos,
+        };
+
+        let host_framenumber = self.store_fno;
+        self.store_fno += 1;
+
+        let width = image.width();
+        let height = image.height();
+        let stride = formats::Stride::stride(&image).try_into()?;
+        let pixel_format = image.pixel_format();
+        let (image_data, _) = image.into_data_extra();
+        let extra = Box::new(VideoFileExtra {
+            host_timestamp: now,
+            host_framenumber,
+            frame_id: (idx + 1) as u64,
+            device_timestamp,
+        });
+        Ok(DynamicFrame::new(
+            width,
+            height,
+            stride,
+            extra,
+            image_data,
+            pixel_format,
+        ))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VideoFileExtra {
+    host_timestamp: DateTime<Utc>,
+    host_framenumber: usize,
+    /// Index of the frame in the file, plus one.
+    pub frame_id: u64,
+    /// The original timestamp of the frame, in nanoseconds.
+    ///
+    /// This is relative to the UNIX epoch if the file stores the absolute time
+    /// of the first frame, otherwise relative to the first frame.
+    pub device_timestamp: u64,
+}
+
+impl HostTimeData for VideoFileExtra {
+    fn host_framenumber(&self) -> usize {
+        self.host_framenumber
+    }
+    fn host_timestamp(&self) -> DateTime<Utc> {
+        self.host_timestamp
+    }
+}
@@ -0,0 +1,76 @@
+use basic_frame::{BasicExtra, BasicFrame};
+use chrono::{DateTime, TimeZone, Utc};
+use machine_vision_formats::pixel_format::Mono8;
+
+use ci2::{Camera, CameraModule};
+use ci2_video_file::{Pacing, VideoFileConfig};
+
+const W: u32 = 8;
+const H: u32 = 4;
+const N_FRAMES: usize = 5;
+const DT_MSEC: i64 = 10;
+
+fn write_fmf(path: &std::path::Path) -> anyhow::Result<DateTime<Utc>> {
+    let t0 = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+    let f = std::fs::File::create(path)?;
+    let mut writer = fmf::FMFWriter::new(f)?;
+    for i in 0..N_FRAMES {
+        let host_timestamp = t0 + chrono::Duration::milliseconds(i as i64 * DT_MSEC);
+        let frame: BasicFrame<Mono8> = BasicFrame {
+            width: W,
+            height: H,
+            stride: W,
+            image_data: vec![i as u8; (W * H) as usize],
+            extra: Box::new(BasicExtra {
+                host_timestamp,
+                host_framenumber: i,
+            }),
+            pixel_format: std::marker::PhantomData,
+        };
+        writer.write(&frame, host_timestamp)?;
+    }
+    writer.close()?;
+    Ok(t0)
+}
+
+#[test]
+fn test_playback() -> anyhow::Result<()> {
+    let tmpdir = tempfile::tempdir()?;
+    let path = tmpdir.path().join("movie.fmf");
+    let t0 = write_fmf(&path)?;
+
+    let module = ci2_video_file::new_module_with_config(VideoFileConfig {
+        paths: vec![path.clone()],
+        pacing: Pacing::AsFastAsPossible,
+        looping: false,
+    });
+    let mut module = &module;
+    let infos = module.camera_infos()?;
+    assert_eq!(infos.len(), 1);
+
+    let mut cam = module.camera(infos[0].name())?;
+    assert_eq!(cam.width()?, W);
+    assert_eq!(cam.height()?, H);
+
+    let t0_nanos: u64 = t0.timestamp_nanos_opt().unwrap().try_into()?;
+    let frame_info_extractor = module.frame_info_extractor();
+    cam.acquisition_start()?;
+    for i in 0..N_FRAMES {
+        let frame = cam.next_frame()?;
+        let info = frame_info_extractor.extract_frame_info(&frame);
+        assert_eq!(info.frame_id.unwrap().get(), i as u64 + 1);
+        assert_eq!(info.host_framenumber, i);
+        let expected = t0_nanos + i as u64 * DT_MSEC as u64 * 1_000_000;
+        let actual = info.device_timestamp.unwrap().get();
+        // FMF stores timestamps as floating point seconds.
+        assert!(actual.abs_diff(expected) < 1_000, "frame {i}");
+        assert!(frame
+            .image_data_without_format()
+            .iter()
+            .all(|x| *x == i as u8));
+    }
+    // Without looping, reading past the end is an error.
+    assert!(cam.next_frame().is_err());
+    cam.acquisition_stop()?;
+    Ok(())
+}
@@ -0,0 +1,412 @@
+//! Robust fitting of clock models.
+//!
+//! A clock model relates the time of a device clock (the pulse number of the
+//! triggerbox or the timestamp counter of a camera) to the host clock as
+//! `host = gain * device + offset`. The host time of a measurement is
+//! occasionally late, for example when a USB reply is delayed, and a single
+//! such measurement skews an ordinary least squares fit.
+//!
+//! [fit] therefore starts from the Theil-Sen estimator, refines it by
+//! iteratively reweighted least squares with the Huber loss and rejects
+//! measurements whose residual exceeds
+//! [FitConfig::outlier_threshold] robust standard deviations.
+//! [ClockModelEstimator] fits online over a sliding window of recent
+//! measurements, which tracks drift of the device clock.
+use std::collections::VecDeque;
+
+/// Ratio of standard deviation to median absolute deviation for normally
+/// distributed residuals.
+const MAD_TO_STD: f64 = 1.4826;
+
+const MAX_ITERATIONS: usize = 50;
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("need at least two measurements, got {0}")]
+    TooFewMeasurements(usize),
+    #[error("measurements do not span a range of device times")]
+    Degenerate,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FitConfig {
+    /// Residuals up to this many robust standard deviations have full weight
+    /// and larger residuals are down-weighted.
+    pub huber_k: f64,
+    /// Measurements with residuals beyond this many robust standard
+    /// deviations are rejected.
+    pub outlier_threshold: f64,
+    /// Lower bound of the robust standard deviation, in units of host time.
+    /// This keeps measurements from being rejected when the other residuals
+    /// are only due to rounding. The default suits host times in seconds.
+    pub min_residual_std: f64,
+}
+
+impl Default for FitConfig {
+    fn default() -> Self {
+        Self {
+            huber_k: 1.345,
+            outlier_threshold: 4.0,
+            min_residual_std: 1e-6,
+        }
+    }
+}
+
+/// A clock model with statistics of its fit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fit {
+    pub gain: f64,
+    pub offset: f64,
+    /// Robust standard deviation of the residuals, from their median
+    /// absolute deviation.
+    pub residual_std: f64,
+    /// Sum of squared residuals of the inliers.
+    pub sum_sq_residuals: f64,
+    /// Largest absolute residual of the inliers.
+    pub max_abs_residual: f64,
+    pub n_measurements: usize,
+    pub n_inliers: usize,
+    /// Standard error of host times predicted for the device time of the
+    /// last measurement. This is the confidence of the model for timestamps
+    /// of current frames.
+    pub prediction_std: f64,
+}
+
+impl Fit {
+    /// The host time for `device_time`.
+    pub fn predict(&self, device_time: f64) -> f64 {
+        self.gain * device_time + self.offset
+    }
+
+    /// Whether a measurement with this residual was rejected by the fit.
+    pub fn is_outlier(&self, residual: f64, cfg: &FitConfig) -> bool {
+        residual.abs() > cfg.outlier_threshold * self.residual_std
+    }
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let n = values.len();
+    if n & 1 == 1 {
+        values[n / 2]
+    } else {
+        (values[n / 2 - 1] + values[n / 2]) / 2.0
+    }
+}
+
+/// Weighted least squares line through centered points, `None` if the
+/// weighted points have no spread in `x`.
+fn weighted_line(points: &[(f64, f64)], weights: &[f64]) -> Option<(f64, f64)> {
+    let sum_w: f64 = weights.iter().sum();
+    if sum_w <= 0.0 {
+        return None;
+    }
+    let (mut mean_x, mut mean_y) = (0.0, 0.0);
+    for ((x, y), w) in points.iter().zip(weights) {
+        mean_x += w * x;
+        mean_y += w * y;
+    }
+    mean_x /= sum_w;
+    mean_y /= sum_w;
+    let (mut sxx, mut sxy) = (0.0, 0.0);
+    for ((x, y), w) in points.iter().zip(weights) {
+        sxx += w * (x - mean_x) * (x - mean_x);
+        sxy += w * (x - mean_x) * (y - mean_y);
+    }
+    if sxx <= 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some((slope, mean_y - slope * mean_x))
+}
+
+/// Fit a clock model to `(device_time, host_time)` measurements.
+///
+/// The last measurement is taken as the current time for
+/// [Fit::prediction_std].
+pub fn fit(measurements: &[(f64, f64)], cfg: &FitConfig) -> Result<Fit> {
+    let n = measurements.len();
+    if n < 2 {
+        return Err(Error::TooFewMeasurements(n));
+    }
+
+    // Center the measurements to keep precision with large times.
+    let x0 = measurements.iter().map(|m| m.0).sum::<f64>() / n as f64;
+    let y0 = measurements.iter().map(|m| m.1).sum::<f64>() / n as f64;
+    let points: Vec<(f64, f64)> = measurements.iter().map(|(x, y)| (x - x0, y - y0)).collect();
+
+    // Theil-Sen estimate as robust starting point.
+    let mut slopes = Vec::with_capacity(n * (n - 1) / 2);
+    for (i, (xi, yi)) in points.iter().enumerate() {
+        for (xj, yj) in points[i + 1..].iter() {
+            if xj != xi {
+                slopes.push((yj - yi) / (xj - xi));
+            }
+        }
+    }
+    if slopes.is_empty() {
+        return Err(Error::Degenerate);
+    }
+    let mut slope = median(&mut slopes);
+    let mut intercept = median(
+        &mut points
+            .iter()
+            .map(|(x, y)| y - slope * x)
+            .collect::<Vec<_>>(),
+    );
+
+    let residuals = |slope: f64, intercept: f64| -> Vec<f64> {
+        points
+            .iter()
+            .map(|(x, y)| y - slope * x - intercept)
+            .collect()
+    };
+    let robust_std = |residuals: &[f64]| -> f64 {
+        let mut abs: Vec<f64> = residuals.iter().map(|r| r.abs()).collect();
+        (MAD_TO_STD * median(&mut abs)).max(cfg.min_residual_std)
+    };
+
+    // Iteratively reweighted least squares with the Huber loss.
+    let mut weights = vec![1.0; n];
+    for _ in 0..MAX_ITERATIONS {
+        let r = residuals(slope, intercept);
+        let std = robust_std(&r);
+        for (w, r) in weights.iter_mut().zip(r.iter()) {
+            let r = r.abs();
+            *w = if r > cfg.outlier_threshold * std {
+                0.0
+            } else if r > cfg.huber_k * std {
+                cfg.huber_k * std / r
+            } else {
+                1.0
+            };
+        }
+        let (next_slope, next_intercept) =
+            weighted_line(&points, &weights).ok_or(Error::Degenerate)?;
+        let converged = (next_slope - slope).abs() <= 1e-12 * slope.abs().max(1e-300)
+            && (next_intercept - intercept).abs() <= 1e-3 * std;
+        slope = next_slope;
+        intercept = next_intercept;
+        if converged {
+            break;
+        }
+    }
+
+    let r = residuals(slope, intercept);
+    let residual_std = robust_std(&r);
+    let inliers: Vec<usize> = (0..n)
+        .filter(|&i| r[i].abs() <= cfg.outlier_threshold * residual_std)
+        .collect();
+    let sum_sq_residuals = inliers.iter().map(|&i| r[i] * r[i]).sum();
+    let max_abs_residual = inliers.iter().map(|&i| r[i].abs()).fold(0.0, f64::max);
+
+    // Standard error of the line at the last measurement.
+    let n_inliers = inliers.len();
+    let mean_x = inliers.iter().map(|&i| points[i].0).sum::<f64>() / n_inliers as f64;
+    let sxx: f64 = inliers
+        .iter()
+        .map(|&i| (points[i].0 - mean_x).powi(2))
+        .sum();
+    let dx = points[n - 1].0 - mean_x;
+    let leverage = if sxx > 0.0 { dx * dx / sxx } else { 0.0 };
+    let prediction_std = residual_std * (1.0 / n_inliers as f64 + leverage).sqrt();
+
+    Ok(Fit {
+        gain: slope,
+        offset: y0 + intercept - slope * x0,
+        residual_std,
+        sum_sq_residuals,
+        max_abs_residual,
+        n_measurements: n,
+        n_inliers,
+        prediction_std,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EstimatorConfig {
+    pub fit: FitConfig,
+    /// Number of recent measurements which are fit.
+    pub window_len: usize,
+    /// Number of measurements before the first fit.
+    pub min_measurements: usize,
+}
+
+impl Default for EstimatorConfig {
+    fn default() -> Self {
+        Self {
+            fit: FitConfig::default(),
+            window_len: 100,
+            min_measurements: 5,
+        }
+    }
+}
+
+/// The result of adding a measurement to [ClockModelEstimator].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Update {
+    /// Residual of the new measurement with the updated fit.
+    pub residual: f64,
+    /// Whether the new measurement was rejected.
+    pub is_outlier: bool,
+    pub fit: Fit,
+}
+
+/// Online clock model fit over a sliding window of measurements.
+#[derive(Debug, Clone)]
+pub struct ClockModelEstimator {
+    cfg: EstimatorConfig,
+    window: VecDeque<(f64, f64)>,
+    last_device_time: Option<f64>,
+    fit: Option<Fit>,
+}
+
+impl ClockModelEstimator {
+    pub fn new(cfg: EstimatorConfig) -> Self {
+        Self {
+            window: VecDeque::with_capacity(cfg.window_len),
+            cfg,
+            last_device_time: None,
+            fit: None,
+        }
+    }
+
+    pub fn config(&self) -> &EstimatorConfig {
+        &self.cfg
+    }
+
+    /// The current fit, `None` before enough measurements.
+    pub fn fit(&self) -> Option<&Fit> {
+        self.fit.as_ref()
+    }
+
+    /// Forget all measurements.
+    pub fn reset(&mut self) {
+        self.window.clear();
+        self.last_device_time = None;
+        self.fit = None;
+    }
+
+    /// Add a measurement and update the fit.
+    ///
+    /// A device time which does not increase means that the device clock was
+    /// stopped or reset. The earlier measurements are then forgotten and a new
+    /// fit is started from this measurement.
+    pub fn push(&mut self, device_time: f64, host_time: f64) -> Option<Update> {
+        let last_device_time = self.last_device_time.replace(device_time);
+        if last_device_time.is_some_and(|last| device_time <= last) {
+            self.window.clear();
+            self.fit = None;
+        }
+        if self.window.len() == self.cfg.window_len {
+            self.window.pop_front();
+        }
+        self.window.push_back((device_time, host_time));
+        if self.window.len() < self.cfg.min_measurements.max(2) {
+            return None;
+        }
+        let fit = fit(self.window.make_contiguous(), &self.cfg.fit).ok()?;
+        self.fit = Some(fit);
+        let residual = host_time - fit.predict(device_time);
+        Some(Update {
+            residual,
+            is_outlier: fit.is_outlier(residual, &self.cfg.fit),
+            fit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_line() {
+        let epsilon = 1e-12;
+        let cfg = FitConfig::default();
+
+        let data = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)];
+        let fit = fit(&data, &cfg).unwrap();
+        assert!((fit.gain - 1.0).abs() < epsilon);
+        assert!((fit.offset - 0.0).abs() < epsilon);
+
+        let data = vec![(0.0, 12.0), (1.0, 22.0), (2.0, 32.0), (3.0, 42.0)];
+        let fit = super::fit(&data, &cfg).unwrap();
+        assert!((fit.gain - 10.0).abs() < epsilon);
+        assert!((fit.offset - 12.0).abs() < epsilon);
+        assert_eq!(fit.n_inliers, 4);
+    }
+
+    #[test]
+    fn errors() {
+        let cfg = FitConfig::default();
+        assert_eq!(fit(&[(1.0, 1.0)], &cfg), Err(Error::TooFewMeasurements(1)));
+        assert_eq!(fit(&[(1.0, 1.0), (1.0, 2.0)], &cfg), Err(Error::Degenerate));
+    }
+
+    /// Triggerbox-like measurements at 100 Hz with jitter of +-0.5 ms.
+    fn measurements(n: usize) -> Vec<(f64, f64)> {
+        let gain = 0.01 * (1.0 - 50e-6);
+        let offset = 1.7e9;
+        (0..n)
+            .map(|i| {
+                let frame = 150.0 * i as f64 + 0.3;
+                let jitter = 0.5e-3 * ((i * 7919 % 13) as f64 / 6.0 - 1.0);
+                (frame, gain * frame + offset + jitter)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rejects_late_replies() {
+        let cfg = FitConfig::default();
+        let mut data = measurements(30);
+        let clean = fit(&data, &cfg).unwrap();
+        // Two replies delayed by 50 ms.
+        data[10].1 += 0.05;
+        data[20].1 += 0.05;
+        let robust = fit(&data, &cfg).unwrap();
+        assert_eq!(robust.n_inliers, 28);
+        assert!(robust.is_outlier(data[10].1 - robust.predict(data[10].0), &cfg));
+        let frame = data[29].0;
+        assert!((robust.predict(frame) - clean.predict(frame)).abs() < 0.2e-3);
+        assert!(robust.residual_std < 1e-3);
+        assert!(robust.prediction_std < robust.residual_std);
+    }
+
+    #[test]
+    fn estimator_tracks_drift_and_resets() {
+        let mut estimator = ClockModelEstimator::new(EstimatorConfig {
+            window_len: 20,
+            ..Default::default()
+        });
+        let data = measurements(40);
+        for (i, (frame, t)) in data.iter().enumerate() {
+            // The drift changes halfway.
+            let t = if i < 20 {
+                *t
+            } else {
+                t + 100e-6 * (frame - data[20].0) * 0.01
+            };
+            let update = estimator.push(*frame, t);
+            assert_eq!(update.is_some(), i >= 4);
+        }
+        let fit = estimator.fit().unwrap();
+        assert_eq!(fit.n_measurements, 20);
+        let expected_gain = 0.01 * (1.0 - 50e-6 + 100e-6);
+        assert!((fit.gain - expected_gain).abs() / expected_gain < 10e-6);
+
+        // A late reply is flagged.
+        let (frame, t) = (data[39].0 + 150.0, data[39].1 + 1.5 + 0.1);
+        let update = estimator.push(frame, t).unwrap();
+        assert!(update.is_outlier);
+        assert!(update.residual > 0.09);
+
+        // The device was reset.
+        assert!(estimator.push(0.0, t + 1.0).is_none());
+        assert!(estimator.fit().is_none());
+        assert_eq!(estimator.window.len(), 1);
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright 2020-2024 Andrew D. Straw.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Types for version 1 of the Braid REST API.
+//!
+//! This API is intended for scripted control of Braid. In contrast to
+//! [crate::BraidHttpApiCallback], which is an internal interface between Braid,
+//! Strand Camera and the web UI, this API is versioned and changes to it will
+//! be backwards compatible within a version. Requests are authorized with a
+//! bearer token in the `Authorization` header.
+//!
+//! The API is described in OpenAPI format at [OPENAPI_PATH].
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ConnectedCameraSyncState, RecentStats};
+
+/// Body of `POST annotations`.
+pub use crate::NewAnnotation;
+/// Response to `GET tracking-params`.
+pub use crate::TrackingParams;
+/// Body of `PUT tracking-params`.
+pub use crate::TrackingParamsUpdate;
+
+/// The version of the API implemented by these types.
+pub const API_VERSION: u32 = 1;
+
+/// Prefix of all URL paths of this API version.
+pub const API_V1_PREFIX: &str = "api/v1";
+
+// URL paths relative to [API_V1_PREFIX].
+pub const STATUS_PATH: &str = "status";
+pub const CAMERAS_PATH: &str = "cameras";
+pub const RECORDING_BRAIDZ_PATH: &str = "recording/braidz";
+pub const RECORDING_MP4_PATH: &str = "recording/mp4";
+pub const EXPERIMENT_UUID_PATH: &str = "experiment-uuid";
+pub const POST_TRIGGER_BUFFER_PATH: &str = "post-trigger/buffer-size";
+pub const POST_TRIGGER_MP4_PATH: &str = "post-trigger/mp4";
+pub const ANNOTATIONS_PATH: &str = "annotations";
+pub const TRACKING_PARAMS_PATH: &str = "tracking-params";
+pub const OPENAPI_PATH: &str = "openapi.yaml";
+
+/// Response to `GET status`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StatusResponse {
+    /// Always equal to [API_VERSION].
+    pub api_version: u32,
+    /// The name of the `.braid` directory currently being recorded, if any.
+    pub braidz_recording: Option<String>,
+    /// Whether MP4 files are currently being recorded on all cameras.
+    pub mp4_recording: bool,
+    /// The number of frames buffered in each camera for post-triggering.
+    pub post_trigger_buffer_size: usize,
+    /// Filename of the calibration in use, if any.
+    pub calibration_filename: Option<String>,
+    /// Whether all cameras in the configuration are connected and
+    /// synchronized.
+    pub all_expected_cameras_are_synced: bool,
+}
+
+/// An element of the response to `GET cameras`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CameraInfo {
+    /// The camera name.
+    pub name: String,
+    /// The synchronization state of the camera.
+    pub sync_state: ConnectedCameraSyncState,
+    /// Frame and detection counts.
+    pub recent_stats: RecentStats,
+}
+
+/// Body of `PUT recording/braidz` and `PUT recording/mp4`.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct RecordingRequest {
+    /// `true` to start recording, `false` to stop recording.
+    pub recording: bool,
+}
+
+/// Body of `PUT experiment-uuid`.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ExperimentUuidRequest {
+    /// The UUID saved to the `experiment_info` table of the `.braidz` file.
+    pub uuid: String,
+}
+
+/// Body of `PUT post-trigger/buffer-size`.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PostTriggerBufferRequest {
+    /// The number of frames to buffer in each camera.
+    pub num_frames: usize,
+}
+
+/// Response to `POST annotations`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AnnotationResponse {
+    /// The synchronized frame number saved with the annotation, if the
+    /// cameras are synchronized.
+    pub frame: Option<u64>,
+    /// The trigger timestamp saved with the annotation (seconds since the
+    /// Unix epoch), if available.
+    pub timestamp: Option<f64>,
+}
+
+/// Body of all error responses.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub message: String,
+}
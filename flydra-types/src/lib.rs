@@ -34,6 +34,8 @@ pub const DATA2D_DISTORTED_CSV_FNAME: &str = "data2d_distorted.csv";
 pub const CAM_INFO_CSV_FNAME: &str = "cam_info.csv";
 pub const TRIGGER_CLOCK_INFO_CSV_FNAME: &str = "trigger_clock_info.csv";
 pub const EXPERIMENT_INFO_CSV_FNAME: &str = "experiment_info.csv";
+pub const ANNOTATIONS_CSV_FNAME: &str = "annotations.csv";
+pub const TRACKING_PARAMS_UPDATES_CSV_FNAME: &str = "tracking_params_updates.csv";
 pub const TEXTLOG_CSV_FNAME: &str = "textlog.csv";
 
 // Other files
@@ -170,6 +172,13 @@ pub mod braid_http {
     // URL paths on Braid HTTP server.
     pub const REMOTE_CAMERA_INFO_PATH: &str = "remote-camera-info";
     pub const CAM_PROXY_PATH: &str = "cam-proxy";
+    pub const AGENT_COMMANDS_PATH: &str = "agent-commands";
+
+    /// Encode agent name to be a single URL path component.
+    pub fn encode_agent_name(agent_name: &str) -> String {
+        percent_encoding::utf8_percent_encode(agent_name, percent_encoding::NON_ALPHANUMERIC)
+            .to_string()
+    }
 
     /// Encode camera name, potentially with slashes or spaces, to be a single
     /// URL path component.
@@ -315,6 +324,12 @@ pub struct BraidCameraConfig {
     /// The interval at which the current image should be sent, in milliseconds.
     #[serde(default = "default_send_current_image_interval_msec")]
     pub send_current_image_interval_msec: u64,
+    /// Name of the `braid-agent` which starts this camera.
+    ///
+    /// If set, Strand Camera is not started by Braid itself but rather by the
+    /// `braid-agent` with this name, which typically runs on another computer.
+    /// `start_backend` specifies which Strand Camera program the agent runs.
+    pub agent: Option<String>,
 
     /// Deprecated, useless old config option (not removed for backwards compatibility)
     #[serde(
@@ -349,6 +364,10 @@ pub enum StartCameraBackend {
     Pylon,
     /// Start a Vimba camera locally using `strand-cam-vimba` program.
     Vimba,
+    /// Play back a recorded video file using `strand-cam-video-file` program.
+    ///
+    /// The camera name is the path of the video file.
+    VideoFile,
 }
 
 impl StartCameraBackend {
@@ -357,6 +376,7 @@ impl StartCameraBackend {
             StartCameraBackend::Remote => None,
             StartCameraBackend::Pylon => Some("strand-cam-pylon"),
             StartCameraBackend::Vimba => Some("strand-cam-vimba"),
+            StartCameraBackend::VideoFile => Some("strand-cam-video-file"),
         }
     }
 }
@@ -374,10 +394,37 @@ impl BraidCameraConfig {
                 DEFAULT_ACQUISITION_DURATION_ALLOWED_IMPRECISION_MSEC,
             http_server_addr: None,
             send_current_image_interval_msec: default_send_current_image_interval_msec(),
+            agent: None,
         }
     }
 }
 
+/// Command from Braid to a `braid-agent`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum AgentCommand {
+    /// Start Strand Camera for this camera.
+    Launch(BraidCameraConfig),
+    /// Stop Strand Camera for this camera.
+    Stop(RawCamName),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct RegisterAgent {
+    /// The name of the agent, as used in [BraidCameraConfig::agent].
+    pub agent_name: String,
+    /// The hostname of the computer on which the agent runs.
+    pub hostname: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AgentCameraExited {
+    /// The name of the agent which started the Strand Camera process.
+    pub agent_name: String,
+    pub raw_cam_name: RawCamName,
+    /// Whether the process exited successfully.
+    pub success: bool,
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct PerCamSaveData {
     pub current_image_png: PngImageData,
@@ -449,6 +496,10 @@ pub enum ConnectedCameraSyncState {
     Unsynchronized,
     /// This `u64` is frame0, the offset to go from camera frame to sync frame.
     Synchronized(u64),
+    /// The camera joined the running sync frame timeline after the other
+    /// cameras were synchronized (e.g. because it was restarted). This `u64`
+    /// is added to the camera frame to get the sync frame.
+    Rejoined(u64),
 }
 
 impl ConnectedCameraSyncState {
@@ -456,6 +507,7 @@ impl ConnectedCameraSyncState {
         match self {
             ConnectedCameraSyncState::Unsynchronized => false,
             ConnectedCameraSyncState::Synchronized(_) => true,
+            ConnectedCameraSyncState::Rejoined(_) => true,
         }
     }
 }
@@ -475,6 +527,10 @@ pub struct BraidHttpApiSharedState {
     pub model_server_addr: Option<SocketAddr>,
     pub flydra_app_name: String,
     pub all_expected_cameras_are_synced: bool,
+    /// The mini arenas used for tracking.
+    pub mini_arena_config: MiniArenaConfig,
+    /// The tracking parameters currently used for new objects.
+    pub tracking_params: TrackingParams,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
@@ -702,6 +758,93 @@ pub struct TextlogRow {
     pub message: String,
 }
 
+/// A user annotation saved in the `annotations` table.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AnnotationRow {
+    // changes to this struct should update BraidMetadataSchemaTag
+    /// The most recent synchronized frame number when the annotation was made.
+    ///
+    /// This is empty if the cameras were not yet synchronized.
+    pub frame: Option<SyncFno>,
+    /// The trigger timestamp of `frame`, if available.
+    #[serde(with = "crate::timestamp_opt_f64")]
+    pub timestamp: Option<FlydraFloatTimestampLocal<Triggerbox>>,
+    /// The time Braid received the annotation.
+    #[serde(with = "crate::timestamp_f64")]
+    pub host_timestamp: FlydraFloatTimestampLocal<HostClock>,
+    /// The kind of annotation, e.g. `"note"` or `"stimulus_onset"`.
+    pub kind: String,
+    pub message: String,
+    /// Arbitrary JSON-encoded data. Empty if no payload was given.
+    pub payload: String,
+}
+
+/// A new annotation to be saved, stamped by Braid with the current frame.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NewAnnotation {
+    /// The kind of annotation, e.g. `"note"` or `"stimulus_onset"`.
+    pub kind: String,
+    #[serde(default)]
+    pub message: String,
+    /// Arbitrary data saved with the annotation.
+    #[serde(default)]
+    pub payload: Option<serde_json::Value>,
+}
+
+/// Which tracked objects a change of [TrackingParams] applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackingParamsScope {
+    /// Objects already being tracked keep the parameters they were born with.
+    #[default]
+    NewObjects,
+    /// The parameters are also applied to all objects already being tracked.
+    AllObjects,
+}
+
+/// A change of the tracking parameters during tracking.
+///
+/// The `mini_arena_config` and whether `hypothesis_test_params` is set (i.e.
+/// full 3D or flat 3D tracking) cannot be changed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrackingParamsUpdate {
+    pub tracking_params: TrackingParams,
+    #[serde(default)]
+    pub scope: TrackingParamsScope,
+}
+
+/// A row of the `tracking_params_updates` table.
+///
+/// Each row records a change of the tracking parameters, which was applied
+/// before tracking `frame`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrackingParamsUpdateRow {
+    // changes to this struct should update BraidMetadataSchemaTag
+    pub frame: SyncFno,
+    pub scope: TrackingParamsScope,
+    /// The new [TrackingParams], encoded as JSON.
+    pub tracking_params: String,
+}
+
+impl TrackingParamsUpdateRow {
+    pub fn new(frame: SyncFno, update: &TrackingParamsUpdate) -> Self {
+        Self {
+            frame,
+            scope: update.scope,
+            // Serializing `TrackingParams` to JSON cannot fail.
+            tracking_params: serde_json::to_string(&update.tracking_params).unwrap(),
+        }
+    }
+
+    /// Decode the update saved in this row.
+    pub fn update(&self) -> Result<TrackingParamsUpdate, serde_json::Error> {
+        Ok(TrackingParamsUpdate {
+            tracking_params: serde_json::from_str(&self.tracking_params)?,
+            scope: self.scope,
+        })
+    }
+}
+
 /// Tracking parameters
 ///
 /// The terminology used is as defined at [the Wikipedia page on the Kalman
@@ -736,7 +879,7 @@ pub struct TextlogRow {
 /// that 2**Q**(τ) = **Q**(2τ). In other words, two successive additions of this
 /// covariance will have an identical effect to a single addtion for twice the
 /// time interval.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct TrackingParams {
     /// This is used to scale the state noise covariance matrix **Q** as
@@ -908,6 +1051,11 @@ impl XYGridConfig {
         }
     }
 
+    /// The radius of each mini arena.
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
     pub fn iter_centers(&self) -> impl Iterator<Item = (f64, f64)> {
         XYGridIter {
             col_centers: self.x_centers.0.clone(),
@@ -993,7 +1141,7 @@ pub fn default_tracking_params_flat_3d() -> TrackingParams {
 }
 
 /// Hypothesis testing parameters.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct HypothesisTestParams {
     pub minimum_number_of_cameras: u8,
     pub hypothesis_test_max_acceptable_error: f64,
@@ -1043,6 +1191,15 @@ pub enum BraidHttpApiCallback {
     SetPostTriggerBufferSize(usize),
     /// Initiate MKV recording using post trigger
     PostTriggerMp4Recording,
+    /// Called from braid-agent to register itself. Braid will then queue
+    /// [AgentCommand::Launch] commands for the agent's cameras.
+    RegisterAgent(RegisterAgent),
+    /// Called from braid-agent when a Strand Camera process it started ended.
+    AgentCameraExited(AgentCameraExited),
+    /// Save an annotation in the `annotations` table.
+    AddAnnotation(NewAnnotation),
+    /// Change the tracking parameters while tracking.
+    SetTrackingParams(TrackingParamsUpdate),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
@@ -1079,6 +1236,8 @@ pub struct FlydraRawUdpPacket {
     pub points: Vec<FlydraRawUdpPoint>,
 }
 
+pub mod braid_api_v1;
+
 mod synced_frame;
 pub use synced_frame::SyncFno;
 
@@ -1128,6 +1287,25 @@ pub struct TriggerClockInfoRow {
     pub tcnt: u8,
     #[serde(with = "crate::timestamp_f64")]
     pub stop_timestamp: FlydraFloatTimestampLocal<HostClock>,
+    /// Residual of this measurement with the updated clock model, in seconds.
+    /// Empty if the measurement was not used.
+    #[serde(default)]
+    pub residual: Option<f64>,
+    /// Whether the clock model fit rejected this measurement.
+    #[serde(default)]
+    pub outlier: Option<bool>,
+    /// Clock model after this measurement, `timestamp = model_gain * frame +
+    /// model_offset`.
+    #[serde(default)]
+    pub model_gain: Option<f64>,
+    #[serde(default)]
+    pub model_offset: Option<f64>,
+    /// Robust standard deviation of the clock model residuals, in seconds.
+    #[serde(default)]
+    pub model_residual_std: Option<f64>,
+    /// Standard error of timestamps predicted by the clock model, in seconds.
+    #[serde(default)]
+    pub model_prediction_std: Option<f64>,
 }
 
 bitflags! {
@@ -1191,6 +1369,30 @@ impl Default for FakeSyncConfig {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceTimestampConfig {
+    /// The rate at which tracking results are output.
+    ///
+    /// Synchronized frame numbers count intervals of this duration since the
+    /// Unix epoch. This should be higher than the frame rate of the fastest
+    /// camera, because only one frame per camera is used in each interval.
+    #[serde(default = "default_output_framerate")]
+    pub output_framerate: f64,
+}
+
+const fn default_output_framerate() -> f64 {
+    100.0
+}
+
+impl Default for DeviceTimestampConfig {
+    fn default() -> Self {
+        Self {
+            output_framerate: default_output_framerate(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 #[serde(tag = "trigger_type")]
@@ -1200,7 +1402,9 @@ pub enum TriggerType {
     TriggerboxV1(TriggerboxConfig),
     /// Cameras are synchronized using PTP (Precision Time Protocol, IEEE 1588).
     PtpSync(PtpSyncConfig),
-    DeviceTimestamp,
+    /// Cameras are not synchronized. Each observation is tracked at its own
+    /// timestamp, computed from the camera's device timestamp.
+    DeviceTimestamp(DeviceTimestampConfig),
     /// Cameras are not synchronized, but we pretend they are.
     FakeSync(FakeSyncConfig),
 }
@@ -1364,3 +1568,8 @@ where
 
 pub const BRAID_EVENTS_URL_PATH: &str = "braid-events";
 pub const BRAID_EVENT_NAME: &str = "braid";
+/// Path of the Braid HTTP server with the event stream of the model server.
+///
+/// The events have the name [BRAID_EVENT_NAME] and are in the format of the
+/// "Braid pose API" as sent by the model server.
+pub const MODEL_EVENTS_URL_PATH: &str = "model-events";
@@ -97,6 +97,12 @@ fn test_serialize_timestamps_to_csv() -> eyre::Result<()> {
         framecount: 123,
         tcnt: 45,
         stop_timestamp: datetime_conversion::f64_to_datetime(t2_orig).into(),
+        residual: Some(-0.25e-3),
+        outlier: Some(false),
+        model_gain: Some(0.01),
+        model_offset: Some(t1_orig),
+        model_residual_std: Some(0.5e-3),
+        model_prediction_std: None,
     };
 
     let mut wtr = csv::Writer::from_writer(vec![]);
@@ -116,3 +122,15 @@ fn test_serialize_timestamps_to_csv() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_read_trigger_clock_info_without_clock_model() -> eyre::Result<()> {
+    // Files saved before the clock model columns were added.
+    let buf = b"start_timestamp,framecount,tcnt,stop_timestamp\n123.5,123,45,124.5\n";
+    let mut rdr = csv::Reader::from_reader(buf.as_slice());
+    let row: TriggerClockInfoRow = rdr.deserialize().next().unwrap()?;
+    assert_eq!(row.framecount, 123);
+    assert_eq!(row.residual, None);
+    assert_eq!(row.model_gain, None);
+    Ok(())
+}
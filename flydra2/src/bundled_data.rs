@@ -73,6 +73,8 @@ pub(crate) struct PerMiniArenaAllCamsOneFrameUndistorted {
 pub(crate) struct BundledAllCamsOneFrameUndistorted {
     pub(crate) tdpt: TimeDataPassthrough,
     pub(crate) per_mini_arena: Vec<PerMiniArenaAllCamsOneFrameUndistorted>,
+    /// The trigger timestamp of each camera's frame, in seconds.
+    pub(crate) cam_timestamps: BTreeMap<RawCamName, f64>,
 }
 
 // impl BundledAllCamsOneFrameUndistorted {
@@ -204,6 +206,18 @@ impl BundledAllCamsOneFrameDistorted {
             .map(|_| PerMiniArenaAllCamsOneFrameUndistorted::default())
             .collect();
 
+        let cam_timestamps = self
+            .inner
+            .iter()
+            .filter_map(|fdp| {
+                let frame_data = &fdp.frame_data;
+                frame_data
+                    .trigger_timestamp
+                    .as_ref()
+                    .map(|ts| (frame_data.cam_name.clone(), ts.as_f64()))
+            })
+            .collect();
+
         for distorted in self.inner.into_iter() {
             undistort_points_and_assign_arena(
                 distorted,
@@ -216,6 +230,7 @@ impl BundledAllCamsOneFrameDistorted {
         BundledAllCamsOneFrameUndistorted {
             tdpt,
             per_mini_arena,
+            cam_timestamps,
         }
     }
 }
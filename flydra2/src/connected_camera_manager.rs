@@ -6,9 +6,14 @@ use tracing::{debug, error, info};
 
 use crate::{safe_u8, CamInfoRow, MyFloat};
 use flydra_types::{
-    BuiServerInfo, CamInfo, CamNum, ConnectedCameraSyncState, PtpStamp, PtpSyncConfig, RawCamName,
-    RecentStats, SyncFno, TriggerType, TRIGGERBOX_SYNC_SECONDS,
+    BuiServerInfo, CamInfo, CamNum, ConnectedCameraSyncState, DeviceTimestampConfig, PtpStamp,
+    PtpSyncConfig, RawCamName, RecentStats, SyncFno, TriggerType, TRIGGERBOX_SYNC_SECONDS,
 };
+use rust_cam_bui_types::ClockModel;
+
+/// Number of frames used to estimate the frame offset of a camera which joins
+/// the sync frame timeline after the triggerbox synchronization pause.
+const REJOIN_NUM_SAMPLES: usize = 20;
 
 pub(crate) trait HasCameraList {
     fn camera_list(&self) -> CameraList;
@@ -44,6 +49,14 @@ pub struct ConnectedCameraInfo {
     http_camserver_info: BuiServerInfo,
     frames_during_sync: u64,
     _camera_periodic_signal_period_usec: Option<f64>,
+    /// When the most recent frame from this camera arrived.
+    last_frame_arrival: Mutex<std::time::Instant>,
+    /// Fractional frame offsets collected while rejoining the sync timeline.
+    rejoin_samples: Vec<f64>,
+    /// Whether the error that this camera cannot rejoin was logged.
+    rejoin_error_logged: AtomicBool,
+    /// The most recent synced frame when using device timestamps.
+    last_device_timestamp_frame: Mutex<Option<u64>>,
 }
 
 impl ConnectedCameraInfo {
@@ -286,17 +299,45 @@ impl ConnectedCamerasManager {
                     http_camserver_info: http_camserver_info.clone(),
                     frames_during_sync: 0,
                     _camera_periodic_signal_period_usec: camera_periodic_signal_period_usec,
+                    last_frame_arrival: Mutex::new(std::time::Instant::now()),
+                    rejoin_samples: Vec::new(),
+                    rejoin_error_logged: AtomicBool::new(false),
+                    last_device_timestamp_frame: Mutex::new(None),
                 },
             );
         }
         this
     }
 
+    /// Remove a camera, e.g. because it quit or stopped sending frames.
+    ///
+    /// The camera number is kept reserved so that the camera is assigned the
+    /// same number if it connects again.
     pub fn remove(&mut self, raw_cam_name: &RawCamName) {
-        self.inner.write().ccis.remove(raw_cam_name);
+        {
+            // This scope is for the write lock on self.inner. Keep it minimal.
+            let mut inner = self.inner.write();
+            if let Some(cci) = inner.ccis.remove(raw_cam_name) {
+                inner
+                    .not_yet_connected
+                    .insert(raw_cam_name.clone(), cci.cam_num);
+            }
+        }
         self.notify_cam_changed_listeners();
     }
 
+    /// Return the names of connected cameras from which no frame has arrived
+    /// within `timeout`.
+    pub fn stale_cameras(&self, timeout: std::time::Duration) -> Vec<RawCamName> {
+        self.inner
+            .read()
+            .ccis
+            .values()
+            .filter(|cci| cci.last_frame_arrival.lock().elapsed() > timeout)
+            .map(|cci| cci.raw_cam_name.clone())
+            .collect()
+    }
+
     /// This is called to register a camera when it connects to the mainbrain.
     ///
     /// See `new_single_cam` for the case when only a single camera will be
@@ -353,6 +394,10 @@ impl ConnectedCamerasManager {
                     http_camserver_info: http_camserver_info.clone(),
                     frames_during_sync: 0,
                     _camera_periodic_signal_period_usec: camera_periodic_signal_period_usec,
+                    last_frame_arrival: Mutex::new(std::time::Instant::now()),
+                    rejoin_samples: Vec::new(),
+                    rejoin_error_logged: AtomicBool::new(false),
+                    last_device_timestamp_frame: Mutex::new(None),
                 },
             );
             cam_num
@@ -369,26 +414,35 @@ impl ConnectedCamerasManager {
 
     /// Register that a new frame was received
     ///
+    /// `on_new_sync_state` is called when the camera becomes synchronized.
+    /// `clock_model` is used to place cameras which missed the triggerbox
+    /// synchronization pause onto the running sync frame timeline.
+    ///
     /// Returns synced frame number
     pub fn got_new_frame_live<F>(
         &self,
         packet: &flydra_types::FlydraRawUdpPacket,
         sync_pulse_pause_started_arc: &Arc<RwLock<Option<std::time::Instant>>>,
-        send_new_frame_offset: F,
+        clock_model: Option<&ClockModel>,
+        on_new_sync_state: F,
         trigger_cfg: &TriggerType,
     ) -> Option<SyncFno>
     where
-        F: FnMut(u64),
+        F: FnMut(&ConnectedCameraSyncState),
     {
         let sync_data = match &trigger_cfg {
             TriggerType::TriggerboxV1(_) => self.got_new_frame_live_triggerbox(
                 packet,
                 sync_pulse_pause_started_arc,
+                clock_model,
                 TRIGGERBOX_SYNC_SECONDS,
             ),
-            TriggerType::FakeSync(_) => {
-                self.got_new_frame_live_triggerbox(packet, sync_pulse_pause_started_arc, 0)
-            }
+            TriggerType::FakeSync(_) => self.got_new_frame_live_triggerbox(
+                packet,
+                sync_pulse_pause_started_arc,
+                clock_model,
+                0,
+            ),
             TriggerType::PtpSync(ptpcfg) => {
                 if let Some(sync_data) = self.got_new_frame_live_ptp(packet, ptpcfg) {
                     sync_data
@@ -396,11 +450,11 @@ impl ConnectedCamerasManager {
                     return None;
                 }
             }
-            TriggerType::DeviceTimestamp => {
-                todo!();
+            TriggerType::DeviceTimestamp(cfg) => {
+                self.got_new_frame_live_device_timestamp(packet, cfg)?
             }
         };
-        self.finish_got_new_frame_live(sync_data, send_new_frame_offset)
+        self.finish_got_new_frame_live(sync_data, on_new_sync_state)
     }
 
     /// Register that a new frame was received if we are using the triggerbox (or fake sync).
@@ -408,6 +462,7 @@ impl ConnectedCamerasManager {
         &self,
         packet: &flydra_types::FlydraRawUdpPacket,
         sync_pulse_pause_started_arc: &Arc<RwLock<Option<std::time::Instant>>>,
+        clock_model: Option<&ClockModel>,
         sync_time_min_sec: u64,
     ) -> SyncData {
         assert!(packet.framenumber >= 0);
@@ -419,9 +474,10 @@ impl ConnectedCamerasManager {
 
         let cam_frame = packet.framenumber as u64;
         let mut synced_frame = None;
-        let mut new_frame0 = None;
+        let mut new_sync_state = None;
         let mut got_frame_during_sync_time = false;
         let mut do_check_if_all_cameras_present = false;
+        let mut rejoin_sample = None;
         {
             let inner = self.inner.read();
             if let Some(cci) = inner.ccis.get(&raw_cam_name) {
@@ -437,10 +493,30 @@ impl ConnectedCamerasManager {
                                 // Camera is not synchronized, but we are
                                 // expecting a sync pulse. Therefore,
                                 // synchronize the camera now.
-                                new_frame0 = Some(cam_frame - crate::TRIGGERBOX_FIRST_PULSE);
+                                new_sync_state = Some(ConnectedCameraSyncState::Synchronized(
+                                    cam_frame - crate::TRIGGERBOX_FIRST_PULSE,
+                                ));
 
                                 // // `synced_frame` is the first pulsenumber.
                                 synced_frame = Some(crate::TRIGGERBOX_FIRST_PULSE);
+                            } else if sync_time_max <= elapsed {
+                                // The synchronization pause is long over but
+                                // this camera missed it (e.g. it was
+                                // restarted). Use the clock model to estimate
+                                // where it is on the sync frame timeline.
+                                if let Some(cm) = clock_model {
+                                    let received = packet.cam_received_time.as_f64();
+                                    let frac_frame = (received - cm.offset) / cm.gain;
+                                    rejoin_sample = Some(frac_frame - cam_frame as f64);
+                                } else if !cci.rejoin_error_logged.swap(true, Ordering::SeqCst) {
+                                    // With fake synchronization, there is never
+                                    // a clock model after the pause.
+                                    error!(
+                                        "Camera \"{}\" missed the synchronization and cannot \
+                                        rejoin without a clock model. Its frames are dropped.",
+                                        raw_cam_name.as_str()
+                                    );
+                                }
                             } else if std::time::Duration::from_millis(50) < elapsed {
                                 // If we are 50 msec into the pause but we get a
                                 // frame but it hasn't get been sync_time_min,
@@ -476,6 +552,9 @@ impl ConnectedCamerasManager {
                             synced_frame = Some(corrected_frame_number);
                         }
                     }
+                    Rejoined(delta) => {
+                        synced_frame = Some(cam_frame + delta);
+                    }
                 };
             }
             // If we do not know the camera, it is because we are starting up
@@ -483,6 +562,43 @@ impl ConnectedCamerasManager {
             // we should ignore this new data.
         }
 
+        if let Some(rejoin_sample) = rejoin_sample {
+            // This scope is for the write lock on self.inner. Keep it minimal.
+            let mut inner = self.inner.write();
+            if let Some(cci) = inner.ccis.get_mut(&raw_cam_name) {
+                cci.rejoin_samples.push(rejoin_sample);
+                if cci.rejoin_samples.len() >= REJOIN_NUM_SAMPLES {
+                    // Each sample is the true offset plus the latency between
+                    // trigger and frame arrival, in units of frames. Latency
+                    // is never negative, so the smallest sample is the best
+                    // estimate. This assumes the minimum latency is less than
+                    // one frame period.
+                    let min_sample = cci
+                        .rejoin_samples
+                        .iter()
+                        .copied()
+                        .fold(f64::INFINITY, f64::min);
+                    cci.rejoin_samples.clear();
+                    let delta = min_sample.floor() as i64;
+                    let synced = cam_frame as i64 + delta;
+                    if synced >= 0 {
+                        let state = if delta <= 0 {
+                            ConnectedCameraSyncState::Synchronized((-delta) as u64)
+                        } else {
+                            ConnectedCameraSyncState::Rejoined(delta as u64)
+                        };
+                        new_sync_state = Some(state);
+                        synced_frame = Some(synced as u64);
+                    } else {
+                        error!(
+                            "Camera \"{}\" cannot rejoin: computed negative sync frame.",
+                            raw_cam_name.as_str()
+                        );
+                    }
+                }
+            }
+        }
+
         if got_frame_during_sync_time {
             let frames_during_sync = {
                 // This scope is for the write lock on self.inner. Keep it minimal.
@@ -492,9 +608,8 @@ impl ConnectedCamerasManager {
                         cci.frames_during_sync += 1;
                         cci.frames_during_sync
                     }
-                    None => {
-                        panic!("reached impossible code.");
-                    }
+                    // The camera was removed since this frame was processed.
+                    None => 0,
                 };
                 frames_during_sync
             };
@@ -508,7 +623,7 @@ impl ConnectedCamerasManager {
             }
         }
         SyncData {
-            new_frame0,
+            new_sync_state,
             raw_cam_name,
             do_check_if_all_cameras_present,
             synced_frame,
@@ -570,18 +685,21 @@ impl ConnectedCamerasManager {
             let mut do_check_if_all_cameras_present = false;
 
             let synced_frame = Some(raw_fno);
-            let mut new_frame0 = None;
+            let mut new_sync_state = None;
             use crate::ConnectedCameraSyncState::*;
             match &cci.sync_state {
                 Unsynchronized => {
-                    new_frame0 = Some(0);
+                    // Cameras connecting late (e.g. after a restart) join
+                    // the timeline the same way because the sync frame is
+                    // computed from the PTP timestamp alone.
+                    new_sync_state = Some(Synchronized(0));
                     do_check_if_all_cameras_present = true;
                 }
-                Synchronized(_frame0) => {}
+                Synchronized(_) | Rejoined(_) => {}
             }
 
             Some(SyncData {
-                new_frame0,
+                new_sync_state,
                 raw_cam_name,
                 do_check_if_all_cameras_present,
                 synced_frame,
@@ -592,32 +710,105 @@ impl ConnectedCamerasManager {
         }
     }
 
+    /// Register that a new frame was received if we are using device timestamps
+    ///
+    /// The synced frame is the output interval containing the (corrected)
+    /// timestamp of the frame. Output frame `n` covers the interval ending at
+    /// `n / output_framerate` seconds after the Unix epoch.
+    fn got_new_frame_live_device_timestamp(
+        &self,
+        packet: &flydra_types::FlydraRawUdpPacket,
+        cfg: &DeviceTimestampConfig,
+    ) -> Option<SyncData> {
+        let raw_cam_name = RawCamName::new(packet.cam_name.clone());
+
+        let timestamp = match &packet.timestamp {
+            Some(timestamp) => timestamp.as_f64(),
+            None => {
+                debug!(
+                    "No timestamp for frame from camera \"{}\". Dropping.",
+                    raw_cam_name.as_str()
+                );
+                return None;
+            }
+        };
+        let raw_fno = (timestamp * cfg.output_framerate).ceil() as u64;
+
+        let inner = self.inner.read();
+        // If we do not know the camera, it is starting up (or shutting down).
+        let cci = inner.ccis.get(&raw_cam_name)?;
+
+        {
+            let mut last = cci.last_device_timestamp_frame.lock();
+            if last.map_or(false, |last| raw_fno <= last) {
+                // Only one frame per camera can be used in each output
+                // interval.
+                debug!(
+                    "Camera \"{}\" already has data for frame {}. Dropping.",
+                    raw_cam_name.as_str(),
+                    raw_fno
+                );
+                return None;
+            }
+            *last = Some(raw_fno);
+        }
+
+        let mut do_check_if_all_cameras_present = false;
+        let mut new_sync_state = None;
+        use crate::ConnectedCameraSyncState::*;
+        match &cci.sync_state {
+            Unsynchronized => {
+                // As with PTP, the sync frame is computed from the timestamp
+                // alone, so no synchronization pause is required.
+                new_sync_state = Some(Synchronized(0));
+                do_check_if_all_cameras_present = true;
+            }
+            Synchronized(_) | Rejoined(_) => {}
+        }
+
+        Some(SyncData {
+            new_sync_state,
+            raw_cam_name,
+            do_check_if_all_cameras_present,
+            synced_frame: Some(raw_fno),
+        })
+    }
+
     fn finish_got_new_frame_live<F>(
         &self,
         sync_data: SyncData,
-        mut send_new_frame_offset: F,
+        mut on_new_sync_state: F,
     ) -> Option<SyncFno>
     where
-        F: FnMut(u64),
+        F: FnMut(&ConnectedCameraSyncState),
     {
         let SyncData {
-            new_frame0,
+            new_sync_state,
             raw_cam_name,
             do_check_if_all_cameras_present,
             synced_frame,
         } = sync_data;
+        if let Some(cci) = self.inner.read().ccis.get(&raw_cam_name) {
+            *cci.last_frame_arrival.lock() = std::time::Instant::now();
+        }
         let mut do_check_if_all_cameras_synchronized = false;
-        if let Some(frame0) = new_frame0 {
+        if let Some(sync_state) = new_sync_state {
             // Perform the book-keeping associated with synchronization.
             {
                 // This scope is for the write lock on self.inner. Keep it minimal.
                 let mut inner = self.inner.write();
                 match inner.ccis.get_mut(&raw_cam_name) {
                     Some(cci) => {
-                        cci.sync_state = ConnectedCameraSyncState::Synchronized(frame0);
+                        cci.sync_state = sync_state.clone();
                     }
                     None => {
-                        panic!("reached impossible code.");
+                        // The camera was removed (e.g. to be relaunched)
+                        // since this frame was processed.
+                        debug!(
+                            "Camera \"{}\" removed before it synchronized. Dropping frame.",
+                            raw_cam_name.as_str()
+                        );
+                        return None;
                     }
                 }
             }
@@ -625,11 +816,11 @@ impl ConnectedCamerasManager {
             self.notify_cam_changed_listeners();
 
             // Do notifications associated with synchronization.
-            send_new_frame_offset(frame0);
+            on_new_sync_state(&sync_state);
             info!(
-                "cam \"{}\" synchronized with frame offset: {}",
+                "cam \"{}\" synchronized: {:?}",
                 raw_cam_name.as_str(),
-                frame0,
+                sync_state,
             );
             do_check_if_all_cameras_synchronized = true;
         }
@@ -744,7 +935,7 @@ impl std::fmt::Debug for ConnectedCamerasManager {
 
 #[derive(Debug)]
 struct SyncData {
-    new_frame0: Option<u64>,
+    new_sync_state: Option<ConnectedCameraSyncState>,
     raw_cam_name: RawCamName,
     do_check_if_all_cameras_present: bool,
     synced_frame: Option<u64>,
@@ -764,3 +955,189 @@ fn test_camera_list() {
     let c2 = CameraList::new(&[4, 3, 2, 5]);
     assert!(c1 != c2);
 }
+
+#[test]
+fn test_reconnect_keeps_cam_num() {
+    let mut cam_manager = ConnectedCamerasManager::new(
+        &None,
+        BTreeSet::new(),
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(AtomicBool::new(false)),
+        None,
+    );
+    let cam1 = RawCamName::new("cam1".to_string());
+    let cam2 = RawCamName::new("cam2".to_string());
+    for cam in [&cam1, &cam2] {
+        cam_manager
+            .register_new_camera(cam, &BuiServerInfo::NoServer, None)
+            .unwrap();
+    }
+    let cam_num1 = cam_manager.cam_num(&cam1).unwrap();
+
+    cam_manager.remove(&cam1);
+    assert_eq!(cam_manager.all_raw_cam_names(), vec![cam2.clone()]);
+
+    cam_manager
+        .register_new_camera(&cam1, &BuiServerInfo::NoServer, None)
+        .unwrap();
+    assert_eq!(cam_manager.cam_num(&cam1), Some(cam_num1));
+
+    assert!(cam_manager
+        .stale_cameras(std::time::Duration::from_secs(60))
+        .is_empty());
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    assert_eq!(
+        cam_manager
+            .stale_cameras(std::time::Duration::from_millis(1))
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn test_device_timestamp_frames() {
+    use flydra_types::{FlydraFloatTimestampLocal, ImageProcessingSteps};
+
+    let mut cam_manager = ConnectedCamerasManager::new(
+        &None,
+        BTreeSet::new(),
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(AtomicBool::new(false)),
+        None,
+    );
+    let cam1 = RawCamName::new("cam1".to_string());
+    cam_manager
+        .register_new_camera(&cam1, &BuiServerInfo::NoServer, None)
+        .unwrap();
+
+    let trigger_cfg = TriggerType::DeviceTimestamp(DeviceTimestampConfig {
+        output_framerate: 100.0,
+    });
+    let sync_pulse_pause_started_arc = Arc::new(RwLock::new(None));
+    let got_frame = |timestamp: f64| {
+        let packet = flydra_types::FlydraRawUdpPacket {
+            cam_name: cam1.as_str().to_string(),
+            timestamp: Some(FlydraFloatTimestampLocal::from_f64(timestamp)),
+            cam_received_time: FlydraFloatTimestampLocal::from_f64(timestamp + 0.005),
+            device_timestamp: None,
+            block_id: None,
+            framenumber: 0,
+            n_frames_skipped: 0,
+            done_camnode_processing: 0.0,
+            preprocess_stamp: 0.0,
+            image_processing_steps: ImageProcessingSteps::empty(),
+            points: vec![],
+        };
+        cam_manager.got_new_frame_live(
+            &packet,
+            &sync_pulse_pause_started_arc,
+            None,
+            |_| {},
+            &trigger_cfg,
+        )
+    };
+
+    // Frame `n` is the interval ending at `n / output_framerate`.
+    assert_eq!(got_frame(1000.0012), Some(SyncFno(100001)));
+    assert_eq!(got_frame(1000.0162), Some(SyncFno(100002)));
+    // A second frame from the same camera in the same interval is dropped.
+    assert_eq!(got_frame(1000.0181), None);
+    assert_eq!(got_frame(1000.0251), Some(SyncFno(100003)));
+}
+
+#[test]
+fn test_restarted_camera_rejoins() {
+    use flydra_types::{FlydraFloatTimestampLocal, ImageProcessingSteps, TriggerboxConfig};
+
+    let mut cam_manager = ConnectedCamerasManager::new(
+        &None,
+        BTreeSet::new(),
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(AtomicBool::new(false)),
+        None,
+    );
+    let cam1 = RawCamName::new("cam1".to_string());
+    let cam2 = RawCamName::new("cam2".to_string());
+    for cam in [&cam1, &cam2] {
+        cam_manager
+            .register_new_camera(cam, &BuiServerInfo::NoServer, None)
+            .unwrap();
+    }
+
+    let trigger_cfg = TriggerType::TriggerboxV1(TriggerboxConfig::default());
+    // The synchronization pause is long over.
+    let pause_started = std::time::Instant::now()
+        .checked_sub(std::time::Duration::from_secs(TRIGGERBOX_SYNC_SECONDS + 10))
+        .unwrap();
+    let sync_pulse_pause_started_arc = Arc::new(RwLock::new(Some(pause_started)));
+    // Sync frame `n` is triggered at `1000 + n / 100` seconds.
+    let clock_model = ClockModel {
+        gain: 0.01,
+        offset: 1000.0,
+        residuals: 0.0,
+        n_measurements: 100,
+        n_inliers: None,
+        residual_std: None,
+        prediction_std: None,
+    };
+
+    let mut new_states = Vec::new();
+    let mut got_frame = |cam: &RawCamName, cam_frame: i64, sync_frame: u64, latency: f64| {
+        let received = clock_model.offset + clock_model.gain * (sync_frame as f64 + latency);
+        let packet = flydra_types::FlydraRawUdpPacket {
+            cam_name: cam.as_str().to_string(),
+            timestamp: None,
+            cam_received_time: FlydraFloatTimestampLocal::from_f64(received),
+            device_timestamp: None,
+            block_id: None,
+            framenumber: cam_frame,
+            n_frames_skipped: 0,
+            done_camnode_processing: 0.0,
+            preprocess_stamp: 0.0,
+            image_processing_steps: ImageProcessingSteps::empty(),
+            points: vec![],
+        };
+        cam_manager.got_new_frame_live(
+            &packet,
+            &sync_pulse_pause_started_arc,
+            Some(&clock_model),
+            |state| new_states.push((cam.clone(), state.clone())),
+            &trigger_cfg,
+        )
+    };
+
+    // Latency in frames. The mean is more than a frame, so only the minimum
+    // gives the correct offset.
+    let latency = |i: usize| if i == 7 { 0.2 } else { 1.5 };
+
+    // cam1 was restarted, so its frame numbers are behind the sync frames.
+    for i in 0..REJOIN_NUM_SAMPLES - 1 {
+        let sync_frame = 5000 + i as u64;
+        assert_eq!(
+            got_frame(&cam1, sync_frame as i64 - 500, sync_frame, latency(i)),
+            None
+        );
+    }
+    // The last sample places the camera on the sync frame timeline.
+    assert_eq!(got_frame(&cam1, 4519, 5019, 1.5), Some(SyncFno(5019)));
+    assert_eq!(got_frame(&cam1, 4520, 5020, 1.5), Some(SyncFno(5020)));
+
+    // cam2 frame numbers are ahead of the sync frames.
+    for i in 0..REJOIN_NUM_SAMPLES {
+        let sync_frame = 6000 + i as u64;
+        let expected = (i == REJOIN_NUM_SAMPLES - 1).then_some(SyncFno(sync_frame));
+        assert_eq!(
+            got_frame(&cam2, sync_frame as i64 + 300, sync_frame, latency(i)),
+            expected
+        );
+    }
+    assert_eq!(got_frame(&cam2, 6320, 6020, 1.5), Some(SyncFno(6020)));
+
+    assert_eq!(
+        new_states,
+        vec![
+            (cam1, ConnectedCameraSyncState::Rejoined(500)),
+            (cam2, ConnectedCameraSyncState::Synchronized(300)),
+        ]
+    );
+}
@@ -32,9 +32,10 @@ use mvg::{DistortedPixel, PointWorldFrame, PointWorldFrameWithSumReprojError};
 pub use braidz_types::BraidMetadata;
 
 use flydra_types::{
-    CamInfoRow, CamNum, ConnectedCameraSyncState, DataAssocRow, FlydraFloatTimestampLocal,
-    HostClock, KalmanEstimatesRow, RawCamName, SyncFno, TextlogRow, TrackingParams,
-    TriggerClockInfoRow, Triggerbox, RECONSTRUCT_LATENCY_HLOG_FNAME, REPROJECTION_DIST_HLOG_FNAME,
+    AnnotationRow, CamInfoRow, CamNum, ConnectedCameraSyncState, DataAssocRow,
+    FlydraFloatTimestampLocal, HostClock, KalmanEstimatesRow, RawCamName, SyncFno, TextlogRow,
+    TrackingParams, TrackingParamsUpdate, TrackingParamsUpdateRow, TriggerClockInfoRow, Triggerbox,
+    RECONSTRUCT_LATENCY_HLOG_FNAME, REPROJECTION_DIST_HLOG_FNAME,
 };
 pub use flydra_types::{Data2dDistortedRow, Data2dDistortedRowF32};
 
@@ -57,7 +58,9 @@ mod tracking_core;
 mod mini_arenas;
 
 mod model_server;
-pub use crate::model_server::{new_model_server, SendKalmanEstimatesRow, SendType};
+pub use crate::model_server::{
+    model_event_frame, new_model_server, SendKalmanEstimatesRow, SendType,
+};
 
 use crate::contiguous_stream::make_contiguous;
 use crate::frame_bundler::bundle_frames;
@@ -476,6 +479,8 @@ pub enum SaveToDiskMsg {
     Textlog(TextlogRow),
     TriggerClockInfo(TriggerClockInfoRow),
     SetExperimentUuid(String),
+    Annotation(AnnotationRow),
+    TrackingParamsUpdate(TrackingParamsUpdateRow),
 }
 
 /// Acts like a `csv::Writer` but buffers and orders by frame.
@@ -675,6 +680,28 @@ pub struct CoordProcessorConfig {
     pub ignore_latency: bool,
     pub mini_arena_debug_image_dir: Option<std::path::PathBuf>,
     pub write_buffer_size_num_messages: usize,
+    /// Track each observation at the trigger timestamp of its camera.
+    ///
+    /// This is used when the cameras are not synchronized (e.g. with
+    /// [flydra_types::TriggerType::DeviceTimestamp]). Synced frame `n` is then
+    /// the interval ending at `n / fps` seconds after the Unix epoch and
+    /// tracking results are output at the end of each interval.
+    pub per_observation_timestamps: bool,
+}
+
+/// Check if the tracking parameters can be changed from `current` to `new`
+/// while tracking.
+pub fn check_tracking_params_update(
+    current: &TrackingParams,
+    new: &TrackingParams,
+) -> std::result::Result<(), &'static str> {
+    if current.mini_arena_config != new.mini_arena_config {
+        return Err("mini_arena_config cannot be changed while tracking");
+    }
+    if current.hypothesis_test_params.is_some() != new.hypothesis_test_params.is_some() {
+        return Err("cannot switch between full 3D and flat 3D tracking while tracking");
+    }
+    Ok(())
 }
 
 /// A [tokio::sync::mpsc::Sender] which cannot be cloned.
@@ -728,6 +755,33 @@ pub struct CoordProcessor {
         Vec<crate::tracking_core::ModelCollection<crate::tracking_core::CollectionFrameDone>>,
     >,
     next_obj_id: Arc<Mutex<u32>>,
+    tracking_params_changes: TrackingParamsChanges,
+    per_observation_timestamps: bool,
+}
+
+/// Pending changes of the tracking parameters.
+#[derive(Debug, Default)]
+struct TrackingParamsChanges {
+    /// Receives changes while tracking.
+    rx: Option<tokio::sync::mpsc::Receiver<TrackingParamsUpdate>>,
+    /// Changes to apply at given frames, sorted by frame.
+    scheduled: std::collections::VecDeque<(SyncFno, TrackingParamsUpdate)>,
+}
+
+impl TrackingParamsChanges {
+    /// Take all changes to apply prior to tracking `frame`.
+    fn take_pending(&mut self, frame: SyncFno) -> Vec<TrackingParamsUpdate> {
+        let mut updates = Vec::new();
+        while self.scheduled.front().map_or(false, |(f, _)| *f <= frame) {
+            updates.push(self.scheduled.pop_front().unwrap().1);
+        }
+        if let Some(rx) = self.rx.as_mut() {
+            while let Ok(update) = rx.try_recv() {
+                updates.push(update);
+            }
+        }
+        updates
+    }
 }
 
 impl CoordProcessor {
@@ -744,6 +798,7 @@ impl CoordProcessor {
             ignore_latency,
             mini_arena_debug_image_dir,
             write_buffer_size_num_messages,
+            per_observation_timestamps,
         } = cfg;
 
         trace!("CoordProcessor using {:?}", recon);
@@ -787,9 +842,39 @@ impl CoordProcessor {
             model_collections: None,
             mini_arena_images,
             next_obj_id: Arc::new(Mutex::new(0)),
+            tracking_params_changes: Default::default(),
+            per_observation_timestamps,
         })
     }
 
+    /// Get a sender with which the tracking parameters can be changed while
+    /// tracking.
+    ///
+    /// Each change is applied prior to tracking the next frame and saved in
+    /// the `tracking_params_updates` table. Calling this again disconnects
+    /// previously returned senders.
+    pub fn tracking_params_sender(&mut self) -> tokio::sync::mpsc::Sender<TrackingParamsUpdate> {
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        self.tracking_params_changes.rx = Some(rx);
+        tx
+    }
+
+    /// Change the tracking parameters prior to tracking `frame`.
+    ///
+    /// This is used to reproduce changes recorded in the
+    /// `tracking_params_updates` table.
+    pub fn schedule_tracking_params_update(
+        &mut self,
+        frame: SyncFno,
+        update: TrackingParamsUpdate,
+    ) -> std::result::Result<(), &'static str> {
+        check_tracking_params_update(&self.tracking_params, &update.tracking_params)?;
+        let scheduled = &mut self.tracking_params_changes.scheduled;
+        let idx = scheduled.partition_point(|(f, _)| *f <= frame);
+        scheduled.insert(idx, (frame, update));
+        Ok(())
+    }
+
     fn new_model_collections(
         &self,
         recon: &flydra_mvg::FlydraMultiCameraSystem<MyFloat>,
@@ -927,8 +1012,39 @@ impl CoordProcessor {
             );
             prev_frame = bundle.frame();
 
+            // Apply changes of the tracking parameters. (The incoming stream
+            // holds a borrow of `self.braidz_write_tx`, so this cannot be a
+            // method taking `&mut self`.)
+            for update in self.tracking_params_changes.take_pending(bundle.frame()) {
+                if let Err(msg) =
+                    check_tracking_params_update(&self.tracking_params, &update.tracking_params)
+                {
+                    error!("Ignoring change of tracking parameters: {msg}");
+                    continue;
+                }
+                info!(
+                    "At frame {}, using TrackingParams {:?} for {:?}",
+                    bundle.frame(),
+                    update.tracking_params,
+                    update.scope
+                );
+                let params = Arc::new(update.tracking_params.clone());
+                if let Some(model_collections) = self.model_collections.as_mut() {
+                    for mc in model_collections.iter_mut() {
+                        mc.set_tracking_params(params.clone(), update.scope);
+                    }
+                }
+                self.tracking_params = params;
+                self.braidz_write_tx
+                    .send(SaveToDiskMsg::TrackingParamsUpdate(
+                        TrackingParamsUpdateRow::new(bundle.frame(), &update),
+                    ))
+                    .await
+                    .unwrap();
+            }
+
             // Undistort incoming points and assign to mini arenas.
-            let undistorted = if let Some(recon) = &self.recon {
+            let mut undistorted = if let Some(recon) = &self.recon {
                 bundle.undistort_and_split_to_mini_arenas(
                     recon,
                     &self.mini_arena_images,
@@ -947,35 +1063,60 @@ impl CoordProcessor {
                 debug_assert_eq!(undistorted.per_mini_arena.len(), mcs.len());
             }
 
+            if self.per_observation_timestamps {
+                // The output time is the end of the frame interval rather
+                // than the timestamp of any one camera.
+                let fps = expected_framerate.expect("expected_framerate must be set") as f64;
+                let frame_end = undistorted.tdpt.frame.0 as f64 / fps;
+                undistorted.tdpt.timestamp = Some(FlydraFloatTimestampLocal::from_f64(frame_end));
+            }
+
             // TODO: split processing across arenas into multiple threads.
             if let Some(model_collections) = self.model_collections.take() {
-                // Across all arenas, predict motion (Kalman prediction step).
-                let model_collections = model_collections
-                    .into_iter()
-                    .map(|mc| mc.predict_motion())
-                    .collect::<Vec<_>>();
-
                 let tdpt = &undistorted.tdpt;
 
-                // ---------------------------------
-                // ---------------------------------
-                // ---------------------------------
-
-                // Across all arenas, compute likelihood of each observation.
-                let model_collections = model_collections
-                    .into_iter()
-                    .zip(undistorted.per_mini_arena.iter())
-                    .map(|(mc, arena_bundle)| mc.compute_observation_likes(tdpt, arena_bundle))
-                    .collect::<Vec<_>>();
-
-                // Across all arenas, perform data association
-                let model_collections_and_unused_observations = model_collections
-                    .into_iter()
-                    .zip(undistorted.per_mini_arena.into_iter())
-                    .map(|(mc, arena_bundle)| {
-                        mc.solve_data_association_and_update(tdpt, arena_bundle)
-                    })
-                    .collect::<Vec<_>>();
+                let model_collections_and_unused_observations = if self.per_observation_timestamps {
+                    // Across all arenas, predict and update observation by
+                    // observation.
+                    let cam_timestamps = &undistorted.cam_timestamps;
+                    model_collections
+                        .into_iter()
+                        .zip(undistorted.per_mini_arena.into_iter())
+                        .map(|(mc, arena_bundle)| {
+                            mc.predict_and_update_per_observation(
+                                tdpt,
+                                arena_bundle,
+                                cam_timestamps,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                } else {
+                    // Across all arenas, predict motion (Kalman prediction step).
+                    let model_collections = model_collections
+                        .into_iter()
+                        .map(|mc| mc.predict_motion())
+                        .collect::<Vec<_>>();
+
+                    // ---------------------------------
+                    // ---------------------------------
+                    // ---------------------------------
+
+                    // Across all arenas, compute likelihood of each observation.
+                    let model_collections = model_collections
+                        .into_iter()
+                        .zip(undistorted.per_mini_arena.iter())
+                        .map(|(mc, arena_bundle)| mc.compute_observation_likes(tdpt, arena_bundle))
+                        .collect::<Vec<_>>();
+
+                    // Across all arenas, perform data association
+                    model_collections
+                        .into_iter()
+                        .zip(undistorted.per_mini_arena.into_iter())
+                        .map(|(mc, arena_bundle)| {
+                            mc.solve_data_association_and_update(tdpt, arena_bundle)
+                        })
+                        .collect::<Vec<_>>()
+                };
 
                 // ---------------------------------
                 // ---------------------------------
@@ -1075,3 +1216,40 @@ fn test_csv_nan() {
         assert_eq!(count, 1);
     }
 }
+
+#[test]
+fn test_tracking_params_update_roundtrip() {
+    use flydra_types::{TrackingParamsScope, XYGridConfig};
+
+    let mut tracking_params = flydra_types::default_tracking_params_full_3d();
+    tracking_params.motion_noise_scale *= 2.0;
+    let update = TrackingParamsUpdate {
+        tracking_params,
+        scope: TrackingParamsScope::AllObjects,
+    };
+    let row = TrackingParamsUpdateRow::new(SyncFno(123), &update);
+
+    let mut csv_buf = Vec::<u8>::new();
+    {
+        let mut wtr = csv::Writer::from_writer(&mut csv_buf);
+        wtr.serialize(&row).unwrap();
+    }
+
+    let rdr = csv::Reader::from_reader(csv_buf.as_slice());
+    let rows: Vec<TrackingParamsUpdateRow> = rdr
+        .into_deserialize()
+        .collect::<std::result::Result<_, _>>()
+        .unwrap();
+    assert_eq!(rows, vec![row]);
+    assert_eq!(rows[0].update().unwrap(), update);
+
+    // Changing the mini arenas or switching to flat 3D tracking is not possible.
+    let current = flydra_types::default_tracking_params_full_3d();
+    assert!(check_tracking_params_update(&current, &update.tracking_params).is_ok());
+    let mut new = current.clone();
+    new.mini_arena_config =
+        flydra_types::MiniArenaConfig::XYGrid(XYGridConfig::new(&[0.0, 1.0], &[0.0], 0.1));
+    assert!(check_tracking_params_update(&current, &new).is_err());
+    let new = flydra_types::default_tracking_params_flat_3d();
+    assert!(check_tracking_params_update(&current, &new).is_err());
+}
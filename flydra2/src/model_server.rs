@@ -46,7 +46,7 @@ async fn events_handler(
 
     // If we extracted a calibration above, send it already now.
     if let Some(cal_data) = cal_data {
-        let cal_body = get_body(&cal_data);
+        let cal_body = model_event_frame(&cal_data);
         tx.send(Ok(Frame::data(cal_body.into()))).await.unwrap();
     }
 
@@ -250,7 +250,10 @@ pub async fn new_model_server(
     Ok(())
 }
 
-fn get_body(data: &(SendType, TimeDataPassthrough)) -> String {
+/// Encode a message in the event stream format of the model server.
+///
+/// This is the format of the "Braid pose API".
+pub fn model_event_frame(data: &(SendType, TimeDataPassthrough)) -> String {
     let (msg, tdpt) = data;
     let latency: f64 = if let Some(ref tt) = tdpt.trigger_timestamp() {
         let now_f64 = datetime_conversion::datetime_to_f64(&chrono::Local::now());
@@ -280,7 +283,7 @@ async fn send_msg(
     data: &(SendType, TimeDataPassthrough),
     app_state: &ModelServerAppState,
 ) -> Result<()> {
-    let buf = get_body(data);
+    let buf = model_event_frame(data);
     app_state.event_broadcaster.broadcast_frame(buf).await;
     Ok(())
 }
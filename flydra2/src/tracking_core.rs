@@ -18,7 +18,7 @@ use adskalman::{StateAndCovariance, TransitionModelLinearNoControl};
 
 use flydra_types::{
     CamNum, DataAssocRow, FlydraFloatTimestampLocal, FlydraRawUdpPoint, KalmanEstimatesRow,
-    RawCamName, SyncFno, TrackingParams, Triggerbox,
+    RawCamName, SyncFno, TrackingParams, TrackingParamsScope, Triggerbox,
 };
 
 use crate::bundled_data::{MiniArenaPointPerCam, PerMiniArenaAllCamsOneFrameUndistorted};
@@ -49,6 +49,8 @@ struct ModelFrameDone {}
 #[derive(Debug)]
 struct ModelFrameStarted {
     prior: StateAndCovariance<MyFloat, U6>,
+    /// data association info from earlier observations on this frame.
+    data_assoc_this_timestamp: Vec<DataAssocInfo>,
 }
 
 #[derive(Debug)]
@@ -73,6 +75,8 @@ struct ModelFrameWithObservationLikes {
     obs_models_and_likelihoods: Vec<ObservationModel>,
     /// The estimate prior to update from observation.
     prior: StateAndCovariance<MyFloat, U6>,
+    /// data association info from earlier observations on this frame.
+    data_assoc_this_timestamp: Vec<DataAssocInfo>,
 }
 
 #[derive(Debug)]
@@ -151,6 +155,10 @@ struct LMInner {
     obj_id: u32,
     /// Initial start frame number
     _start_frame: SyncFno,
+    /// The tracking parameters used for this model.
+    params: Arc<TrackingParams>,
+    /// The motion model computed from `params`.
+    motion_model: MotionModel3DFixedDt<MyFloat>,
 }
 
 impl LivingModel<ModelFrameStarted> {
@@ -277,6 +285,7 @@ impl LivingModel<ModelFrameStarted> {
             state: ModelFrameWithObservationLikes {
                 obs_models_and_likelihoods,
                 prior: self.state.prior,
+                data_assoc_this_timestamp: self.state.data_assoc_this_timestamp,
             },
             posteriors: self.posteriors,
             last_observation_offset: self.last_observation_offset,
@@ -525,26 +534,7 @@ pub(crate) fn initialize_model_collection(
     cam_manager: ConnectedCamerasManager,
     mini_arena_idx: MiniArenaIndex,
 ) -> ModelCollection<CollectionFrameDone> {
-    let motion_noise_scale = params.motion_noise_scale;
-    let dt = 1.0 / fps as f64;
-
-    let (new_obj, motion_model) = if params.hypothesis_test_params.is_some() {
-        // full 3d tracking
-        let new_obj = NewObjectTestFull3D::new(recon.clone(), params.clone());
-        let motion_model_generator = ConstantVelocity3DModel::new(motion_noise_scale);
-        (
-            Box::new(new_obj) as Box<dyn HypothesisTest + Send + Sync>,
-            motion_model_generator.calc_for_dt(dt),
-        )
-    } else {
-        // "flat 3d" (2d) tracking
-        let new_obj = NewObjectTestFlat3D::new(recon.clone(), params.clone());
-        let motion_model_generator = FlatZZero3DModel::new(motion_noise_scale);
-        (
-            Box::new(new_obj) as Box<dyn HypothesisTest + Send + Sync>,
-            motion_model_generator.calc_for_dt(dt),
-        )
-    };
+    let (new_obj, motion_model) = new_obj_and_motion_model(&params, &recon, fps);
 
     ModelCollection {
         state: CollectionFrameDone { models: vec![] },
@@ -552,6 +542,7 @@ pub(crate) fn initialize_model_collection(
             mini_arena_idx,
             params,
             recon,
+            fps,
             new_obj,
             motion_model,
             cam_manager,
@@ -559,6 +550,38 @@ pub(crate) fn initialize_model_collection(
     }
 }
 
+fn new_obj_and_motion_model(
+    params: &Arc<TrackingParams>,
+    recon: &flydra_mvg::FlydraMultiCameraSystem<MyFloat>,
+    fps: f32,
+) -> (
+    Box<dyn HypothesisTest + Send + Sync>,
+    MotionModel3DFixedDt<MyFloat>,
+) {
+    let dt = 1.0 / fps as f64;
+
+    let new_obj = if params.hypothesis_test_params.is_some() {
+        // full 3d tracking
+        Box::new(NewObjectTestFull3D::new(recon.clone(), params.clone()))
+            as Box<dyn HypothesisTest + Send + Sync>
+    } else {
+        // "flat 3d" (2d) tracking
+        Box::new(NewObjectTestFlat3D::new(recon.clone(), params.clone()))
+            as Box<dyn HypothesisTest + Send + Sync>
+    };
+    (new_obj, motion_model_for_dt(params, dt))
+}
+
+/// The motion model used with `params` for a time step of `dt` seconds.
+fn motion_model_for_dt(params: &TrackingParams, dt: f64) -> MotionModel3DFixedDt<MyFloat> {
+    let motion_noise_scale = params.motion_noise_scale;
+    if params.hypothesis_test_params.is_some() {
+        ConstantVelocity3DModel::new(motion_noise_scale).calc_for_dt(dt)
+    } else {
+        FlatZZero3DModel::new(motion_noise_scale).calc_for_dt(dt)
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct ModelCollection<S: CollectionState> {
     state: S,
@@ -568,14 +591,38 @@ pub(crate) struct ModelCollection<S: CollectionState> {
 #[derive(Clone)]
 pub(crate) struct MCInner {
     pub(crate) mini_arena_idx: MiniArenaIndex,
+    /// The tracking parameters used for new models.
     params: Arc<TrackingParams>,
     pub(crate) recon: flydra_mvg::FlydraMultiCameraSystem<MyFloat>,
+    fps: f32,
     new_obj: Box<dyn HypothesisTest + Send + Sync>,
     motion_model: MotionModel3DFixedDt<MyFloat>,
     cam_manager: ConnectedCamerasManager,
 }
 
 impl ModelCollection<CollectionFrameDone> {
+    /// Change the tracking parameters.
+    ///
+    /// New models are always created with `params`. Existing models are only
+    /// changed if `scope` is [TrackingParamsScope::AllObjects].
+    pub(crate) fn set_tracking_params(
+        &mut self,
+        params: Arc<TrackingParams>,
+        scope: TrackingParamsScope,
+    ) {
+        let (new_obj, motion_model) =
+            new_obj_and_motion_model(&params, &self.mcinner.recon, self.mcinner.fps);
+        if scope == TrackingParamsScope::AllObjects {
+            for model in self.state.models.iter_mut() {
+                model.lmi.params = params.clone();
+                model.lmi.motion_model = motion_model.clone();
+            }
+        }
+        self.mcinner.params = params;
+        self.mcinner.new_obj = new_obj;
+        self.mcinner.motion_model = motion_model;
+    }
+
     #[tracing::instrument(level = "debug", skip_all)]
     pub(crate) fn predict_motion(self) -> ModelCollection<CollectionFrameStarted> {
         let mcinner = self.mcinner;
@@ -585,10 +632,13 @@ impl ModelCollection<CollectionFrameDone> {
             .into_iter()
             .map(|x| {
                 let last = &x.posteriors[x.posteriors.len() - 1];
-                let prior = mcinner.motion_model.predict(&last.estimate);
+                let prior = x.lmi.motion_model.predict(&last.estimate);
                 LivingModel {
                     gestation_age: x.gestation_age,
-                    state: ModelFrameStarted { prior },
+                    state: ModelFrameStarted {
+                        prior,
+                        data_assoc_this_timestamp: vec![],
+                    },
                     posteriors: x.posteriors,
                     last_observation_offset: x.last_observation_offset,
                     lmi: x.lmi,
@@ -600,6 +650,101 @@ impl ModelCollection<CollectionFrameDone> {
             mcinner,
         }
     }
+
+    /// Like [Self::predict_motion] but for a time step of `dt` seconds.
+    fn predict_motion_dt(self, dt: f64) -> ModelCollection<CollectionFrameStarted> {
+        let mcinner = self.mcinner;
+        let models = self
+            .state
+            .models
+            .into_iter()
+            .map(|x| {
+                let last = &x.posteriors[x.posteriors.len() - 1];
+                let prior = motion_model_for_dt(&x.lmi.params, dt).predict(&last.estimate);
+                LivingModel {
+                    gestation_age: x.gestation_age,
+                    state: ModelFrameStarted {
+                        prior,
+                        data_assoc_this_timestamp: vec![],
+                    },
+                    posteriors: x.posteriors,
+                    last_observation_offset: x.last_observation_offset,
+                    lmi: x.lmi,
+                }
+            })
+            .collect();
+        ModelCollection {
+            state: CollectionFrameStarted { models },
+            mcinner,
+        }
+    }
+
+    /// Perform the Kalman prediction and update steps when each camera has
+    /// its own timestamp.
+    ///
+    /// Rather than assuming all observations were made at the time of the
+    /// frame, the observations from each camera are used in order of their
+    /// timestamps in `cam_timestamps`, predicting with variable `dt` between
+    /// them. The posterior is finally predicted to the trigger timestamp of
+    /// `tdpt`, which ends the frame interval of duration `1/fps`.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub(crate) fn predict_and_update_per_observation(
+        self,
+        tdpt: &TimeDataPassthrough,
+        arena_bundle: PerMiniArenaAllCamsOneFrameUndistorted,
+        cam_timestamps: &BTreeMap<RawCamName, f64>,
+    ) -> (
+        ModelCollection<CollectionFramePosteriors>,
+        UnusedDataPerArena,
+    ) {
+        let frame_dt = 1.0 / self.mcinner.fps as f64;
+        let frame_time = tdpt.timestamp.as_ref().map(|ts| ts.as_f64());
+
+        // Split the observations by camera and sort by time relative to the
+        // end of the frame. A final empty step predicts to the end of the frame.
+        let mut steps: Vec<_> = arena_bundle
+            .per_cam
+            .into_iter()
+            .map(|(cam_name, points)| {
+                let offset = match (frame_time, cam_timestamps.get(&cam_name)) {
+                    (Some(frame_time), Some(cam_time)) => {
+                        (cam_time - frame_time).clamp(-frame_dt, 0.0)
+                    }
+                    _ => 0.0,
+                };
+                let per_cam = BTreeMap::from([(cam_name, points)]);
+                (offset, PerMiniArenaAllCamsOneFrameUndistorted { per_cam })
+            })
+            .collect();
+        steps.sort_by(|a, b| a.0.total_cmp(&b.0));
+        steps.push((0.0, PerMiniArenaAllCamsOneFrameUndistorted::default()));
+
+        let mut unused_bundle_per_cam = BTreeMap::new();
+        let mut time = steps[0].0;
+        let mut started = self.predict_motion_dt(time + frame_dt);
+        let mut steps = steps.into_iter().peekable();
+        while let Some((_, step_bundle)) = steps.next() {
+            let (posteriors, unused) = started
+                .compute_observation_likes(tdpt, &step_bundle)
+                .solve_data_association_and_update(tdpt, step_bundle);
+            unused_bundle_per_cam.extend(unused.0.per_cam);
+            match steps.peek() {
+                Some((offset, _)) => {
+                    started = posteriors.predict_motion_dt(offset - time);
+                    time = *offset;
+                }
+                None => {
+                    return (
+                        posteriors,
+                        UnusedDataPerArena(PerMiniArenaAllCamsOneFrameUndistorted {
+                            per_cam: unused_bundle_per_cam,
+                        }),
+                    );
+                }
+            }
+        }
+        unreachable!("the final step predicts to the end of the frame");
+    }
 }
 
 impl ModelCollection<CollectionFrameStarted> {
@@ -620,10 +765,12 @@ impl ModelCollection<CollectionFrameStarted> {
             .models
             .into_iter()
             .map(|x| {
+                let ekf_observation_covariance_pixels =
+                    x.lmi.params.ekf_observation_covariance_pixels;
                 x.compute_observation_likelihoods(
                     arena_bundle,
                     &mcinner.recon,
-                    mcinner.params.ekf_observation_covariance_pixels,
+                    ekf_observation_covariance_pixels,
                 )
             })
             .collect();
@@ -677,7 +824,7 @@ impl ModelCollection<CollectionFrameWithObservationLikes> {
                     // Destructure old model into constituent parts.
                     let LivingModel {
                         gestation_age,
-                        state,
+                        mut state,
                         posteriors,
                         last_observation_offset,
                         lmi,
@@ -693,7 +840,10 @@ impl ModelCollection<CollectionFrameWithObservationLikes> {
                                 estimate: state.prior.clone(), // just the prior initially
                                 tdpt: tdpt.clone(),
                             },
-                            data_assoc_this_timestamp: vec![], // no observations yet
+                            // observations from earlier steps of this frame
+                            data_assoc_this_timestamp: std::mem::take(
+                                &mut state.data_assoc_this_timestamp,
+                            ),
                         },
                         posteriors,
                         last_observation_offset,
@@ -773,7 +923,8 @@ impl ModelCollection<CollectionFrameWithObservationLikes> {
                     trace!("row_idx {}, best_col {:?}", row_idx, best_col);
 
                     if let Some((best_idx, best_wantedness)) = best_col {
-                        if best_wantedness > self.mcinner.params.accept_observation_min_likelihood {
+                        if best_wantedness > next_model.lmi.params.accept_observation_min_likelihood
+                        {
                             // don't take unwanted point
                             unused_col_idxs.remove(&best_idx);
 
@@ -872,6 +1023,37 @@ impl ModelCollection<CollectionFrameWithObservationLikes> {
     }
 }
 
+impl ModelCollection<CollectionFramePosteriors> {
+    /// Predict the posteriors forward by `dt` seconds, keeping the data
+    /// association of the frame so far.
+    fn predict_motion_dt(self, dt: f64) -> ModelCollection<CollectionFrameStarted> {
+        let mcinner = self.mcinner;
+        let models = self
+            .state
+            .models_with_posteriors
+            .into_iter()
+            .map(|x| {
+                let prior =
+                    motion_model_for_dt(&x.lmi.params, dt).predict(&x.state.posterior.estimate);
+                LivingModel {
+                    gestation_age: x.gestation_age,
+                    state: ModelFrameStarted {
+                        prior,
+                        data_assoc_this_timestamp: x.state.data_assoc_this_timestamp,
+                    },
+                    posteriors: x.posteriors,
+                    last_observation_offset: x.last_observation_offset,
+                    lmi: x.lmi,
+                }
+            })
+            .collect();
+        ModelCollection {
+            state: CollectionFrameStarted { models },
+            mcinner,
+        }
+    }
+}
+
 fn arg_max_col(a: &[f64]) -> Option<(usize, f64)> {
     let mut r = None;
     for (i, val) in a.iter().enumerate() {
@@ -930,9 +1112,8 @@ impl ModelCollection<CollectionFramePosteriors> {
         let mut to_kill = Vec::with_capacity(orig_models.len());
         let mut to_live = Vec::with_capacity(orig_models.len() + 1);
 
-        let max_variance = self.mcinner.params.max_position_std_meters.powi(2) as f64; // square so that it is in variance units
-
         for model in orig_models.into_iter() {
+            let max_variance = model.lmi.params.max_position_std_meters.powi(2) as f64; // square so that it is in variance units
             let covar_size = model.state.covariance_size();
             // trace!(
             //     "frame: {}, obj_id: {}, covar_size: {}, max_variance: {}",
@@ -1039,6 +1220,8 @@ impl ModelCollection<CollectionFramePosteriors> {
                     lmi: LMInner {
                         obj_id,
                         _start_frame: tdpt.frame,
+                        params: self.mcinner.params.clone(),
+                        motion_model: self.mcinner.motion_model.clone(),
                     },
                 };
 
@@ -1059,11 +1242,10 @@ impl ModelCollection<CollectionFramePosteriors> {
             }
         }
 
-        let num_observations_to_visibility = self.mcinner.params.num_observations_to_visibility;
-
         let mut models = vec![];
         let mut save_messages = Vec::new();
         for x in to_live.into_iter() {
+            let num_observations_to_visibility = x.lmi.params.num_observations_to_visibility;
             let (this_models, this_result_messages, this_sav_msgs) =
                 x.finish_frame(num_observations_to_visibility);
             save_messages.extend(this_sav_msgs);
@@ -1125,3 +1307,146 @@ fn convert_pt(input: &flydra_types::FlydraRawUdpPoint) -> mvg::DistortedPixel<My
         coords: nalgebra::geometry::Point2::new(input.x0_abs, input.y0_abs),
     }
 }
+
+#[test]
+fn test_predict_and_update_per_observation() {
+    use nalgebra::{Matrix3, Matrix3x4};
+
+    use crate::{bundled_data::Undistorted, NumberedRawUdpPoint};
+
+    let fps = 100.0;
+    let frame_dt = 0.01;
+
+    // Camera 1 looks down the Z axis and camera 2 down the X axis.
+    let k = Matrix3::new(600.0, 0.0, 320.0, 0.0, 600.0, 240.0, 0.0, 0.0, 1.0);
+    let rt1 = Matrix3x4::new(1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 5.0);
+    let rt2 = Matrix3x4::new(0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, -1.0, 0.0, 0.0, 5.0);
+    let cams = [("cam1", rt1), ("cam2", rt2)]
+        .into_iter()
+        .map(|(name, rt)| {
+            let cam = mvg::Camera::from_pmat(640, 480, &(k * rt)).unwrap();
+            (name.to_string(), cam)
+        })
+        .collect();
+    let recon = flydra_mvg::FlydraMultiCameraSystem::new(cams, None);
+    let cam1 = RawCamName::new("cam1".to_string());
+    let cam2 = RawCamName::new("cam2".to_string());
+    let cam_manager = ConnectedCamerasManager::new(
+        &Some(recon.clone()),
+        std::collections::BTreeSet::new(),
+        Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        None,
+    );
+
+    let params = Arc::new(flydra_types::default_tracking_params_full_3d());
+    let mut mc = initialize_model_collection(
+        params.clone(),
+        recon.clone(),
+        fps,
+        cam_manager.clone(),
+        MiniArenaIndex::new(0),
+    );
+
+    // The target moves with constant velocity. It is at the origin at the end
+    // of the previous frame interval.
+    let frame_end = 1000.0;
+    let true_state = |t: f64| {
+        let dt = t - (frame_end - frame_dt);
+        Vector6::new(dt, 0.5 * dt, -0.2 * dt, 1.0, 0.5, -0.2)
+    };
+    let prev_tdpt = TimeDataPassthrough::new(
+        SyncFno(99_999),
+        &Some(FlydraFloatTimestampLocal::from_f64(frame_end - frame_dt)),
+    );
+    mc.state.models.push(LivingModel {
+        gestation_age: None,
+        state: ModelFrameDone {},
+        posteriors: vec![StampedEstimate {
+            estimate: StateAndCovariance::new(
+                true_state(frame_end - frame_dt),
+                Matrix6::identity() * 1e-6,
+            ),
+            tdpt: prev_tdpt,
+        }],
+        last_observation_offset: 0,
+        lmi: LMInner {
+            obj_id: 1,
+            _start_frame: SyncFno(99_990),
+            params,
+            motion_model: mc.mcinner.motion_model.clone(),
+        },
+    });
+
+    let point = |idx: u8, x: f64, y: f64| MiniArenaPointPerCam {
+        undistorted: Undistorted { idx, x, y },
+        numbered_raw_udp_point: NumberedRawUdpPoint {
+            idx,
+            pt: FlydraRawUdpPoint {
+                x0_abs: x,
+                y0_abs: y,
+                area: 1.0,
+                maybe_slope_eccentricty: None,
+                cur_val: 255,
+                mean_val: 0.0,
+                sumsqf_val: 0.0,
+            },
+        },
+    };
+    let observe = |cam_name: &RawCamName, t: f64| {
+        let cam = recon.cam_by_name(cam_name.as_str()).unwrap();
+        let pixel = cam.project_3d_to_pixel(&to_world_point(&true_state(t)));
+        point(0, pixel.coords.x, pixel.coords.y)
+    };
+
+    // Camera 2 observes before camera 1. Camera 1 also sees something else.
+    let t1 = frame_end - 0.002;
+    let t2 = frame_end - 0.007;
+    let arena_bundle = PerMiniArenaAllCamsOneFrameUndistorted {
+        per_cam: BTreeMap::from([
+            (cam1.clone(), vec![observe(&cam1, t1), point(1, 10.0, 10.0)]),
+            (cam2.clone(), vec![observe(&cam2, t2)]),
+        ]),
+    };
+    let cam_timestamps = BTreeMap::from([(cam1.clone(), t1), (cam2.clone(), t2)]);
+    let tdpt = TimeDataPassthrough::new(
+        SyncFno(100_000),
+        &Some(FlydraFloatTimestampLocal::from_f64(frame_end)),
+    );
+
+    let (mc, unused) = mc.predict_and_update_per_observation(&tdpt, arena_bundle, &cam_timestamps);
+
+    let model = &mc.state.models_with_posteriors[0];
+    let cam_nums: Vec<_> = model
+        .state
+        .data_assoc_this_timestamp
+        .iter()
+        .map(|assoc| assoc.cam_num)
+        .collect();
+    assert_eq!(
+        cam_nums,
+        vec![
+            cam_manager.cam_num(&cam2).unwrap(),
+            cam_manager.cam_num(&cam1).unwrap()
+        ]
+    );
+
+    // The posterior is at the end of the frame interval.
+    let posterior = &model.state.posterior;
+    assert_eq!(posterior.frame(), SyncFno(100_000));
+    let expected = true_state(frame_end);
+    for i in 0..3 {
+        let actual = posterior.estimate.state()[i];
+        assert!(
+            (actual - expected[i]).abs() < 1e-6,
+            "coordinate {i}: {actual} != {}",
+            expected[i]
+        );
+    }
+
+    // The point not used for the model is available for new objects.
+    let unused = &unused.0.per_cam;
+    assert_eq!(unused[&cam1].len(), 1);
+    assert_eq!(unused[&cam1][0].undistorted.idx, 1);
+    assert!(unused[&cam2].is_empty());
+}
@@ -27,6 +27,8 @@ struct WritingState {
     textlog_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
     trigger_clock_info_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
     experiment_info_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
+    annotations_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
+    tracking_params_updates_wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
     writer_stats: Option<(usize, usize)>,
     file_start_time: std::time::SystemTime,
 
@@ -286,6 +288,22 @@ impl WritingState {
             csv::Writer::from_writer(Box::new(fd) as Box<dyn std::io::Write + Send>)
         };
 
+        let annotations_wtr = {
+            // Like experiment_info, this is not streamed to .gz and is flushed
+            // after each row.
+            let mut csv_path = output_dirname.clone();
+            csv_path.push(flydra_types::ANNOTATIONS_CSV_FNAME);
+            let fd = std::fs::File::create(&csv_path)?;
+            csv::Writer::from_writer(Box::new(fd) as Box<dyn std::io::Write + Send>)
+        };
+
+        let tracking_params_updates_wtr = {
+            let mut csv_path = output_dirname.clone();
+            csv_path.push(flydra_types::TRACKING_PARAMS_UPDATES_CSV_FNAME);
+            let fd = std::fs::File::create(&csv_path)?;
+            csv::Writer::from_writer(Box::new(fd) as Box<dyn std::io::Write + Send>)
+        };
+
         let data_assoc_wtr = if let Some(ref _recon) = recon {
             let mut csv_path = output_dirname.clone();
             csv_path.push(format!("{}.gz", flydra_types::DATA_ASSOCIATE_CSV_FNAME));
@@ -333,6 +351,8 @@ impl WritingState {
             textlog_wtr,
             trigger_clock_info_wtr,
             experiment_info_wtr,
+            annotations_wtr,
+            tracking_params_updates_wtr,
             writer_stats,
             file_start_time,
             reconstruction_latency_usec,
@@ -360,6 +380,8 @@ impl WritingState {
         self.textlog_wtr.flush()?;
         self.trigger_clock_info_wtr.flush()?;
         self.experiment_info_wtr.flush()?;
+        self.annotations_wtr.flush()?;
+        self.tracking_params_updates_wtr.flush()?;
         self.last_flush = std::time::Instant::now();
         Ok(())
     }
@@ -388,6 +410,8 @@ impl Drop for WritingState {
             self.textlog_wtr = dummy_csv();
             self.trigger_clock_info_wtr = dummy_csv();
             self.experiment_info_wtr = dummy_csv();
+            self.annotations_wtr = dummy_csv();
+            self.tracking_params_updates_wtr = dummy_csv();
         }
 
         // Move out original output name so that a subsequent call to `drop()`
@@ -484,7 +508,7 @@ pub(crate) fn writer_task_main(
     mut braidz_write_rx: tokio::sync::mpsc::Receiver<SaveToDiskMsg>,
     cam_manager: ConnectedCamerasManager,
     recon: Option<flydra_mvg::FlydraMultiCameraSystem<MyFloat>>,
-    tracking_params: Arc<TrackingParams>,
+    mut tracking_params: Arc<TrackingParams>,
     save_empty_data2d: bool,
     metadata_builder: BraidMetadataBuilder,
     ignore_latency: bool,
@@ -625,6 +649,22 @@ pub(crate) fn writer_task_main(
                     ws.experiment_info_wtr.serialize(&entry)?;
                 }
             }
+            Annotation(entry) => {
+                if let Some(ref mut ws) = writing_state {
+                    ws.annotations_wtr.serialize(&entry)?;
+                    ws.annotations_wtr.flush()?;
+                }
+                // simply drop data if no file opened
+            }
+            TrackingParamsUpdate(entry) => {
+                // Recordings started later save the current parameters.
+                let update = entry.update()?;
+                tracking_params = Arc::new(update.tracking_params);
+                if let Some(ref mut ws) = writing_state {
+                    ws.tracking_params_updates_wtr.serialize(&entry)?;
+                    ws.tracking_params_updates_wtr.flush()?;
+                }
+            }
             Textlog(entry) => {
                 if let Some(ref mut ws) = writing_state {
                     ws.textlog_wtr.serialize(&entry)?;
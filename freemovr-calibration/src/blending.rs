@@ -0,0 +1,433 @@
+//! Edge blending of overlapping projectors and photometric correction.
+//!
+//! Where the images of several projectors overlap on the display, each
+//! projector is attenuated so that the total brightness stays continuous. The
+//! linear alpha of a projector pixel is its distance to the edge of the
+//! projector image ("feathering") divided by the sum of these distances over
+//! all projectors illuminating the same display point. Because projector
+//! output is not linear in the input value, alpha is converted with the
+//! display gamma before it is applied.
+//!
+//! Optionally, the brightness of each projector, measured by a camera, is
+//! equalized per color channel to a common target brightness. The camera
+//! response is assumed to be linear.
+
+use nalgebra::geometry::Point2;
+use ncollide2d::query::point_query::PointQuery;
+
+use crate::{
+    error::Error,
+    structured_light::{camera_ray, intersect_display, project_in_front, Correspondences},
+    DisplayGeometry, FloatImage, Result, VDispInfo,
+};
+
+/// A projector with its fit pinhole and texture coordinate map
+pub struct Projector<'a> {
+    pub camera: &'a mvg::Camera<f64>,
+    pub vdisp: &'a VDispInfo,
+}
+
+impl Projector<'_> {
+    /// Which pixels of the projector illuminate the display.
+    fn valid_pixels(&self) -> Result<Vec<bool>> {
+        let (width, height) = (self.camera.width(), self.camera.height());
+        let (mask, texcoords, nchan) = self.vdisp;
+        if texcoords.len() != width * height * nchan {
+            return Err(Error::ImageSizeMismatch);
+        }
+        let m = nalgebra::geometry::Isometry::identity();
+        let mut valid = Vec::with_capacity(width * height);
+        for row in 0..height {
+            for col in 0..width {
+                let cur_pos = Point2::new(col as f64, row as f64);
+                let u = texcoords[(row * width + col) * nchan];
+                valid.push(!u.is_nan() && mask.distance_to_point(&m, &cur_pos, true) < 1.0);
+            }
+        }
+        Ok(valid)
+    }
+}
+
+/// Distance (in pixels) of each valid pixel to the nearest invalid pixel or
+/// the image border, computed with a two-pass chamfer transform.
+fn distance_to_edge(valid: &[bool], width: usize, height: usize) -> Vec<f64> {
+    const DIAG: f64 = std::f64::consts::SQRT_2;
+    let mut dist: Vec<f64> = valid
+        .iter()
+        .map(|v| if *v { f64::INFINITY } else { 0.0 })
+        .collect();
+    let get = |dist: &[f64], x: usize, y: usize, dx: isize, dy: isize| -> f64 {
+        let (x, y) = (x as isize + dx, y as isize + dy);
+        if x < 0 || y < 0 || x >= width as isize || y >= height as isize {
+            0.0
+        } else {
+            dist[y as usize * width + x as usize]
+        }
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            if dist[i] == 0.0 {
+                continue;
+            }
+            let d = (get(&dist, x, y, -1, 0) + 1.0)
+                .min(get(&dist, x, y, 0, -1) + 1.0)
+                .min(get(&dist, x, y, -1, -1) + DIAG)
+                .min(get(&dist, x, y, 1, -1) + DIAG);
+            dist[i] = dist[i].min(d);
+        }
+    }
+    for y in (0..height).rev() {
+        for x in (0..width).rev() {
+            let i = y * width + x;
+            if dist[i] == 0.0 {
+                continue;
+            }
+            let d = (get(&dist, x, y, 1, 0) + 1.0)
+                .min(get(&dist, x, y, 0, 1) + 1.0)
+                .min(get(&dist, x, y, 1, 1) + DIAG)
+                .min(get(&dist, x, y, -1, 1) + DIAG);
+            dist[i] = dist[i].min(d);
+        }
+    }
+    dist
+}
+
+/// Convert a linear intensity factor into a projector input value factor.
+fn apply_gamma(linear: f64, gamma: f64) -> f64 {
+    linear.max(0.0).powf(1.0 / gamma)
+}
+
+/// Alpha blending mask of a projector
+#[derive(Debug, Clone)]
+pub struct BlendMask {
+    width: usize,
+    height: usize,
+    /// Linear alpha of each pixel (row-major), 0.0 where the projector does
+    /// not illuminate the display
+    alpha: Vec<f64>,
+}
+
+impl BlendMask {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The linear alpha of a projector pixel.
+    pub fn alpha(&self, x: usize, y: usize) -> f64 {
+        self.alpha[y * self.width + x]
+    }
+
+    /// The mask as projector input value factors (identical R, G and B).
+    pub fn to_float_image(&self, gamma: f64) -> FloatImage {
+        let pixels = self
+            .alpha
+            .iter()
+            .map(|a| {
+                let v = apply_gamma(*a, gamma);
+                (v, v, v)
+            })
+            .collect();
+        FloatImage {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+
+    /// Multiply the blending (blue) channel of a calibration image.
+    ///
+    /// `cal_image` is a FreemoVR calibration image of this projector, e.g.
+    /// from [crate::fit_pinholes_compute_cal_image].
+    pub fn apply(&self, cal_image: &mut FloatImage, gamma: f64) -> Result<()> {
+        if cal_image.width != self.width || cal_image.height != self.height {
+            return Err(Error::ImageSizeMismatch);
+        }
+        for (px, a) in cal_image.pixels.iter_mut().zip(self.alpha.iter()) {
+            // Negative values mark pixels without texture coordinates.
+            if px.2 > 0.0 {
+                px.2 *= apply_gamma(*a, gamma);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compute the alpha blending mask of each projector.
+///
+/// The projectors must be calibrated on the same display geometry `geom`.
+/// Occlusion of one projector by the display is not considered.
+pub fn compute_blend_masks(
+    projectors: &[Projector],
+    geom: &dyn DisplayGeometry,
+) -> Result<Vec<BlendMask>> {
+    let valid = projectors
+        .iter()
+        .map(|p| p.valid_pixels())
+        .collect::<Result<Vec<_>>>()?;
+    let dists: Vec<Vec<f64>> = projectors
+        .iter()
+        .zip(valid.iter())
+        .map(|(p, v)| distance_to_edge(v, p.camera.width(), p.camera.height()))
+        .collect();
+
+    let mut result = Vec::with_capacity(projectors.len());
+    for (i, projector) in projectors.iter().enumerate() {
+        let (width, height) = (projector.camera.width(), projector.camera.height());
+        let mut alpha = vec![0.0; width * height];
+        let mut n_overlap = 0;
+        for y in 0..height {
+            for x in 0..width {
+                let idx = y * width + x;
+                if !valid[i][idx] {
+                    continue;
+                }
+                let own = dists[i][idx];
+                let ray = camera_ray(projector.camera, Point2::new(x as f64, y as f64));
+                let Some((wc, _tc)) = intersect_display(geom, &ray) else {
+                    alpha[idx] = 1.0;
+                    continue;
+                };
+                let mut total = own;
+                for (j, other) in projectors.iter().enumerate() {
+                    if j == i {
+                        continue;
+                    }
+                    let Some(px) = project_in_front(other.camera, wc) else {
+                        continue;
+                    };
+                    let (ox, oy) = (px.x.round(), px.y.round());
+                    if ox < 0.0
+                        || oy < 0.0
+                        || ox >= other.camera.width() as f64
+                        || oy >= other.camera.height() as f64
+                    {
+                        continue;
+                    }
+                    let other_idx = oy as usize * other.camera.width() + ox as usize;
+                    if valid[j][other_idx] {
+                        total += dists[j][other_idx];
+                    }
+                }
+                if total > own {
+                    n_overlap += 1;
+                }
+                alpha[idx] = own / total;
+            }
+        }
+        info!("projector {}: {} pixels in overlap regions", i, n_overlap);
+        result.push(BlendMask {
+            width,
+            height,
+            alpha,
+        });
+    }
+    Ok(result)
+}
+
+/// Brightness of a projector measured by a camera
+#[derive(Debug, Clone)]
+pub struct BrightnessMap {
+    width: usize,
+    height: usize,
+    /// Smoothed RGB brightness of each projector pixel (row-major), if
+    /// measured
+    rgb: Vec<Option<[f64; 3]>>,
+}
+
+impl BrightnessMap {
+    /// The measured brightness of a projector pixel.
+    pub fn get(&self, x: usize, y: usize) -> Option<[f64; 3]> {
+        self.rgb[y * self.width + x]
+    }
+}
+
+/// Measure the brightness of a projector from a camera capture.
+///
+/// `capture_rgb` is a row-major RGB8 camera image of the projector showing
+/// full white (with all other projectors dark) and `correspondences` are the
+/// decoded structured light correspondences of the same camera and
+/// projector. The measurements are averaged in square cells of `cell_size`
+/// projector pixels and interpolated bilinearly between cell centers.
+pub fn measure_brightness(
+    correspondences: &Correspondences,
+    capture_rgb: &[u8],
+    proj_width: usize,
+    proj_height: usize,
+    cell_size: usize,
+) -> Result<BrightnessMap> {
+    let cam_width = correspondences.cam_width();
+    if capture_rgb.len() != cam_width * correspondences.cam_height() * 3 {
+        return Err(Error::InvalidCaptureSize);
+    }
+    if proj_width == 0 || proj_height == 0 {
+        return Err(Error::ImageSizeMismatch);
+    }
+    let cell_size = cell_size.max(1);
+    let nx = proj_width.div_ceil(cell_size);
+    let ny = proj_height.div_ceil(cell_size);
+
+    // (count, sum of RGB)
+    let mut cells = vec![(0usize, [0.0; 3]); nx * ny];
+    for (cam_px, proj_px) in correspondences.iter() {
+        let (px, py) = (proj_px.x.round(), proj_px.y.round());
+        if px < 0.0 || py < 0.0 || px >= proj_width as f64 || py >= proj_height as f64 {
+            continue;
+        }
+        let cell = &mut cells[(py as usize / cell_size) * nx + px as usize / cell_size];
+        let start = (cam_px.y as usize * cam_width + cam_px.x as usize) * 3;
+        cell.0 += 1;
+        for (sum, value) in cell.1.iter_mut().zip(&capture_rgb[start..start + 3]) {
+            *sum += *value as f64;
+        }
+    }
+    let cell_means: Vec<Option<[f64; 3]>> = cells
+        .iter()
+        .map(|(count, sum)| {
+            if *count == 0 {
+                None
+            } else {
+                Some(sum.map(|s| s / *count as f64))
+            }
+        })
+        .collect();
+
+    // Bilinear interpolation between cell centers, using only measured cells.
+    let half = (cell_size as f64 - 1.0) / 2.0;
+    let mut rgb = Vec::with_capacity(proj_width * proj_height);
+    for y in 0..proj_height {
+        let cy = ((y as f64 - half) / cell_size as f64).clamp(0.0, (ny - 1) as f64);
+        let (cy0, fy) = (cy.floor() as usize, cy.fract());
+        let cy1 = (cy0 + 1).min(ny - 1);
+        for x in 0..proj_width {
+            let cx = ((x as f64 - half) / cell_size as f64).clamp(0.0, (nx - 1) as f64);
+            let (cx0, fx) = (cx.floor() as usize, cx.fract());
+            let cx1 = (cx0 + 1).min(nx - 1);
+            let mut weight_sum = 0.0;
+            let mut value = [0.0; 3];
+            for (cell_x, cell_y, weight) in [
+                (cx0, cy0, (1.0 - fx) * (1.0 - fy)),
+                (cx1, cy0, fx * (1.0 - fy)),
+                (cx0, cy1, (1.0 - fx) * fy),
+                (cx1, cy1, fx * fy),
+            ] {
+                if let Some(mean) = cell_means[cell_y * nx + cell_x] {
+                    weight_sum += weight;
+                    for (v, m) in value.iter_mut().zip(mean.iter()) {
+                        *v += weight * m;
+                    }
+                }
+            }
+            if weight_sum > 0.0 {
+                rgb.push(Some(value.map(|v| v / weight_sum)));
+            } else {
+                rgb.push(None);
+            }
+        }
+    }
+    Ok(BrightnessMap {
+        width: proj_width,
+        height: proj_height,
+        rgb,
+    })
+}
+
+/// Per-channel brightness gains of a projector
+#[derive(Debug, Clone)]
+pub struct GainMap {
+    width: usize,
+    height: usize,
+    /// Linear RGB gain of each pixel (row-major), at most 1.0
+    gain: Vec<[f64; 3]>,
+}
+
+impl GainMap {
+    /// The linear gain of a projector pixel.
+    pub fn get(&self, x: usize, y: usize) -> [f64; 3] {
+        self.gain[y * self.width + x]
+    }
+}
+
+/// Compute gains equalizing the brightness and color of all projectors.
+///
+/// The target brightness of each color channel is the `quantile` (e.g. 0.05)
+/// of all measured brightnesses, so that dim regions are not amplified
+/// beyond what the projectors can show. Pixels without measurement keep a
+/// gain of 1.0.
+pub fn uniformity_gains(maps: &[BrightnessMap], quantile: f64) -> Vec<GainMap> {
+    let target: [f64; 3] = std::array::from_fn(|chan| {
+        let mut values: Vec<f64> = maps
+            .iter()
+            .flat_map(|m| m.rgb.iter().filter_map(|v| v.map(|v| v[chan])))
+            .collect();
+        if values.is_empty() {
+            return f64::NAN;
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let idx = ((values.len() - 1) as f64 * quantile.clamp(0.0, 1.0)).round() as usize;
+        values[idx]
+    });
+    info!("target brightness for uniformity correction: {:?}", target);
+
+    maps.iter()
+        .map(|m| {
+            let gain = m
+                .rgb
+                .iter()
+                .map(|v| match v {
+                    Some(v) => std::array::from_fn(|chan| {
+                        if v[chan] > 0.0 {
+                            (target[chan] / v[chan]).min(1.0)
+                        } else {
+                            1.0
+                        }
+                    }),
+                    None => [1.0; 3],
+                })
+                .collect();
+            GainMap {
+                width: m.width,
+                height: m.height,
+                gain,
+            }
+        })
+        .collect()
+}
+
+/// The combined blending and photometric correction of a projector.
+///
+/// Each RGB value is the factor by which the projector input value must be
+/// multiplied. Save with [crate::ExrWriter].
+pub fn correction_image(
+    blend: &BlendMask,
+    gains: Option<&GainMap>,
+    gamma: f64,
+) -> Result<FloatImage> {
+    if let Some(gains) = gains {
+        if gains.width != blend.width || gains.height != blend.height {
+            return Err(Error::ImageSizeMismatch);
+        }
+    }
+    let pixels = blend
+        .alpha
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let g = gains.map(|g| g.gain[i]).unwrap_or([1.0; 3]);
+            (
+                apply_gamma(a * g[0], gamma),
+                apply_gamma(a * g[1], gamma),
+                apply_gamma(a * g[2], gamma),
+            )
+        })
+        .collect();
+    Ok(FloatImage {
+        width: blend.width,
+        height: blend.height,
+        pixels,
+    })
+}
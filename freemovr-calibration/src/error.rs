@@ -40,9 +40,9 @@ pub enum Error {
     },
     #[cfg(feature = "opencv")]
     #[error("{source}")]
-    OpenCvCalibrate {
+    Camcal {
         #[from]
-        source: opencv_calibrate::Error,
+        source: camcal::Error,
         #[cfg(feature = "backtrace")]
         backtrace: std::backtrace::Backtrace,
     },
@@ -69,6 +69,16 @@ pub enum Error {
     Csv(#[from] csv::Error),
     #[error("svd error: {0}")]
     SvdError(&'static str),
+    #[error("invalid pattern sequence: {0}")]
+    InvalidPatternSequence(&'static str),
+    #[error("expected {expected} captured images, got {actual}")]
+    WrongNumberOfCaptures { expected: usize, actual: usize },
+    #[error("captured image has wrong size")]
+    InvalidCaptureSize,
+    #[error("too few correspondences: {0}")]
+    TooFewCorrespondences(usize),
+    #[error("image size mismatch")]
+    ImageSizeMismatch,
     #[error(transparent)]
     Other(
         #[from]
@@ -10,9 +10,11 @@ use anyhow::Context;
 
 use nalgebra::geometry::{Point2, Point3};
 
+pub mod blending;
 mod error;
 mod exr;
 pub mod pinhole_wizard_yaml_support;
+pub mod structured_light;
 mod trimesh_ext;
 use trimesh_ext::FaceIndices;
 
@@ -224,6 +226,27 @@ impl PinholeCalData {
             geom,
         })
     }
+
+    /// Create from an already fit pinhole of a single fullscreen display.
+    pub(crate) fn from_fit(
+        display: crate::types::SimpleDisplay,
+        geom: TriMeshGeom,
+        uv_display_points: Vec<crate::types::SimpleUVCorrespondance>,
+        cam: mvg::Camera<f64>,
+    ) -> Self {
+        let data = crate::pinhole_wizard_yaml_support::SimplePinholeNoFile {
+            display,
+            uv_display_points,
+        };
+        let name = types::VirtualDisplayName(
+            crate::pinhole_wizard_yaml_support::DEFAULT_VDISP.to_string(),
+        );
+        Self {
+            data,
+            pinhole_fits: vec![(name, cam)],
+            geom,
+        }
+    }
 }
 
 // Implements `PinholeCal` trait in a way that requires disk access.
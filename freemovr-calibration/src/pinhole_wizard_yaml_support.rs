@@ -15,7 +15,7 @@ use std::path::Path;
 
 use crate::trimesh_ext::FaceIndices;
 
-const DEFAULT_VDISP: &'static str = "fullscreen";
+pub(crate) const DEFAULT_VDISP: &'static str = "fullscreen";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
@@ -489,25 +489,35 @@ pub fn solve_no_distortion_display_camera<D: PinholeCalib>(
             this_vdisp_corr.len()
         );
 
-        let dlt_pmat =
-            dlt::dlt_corresponding(&this_vdisp_corr, epsilon).map_err(|e| Error::SvdError(e))?;
-
-        // println!("pmat: {}", pretty_print_nalgebra::pretty_print!(&dlt_pmat));
-
-        let cam1 = mvg::Camera::from_pmat(data.width(), data.height(), &dlt_pmat)?;
-        let cam2 = cam1.flip().expect("flip camera");
-
-        // take whichever camera points towards objects
-        let cam = if mean_forward(&cam1, &this_vdisp_corr) > mean_forward(&cam2, &this_vdisp_corr) {
-            cam1
-        } else {
-            cam2
-        };
+        let cam = fit_display_camera(data.width(), data.height(), &this_vdisp_corr, epsilon)?;
         result.push((vdisp.id.clone(), cam));
     }
     Ok(result)
 }
 
+/// Solve (using DLT) the pinhole camera of a display from corresponding points
+pub(crate) fn fit_display_camera(
+    width: usize,
+    height: usize,
+    corr: &[dlt::CorrespondingPoint<f64>],
+    epsilon: f64,
+) -> Result<mvg::Camera<f64>> {
+    let dlt_pmat = dlt::dlt_corresponding(corr, epsilon).map_err(|e| Error::SvdError(e))?;
+
+    // println!("pmat: {}", pretty_print_nalgebra::pretty_print!(&dlt_pmat));
+
+    let cam1 = mvg::Camera::from_pmat(width, height, &dlt_pmat)?;
+    let cam2 = cam1.flip().expect("flip camera");
+
+    // take whichever camera points towards objects
+    let cam = if mean_forward(&cam1, corr) > mean_forward(&cam2, corr) {
+        cam1
+    } else {
+        cam2
+    };
+    Ok(cam)
+}
+
 fn mean_forward(cam: &mvg::Camera<f64>, pts: &[dlt::CorrespondingPoint<f64>]) -> f64 {
     use mvg::PointWorldFrame;
     let mut accum = 0.0;
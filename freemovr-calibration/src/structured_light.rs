@@ -0,0 +1,519 @@
+//! Automatic display calibration with structured light.
+//!
+//! Each projector shows a [PatternSequence] of Gray code patterns, optionally
+//! followed by phase-shifted sinusoids for sub-pixel precision. A calibrated
+//! camera viewing the display surface captures every pattern. Decoding the
+//! captures gives, for each camera pixel, the projector pixel which illuminates
+//! it. Casting the camera ray onto the display geometry then gives the world
+//! and texture coordinates seen by that projector pixel, from which the
+//! projector pinhole is fit with DLT (see [fit_display]).
+
+use nalgebra::geometry::{Point2, Point3};
+
+use crate::{
+    error::Error,
+    pinhole_wizard_yaml_support::fit_display_camera,
+    types::{SimpleDisplay, SimpleUVCorrespondance},
+    DisplayGeometry, PinholeCal, PinholeCalData, Result, TriMeshGeom, VDispInfo,
+};
+
+/// Minimum number of correspondences required to fit a projector.
+const MIN_FIT_POINTS: usize = 6;
+
+/// Axis of the projector image encoded by a pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+/// Sinusoidal phase-shift patterns shown after the Gray code patterns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseShift {
+    /// Period of the sinusoid in projector pixels (at least 2)
+    pub period: usize,
+    /// Number of phase steps for each axis (at least 3)
+    pub steps: usize,
+}
+
+/// A single pattern of a [PatternSequence]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// Fully illuminated reference
+    White,
+    /// Unilluminated reference
+    Black,
+    /// One bit of the Gray code of the projector column or row
+    ///
+    /// Bit 0 is the most significant bit. Each bit is shown normally and
+    /// inverted, so that decoding does not depend on a global threshold.
+    Gray {
+        axis: Axis,
+        bit: u32,
+        inverted: bool,
+    },
+    /// One step of the phase-shifted sinusoid along an axis
+    Phase { axis: Axis, step: usize },
+}
+
+/// The patterns to show on a projector of a given size
+#[derive(Debug, Clone)]
+pub struct PatternSequence {
+    width: usize,
+    height: usize,
+    phase_shift: Option<PhaseShift>,
+    patterns: Vec<Pattern>,
+}
+
+/// Number of bits needed to encode `size` different values.
+fn n_bits(size: usize) -> u32 {
+    (usize::BITS - size.saturating_sub(1).leading_zeros()).max(1)
+}
+
+fn gray_to_binary(gray: usize) -> usize {
+    let mut binary = gray;
+    let mut shift = gray >> 1;
+    while shift != 0 {
+        binary ^= shift;
+        shift >>= 1;
+    }
+    binary
+}
+
+impl PatternSequence {
+    /// Create the patterns for a projector with `width` x `height` pixels.
+    pub fn new(width: usize, height: usize, phase_shift: Option<PhaseShift>) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidPatternSequence("empty projector image"));
+        }
+        if let Some(ps) = &phase_shift {
+            if ps.period < 2 {
+                return Err(Error::InvalidPatternSequence(
+                    "phase shift period must be at least 2 pixels",
+                ));
+            }
+            if ps.steps < 3 {
+                return Err(Error::InvalidPatternSequence(
+                    "phase shift needs at least 3 steps",
+                ));
+            }
+        }
+
+        let mut patterns = vec![Pattern::White, Pattern::Black];
+        for (axis, size) in [(Axis::X, width), (Axis::Y, height)] {
+            for bit in 0..n_bits(size) {
+                for inverted in [false, true] {
+                    patterns.push(Pattern::Gray {
+                        axis,
+                        bit,
+                        inverted,
+                    });
+                }
+            }
+        }
+        if let Some(ps) = &phase_shift {
+            for axis in [Axis::X, Axis::Y] {
+                for step in 0..ps.steps {
+                    patterns.push(Pattern::Phase { axis, step });
+                }
+            }
+        }
+        Ok(Self {
+            width,
+            height,
+            phase_shift,
+            patterns,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The patterns in the order they must be shown and captured.
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    fn axis_size(&self, axis: Axis) -> usize {
+        match axis {
+            Axis::X => self.width,
+            Axis::Y => self.height,
+        }
+    }
+
+    /// Render a pattern as a row-major 8-bit grayscale projector image.
+    pub fn render(&self, pattern: &Pattern) -> Vec<u8> {
+        let value = |x: usize, y: usize| -> u8 {
+            match *pattern {
+                Pattern::White => 255,
+                Pattern::Black => 0,
+                Pattern::Gray {
+                    axis,
+                    bit,
+                    inverted,
+                } => {
+                    let pos = match axis {
+                        Axis::X => x,
+                        Axis::Y => y,
+                    };
+                    let gray = pos ^ (pos >> 1);
+                    let shift = n_bits(self.axis_size(axis)) - 1 - bit;
+                    let on = (gray >> shift) & 1 == 1;
+                    if on != inverted {
+                        255
+                    } else {
+                        0
+                    }
+                }
+                Pattern::Phase { axis, step } => {
+                    // Only constructed when phase shift patterns are present.
+                    let ps = self.phase_shift.unwrap();
+                    let pos = match axis {
+                        Axis::X => x,
+                        Axis::Y => y,
+                    };
+                    let phase = std::f64::consts::TAU
+                        * (pos as f64 / ps.period as f64 - step as f64 / ps.steps as f64);
+                    (127.5 + 127.5 * phase.cos()).round() as u8
+                }
+            }
+        };
+        let mut result = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                result.push(value(x, y));
+            }
+        }
+        result
+    }
+
+    /// Decode camera captures of all patterns into correspondences.
+    ///
+    /// `captures` are row-major 8-bit grayscale camera images in the order of
+    /// [Self::patterns]. Camera pixels where the difference between the
+    /// white and black reference images is below `min_contrast` are not
+    /// illuminated by the projector and are left without correspondence.
+    pub fn decode(
+        &self,
+        cam_width: usize,
+        cam_height: usize,
+        captures: &[&[u8]],
+        min_contrast: u8,
+    ) -> Result<Correspondences> {
+        if captures.len() != self.patterns.len() {
+            return Err(Error::WrongNumberOfCaptures {
+                expected: self.patterns.len(),
+                actual: captures.len(),
+            });
+        }
+        let n_pixels = cam_width * cam_height;
+        if captures.iter().any(|c| c.len() != n_pixels) {
+            return Err(Error::InvalidCaptureSize);
+        }
+        let capture_of = |pattern: &Pattern| -> &[u8] {
+            let idx = self.patterns.iter().position(|p| p == pattern).unwrap();
+            captures[idx]
+        };
+        let white = capture_of(&Pattern::White);
+        let black = capture_of(&Pattern::Black);
+
+        // The captures of the Gray code bits (normal and inverted) and the
+        // phase steps for each axis.
+        let axis_captures = |axis: Axis| {
+            let gray: Vec<(&[u8], &[u8])> = (0..n_bits(self.axis_size(axis)))
+                .map(|bit| {
+                    let normal = capture_of(&Pattern::Gray {
+                        axis,
+                        bit,
+                        inverted: false,
+                    });
+                    let inverted = capture_of(&Pattern::Gray {
+                        axis,
+                        bit,
+                        inverted: true,
+                    });
+                    (normal, inverted)
+                })
+                .collect();
+            let phase: Vec<&[u8]> = self
+                .phase_shift
+                .iter()
+                .flat_map(|ps| {
+                    (0..ps.steps).map(move |step| capture_of(&Pattern::Phase { axis, step }))
+                })
+                .collect();
+            (gray, phase)
+        };
+        let x_captures = axis_captures(Axis::X);
+        let y_captures = axis_captures(Axis::Y);
+
+        let decode_axis = |axis: Axis, i: usize| -> Option<f64> {
+            let size = self.axis_size(axis);
+            let (gray_captures, phase_captures) = match axis {
+                Axis::X => &x_captures,
+                Axis::Y => &y_captures,
+            };
+            let mut gray = 0;
+            for (normal, inverted) in gray_captures.iter() {
+                gray = (gray << 1) | usize::from(normal[i] > inverted[i]);
+            }
+            let coarse = gray_to_binary(gray);
+            if coarse >= size {
+                return None;
+            }
+            let coarse = coarse as f64;
+            let Some(ps) = &self.phase_shift else {
+                return Some(coarse);
+            };
+
+            // Phase of the sinusoid at this pixel, which is unwrapped with the
+            // Gray code position.
+            let (mut sum_sin, mut sum_cos) = (0.0, 0.0);
+            for (step, capture) in phase_captures.iter().enumerate() {
+                let delta = std::f64::consts::TAU * step as f64 / ps.steps as f64;
+                sum_sin += capture[i] as f64 * delta.sin();
+                sum_cos += capture[i] as f64 * delta.cos();
+            }
+            let phase = sum_sin.atan2(sum_cos).rem_euclid(std::f64::consts::TAU);
+            let period = ps.period as f64;
+            let frac = phase / std::f64::consts::TAU * period;
+            let fine = frac + period * ((coarse - frac) / period).round();
+            if (fine - coarse).abs() > 1.0 {
+                // Inconsistent with the Gray code, e.g. due to low modulation.
+                Some(coarse)
+            } else {
+                Some(fine)
+            }
+        };
+
+        let projector = (0..n_pixels)
+            .map(|i| {
+                if white[i].saturating_sub(black[i]) < min_contrast {
+                    return None;
+                }
+                let x = decode_axis(Axis::X, i)?;
+                let y = decode_axis(Axis::Y, i)?;
+                Some(Point2::new(x, y))
+            })
+            .collect();
+
+        Ok(Correspondences {
+            cam_width,
+            cam_height,
+            projector,
+        })
+    }
+}
+
+/// Dense correspondences between camera and projector pixels
+#[derive(Debug, Clone)]
+pub struct Correspondences {
+    cam_width: usize,
+    cam_height: usize,
+    /// Projector pixel for each camera pixel (row-major)
+    projector: Vec<Option<Point2<f64>>>,
+}
+
+impl Correspondences {
+    pub fn cam_width(&self) -> usize {
+        self.cam_width
+    }
+
+    pub fn cam_height(&self) -> usize {
+        self.cam_height
+    }
+
+    /// The projector pixel illuminating camera pixel (`x`, `y`), if any.
+    pub fn get(&self, x: usize, y: usize) -> Option<Point2<f64>> {
+        self.projector[y * self.cam_width + x]
+    }
+
+    /// Iterate over (camera pixel, projector pixel) pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (Point2<f64>, Point2<f64>)> + '_ {
+        self.projector.iter().enumerate().filter_map(|(i, p)| {
+            let cam = Point2::new((i % self.cam_width) as f64, (i / self.cam_width) as f64);
+            p.map(|p| (cam, p))
+        })
+    }
+
+    /// Number of camera pixels with a correspondence
+    pub fn n_valid(&self) -> usize {
+        self.projector.iter().filter(|p| p.is_some()).count()
+    }
+}
+
+/// The ray from the camera center through a (distorted) camera pixel.
+pub(crate) fn camera_ray(
+    cam: &mvg::Camera<f64>,
+    coords: Point2<f64>,
+) -> ncollide3d::query::Ray<f64> {
+    let center = cam.extrinsics().camcenter();
+    let cam_px = mvg::DistortedPixel { coords };
+    let world_coord = cam.project_distorted_pixel_to_3d_with_dist(&cam_px, 1.0);
+    let dir = world_coord.coords - center;
+    ncollide3d::query::Ray::new(*center, dir)
+}
+
+/// The world and texture coordinates where a camera ray hits the display.
+pub(crate) fn intersect_display(
+    geom: &dyn DisplayGeometry,
+    ray: &ncollide3d::query::Ray<f64>,
+) -> Option<(Point3<f64>, Point2<f64>)> {
+    let eye = nalgebra::Isometry3::identity();
+    let hit = geom
+        .ncollide_shape()
+        .toi_and_normal_and_uv_with_ray(&eye, ray, f64::MAX, true)?;
+    Some((ray.point_at(hit.toi), hit.uvs?))
+}
+
+/// Project a world point to a (distorted) pixel if it is in front of the camera.
+pub(crate) fn project_in_front(cam: &mvg::Camera<f64>, wc: Point3<f64>) -> Option<Point2<f64>> {
+    let wc = mvg::PointWorldFrame { coords: wc };
+    let wc2b: cam_geom::Points<_, _, nalgebra::U1, _> = (&wc).into();
+    let cam_frame = cam.extrinsics().world_to_camera(&wc2b);
+    if cam_frame.data[(0, 2)] <= 0.0 {
+        return None;
+    }
+    Some(cam.project_3d_to_distorted_pixel(&wc).coords)
+}
+
+/// The result of fitting a projector to structured light correspondences
+pub struct StructuredLightFit {
+    /// Fit pinhole with a single fullscreen virtual display
+    pub cal: PinholeCalData,
+    /// Number of correspondences used for the fit
+    pub n_points: usize,
+    /// Mean distance (in projector pixels) between the decoded projector
+    /// pixels and the reprojection of their world coordinates
+    pub mean_reproj_dist: f64,
+}
+
+impl StructuredLightFit {
+    /// The fit projector pinhole
+    pub fn camera(&self) -> &mvg::Camera<f64> {
+        &self.cal.pinhole_fits()[0].1
+    }
+
+    /// Compute the texture coordinate of every projector pixel.
+    pub fn compute_vdisp_info(&self) -> Result<VDispInfo> {
+        let mut vdisps = crate::compute_vdisp_images(&self.cal, false, false)?;
+        Ok(vdisps.remove(0))
+    }
+}
+
+/// Fit the projector pinhole from decoded structured light correspondences.
+///
+/// `camera` is the calibrated camera which captured the patterns and `geom`
+/// is the display surface. At most `max_points` correspondences, evenly
+/// subsampled, are used for the DLT.
+pub fn fit_display(
+    sequence: &PatternSequence,
+    correspondences: &Correspondences,
+    camera: &mvg::Camera<f64>,
+    geom: TriMeshGeom,
+    max_points: usize,
+    epsilon: f64,
+) -> Result<StructuredLightFit> {
+    let n_valid = correspondences.n_valid();
+    let stride = n_valid.div_ceil(max_points.max(1)).max(1);
+
+    let mut dlt_points = Vec::new();
+    let mut uv_display_points = Vec::new();
+    for (cam_px, proj_px) in correspondences.iter().step_by(stride) {
+        let ray = camera_ray(camera, cam_px);
+        let Some((wc, tc)) = intersect_display(&geom, &ray) else {
+            continue;
+        };
+        dlt_points.push(dlt::CorrespondingPoint {
+            object_point: [wc.x, wc.y, wc.z],
+            image_point: [proj_px.x, proj_px.y],
+        });
+        uv_display_points.push(SimpleUVCorrespondance {
+            display_x: proj_px.x,
+            display_y: proj_px.y,
+            texture_u: tc.x,
+            texture_v: tc.y,
+        });
+    }
+    if dlt_points.len() < MIN_FIT_POINTS {
+        return Err(Error::TooFewCorrespondences(dlt_points.len()));
+    }
+    info!(
+        "fitting projector with {} of {} structured light correspondences",
+        dlt_points.len(),
+        n_valid
+    );
+
+    let (width, height) = (sequence.width(), sequence.height());
+    let cam = fit_display_camera(width, height, &dlt_points, epsilon)?;
+
+    let sum_dist: f64 = dlt_points
+        .iter()
+        .map(|pt| {
+            let wc = mvg::PointWorldFrame {
+                coords: Point3::from(pt.object_point),
+            };
+            let proj = cam.project_3d_to_pixel(&wc);
+            let [x, y] = pt.image_point;
+            (proj.coords - Point2::new(x, y)).norm()
+        })
+        .sum();
+    let n_points = dlt_points.len();
+
+    let display = SimpleDisplay { width, height };
+    let cal = PinholeCalData::from_fit(display, geom, uv_display_points, cam);
+    Ok(StructuredLightFit {
+        cal,
+        n_points,
+        mean_reproj_dist: sum_dist / n_points as f64,
+    })
+}
+
+/// Render the image a camera captures of a pattern shown by a projector.
+///
+/// This is intended for testing with synthetic data. The display surface is
+/// a uniform diffuse reflector without ambient light, the pattern is sampled
+/// bilinearly and occlusion of the projector by the display is ignored. The
+/// result is a row-major 8-bit grayscale camera image.
+pub fn render_capture(
+    camera: &mvg::Camera<f64>,
+    projector: &mvg::Camera<f64>,
+    geom: &dyn DisplayGeometry,
+    pattern: &[u8],
+) -> Vec<u8> {
+    let (pw, ph) = (projector.width(), projector.height());
+    debug_assert_eq!(pattern.len(), pw * ph);
+    let sample = |x: f64, y: f64| -> f64 {
+        if x < -0.5 || y < -0.5 || x > pw as f64 - 0.5 || y > ph as f64 - 0.5 {
+            return 0.0;
+        }
+        let x = x.clamp(0.0, (pw - 1) as f64);
+        let y = y.clamp(0.0, (ph - 1) as f64);
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(pw - 1), (y0 + 1).min(ph - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+        let p = |x: usize, y: usize| pattern[y * pw + x] as f64;
+        (1.0 - fy) * ((1.0 - fx) * p(x0, y0) + fx * p(x1, y0))
+            + fy * ((1.0 - fx) * p(x0, y1) + fx * p(x1, y1))
+    };
+
+    let mut result = vec![0u8; camera.width() * camera.height()];
+    for camy in 0..camera.height() {
+        for camx in 0..camera.width() {
+            let ray = camera_ray(camera, Point2::new(camx as f64, camy as f64));
+            let Some((wc, _tc)) = intersect_display(geom, &ray) else {
+                continue;
+            };
+            let Some(px) = project_in_front(projector, wc) else {
+                continue;
+            };
+            result[camy * camera.width() + camx] = sample(px.x, px.y).round() as u8;
+        }
+    }
+    result
+}
@@ -0,0 +1,183 @@
+use nalgebra::{
+    geometry::{Point2, Point3},
+    Vector3,
+};
+
+use freemovr_calibration::{
+    blending::{
+        compute_blend_masks, correction_image, measure_brightness, uniformity_gains, Projector,
+    },
+    compute_image_for_camera_view,
+    structured_light::{render_capture, PatternSequence},
+    Computable, TriMeshGeom, VDispInfo,
+};
+
+mod common;
+use common::{flat_screen, make_cam};
+
+fn vdisp_info(cam: &mvg::Camera<f64>, geom: &TriMeshGeom) -> VDispInfo {
+    let (w, h) = (cam.width() as f64, cam.height() as f64);
+    let mask = ncollide_geom::mask_from_points(&[(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)]);
+    let (texcoords, nchan) =
+        compute_image_for_camera_view(cam, Computable::TexCoords, geom, &mask).unwrap();
+    (mask, texcoords, nchan)
+}
+
+/// The point on the screen (z=0) illuminated by a projector pixel.
+fn screen_point(cam: &mvg::Camera<f64>, x: usize, y: usize) -> Point3<f64> {
+    let center = cam.extrinsics().camcenter();
+    let on_ray = cam.project_distorted_pixel_to_3d_with_dist(
+        &mvg::DistortedPixel {
+            coords: Point2::new(x as f64, y as f64),
+        },
+        1.0,
+    );
+    let dir = on_ray.coords - center;
+    center + dir * (-center.z / dir.z)
+}
+
+/// Two projectors side by side, overlapping in the middle of the screen.
+fn two_projectors() -> (mvg::Camera<f64>, mvg::Camera<f64>) {
+    let left = make_cam(
+        64,
+        48,
+        100.0,
+        Vector3::new(-0.5, 0.0, 3.0),
+        Vector3::new(-0.5, 0.0, 0.0),
+    );
+    let right = make_cam(
+        64,
+        48,
+        100.0,
+        Vector3::new(0.5, 0.0, 3.0),
+        Vector3::new(0.5, 0.0, 0.0),
+    );
+    (left, right)
+}
+
+#[test]
+fn blend_masks_sum_to_one() {
+    let geom = flat_screen();
+    let (left, right) = two_projectors();
+    let left_vdisp = vdisp_info(&left, &geom);
+    let right_vdisp = vdisp_info(&right, &geom);
+    let projectors = [
+        Projector {
+            camera: &left,
+            vdisp: &left_vdisp,
+        },
+        Projector {
+            camera: &right,
+            vdisp: &right_vdisp,
+        },
+    ];
+    let masks = compute_blend_masks(&projectors, &geom).unwrap();
+    assert_eq!(masks.len(), 2);
+
+    let y = 24;
+    let mut n_overlap = 0;
+    for x in 0..left.width() {
+        let wc = screen_point(&left, x, y);
+        let alpha = masks[0].alpha(x, y);
+        if wc.x < -1.0 {
+            // off the screen
+            assert_eq!(alpha, 0.0);
+        } else if wc.x < -0.5 {
+            // only the left projector
+            assert_eq!(alpha, 1.0);
+        } else if wc.x > -0.35 && wc.x < 0.35 {
+            let px = right
+                .project_3d_to_pixel(&mvg::PointWorldFrame { coords: wc })
+                .coords;
+            let other_alpha = masks[1].alpha(px.x.round() as usize, px.y.round() as usize);
+            assert!(alpha > 0.0 && alpha < 1.0, "alpha {alpha} at {wc}");
+            assert!(
+                (alpha + other_alpha - 1.0).abs() < 0.05,
+                "alphas {alpha} and {other_alpha} at {wc}"
+            );
+            n_overlap += 1;
+        }
+    }
+    assert!(n_overlap > 10);
+
+    // Gamma is applied in the exported correction image.
+    let gamma = 2.2;
+    let image = correction_image(&masks[0], None, gamma).unwrap();
+    let (x, y) = (40, 24);
+    let expected = masks[0].alpha(x, y).powf(1.0 / gamma);
+    let actual = image.sample(y, x);
+    assert!((actual.0 - expected).abs() < 1e-10);
+    assert!((actual.2 - expected).abs() < 1e-10);
+}
+
+#[test]
+fn photometric_uniformity() {
+    let geom = flat_screen();
+    let camera = make_cam(
+        200,
+        150,
+        150.0,
+        Vector3::new(0.0, 0.0, 4.0),
+        Vector3::new(0.0, 0.0, 0.0),
+    );
+    let (projector, _) = two_projectors();
+    let (pw, ph) = (projector.width(), projector.height());
+
+    let sequence = PatternSequence::new(pw, ph, None).unwrap();
+    let captures: Vec<Vec<u8>> = sequence
+        .patterns()
+        .iter()
+        .map(|pattern| render_capture(&camera, &projector, &geom, &sequence.render(pattern)))
+        .collect();
+    let captures: Vec<&[u8]> = captures.iter().map(|c| c.as_slice()).collect();
+    let correspondences = sequence
+        .decode(camera.width(), camera.height(), &captures, 10)
+        .unwrap();
+
+    // A projector which is brighter on the left and has a weak green channel.
+    let truth = |x: usize| 250.0 - 100.0 * x as f64 / (pw - 1) as f64;
+    let mut white = Vec::with_capacity(pw * ph);
+    for _y in 0..ph {
+        for x in 0..pw {
+            white.push(truth(x).round() as u8);
+        }
+    }
+    let gray_capture = render_capture(&camera, &projector, &geom, &white);
+    let rgb_capture: Vec<u8> = gray_capture
+        .iter()
+        .flat_map(|v| [*v, (*v as f64 * 0.8).round() as u8, *v])
+        .collect();
+
+    let brightness = measure_brightness(&correspondences, &rgb_capture, pw, ph, 8).unwrap();
+    let gains = uniformity_gains(std::slice::from_ref(&brightness), 0.0);
+
+    // Pixels of the projector which illuminate the screen, away from the
+    // image border where the brightness is extrapolated.
+    let y = 24;
+    let xs = (4..pw - 4).filter(|x| screen_point(&projector, *x, y).x > -0.95);
+    let mut target = None;
+    for x in xs {
+        let measured = brightness.get(x, y).unwrap();
+        assert!((measured[0] - truth(x)).abs() < 4.0, "x {x}: {measured:?}");
+        assert!(
+            (measured[1] - 0.8 * truth(x)).abs() < 4.0,
+            "x {x}: {measured:?}"
+        );
+
+        // After correction, all pixels have the same brightness in each
+        // channel.
+        let gain = gains[0].get(x, y);
+        let corrected = [
+            gain[0] * truth(x),
+            gain[1] * 0.8 * truth(x),
+            gain[2] * truth(x),
+        ];
+        let target = *target.get_or_insert(corrected);
+        for (c, t) in corrected.iter().zip(target.iter()) {
+            assert!(
+                (c - t).abs() < 0.04 * t,
+                "x {x}: {corrected:?} vs {target:?}"
+            );
+        }
+    }
+}
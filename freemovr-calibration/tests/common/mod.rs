@@ -0,0 +1,45 @@
+//! Helpers shared by the integration tests.
+
+use nalgebra::{
+    geometry::{Point2, Point3},
+    Vector3,
+};
+
+use freemovr_calibration::TriMeshGeom;
+
+pub fn make_cam(
+    width: usize,
+    height: usize,
+    f: f64,
+    camcenter: Vector3<f64>,
+    lookat: Vector3<f64>,
+) -> mvg::Camera<f64> {
+    let up = nalgebra::core::Unit::new_normalize(Vector3::new(0.0, 1.0, 0.0));
+    let extrinsics = cam_geom::ExtrinsicParameters::from_view(&camcenter, &lookat, &up);
+    let params = cam_geom::PerspectiveParams {
+        fx: f,
+        fy: f,
+        skew: 0.0,
+        cx: width as f64 / 2.0,
+        cy: height as f64 / 2.0,
+    };
+    let intrinsics: cam_geom::IntrinsicParametersPerspective<_> = params.into();
+    mvg::Camera::new(width, height, extrinsics, intrinsics.into()).unwrap()
+}
+
+/// A flat 2x2 screen in the z=0 plane with texture coordinates spanning 0..1.
+pub fn flat_screen() -> TriMeshGeom {
+    let coords = vec![
+        Point3::new(-1.0, -1.0, 0.0),
+        Point3::new(1.0, -1.0, 0.0),
+        Point3::new(1.0, 1.0, 0.0),
+        Point3::new(-1.0, 1.0, 0.0),
+    ];
+    let uvs = coords
+        .iter()
+        .map(|c| Point2::new((c.x + 1.0) / 2.0, (c.y + 1.0) / 2.0))
+        .collect();
+    let indices = vec![Point3::new(0, 1, 2), Point3::new(0, 2, 3)];
+    let mesh = ncollide3d::shape::TriMesh::new(coords, indices, Some(uvs));
+    TriMeshGeom::new(&mesh, None).unwrap()
+}
@@ -0,0 +1,90 @@
+use nalgebra::{
+    geometry::{Point2, Point3},
+    Vector3,
+};
+
+use freemovr_calibration::structured_light::{
+    fit_display, render_capture, PatternSequence, PhaseShift,
+};
+
+mod common;
+use common::{flat_screen, make_cam};
+
+/// Calibrate a projector from synthetic captures of a flat screen and compare
+/// with the true projector.
+fn check_synthetic_calibration(phase_shift: Option<PhaseShift>, max_dist: f64) {
+    let geom = flat_screen();
+    let origin = Vector3::new(0.0, 0.0, 0.0);
+    let camera = make_cam(160, 120, 125.0, Vector3::new(0.0, 0.0, 4.0), origin);
+    let projector = make_cam(64, 48, 50.0, Vector3::new(0.5, 0.3, 3.0), origin);
+
+    let sequence =
+        PatternSequence::new(projector.width(), projector.height(), phase_shift).unwrap();
+    let captures: Vec<Vec<u8>> = sequence
+        .patterns()
+        .iter()
+        .map(|pattern| render_capture(&camera, &projector, &geom, &sequence.render(pattern)))
+        .collect();
+    let captures: Vec<&[u8]> = captures.iter().map(|c| c.as_slice()).collect();
+
+    assert!(sequence
+        .decode(camera.width(), camera.height(), &captures[1..], 10)
+        .is_err());
+    let correspondences = sequence
+        .decode(camera.width(), camera.height(), &captures, 10)
+        .unwrap();
+    assert!(correspondences.n_valid() > 1000);
+
+    let fit = fit_display(&sequence, &correspondences, &camera, geom, 2000, 1e-10).unwrap();
+    assert!(
+        fit.mean_reproj_dist < max_dist,
+        "mean reprojection distance {}",
+        fit.mean_reproj_dist
+    );
+
+    // The fit projector matches the true projector on the screen.
+    for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (0.0, 0.0), (0.5, 0.8)] {
+        let wc = mvg::PointWorldFrame {
+            coords: Point3::new(x, y, 0.0),
+        };
+        let expected = projector.project_3d_to_pixel(&wc).coords;
+        let actual = fit.camera().project_3d_to_pixel(&wc).coords;
+        assert!(
+            (expected - actual).norm() < 2.0 * max_dist,
+            "expected {expected}, actual {actual}"
+        );
+    }
+
+    // The texture coordinates of a projector pixel match the screen point it
+    // illuminates.
+    let (_mask, texcoords, nchan) = fit.compute_vdisp_info().unwrap();
+    assert_eq!(nchan, 2);
+    let (px, py) = (40, 20);
+    let center = projector.extrinsics().camcenter();
+    let on_ray = projector.project_distorted_pixel_to_3d_with_dist(
+        &mvg::DistortedPixel {
+            coords: Point2::new(px as f64, py as f64),
+        },
+        1.0,
+    );
+    let dir = on_ray.coords - center;
+    let wc = center + dir * (-center.z / dir.z);
+    let idx = (py * projector.width() + px) * nchan;
+    let (u, v) = (texcoords[idx], texcoords[idx + 1]);
+    assert!((u - (wc.x + 1.0) / 2.0).abs() < 0.01, "u {u}, world {wc}");
+    assert!((v - (wc.y + 1.0) / 2.0).abs() < 0.01, "v {v}, world {wc}");
+}
+
+#[test]
+fn structured_light_gray_code() {
+    check_synthetic_calibration(None, 0.5);
+}
+
+#[test]
+fn structured_light_phase_shift() {
+    let phase_shift = PhaseShift {
+        period: 16,
+        steps: 4,
+    };
+    check_synthetic_calibration(Some(phase_shift), 0.1);
+}
@@ -31,6 +31,11 @@ struct Settings {
     decimate: f32,
     blur: f32,
     refine_edges: bool,
+    tag_size: f64,
+    fx: f64,
+    fy: f64,
+    cx: f64,
+    cy: f64,
 }
 
 const DEFAULT_FAMILY: TagFamily = TagFamily::Family36h11;
@@ -38,6 +43,11 @@ const DEFAULT_MAXHAMMING: i32 = 1;
 const DEFAULT_DECIMATE: f32 = 2.0;
 const DEFAULT_BLUR: f32 = 0.0;
 const DEFAULT_REFINE_EDGES: bool = true;
+const DEFAULT_TAG_SIZE: f64 = 0.0;
+const DEFAULT_FX: f64 = 0.0;
+const DEFAULT_FY: f64 = 0.0;
+const DEFAULT_CX: f64 = 0.0;
+const DEFAULT_CY: f64 = 0.0;
 
 impl Default for Settings {
     fn default() -> Self {
@@ -47,12 +57,36 @@ impl Default for Settings {
             decimate: DEFAULT_DECIMATE,
             blur: DEFAULT_BLUR,
             refine_edges: DEFAULT_REFINE_EDGES,
+            tag_size: DEFAULT_TAG_SIZE,
+            fx: DEFAULT_FX,
+            fy: DEFAULT_FY,
+            cx: DEFAULT_CX,
+            cy: DEFAULT_CY,
+        }
+    }
+}
+
+impl Settings {
+    /// Return the tag size and camera intrinsics if pose estimation is enabled.
+    fn pose_estimation(&self) -> Option<(f64, apriltag::CameraIntrinsics)> {
+        if self.tag_size > 0.0 && self.fx > 0.0 && self.fy > 0.0 {
+            Some((
+                self.tag_size,
+                apriltag::CameraIntrinsics {
+                    fx: self.fx,
+                    fy: self.fy,
+                    cx: self.cx,
+                    cy: self.cy,
+                },
+            ))
+        } else {
+            None
         }
     }
 }
 
 // Metadata for the properties
-static PROPERTIES: [subclass::Property; 5] = [
+static PROPERTIES: [subclass::Property; 10] = [
     subclass::Property("family", |name| {
         glib::ParamSpec::enum_(
             name,
@@ -105,6 +139,62 @@ static PROPERTIES: [subclass::Property; 5] = [
             glib::ParamFlags::READWRITE,
         )
     }),
+    subclass::Property("tag-size", |name| {
+        glib::ParamSpec::double(
+            name,
+            "Tag size",
+            "Edge length of the tag's black border. If positive (and fx and fy are set), \
+            the tag pose is estimated with translation in the same units.",
+            0.0,
+            std::f64::MAX,
+            DEFAULT_TAG_SIZE,
+            glib::ParamFlags::READWRITE,
+        )
+    }),
+    subclass::Property("fx", |name| {
+        glib::ParamSpec::double(
+            name,
+            "fx",
+            "Camera focal length in x direction (pixels) for pose estimation",
+            0.0,
+            std::f64::MAX,
+            DEFAULT_FX,
+            glib::ParamFlags::READWRITE,
+        )
+    }),
+    subclass::Property("fy", |name| {
+        glib::ParamSpec::double(
+            name,
+            "fy",
+            "Camera focal length in y direction (pixels) for pose estimation",
+            0.0,
+            std::f64::MAX,
+            DEFAULT_FY,
+            glib::ParamFlags::READWRITE,
+        )
+    }),
+    subclass::Property("cx", |name| {
+        glib::ParamSpec::double(
+            name,
+            "cx",
+            "Camera principal point x coordinate (pixels) for pose estimation",
+            std::f64::MIN,
+            std::f64::MAX,
+            DEFAULT_CX,
+            glib::ParamFlags::READWRITE,
+        )
+    }),
+    subclass::Property("cy", |name| {
+        glib::ParamSpec::double(
+            name,
+            "cy",
+            "Camera principal point y coordinate (pixels) for pose estimation",
+            std::f64::MIN,
+            std::f64::MAX,
+            DEFAULT_CY,
+            glib::ParamFlags::READWRITE,
+        )
+    }),
     // TODO add threads, debug as properties?
 ];
 
@@ -114,6 +204,7 @@ struct State {
     write_headers: bool,
     video_info: gst_video::VideoInfo,
     inner: apriltag::Detector,
+    pose_estimation: Option<(f64, apriltag::CameraIntrinsics)>,
 }
 
 // Struct containing all the element data
@@ -218,10 +309,29 @@ struct DetectionSerializer {
     h21: f64,
     // no h22 because it is always 1.0
     family: String,
+    r00: Option<f64>,
+    r01: Option<f64>,
+    r02: Option<f64>,
+    r10: Option<f64>,
+    r11: Option<f64>,
+    r12: Option<f64>,
+    r20: Option<f64>,
+    r21: Option<f64>,
+    r22: Option<f64>,
+    tx: Option<f64>,
+    ty: Option<f64>,
+    tz: Option<f64>,
+    pose_error: Option<f64>,
 }
 
-fn to_serializer(orig: &apriltag::Detection, pts: gst::ClockTime) -> DetectionSerializer {
+fn to_serializer(
+    orig: &apriltag::Detection,
+    pts: gst::ClockTime,
+    pose: Option<&apriltag::Pose>,
+) -> DetectionSerializer {
     let h = orig.h();
+    let r = |i: usize| pose.map(|p| p.rotation[i]);
+    let t = |i: usize| pose.map(|p| p.translation[i]);
     // We are not going to save h22, so (in debug builds) let's check it meets
     // our expectations.
     debug_assert!((h[8] - 1.0).abs() < 1e-16);
@@ -240,6 +350,19 @@ fn to_serializer(orig: &apriltag::Detection, pts: gst::ClockTime) -> DetectionSe
         h20: h[6],
         h21: h[7],
         family: orig.family_type().to_str().to_string(),
+        r00: r(0),
+        r01: r(1),
+        r02: r(2),
+        r10: r(3),
+        r11: r(4),
+        r12: r(5),
+        r20: r(6),
+        r21: r(7),
+        r22: r(8),
+        tx: t(0),
+        ty: t(1),
+        tz: t(2),
+        pose_error: pose.map(|p| p.error),
     }
 }
 
@@ -247,13 +370,16 @@ fn to_csv_lines(
     detections: &[apriltag::Detection],
     write_headers: bool,
     pts: gst::ClockTime,
+    pose_estimation: Option<&(f64, apriltag::CameraIntrinsics)>,
 ) -> gst::Buffer {
     let mut wtr = csv::WriterBuilder::new()
         .has_headers(write_headers)
         .from_writer(Vec::new());
 
     for d in detections.iter() {
-        let d2 = to_serializer(&d, pts);
+        let pose =
+            pose_estimation.map(|(tag_size, intrinsics)| d.estimate_pose(*tag_size, intrinsics));
+        let d2 = to_serializer(&d, pts, pose.as_ref());
         wtr.serialize(d2).expect("serialize");
     }
     let my_bytes = wtr.into_inner().expect("into inner buffer");
@@ -338,7 +464,7 @@ impl AprilTagDetector {
         gst_debug!(CAT, obj: pad, "Handling buffer {:?}", inbuf);
         let pts = inbuf.get_pts();
 
-        let (detections, write_headers) = {
+        let (detections, write_headers, pose_estimation) = {
             let mut state_guard = self.state.lock().unwrap();
             let state = state_guard.as_mut().unwrap();
 
@@ -372,11 +498,16 @@ impl AprilTagDetector {
                 state.write_headers = false;
             }
 
-            (detections, write_headers)
+            (detections, write_headers, state.pose_estimation.clone())
             // drop state_guard here
         };
 
-        let buffer = to_csv_lines(detections.as_slice(), write_headers, pts);
+        let buffer = to_csv_lines(
+            detections.as_slice(),
+            write_headers,
+            pts,
+            pose_estimation.as_ref(),
+        );
 
         self.srcpad.push(buffer).map_err(|err| {
             gst_error!(CAT, obj: element, "Failed to push buffer {:?}", err);
@@ -419,6 +550,7 @@ impl AprilTagDetector {
                         write_headers: true,
                         video_info,
                         inner: make_detector(&settings_guard),
+                        pose_estimation: settings_guard.pose_estimation(),
                     });
                 }
 
@@ -626,6 +758,21 @@ impl ObjectImpl for AprilTagDetector {
             subclass::Property("refine-edges", ..) => {
                 settings.refine_edges = value.get_some().unwrap();
             }
+            subclass::Property("tag-size", ..) => {
+                settings.tag_size = value.get_some().unwrap();
+            }
+            subclass::Property("fx", ..) => {
+                settings.fx = value.get_some().unwrap();
+            }
+            subclass::Property("fy", ..) => {
+                settings.fy = value.get_some().unwrap();
+            }
+            subclass::Property("cx", ..) => {
+                settings.cx = value.get_some().unwrap();
+            }
+            subclass::Property("cy", ..) => {
+                settings.cy = value.get_some().unwrap();
+            }
             _ => unimplemented!(),
         }
 
@@ -634,6 +781,7 @@ impl ObjectImpl for AprilTagDetector {
         let mut state_guard = self.state.lock().unwrap();
         if let Some(state) = state_guard.as_mut() {
             state.inner = make_detector(&settings);
+            state.pose_estimation = settings.pose_estimation();
         }
     }
 
@@ -663,6 +811,26 @@ impl ObjectImpl for AprilTagDetector {
                 let settings = self.settings.lock().unwrap();
                 Ok(settings.refine_edges.to_value())
             }
+            subclass::Property("tag-size", ..) => {
+                let settings = self.settings.lock().unwrap();
+                Ok(settings.tag_size.to_value())
+            }
+            subclass::Property("fx", ..) => {
+                let settings = self.settings.lock().unwrap();
+                Ok(settings.fx.to_value())
+            }
+            subclass::Property("fy", ..) => {
+                let settings = self.settings.lock().unwrap();
+                Ok(settings.fy.to_value())
+            }
+            subclass::Property("cx", ..) => {
+                let settings = self.settings.lock().unwrap();
+                Ok(settings.cx.to_value())
+            }
+            subclass::Property("cy", ..) => {
+                let settings = self.settings.lock().unwrap();
+                Ok(settings.cy.to_value())
+            }
             _ => unimplemented!(),
         }
     }
@@ -0,0 +1,292 @@
+//! Emulation of the LED box firmware, for testing hosts without a device.
+//!
+//! The emulator handles [ToDevice] messages and executes pulse trains on a
+//! virtual device clock which is advanced explicitly, so tests are
+//! deterministic. Edges are executed exactly at their scheduled times.
+
+use std::vec::Vec;
+
+use {
+    supports_pulse_trains, DeviceState, FromDevice, OnState, Sequencer, ToDevice, TriggerEdge,
+    COMM_VERSION,
+};
+
+#[derive(Debug, Clone)]
+pub struct DeviceEmulator {
+    version: u16,
+    state: DeviceState,
+    sequencer: Sequencer,
+    now_us: u64,
+}
+
+impl DeviceEmulator {
+    pub fn new() -> DeviceEmulator {
+        DeviceEmulator::with_version(COMM_VERSION)
+    }
+
+    /// Emulate firmware speaking communication version `version`.
+    ///
+    /// Messages which are not part of this version are dropped without
+    /// response, like the firmware drops messages it cannot deserialize.
+    pub fn with_version(version: u16) -> DeviceEmulator {
+        DeviceEmulator {
+            version,
+            state: DeviceState::default(),
+            sequencer: Sequencer::new(),
+            now_us: 0,
+        }
+    }
+
+    /// The device clock, in microseconds.
+    pub fn now_us(&self) -> u64 {
+        self.now_us
+    }
+
+    /// Handle a message from the host at the current device time.
+    pub fn handle(&mut self, msg: ToDevice) -> Vec<FromDevice> {
+        let pulse_trains = supports_pulse_trains(self.version);
+        match msg {
+            ToDevice::DeviceState(next_state) => {
+                self.state = next_state;
+                vec![FromDevice::StateWasSet]
+            }
+            ToDevice::EchoRequest8(buf) => vec![FromDevice::EchoResponse8(buf)],
+            ToDevice::VersionRequest => vec![FromDevice::VersionResponse(self.version)],
+            ToDevice::LoadPulseTrain(_)
+            | ToDevice::StartPulseTrains(_)
+            | ToDevice::StopPulseTrains
+                if !pulse_trains =>
+            {
+                vec![]
+            }
+            ToDevice::LoadPulseTrain(train) => match self.sequencer.load(train) {
+                Ok(()) => vec![FromDevice::PulseTrainLoaded(train.channel)],
+                Err(e) => vec![FromDevice::PulseTrainError(e)],
+            },
+            ToDevice::StartPulseTrains(condition) => {
+                match self.sequencer.start(condition, self.now_us) {
+                    Ok(Some(start_us)) => {
+                        let mut result = vec![FromDevice::PulseTrainsStarted(start_us)];
+                        result.extend(self.execute());
+                        result
+                    }
+                    Ok(None) => vec![FromDevice::PulseTrainsArmed],
+                    Err(e) => vec![FromDevice::PulseTrainError(e)],
+                }
+            }
+            ToDevice::StopPulseTrains => {
+                self.sequencer.stop();
+                vec![FromDevice::PulseTrainsStopped]
+            }
+        }
+    }
+
+    /// Advance the device clock to `now_us`, executing pulse train edges.
+    pub fn advance_to(&mut self, now_us: u64) -> Vec<FromDevice> {
+        let mut result = Vec::new();
+        while let Some(edge_us) = self.sequencer.next_edge_us() {
+            if edge_us > now_us {
+                break;
+            }
+            self.now_us = edge_us.max(self.now_us);
+            result.extend(self.execute());
+        }
+        self.now_us = now_us.max(self.now_us);
+        result
+    }
+
+    /// Apply an edge of the external trigger input at the current time.
+    pub fn trigger(&mut self, edge: TriggerEdge) -> Vec<FromDevice> {
+        match self.sequencer.trigger(edge, self.now_us) {
+            Some(start_us) => {
+                let mut result = vec![FromDevice::PulseTrainsStarted(start_us)];
+                result.extend(self.execute());
+                result
+            }
+            None => vec![],
+        }
+    }
+
+    /// Execute the edges which are due now.
+    fn execute(&mut self) -> Vec<FromDevice> {
+        let mut result = Vec::new();
+        while let Some(edge) = self.sequencer.poll_edge(self.now_us) {
+            result.push(FromDevice::PulseEdge(edge));
+        }
+        if let Some(finish_us) = self.sequencer.poll_finished() {
+            result.push(FromDevice::PulseTrainsFinished(finish_us));
+        }
+        result
+    }
+
+    /// Output intensity of `channel` (1 to 4) at the current time.
+    ///
+    /// Channels with a loaded pulse train are controlled by it, the others by
+    /// the last [DeviceState].
+    pub fn intensity(&self, channel: u8) -> u16 {
+        if let Some(intensity) = self.sequencer.intensity(channel, self.now_us) {
+            return intensity;
+        }
+        let ch = match channel {
+            1 => &self.state.ch1,
+            2 => &self.state.ch2,
+            3 => &self.state.ch3,
+            4 => &self.state.ch4,
+            _ => return 0,
+        };
+        match ch.on_state {
+            OnState::Off => 0,
+            OnState::ConstantOn => ch.intensity,
+        }
+    }
+}
+
+impl Default for DeviceEmulator {
+    fn default() -> DeviceEmulator {
+        DeviceEmulator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {
+        is_compatible_version, PulseEdge, PulseTrain, PulseTrainError, StartCondition,
+        MIN_COMM_VERSION,
+    };
+
+    fn train(channel: u8) -> PulseTrain {
+        PulseTrain {
+            channel,
+            onset_us: 1000,
+            duration_us: 200,
+            period_us: 500,
+            repeat_count: 3,
+            start_intensity: 1000,
+            end_intensity: 1000,
+        }
+    }
+
+    fn edges(msgs: &[FromDevice]) -> Vec<PulseEdge> {
+        msgs.iter()
+            .filter_map(|msg| match msg {
+                FromDevice::PulseEdge(edge) => Some(*edge),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_version_negotiation() {
+        for version in [MIN_COMM_VERSION, COMM_VERSION].iter() {
+            let mut dev = DeviceEmulator::with_version(*version);
+            let found = match dev.handle(ToDevice::VersionRequest).as_slice() {
+                [FromDevice::VersionResponse(found)] => *found,
+                other => panic!("unexpected response {:?}", other),
+            };
+            assert!(is_compatible_version(found));
+            let response = dev.handle(ToDevice::LoadPulseTrain(train(1)));
+            if supports_pulse_trains(found) {
+                assert_eq!(response, vec![FromDevice::PulseTrainLoaded(1)]);
+            } else {
+                assert!(response.is_empty());
+            }
+        }
+        assert!(!is_compatible_version(COMM_VERSION + 1));
+    }
+
+    #[test]
+    fn test_pulse_train() {
+        let mut dev = DeviceEmulator::new();
+        dev.advance_to(10_000);
+        dev.handle(ToDevice::LoadPulseTrain(train(2)));
+        assert_eq!(
+            dev.handle(ToDevice::StartPulseTrains(StartCondition::Immediate)),
+            vec![FromDevice::PulseTrainsStarted(10_000)]
+        );
+        assert_eq!(dev.intensity(2), 0);
+
+        dev.advance_to(11_100);
+        assert_eq!(dev.intensity(2), 1000);
+        let msgs = dev.advance_to(20_000);
+        assert_eq!(dev.intensity(2), 0);
+
+        let edges = edges(&msgs);
+        let times: Vec<u64> = edges.iter().map(|e| e.executed_us).collect();
+        assert_eq!(times, vec![11_200, 11_500, 11_700, 12_000, 12_200]);
+        assert!(edges.iter().all(|e| e.scheduled_us == e.executed_us));
+        assert_eq!(msgs.last(), Some(&FromDevice::PulseTrainsFinished(12_200)));
+        // The onset of the first pulse was executed by the previous advance.
+        assert_eq!(edges[0].pulse, 0);
+        assert!(!edges[0].on);
+    }
+
+    #[test]
+    fn test_ramp_and_external_trigger() {
+        let mut dev = DeviceEmulator::new();
+        let ramp = PulseTrain {
+            start_intensity: 0,
+            end_intensity: 10_000,
+            repeat_count: 1,
+            period_us: 0,
+            ..train(1)
+        };
+        dev.handle(ToDevice::LoadPulseTrain(ramp));
+        assert_eq!(
+            dev.handle(ToDevice::StartPulseTrains(StartCondition::ExternalEdge(
+                TriggerEdge::Rising
+            ))),
+            vec![FromDevice::PulseTrainsArmed]
+        );
+        dev.advance_to(5000);
+        assert!(dev.trigger(TriggerEdge::Falling).is_empty());
+        assert_eq!(
+            dev.trigger(TriggerEdge::Rising),
+            vec![FromDevice::PulseTrainsStarted(5000)]
+        );
+
+        dev.advance_to(6100);
+        assert_eq!(dev.intensity(1), 5000);
+        let msgs = dev.advance_to(7000);
+        assert_eq!(edges(&msgs).len(), 1);
+        assert_eq!(msgs.last(), Some(&FromDevice::PulseTrainsFinished(6200)));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut dev = DeviceEmulator::new();
+        assert_eq!(
+            dev.handle(ToDevice::StartPulseTrains(StartCondition::Immediate)),
+            vec![FromDevice::PulseTrainError(PulseTrainError::NoneLoaded)]
+        );
+        let overlapping = PulseTrain {
+            period_us: 200,
+            ..train(1)
+        };
+        assert_eq!(
+            dev.handle(ToDevice::LoadPulseTrain(overlapping)),
+            vec![FromDevice::PulseTrainError(
+                PulseTrainError::DurationNotShorterThanPeriod
+            )]
+        );
+        assert_eq!(
+            dev.handle(ToDevice::LoadPulseTrain(train(5))),
+            vec![FromDevice::PulseTrainError(
+                PulseTrainError::InvalidChannel(5)
+            )]
+        );
+
+        dev.handle(ToDevice::LoadPulseTrain(train(1)));
+        dev.handle(ToDevice::StartPulseTrains(StartCondition::Immediate));
+        assert_eq!(
+            dev.handle(ToDevice::LoadPulseTrain(train(2))),
+            vec![FromDevice::PulseTrainError(PulseTrainError::Busy)]
+        );
+        assert_eq!(
+            dev.handle(ToDevice::StopPulseTrains),
+            vec![FromDevice::PulseTrainsStopped]
+        );
+        assert_eq!(dev.intensity(1), 0);
+        assert!(dev.advance_to(100_000).is_empty());
+    }
+}
@@ -11,9 +11,39 @@ extern crate core as std;
 
 use enum_iter::EnumIter;
 
+mod sequencer;
+pub use sequencer::Sequencer;
+
+#[cfg(feature = "std")]
+pub mod emulator;
+
 pub const MAX_INTENSITY: u16 = 16000;
-pub const COMM_VERSION: u16 = 3;
 pub const BAUD_RATE: u32 = 230_400;
+pub const NUM_CHANNELS: usize = 4;
+
+/// Version of the communication protocol.
+///
+/// Version 4 added hardware-timed pulse trains.
+pub const COMM_VERSION: u16 = 4;
+
+/// Oldest version of the communication protocol which hosts support.
+pub const MIN_COMM_VERSION: u16 = 3;
+
+/// First version of the communication protocol with pulse trains.
+pub const PULSE_TRAIN_COMM_VERSION: u16 = 4;
+
+/// Whether a host can talk to a device reporting `version`.
+///
+/// Devices with a version older than [COMM_VERSION] do not understand all
+/// messages. Use [supports_pulse_trains] to check which are available.
+pub fn is_compatible_version(version: u16) -> bool {
+    (MIN_COMM_VERSION..=COMM_VERSION).contains(&version)
+}
+
+/// Whether a device reporting `version` can execute pulse trains.
+pub fn supports_pulse_trains(version: u16) -> bool {
+    version >= PULSE_TRAIN_COMM_VERSION
+}
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
 #[cfg_attr(feature = "print-defmt", derive(defmt::Format))]
@@ -21,6 +51,17 @@ pub enum ToDevice {
     DeviceState(DeviceState),
     EchoRequest8((u8, u8, u8, u8, u8, u8, u8, u8)),
     VersionRequest,
+    /// Load a pulse train, replacing any previous one on its channel.
+    ///
+    /// While a pulse train is loaded, it controls its channel instead of
+    /// [DeviceState].
+    LoadPulseTrain(PulseTrain),
+    /// Start all loaded pulse trains together.
+    StartPulseTrains(StartCondition),
+    /// Stop and unload all pulse trains.
+    ///
+    /// Their channels return to the last [DeviceState].
+    StopPulseTrains,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
@@ -30,6 +71,19 @@ pub enum FromDevice {
     EchoResponse8((u8, u8, u8, u8, u8, u8, u8, u8)),
     VersionResponse(u16),
     StateWasSet,
+    /// A pulse train was loaded on this channel.
+    PulseTrainLoaded(u8),
+    /// A pulse train command was rejected.
+    PulseTrainError(PulseTrainError),
+    /// The pulse trains wait for the external trigger.
+    PulseTrainsArmed,
+    /// The pulse trains started at this device time, in microseconds.
+    PulseTrainsStarted(u64),
+    /// An edge of a pulse train was executed.
+    PulseEdge(PulseEdge),
+    /// All pulse trains finished at this device time, in microseconds.
+    PulseTrainsFinished(u64),
+    PulseTrainsStopped,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
@@ -111,6 +165,93 @@ impl std::fmt::Display for OnState {
     }
 }
 
+/// A train of pulses on one channel, timed by the device.
+///
+/// Each pulse ramps linearly from `start_intensity` to `end_intensity`. Equal
+/// intensities give square pulses. The LED is off between pulses.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "print-defmt", derive(defmt::Format))]
+pub struct PulseTrain {
+    pub channel: u8,
+    /// Delay from the start to the onset of the first pulse, in microseconds.
+    pub onset_us: u32,
+    /// Duration of each pulse, in microseconds.
+    pub duration_us: u32,
+    /// Time from the onset of one pulse to the next, in microseconds.
+    ///
+    /// Must be longer than `duration_us` unless there is only one pulse.
+    pub period_us: u32,
+    /// Number of pulses. Zero repeats until the pulse trains are stopped.
+    pub repeat_count: u16,
+    pub start_intensity: u16,
+    pub end_intensity: u16,
+}
+
+/// When loaded pulse trains start.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "print-defmt", derive(defmt::Format))]
+pub enum StartCondition {
+    Immediate,
+    /// Start on this edge of the external trigger input.
+    ExternalEdge(TriggerEdge),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "print-defmt", derive(defmt::Format))]
+pub enum TriggerEdge {
+    Rising,
+    Falling,
+}
+
+/// An executed onset or end of a pulse.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "print-defmt", derive(defmt::Format))]
+pub struct PulseEdge {
+    pub channel: u8,
+    /// Index of the pulse in its train, starting at zero.
+    pub pulse: u32,
+    /// `true` at the onset of the pulse, `false` at its end.
+    pub on: bool,
+    /// Scheduled device time, in microseconds.
+    pub scheduled_us: u64,
+    /// Device time at which the edge was executed, in microseconds.
+    pub executed_us: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[cfg_attr(feature = "print-defmt", derive(defmt::Format))]
+pub enum PulseTrainError {
+    InvalidChannel(u8),
+    ZeroDuration,
+    /// The pulses would overlap.
+    DurationNotShorterThanPeriod,
+    IntensityTooHigh,
+    /// No pulse trains were loaded.
+    NoneLoaded,
+    /// Pulse trains are running or armed.
+    Busy,
+    /// The firmware does not implement pulse trains.
+    Unsupported,
+}
+
+impl std::fmt::Display for PulseTrainError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            PulseTrainError::InvalidChannel(num) => write!(fmt, "invalid channel {}", num),
+            PulseTrainError::ZeroDuration => write!(fmt, "pulse duration is zero"),
+            PulseTrainError::DurationNotShorterThanPeriod => {
+                write!(fmt, "pulse duration is not shorter than period")
+            }
+            PulseTrainError::IntensityTooHigh => {
+                write!(fmt, "intensity exceeds {}", MAX_INTENSITY)
+            }
+            PulseTrainError::NoneLoaded => write!(fmt, "no pulse trains loaded"),
+            PulseTrainError::Busy => write!(fmt, "pulse trains are running"),
+            PulseTrainError::Unsupported => write!(fmt, "pulse trains not supported"),
+        }
+    }
+}
+
 // I found this necessary to avoid lifetime error in led-box-firmware. Not
 // sure why this needs to be allocated as with const to be 'static in this
 // case (but not in standard linux target).
@@ -0,0 +1,264 @@
+use {
+    PulseEdge, PulseTrain, PulseTrainError, StartCondition, TriggerEdge, MAX_INTENSITY,
+    NUM_CHANNELS,
+};
+
+impl PulseTrain {
+    pub fn validate(&self) -> Result<(), PulseTrainError> {
+        if self.channel < 1 || self.channel as usize > NUM_CHANNELS {
+            return Err(PulseTrainError::InvalidChannel(self.channel));
+        }
+        if self.duration_us == 0 {
+            return Err(PulseTrainError::ZeroDuration);
+        }
+        if self.repeat_count != 1 && self.duration_us >= self.period_us {
+            return Err(PulseTrainError::DurationNotShorterThanPeriod);
+        }
+        if self.start_intensity > MAX_INTENSITY || self.end_intensity > MAX_INTENSITY {
+            return Err(PulseTrainError::IntensityTooHigh);
+        }
+        Ok(())
+    }
+
+    /// Time of edge `n` after the start, in microseconds.
+    ///
+    /// Even edges are pulse onsets and odd edges are pulse ends. Returns `None`
+    /// after the last pulse.
+    fn edge_offset_us(&self, n: u64) -> Option<u64> {
+        let pulse = n / 2;
+        if self.repeat_count != 0 && pulse >= self.repeat_count as u64 {
+            return None;
+        }
+        let onset = self.onset_us as u64 + pulse * self.period_us as u64;
+        if n & 1 == 0 {
+            Some(onset)
+        } else {
+            Some(onset + self.duration_us as u64)
+        }
+    }
+
+    /// Time since the onset of the current pulse, if a pulse is on
+    /// `elapsed_us` microseconds after the start.
+    fn time_in_pulse(&self, elapsed_us: u64) -> Option<u64> {
+        let since_onset = elapsed_us.checked_sub(self.onset_us as u64)?;
+        let (pulse, within) = if self.period_us == 0 {
+            (0, since_onset)
+        } else {
+            let period = self.period_us as u64;
+            (since_onset / period, since_onset % period)
+        };
+        if self.repeat_count != 0 && pulse >= self.repeat_count as u64 {
+            return None;
+        }
+        if within >= self.duration_us as u64 {
+            return None;
+        }
+        Some(within)
+    }
+
+    /// Output intensity `elapsed_us` microseconds after the start.
+    pub fn intensity_at(&self, elapsed_us: u64) -> u16 {
+        match self.time_in_pulse(elapsed_us) {
+            Some(within) => {
+                let start = self.start_intensity as i64;
+                let end = self.end_intensity as i64;
+                (start + (end - start) * within as i64 / self.duration_us as i64) as u16
+            }
+            None => 0,
+        }
+    }
+
+    fn is_ramp(&self) -> bool {
+        self.start_intensity != self.end_intensity
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum SequencerState {
+    Idle,
+    Armed(TriggerEdge),
+    Running { start_us: u64 },
+}
+
+/// Executes pulse trains on a device clock.
+///
+/// This is shared by the firmware and the host-side emulator so that
+/// both time pulses identically. Times are device times in microseconds.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Sequencer {
+    trains: [Option<PulseTrain>; NUM_CHANNELS],
+    /// Index of the next edge of each train.
+    next_edge: [u64; NUM_CHANNELS],
+    last_edge_us: u64,
+    state: SequencerState,
+}
+
+impl Sequencer {
+    pub const fn new() -> Sequencer {
+        Sequencer {
+            trains: [None; NUM_CHANNELS],
+            next_edge: [0; NUM_CHANNELS],
+            last_edge_us: 0,
+            state: SequencerState::Idle,
+        }
+    }
+
+    /// Load `train`, replacing any previous one on its channel.
+    pub fn load(&mut self, train: PulseTrain) -> Result<(), PulseTrainError> {
+        if self.is_active() {
+            return Err(PulseTrainError::Busy);
+        }
+        train.validate()?;
+        self.trains[train.channel as usize - 1] = Some(train);
+        Ok(())
+    }
+
+    /// Start the loaded pulse trains.
+    ///
+    /// Returns the start time if the pulse trains started immediately rather
+    /// than waiting for the external trigger.
+    pub fn start(
+        &mut self,
+        condition: StartCondition,
+        now_us: u64,
+    ) -> Result<Option<u64>, PulseTrainError> {
+        if self.is_active() {
+            return Err(PulseTrainError::Busy);
+        }
+        if self.trains.iter().all(|t| t.is_none()) {
+            return Err(PulseTrainError::NoneLoaded);
+        }
+        self.next_edge = [0; NUM_CHANNELS];
+        match condition {
+            StartCondition::Immediate => {
+                self.state = SequencerState::Running { start_us: now_us };
+                Ok(Some(now_us))
+            }
+            StartCondition::ExternalEdge(edge) => {
+                self.state = SequencerState::Armed(edge);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Handle an edge of the external trigger input.
+    ///
+    /// Returns the start time if this started the pulse trains.
+    pub fn trigger(&mut self, edge: TriggerEdge, now_us: u64) -> Option<u64> {
+        if self.state == SequencerState::Armed(edge) {
+            self.state = SequencerState::Running { start_us: now_us };
+            Some(now_us)
+        } else {
+            None
+        }
+    }
+
+    /// Stop and unload all pulse trains.
+    pub fn stop(&mut self) {
+        *self = Sequencer::new();
+    }
+
+    /// Whether pulse trains are running or armed.
+    pub fn is_active(&self) -> bool {
+        self.state != SequencerState::Idle
+    }
+
+    /// Whether a pulse train is loaded on `channel`.
+    pub fn has_train(&self, channel: u8) -> bool {
+        self.train(channel).is_some()
+    }
+
+    fn train(&self, channel: u8) -> Option<&PulseTrain> {
+        match channel {
+            1..=4 => self.trains[channel as usize - 1].as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Output intensity of `channel`, if it is controlled by a pulse train.
+    pub fn intensity(&self, channel: u8, now_us: u64) -> Option<u16> {
+        let train = self.train(channel)?;
+        match self.state {
+            SequencerState::Running { start_us } => {
+                Some(train.intensity_at(now_us.saturating_sub(start_us)))
+            }
+            SequencerState::Idle | SequencerState::Armed(_) => Some(0),
+        }
+    }
+
+    /// Whether a ramped pulse is on, so that the output must be updated
+    /// periodically rather than only at edges.
+    pub fn is_ramping(&self, now_us: u64) -> bool {
+        let start_us = match self.state {
+            SequencerState::Running { start_us } => start_us,
+            SequencerState::Idle | SequencerState::Armed(_) => return false,
+        };
+        self.trains.iter().flatten().any(|train| {
+            train.is_ramp()
+                && train
+                    .time_in_pulse(now_us.saturating_sub(start_us))
+                    .is_some()
+        })
+    }
+
+    /// Device time of the next edge.
+    pub fn next_edge_us(&self) -> Option<u64> {
+        self.next_due().map(|(_, scheduled_us)| scheduled_us)
+    }
+
+    fn next_due(&self) -> Option<(usize, u64)> {
+        let start_us = match self.state {
+            SequencerState::Running { start_us } => start_us,
+            SequencerState::Idle | SequencerState::Armed(_) => return None,
+        };
+        self.trains
+            .iter()
+            .enumerate()
+            .filter_map(|(i, train)| {
+                let offset = train.as_ref()?.edge_offset_us(self.next_edge[i])?;
+                Some((i, start_us + offset))
+            })
+            .min_by_key(|(_, scheduled_us)| *scheduled_us)
+    }
+
+    /// Return the next edge which is due at `now_us`.
+    ///
+    /// Call this repeatedly until it returns `None`, setting the outputs to
+    /// [Self::intensity] after each edge.
+    pub fn poll_edge(&mut self, now_us: u64) -> Option<PulseEdge> {
+        let (i, scheduled_us) = self.next_due()?;
+        if scheduled_us > now_us {
+            return None;
+        }
+        let n = self.next_edge[i];
+        self.next_edge[i] += 1;
+        self.last_edge_us = scheduled_us;
+        Some(PulseEdge {
+            channel: i as u8 + 1,
+            pulse: (n / 2) as u32,
+            on: n & 1 == 0,
+            scheduled_us,
+            executed_us: now_us,
+        })
+    }
+
+    /// Check whether all pulse trains finished.
+    ///
+    /// Returns the time of the last edge once, after which the pulse trains
+    /// remain loaded and can be started again.
+    pub fn poll_finished(&mut self) -> Option<u64> {
+        match self.state {
+            SequencerState::Running { .. } if self.next_due().is_none() => {
+                self.state = SequencerState::Idle;
+                Some(self.last_edge_us)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for Sequencer {
+    fn default() -> Sequencer {
+        Sequencer::new()
+    }
+}
@@ -4,6 +4,7 @@
 //  PWM LED channel 2: gpio7
 //  PWM LED channel 3: gpio8
 //  PWM LED channel 4: gpio9
+//  External trigger input for pulse trains: gpio10
 
 // Reserved Pins (do not use except for these assignments):
 //  Pico (but not Pico W) LED: gpio25
@@ -21,13 +22,18 @@ use defmt_rtt as _;
 use panic_probe as _;
 use rtic::Mutex;
 
-use led_box_comms::{ChannelState, DeviceState, FromDevice, OnState, ToDevice};
+use led_box_comms::{
+    ChannelState, DeviceState, FromDevice, OnState, PulseEdge, Sequencer, ToDevice, TriggerEdge,
+};
 
 use json_lines::accumulator::{FeedResult, NewlinesAccumulator};
 
 const ZERO_INTENSITY: u16 = 0;
 const LED_PWM_FREQ_HZ: f64 = 500.0;
 
+/// Interval at which the intensity of ramped pulses is updated.
+const RAMP_UPDATE_US: u64 = 1000;
+
 #[rtic::app(device = rp_pico::hal::pac, peripherals = true, dispatchers = [I2C0_IRQ])]
 mod app {
     use super::*;
@@ -42,6 +48,7 @@ mod app {
     use rp2040_hal::{
         self as hal,
         clocks::init_clocks_and_plls,
+        gpio::Interrupt,
         pwm,
         timer::{monotonic::Monotonic, Alarm0},
         usb::UsbBus,
@@ -68,14 +75,22 @@ mod app {
         >,
         inner_led_state: InnerLedState,
         usb_serial: SerialPort<'static, UsbBus>,
+        pwms: PwmData,
+        sequencer: Sequencer,
+        /// Incremented whenever pulse trains are started or stopped so that
+        /// pending ticks of a previous run are ignored.
+        sequence_run: u32,
     }
 
+    type TriggerPin =
+        hal::gpio::Pin<hal::gpio::bank0::Gpio10, hal::gpio::FunctionSioInput, hal::gpio::PullDown>;
+
     #[monotonic(binds = TIMER_IRQ_0, default = true)]
     type MyMono = Monotonic<Alarm0>;
 
     #[local]
     struct Local {
-        pwms: PwmData,
+        trigger_pin: TriggerPin,
         usb_dev: UsbDevice<'static, UsbBus>,
         rx_prod: Producer<'static, UsbFrame, NUM_FRAMES>,
         rx_cons: Consumer<'static, UsbFrame, NUM_FRAMES>,
@@ -175,6 +190,10 @@ mod app {
         pwms.pwm4_slice.channel_b.output_to(pins.gpio9);
         pwms.pwm4_slice.enable();
 
+        let trigger_pin: TriggerPin = pins.gpio10.reconfigure();
+        trigger_pin.set_interrupt_enabled(Interrupt::EdgeHigh, true);
+        trigger_pin.set_interrupt_enabled(Interrupt::EdgeLow, true);
+
         // Statically allocate memory for a `Queue<UsbFrame, NUM_FRAMES>`.
         static RX_Q: StaticCell<Queue<UsbFrame, NUM_FRAMES>> = StaticCell::new();
         let rx_queue: &'static mut Queue<UsbFrame, NUM_FRAMES> = RX_Q.init(Queue::new());
@@ -189,9 +208,12 @@ mod app {
                 green_led,
                 usb_serial,
                 inner_led_state: InnerLedState::default(),
+                pwms,
+                sequencer: Sequencer::new(),
+                sequence_run: 0,
             },
             Local {
-                pwms,
+                trigger_pin,
                 usb_dev,
                 rx_prod,
                 rx_cons,
@@ -200,7 +222,7 @@ mod app {
         )
     }
 
-    #[idle(shared = [usb_serial, inner_led_state, green_led], local = [pwms,rx_cons])]
+    #[idle(shared = [usb_serial, inner_led_state, green_led, pwms, sequencer, sequence_run], local = [rx_cons])]
     fn idle(mut ctx: idle::Context) -> ! {
         let mut decoder = NewlinesAccumulator::<512>::new();
         let mut current_device_state = DeviceState::default();
@@ -228,6 +250,8 @@ mod app {
 
             if let Some(msg) = ret {
                 let response;
+                // Run of pulse trains to start after sending the response.
+                let mut start_run = None;
                 match msg {
                     ToDevice::DeviceState(next_state) => {
                         update_device_state(&mut current_device_state, &next_state, &mut ctx);
@@ -242,6 +266,57 @@ mod app {
                         response = FromDevice::VersionResponse(led_box_comms::COMM_VERSION);
                         defmt::debug!("version request");
                     }
+                    ToDevice::LoadPulseTrain(train) => {
+                        response = match ctx.shared.sequencer.lock(|s| s.load(train)) {
+                            Ok(()) => {
+                                // The channel is off until the pulse train starts.
+                                ctx.shared
+                                    .pwms
+                                    .lock(|pwms| set_duty(pwms, train.channel, ZERO_INTENSITY));
+                                FromDevice::PulseTrainLoaded(train.channel)
+                            }
+                            Err(e) => FromDevice::PulseTrainError(e),
+                        };
+                        defmt::debug!("load pulse train");
+                    }
+                    ToDevice::StartPulseTrains(condition) => {
+                        let now_us = monotonics::now().ticks();
+                        response = match ctx.shared.sequencer.lock(|s| s.start(condition, now_us)) {
+                            Ok(started) => {
+                                let run = ctx.shared.sequence_run.lock(|run| {
+                                    *run = run.wrapping_add(1);
+                                    *run
+                                });
+                                match started {
+                                    Some(start_us) => {
+                                        start_run = Some(run);
+                                        FromDevice::PulseTrainsStarted(start_us)
+                                    }
+                                    None => FromDevice::PulseTrainsArmed,
+                                }
+                            }
+                            Err(e) => FromDevice::PulseTrainError(e),
+                        };
+                        defmt::debug!("start pulse trains");
+                    }
+                    ToDevice::StopPulseTrains => {
+                        ctx.shared
+                            .sequence_run
+                            .lock(|run| *run = run.wrapping_add(1));
+                        ctx.shared.sequencer.lock(|s| s.stop());
+                        // Return the channels to the last static state.
+                        let static_state = current_device_state;
+                        for ch in [
+                            static_state.ch1,
+                            static_state.ch2,
+                            static_state.ch3,
+                            static_state.ch4,
+                        ] {
+                            update_led_state(&ch, &mut ctx);
+                        }
+                        response = FromDevice::PulseTrainsStopped;
+                        defmt::debug!("stop pulse trains");
+                    }
                 }
 
                 let encoded = json_lines::to_slice_newline(&response, &mut out_buf[..]).unwrap();
@@ -250,6 +325,10 @@ mod app {
                     usb_serial.write(&encoded).unwrap();
                 });
                 defmt::trace!("sent {} bytes", encoded.len());
+
+                if let Some(run) = start_run {
+                    pulse_tick::spawn(run).ok();
+                }
             }
         }
     }
@@ -346,23 +425,117 @@ mod app {
                 }
             })
         }
+        // Channels with a pulse train are controlled by the sequencer.
+        if ctx.shared.sequencer.lock(|s| s.has_train(next_state.num)) {
+            set_pwm3_now = None;
+        }
         if let Some(pwm_period) = set_pwm3_now {
             defmt::info!(
                 "setting channel {} to period {}",
                 next_state.num,
                 pwm_period
             );
-            match next_state.num {
-                1 => ctx.local.pwms.pwm3_slice.channel_a.set_duty(pwm_period),
-                2 => ctx.local.pwms.pwm3_slice.channel_b.set_duty(pwm_period),
-                3 => ctx.local.pwms.pwm4_slice.channel_a.set_duty(pwm_period),
-                4 => ctx.local.pwms.pwm4_slice.channel_b.set_duty(pwm_period),
-                _ => panic!("unknown channel"),
-            };
+            ctx.shared
+                .pwms
+                .lock(|pwms| set_duty(pwms, next_state.num, pwm_period));
         }
         // rtic::pend(pac::Interrupt::TIM2);
     }
 
+    fn set_duty(pwms: &mut PwmData, num: u8, pwm_period: u16) {
+        match num {
+            1 => pwms.pwm3_slice.channel_a.set_duty(pwm_period),
+            2 => pwms.pwm3_slice.channel_b.set_duty(pwm_period),
+            3 => pwms.pwm4_slice.channel_a.set_duty(pwm_period),
+            4 => pwms.pwm4_slice.channel_b.set_duty(pwm_period),
+            _ => panic!("unknown channel"),
+        };
+    }
+
+    fn send_msg(usb_serial: &mut SerialPort<'static, UsbBus>, msg: &FromDevice) {
+        let mut out_buf = [0u8; 256];
+        let encoded = json_lines::to_slice_newline(msg, &mut out_buf[..]).unwrap();
+        if usb_serial.write(encoded).is_err() {
+            defmt::warn!("could not send message to host");
+        }
+    }
+
+    /// Execute the due edges of the pulse trains and schedule the next tick.
+    ///
+    /// The reported edge times are when the new duty cycle was written. The
+    /// PWM hardware applies it at the end of the current PWM period.
+    #[task(priority = 2, capacity = 4, shared = [usb_serial, pwms, sequencer, sequence_run])]
+    fn pulse_tick(mut ctx: pulse_tick::Context, run: u32) {
+        if ctx.shared.sequence_run.lock(|current| *current != run) {
+            // Pulse trains were stopped or restarted since this was scheduled.
+            return;
+        }
+        let now_us = monotonics::now().ticks();
+        let mut edges: heapless::Vec<PulseEdge, 8> = heapless::Vec::new();
+        let (intensities, finished, next_us) = ctx.shared.sequencer.lock(|sequencer| {
+            while !edges.is_full() {
+                match sequencer.poll_edge(now_us) {
+                    Some(edge) => edges.push(edge).unwrap(),
+                    None => break,
+                }
+            }
+            let intensities = [1, 2, 3, 4].map(|num| sequencer.intensity(num, now_us));
+            let mut next_us = sequencer.next_edge_us();
+            if sequencer.is_ramping(now_us) {
+                let ramp_us = now_us + RAMP_UPDATE_US;
+                next_us = Some(next_us.map_or(ramp_us, |t| t.min(ramp_us)));
+            }
+            (intensities, sequencer.poll_finished(), next_us)
+        });
+
+        ctx.shared.pwms.lock(|pwms| {
+            for (num, intensity) in (1..).zip(intensities) {
+                if let Some(intensity) = intensity {
+                    set_duty(pwms, num, intensity);
+                }
+            }
+        });
+
+        ctx.shared.usb_serial.lock(|usb_serial| {
+            for edge in edges {
+                send_msg(usb_serial, &FromDevice::PulseEdge(edge));
+            }
+            if let Some(finish_us) = finished {
+                send_msg(usb_serial, &FromDevice::PulseTrainsFinished(finish_us));
+            }
+        });
+
+        if let Some(next_us) = next_us {
+            let at = fugit::TimerInstantU64::<1_000_000>::from_ticks(next_us);
+            if pulse_tick::spawn_at(at, run).is_err() {
+                defmt::error!("could not schedule pulse train tick");
+            }
+        }
+    }
+
+    #[task(binds = IO_IRQ_BANK0, priority = 2, shared = [usb_serial, sequencer, sequence_run], local = [trigger_pin])]
+    fn on_trigger(mut ctx: on_trigger::Context) {
+        let now_us = monotonics::now().ticks();
+        let trigger_pin = ctx.local.trigger_pin;
+        for (interrupt, edge) in [
+            (Interrupt::EdgeHigh, TriggerEdge::Rising),
+            (Interrupt::EdgeLow, TriggerEdge::Falling),
+        ] {
+            if !trigger_pin.interrupt_status(interrupt) {
+                continue;
+            }
+            trigger_pin.clear_interrupt(interrupt);
+            if let Some(start_us) = ctx.shared.sequencer.lock(|s| s.trigger(edge, now_us)) {
+                defmt::debug!("pulse trains triggered");
+                let run = ctx.shared.sequence_run.lock(|run| *run);
+                pulse_tick::spawn(run).ok();
+                ctx.shared.usb_serial.lock(|usb_serial| {
+                    send_msg(usb_serial, &FromDevice::PulseTrainsStarted(start_us));
+                });
+            }
+        }
+    }
+
     fn update_device_state(
         current_state: &mut DeviceState,
         next_state: &DeviceState,
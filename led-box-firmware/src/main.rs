@@ -35,7 +35,7 @@ use defmt::{error, info};
 
 use rtic::Mutex;
 
-use led_box_comms::{ChannelState, DeviceState, FromDevice, OnState, ToDevice};
+use led_box_comms::{ChannelState, DeviceState, FromDevice, OnState, PulseTrainError, ToDevice};
 use stm32f3xx_hal::gpio::gpioa::PA5;
 
 use json_lines::accumulator::{FeedResult, NewlinesAccumulator};
@@ -45,6 +45,9 @@ pub type UserLED = PA5<Output<PushPull>>;
 const ZERO_INTENSITY: u16 = 0;
 const LED_PWM_FREQ: Hertz = Hertz(500);
 
+/// This firmware implements communication version 3, without pulse trains.
+const FIRMWARE_COMM_VERSION: u16 = 3;
+
 #[rtic::app(device = stm32f3xx_hal::pac, peripherals = true)]
 mod app {
     use super::*;
@@ -96,7 +99,7 @@ mod app {
         // Device specific peripherals
         info!(
             "hello from f303, COMM_VERSION {}, BAUD_RATE {}, encoding {}",
-            led_box_comms::COMM_VERSION,
+            FIRMWARE_COMM_VERSION,
             led_box_comms::BAUD_RATE,
             "JSON + newlines",
         );
@@ -262,9 +265,15 @@ mod app {
                         defmt::debug!("echo");
                     }
                     ToDevice::VersionRequest => {
-                        response = FromDevice::VersionResponse(led_box_comms::COMM_VERSION);
+                        response = FromDevice::VersionResponse(FIRMWARE_COMM_VERSION);
                         defmt::debug!("version request");
                     }
+                    ToDevice::LoadPulseTrain(_)
+                    | ToDevice::StartPulseTrains(_)
+                    | ToDevice::StopPulseTrains => {
+                        response = FromDevice::PulseTrainError(PulseTrainError::Unsupported);
+                        defmt::debug!("pulse trains not supported");
+                    }
                 }
 
                 let encoded = json_lines::to_slice_newline(&response, &mut out_buf[..]).unwrap();
@@ -0,0 +1,66 @@
+//! Messages of the network API.
+//!
+//! Over TCP, each [Request] and [Response] is one line of JSON. The HTTP API
+//! uses the same types as JSON bodies.
+
+use serde::{Deserialize, Serialize};
+
+use led_box_comms::{ChannelState, DeviceState};
+
+use crate::box_status::BoxStatus;
+
+/// Default address of the JSON lines TCP server.
+pub const DEFAULT_TCP_ADDR: &str = "127.0.0.1:5110";
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum Request {
+    /// Open the LED box at this serial device.
+    Connect(String),
+    /// Set the state of one channel.
+    SetChannel(ChannelState),
+    /// Set the state of all channels.
+    SetState(DeviceState),
+    GetStatus,
+    /// List the serial devices of the server.
+    ListPorts,
+    /// Send the status now and again after each change.
+    Subscribe,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum Response {
+    Ok,
+    Status(BoxStatus),
+    Ports(Vec<String>),
+    Error(String),
+}
+
+pub(crate) fn check_channel(chan: &ChannelState) -> Result<(), String> {
+    if !(1..=4).contains(&chan.num) {
+        return Err(format!("unknown channel {}", chan.num));
+    }
+    if chan.intensity > led_box_comms::MAX_INTENSITY {
+        return Err(format!(
+            "intensity {} of channel {} exceeds {}",
+            chan.intensity,
+            chan.num,
+            led_box_comms::MAX_INTENSITY
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_state(state: &DeviceState) -> Result<(), String> {
+    for (num, chan) in [
+        (1, &state.ch1),
+        (2, &state.ch2),
+        (3, &state.ch3),
+        (4, &state.ch4),
+    ] {
+        if chan.num != num {
+            return Err(format!("ch{num} has channel number {}", chan.num));
+        }
+        check_channel(chan)?;
+    }
+    Ok(())
+}
@@ -4,71 +4,79 @@ use parking_lot::Mutex;
 
 use eframe::egui;
 
-use log::info;
+use led_box_comms::OnState;
 
-use crate::box_status::{BoxManager, BoxStatus, Cmd};
+use crate::{api::Request, box_status::BoxStatus, client::ClientState};
 
 pub struct LedBoxApp {
-    available_ports: Vec<String>,
-
-    box_manager: Arc<Mutex<BoxManager>>,
-    cmd_tx: tokio::sync::mpsc::Sender<Cmd>,
+    client_state: Arc<Mutex<ClientState>>,
+    req_tx: tokio::sync::mpsc::Sender<Request>,
 }
 
 impl LedBoxApp {
     pub fn new(
-        available_ports: Vec<String>,
-        box_manager: Arc<Mutex<BoxManager>>,
-        cmd_tx: tokio::sync::mpsc::Sender<Cmd>,
+        client_state: Arc<Mutex<ClientState>>,
+        req_tx: tokio::sync::mpsc::Sender<Request>,
         cc: &eframe::CreationContext<'_>,
     ) -> Self {
-        box_manager.lock().frame = Some(cc.egui_ctx.clone());
+        client_state.lock().frame = Some(cc.egui_ctx.clone());
         Self {
-            available_ports,
-            box_manager,
-            cmd_tx,
+            client_state,
+            req_tx,
         }
     }
 }
 
 impl eframe::App for LedBoxApp {
-    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        info!("app sending Cmd::Quit command to serial loop");
-        self.cmd_tx.blocking_send(Cmd::Quit).unwrap();
-    }
-
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let Self {
-            available_ports,
-            box_manager,
-            cmd_tx,
+            client_state,
+            req_tx,
         } = self;
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("LED box control");
 
             {
-                let status = box_manager.lock().status();
+                let (status, available_ports, error) = {
+                    let guard = client_state.lock();
+                    (
+                        guard.status.clone(),
+                        guard.ports.clone(),
+                        guard.error.clone(),
+                    )
+                };
+                if let Some(error) = error {
+                    ui.label(format!(" Error: {error} "));
+                }
                 match &status {
-                    BoxStatus::Unconnected => {
+                    None => {
+                        ui.label(" Waiting for server ");
+                    }
+                    Some(BoxStatus::Unconnected) => {
                         ui.label(" Unconnected ");
                         if available_ports.is_empty() {
                             ui.label(" No connected devices.");
                         } else {
                             for port in available_ports.iter() {
                                 if ui.button(port).clicked() {
-                                    cmd_tx.blocking_send(Cmd::Connect(port.clone())).unwrap();
+                                    req_tx.blocking_send(Request::Connect(port.clone())).ok();
                                 }
                             }
                         }
                     }
-                    &BoxStatus::Connected(_state) => {
+                    Some(BoxStatus::Connected(state)) => {
                         ui.label(" Connected ");
-                        for chan in &[1, 2, 3] {
-                            let label = format!("Toggle LED {}", chan);
+                        for chan in [&state.ch1, &state.ch2, &state.ch3] {
+                            let label = format!("Toggle LED {}", chan.num);
                             if ui.button(label).clicked() {
-                                cmd_tx.blocking_send(Cmd::Toggle(*chan)).unwrap();
+                                let mut next_chan = *chan;
+                                next_chan.on_state = match chan.on_state {
+                                    OnState::ConstantOn => OnState::Off,
+                                    OnState::Off => OnState::ConstantOn,
+                                };
+                                req_tx.blocking_send(Request::SetChannel(next_chan)).ok();
                             }
                         }
                     }
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
-use eframe::egui;
 use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
 use tokio_serial::SerialPortBuilderExt;
 use tokio_util::codec::Decoder;
 
@@ -15,7 +15,8 @@ use led_box_comms::{ChannelState, DeviceState, OnState, ToDevice};
 #[derive(Debug, PartialEq, Clone)]
 pub enum Cmd {
     Connect(String),
-    Toggle(u8),
+    SetChannel(ChannelState),
+    SetState(DeviceState),
     Quit,
 }
 
@@ -32,14 +33,14 @@ fn _test_box_manager_is_send() {
 
 pub struct BoxManager {
     inner: Option<BoxManagerInner>,
-    pub(crate) frame: Option<egui::Context>,
+    status_tx: tokio::sync::watch::Sender<BoxStatus>,
 }
 
 impl BoxManager {
     pub fn new() -> Self {
         Self {
             inner: None,
-            frame: None,
+            status_tx: tokio::sync::watch::channel(BoxStatus::Unconnected).0,
         }
     }
 
@@ -50,9 +51,29 @@ impl BoxManager {
             BoxStatus::Unconnected
         }
     }
+
+    /// Receive the status now and after each change.
+    pub fn subscribe(&self) -> tokio::sync::watch::Receiver<BoxStatus> {
+        self.status_tx.subscribe()
+    }
+
+    fn notify(&self) {
+        self.status_tx.send_replace(self.status());
+    }
+
+    /// Set the state as if a box were connected, without a device.
+    #[cfg(test)]
+    pub(crate) fn set_state_without_device(&mut self, state: DeviceState) {
+        let to_box_writer = tokio::sync::mpsc::channel(1).0;
+        self.inner = Some(BoxManagerInner {
+            to_box_writer,
+            state,
+        });
+        self.notify();
+    }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum BoxStatus {
     Connected(DeviceState),
     Unconnected,
@@ -81,8 +102,8 @@ pub async fn handle_box(
                 device_name = port;
                 break;
             }
-            Some(Cmd::Toggle(_chan)) => {
-                panic!("Cannot toggle LED when not yet connected");
+            Some(Cmd::SetChannel(_)) | Some(Cmd::SetState(_)) => {
+                error!("Cannot set LED state when not yet connected");
             }
             Some(Cmd::Quit) | None => {
                 error!("exiting serial task before device opened");
@@ -102,9 +123,7 @@ pub async fn handle_box(
     info!("connecting to {device_name}");
 
     #[allow(unused_mut)]
-    let mut port = tokio_serial::new(&device_name, led_box_comms::BAUD_RATE)
-        .open_native_async()
-        .unwrap();
+    let mut port = tokio_serial::new(&device_name, led_box_comms::BAUD_RATE).open_native_async()?;
     debug!("connected to {device_name}");
 
     #[cfg(unix)]
@@ -138,13 +157,14 @@ pub async fn handle_box(
         .await?;
 
     match serial_reader.next().await {
+        Some(Ok(led_box_comms::FromDevice::VersionResponse(found)))
+            if led_box_comms::is_compatible_version(found) =>
+        {
+            info!("Connected to firmware version {found}");
+        }
         Some(Ok(from_device_msg)) => {
-            assert_eq!(
-                from_device_msg,
-                led_box_comms::FromDevice::VersionResponse(led_box_comms::COMM_VERSION)
-            );
-            info!(
-                "Connected to firmware version {}",
+            anyhow::bail!(
+                "Unexpected response from LED box {from_device_msg:?}. (Needed version: {})",
                 led_box_comms::COMM_VERSION
             );
         }
@@ -159,17 +179,13 @@ pub async fn handle_box(
     let msg = ToDevice::DeviceState(next_state);
     to_box_writer.send(msg).await.unwrap();
 
-    let mut frame = {
+    {
         let mut guard = box_manager.lock();
         guard.inner = Some(BoxManagerInner {
             to_box_writer,
             state: next_state,
         });
-        guard.frame.clone()
-    };
-
-    if let Some(frame_ref) = frame.as_mut() {
-        frame_ref.request_repaint();
+        guard.notify();
     }
 
     let start_led_box_instant = std::time::Instant::now();
@@ -190,10 +206,17 @@ pub async fn handle_box(
                 }
                 Ok(led_box_comms::FromDevice::StateWasSet)
                 | Ok(led_box_comms::FromDevice::DeviceState(_)) => {}
+                Ok(led_box_comms::FromDevice::PulseTrainLoaded(_))
+                | Ok(led_box_comms::FromDevice::PulseTrainError(_))
+                | Ok(led_box_comms::FromDevice::PulseTrainsArmed)
+                | Ok(led_box_comms::FromDevice::PulseTrainsStarted(_))
+                | Ok(led_box_comms::FromDevice::PulseEdge(_))
+                | Ok(led_box_comms::FromDevice::PulseTrainsFinished(_))
+                | Ok(led_box_comms::FromDevice::PulseTrainsStopped) => {}
                 Ok(led_box_comms::FromDevice::VersionResponse(found)) => {
                     info!("Found comm version {found}.");
                     let expected = led_box_comms::COMM_VERSION;
-                    if found != expected {
+                    if !led_box_comms::is_compatible_version(found) {
                         panic!("This program compiled to support comm version {expected}, but found version {found}.");
                     }
                 }
@@ -253,37 +276,41 @@ pub async fn handle_box(
 }
 
 async fn handle_cmd(cmd: Cmd, box_manager: &mut Arc<Mutex<BoxManager>>) -> anyhow::Result<()> {
-    match cmd {
+    let next_state = match cmd {
         Cmd::Quit => {
             panic!("should handle quit outside this function");
         }
         Cmd::Connect(_) => {
             log::warn!("already connected");
+            return Ok(());
         }
-        Cmd::Toggle(chan) => {
-            let mut guard = box_manager.lock();
-            {
-                let inner = guard.inner.as_mut().unwrap();
-                {
-                    let chan_ref = match chan {
-                        1 => &mut inner.state.ch1,
-                        2 => &mut inner.state.ch2,
-                        3 => &mut inner.state.ch3,
-                        4 => &mut inner.state.ch4,
-                        other => {
-                            panic!("unknown channel {}", other);
-                        }
-                    };
-                    let next_on_state = match chan_ref.on_state {
-                        OnState::ConstantOn => OnState::Off,
-                        OnState::Off => OnState::ConstantOn,
-                    };
-                    chan_ref.on_state = next_on_state;
+        Cmd::SetChannel(next_chan) => {
+            let mut state = box_manager.lock().inner.as_ref().unwrap().state;
+            let chan_ref = match next_chan.num {
+                1 => &mut state.ch1,
+                2 => &mut state.ch2,
+                3 => &mut state.ch3,
+                4 => &mut state.ch4,
+                other => {
+                    panic!("unknown channel {}", other);
                 }
-                let msg = ToDevice::DeviceState(inner.state.clone());
-                inner.to_box_writer.send(msg).await?
-            }
+            };
+            *chan_ref = next_chan;
+            state
         }
-    }
+        Cmd::SetState(state) => state,
+    };
+    // Do not hold the lock while sending.
+    let to_box_writer = {
+        let mut guard = box_manager.lock();
+        let inner = guard.inner.as_mut().unwrap();
+        inner.state = next_state;
+        let to_box_writer = inner.to_box_writer.clone();
+        guard.notify();
+        to_box_writer
+    };
+    to_box_writer
+        .send(ToDevice::DeviceState(next_state))
+        .await?;
     Ok(())
 }
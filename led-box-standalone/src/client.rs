@@ -0,0 +1,83 @@
+//! Client of the TCP server, used by the GUI.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use futures::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use tokio_util::codec::Decoder;
+
+use log::error;
+
+use json_lines::codec::JsonLinesCodec;
+
+use crate::{
+    api::{Request, Response},
+    box_status::BoxStatus,
+};
+
+/// The state of the server as last reported to the client.
+#[derive(Default)]
+pub struct ClientState {
+    /// `None` until the first status was received.
+    pub status: Option<BoxStatus>,
+    pub ports: Vec<String>,
+    /// Set if the connection to the server failed.
+    pub error: Option<String>,
+    pub(crate) frame: Option<egui::Context>,
+}
+
+impl ClientState {
+    pub(crate) fn request_repaint(&self) {
+        if let Some(frame) = self.frame.as_ref() {
+            frame.request_repaint();
+        }
+    }
+}
+
+/// Connect to the server at `addr` and forward requests until `req_rx` is
+/// closed.
+pub async fn run_client(
+    addr: std::net::SocketAddr,
+    client_state: Arc<Mutex<ClientState>>,
+    mut req_rx: tokio::sync::mpsc::Receiver<Request>,
+) -> anyhow::Result<()> {
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let (mut writer, mut reader) = JsonLinesCodec::default().framed(stream).split();
+    writer.send(Request::Subscribe).await?;
+    writer.send(Request::ListPorts).await?;
+    loop {
+        tokio::select! {
+            req = req_rx.recv() => {
+                match req {
+                    Some(req) => writer.send(req).await?,
+                    None => return Ok(()),
+                }
+            }
+            response = reader.next() => {
+                match response {
+                    Some(Ok(Response::Status(status))) => {
+                        let mut guard = client_state.lock();
+                        guard.status = Some(status);
+                        guard.request_repaint();
+                    }
+                    Some(Ok(Response::Ports(ports))) => {
+                        let mut guard = client_state.lock();
+                        guard.ports = ports;
+                        guard.request_repaint();
+                    }
+                    Some(Ok(Response::Ok)) => {}
+                    Some(Ok(Response::Error(e))) => {
+                        error!("LED box server: {e}");
+                    }
+                    Some(Err(e)) => {
+                        return Err(e.into());
+                    }
+                    None => {
+                        anyhow::bail!("LED box server closed connection");
+                    }
+                }
+            }
+        }
+    }
+}
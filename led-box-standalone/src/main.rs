@@ -1,16 +1,76 @@
-use std::sync::Arc;
+use std::{future::Future, net::SocketAddr, sync::Arc};
 
+use clap::Parser;
 use parking_lot::Mutex;
 
+use log::{error, info};
+
+mod api;
 mod app;
 mod box_status;
+mod client;
+mod server;
+
+use box_status::{handle_box, BoxManager, Cmd};
+use client::ClientState;
+
+#[derive(Debug, Parser)]
+#[command(author, version, about)]
+struct Cli {
+    /// Run without GUI, serving the LED box to network clients
+    #[arg(long)]
+    headless: bool,
+
+    /// Serial device of the LED box to open at startup
+    #[arg(short, long)]
+    device: Option<String>,
 
-use box_status::handle_box;
+    /// Address of the JSON lines TCP server
+    #[arg(long, default_value = api::DEFAULT_TCP_ADDR)]
+    listen: SocketAddr,
 
-fn to_device_name(spi: &tokio_serial::SerialPortInfo) -> String {
-    let name = spi.port_name.clone();
-    // This is necessary on linux:
-    name.replace("/sys/class/tty/", "/dev/")
+    /// Address of the HTTP server. Disabled if not given.
+    #[arg(long)]
+    http: Option<SocketAddr>,
+
+    /// Connect the GUI to a running server rather than starting one
+    #[arg(long, conflicts_with_all = ["headless", "device", "http"])]
+    server: Option<SocketAddr>,
+}
+
+async fn log_error<F: Future<Output = anyhow::Result<()>>>(fut: F) {
+    if let Err(e) = fut.await {
+        error!("{e}");
+    }
+}
+
+/// Start the task owning the LED box and the servers.
+///
+/// Returns the address of the TCP server.
+async fn start_server(
+    cli: &Cli,
+) -> anyhow::Result<(
+    SocketAddr,
+    tokio::sync::mpsc::Sender<Cmd>,
+    tokio::task::JoinHandle<anyhow::Result<()>>,
+)> {
+    let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(10);
+    let box_manager = Arc::new(Mutex::new(BoxManager::new()));
+    let box_task = tokio::spawn(handle_box(box_manager.clone(), cmd_rx));
+    let server = server::Server::new(box_manager, cmd_tx.clone());
+
+    let listener = tokio::net::TcpListener::bind(cli.listen).await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(log_error(server::serve_tcp(listener, server.clone())));
+    if let Some(http) = cli.http {
+        let listener = tokio::net::TcpListener::bind(http).await?;
+        tokio::spawn(log_error(server::serve_http(listener, server)));
+    }
+
+    if let Some(device) = cli.device.clone() {
+        cmd_tx.send(Cmd::Connect(device)).await?;
+    }
+    Ok((addr, cmd_tx, box_task))
 }
 
 fn main() -> anyhow::Result<()> {
@@ -18,18 +78,35 @@ fn main() -> anyhow::Result<()> {
         std::env::set_var("RUST_LOG", "info");
     }
     env_tracing_logger::init();
+    let cli = Cli::parse();
 
-    let (tx, cmd_rx) = tokio::sync::mpsc::channel(10);
+    if cli.headless {
+        return tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(async {
+                let (_addr, cmd_tx, box_task) = start_server(&cli).await?;
+                tokio::select! {
+                    result = tokio::signal::ctrl_c() => {
+                        result?;
+                        info!("sending Cmd::Quit command to serial loop");
+                        cmd_tx.send(Cmd::Quit).await.ok();
+                    }
+                    result = box_task => {
+                        result??;
+                    }
+                }
+                Ok(())
+            });
+    }
 
-    let available_ports = tokio_serial::available_ports()?
-        .iter()
-        .map(to_device_name)
-        .filter(|x| x != "/dev/ttyS0")
-        .collect();
-    let box_manager = Arc::new(Mutex::new(box_status::BoxManager::new()));
+    // The GUI is a client of the TCP server, which is started in this
+    // process unless connecting to a running server.
+    let client_state = Arc::new(Mutex::new(ClientState::default()));
+    let (req_tx, req_rx) = tokio::sync::mpsc::channel(10);
 
     let _tokio_join_handle = {
-        let box_manager = box_manager.clone();
+        let client_state = client_state.clone();
         std::thread::Builder::new()
             .name("tokio-thread".to_string())
             .spawn(move || {
@@ -38,7 +115,22 @@ fn main() -> anyhow::Result<()> {
                     .enable_all()
                     .build()
                     .unwrap()
-                    .block_on(async { handle_box(box_manager, cmd_rx).await })
+                    .block_on(async {
+                        let result = async {
+                            let addr = match cli.server {
+                                Some(addr) => addr,
+                                None => start_server(&cli).await?.0,
+                            };
+                            client::run_client(addr, client_state.clone(), req_rx).await
+                        }
+                        .await;
+                        if let Err(e) = &result {
+                            error!("{e}");
+                            let mut guard = client_state.lock();
+                            guard.error = Some(e.to_string());
+                            guard.request_repaint();
+                        }
+                    })
             })
             .map_err(|e| anyhow::anyhow!("runtime failed with error {e}"))?
     };
@@ -47,7 +139,7 @@ fn main() -> anyhow::Result<()> {
     eframe::run_native(
         "LED box control",
         native_options,
-        Box::new(|cc| Box::new(app::LedBoxApp::new(available_ports, box_manager, tx, cc))),
+        Box::new(|cc| Box::new(app::LedBoxApp::new(client_state, req_tx, cc))),
     )
     .map_err(|e| anyhow::anyhow!("running failed with error {e}"))?;
     Ok(())
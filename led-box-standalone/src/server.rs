@@ -0,0 +1,398 @@
+//! Network server sharing the LED box between programs.
+//!
+//! The serial port is owned by [handle_box](crate::box_status::handle_box).
+//! Clients control it with the JSON lines TCP API or the HTTP API:
+//!
+//! - `GET /status` returns the [BoxStatus].
+//! - `GET /ports` lists the serial devices.
+//! - `POST /connect` opens the serial device given as JSON string.
+//! - `POST /channel` sets a [led_box_comms::ChannelState].
+//! - `POST /state` sets a [led_box_comms::DeviceState].
+//! - `GET /events` streams the [BoxStatus] after each change as server-sent
+//!   events.
+
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{sse, IntoResponse, Sse},
+    routing::{get, post},
+    Json, Router,
+};
+use futures::{SinkExt, Stream, StreamExt};
+use parking_lot::Mutex;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::Decoder;
+
+use log::{debug, info, warn};
+
+use json_lines::codec::JsonLinesCodec;
+
+use crate::{
+    api::{check_channel, check_state, Request, Response},
+    box_status::{BoxManager, BoxStatus, Cmd},
+};
+
+fn to_device_name(spi: &tokio_serial::SerialPortInfo) -> String {
+    let name = spi.port_name.clone();
+    // This is necessary on linux:
+    name.replace("/sys/class/tty/", "/dev/")
+}
+
+pub fn available_ports() -> anyhow::Result<Vec<String>> {
+    Ok(tokio_serial::available_ports()?
+        .iter()
+        .map(to_device_name)
+        .filter(|x| x != "/dev/ttyS0")
+        .collect())
+}
+
+#[derive(Clone)]
+pub struct Server {
+    box_manager: Arc<Mutex<BoxManager>>,
+    cmd_tx: tokio::sync::mpsc::Sender<Cmd>,
+}
+
+impl Server {
+    pub fn new(
+        box_manager: Arc<Mutex<BoxManager>>,
+        cmd_tx: tokio::sync::mpsc::Sender<Cmd>,
+    ) -> Self {
+        Self {
+            box_manager,
+            cmd_tx,
+        }
+    }
+
+    fn subscribe(&self) -> tokio::sync::watch::Receiver<BoxStatus> {
+        self.box_manager.lock().subscribe()
+    }
+
+    async fn send_cmd(&self, cmd: Cmd) -> Response {
+        match self.cmd_tx.send(cmd).await {
+            Ok(()) => Response::Ok,
+            Err(_) => Response::Error("LED box task ended".into()),
+        }
+    }
+
+    pub async fn handle(&self, req: Request) -> Response {
+        let status = self.box_manager.lock().status();
+        let connected = status != BoxStatus::Unconnected;
+        match req {
+            Request::Connect(_) if connected => Response::Error("already connected".into()),
+            Request::Connect(device) => self.send_cmd(Cmd::Connect(device)).await,
+            Request::SetChannel(_) | Request::SetState(_) if !connected => {
+                Response::Error("not connected".into())
+            }
+            Request::SetChannel(chan) => match check_channel(&chan) {
+                Ok(()) => self.send_cmd(Cmd::SetChannel(chan)).await,
+                Err(e) => Response::Error(e),
+            },
+            Request::SetState(state) => match check_state(&state) {
+                Ok(()) => self.send_cmd(Cmd::SetState(state)).await,
+                Err(e) => Response::Error(e),
+            },
+            Request::GetStatus | Request::Subscribe => Response::Status(status),
+            Request::ListPorts => match available_ports() {
+                Ok(ports) => Response::Ports(ports),
+                Err(e) => Response::Error(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Serve the JSON lines TCP API.
+pub async fn serve_tcp(listener: TcpListener, server: Server) -> anyhow::Result<()> {
+    info!("LED box TCP server listening at {}", listener.local_addr()?);
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        debug!("TCP connection from {addr}");
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, server).await {
+                warn!("TCP connection from {addr}: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(stream: TcpStream, server: Server) -> anyhow::Result<()> {
+    let (mut writer, mut reader) = JsonLinesCodec::default().framed(stream).split();
+    let mut status_rx = server.subscribe();
+    let mut subscribed = false;
+    loop {
+        tokio::select! {
+            req = reader.next() => {
+                let req: Request = match req {
+                    Some(Ok(req)) => req,
+                    Some(Err(e)) => {
+                        writer.send(Response::Error(e.to_string())).await?;
+                        return Err(e.into());
+                    }
+                    None => return Ok(()),
+                };
+                if req == Request::Subscribe {
+                    subscribed = true;
+                    // The response contains the current status.
+                    status_rx.borrow_and_update();
+                }
+                writer.send(server.handle(req).await).await?;
+            }
+            changed = status_rx.changed(), if subscribed => {
+                if changed.is_err() {
+                    // The box manager was dropped.
+                    return Ok(());
+                }
+                let status = status_rx.borrow_and_update().clone();
+                writer.send(Response::Status(status)).await?;
+            }
+        }
+    }
+}
+
+fn to_http(response: Response) -> axum::response::Response {
+    match response {
+        Response::Error(_) => (StatusCode::BAD_REQUEST, Json(response)).into_response(),
+        response => Json(response).into_response(),
+    }
+}
+
+async fn get_status(State(server): State<Server>) -> axum::response::Response {
+    to_http(server.handle(Request::GetStatus).await)
+}
+
+async fn get_ports(State(server): State<Server>) -> axum::response::Response {
+    to_http(server.handle(Request::ListPorts).await)
+}
+
+async fn post_connect(
+    State(server): State<Server>,
+    Json(device): Json<String>,
+) -> axum::response::Response {
+    to_http(server.handle(Request::Connect(device)).await)
+}
+
+async fn post_channel(
+    State(server): State<Server>,
+    Json(chan): Json<led_box_comms::ChannelState>,
+) -> axum::response::Response {
+    to_http(server.handle(Request::SetChannel(chan)).await)
+}
+
+async fn post_state(
+    State(server): State<Server>,
+    Json(state): Json<led_box_comms::DeviceState>,
+) -> axum::response::Response {
+    to_http(server.handle(Request::SetState(state)).await)
+}
+
+async fn get_events(
+    State(server): State<Server>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let stream = tokio_stream::wrappers::WatchStream::new(server.subscribe()).map(|status| {
+        let event = sse::Event::default()
+            .json_data(Response::Status(status))
+            .unwrap();
+        Ok(event)
+    });
+    Sse::new(stream).keep_alive(sse::KeepAlive::default())
+}
+
+/// Serve the HTTP API.
+pub async fn serve_http(listener: TcpListener, server: Server) -> anyhow::Result<()> {
+    info!(
+        "LED box HTTP server listening at {}",
+        listener.local_addr()?
+    );
+    let router = Router::new()
+        .route("/status", get(get_status))
+        .route("/ports", get(get_ports))
+        .route("/connect", post(post_connect))
+        .route("/channel", post(post_channel))
+        .route("/state", post(post_state))
+        .route("/events", get(get_events))
+        .with_state(server);
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use led_box_comms::{ChannelState, DeviceState, OnState};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+    };
+
+    use super::*;
+
+    /// Apply the commands to `box_manager` like
+    /// [handle_box](crate::box_status::handle_box), but without a device.
+    /// Each command is also saved in `cmds`.
+    async fn fake_box(
+        box_manager: Arc<Mutex<BoxManager>>,
+        mut cmd_rx: tokio::sync::mpsc::Receiver<Cmd>,
+        cmds: Arc<Mutex<Vec<Cmd>>>,
+    ) {
+        while let Some(cmd) = cmd_rx.recv().await {
+            cmds.lock().push(cmd.clone());
+            let mut guard = box_manager.lock();
+            let mut state = match guard.status() {
+                BoxStatus::Connected(state) => state,
+                BoxStatus::Unconnected => DeviceState::default(),
+            };
+            match cmd {
+                Cmd::Connect(_) | Cmd::Quit => {}
+                Cmd::SetChannel(chan) => match chan.num {
+                    1 => state.ch1 = chan,
+                    2 => state.ch2 = chan,
+                    3 => state.ch3 = chan,
+                    4 => state.ch4 = chan,
+                    other => panic!("unknown channel {other}"),
+                },
+                Cmd::SetState(next_state) => state = next_state,
+            }
+            guard.set_state_without_device(state);
+        }
+    }
+
+    /// Start the TCP server for a fake LED box. Returns the address of the
+    /// server and the commands which reach the box.
+    async fn start_server() -> (SocketAddr, Arc<Mutex<Vec<Cmd>>>) {
+        let box_manager = Arc::new(Mutex::new(BoxManager::new()));
+        let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(10);
+        let cmds = Arc::new(Mutex::new(Vec::new()));
+        tokio::spawn(fake_box(box_manager.clone(), cmd_rx, cmds.clone()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_tcp(listener, Server::new(box_manager, cmd_tx)));
+        (addr, cmds)
+    }
+
+    /// Client of the TCP server writing the JSON lines itself.
+    struct TestClient {
+        reader: tokio::io::Lines<BufReader<OwnedReadHalf>>,
+        writer: OwnedWriteHalf,
+    }
+
+    impl TestClient {
+        async fn connect(addr: SocketAddr) -> Self {
+            let (reader, writer) = TcpStream::connect(addr).await.unwrap().into_split();
+            Self {
+                reader: BufReader::new(reader).lines(),
+                writer,
+            }
+        }
+
+        async fn recv(&mut self) -> Response {
+            let line = tokio::time::timeout(Duration::from_secs(5), self.reader.next_line())
+                .await
+                .expect("no response")
+                .unwrap()
+                .expect("connection closed");
+            serde_json::from_str(&line).unwrap()
+        }
+
+        async fn request(&mut self, req: Request) -> Response {
+            let mut buf = serde_json::to_vec(&req).unwrap();
+            buf.push(b'\n');
+            self.writer.write_all(&buf).await.unwrap();
+            self.recv().await
+        }
+
+        /// Connect to the LED box and wait until the server reports it.
+        async fn connect_box(&mut self) -> DeviceState {
+            assert_eq!(
+                self.request(Request::Subscribe).await,
+                Response::Status(BoxStatus::Unconnected)
+            );
+            let device = "/dev/ttyACM0".to_string();
+            assert_eq!(self.request(Request::Connect(device)).await, Response::Ok);
+            match self.recv().await {
+                Response::Status(BoxStatus::Connected(state)) => state,
+                other => panic!("unexpected response {other:?}"),
+            }
+        }
+    }
+
+    fn is_error(response: &Response) -> bool {
+        matches!(response, Response::Error(_))
+    }
+
+    #[tokio::test]
+    async fn test_set_requires_connection() {
+        let (addr, cmds) = start_server().await;
+        let mut client = TestClient::connect(addr).await;
+
+        let response = client
+            .request(Request::SetChannel(ChannelState::default(1)))
+            .await;
+        assert_eq!(response, Response::Error("not connected".into()));
+        let response = client
+            .request(Request::SetState(DeviceState::default()))
+            .await;
+        assert_eq!(response, Response::Error("not connected".into()));
+        assert_eq!(
+            client.request(Request::GetStatus).await,
+            Response::Status(BoxStatus::Unconnected)
+        );
+        assert!(cmds.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_values_rejected() {
+        let (addr, cmds) = start_server().await;
+        let mut client = TestClient::connect(addr).await;
+        let state = client.connect_box().await;
+
+        let mut chan = ChannelState::default(5);
+        assert!(is_error(&client.request(Request::SetChannel(chan)).await));
+        chan.num = 1;
+        chan.intensity = led_box_comms::MAX_INTENSITY + 1;
+        assert!(is_error(&client.request(Request::SetChannel(chan)).await));
+
+        let mut bad_state = state;
+        bad_state.ch2.num = 3;
+        assert!(is_error(
+            &client.request(Request::SetState(bad_state)).await
+        ));
+        let mut bad_state = state;
+        bad_state.ch4.intensity = led_box_comms::MAX_INTENSITY + 1;
+        assert!(is_error(
+            &client.request(Request::SetState(bad_state)).await
+        ));
+
+        assert_eq!(*cmds.lock(), vec![Cmd::Connect("/dev/ttyACM0".into())]);
+        assert_eq!(
+            client.request(Request::GetStatus).await,
+            Response::Status(BoxStatus::Connected(state))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_pushes_changes() {
+        let (addr, _cmds) = start_server().await;
+        let mut subscriber = TestClient::connect(addr).await;
+        let mut state = subscriber.connect_box().await;
+
+        let mut client = TestClient::connect(addr).await;
+        let chan = ChannelState {
+            num: 2,
+            on_state: OnState::ConstantOn,
+            intensity: 1000,
+        };
+        assert_eq!(
+            client.request(Request::SetChannel(chan)).await,
+            Response::Ok
+        );
+
+        state.ch2 = chan;
+        assert_eq!(
+            subscriber.recv().await,
+            Response::Status(BoxStatus::Connected(state))
+        );
+    }
+}
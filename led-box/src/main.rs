@@ -10,10 +10,14 @@ use log::info;
 use clap::Parser;
 
 use json_lines::codec::JsonLinesCodec;
-use led_box_comms::{ChannelState, DeviceState, OnState, ToDevice};
+use led_box_comms::{ChannelState, DeviceState, OnState, PulseTrain, StartCondition, ToDevice};
 
 /// this handles the serial port and therefore the interaction with the device
-async fn try_serial(serial_device: &str, next_state: &DeviceState) {
+async fn try_serial(
+    serial_device: &str,
+    next_state: &DeviceState,
+    pulse_train: Option<(PulseTrain, StartCondition)>,
+) {
     info!(
         "opening serial port at {} baud. Using encoding '{}'",
         led_box_comms::BAUD_RATE,
@@ -30,6 +34,8 @@ async fn try_serial(serial_device: &str, next_state: &DeviceState) {
 
     let (mut writer, mut reader) = JsonLinesCodec::default().framed(port).split();
 
+    writer.send(ToDevice::VersionRequest).await.unwrap();
+
     let msg = ToDevice::DeviceState(*next_state);
     info!("sending: {:?}", msg);
     writer.send(msg).await.unwrap();
@@ -66,14 +72,50 @@ async fn try_serial(serial_device: &str, next_state: &DeviceState) {
                         Some(Ok(led_box_comms::FromDevice::VersionResponse(found))) => {
                             info!("Found comm version {found}.");
                             let expected = led_box_comms::COMM_VERSION;
-                            if found != expected {
+                            if !led_box_comms::is_compatible_version(found) {
                                 log::error!("This program compiled to support comm version {expected}, but found version {found}.");
                                 return;
                             }
+                            if let Some((train, start)) = pulse_train {
+                                if !led_box_comms::supports_pulse_trains(found) {
+                                    log::error!("Pulse trains need comm version {}, but found version {found}.", led_box_comms::PULSE_TRAIN_COMM_VERSION);
+                                    return;
+                                }
+                                writer.send(ToDevice::LoadPulseTrain(train)).await.unwrap();
+                                writer.send(ToDevice::StartPulseTrains(start)).await.unwrap();
+                            }
                         }
                         Some(Ok(led_box_comms::FromDevice::StateWasSet)) => {
                             info!("state was set");
                         }
+                        Some(Ok(led_box_comms::FromDevice::PulseTrainLoaded(num))) => {
+                            info!("pulse train loaded on channel {num}");
+                        }
+                        Some(Ok(led_box_comms::FromDevice::PulseTrainError(e))) => {
+                            log::error!("pulse train error: {e}");
+                        }
+                        Some(Ok(led_box_comms::FromDevice::PulseTrainsArmed)) => {
+                            info!("waiting for external trigger");
+                        }
+                        Some(Ok(led_box_comms::FromDevice::PulseTrainsStarted(t))) => {
+                            info!("pulse trains started at device time {t} usec");
+                        }
+                        Some(Ok(led_box_comms::FromDevice::PulseEdge(edge))) => {
+                            info!(
+                                "channel {} pulse {} {} at device time {} usec ({} usec late)",
+                                edge.channel,
+                                edge.pulse,
+                                if edge.on { "on" } else { "off" },
+                                edge.executed_us,
+                                edge.executed_us - edge.scheduled_us
+                            );
+                        }
+                        Some(Ok(led_box_comms::FromDevice::PulseTrainsFinished(t))) => {
+                            info!("pulse trains finished at device time {t} usec");
+                        }
+                        Some(Ok(led_box_comms::FromDevice::PulseTrainsStopped)) => {
+                            info!("pulse trains stopped");
+                        }
                         Some(Ok(led_box_comms::FromDevice::DeviceState(_))) => {}
                         Some(Err(e)) => {
                             panic!("unexpected error: {}: {:?}", e, e);
@@ -108,6 +150,21 @@ struct Cli {
     all_leds_on: bool,
     #[arg(long)]
     all_leds_off: bool,
+    /// Run a train of pulses, timed by the device, on this channel
+    #[arg(long)]
+    pulse_channel: Option<u8>,
+    /// Duration of each pulse, in milliseconds
+    #[arg(long, default_value_t = 10.0)]
+    pulse_duration_ms: f64,
+    /// Time from the onset of one pulse to the next, in milliseconds
+    #[arg(long, default_value_t = 100.0)]
+    pulse_period_ms: f64,
+    /// Number of pulses (0 repeats until stopped)
+    #[arg(long, default_value_t = 10)]
+    pulse_count: u16,
+    /// Start the pulse train on a rising edge of the external trigger input
+    #[arg(long)]
+    wait_for_trigger: bool,
 }
 
 #[tokio::main]
@@ -137,7 +194,30 @@ async fn main() -> anyhow::Result<()> {
         ch4: make_chan(4, on_state),
     };
 
-    try_serial(&device_name, &next_state).await;
+    let pulse_train = cli.pulse_channel.map(|channel| {
+        let train = PulseTrain {
+            channel,
+            onset_us: 0,
+            duration_us: (cli.pulse_duration_ms * 1000.0).round() as u32,
+            period_us: (cli.pulse_period_ms * 1000.0).round() as u32,
+            repeat_count: cli.pulse_count,
+            start_intensity: led_box_comms::MAX_INTENSITY,
+            end_intensity: led_box_comms::MAX_INTENSITY,
+        };
+        let start = if cli.wait_for_trigger {
+            StartCondition::ExternalEdge(led_box_comms::TriggerEdge::Rising)
+        } else {
+            StartCondition::Immediate
+        };
+        (train, start)
+    });
+    if let Some((train, _)) = pulse_train.as_ref() {
+        train
+            .validate()
+            .map_err(|e| anyhow::anyhow!("invalid pulse train: {e}"))?;
+    }
+
+    try_serial(&device_name, &next_state, pulse_train).await;
 
     Ok(())
 }
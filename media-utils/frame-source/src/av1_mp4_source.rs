@@ -0,0 +1,632 @@
+// Copyright 2024 Andrew D. Straw.
+
+//! Reader for MP4 files with AV1 video, as saved by `mp4-writer`.
+//!
+//! The `mp4` crate is used to read sample data and timing, but as it does not
+//! know the `av01` sample entry, the track configuration and our metadata
+//! (stored in a `uuid` box with [H264_METADATA_UUID]) are parsed here.
+//!
+//! Decoding requires the `av1-decode` feature, which uses libdav1d. Without
+//! decoding, frames are returned as [ImageData::EncodedAv1].
+
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use color_eyre::eyre::{self as anyhow, WrapErr};
+
+use ci2_remote_control::{H264Metadata, H264_METADATA_UUID, H264_METADATA_VERSION};
+
+use crate::{
+    h264_source::SeekRead, FrameData, FrameDataSource, ImageData, Result, Timestamp,
+    TimestampSource,
+};
+
+/// Information parsed from the MP4 boxes which the `mp4` crate ignores.
+#[derive(Debug, Clone, PartialEq)]
+struct Av1TrackInfo {
+    width: u32,
+    height: u32,
+    /// Bits per sample, from the `av1C` box (8 if it is missing).
+    bit_depth: u8,
+    h264_metadata: Option<H264Metadata>,
+}
+
+pub struct Av1Mp4Source {
+    mp4_reader: mp4::Mp4Reader<Box<dyn SeekRead + Send>>,
+    track_id: u32,
+    /// timestamps from MP4 samples, one per frame
+    mp4_pts: Vec<std::time::Duration>,
+    width: u32,
+    height: u32,
+    h264_metadata: Option<H264Metadata>,
+    do_decode: bool,
+}
+
+impl FrameDataSource for Av1Mp4Source {
+    fn width(&self) -> u32 {
+        self.width
+    }
+    fn height(&self) -> u32 {
+        self.height
+    }
+    fn camera_name(&self) -> Option<&str> {
+        self.h264_metadata
+            .as_ref()
+            .and_then(|x| x.camera_name.as_deref())
+    }
+    fn gamma(&self) -> Option<f32> {
+        self.h264_metadata.as_ref().and_then(|x| x.gamma)
+    }
+    fn frame0_time(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        // `mp4-writer` sets the creation time to the time of the first frame.
+        self.h264_metadata.as_ref().map(|x| x.creation_time)
+    }
+    fn skip_n_frames(&mut self, n_frames: usize) -> Result<()> {
+        if n_frames > 0 {
+            anyhow::bail!("Skipping frames with AV1 file is not supported.");
+        }
+        Ok(())
+    }
+    fn estimate_luminance_range(&mut self) -> Result<(u16, u16)> {
+        anyhow::bail!("AV1 luminance scanning not implemented");
+    }
+    fn has_timestamps(&self) -> bool {
+        true
+    }
+    fn timestamp_source(&self) -> &str {
+        "MP4 PTS"
+    }
+    fn iter<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<FrameData>> + 'a> {
+        Box::new(Av1Iter {
+            parent: self,
+            frame_idx: 0,
+            #[cfg(feature = "av1-decode")]
+            decoder: None,
+        })
+    }
+}
+
+struct Av1Iter<'parent> {
+    parent: &'parent mut Av1Mp4Source,
+    frame_idx: usize,
+    #[cfg(feature = "av1-decode")]
+    decoder: Option<dav1d_decoder::Av1Decoder>,
+}
+
+impl<'parent> Iterator for Av1Iter<'parent> {
+    type Item = Result<FrameData>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.frame_idx;
+        let pts = *self.parent.mp4_pts.get(idx)?;
+        self.frame_idx += 1;
+        Some(self.read_frame(idx, pts))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.parent.mp4_pts.len() - self.frame_idx;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'parent> Av1Iter<'parent> {
+    fn read_frame(&mut self, idx: usize, pts: std::time::Duration) -> Result<FrameData> {
+        // mp4 uses 1 based indexing
+        let sample_id = u32::try_from(idx + 1).unwrap();
+        let sample = self
+            .parent
+            .mp4_reader
+            .read_sample(self.parent.track_id, sample_id)?
+            .ok_or_else(|| anyhow::anyhow!("sample in track disappeared"))?;
+        let buf_len = sample.bytes.len();
+
+        let image = if self.parent.do_decode {
+            self.decode(sample.bytes.to_vec(), idx, pts)?
+        } else {
+            ImageData::EncodedAv1(sample.bytes.to_vec())
+        };
+
+        Ok(FrameData {
+            timestamp: Timestamp::Duration(pts),
+            image,
+            buf_len,
+            idx,
+        })
+    }
+
+    #[cfg(feature = "av1-decode")]
+    fn decode(
+        &mut self,
+        sample: Vec<u8>,
+        idx: usize,
+        pts: std::time::Duration,
+    ) -> Result<ImageData> {
+        let host_timestamp = match &self.parent.h264_metadata {
+            Some(md) => {
+                md.creation_time.with_timezone(&chrono::Utc)
+                    + chrono::Duration::from_std(pts).unwrap()
+            }
+            // No possible source of timestamp, use dummy value.
+            None => chrono::TimeZone::timestamp_opt(&chrono::Utc, 0, 0).unwrap(),
+        };
+
+        let decoder = match &mut self.decoder {
+            Some(decoder) => decoder,
+            None => self.decoder.insert(dav1d_decoder::Av1Decoder::new()?),
+        };
+        let frame = decoder.decode(sample, host_timestamp, idx)?;
+        Ok(ImageData::Decoded(frame))
+    }
+
+    #[cfg(not(feature = "av1-decode"))]
+    fn decode(
+        &mut self,
+        _sample: Vec<u8>,
+        _idx: usize,
+        _pts: std::time::Duration,
+    ) -> Result<ImageData> {
+        unreachable!("decoding is rejected when opening the source");
+    }
+}
+
+#[cfg(feature = "av1-decode")]
+mod dav1d_decoder {
+    use color_eyre::eyre::{self as anyhow};
+    use dav1d::{PixelLayout, PlanarImageComponent};
+
+    use basic_frame::{BasicExtra, BasicFrame, DynamicFrame};
+    use machine_vision_formats::pixel_format::{Mono8, NV12};
+
+    use crate::Result;
+
+    pub(super) struct Av1Decoder {
+        inner: dav1d::Decoder,
+    }
+
+    impl Av1Decoder {
+        pub(super) fn new() -> Result<Self> {
+            let mut settings = dav1d::Settings::new();
+            // Return each picture as soon as it is decoded.
+            settings.set_max_frame_delay(1);
+            let inner = dav1d::Decoder::with_settings(&settings)
+                .map_err(|e| anyhow::anyhow!("creating dav1d decoder: {e:?}"))?;
+            Ok(Self { inner })
+        }
+
+        pub(super) fn decode(
+            &mut self,
+            sample: Vec<u8>,
+            host_timestamp: chrono::DateTime<chrono::Utc>,
+            host_framenumber: usize,
+        ) -> Result<DynamicFrame> {
+            if let Err(e) = self.inner.send_data(sample, None, None, None) {
+                if !e.is_again() {
+                    anyhow::bail!("dav1d decoding failed: {e:?}");
+                }
+            }
+            let pic = self
+                .inner
+                .get_picture()
+                .map_err(|e| anyhow::anyhow!("dav1d did not return a picture: {e:?}"))?;
+            if pic.bit_depth() != 8 {
+                anyhow::bail!(
+                    "Cannot decode {}-bit AV1 video. Only 8-bit video can be decoded.",
+                    pic.bit_depth()
+                );
+            }
+
+            let width = pic.width();
+            let height = pic.height();
+            let extra = Box::new(BasicExtra {
+                host_timestamp,
+                host_framenumber,
+            });
+
+            let y_stride = pic.stride(PlanarImageComponent::Y) as usize;
+            let y_plane = pic.plane(PlanarImageComponent::Y);
+            let y_plane: &[u8] = y_plane.as_ref();
+            let mut image_data = Vec::with_capacity(width as usize * height as usize * 3 / 2);
+            for row in y_plane.chunks(y_stride).take(height as usize) {
+                image_data.extend_from_slice(&row[..width as usize]);
+            }
+
+            let frame = match pic.pixel_layout() {
+                PixelLayout::I400 => DynamicFrame::Mono8(BasicFrame::<Mono8> {
+                    width,
+                    height,
+                    stride: width,
+                    image_data,
+                    pixel_format: std::marker::PhantomData,
+                    extra,
+                }),
+                PixelLayout::I420 => {
+                    // Interleave the chroma planes to NV12.
+                    let uv_stride = pic.stride(PlanarImageComponent::U) as usize;
+                    let u_plane = pic.plane(PlanarImageComponent::U);
+                    let u_plane: &[u8] = u_plane.as_ref();
+                    let v_plane = pic.plane(PlanarImageComponent::V);
+                    let v_plane: &[u8] = v_plane.as_ref();
+                    let uv_width = width.div_ceil(2) as usize;
+                    for (u_row, v_row) in u_plane
+                        .chunks(uv_stride)
+                        .zip(v_plane.chunks(uv_stride))
+                        .take(height.div_ceil(2) as usize)
+                    {
+                        let mut row = Vec::with_capacity(width as usize);
+                        for (u, v) in u_row[..uv_width].iter().zip(&v_row[..uv_width]) {
+                            row.push(*u);
+                            row.push(*v);
+                        }
+                        row.truncate(width as usize);
+                        image_data.extend(row);
+                    }
+                    DynamicFrame::NV12(BasicFrame::<NV12> {
+                        width,
+                        height,
+                        stride: width,
+                        image_data,
+                        pixel_format: std::marker::PhantomData,
+                        extra,
+                    })
+                }
+                layout => {
+                    anyhow::bail!("AV1 pixel layout {layout:?} not supported");
+                }
+            };
+            Ok(frame)
+        }
+    }
+}
+
+/// Check if the MP4 file at `path` contains AV1 video.
+///
+/// Files which cannot be opened or parsed are reported as not containing AV1
+/// video, so that the (more thorough) MP4 reader reports the problem.
+pub fn is_av1_mp4<P: AsRef<Path>>(path: P) -> bool {
+    let rdr = match std::fs::File::open(path.as_ref()) {
+        Ok(rdr) => rdr,
+        Err(_) => return false,
+    };
+    match read_av1_track_info(&mut std::io::BufReader::new(rdr)) {
+        Ok(info) => info.is_some(),
+        Err(e) => {
+            tracing::debug!(
+                "Could not check {} for AV1 video: {e}",
+                path.as_ref().display()
+            );
+            false
+        }
+    }
+}
+
+/// Open an AV1 MP4 file.
+///
+/// AV1 files only have MP4 PTS timestamps, so `timestamp_source` must be
+/// [TimestampSource::BestGuess] or [TimestampSource::Mp4Pts]. If `do_decode`
+/// is true, frames are decoded, which requires the `av1-decode` feature.
+pub fn from_path_with_timestamp_source<P: AsRef<Path>>(
+    path: P,
+    do_decode: bool,
+    timestamp_source: TimestampSource,
+) -> Result<Av1Mp4Source> {
+    let rdr = std::fs::File::open(path.as_ref())
+        .with_context(|| format!("Opening {}", path.as_ref().display()))?;
+    let size = rdr.metadata()?.len();
+    let buf_reader: Box<(dyn SeekRead + Send + 'static)> = Box::new(std::io::BufReader::new(rdr));
+    from_reader_with_timestamp_source(buf_reader, size, do_decode, timestamp_source)
+        .with_context(|| format!("Reading MP4 file {}", path.as_ref().display()))
+}
+
+pub(crate) fn from_reader_with_timestamp_source(
+    mut rdr: Box<dyn SeekRead + Send>,
+    size: u64,
+    do_decode: bool,
+    timestamp_source: TimestampSource,
+) -> Result<Av1Mp4Source> {
+    match timestamp_source {
+        TimestampSource::BestGuess | TimestampSource::Mp4Pts => {}
+        TimestampSource::FrameInfoRecvTime | TimestampSource::MispMicrosectime => {
+            anyhow::bail!(
+                "Requested timestamp source {timestamp_source:?}, but AV1 files only have MP4 PTS."
+            );
+        }
+    }
+    if do_decode && !cfg!(feature = "av1-decode") {
+        anyhow::bail!(
+            "Cannot decode AV1 video: frame-source was built without the `av1-decode` feature."
+        );
+    }
+
+    let info = read_av1_track_info(&mut rdr)?
+        .ok_or_else(|| anyhow::anyhow!("No AV1 video track found in MP4 file."))?;
+    if do_decode && info.bit_depth != 8 {
+        anyhow::bail!(
+            "Cannot decode {}-bit AV1 video. Only 8-bit video can be decoded.",
+            info.bit_depth
+        );
+    }
+    rdr.seek(SeekFrom::Start(0))?;
+    let mut mp4_reader = mp4::Mp4Reader::read_header(rdr, size)?;
+
+    let mut video_tracks = mp4_reader
+        .tracks()
+        .iter()
+        .filter(|(_, track)| matches!(track.track_type(), Ok(mp4::TrackType::Video)))
+        .map(|(track_id, _)| *track_id);
+    let track_id = video_tracks
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No video track found in MP4 file."))?;
+    if video_tracks.next().is_some() {
+        anyhow::bail!("only MP4 files with a single AV1 video track are supported");
+    }
+
+    let timescale = mp4_reader.timescale();
+    let num_samples = mp4_reader.sample_count(track_id)?;
+    let mut mp4_pts = Vec::with_capacity(num_samples as usize);
+    // mp4 uses 1 based indexing
+    for sample_id in 1..=num_samples {
+        let (start_time, _duration) = mp4_reader.sample_time_duration(track_id, sample_id)?;
+        mp4_pts.push(std::time::Duration::from_secs_f64(
+            start_time as f64 / timescale as f64,
+        ));
+    }
+
+    Ok(Av1Mp4Source {
+        mp4_reader,
+        track_id,
+        mp4_pts,
+        width: info.width,
+        height: info.height,
+        h264_metadata: info.h264_metadata,
+        do_decode,
+    })
+}
+
+/// Largest box payload read into memory while looking for AV1 video.
+///
+/// Only the `stsd` box with the sample entries and our metadata are read, so
+/// larger boxes indicate a corrupt file.
+const MAX_PROBE_PAYLOAD_SIZE: u64 = 1024 * 1024;
+
+/// Parse the boxes of an MP4 file for an `av01` sample entry.
+///
+/// Returns `None` if there is no AV1 track.
+fn read_av1_track_info<R: Read + Seek>(rdr: &mut R) -> Result<Option<Av1TrackInfo>> {
+    rdr.seek(SeekFrom::Start(0))?;
+    let mut h264_metadata = None;
+    let mut entry = None;
+    while let Some((box_type, size)) = read_box_header(rdr)? {
+        let end = box_end(rdr, size)?;
+        match &box_type {
+            b"moov" if entry.is_none() => {
+                entry = find_av01_entry(rdr, end)?;
+            }
+            b"uuid" if size >= 16 => {
+                let mut uuid = [0u8; 16];
+                rdr.read_exact(&mut uuid)?;
+                if uuid == H264_METADATA_UUID {
+                    let payload = read_payload(rdr, size - 16)?;
+                    let md: H264Metadata = serde_json::from_slice(&payload)?;
+                    if md.version != H264_METADATA_VERSION {
+                        anyhow::bail!("unexpected version in metadata");
+                    }
+                    h264_metadata = Some(md);
+                }
+            }
+            _ => {}
+        }
+        rdr.seek(SeekFrom::Start(end))?;
+    }
+    Ok(entry.map(|(width, height, bit_depth)| Av1TrackInfo {
+        width,
+        height,
+        bit_depth,
+        h264_metadata,
+    }))
+}
+
+/// The file position after a box with payload `size` starting at the current
+/// position.
+fn box_end<R: Seek>(rdr: &mut R, size: u64) -> Result<u64> {
+    rdr.stream_position()?
+        .checked_add(size)
+        .ok_or_else(|| anyhow::anyhow!("invalid MP4 box size"))
+}
+
+/// Read a box payload of `size` bytes, which must be small.
+fn read_payload<R: Read>(rdr: &mut R, size: u64) -> Result<Vec<u8>> {
+    if size > MAX_PROBE_PAYLOAD_SIZE {
+        anyhow::bail!("MP4 box of {size} bytes too large");
+    }
+    let mut payload = vec![0u8; size as usize];
+    rdr.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Find the width, height and bit depth of an `av01` sample entry in the
+/// `moov` box ending at `moov_end`.
+fn find_av01_entry<R: Read + Seek>(rdr: &mut R, moov_end: u64) -> Result<Option<(u32, u32, u8)>> {
+    const PATH: [&[u8; 4]; 4] = [b"mdia", b"minf", b"stbl", b"stsd"];
+    while rdr.stream_position()? < moov_end {
+        let Some((box_type, size)) = read_box_header(rdr)? else {
+            break;
+        };
+        let trak_end = box_end(rdr, size)?;
+        if &box_type == b"trak" {
+            if let Some(stsd_size) = find_child_box(rdr, trak_end, &PATH)? {
+                let stsd = read_payload(rdr, stsd_size)?;
+                if let Some(entry) = parse_av01_entry(&stsd)? {
+                    return Ok(Some(entry));
+                }
+            }
+        }
+        rdr.seek(SeekFrom::Start(trak_end))?;
+    }
+    Ok(None)
+}
+
+/// Seek to the payload of the box at `path` below the box ending at `end`.
+///
+/// Returns the payload size, or `None` if there is no such box.
+fn find_child_box<R: Read + Seek>(
+    rdr: &mut R,
+    mut end: u64,
+    path: &[&[u8; 4]],
+) -> Result<Option<u64>> {
+    'path: for name in path {
+        while rdr.stream_position()? < end {
+            let Some((box_type, size)) = read_box_header(rdr)? else {
+                return Ok(None);
+            };
+            let child_end = box_end(rdr, size)?;
+            if &&box_type == name {
+                end = child_end;
+                continue 'path;
+            }
+            rdr.seek(SeekFrom::Start(child_end))?;
+        }
+        return Ok(None);
+    }
+    Ok(Some(end - rdr.stream_position()?))
+}
+
+/// Read a box header, returning the box type and payload size.
+fn read_box_header<R: Read + Seek>(rdr: &mut R) -> Result<Option<([u8; 4], u64)>> {
+    let mut header = [0u8; 8];
+    match rdr.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let size = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let box_type: [u8; 4] = header[4..8].try_into().unwrap();
+    let payload_size = match size {
+        0 => {
+            // box extends to end of file
+            let pos = rdr.stream_position()?;
+            let end = rdr.seek(SeekFrom::End(0))?;
+            rdr.seek(SeekFrom::Start(pos))?;
+            end - pos
+        }
+        1 => {
+            let mut largesize = [0u8; 8];
+            rdr.read_exact(&mut largesize)?;
+            u64::from_be_bytes(largesize)
+                .checked_sub(16)
+                .ok_or_else(|| anyhow::anyhow!("invalid MP4 box size"))?
+        }
+        size => u64::from(size)
+            .checked_sub(8)
+            .ok_or_else(|| anyhow::anyhow!("invalid MP4 box size"))?,
+    };
+    Ok(Some((box_type, payload_size)))
+}
+
+/// Iterate over the child boxes in `buf`.
+fn child_boxes(mut buf: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> + '_ {
+    std::iter::from_fn(move || {
+        if buf.len() < 8 {
+            return None;
+        }
+        let size = u32::from_be_bytes(buf[0..4].try_into().unwrap()) as usize;
+        if size < 8 || size > buf.len() {
+            return None;
+        }
+        let box_type: [u8; 4] = buf[4..8].try_into().unwrap();
+        let payload = &buf[8..size];
+        buf = &buf[size..];
+        Some((box_type, payload))
+    })
+}
+
+/// Find the width, height and bit depth of an `av01` sample entry in a `stsd`
+/// payload.
+fn parse_av01_entry(stsd: &[u8]) -> Result<Option<(u32, u32, u8)>> {
+    // Skip version, flags and entry count.
+    if stsd.len() < 8 {
+        anyhow::bail!("stsd box too short");
+    }
+    for (entry_type, entry) in child_boxes(&stsd[8..]) {
+        if &entry_type == b"av01" {
+            // Width and height follow 24 bytes of visual sample entry fields.
+            // The child boxes follow the 78 bytes of all these fields.
+            if entry.len() < 78 {
+                anyhow::bail!("av01 box too short");
+            }
+            let width = u16::from_be_bytes(entry[24..26].try_into().unwrap());
+            let height = u16::from_be_bytes(entry[26..28].try_into().unwrap());
+            let bit_depth = child_boxes(&entry[78..])
+                .find(|(t, _)| t == b"av1C")
+                .and_then(|(_, av1c)| av1c.get(2))
+                .map(|flags| {
+                    // high_bitdepth and twelve_bit flags
+                    match (flags & 0x40 != 0, flags & 0x20 != 0) {
+                        (false, _) => 8,
+                        (true, false) => 10,
+                        (true, true) => 12,
+                    }
+                })
+                .unwrap_or(8);
+            return Ok(Some((width.into(), height.into(), bit_depth)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let size = u32::try_from(payload.len() + 8).unwrap();
+        [&size.to_be_bytes()[..], box_type, payload].concat()
+    }
+
+    /// A `moov` box with a single track with an `av01` sample entry.
+    fn av01_moov(av1c_flags: u8) -> Vec<u8> {
+        let mut av01 = vec![0u8; 78];
+        av01[24..26].copy_from_slice(&640u16.to_be_bytes());
+        av01[26..28].copy_from_slice(&480u16.to_be_bytes());
+        av01.extend(mp4_box(b"av1C", &[0x81, 0x08, av1c_flags, 0x00]));
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(mp4_box(b"av01", &av01));
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+        let mdia = mp4_box(b"mdia", &mp4_box(b"minf", &stbl));
+        let trak = [mp4_box(b"tkhd", &[0u8; 84]), mdia].concat();
+        mp4_box(b"moov", &mp4_box(b"trak", &trak))
+    }
+
+    #[test]
+    fn av1_track_info() {
+        let ftyp = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        let file = [ftyp.clone(), av01_moov(0x0c)].concat();
+        let info = read_av1_track_info(&mut std::io::Cursor::new(file))
+            .unwrap()
+            .unwrap();
+        assert_eq!((info.width, info.height, info.bit_depth), (640, 480, 8));
+
+        let file = [ftyp, av01_moov(0x4c)].concat();
+        let info = read_av1_track_info(&mut std::io::Cursor::new(file))
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.bit_depth, 10);
+    }
+
+    #[test]
+    fn oversized_boxes_not_read() {
+        // A `moov` box claiming to be larger than the file.
+        let mut file = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        file.extend(0xffff_fff0u32.to_be_bytes());
+        file.extend(b"moov");
+        let info = read_av1_track_info(&mut std::io::Cursor::new(file)).unwrap();
+        assert!(info.is_none());
+
+        // A `stsd` box too large to be read.
+        let mut stbl = 0x7fff_fff0u32.to_be_bytes().to_vec();
+        stbl.extend(b"stsd");
+        let mdia = mp4_box(b"mdia", &mp4_box(b"minf", &mp4_box(b"stbl", &stbl)));
+        let file = mp4_box(b"moov", &mp4_box(b"trak", &mdia));
+        assert!(read_av1_track_info(&mut std::io::Cursor::new(file)).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+// Copyright 2024 Andrew D. Straw.
+
+//! Decoder for monochrome H264 streams consisting only of I_PCM macroblocks.
+//!
+//! This is what LessAVC produces. OpenH264 does not decode bit depths above 8,
+//! so for high bit depth data (e.g. 12 bit monochrome) we decode ourselves.
+//! Since I_PCM macroblocks store raw samples, this is simple: parse the slice
+//! header and copy the samples out.
+
+use std::io::Read;
+
+use color_eyre::eyre::{self as anyhow};
+use h264_reader::nal::{
+    pps::PicParameterSet,
+    sps::{ChromaFormat, FrameMbsFlags, PicOrderCntType, SeqParameterSet},
+    Nal, RefNal, UnitType,
+};
+
+use crate::Result;
+
+/// The value of `mb_type` for I_PCM macroblocks in I slices.
+const MB_TYPE_I_PCM: u32 = 25;
+
+/// Parameters from the SPS and PPS needed to decode I_PCM slices.
+#[derive(Debug, Clone)]
+pub(crate) struct PcmParams {
+    bit_depth: u8,
+    width_mbs: u32,
+    height_mbs: u32,
+    log2_max_frame_num: u32,
+    poc: PocParams,
+    bottom_field_pic_order_in_frame_present: bool,
+    redundant_pic_cnt_present: bool,
+    deblocking_filter_control_present: bool,
+    /// Cropping rectangle (left, top, width, height) in pixels.
+    crop: (u32, u32, u32, u32),
+}
+
+#[derive(Debug, Clone)]
+enum PocParams {
+    TypeZero { log2_max_pic_order_cnt_lsb: u32 },
+    TypeOne { delta_pic_order_always_zero: bool },
+    TypeTwo,
+}
+
+impl PcmParams {
+    pub(crate) fn new(sps: &SeqParameterSet, pps: &PicParameterSet) -> Result<Self> {
+        if !matches!(sps.chroma_info.chroma_format, ChromaFormat::Monochrome) {
+            anyhow::bail!("I_PCM decoding only supported for monochrome data");
+        }
+        if !matches!(sps.frame_mbs_flags, FrameMbsFlags::Frames) {
+            anyhow::bail!("I_PCM decoding only supported for progressive frames");
+        }
+        if pps.entropy_coding_mode_flag {
+            anyhow::bail!("I_PCM decoding only supported with CAVLC entropy coding");
+        }
+        let poc = match &sps.pic_order_cnt {
+            PicOrderCntType::TypeZero {
+                log2_max_pic_order_cnt_lsb_minus4,
+            } => PocParams::TypeZero {
+                log2_max_pic_order_cnt_lsb: u32::from(*log2_max_pic_order_cnt_lsb_minus4) + 4,
+            },
+            PicOrderCntType::TypeOne {
+                delta_pic_order_always_zero_flag,
+                ..
+            } => PocParams::TypeOne {
+                delta_pic_order_always_zero: *delta_pic_order_always_zero_flag,
+            },
+            PicOrderCntType::TypeTwo => PocParams::TypeTwo,
+        };
+        let (width, height) = sps
+            .pixel_dimensions()
+            .map_err(|e| anyhow::anyhow!("bad SPS dimensions: {e:?}"))?;
+        // For monochrome, frame progressive data, crop units are 1 pixel.
+        let (left, top) = sps
+            .frame_cropping
+            .as_ref()
+            .map(|c| (c.left_offset, c.top_offset))
+            .unwrap_or((0, 0));
+        Ok(Self {
+            bit_depth: sps.chroma_info.bit_depth_luma_minus8 + 8,
+            width_mbs: sps.pic_width_in_mbs_minus1 + 1,
+            height_mbs: sps.pic_height_in_map_units_minus1 + 1,
+            log2_max_frame_num: u32::from(sps.log2_max_frame_num_minus4) + 4,
+            poc,
+            bottom_field_pic_order_in_frame_present: pps
+                .bottom_field_pic_order_in_frame_present_flag,
+            redundant_pic_cnt_present: pps.redundant_pic_cnt_present_flag,
+            deblocking_filter_control_present: pps.deblocking_filter_control_present_flag,
+            crop: (left, top, width, height),
+        })
+    }
+
+    pub(crate) fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    /// Decode all slices of a frame, returning the cropped luma samples.
+    pub(crate) fn decode_frame(&self, nal_units: &[Vec<u8>]) -> Result<DecodedMono> {
+        let full_width = self.width_mbs as usize * 16;
+        let mut luma = vec![0u16; full_width * self.height_mbs as usize * 16];
+        let total_mbs = self.width_mbs * self.height_mbs;
+        let mut n_decoded = 0;
+
+        for nal_unit in nal_units.iter() {
+            let nal = RefNal::new(nal_unit.as_slice(), &[], true);
+            let header = nal
+                .header()
+                .map_err(|e| anyhow::anyhow!("bad NAL header: {e:?}"))?;
+            let is_idr = match header.nal_unit_type() {
+                UnitType::SliceLayerWithoutPartitioningIdr => true,
+                UnitType::SliceLayerWithoutPartitioningNonIdr => false,
+                _ => continue,
+            };
+            let mut rbsp = Vec::new();
+            nal.rbsp_bytes().read_to_end(&mut rbsp)?;
+            let mut rdr = BitReader::new(&rbsp);
+            let first_mb = self.parse_slice_header(&mut rdr, is_idr, header.nal_ref_idc() != 0)?;
+
+            let mut mb_addr = first_mb;
+            while mb_addr < total_mbs && rdr.more_rbsp_data() {
+                let mb_type = rdr.read_ue()?;
+                if mb_type != MB_TYPE_I_PCM {
+                    anyhow::bail!("macroblock type {mb_type} is not I_PCM");
+                }
+                rdr.byte_align();
+                let mb_x = (mb_addr % self.width_mbs) as usize * 16;
+                let mb_y = (mb_addr / self.width_mbs) as usize * 16;
+                for row in 0..16 {
+                    let start = (mb_y + row) * full_width + mb_x;
+                    for dest in luma[start..start + 16].iter_mut() {
+                        *dest = rdr
+                            .read_bits(u32::from(self.bit_depth))?
+                            .try_into()
+                            .unwrap();
+                    }
+                }
+                mb_addr += 1;
+                n_decoded += 1;
+            }
+        }
+
+        if n_decoded != total_mbs {
+            anyhow::bail!("decoded {n_decoded} macroblocks, expected {total_mbs}");
+        }
+
+        let (left, top, width, height) = self.crop;
+        let mut data = Vec::with_capacity(width as usize * height as usize);
+        for row in top..top + height {
+            let start = row as usize * full_width + left as usize;
+            data.extend_from_slice(&luma[start..start + width as usize]);
+        }
+        Ok(DecodedMono {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Parse an I slice header, returning `first_mb_in_slice`.
+    fn parse_slice_header(
+        &self,
+        rdr: &mut BitReader<'_>,
+        is_idr: bool,
+        is_reference: bool,
+    ) -> Result<u32> {
+        let first_mb_in_slice = rdr.read_ue()?;
+        let slice_type = rdr.read_ue()?;
+        if slice_type % 5 != 2 {
+            anyhow::bail!("I_PCM decoding only supported for I slices, not type {slice_type}");
+        }
+        let _pic_parameter_set_id = rdr.read_ue()?;
+        let _frame_num = rdr.read_bits(self.log2_max_frame_num)?;
+        if is_idr {
+            let _idr_pic_id = rdr.read_ue()?;
+        }
+        match self.poc {
+            PocParams::TypeZero {
+                log2_max_pic_order_cnt_lsb,
+            } => {
+                let _pic_order_cnt_lsb = rdr.read_bits(log2_max_pic_order_cnt_lsb)?;
+                if self.bottom_field_pic_order_in_frame_present {
+                    let _delta_pic_order_cnt_bottom = rdr.read_se()?;
+                }
+            }
+            PocParams::TypeOne {
+                delta_pic_order_always_zero,
+            } => {
+                if !delta_pic_order_always_zero {
+                    let _delta_pic_order_cnt_0 = rdr.read_se()?;
+                    if self.bottom_field_pic_order_in_frame_present {
+                        let _delta_pic_order_cnt_1 = rdr.read_se()?;
+                    }
+                }
+            }
+            PocParams::TypeTwo => {}
+        }
+        if self.redundant_pic_cnt_present {
+            let _redundant_pic_cnt = rdr.read_ue()?;
+        }
+        if is_reference {
+            // dec_ref_pic_marking()
+            if is_idr {
+                let _no_output_of_prior_pics_flag = rdr.read_bit()?;
+                let _long_term_reference_flag = rdr.read_bit()?;
+            } else if rdr.read_bit()? {
+                // adaptive_ref_pic_marking_mode_flag
+                loop {
+                    let op = rdr.read_ue()?;
+                    match op {
+                        0 => break,
+                        1 | 2 | 4 | 6 => {
+                            rdr.read_ue()?;
+                        }
+                        3 => {
+                            rdr.read_ue()?;
+                            rdr.read_ue()?;
+                        }
+                        5 => {}
+                        _ => anyhow::bail!("invalid memory_management_control_operation {op}"),
+                    }
+                }
+            }
+        }
+        let _slice_qp_delta = rdr.read_se()?;
+        if self.deblocking_filter_control_present {
+            let disable_deblocking_filter_idc = rdr.read_ue()?;
+            if disable_deblocking_filter_idc != 1 {
+                let _slice_alpha_c0_offset_div2 = rdr.read_se()?;
+                let _slice_beta_offset_div2 = rdr.read_se()?;
+            }
+        }
+        Ok(first_mb_in_slice)
+    }
+}
+
+/// A decoded monochrome image.
+pub(crate) struct DecodedMono {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) data: Vec<u16>,
+}
+
+/// Reads bits, most significant first, from RBSP data.
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits.
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn read_bit(&mut self) -> Result<bool> {
+        let byte = self
+            .data
+            .get(self.pos / 8)
+            .ok_or_else(|| anyhow::anyhow!("unexpected end of slice data"))?;
+        let bit = (byte >> (7 - (self.pos % 8))) & 0x01;
+        self.pos += 1;
+        Ok(bit == 1)
+    }
+
+    fn read_bits(&mut self, n: u32) -> Result<u32> {
+        debug_assert!(n <= 32);
+        let mut result = 0u32;
+        for _ in 0..n {
+            result = (result << 1) | u32::from(self.read_bit()?);
+        }
+        Ok(result)
+    }
+
+    /// Read an unsigned Exp-Golomb code.
+    fn read_ue(&mut self) -> Result<u32> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                anyhow::bail!("invalid Exp-Golomb code");
+            }
+        }
+        let suffix = self.read_bits(leading_zeros)?;
+        Ok(((1u64 << leading_zeros) - 1 + u64::from(suffix))
+            .try_into()
+            .unwrap())
+    }
+
+    /// Read a signed Exp-Golomb code.
+    fn read_se(&mut self) -> Result<i32> {
+        let k = i64::from(self.read_ue()?);
+        let val = if k % 2 == 1 { (k + 1) / 2 } else { -(k / 2) };
+        Ok(val.try_into().unwrap())
+    }
+
+    fn byte_align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    /// Whether there is data before the `rbsp_stop_one_bit`.
+    fn more_rbsp_data(&self) -> bool {
+        let last_one_bit = self.data.iter().enumerate().rev().find_map(|(i, b)| {
+            if *b == 0 {
+                None
+            } else {
+                Some(i * 8 + 7 - b.trailing_zeros() as usize)
+            }
+        });
+        match last_one_bit {
+            Some(stop_bit) => self.pos < stop_bit,
+            None => false,
+        }
+    }
+}
+
+#[test]
+fn test_exp_golomb() {
+    // ue: 1 -> 0, 010 -> 1, 011 -> 2, 00100 -> 3, then se: 00101 -> -2,
+    // followed by the stop bit.
+    let data = [0b1010_0110, 0b0100_0010, 0b1100_0000];
+    let mut rdr = BitReader::new(&data);
+    assert_eq!(rdr.read_ue().unwrap(), 0);
+    assert_eq!(rdr.read_ue().unwrap(), 1);
+    assert_eq!(rdr.read_ue().unwrap(), 2);
+    assert_eq!(rdr.read_ue().unwrap(), 3);
+    assert!(rdr.more_rbsp_data());
+    assert_eq!(rdr.read_se().unwrap(), -2);
+    assert!(!rdr.more_rbsp_data());
+    rdr.byte_align();
+    assert_eq!(rdr.pos, 24);
+}
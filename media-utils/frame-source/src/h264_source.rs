@@ -20,8 +20,8 @@ use serde::{Deserialize, Serialize};
 use ci2_remote_control::{H264Metadata, H264_METADATA_UUID, H264_METADATA_VERSION};
 
 use crate::{
-    ntp_timestamp::NtpTimestamp, EncodedH264, FrameData, FrameDataSource, H264EncodingVariant,
-    ImageData, MyAsStr, Result, Timestamp, TimestampSource,
+    h264_pcm::PcmParams, ntp_timestamp::NtpTimestamp, EncodedH264, FrameData, FrameDataSource,
+    H264EncodingVariant, ImageData, MyAsStr, Result, Timestamp, TimestampSource,
 };
 
 /// H264 data source. Can come directly from an "Annex B" format .h264 file or
@@ -77,6 +77,9 @@ pub struct H264Source<H: SeekableH264Source> {
     width: u32,
     height: u32,
     do_decode_h264: bool,
+    /// Set for high bit depth data, which we decode ourselves rather than with
+    /// OpenH264.
+    pcm_params: Option<PcmParams>,
     timestamp_source: Option<crate::TimestampSource>,
     has_timestamps: bool,
 }
@@ -133,7 +136,7 @@ impl<H: SeekableH264Source> FrameDataSource for H264Source<H> {
         anyhow::bail!("h264 luminance scanning not implemented");
     }
     fn iter<'a>(&'a mut self) -> Box<dyn Iterator<Item = Result<FrameData>> + 'a> {
-        let openh264_decoder_state = if self.do_decode_h264 {
+        let openh264_decoder_state = if self.do_decode_h264 && self.pcm_params.is_none() {
             Some(openh264::decoder::Decoder::new().unwrap())
         } else {
             None
@@ -442,6 +445,15 @@ where
         let (width, height) =
             widthheight.ok_or_else(|| anyhow::anyhow!("expected SPS not found"))?;
 
+        // OpenH264 cannot decode bit depths above 8. Such data (as saved by
+        // LessAVC) is composed of I_PCM macroblocks which we decode ourselves.
+        let pcm_params = match (parsing_ctx.sps().next(), parsing_ctx.pps().next()) {
+            (Some(sps), Some(pps)) if sps.chroma_info.bit_depth_luma_minus8 > 0 => {
+                Some(PcmParams::new(sps, pps)?)
+            }
+            _ => None,
+        };
+
         let timezone = tz_offset.unwrap_or_else(|| chrono::FixedOffset::east_opt(0).unwrap());
 
         let frame0_precision_time = frame0_precision_time
@@ -507,6 +519,7 @@ where
             width,
             height,
             do_decode_h264,
+            pcm_params,
             timestamp_source,
             has_timestamps,
         })
@@ -565,7 +578,55 @@ impl<'parent, H: SeekableH264Source> Iterator for RawH264Iter<'parent, H> {
                 .seekable_h264_source
                 .read_nal_units_at_locations(nal_locations)?;
 
-            if let Some(decoder) = &mut self.openh264_decoder_state {
+            let host_timestamp = match nti.precise_timestamp {
+                Some(ts) => ts,
+                None => {
+                    if let (Some(mp4_pts), Some(md)) = (mp4_pts, &self.parent.h264_metadata) {
+                        md.creation_time.with_timezone(&chrono::Utc)
+                            + chrono::Duration::from_std(mp4_pts).unwrap()
+                    } else {
+                        // No possible source of timestamp, use dummy value.
+                        chrono::TimeZone::timestamp_opt(&chrono::Utc, 0, 0).unwrap()
+                    }
+                }
+            };
+
+            let pcm_params = if self.parent.do_decode_h264 {
+                self.parent.pcm_params.as_ref()
+            } else {
+                None
+            };
+
+            if let Some(pcm_params) = pcm_params {
+                let decoded = pcm_params.decode_frame(&nal_units)?;
+                // Store the raw counts as floating point values.
+                let image_data: Vec<u8> = decoded
+                    .data
+                    .iter()
+                    .flat_map(|x| f32::from(*x).to_ne_bytes())
+                    .collect();
+                let extra = Box::new(basic_frame::BasicExtra {
+                    host_timestamp,
+                    host_framenumber: frame_number,
+                });
+                let dynamic_frame = basic_frame::DynamicFrame::Mono32f(basic_frame::BasicFrame::<
+                    machine_vision_formats::pixel_format::Mono32f,
+                > {
+                    width: decoded.width,
+                    height: decoded.height,
+                    stride: decoded.width * 4,
+                    image_data,
+                    pixel_format: std::marker::PhantomData,
+                    extra,
+                });
+                let buf_len = nal_units.iter().map(|x| x.len()).sum();
+                Ok(FrameData {
+                    timestamp: frame_timestamp,
+                    image: ImageData::Decoded(dynamic_frame),
+                    buf_len,
+                    idx: frame_number,
+                })
+            } else if let Some(decoder) = &mut self.openh264_decoder_state {
                 // copy into Annex B format for OpenH264
                 let annex_b = copy_nalus_to_annex_b(nal_units.as_slice());
 
@@ -578,21 +639,6 @@ impl<'parent, H: SeekableH264Source> Iterator for RawH264Iter<'parent, H> {
                         let mut image_data = vec![0u8; stride * dim.1];
                         decoded_yuv.write_rgb8(&mut image_data);
 
-                        let host_timestamp = match nti.precise_timestamp {
-                            Some(ts) => ts,
-                            None => {
-                                if let (Some(mp4_pts), Some(md)) =
-                                    (mp4_pts, &self.parent.h264_metadata)
-                                {
-                                    md.creation_time.with_timezone(&chrono::Utc)
-                                        + chrono::Duration::from_std(mp4_pts).unwrap()
-                                } else {
-                                    // No possible source of timestamp, use dummy value.
-                                    chrono::TimeZone::timestamp_opt(&chrono::Utc, 0, 0).unwrap()
-                                }
-                            }
-                        };
-
                         let extra = Box::new(basic_frame::BasicExtra {
                             host_timestamp,
                             host_framenumber: frame_number,
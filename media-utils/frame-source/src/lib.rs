@@ -10,14 +10,18 @@ use basic_frame::DynamicFrame;
 
 pub mod pv_tiff_stack;
 use pv_tiff_stack::TiffImage;
+pub mod av1_mp4_source;
 pub mod fmf_source;
 mod h264_annexb_splitter;
+mod h264_pcm;
 pub mod h264_source;
 pub mod mp4_source;
 pub mod strand_cam_mkv_source;
 
 mod ntp_timestamp;
 #[cfg(test)]
+mod test_mono12;
+#[cfg(test)]
 mod test_timestamps;
 
 /// A source of FrameData
@@ -144,6 +148,8 @@ pub enum ImageData {
     Decoded(DynamicFrame),
     Tiff(TiffImage),
     EncodedH264(EncodedH264),
+    /// The AV1 data of one frame, as stored in an MP4 sample.
+    EncodedAv1(Vec<u8>),
 }
 
 impl std::fmt::Debug for ImageData {
@@ -158,6 +164,9 @@ impl std::fmt::Debug for ImageData {
             ImageData::EncodedH264(_) => {
                 write!(f, "ImageData::EncodedH264")
             }
+            ImageData::EncodedAv1(_) => {
+                write!(f, "ImageData::EncodedAv1")
+            }
         }
     }
 }
@@ -249,6 +258,14 @@ pub fn from_path_with_timestamp_source<P: AsRef<std::path::Path>>(
                     return Ok(Box::new(mkv_video));
                 }
                 Some("mp4") => {
+                    if av1_mp4_source::is_av1_mp4(&input) {
+                        let av1_video = av1_mp4_source::from_path_with_timestamp_source(
+                            &input,
+                            do_decode_h264,
+                            timestamp_source,
+                        )?;
+                        return Ok(Box::new(av1_video));
+                    }
                     let mp4_video = mp4_source::from_path_with_timestamp_source(
                         &input,
                         do_decode_h264,
@@ -0,0 +1,160 @@
+use chrono::{DateTime, Duration, Utc};
+use machine_vision_formats::pixel_format::{Mono32f, Mono8};
+
+use crate::{h264_source::SeekRead, FrameDataSource, ImageData, Result, TimestampSource};
+use ci2_remote_control::Mp4RecordingConfig;
+
+const W: u32 = 32;
+const H: u32 = 18;
+
+#[test]
+fn test_h264_mono12_lossless_roundtrip() -> color_eyre::Result<()> {
+    let start: DateTime<Utc> = DateTime::from_timestamp(60 * 60, 0).unwrap();
+
+    let cfg = Mp4RecordingConfig {
+        codec: ci2_remote_control::Mp4Codec::H264LessAvcMono12,
+        max_framerate: Default::default(),
+        h264_metadata: None,
+    };
+
+    let mut mp4_buf = Vec::new();
+    let mut originals = Vec::new();
+    {
+        let mut my_mp4_writer =
+            mp4_writer::Mp4Writer::new(std::io::Cursor::new(&mut mp4_buf), cfg, None)?;
+
+        const STRIDE: usize = W as usize * 4;
+        for fno in 0..3 {
+            // Full 12 bit range of values.
+            let values: Vec<f32> = (0..W * H)
+                .map(|i| ((i * 37 + fno * 1001) % 4096) as f32)
+                .collect();
+            let image_data = values.iter().flat_map(|x| x.to_ne_bytes()).collect();
+            let frame = simple_frame::SimpleFrame::<Mono32f>::new(
+                W,
+                H,
+                STRIDE.try_into().unwrap(),
+                image_data,
+            )
+            .unwrap();
+            let ts = start + Duration::try_milliseconds(fno.into()).unwrap() * 10;
+            my_mp4_writer.write(&frame, ts)?;
+            originals.push(values);
+        }
+        my_mp4_writer.finish()?;
+    }
+
+    let size = mp4_buf.len() as u64;
+    let rdr = std::io::Cursor::new(mp4_buf);
+    let buf_reader: Box<(dyn SeekRead + Send)> = Box::new(std::io::BufReader::new(rdr));
+    let mp4_reader = mp4::Mp4Reader::read_header(buf_reader, size)?;
+
+    let do_decode_h264 = true;
+    let mut src = crate::mp4_source::from_reader_with_timestamp_source(
+        mp4_reader,
+        do_decode_h264,
+        crate::TimestampSource::BestGuess,
+    )?;
+    assert_eq!(src.width(), W);
+    assert_eq!(src.height(), H);
+
+    let mut n_frames = 0;
+    for (frame, expected) in src.iter().zip(originals.iter()) {
+        let frame = frame?;
+        let decoded = match frame.decoded() {
+            Some(basic_frame::DynamicFrame::Mono32f(decoded)) => decoded,
+            _ => panic!("expected Mono32f frame"),
+        };
+        let actual: Vec<f32> = decoded
+            .image_data
+            .chunks_exact(4)
+            .map(|x| f32::from_ne_bytes(x.try_into().unwrap()))
+            .collect();
+        assert_eq!(&actual, expected);
+        n_frames += 1;
+    }
+    assert_eq!(n_frames, originals.len());
+    Ok(())
+}
+
+#[test]
+fn test_av1_mp4_container() -> color_eyre::Result<()> {
+    let start: DateTime<Utc> = DateTime::from_timestamp(60 * 60, 0).unwrap();
+    let creation_time = start.with_timezone(&chrono::FixedOffset::east_opt(3600).unwrap());
+    let mut h264_metadata = ci2_remote_control::H264Metadata::new("test", creation_time);
+    h264_metadata.camera_name = Some("cam1".into());
+
+    let cfg = Mp4RecordingConfig {
+        codec: ci2_remote_control::Mp4Codec::Av1Rav1e(Default::default()),
+        max_framerate: Default::default(),
+        h264_metadata: Some(h264_metadata),
+    };
+
+    let dt_msec = 20;
+    let n_frames = 10;
+    let mut mp4_buf = Vec::new();
+    {
+        let mut my_mp4_writer =
+            mp4_writer::Mp4Writer::new(std::io::Cursor::new(&mut mp4_buf), cfg, None)?;
+        for fno in 0..n_frames {
+            let image_data = vec![u8::try_from(fno * 20).unwrap(); (W * H) as usize];
+            let frame = simple_frame::SimpleFrame::<Mono8>::new(W, H, W, image_data).unwrap();
+            let ts = start + Duration::try_milliseconds(fno * dt_msec).unwrap();
+            my_mp4_writer.write(&frame, ts)?;
+        }
+        my_mp4_writer.finish()?;
+    }
+
+    let size = mp4_buf.len() as u64;
+    let open = |do_decode, timestamp_source| {
+        let rdr: Box<(dyn SeekRead + Send)> = Box::new(std::io::Cursor::new(mp4_buf.clone()));
+        crate::av1_mp4_source::from_reader_with_timestamp_source(
+            rdr,
+            size,
+            do_decode,
+            timestamp_source,
+        )
+    };
+
+    assert!(open(false, TimestampSource::MispMicrosectime).is_err());
+    assert_eq!(
+        open(true, TimestampSource::BestGuess).is_ok(),
+        cfg!(feature = "av1-decode")
+    );
+
+    let mut src = open(false, TimestampSource::Mp4Pts)?;
+    assert_eq!(src.width(), W);
+    assert_eq!(src.height(), H);
+    assert_eq!(src.camera_name(), Some("cam1"));
+    assert_eq!(src.frame0_time().unwrap(), start);
+
+    let frames: Vec<_> = src.iter().collect::<Result<_>>()?;
+    assert_eq!(frames.len(), n_frames as usize);
+    for (fno, frame) in frames.iter().enumerate() {
+        let expected_pts = Duration::try_milliseconds(fno as i64 * dt_msec).unwrap();
+        assert_eq!(
+            frame.timestamp().unwrap_duration(),
+            expected_pts.to_std().unwrap()
+        );
+        assert!(matches!(frame.image(), ImageData::EncodedAv1(_)));
+    }
+
+    #[cfg(feature = "av1-decode")]
+    for (fno, frame) in open(true, TimestampSource::BestGuess)?.iter().enumerate() {
+        let frame = frame?;
+        let expected_pts = Duration::try_milliseconds(fno as i64 * dt_msec).unwrap();
+        assert_eq!(
+            frame.timestamp().unwrap_duration(),
+            expected_pts.to_std().unwrap()
+        );
+        match frame.decoded() {
+            Some(basic_frame::DynamicFrame::Mono8(decoded)) => {
+                assert_eq!(decoded.width, W);
+                assert_eq!(decoded.height, H);
+            }
+            _ => panic!("expected Mono8 frame"),
+        }
+    }
+
+    Ok(())
+}
@@ -32,6 +32,12 @@ pub enum Error {
         #[cfg(feature = "backtrace")]
         backtrace: Backtrace,
     },
+    #[error("pixel format {fmt} not supported for 12 bit encoding")]
+    UnsupportedMono12Format {
+        fmt: String,
+        #[cfg(feature = "backtrace")]
+        backtrace: Backtrace,
+    },
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -81,23 +87,119 @@ fn gen_y4m_ref(y4m: &convert_image::Y4MFrame) -> Result<YCbCrImage<'_>> {
     Ok(im)
 }
 
+/// Monochrome image with 12 bit samples stored as little-endian `u16`.
+struct Mono12Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Mono12Image {
+    fn stride(&self) -> usize {
+        self.width as usize * 2
+    }
+}
+
+/// Convert a frame to 12 bit monochrome.
+///
+/// `Mono8` data is scaled to fill the 12 bit range. `Mono32f` data is taken to
+/// be raw 12 bit counts and is rounded and clamped to the range 0-4095.
+fn convert_to_mono12<FRAME, FMT>(frame: &FRAME) -> Result<Mono12Image>
+where
+    FRAME: ImageStride<FMT>,
+    FMT: PixelFormat,
+{
+    use machine_vision_formats::PixFmt;
+
+    let fmt = machine_vision_formats::pixel_format::pixfmt::<FMT>().unwrap();
+    let width = frame.width();
+    let height = frame.height();
+    let src_stride = frame.stride();
+    let src = frame.image_data();
+    let mut data = Vec::with_capacity(width as usize * height as usize * 2);
+    for row in 0..height as usize {
+        let row_data = &src[row * src_stride..];
+        match fmt {
+            PixFmt::Mono8 => {
+                for &val in &row_data[..width as usize] {
+                    // Replicate high bits into low bits so 255 maps to 4095.
+                    let val12 = (u16::from(val) << 4) | (u16::from(val) >> 4);
+                    data.extend_from_slice(&val12.to_le_bytes());
+                }
+            }
+            PixFmt::Mono32f => {
+                for chunk in row_data[..width as usize * 4].chunks_exact(4) {
+                    let val = f32::from_ne_bytes(chunk.try_into().unwrap());
+                    let val12 = val.round().clamp(0.0, 4095.0) as u16;
+                    data.extend_from_slice(&val12.to_le_bytes());
+                }
+            }
+            fmt => {
+                return Err(Error::UnsupportedMono12Format {
+                    fmt: format!("{fmt:?}"),
+                    #[cfg(feature = "backtrace")]
+                    backtrace: Backtrace::capture(),
+                });
+            }
+        }
+    }
+    Ok(Mono12Image {
+        width,
+        height,
+        data,
+    })
+}
+
+fn gen_mono12_ref(im: &Mono12Image) -> YCbCrImage<'_> {
+    let y_plane = DataPlane {
+        data: &im.data,
+        stride: im.stride(),
+        bit_depth: less_avc::BitDepth::Depth12,
+    };
+    YCbCrImage {
+        planes: Planes::Mono(y_plane),
+        width: im.width,
+        height: im.height,
+    }
+}
+
 #[derive(Default)]
 pub struct WrappedLessEncoder {
     inner: Option<less_avc::LessEncoder>,
+    mono12: bool,
 }
 
 impl WrappedLessEncoder {
+    /// Create an encoder which saves all frames as 12 bit monochrome.
+    ///
+    /// `Mono8` input is scaled to fill the 12 bit range. `Mono32f` input is
+    /// taken to be raw 12 bit counts and is stored without loss.
+    pub fn new_mono12() -> Self {
+        Self {
+            inner: None,
+            mono12: true,
+        }
+    }
+
     pub fn encode_to_nal_units<FRAME, FMT>(&mut self, frame: &FRAME) -> Result<Vec<Vec<u8>>>
     where
         FRAME: ImageStride<FMT>,
         FMT: PixelFormat,
     {
+        if self.mono12 {
+            let mono12 = convert_to_mono12(frame)?;
+            let im_ref = gen_mono12_ref(&mono12);
+            return self.encode_ycbcr(&im_ref);
+        }
         let y4m = convert_to_y4m(frame)?;
         let y4m_ref = gen_y4m_ref(&y4m)?;
+        self.encode_ycbcr(&y4m_ref)
+    }
 
+    fn encode_ycbcr(&mut self, im: &YCbCrImage<'_>) -> Result<Vec<Vec<u8>>> {
         let (nals, encoder) = match self.inner.take() {
             None => {
-                let (nal_units, encoder) = less_avc::LessEncoder::new(&y4m_ref)?;
+                let (nal_units, encoder) = less_avc::LessEncoder::new(im)?;
                 let nals: Vec<Vec<u8>> = nal_units
                     .into_iter()
                     .map(|nal_unit| nal_unit.to_nal_unit())
@@ -105,7 +207,7 @@ impl WrappedLessEncoder {
                 (nals, encoder)
             }
             Some(mut encoder) => {
-                let nal_unit = encoder.encode(&y4m_ref)?;
+                let nal_unit = encoder.encode(im)?;
                 (vec![nal_unit.to_nal_unit()], encoder)
             }
         };
@@ -0,0 +1,501 @@
+// Copyright 2024 Andrew D. Straw.
+
+//! AV1 encoding with rav1e and a minimal MP4 (ISO BMFF) muxer for it.
+//!
+//! The `mp4` crate which we use for H264 data does not support the `av01`
+//! sample entry, so here we write the few boxes required ourselves. The file
+//! layout is `ftyp`, `uuid` (our metadata), `mdat` and finally `moov`, which is
+//! written once all samples are known.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use ci2_remote_control::{H264Metadata, Rav1eOptions, H264_METADATA_UUID};
+use machine_vision_formats::{ImageStride, PixelFormat};
+use rav1e::prelude::{
+    ChromaSampling, Config, Context, EncoderConfig, EncoderStatus, FrameParameters, FrameType,
+    Opaque, Rational,
+};
+
+use crate::{dur2raw, Result, MOVIE_TIMESCALE, TRACK_ID};
+
+/// OBU header of a temporal delimiter with `obu_has_size_field` set.
+const TEMPORAL_DELIMITER: [u8; 2] = [0x12, 0x00];
+
+pub(crate) struct Rav1eEncoder {
+    opts: Rav1eOptions,
+    /// Framerate given as (numerator, denominator) used for rate control.
+    framerate: Option<(u32, u32)>,
+    ctx: Option<Context<u8>>,
+    h264_metadata: Option<H264Metadata>,
+    first_timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+impl Rav1eEncoder {
+    pub(crate) fn new(
+        opts: Rav1eOptions,
+        framerate: Option<(u32, u32)>,
+        h264_metadata: Option<H264Metadata>,
+        first_timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        Self {
+            opts,
+            framerate,
+            ctx: None,
+            h264_metadata,
+            first_timestamp,
+        }
+    }
+
+    fn new_context(&self, width: usize, height: usize, is_mono: bool) -> Result<Context<u8>> {
+        let (num, den) = self.framerate.unwrap_or((30, 1));
+        let mut enc = EncoderConfig::with_speed_preset(self.opts.speed);
+        enc.width = width;
+        enc.height = height;
+        enc.bit_depth = 8;
+        enc.chroma_sampling = if is_mono {
+            ChromaSampling::Cs400
+        } else {
+            ChromaSampling::Cs420
+        };
+        enc.time_base = Rational::new(den.into(), num.into());
+        // No frame reordering: each input frame results in exactly one packet,
+        // in order, which keeps MP4 sample timing trivial.
+        enc.low_latency = true;
+        enc.quantizer = self.opts.quantizer.into();
+        if let Some(kbps) = self.opts.bitrate {
+            enc.bitrate = (kbps.saturating_mul(1000)).try_into().unwrap_or(i32::MAX);
+        }
+        enc.max_key_frame_interval = self.opts.max_keyframe_interval;
+        let cfg = Config::new().with_encoder_config(enc);
+        Ok(cfg.new_context()?)
+    }
+
+    /// Encode a frame, writing any completed packets to `mp4_segment`.
+    pub(crate) fn encode_frame<T, FRAME, FMT>(
+        &mut self,
+        mp4_segment: &mut crate::MaybeMp4Writer<T>,
+        raw_frame: &FRAME,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()>
+    where
+        T: Write + Seek,
+        FRAME: ImageStride<FMT>,
+        FMT: PixelFormat,
+    {
+        let y4m = convert_image::encode_y4m_frame(
+            raw_frame,
+            convert_image::Y4MColorspace::C420paldv,
+            None,
+        )?;
+        let width: usize = y4m.width.try_into().unwrap();
+        let height: usize = y4m.height.try_into().unwrap();
+        let is_mono = y4m.is_known_mono_only();
+
+        if self.ctx.is_none() {
+            let ctx = self.new_context(width, height, is_mono)?;
+            let av1c = ctx.container_sequence_header();
+            let fd = match std::mem::replace(mp4_segment, crate::MaybeMp4Writer::Nothing) {
+                crate::MaybeMp4Writer::Starting(fd) => fd,
+                _ => return crate::inconsistent_state_err(),
+            };
+            let metadata = self.h264_metadata.as_ref().map(|md| {
+                // As for H264, the creation time is the time of the first frame.
+                let creation_time = timestamp.with_timezone(md.creation_time.offset());
+                H264Metadata {
+                    creation_time,
+                    ..md.clone()
+                }
+            });
+            let writer = Av1Mp4Writer::new(
+                fd,
+                width.try_into().unwrap(),
+                height.try_into().unwrap(),
+                av1c,
+                metadata.as_ref(),
+            )?;
+            *mp4_segment = crate::MaybeMp4Writer::Av1Writer(writer);
+            self.ctx = Some(ctx);
+        }
+        let ctx = self.ctx.as_mut().unwrap();
+
+        let mut frame = ctx.new_frame();
+        frame.planes[0].copy_from_raw_u8(y4m.y_plane_data(), y4m.y_stride(), 1);
+        if !is_mono {
+            frame.planes[1].copy_from_raw_u8(y4m.u_plane_data(), y4m.u_stride(), 1);
+            frame.planes[2].copy_from_raw_u8(y4m.v_plane_data(), y4m.v_stride(), 1);
+        }
+
+        let pts = (timestamp - self.first_timestamp).to_std().unwrap();
+        let params = FrameParameters {
+            opaque: Some(Opaque::new(pts)),
+            ..Default::default()
+        };
+        ctx.send_frame((frame, params))?;
+        self.drain(mp4_segment)
+    }
+
+    /// Flush the encoder and write all remaining packets.
+    pub(crate) fn finish<T>(&mut self, mp4_segment: &mut crate::MaybeMp4Writer<T>) -> Result<()>
+    where
+        T: Write + Seek,
+    {
+        if let Some(ctx) = self.ctx.as_mut() {
+            ctx.flush();
+            self.drain(mp4_segment)?;
+        }
+        Ok(())
+    }
+
+    fn drain<T>(&mut self, mp4_segment: &mut crate::MaybeMp4Writer<T>) -> Result<()>
+    where
+        T: Write + Seek,
+    {
+        let ctx = match self.ctx.as_mut() {
+            Some(ctx) => ctx,
+            None => return Ok(()),
+        };
+        let writer = match mp4_segment {
+            crate::MaybeMp4Writer::Av1Writer(writer) => writer,
+            _ => return crate::inconsistent_state_err(),
+        };
+        loop {
+            match ctx.receive_packet() {
+                Ok(packet) => {
+                    let pts: std::time::Duration = match packet.opaque {
+                        Some(opaque) => {
+                            *opaque.downcast::<std::time::Duration>().map_err(|_| {
+                                crate::Error::InconsistentState {
+                                    #[cfg(feature = "backtrace")]
+                                    backtrace: std::backtrace::Backtrace::capture(),
+                                }
+                            })?
+                        }
+                        None => return crate::inconsistent_state_err(),
+                    };
+                    let is_keyframe = packet.frame_type == FrameType::KEY;
+                    writer.write_sample(&packet.data, dur2raw(&pts), is_keyframe)?;
+                }
+                Err(EncoderStatus::Encoded) => {}
+                Err(EncoderStatus::NeedMoreData) | Err(EncoderStatus::LimitReached) => {
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+struct SampleInfo {
+    offset: u64,
+    size: u32,
+    /// in units of `MOVIE_TIMESCALE`
+    start_time: u64,
+    is_keyframe: bool,
+}
+
+/// Writes AV1 samples into an MP4 file.
+pub(crate) struct Av1Mp4Writer<T: Write + Seek> {
+    fd: T,
+    width: u16,
+    height: u16,
+    av1c: Vec<u8>,
+    /// File position of the `mdat` box header.
+    mdat_start: u64,
+    /// Current file position.
+    pos: u64,
+    samples: Vec<SampleInfo>,
+}
+
+impl<T: Write + Seek> Av1Mp4Writer<T> {
+    fn new(
+        mut fd: T,
+        width: u16,
+        height: u16,
+        av1c: Vec<u8>,
+        metadata: Option<&H264Metadata>,
+    ) -> Result<Self> {
+        let mut pos = fd.stream_position()?;
+
+        let mut ftyp = Vec::new();
+        ftyp.extend(b"isom");
+        ftyp.extend(512u32.to_be_bytes());
+        for brand in [b"isom", b"iso6", b"av01", b"mp41"] {
+            ftyp.extend(brand);
+        }
+        pos += write_box(&mut fd, b"ftyp", &ftyp)?;
+
+        if let Some(md) = metadata {
+            let mut uuid_box = Vec::new();
+            uuid_box.extend(H264_METADATA_UUID);
+            uuid_box.extend(serde_json::to_vec(md).unwrap());
+            pos += write_box(&mut fd, b"uuid", &uuid_box)?;
+        }
+
+        // `mdat` with 64 bit size, filled in when finished.
+        let mdat_start = pos;
+        fd.write_all(&1u32.to_be_bytes())?;
+        fd.write_all(b"mdat")?;
+        fd.write_all(&0u64.to_be_bytes())?;
+        pos += 16;
+
+        Ok(Self {
+            fd,
+            width,
+            height,
+            av1c,
+            mdat_start,
+            pos,
+            samples: Vec::new(),
+        })
+    }
+
+    fn write_sample(&mut self, data: &[u8], start_time: u64, is_keyframe: bool) -> Result<()> {
+        // Temporal delimiters should not be stored in ISO BMFF samples.
+        let data = data.strip_prefix(&TEMPORAL_DELIMITER[..]).unwrap_or(data);
+        self.fd.write_all(data)?;
+        self.samples.push(SampleInfo {
+            offset: self.pos,
+            size: data.len().try_into().unwrap(),
+            start_time,
+            is_keyframe,
+        });
+        self.pos += data.len() as u64;
+        Ok(())
+    }
+
+    /// Finish the `mdat` box and write the `moov` box.
+    pub(crate) fn write_end(&mut self) -> Result<()> {
+        self.fd.seek(SeekFrom::Start(self.mdat_start + 8))?;
+        self.fd
+            .write_all(&(self.pos - self.mdat_start).to_be_bytes())?;
+        self.fd.seek(SeekFrom::Start(self.pos))?;
+
+        let durations = sample_durations(&self.samples);
+        let total_duration: u64 = durations.iter().map(|d| u64::from(*d)).sum();
+        let moov = self.moov(&durations, total_duration);
+        self.pos += write_box(&mut self.fd, b"moov", &moov)?;
+        self.fd.flush()?;
+        Ok(())
+    }
+
+    fn moov(&self, durations: &[u32], total_duration: u64) -> Vec<u8> {
+        let duration32: u32 = total_duration.try_into().unwrap_or(u32::MAX);
+
+        let mut mvhd = full_box_header(0, 0);
+        mvhd.extend(0u32.to_be_bytes()); // creation_time
+        mvhd.extend(0u32.to_be_bytes()); // modification_time
+        mvhd.extend(MOVIE_TIMESCALE.to_be_bytes());
+        mvhd.extend(duration32.to_be_bytes());
+        mvhd.extend(0x0001_0000u32.to_be_bytes()); // rate 1.0
+        mvhd.extend(0x0100u16.to_be_bytes()); // volume 1.0
+        mvhd.extend([0u8; 10]); // reserved
+        mvhd.extend(unity_matrix());
+        mvhd.extend([0u8; 24]); // pre_defined
+        mvhd.extend((TRACK_ID + 1).to_be_bytes()); // next_track_ID
+
+        let mut tkhd = full_box_header(0, 0x03); // enabled, in movie
+        tkhd.extend(0u32.to_be_bytes()); // creation_time
+        tkhd.extend(0u32.to_be_bytes()); // modification_time
+        tkhd.extend(TRACK_ID.to_be_bytes());
+        tkhd.extend(0u32.to_be_bytes()); // reserved
+        tkhd.extend(duration32.to_be_bytes());
+        tkhd.extend([0u8; 8]); // reserved
+        tkhd.extend(0u16.to_be_bytes()); // layer
+        tkhd.extend(0u16.to_be_bytes()); // alternate_group
+        tkhd.extend(0u16.to_be_bytes()); // volume
+        tkhd.extend(0u16.to_be_bytes()); // reserved
+        tkhd.extend(unity_matrix());
+        tkhd.extend((u32::from(self.width) << 16).to_be_bytes());
+        tkhd.extend((u32::from(self.height) << 16).to_be_bytes());
+
+        let mut mdhd = full_box_header(0, 0);
+        mdhd.extend(0u32.to_be_bytes()); // creation_time
+        mdhd.extend(0u32.to_be_bytes()); // modification_time
+        mdhd.extend(MOVIE_TIMESCALE.to_be_bytes());
+        mdhd.extend(duration32.to_be_bytes());
+        mdhd.extend(0x55C4u16.to_be_bytes()); // language "und"
+        mdhd.extend(0u16.to_be_bytes()); // pre_defined
+
+        let mut hdlr = full_box_header(0, 0);
+        hdlr.extend(0u32.to_be_bytes()); // pre_defined
+        hdlr.extend(b"vide");
+        hdlr.extend([0u8; 12]); // reserved
+        hdlr.extend(b"VideoHandler\0");
+
+        let mut vmhd = full_box_header(0, 1);
+        vmhd.extend([0u8; 8]); // graphicsmode and opcolor
+
+        let url = full_box_header(0, 1); // media data is in this file
+        let mut dref = full_box_header(0, 0);
+        dref.extend(1u32.to_be_bytes());
+        dref.extend(box_bytes(b"url ", &url));
+        let dinf = box_bytes(b"dref", &dref);
+
+        let stbl = [
+            box_bytes(b"stsd", &self.stsd()),
+            box_bytes(b"stts", &stts(durations)),
+            box_bytes(b"stss", &stss(&self.samples)),
+            box_bytes(b"stsc", &stsc()),
+            box_bytes(b"stsz", &stsz(&self.samples)),
+            box_bytes(b"co64", &co64(&self.samples)),
+        ]
+        .concat();
+
+        let minf = [
+            box_bytes(b"vmhd", &vmhd),
+            box_bytes(b"dinf", &dinf),
+            box_bytes(b"stbl", &stbl),
+        ]
+        .concat();
+
+        let mdia = [
+            box_bytes(b"mdhd", &mdhd),
+            box_bytes(b"hdlr", &hdlr),
+            box_bytes(b"minf", &minf),
+        ]
+        .concat();
+
+        let trak = [box_bytes(b"tkhd", &tkhd), box_bytes(b"mdia", &mdia)].concat();
+
+        [box_bytes(b"mvhd", &mvhd), box_bytes(b"trak", &trak)].concat()
+    }
+
+    fn stsd(&self) -> Vec<u8> {
+        let mut av01 = Vec::new();
+        av01.extend([0u8; 6]); // reserved
+        av01.extend(1u16.to_be_bytes()); // data_reference_index
+        av01.extend([0u8; 16]); // pre_defined and reserved
+        av01.extend(self.width.to_be_bytes());
+        av01.extend(self.height.to_be_bytes());
+        av01.extend(0x0048_0000u32.to_be_bytes()); // horizresolution 72 dpi
+        av01.extend(0x0048_0000u32.to_be_bytes()); // vertresolution 72 dpi
+        av01.extend(0u32.to_be_bytes()); // reserved
+        av01.extend(1u16.to_be_bytes()); // frame_count
+        let mut compressorname = [0u8; 32];
+        let name = b"rav1e";
+        compressorname[0] = name.len().try_into().unwrap();
+        compressorname[1..=name.len()].copy_from_slice(name);
+        av01.extend(compressorname);
+        av01.extend(0x0018u16.to_be_bytes()); // depth
+        av01.extend((-1i16).to_be_bytes()); // pre_defined
+        av01.extend(box_bytes(b"av1C", &self.av1c));
+
+        let mut stsd = full_box_header(0, 0);
+        stsd.extend(1u32.to_be_bytes());
+        stsd.extend(box_bytes(b"av01", &av01));
+        stsd
+    }
+}
+
+/// Compute the duration of each sample from successive start times.
+///
+/// The last sample is given the same duration as the one before it.
+fn sample_durations(samples: &[SampleInfo]) -> Vec<u32> {
+    let mut durations: Vec<u32> = samples
+        .windows(2)
+        .map(|w| (w[1].start_time - w[0].start_time).try_into().unwrap())
+        .collect();
+    if !samples.is_empty() {
+        durations.push(durations.last().copied().unwrap_or(0));
+    }
+    durations
+}
+
+fn stts(durations: &[u32]) -> Vec<u8> {
+    let mut entries: Vec<(u32, u32)> = Vec::new();
+    for dur in durations {
+        match entries.last_mut() {
+            Some((count, delta)) if delta == dur => *count += 1,
+            _ => entries.push((1, *dur)),
+        }
+    }
+    let mut result = full_box_header(0, 0);
+    result.extend(u32::try_from(entries.len()).unwrap().to_be_bytes());
+    for (count, delta) in entries {
+        result.extend(count.to_be_bytes());
+        result.extend(delta.to_be_bytes());
+    }
+    result
+}
+
+fn stss(samples: &[SampleInfo]) -> Vec<u8> {
+    let keyframes: Vec<u32> = samples
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.is_keyframe)
+        .map(|(i, _)| u32::try_from(i + 1).unwrap())
+        .collect();
+    let mut result = full_box_header(0, 0);
+    result.extend(u32::try_from(keyframes.len()).unwrap().to_be_bytes());
+    for k in keyframes {
+        result.extend(k.to_be_bytes());
+    }
+    result
+}
+
+/// One sample per chunk.
+fn stsc() -> Vec<u8> {
+    let mut result = full_box_header(0, 0);
+    result.extend(1u32.to_be_bytes()); // entry_count
+    result.extend(1u32.to_be_bytes()); // first_chunk
+    result.extend(1u32.to_be_bytes()); // samples_per_chunk
+    result.extend(1u32.to_be_bytes()); // sample_description_index
+    result
+}
+
+fn stsz(samples: &[SampleInfo]) -> Vec<u8> {
+    let mut result = full_box_header(0, 0);
+    result.extend(0u32.to_be_bytes()); // sample_size: sizes given per sample
+    result.extend(u32::try_from(samples.len()).unwrap().to_be_bytes());
+    for s in samples {
+        result.extend(s.size.to_be_bytes());
+    }
+    result
+}
+
+fn co64(samples: &[SampleInfo]) -> Vec<u8> {
+    let mut result = full_box_header(0, 0);
+    result.extend(u32::try_from(samples.len()).unwrap().to_be_bytes());
+    for s in samples {
+        result.extend(s.offset.to_be_bytes());
+    }
+    result
+}
+
+fn unity_matrix() -> Vec<u8> {
+    [0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000]
+        .iter()
+        .flat_map(|x| x.to_be_bytes())
+        .collect()
+}
+
+fn full_box_header(version: u8, flags: u32) -> Vec<u8> {
+    let mut result = flags.to_be_bytes().to_vec();
+    result[0] = version;
+    result
+}
+
+fn box_bytes(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let size: u32 = (payload.len() + 8).try_into().unwrap();
+    let mut result = Vec::with_capacity(payload.len() + 8);
+    result.extend(size.to_be_bytes());
+    result.extend(box_type);
+    result.extend(payload);
+    result
+}
+
+fn write_box<W: Write>(fd: &mut W, box_type: &[u8; 4], payload: &[u8]) -> Result<u64> {
+    let buf = box_bytes(box_type, payload);
+    fd.write_all(&buf)?;
+    Ok(buf.len() as u64)
+}
+
+#[test]
+fn test_stts_run_length() {
+    let buf = stts(&[3000, 3000, 3000, 6000, 3000]);
+    // version/flags, count, then (count, delta) pairs
+    assert_eq!(&buf[4..8], &3u32.to_be_bytes());
+    assert_eq!(&buf[8..16], &[0, 0, 0, 3, 0, 0, 0x0b, 0xb8]);
+    assert_eq!(&buf[16..24], &[0, 0, 0, 1, 0, 0, 0x17, 0x70]);
+}
@@ -32,6 +32,7 @@ use nvenc::{InputBuffer, OutputBuffer, RateControlMode};
 
 use thiserror::Error;
 
+mod av1;
 mod h264_annexb_split;
 use h264_annexb_split::h264_annexb_split;
 
@@ -111,6 +112,10 @@ pub enum Error {
         #[cfg_attr(feature = "backtrace", backtrace)]
         inner: less_avc_wrapper::Error,
     },
+    #[error("rav1e encoder error: {0}")]
+    Rav1eError(#[from] rav1e::prelude::EncoderStatus),
+    #[error("rav1e config error: {0}")]
+    Rav1eConfigError(#[from] rav1e::prelude::InvalidConfig),
 }
 
 impl From<dynlink_nvidia_encode::NvencError> for Error {
@@ -135,6 +140,7 @@ enum MyEncoder<'lib> {
     #[cfg(feature = "openh264")]
     OpenH264(OpenH264Encoder),
     LessH264(LessEncoderWrapper),
+    Av1(av1::Rav1eEncoder),
 }
 
 /// A view of image to have new width
@@ -370,7 +376,9 @@ where
                 match &cfg.codec {
                     ci2_remote_control::Mp4Codec::H264RawStream => {}
                     ci2_remote_control::Mp4Codec::H264LessAvc => {}
+                    ci2_remote_control::Mp4Codec::H264LessAvcMono12 => {}
                     ci2_remote_control::Mp4Codec::H264OpenH264(_) => {}
+                    ci2_remote_control::Mp4Codec::Av1Rav1e(_) => {}
                     ci2_remote_control::Mp4Codec::H264NvEnc(ref opts) => {
                         // scope for anonymous lifetime of ref
                         match &self.nv_enc {
@@ -487,6 +495,21 @@ where
                             first_timestamp: timestamp,
                         })
                     }
+                    ci2_remote_control::Mp4Codec::H264LessAvcMono12 => {
+                        MyEncoder::LessH264(LessEncoderWrapper {
+                            encoder: less_avc_wrapper::WrappedLessEncoder::new_mono12(),
+                            h264_parser,
+                            first_timestamp: timestamp,
+                        })
+                    }
+                    ci2_remote_control::Mp4Codec::Av1Rav1e(opts) => {
+                        MyEncoder::Av1(av1::Rav1eEncoder::new(
+                            opts,
+                            cfg.max_framerate.as_numerator_denominator(),
+                            cfg.h264_metadata,
+                            timestamp,
+                        ))
+                    }
                     #[allow(unused_variables)]
                     ci2_remote_control::Mp4Codec::H264OpenH264(opts) => {
                         #[cfg(feature = "openh264")]
@@ -604,6 +627,9 @@ where
                     }
                     #[cfg(feature = "openh264")]
                     MyEncoder::OpenH264(_encoder) => { /* nothing to do */ }
+                    MyEncoder::Av1(ref mut encoder) => {
+                        encoder.finish(&mut state.mp4_segment)?;
+                    }
                     MyEncoder::Nvidia(ref mut nv_encoder) => {
                         nv_encoder.encoder.end_stream()?;
                         // Now done with all frames, drain the pending data.
@@ -633,8 +659,14 @@ where
                     }
                 }
 
-                if let MaybeMp4Writer::Mp4Writer(mut mp4_writer) = state.mp4_segment {
-                    mp4_writer.write_end()?;
+                match state.mp4_segment {
+                    MaybeMp4Writer::Mp4Writer(mut mp4_writer) => {
+                        mp4_writer.write_end()?;
+                    }
+                    MaybeMp4Writer::Av1Writer(mut av1_writer) => {
+                        av1_writer.write_end()?;
+                    }
+                    MaybeMp4Writer::Nothing | MaybeMp4Writer::Starting(_) => {}
                 }
 
                 trace!("Finalized video.");
@@ -768,6 +800,9 @@ where
                 state_inner.trim_height,
             )?;
         }
+        (MyEncoder::Av1(encoder), Some(_state_inner)) => {
+            encoder.encode_frame(&mut state.mp4_segment, raw_frame, timestamp)?;
+        }
         (MyEncoder::Nvidia(ref mut nv_encoder), Some(state_inner)) => {
             let vram_buf: &mut IOBuffer<_, _> = match nv_encoder.vram_queue.get_available() {
                 Some(iobuf) => iobuf,
@@ -882,7 +917,7 @@ impl LessEncoderWrapper {
             MaybeMp4Writer::Starting(fd) => {
                 start_mp4_writer(fd, sps, pps, trim_width, trim_height)?
             }
-            MaybeMp4Writer::Nothing => {
+            MaybeMp4Writer::Nothing | MaybeMp4Writer::Av1Writer(_) => {
                 panic!("inconsistent state");
             }
         };
@@ -926,7 +961,7 @@ impl<'lib> NvEncoder<'lib> {
                 let pps = self.h264_parser.pps().unwrap();
                 start_mp4_writer(fd, sps, pps, trim_width, trim_height)?
             }
-            MaybeMp4Writer::Nothing => {
+            MaybeMp4Writer::Nothing | MaybeMp4Writer::Av1Writer(_) => {
                 panic!("inconsistent state");
             }
         };
@@ -1011,7 +1046,7 @@ impl OpenH264Encoder {
             MaybeMp4Writer::Starting(fd) => {
                 start_mp4_writer(fd, sps, pps, trim_width, trim_height)?
             }
-            MaybeMp4Writer::Nothing => {
+            MaybeMp4Writer::Nothing | MaybeMp4Writer::Av1Writer(_) => {
                 panic!("inconsistent state");
             }
         };
@@ -1037,6 +1072,7 @@ where
     Nothing,
     Starting(T),
     Mp4Writer(mp4::Mp4Writer<T>),
+    Av1Writer(av1::Av1Mp4Writer<T>),
 }
 
 #[derive(Clone)]
@@ -939,6 +939,9 @@ pub fn run_cli(cli: Cli) -> Result<()> {
                     )
                     .with_context(|| "while writing raw h264 buffer")?;
             }
+            ImageData::EncodedAv1(_) => {
+                anyhow::bail!("cannot convert undecoded AV1 frame");
+            }
         }
 
         // update desired for next frame
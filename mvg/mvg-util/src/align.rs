@@ -0,0 +1,74 @@
+use nalgebra::{Matrix3, Vector3};
+
+use crate::{Error, Result};
+
+/// A similarity transformation `x' = s * rot * x + t`.
+///
+/// The parameters are in the form taken by
+/// [mvg::MultiCameraSystem::align].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Similarity {
+    pub s: f64,
+    pub rot: Matrix3<f64>,
+    pub t: Vector3<f64>,
+}
+
+impl Similarity {
+    pub fn apply(&self, x: &Vector3<f64>) -> Vector3<f64> {
+        self.s * self.rot * x + self.t
+    }
+}
+
+/// Estimate the similarity transformation which maps `src` onto `dst`.
+///
+/// This uses the least-squares method of Umeyama (1991). If `with_scale` is
+/// false, the scale is fixed at one and the result is a rigid transformation.
+pub fn estimate_similarity(
+    src: &[Vector3<f64>],
+    dst: &[Vector3<f64>],
+    with_scale: bool,
+) -> Result<Similarity> {
+    assert_eq!(src.len(), dst.len());
+    let n = src.len();
+    if n < 3 {
+        return Err(Error::NotEnoughPoints { needed: 3, got: n });
+    }
+    let nf = n as f64;
+    let mean_src = src.iter().sum::<Vector3<f64>>() / nf;
+    let mean_dst = dst.iter().sum::<Vector3<f64>>() / nf;
+
+    let mut cov = Matrix3::zeros();
+    let mut var_src = 0.0;
+    for (s, d) in src.iter().zip(dst.iter()) {
+        let s = s - mean_src;
+        let d = d - mean_dst;
+        cov += d * s.transpose();
+        var_src += s.norm_squared();
+    }
+    cov /= nf;
+    var_src /= nf;
+
+    let svd = cov.svd(true, true);
+    let u = svd.u.ok_or(Error::DegeneratePoints)?;
+    let v_t = svd.v_t.ok_or(Error::DegeneratePoints)?;
+    // Singular values are sorted in descending order.
+    let sv = &svd.singular_values;
+    if var_src <= 0.0 || sv[1] <= 1e-10 * sv[0] {
+        return Err(Error::DegeneratePoints);
+    }
+
+    // Ensure a proper rotation (no reflection).
+    let mut d = Matrix3::identity();
+    if (u * v_t).determinant() < 0.0 {
+        d[(2, 2)] = -1.0;
+    }
+    let rot = u * d * v_t;
+
+    let s = if with_scale {
+        sv.dot(&d.diagonal()) / var_src
+    } else {
+        1.0
+    };
+    let t = mean_dst - s * rot * mean_src;
+    Ok(Similarity { s, rot, t })
+}
@@ -0,0 +1,308 @@
+//! Import and export of COLMAP text models.
+//!
+//! A COLMAP model is a directory with `cameras.txt`, `images.txt` and
+//! `points3D.txt`. Each of our cameras becomes one COLMAP camera and one
+//! COLMAP image with the same name. Both COLMAP and [mvg] use the OpenCV
+//! camera coordinate system and store the world-to-camera transformation, so
+//! the extrinsic parameters are used without conversion.
+//!
+//! See <https://colmap.github.io/format.html#text-format>.
+
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+use nalgebra::{Point3, Quaternion, UnitQuaternion, Vector3, Vector5};
+
+use cam_geom::ExtrinsicParameters;
+use mvg::{Camera, MultiCameraSystem};
+use opencv_ros_camera::{Distortion, RosOpenCvIntrinsics};
+
+use crate::{Error, Result};
+
+pub const CAMERAS_TXT: &str = "cameras.txt";
+pub const IMAGES_TXT: &str = "images.txt";
+pub const POINTS3D_TXT: &str = "points3D.txt";
+
+/// The contents of the text files of a COLMAP model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColmapText {
+    pub cameras_txt: String,
+    pub images_txt: String,
+}
+
+/// Convert a calibration to COLMAP text files.
+///
+/// COLMAP cannot represent skew or rectification, so cameras with these
+/// return an error.
+pub fn to_colmap(system: &MultiCameraSystem<f64>) -> Result<ColmapText> {
+    let mut cameras_txt = String::new();
+    let mut images_txt = String::new();
+    writeln!(
+        cameras_txt,
+        "# Camera list with one line of data per camera:"
+    )
+    .unwrap();
+    writeln!(cameras_txt, "#   CAMERA_ID, MODEL, WIDTH, HEIGHT, PARAMS[]").unwrap();
+    writeln!(images_txt, "# Image list with two lines of data per image:").unwrap();
+    writeln!(
+        images_txt,
+        "#   IMAGE_ID, QW, QX, QY, QZ, TX, TY, TZ, CAMERA_ID, NAME"
+    )
+    .unwrap();
+    writeln!(images_txt, "#   POINTS2D[] as (X, Y, POINT3D_ID)").unwrap();
+
+    for (idx, (cam_name, cam)) in system.cams_by_name().iter().enumerate() {
+        let unsupported = |msg: &str| Error::UnsupportedCamera {
+            cam_name: cam_name.clone(),
+            msg: msg.into(),
+        };
+        if cam_name.contains(char::is_whitespace) {
+            return Err(unsupported("COLMAP names cannot contain whitespace"));
+        }
+        let intrinsics = cam.intrinsics();
+        if !intrinsics.rect.is_identity(1e-10) {
+            return Err(unsupported("COLMAP does not support rectification"));
+        }
+        let k = &intrinsics.k;
+        if k[(0, 1)].abs() > 1e-10 * k[(0, 0)].abs() {
+            return Err(unsupported("COLMAP does not support skew"));
+        }
+        let (fx, fy, cx, cy) = (k[(0, 0)], k[(1, 1)], k[(0, 2)], k[(1, 2)]);
+        let d = &intrinsics.distortion;
+        let (k1, k2, p1, p2, k3) = (
+            d.radial1(),
+            d.radial2(),
+            d.tangential1(),
+            d.tangential2(),
+            d.radial3(),
+        );
+
+        let id = idx + 1;
+        let (w, h) = (cam.width(), cam.height());
+        if d.is_linear() {
+            writeln!(cameras_txt, "{id} PINHOLE {w} {h} {fx} {fy} {cx} {cy}").unwrap();
+        } else if k3 == 0.0 {
+            writeln!(
+                cameras_txt,
+                "{id} OPENCV {w} {h} {fx} {fy} {cx} {cy} {k1} {k2} {p1} {p2}"
+            )
+            .unwrap();
+        } else {
+            writeln!(
+                cameras_txt,
+                "{id} FULL_OPENCV {w} {h} {fx} {fy} {cx} {cy} {k1} {k2} {p1} {p2} {k3} 0 0 0"
+            )
+            .unwrap();
+        }
+
+        let extrinsics = cam.extrinsics();
+        let q = UnitQuaternion::from_rotation_matrix(extrinsics.rotation());
+        let t = extrinsics.translation();
+        writeln!(
+            images_txt,
+            "{id} {} {} {} {} {} {} {} {id} {cam_name}",
+            q.w, q.i, q.j, q.k, t.x, t.y, t.z
+        )
+        .unwrap();
+        // No 2D points.
+        writeln!(images_txt).unwrap();
+    }
+    Ok(ColmapText {
+        cameras_txt,
+        images_txt,
+    })
+}
+
+/// Write a calibration as a COLMAP text model into `dir`.
+///
+/// An empty `points3D.txt` is written so that COLMAP can load the model.
+pub fn write_colmap_dir(system: &MultiCameraSystem<f64>, dir: &Path) -> Result<()> {
+    let text = to_colmap(system)?;
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(dir.join(CAMERAS_TXT), text.cameras_txt)?;
+    std::fs::write(dir.join(IMAGES_TXT), text.images_txt)?;
+    std::fs::write(
+        dir.join(POINTS3D_TXT),
+        "# 3D point list with one line of data per point:\n",
+    )?;
+    Ok(())
+}
+
+/// Read a calibration from the COLMAP text model in `dir`.
+pub fn read_colmap_dir(dir: &Path) -> Result<MultiCameraSystem<f64>> {
+    let cameras_txt = std::fs::read_to_string(dir.join(CAMERAS_TXT))?;
+    let images_txt = std::fs::read_to_string(dir.join(IMAGES_TXT))?;
+    from_colmap(&cameras_txt, &images_txt)
+}
+
+struct ColmapCamera {
+    width: usize,
+    height: usize,
+    intrinsics: RosOpenCvIntrinsics<f64>,
+}
+
+/// Parse a calibration from the contents of COLMAP text files.
+///
+/// Every image becomes a camera named after the image (without the
+/// extension of common image file types).
+pub fn from_colmap(cameras_txt: &str, images_txt: &str) -> Result<MultiCameraSystem<f64>> {
+    let mut colmap_cams = BTreeMap::new();
+    for (line_idx, line) in cameras_txt.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse_err = |msg: String| Error::Parse {
+            file: CAMERAS_TXT.into(),
+            line: line_idx + 1,
+            msg,
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(parse_err("expected CAMERA_ID MODEL WIDTH HEIGHT".into()));
+        }
+        let id: u64 = parse(fields[0]).map_err(parse_err)?;
+        let model = fields[1];
+        let width: usize = parse(fields[2]).map_err(parse_err)?;
+        let height: usize = parse(fields[3]).map_err(parse_err)?;
+        let params = fields[4..]
+            .iter()
+            .map(|x| parse::<f64>(x))
+            .collect::<std::result::Result<Vec<f64>, String>>()
+            .map_err(parse_err)?;
+        let intrinsics = colmap_intrinsics(model, &params).map_err(parse_err)?;
+        colmap_cams.insert(
+            id,
+            ColmapCamera {
+                width,
+                height,
+                intrinsics,
+            },
+        );
+    }
+
+    let mut cams_by_name = BTreeMap::new();
+    let mut lines = images_txt.lines().enumerate();
+    while let Some((line_idx, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse_err = |msg: String| Error::Parse {
+            file: IMAGES_TXT.into(),
+            line: line_idx + 1,
+            msg,
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 10 {
+            return Err(parse_err(
+                "expected IMAGE_ID QW QX QY QZ TX TY TZ CAMERA_ID NAME".into(),
+            ));
+        }
+        let v = fields[1..8]
+            .iter()
+            .map(|x| parse::<f64>(x))
+            .collect::<std::result::Result<Vec<f64>, String>>()
+            .map_err(parse_err)?;
+        let camera_id: u64 = parse(fields[8]).map_err(parse_err)?;
+        let name = image_name_to_cam_name(fields[9]);
+
+        let colmap_cam = colmap_cams
+            .get(&camera_id)
+            .ok_or_else(|| parse_err(format!("unknown camera {camera_id}")))?;
+        let rquat = UnitQuaternion::from_quaternion(Quaternion::new(v[0], v[1], v[2], v[3]));
+        let translation = Vector3::new(v[4], v[5], v[6]);
+        let camcenter = Point3::from(-(rquat.inverse() * translation));
+        let extrinsics = ExtrinsicParameters::from_rotation_and_camcenter(rquat, camcenter);
+        let cam = Camera::new(
+            colmap_cam.width,
+            colmap_cam.height,
+            extrinsics,
+            colmap_cam.intrinsics.clone(),
+        )?;
+        if cams_by_name.insert(name.clone(), cam).is_some() {
+            return Err(parse_err(format!("duplicate image name \"{name}\"")));
+        }
+
+        // The following line lists the 2D points, which we do not use.
+        lines.next();
+    }
+    Ok(MultiCameraSystem::new(cams_by_name))
+}
+
+fn parse<T: std::str::FromStr>(s: &str) -> std::result::Result<T, String> {
+    s.parse().map_err(|_| format!("could not parse \"{s}\""))
+}
+
+fn colmap_intrinsics(
+    model: &str,
+    params: &[f64],
+) -> std::result::Result<RosOpenCvIntrinsics<f64>, String> {
+    let n_params = match model {
+        "SIMPLE_PINHOLE" => 3,
+        "PINHOLE" | "SIMPLE_RADIAL" => 4,
+        "RADIAL" => 5,
+        "OPENCV" => 8,
+        "FULL_OPENCV" => 12,
+        _ => return Err(format!("unsupported camera model {model}")),
+    };
+    if params.len() != n_params {
+        return Err(format!(
+            "camera model {model} has {n_params} parameters, found {}",
+            params.len()
+        ));
+    }
+    // (fx, fy, cx, cy, [k1, k2, p1, p2, k3])
+    let (fx, fy, cx, cy, d) = match model {
+        "SIMPLE_PINHOLE" => (params[0], params[0], params[1], params[2], [0.0; 5]),
+        "PINHOLE" => (params[0], params[1], params[2], params[3], [0.0; 5]),
+        "SIMPLE_RADIAL" => (
+            params[0],
+            params[0],
+            params[1],
+            params[2],
+            [params[3], 0.0, 0.0, 0.0, 0.0],
+        ),
+        "RADIAL" => (
+            params[0],
+            params[0],
+            params[1],
+            params[2],
+            [params[3], params[4], 0.0, 0.0, 0.0],
+        ),
+        "OPENCV" => (
+            params[0],
+            params[1],
+            params[2],
+            params[3],
+            [params[4], params[5], params[6], params[7], 0.0],
+        ),
+        "FULL_OPENCV" => {
+            if params[9..].iter().any(|k| *k != 0.0) {
+                return Err("rational distortion coefficients k4, k5, k6 are not supported".into());
+            }
+            (
+                params[0],
+                params[1],
+                params[2],
+                params[3],
+                [params[4], params[5], params[6], params[7], params[8]],
+            )
+        }
+        _ => unreachable!(),
+    };
+    let distortion = Distortion::from_opencv_vec(Vector5::from(d));
+    Ok(RosOpenCvIntrinsics::from_params_with_distortion(
+        fx, 0.0, fy, cx, cy, distortion,
+    ))
+}
+
+fn image_name_to_cam_name(image_name: &str) -> String {
+    const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "tif", "tiff", "bmp"];
+    let path = Path::new(image_name);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()) => {
+            path.with_extension("").to_string_lossy().into_owned()
+        }
+        _ => image_name.to_string(),
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use nalgebra::{UnitQuaternion, Vector3};
+
+use mvg::{Camera, MultiCameraSystem};
+
+/// The difference between the two versions of a camera.
+///
+/// All deltas are `b - a`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraDiff {
+    /// Displacement of the camera center.
+    pub center_delta: Vector3<f64>,
+    /// Angle (in radians) of the rotation between the two orientations.
+    pub orientation_angle: f64,
+    pub fx_delta: f64,
+    pub fy_delta: f64,
+    pub cx_delta: f64,
+    pub cy_delta: f64,
+    pub skew_delta: f64,
+    /// Change of distortion coefficients (k1, k2, p1, p2, k3).
+    pub distortion_delta: [f64; 5],
+    pub width_changed: bool,
+    pub height_changed: bool,
+}
+
+impl CameraDiff {
+    fn new(a: &Camera<f64>, b: &Camera<f64>) -> Self {
+        let center_delta = b.extrinsics().camcenter() - a.extrinsics().camcenter();
+        let qa = UnitQuaternion::from_rotation_matrix(a.extrinsics().rotation());
+        let qb = UnitQuaternion::from_rotation_matrix(b.extrinsics().rotation());
+        let orientation_angle = qa.angle_to(&qb);
+
+        let ka = &a.intrinsics().k;
+        let kb = &b.intrinsics().k;
+        let da = distortion_vec(a);
+        let db = distortion_vec(b);
+        let distortion_delta = std::array::from_fn(|i| db[i] - da[i]);
+        Self {
+            center_delta,
+            orientation_angle,
+            fx_delta: kb[(0, 0)] - ka[(0, 0)],
+            fy_delta: kb[(1, 1)] - ka[(1, 1)],
+            cx_delta: kb[(0, 2)] - ka[(0, 2)],
+            cy_delta: kb[(1, 2)] - ka[(1, 2)],
+            skew_delta: kb[(0, 1)] - ka[(0, 1)],
+            distortion_delta,
+            width_changed: a.width() != b.width(),
+            height_changed: a.height() != b.height(),
+        }
+    }
+}
+
+fn distortion_vec(cam: &Camera<f64>) -> [f64; 5] {
+    let d = &cam.intrinsics().distortion;
+    [
+        d.radial1(),
+        d.radial2(),
+        d.tangential1(),
+        d.tangential2(),
+        d.radial3(),
+    ]
+}
+
+impl std::fmt::Display for CameraDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let c = &self.center_delta;
+        writeln!(f, "  center moved {} ({}, {}, {})", c.norm(), c.x, c.y, c.z)?;
+        writeln!(
+            f,
+            "  orientation changed {} degrees",
+            self.orientation_angle.to_degrees()
+        )?;
+        writeln!(
+            f,
+            "  fx {:+}, fy {:+}, cx {:+}, cy {:+}, skew {:+}",
+            self.fx_delta, self.fy_delta, self.cx_delta, self.cy_delta, self.skew_delta
+        )?;
+        let d = &self.distortion_delta;
+        writeln!(
+            f,
+            "  distortion k1 {:+}, k2 {:+}, p1 {:+}, p2 {:+}, k3 {:+}",
+            d[0], d[1], d[2], d[3], d[4]
+        )?;
+        if self.width_changed || self.height_changed {
+            writeln!(f, "  image size changed")?;
+        }
+        Ok(())
+    }
+}
+
+/// The difference between two calibrations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemDiff {
+    /// Cameras present in both calibrations.
+    pub cameras: BTreeMap<String, CameraDiff>,
+    /// Names of cameras present only in the first calibration.
+    pub only_in_a: Vec<String>,
+    /// Names of cameras present only in the second calibration.
+    pub only_in_b: Vec<String>,
+}
+
+impl std::fmt::Display for SystemDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (cam_name, diff) in self.cameras.iter() {
+            writeln!(f, "{cam_name}:")?;
+            write!(f, "{diff}")?;
+        }
+        for cam_name in self.only_in_a.iter() {
+            writeln!(f, "{cam_name}: only in first calibration")?;
+        }
+        for cam_name in self.only_in_b.iter() {
+            writeln!(f, "{cam_name}: only in second calibration")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two calibrations camera by camera.
+pub fn diff_systems(a: &MultiCameraSystem<f64>, b: &MultiCameraSystem<f64>) -> SystemDiff {
+    let a = a.cams_by_name();
+    let b = b.cams_by_name();
+    let cameras = a
+        .iter()
+        .filter_map(|(name, cam_a)| {
+            b.get(name)
+                .map(|cam_b| (name.clone(), CameraDiff::new(cam_a, cam_b)))
+        })
+        .collect();
+    let only_in_a = a.keys().filter(|k| !b.contains_key(*k)).cloned().collect();
+    let only_in_b = b.keys().filter(|k| !a.contains_key(*k)).cloned().collect();
+    SystemDiff {
+        cameras,
+        only_in_a,
+        only_in_b,
+    }
+}
@@ -0,0 +1,143 @@
+//! Convert, align and compare multi-camera calibrations.
+//!
+//! Calibrations can be read from and written to flydra XML, pymvg JSON and
+//! COLMAP text files. The intrinsic parameters of each camera can
+//! additionally be written as ROS/OpenCV YAML files.
+#![deny(rust_2018_idioms)]
+
+use std::path::Path;
+
+use thiserror::Error;
+
+use flydra_mvg::FlydraMultiCameraSystem;
+
+mod align;
+pub use align::{estimate_similarity, Similarity};
+
+pub mod colmap;
+
+mod diff;
+pub use diff::{diff_systems, CameraDiff, SystemDiff};
+
+mod ros_yaml;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("mvg error: {0}")]
+    Mvg(#[from] mvg::MvgError),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("parse error in {file} line {line}: {msg}")]
+    Parse {
+        file: String,
+        line: usize,
+        msg: String,
+    },
+    #[error("camera \"{cam_name}\": {msg}")]
+    UnsupportedCamera { cam_name: String, msg: String },
+    #[error("{0}")]
+    UnsupportedFormat(String),
+    #[error("need at least {needed} point correspondences, got {got}")]
+    NotEnoughPoints { needed: usize, got: usize },
+    #[error("points are degenerate (collinear or coincident)")]
+    DegeneratePoints,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A file format for a multi-camera calibration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CalFormat {
+    /// Flydra XML file
+    FlydraXml,
+    /// pymvg JSON file
+    Pymvg,
+    /// Directory with COLMAP `cameras.txt` and `images.txt` files
+    Colmap,
+    /// Directory with one ROS/OpenCV YAML file of intrinsic parameters per
+    /// camera (write only)
+    RosYaml,
+}
+
+impl CalFormat {
+    /// Guess the format from the path.
+    ///
+    /// Returns `None` if the format cannot be determined.
+    pub fn guess<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let path = path.as_ref();
+        if path.join(colmap::CAMERAS_TXT).exists() {
+            return Some(Self::Colmap);
+        }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("xml") => Some(Self::FlydraXml),
+            Some("json") | Some("pymvg") => Some(Self::Pymvg),
+            _ => None,
+        }
+    }
+}
+
+/// Read a calibration.
+pub fn read_calibration<P: AsRef<Path>>(
+    path: P,
+    format: CalFormat,
+) -> Result<FlydraMultiCameraSystem<f64>> {
+    let path = path.as_ref();
+    match format {
+        CalFormat::FlydraXml => {
+            let fd = std::fs::File::open(path)?;
+            Ok(FlydraMultiCameraSystem::from_flydra_xml(fd)?)
+        }
+        CalFormat::Pymvg => {
+            let fd = std::fs::File::open(path)?;
+            let system = mvg::MultiCameraSystem::from_pymvg_json(fd)?;
+            Ok(FlydraMultiCameraSystem::from_system(system, None))
+        }
+        CalFormat::Colmap => {
+            let system = colmap::read_colmap_dir(path)?;
+            Ok(FlydraMultiCameraSystem::from_system(system, None))
+        }
+        CalFormat::RosYaml => Err(Error::UnsupportedFormat(
+            "ROS YAML files contain only intrinsic parameters and cannot be read as a \
+            calibration"
+                .into(),
+        )),
+    }
+}
+
+/// Write a calibration.
+///
+/// For [CalFormat::Colmap] and [CalFormat::RosYaml], `path` is a directory
+/// which is created if needed.
+pub fn write_calibration<P: AsRef<Path>>(
+    cal: &FlydraMultiCameraSystem<f64>,
+    path: P,
+    format: CalFormat,
+) -> Result<()> {
+    let path = path.as_ref();
+    if cal.has_refractive_boundary() && format != CalFormat::FlydraXml {
+        return Err(Error::UnsupportedFormat(format!(
+            "calibration has a refractive boundary, which cannot be saved as {format:?}"
+        )));
+    }
+    match format {
+        CalFormat::FlydraXml => {
+            let fd = std::fs::File::create(path)?;
+            cal.to_flydra_xml(fd)?;
+        }
+        CalFormat::Pymvg => {
+            let mut fd = std::fs::File::create(path)?;
+            cal.system().to_pymvg_writer(&mut fd)?;
+        }
+        CalFormat::Colmap => {
+            colmap::write_colmap_dir(cal.system(), path)?;
+        }
+        CalFormat::RosYaml => {
+            ros_yaml::write_ros_yaml_dir(cal.system(), path)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,183 @@
+#[macro_use]
+extern crate pretty_print_nalgebra;
+
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{self, Result};
+use nalgebra::Vector3;
+
+use flydra_mvg::FlydraMultiCameraSystem;
+use mvg_util::{read_calibration, write_calibration, CalFormat};
+
+#[derive(Debug, Parser)]
+#[command(name = "mvg-util", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the P and K matrices of each camera.
+    PrintCal {
+        /// Input calibration
+        input: PathBuf,
+        /// Format of the input calibration (guessed from the path if not given)
+        #[arg(long)]
+        from: Option<CalFormat>,
+    },
+    /// Convert a calibration between formats.
+    ///
+    /// Flydra XML, pymvg JSON and COLMAP text models (a directory with
+    /// `cameras.txt` and `images.txt`) can be read and written. The intrinsic
+    /// parameters can also be written as one ROS/OpenCV YAML file per camera.
+    Convert {
+        /// Input calibration
+        input: PathBuf,
+        /// Output calibration
+        output: PathBuf,
+        /// Format of the input calibration (guessed from the path if not given)
+        #[arg(long)]
+        from: Option<CalFormat>,
+        /// Format of the output calibration (guessed from the path if not given)
+        #[arg(long)]
+        to: Option<CalFormat>,
+    },
+    /// Apply the similarity transformation which best maps 3D points onto new
+    /// coordinates.
+    ///
+    /// The points are read from a CSV file with columns `x`, `y`, `z` (in
+    /// the coordinate frame of the input calibration) and `new_x`, `new_y`,
+    /// `new_z` (the desired coordinates). At least three non-collinear points
+    /// are required.
+    Align {
+        /// Input calibration
+        input: PathBuf,
+        /// CSV file with 3D point correspondences
+        points: PathBuf,
+        /// Output calibration
+        output: PathBuf,
+        /// Format of the input calibration (guessed from the path if not given)
+        #[arg(long)]
+        from: Option<CalFormat>,
+        /// Format of the output calibration (guessed from the path if not given)
+        #[arg(long)]
+        to: Option<CalFormat>,
+        /// Do not scale, only rotate and translate
+        #[arg(long)]
+        rigid: bool,
+    },
+    /// Compare two calibrations camera by camera.
+    Diff {
+        /// First calibration
+        a: PathBuf,
+        /// Second calibration
+        b: PathBuf,
+    },
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PointCorrespondence {
+    x: f64,
+    y: f64,
+    z: f64,
+    new_x: f64,
+    new_y: f64,
+    new_z: f64,
+}
+
+fn get_format(path: &Path, format: Option<CalFormat>) -> Result<CalFormat> {
+    format.or_else(|| CalFormat::guess(path)).ok_or_else(|| {
+        eyre::eyre!(
+            "cannot determine calibration format of \"{}\", please specify it",
+            path.display()
+        )
+    })
+}
+
+fn read(path: &Path, format: Option<CalFormat>) -> Result<FlydraMultiCameraSystem<f64>> {
+    let format = get_format(path, format)?;
+    Ok(read_calibration(path, format)?)
+}
+
+fn print_cal(cams: &FlydraMultiCameraSystem<f64>) {
+    for cam_name in cams.cam_names() {
+        let cam = cams.cam_by_name(cam_name).unwrap();
+        println!("  {}", cam_name);
+        let intrinsics = cam.do_not_use_intrinsics();
+        println!("P {}", pretty_print!(intrinsics.p));
+        println!("K {}", pretty_print!(intrinsics.k));
+    }
+}
+
+fn align(
+    cal: &FlydraMultiCameraSystem<f64>,
+    points: &Path,
+    rigid: bool,
+) -> Result<FlydraMultiCameraSystem<f64>> {
+    if cal.has_refractive_boundary() {
+        eyre::bail!("aligning calibrations with a refractive boundary is not supported");
+    }
+    let mut src = Vec::new();
+    let mut dst = Vec::new();
+    for row in csv::Reader::from_path(points)?.deserialize() {
+        let row: PointCorrespondence = row?;
+        src.push(Vector3::new(row.x, row.y, row.z));
+        dst.push(Vector3::new(row.new_x, row.new_y, row.new_z));
+    }
+    let xform = mvg_util::estimate_similarity(&src, &dst, !rigid)?;
+    println!("scale: {}", xform.s);
+    println!("rotation: {}", pretty_print!(xform.rot));
+    println!("translation: {}", pretty_print!(xform.t));
+    for (s, d) in src.iter().zip(dst.iter()) {
+        println!(
+            "  ({}, {}, {}): residual {}",
+            d.x,
+            d.y,
+            d.z,
+            (xform.apply(s) - d).norm()
+        );
+    }
+
+    let aligned = cal.system().align(xform.s, xform.rot, xform.t)?;
+    Ok(FlydraMultiCameraSystem::from_system(aligned, None))
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::PrintCal { input, from } => {
+            let cams = read(&input, from)?;
+            println!("# ----- {:?} ----- ", input);
+            print_cal(&cams);
+        }
+        Command::Convert {
+            input,
+            output,
+            from,
+            to,
+        } => {
+            let cal = read(&input, from)?;
+            write_calibration(&cal, &output, get_format(&output, to)?)?;
+        }
+        Command::Align {
+            input,
+            points,
+            output,
+            from,
+            to,
+            rigid,
+        } => {
+            let cal = read(&input, from)?;
+            let aligned = align(&cal, &points, rigid)?;
+            write_calibration(&aligned, &output, get_format(&output, to)?)?;
+        }
+        Command::Diff { a, b } => {
+            let a = read(&a, None)?;
+            let b = read(&b, None)?;
+            print!("{}", mvg_util::diff_systems(a.system(), b.system()));
+        }
+    }
+    Ok(())
+}
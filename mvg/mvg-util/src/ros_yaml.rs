@@ -0,0 +1,66 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use mvg::MultiCameraSystem;
+
+#[derive(Serialize)]
+struct RosMatrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+
+/// The camera calibration file format used by ROS and OpenCV.
+#[derive(Serialize)]
+struct RosCameraInfo {
+    image_width: usize,
+    image_height: usize,
+    camera_name: String,
+    camera_matrix: RosMatrix,
+    distortion_model: &'static str,
+    distortion_coefficients: RosMatrix,
+    rectification_matrix: RosMatrix,
+    projection_matrix: RosMatrix,
+}
+
+/// Write the intrinsic parameters of each camera to `<dir>/<camera name>.yaml`.
+pub(crate) fn write_ros_yaml_dir(system: &MultiCameraSystem<f64>, dir: &Path) -> crate::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for (cam_name, cam) in system.cams_by_name().iter() {
+        let intrinsics = cam.intrinsics();
+        let d = &intrinsics.distortion;
+        // ROS files store matrices in row-major order.
+        let info = RosCameraInfo {
+            image_width: cam.width(),
+            image_height: cam.height(),
+            camera_name: cam_name.clone(),
+            camera_matrix: row_major(3, 3, |i, j| intrinsics.k[(i, j)]),
+            distortion_model: "plumb_bob",
+            distortion_coefficients: RosMatrix {
+                rows: 1,
+                cols: 5,
+                data: vec![
+                    d.radial1(),
+                    d.radial2(),
+                    d.tangential1(),
+                    d.tangential2(),
+                    d.radial3(),
+                ],
+            },
+            rectification_matrix: row_major(3, 3, |i, j| intrinsics.rect[(i, j)]),
+            projection_matrix: row_major(3, 4, |i, j| intrinsics.p[(i, j)]),
+        };
+        let fd = std::fs::File::create(dir.join(format!("{cam_name}.yaml")))?;
+        serde_yaml::to_writer(fd, &info)?;
+    }
+    Ok(())
+}
+
+fn row_major(rows: usize, cols: usize, f: impl Fn(usize, usize) -> f64) -> RosMatrix {
+    let data = (0..rows)
+        .flat_map(|i| (0..cols).map(move |j| (i, j)))
+        .map(|(i, j)| f(i, j))
+        .collect();
+    RosMatrix { rows, cols, data }
+}
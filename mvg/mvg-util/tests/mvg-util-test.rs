@@ -0,0 +1,78 @@
+use nalgebra::{Rotation3, Vector3};
+
+use mvg_util::{read_calibration, write_calibration, CalFormat};
+
+fn assert_nearly_same(diff: &mvg_util::SystemDiff) {
+    assert!(diff.only_in_a.is_empty());
+    assert!(diff.only_in_b.is_empty());
+    for (cam_name, d) in diff.cameras.iter() {
+        assert!(d.center_delta.norm() < 1e-6, "{cam_name}: {d}");
+        assert!(d.orientation_angle < 1e-6, "{cam_name}: {d}");
+        assert!(d.fx_delta.abs() < 1e-6, "{cam_name}: {d}");
+        assert!(d.cy_delta.abs() < 1e-6, "{cam_name}: {d}");
+        assert!(d.distortion_delta.iter().all(|x| x.abs() < 1e-9));
+    }
+}
+
+#[test]
+fn test_colmap_roundtrip() {
+    let orig = read_calibration("test-data/value0.xml", CalFormat::FlydraXml).unwrap();
+    let tmpdir = tempfile::tempdir().unwrap();
+    let colmap_dir = tmpdir.path().join("colmap");
+    write_calibration(&orig, &colmap_dir, CalFormat::Colmap).unwrap();
+
+    assert_eq!(CalFormat::guess(&colmap_dir), Some(CalFormat::Colmap));
+    let loaded = read_calibration(&colmap_dir, CalFormat::Colmap).unwrap();
+    assert_eq!(loaded.len(), orig.len());
+    assert_nearly_same(&mvg_util::diff_systems(orig.system(), loaded.system()));
+}
+
+#[test]
+fn test_pymvg_roundtrip() {
+    let orig = read_calibration("test-data/value0.xml", CalFormat::FlydraXml).unwrap();
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = tmpdir.path().join("cal.json");
+    write_calibration(&orig, &path, CalFormat::Pymvg).unwrap();
+    let loaded = read_calibration(&path, CalFormat::guess(&path).unwrap()).unwrap();
+    assert_nearly_same(&mvg_util::diff_systems(orig.system(), loaded.system()));
+}
+
+#[test]
+fn test_diff() {
+    let a = read_calibration("test-data/value0.xml", CalFormat::FlydraXml).unwrap();
+    let b = read_calibration("test-data/value1.xml", CalFormat::FlydraXml).unwrap();
+    let diff = mvg_util::diff_systems(a.system(), b.system());
+    assert_eq!(diff.cameras.len(), a.len());
+    assert!(diff
+        .cameras
+        .values()
+        .any(|d| d.center_delta.norm() > 1e-6 || d.orientation_angle > 1e-6));
+}
+
+#[test]
+fn test_align() {
+    let orig = read_calibration("test-data/value0.xml", CalFormat::FlydraXml).unwrap();
+    let s = 2.0;
+    let rot = *Rotation3::from_euler_angles(0.1, -0.2, 0.3).matrix();
+    let t = Vector3::new(1.0, 2.0, -3.0);
+    let moved = orig.system().align(s, rot, t).unwrap();
+
+    // Recover the transformation from the camera centers.
+    let centers = |system: &mvg::MultiCameraSystem<f64>| -> Vec<Vector3<f64>> {
+        system
+            .cams_by_name()
+            .values()
+            .map(|cam| cam.extrinsics().camcenter().coords)
+            .collect()
+    };
+    let xform =
+        mvg_util::estimate_similarity(&centers(orig.system()), &centers(&moved), true).unwrap();
+    assert!((xform.s - s).abs() < 1e-6);
+    assert!((xform.rot - rot).norm() < 1e-6);
+    assert!((xform.t - t).norm() < 1e-6);
+
+    let rigid =
+        mvg_util::estimate_similarity(&centers(orig.system()), &centers(orig.system()), false)
+            .unwrap();
+    assert!((rigid.rot - nalgebra::Matrix3::identity()).norm() < 1e-9);
+}
@@ -40,4 +40,13 @@ pub struct ClockModel {
     pub offset: f64,
     pub residuals: f64,
     pub n_measurements: u64,
+    /// Number of measurements used for the fit after outlier rejection.
+    #[serde(default)]
+    pub n_inliers: Option<u64>,
+    /// Robust standard deviation of the residuals of the fit.
+    #[serde(default)]
+    pub residual_std: Option<f64>,
+    /// Standard error of times predicted with the model for current frames.
+    #[serde(default)]
+    pub prediction_std: Option<f64>,
 }
@@ -1,35 +1,36 @@
 #!/usr/bin/env python
 import argparse
-import json
 import time
-import sys
-from urllib.parse import urlparse
+from urllib.parse import urlparse, parse_qs
 import requests  # https://docs.python-requests.org/en/latest/user/install
 
 
-class BraidProxy:
-    def __init__(self, braid_url):
-        self.callback_url = urlparse(braid_url)._replace(path="callback").geturl()
-        # Setup initial session
+class BraidApi:
+    """Client for version 1 of the Braid REST API.
+
+    See `/api/v1/openapi.yaml` on the Braid server for a full description.
+    """
+
+    def __init__(self, braid_url, token=None):
+        url = urlparse(braid_url)
+        if token is None:
+            # Use the token from the URL printed by Braid at startup, if any.
+            token = parse_qs(url.query).get("token", [None])[0]
+        self.base_url = url._replace(path="/api/v1/", query="").geturl()
         self.session = requests.session()
-        r = self.session.get(braid_url)
-        if r.status_code != requests.codes.ok:
-            print(f"request URL: {braid_url}")
-            print("request failed. response:")
-            print(r.text)
-            raise RuntimeError("connection to braid failed.")
-
-    def send(self, cmd_dict):
-        body = json.dumps(cmd_dict)
-        r = self.session.post(
-            self.callback_url, data=body, headers={"Content-Type": "application/json"}
-        )
-        if r.status_code != requests.codes.ok:
-            print(
-                "error making request, status code {}".format(r.status_code),
-                file=sys.stderr,
-            )
-            sys.exit(1)
+        if token is not None:
+            self.session.headers["Authorization"] = f"Bearer {token}"
+
+    def _check(self, r):
+        if not r.ok:
+            raise RuntimeError(f"request failed ({r.status_code}): {r.text}")
+        return r
+
+    def get(self, path):
+        return self._check(self.session.get(self.base_url + path)).json()
+
+    def put(self, path, body):
+        self._check(self.session.put(self.base_url + path, json=body))
 
 
 def main():
@@ -41,14 +42,23 @@ def main():
         default="http://127.0.0.1:33333/",
         help="URL of Braid",
     )
+    parser.add_argument(
+        "--token",
+        type=str,
+        default=None,
+        help="API token (default: taken from the URL)",
+    )
     args = parser.parse_args()
-    braid = BraidProxy(braid_url=args.braid_url)
+    braid = BraidApi(braid_url=args.braid_url, token=args.token)
+
+    for cam in braid.get("cameras"):
+        print(f"camera {cam['name']}: {cam['recent_stats']}")
 
-    braid.send({"DoRecordMp4Files": True})
+    braid.put("recording/mp4", {"recording": True})
     print("Recording for 5 seconds...")
     time.sleep(5.0)
 
-    braid.send({"DoRecordMp4Files": False})
+    braid.put("recording/mp4", {"recording": False})
     print("...finished.")
 
 
@@ -109,6 +109,25 @@ pub struct ApriltagState {
     pub do_detection: bool,
     pub april_family: TagFamily,
     pub is_recording_csv: Option<RecordingPath>,
+    /// Parameters for estimating the 6-DOF pose of each detected tag. If None,
+    /// tag poses are not estimated.
+    pub pose_estimation: Option<ApriltagPoseConfig>,
+}
+
+/// Parameters for estimating the 6-DOF pose of April tags.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApriltagPoseConfig {
+    /// Edge length of the tag's black border, in meters.
+    pub tag_size_meters: f64,
+    /// Focal length in x direction (pixels)
+    pub fx: f64,
+    /// Focal length in y direction (pixels)
+    pub fy: f64,
+    /// Principal point x coordinate (pixels)
+    pub cx: f64,
+    /// Principal point y coordinate (pixels)
+    pub cy: f64,
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -55,6 +55,16 @@ struct DerivedArgs {
     /// If set, output a copy of the video stream on this v4l2 device (e.g. `/dev/video0`)
     #[arg(long)]
     v4l2loopback: Option<PathBuf>,
+    #[cfg(feature = "fiducial")]
+    /// Edge length of the black border of April tags (in meters). If set, the
+    /// 6-DOF pose of each detected tag is saved. Requires `--apriltag-camera-info`.
+    #[arg(long, requires = "apriltag_camera_info")]
+    apriltag_tag_size: Option<f64>,
+    #[cfg(feature = "fiducial")]
+    /// Camera intrinsics (ROS camera info YAML file, e.g. from checkerboard
+    /// calibration) used to estimate April tag poses.
+    #[arg(long)]
+    apriltag_camera_info: Option<PathBuf>,
 }
 
 fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
@@ -345,7 +355,7 @@ fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
 
     // Since DerivedArgs implements FromArgMatches, we can extract it from the unstructured ArgMatches.
     // This is the main benefit of using derived arguments.
-    #[cfg(target_os = "linux")]
+    #[cfg(any(target_os = "linux", feature = "fiducial"))]
     let derived_matches = DerivedArgs::from_arg_matches(&matches)
         .map_err(|err| err.exit())
         .unwrap();
@@ -371,6 +381,10 @@ fn parse_args(app_name: &str) -> Result<StrandCamArgs> {
         model_server_addr,
         #[cfg(feature = "fiducial")]
         apriltag_csv_filename_template,
+        #[cfg(feature = "fiducial")]
+        apriltag_tag_size: derived_matches.apriltag_tag_size,
+        #[cfg(feature = "fiducial")]
+        apriltag_camera_info: derived_matches.apriltag_camera_info,
         #[cfg(target_os = "linux")]
         v4l2loopback: derived_matches.v4l2loopback,
         ..Default::default()
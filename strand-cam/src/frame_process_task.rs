@@ -350,6 +350,7 @@ pub(crate) async fn frame_process_task<'a>(
                                         mini_arena_debug_image_dir: None,
                                         write_buffer_size_num_messages: args
                                             .write_buffer_size_num_messages,
+                                        per_observation_timestamps: false,
                                     },
                                     cam_manager,
                                     Some(recon),
@@ -547,11 +548,16 @@ pub(crate) async fn frame_process_task<'a>(
                 #[cfg(feature = "fiducial")]
                 {
                     if let Some(x) = store_cache.as_ref() {
+                        let pose_estimation = x
+                            .apriltag_state
+                            .as_ref()
+                            .and_then(|ts| ts.pose_estimation.clone());
                         apriltag_writer = Some(AprilTagWriter::new(
                             format_str_apriltags_csv,
                             &x.camera_name,
                             x.image_width as usize,
                             x.image_height as usize,
+                            pose_estimation,
                         )?);
                     }
                 }
@@ -608,7 +614,7 @@ pub(crate) async fn frame_process_task<'a>(
                         }
                         Some(ptp_stamp.try_into().unwrap())
                     }
-                    Some(TriggerType::DeviceTimestamp) => {
+                    Some(TriggerType::DeviceTimestamp(_)) => {
                         let cm = device_clock_model.as_ref().unwrap();
                         let this_local_and_cam_time0 = local_and_cam_time0.as_ref().unwrap();
                         let (local_time0, cam_time0) = this_local_and_cam_time0;
@@ -617,14 +623,14 @@ pub(crate) async fn frame_process_task<'a>(
                         let local_elapsed_nanos: f64 =
                             (device_elapsed_nanos as f64) * cm.gain + cm.offset;
                         // let ts: f64 = (device_timestamp as f64) * cm.gain + cm.offset;
-                        dbg!((local_elapsed_nanos, device_timestamp, &cm));
+                        tracing::trace!(local_elapsed_nanos, device_timestamp, "{cm:?}");
 
                         let local_nanos = local_time0 + local_elapsed_nanos.round() as u64;
                         let local: chrono::DateTime<chrono::Utc> =
                             PtpStamp::new(local_nanos).try_into().unwrap();
-                        let x = FlydraFloatTimestampLocal::<flydra_types::Triggerbox>::from(local);
-                        dbg!(&x);
-                        Some(x)
+                        Some(FlydraFloatTimestampLocal::<flydra_types::Triggerbox>::from(
+                            local,
+                        ))
                     }
                     None => None,
                 };
@@ -724,20 +730,20 @@ pub(crate) async fn frame_process_task<'a>(
                             );
 
                             let corners = basic_frame::match_all_dynamic_fmts!(&frame, x, {
-                                let rgb: Box<
-                                    dyn formats::ImageStride<formats::pixel_format::RGB8>,
+                                let mono: Box<
+                                    dyn formats::ImageStride<formats::pixel_format::Mono8>,
                                 > = Box::new(convert_image::convert::<
                                     _,
-                                    formats::pixel_format::RGB8,
+                                    formats::pixel_format::Mono8,
                                 >(x)?);
-                                let corners = opencv_calibrate::find_chessboard_corners(
-                                    rgb.image_data(),
-                                    rgb.width(),
-                                    rgb.height(),
+                                camcal::find_chessboard_corners(
+                                    mono.image_data(),
+                                    mono.width(),
+                                    mono.height(),
+                                    mono.stride(),
                                     checkerboard_data.width as usize,
                                     checkerboard_data.height as usize,
-                                )?;
-                                corners
+                                )
                             });
 
                             let work_duration = start_time.elapsed();
@@ -1530,6 +1536,7 @@ fn make_family(family: &ci2_remote_control::TagFamily) -> apriltag::Family {
 struct AprilTagWriter {
     wtr: csv::Writer<Box<dyn std::io::Write + Send>>,
     t0: chrono::DateTime<chrono::Utc>,
+    pose_estimation: Option<(f64, apriltag::CameraIntrinsics)>,
 }
 
 #[cfg(feature = "fiducial")]
@@ -1539,6 +1546,7 @@ impl AprilTagWriter {
         camera_name: &str,
         camera_width_pixels: usize,
         camera_height_pixels: usize,
+        pose_estimation: Option<strand_cam_storetype::ApriltagPoseConfig>,
     ) -> Result<Self> {
         let now: chrono::DateTime<chrono::Utc> = chrono::Utc::now();
         let local = now.with_timezone(&chrono::Local);
@@ -1553,6 +1561,7 @@ impl AprilTagWriter {
             camera_name: camera_name.to_string(),
             camera_width_pixels,
             camera_height_pixels,
+            pose_estimation: pose_estimation.clone(),
         };
         let cfg_yaml = serde_yaml::to_string(&april_config).unwrap();
         writeln!(
@@ -1567,6 +1576,14 @@ impl AprilTagWriter {
             fd,
             "# it always has value 1. The center pixel of the detection is (h02,h12)."
         )?;
+        writeln!(
+            fd,
+            "# If pose estimation is enabled, the tag pose in camera coordinates is given by"
+        )?;
+        writeln!(
+            fd,
+            "# the row-major rotation matrix (r00,...) and translation (tx,ty,tz) in meters."
+        )?;
         writeln!(fd, "# -- start of yaml config --")?;
         for line in cfg_yaml.lines() {
             writeln!(fd, "# {}", line)?;
@@ -1575,7 +1592,21 @@ impl AprilTagWriter {
 
         let wtr = csv::Writer::from_writer(fd);
 
-        Ok(Self { wtr, t0: now })
+        let pose_estimation = pose_estimation.map(|p| {
+            let intrinsics = apriltag::CameraIntrinsics {
+                fx: p.fx,
+                fy: p.fy,
+                cx: p.cx,
+                cy: p.cy,
+            };
+            (p.tag_size_meters, intrinsics)
+        });
+
+        Ok(Self {
+            wtr,
+            t0: now,
+            pose_estimation,
+        })
     }
     fn save(
         &mut self,
@@ -1588,7 +1619,12 @@ impl AprilTagWriter {
             .num_microseconds()
             .unwrap();
         for det in detections.as_slice().iter() {
-            let atd: DetectionSerializer = to_serializer(det, frame, time_microseconds);
+            let pose = self
+                .pose_estimation
+                .as_ref()
+                .map(|(tag_size, intrinsics)| det.estimate_pose(*tag_size, intrinsics));
+            let atd: DetectionSerializer =
+                to_serializer(det, frame, time_microseconds, pose.as_ref());
             self.wtr.serialize(atd)?;
         }
         Ok(())
@@ -1621,6 +1657,8 @@ struct AprilConfig {
     camera_name: String,
     camera_width_pixels: usize,
     camera_height_pixels: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pose_estimation: Option<strand_cam_storetype::ApriltagPoseConfig>,
 }
 
 // The center pixel of the detection is (h02,h12)
@@ -1642,6 +1680,19 @@ struct DetectionSerializer {
     h21: f64,
     // no h22 because it is always 1.0
     family: String,
+    r00: Option<f64>,
+    r01: Option<f64>,
+    r02: Option<f64>,
+    r10: Option<f64>,
+    r11: Option<f64>,
+    r12: Option<f64>,
+    r20: Option<f64>,
+    r21: Option<f64>,
+    r22: Option<f64>,
+    tx: Option<f64>,
+    ty: Option<f64>,
+    tz: Option<f64>,
+    pose_error: Option<f64>,
 }
 
 #[cfg(feature = "fiducial")]
@@ -1655,8 +1706,11 @@ fn to_serializer(
     orig: &apriltag::Detection,
     frame: usize,
     time_microseconds: i64,
+    pose: Option<&apriltag::Pose>,
 ) -> DetectionSerializer {
     let h = orig.h();
+    let r = |i: usize| pose.map(|p| p.rotation[i]);
+    let t = |i: usize| pose.map(|p| p.translation[i]);
     // We are not going to save h22, so (in debug builds) let's check it meets
     // our expectations.
     debug_assert!((h[8] - 1.0).abs() < 1e-16);
@@ -1675,6 +1729,19 @@ fn to_serializer(
         h20: h[6],
         h21: h[7],
         family: orig.family_type().to_str().to_string(),
+        r00: r(0),
+        r01: r(1),
+        r02: r(2),
+        r10: r(3),
+        r11: r(4),
+        r12: r(5),
+        r20: r(6),
+        r21: r(7),
+        r22: r(8),
+        tx: t(0),
+        ty: t(1),
+        tz: t(2),
+        pose_error: pose.map(|p| p.error),
     }
 }
 
@@ -101,7 +101,6 @@ const BRAID_COOKIE_KEY: &str = "braid-cookie";
 #[cfg(feature = "flydratrax")]
 mod flydratrax_handle_msg;
 
-mod clock_model;
 mod datagram_socket;
 mod post_trigger_buffer;
 
@@ -677,6 +676,12 @@ pub struct StrandCamArgs {
     pub flydratrax_calibration_source: CalSource,
     #[cfg(feature = "fiducial")]
     pub apriltag_csv_filename_template: String,
+    /// Edge length of April tags (in meters) for tag pose estimation.
+    #[cfg(feature = "fiducial")]
+    pub apriltag_tag_size: Option<f64>,
+    /// Camera intrinsics (ROS camera info YAML) for tag pose estimation.
+    #[cfg(feature = "fiducial")]
+    pub apriltag_camera_info: Option<PathBuf>,
     #[cfg(feature = "flydratrax")]
     pub write_buffer_size_num_messages: usize,
     #[cfg(target_os = "linux")]
@@ -685,6 +690,43 @@ pub struct StrandCamArgs {
 
 pub type SaveEmptyData2dType = bool;
 
+#[cfg(feature = "fiducial")]
+fn load_apriltag_pose_config(
+    args: &StrandCamArgs,
+) -> Result<Option<strand_cam_storetype::ApriltagPoseConfig>> {
+    let tag_size_meters = match args.apriltag_tag_size {
+        Some(tag_size_meters) => tag_size_meters,
+        None => return Ok(None),
+    };
+    let camera_info_path = args
+        .apriltag_camera_info
+        .as_ref()
+        .ok_or_else(|| eyre!("April tag pose estimation requires camera intrinsics"))?;
+    let buf = std::fs::read_to_string(camera_info_path)
+        .with_context(|| format!("opening {}", camera_info_path.display()))?;
+    let ci: opencv_ros_camera::RosCameraInfo<f64> = serde_yaml::from_str(&buf)
+        .with_context(|| format!("while parsing {}", camera_info_path.display()))?;
+    let named: opencv_ros_camera::NamedIntrinsicParameters<f64> = ci
+        .try_into()
+        .map_err(|e| eyre!("invalid camera info {}: {e:?}", camera_info_path.display()))?;
+    let intrinsics = named.intrinsics;
+    if !intrinsics.distortion.is_linear() {
+        warn!(
+            "Camera intrinsics in {} include lens distortion, which is ignored for April tag \
+            pose estimation.",
+            camera_info_path.display()
+        );
+    }
+    let k = &intrinsics.k;
+    Ok(Some(strand_cam_storetype::ApriltagPoseConfig {
+        tag_size_meters,
+        fx: k[(0, 0)],
+        fy: k[(1, 1)],
+        cx: k[(0, 2)],
+        cy: k[(1, 2)],
+    }))
+}
+
 #[derive(Debug)]
 pub enum CalSource {
     /// Use circular tracking region to create calibration
@@ -708,6 +750,10 @@ impl Default for StrandCamArgs {
             #[cfg(feature = "fiducial")]
             apriltag_csv_filename_template: strand_cam_storetype::APRILTAG_CSV_TEMPLATE_DEFAULT
                 .to_string(),
+            #[cfg(feature = "fiducial")]
+            apriltag_tag_size: None,
+            #[cfg(feature = "fiducial")]
+            apriltag_camera_info: None,
             csv_save_dir: "/dev/null".to_string(),
             led_box_device_path: None,
             #[cfg(feature = "plugin-process-frame")]
@@ -1654,7 +1700,7 @@ where
                 cam.feature_enum_set("TriggerSource", "PeriodicSignal1")?;
             }
         }
-        Some(TriggerType::DeviceTimestamp) => {
+        Some(TriggerType::DeviceTimestamp(_)) => {
             // Attempt to relate camera timestamps to our clock
             tracing::info!("Reading camera timestamps to fit initial clock model.");
 
@@ -1680,14 +1726,29 @@ where
                     tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
                 }
             }
-            let (gain, offset, residuals) = clock_model::fit_time_model(&local_remote)?;
-            dbg!((gain, offset, residuals));
+            // Times are in nanoseconds.
+            let fit_cfg = clock_model::FitConfig {
+                min_residual_std: 1e3,
+                ..Default::default()
+            };
+            let fit = clock_model::fit(&local_remote, &fit_cfg)?;
+            tracing::debug!("camera clock fit: {fit:?}");
+            if fit.n_inliers < fit.n_measurements {
+                tracing::warn!(
+                    "Rejected {} of {} camera timestamp measurements.",
+                    fit.n_measurements - fit.n_inliers,
+                    fit.n_measurements
+                );
+            }
 
             let cm = rust_cam_bui_types::ClockModel {
-                gain,
-                offset,
-                residuals,
-                n_measurements: local_remote.len().try_into().unwrap(),
+                gain: fit.gain,
+                offset: fit.offset,
+                residuals: fit.sum_sq_residuals,
+                n_measurements: fit.n_measurements.try_into().unwrap(),
+                n_inliers: Some(fit.n_inliers.try_into().unwrap()),
+                residual_std: Some(fit.residual_std),
+                prediction_std: Some(fit.prediction_std),
             };
 
             let device_timestamp: u64 = tmp_debug_device_timestamp.unwrap().try_into().unwrap();
@@ -1905,7 +1966,10 @@ where
     let apriltag_state = None;
 
     #[cfg(feature = "fiducial")]
-    let apriltag_state = Some(ApriltagState::default());
+    let apriltag_state = Some(ApriltagState {
+        pose_estimation: load_apriltag_pose_config(&args)?,
+        ..Default::default()
+    });
 
     let im_ops_state = ImOpsState::default();
 
@@ -3142,8 +3206,23 @@ where
 
                             let size =
                                 camcal::PixelSize::new(image_width as usize, image_height as usize);
-                            match camcal::compute_intrinsics::<f64>(size, &goodcorners) {
-                                Ok(intrinsics) => {
+                            let opts = camcal::CalibrationOptions::default();
+                            match camcal::compute_intrinsics_with_report::<f64>(
+                                size,
+                                &goodcorners,
+                                &opts,
+                            ) {
+                                Ok(cal) => {
+                                    let n_rejected =
+                                        cal.per_image.iter().filter(|e| e.rejected).count();
+                                    info!(
+                                        "mean reprojection error {:.3} pixels, {} of {} \
+                                        images rejected as outliers",
+                                        cal.mean_reprojection_distance_pixels,
+                                        n_rejected,
+                                        cal.per_image.len()
+                                    );
+                                    let intrinsics = cal.intrinsics;
                                     info!("got calibrated intrinsics: {:?}", intrinsics);
 
                                     // Convert from mvg to ROS format.
@@ -3361,11 +3440,10 @@ where
 
             match tokio::time::timeout(std::time::Duration::from_millis(50), reader.next()).await {
                 Ok(Some(Ok(msg))) => match msg {
-                    led_box_comms::FromDevice::VersionResponse(led_box_comms::COMM_VERSION) => {
-                        info!(
-                            "Connected to firmware version {}",
-                            led_box_comms::COMM_VERSION
-                        );
+                    led_box_comms::FromDevice::VersionResponse(found)
+                        if led_box_comms::is_compatible_version(found) =>
+                    {
+                        info!("Connected to firmware version {found}");
                     }
                     msg => {
                         eyre::bail!("Unexpected response from LED Box {:?}. Is your firmware version correct? (Needed version: {})",
@@ -3638,6 +3716,22 @@ impl FinalMp4RecordingConfig {
                     preset,
                 })
             }
+            CodecSelection::H264LessAvcMono12 => {
+                if shared.mp4_bitrate != ci2_remote_control::BitrateSelection::BitrateUnlimited {
+                    warn!("ignoring mp4 bitrate with lossless LessAVC codec");
+                }
+                Mp4Codec::H264LessAvcMono12
+            }
+            CodecSelection::Av1Rav1e => {
+                let bitrate = match shared.mp4_bitrate {
+                    ci2_remote_control::BitrateSelection::BitrateUnlimited => None,
+                    _ => Some(bitrate_to_u32(&shared.mp4_bitrate)),
+                };
+                Mp4Codec::Av1Rav1e(ci2_remote_control::Rav1eOptions {
+                    bitrate,
+                    ..Default::default()
+                })
+            }
         };
         // See https://github.com/chronotope/chrono/issues/576
         let fixed = chrono::DateTime::<chrono::FixedOffset>::from_naive_utc_and_offset(
@@ -0,0 +1,12 @@
+use color_eyre::eyre::Result;
+
+lazy_static::lazy_static! {
+    static ref ARAVIS_MODULE: ci2_aravis::WrappedModule = ci2_aravis::new_module().unwrap();
+}
+
+fn main() -> Result<()> {
+    let guard = ci2_aravis::make_singleton_guard(&&*ARAVIS_MODULE)?;
+    let mymod = ci2_async::into_threaded_async(&*ARAVIS_MODULE, &guard);
+    strand_cam::cli_app::cli_main(mymod, env!("CARGO_PKG_NAME"))?;
+    Ok(())
+}
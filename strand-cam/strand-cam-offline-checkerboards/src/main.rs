@@ -68,24 +68,27 @@ fn main() -> Result<()> {
     let mut image_height = 0;
 
     let mut collected_corners = Vec::with_capacity(fnames.len());
+    let mut corner_fnames = Vec::with_capacity(fnames.len());
     for fname in fnames.iter() {
         info!("{}", fname.display());
         let img = image::open(&fname).with_context(|| format!("Opening {}", fname.display()))?;
         let (w, h) = img.dimensions();
         image_width = w;
         image_height = h;
-        let rgb = img.to_rgb8().into_raw();
+        let gray = img.to_luma8().into_raw();
 
-        let corners = opencv_calibrate::find_chessboard_corners(
-            &rgb,
+        let corners = camcal::find_chessboard_corners(
+            &gray,
             w,
             h,
+            w as usize,
             checkerboard_data.width as usize,
             checkerboard_data.height as usize,
-        )?;
+        );
         info!("    {:?} corners.", corners.as_ref().map(|x| x.len()));
         if let Some(corners) = corners {
             collected_corners.push(corners);
+            corner_fnames.push(fname);
         }
     }
 
@@ -101,13 +104,22 @@ fn main() -> Result<()> {
         .collect();
 
     let size = camcal::PixelSize::new(image_width as usize, image_height as usize);
-    match camcal::compute_intrinsics_with_raw_opencv::<f64>(size, &goodcorners) {
-        Ok(raw_opencv_cal) => {
-            let intrinsics = camcal::convert_to_cam_geom::<f64>(&raw_opencv_cal);
-
+    let opts = camcal::CalibrationOptions::default();
+    match camcal::compute_intrinsics_with_report::<f64>(size, &goodcorners, &opts) {
+        Ok(cal) => {
+            let intrinsics = cal.intrinsics;
+
+            for (fname, err) in corner_fnames.iter().zip(cal.per_image.iter()) {
+                info!(
+                    "{}: mean reprojection error {:.3} pixels{}",
+                    fname.display(),
+                    err.mean_distance_pixels,
+                    if err.rejected { " (rejected)" } else { "" }
+                );
+            }
             info!(
                 "Mean reprojection error: {}",
-                raw_opencv_cal.mean_reprojection_distance_pixels
+                cal.mean_reprojection_distance_pixels
             );
             info!("got calibrated intrinsics: {:?}", intrinsics);
 
@@ -141,7 +153,7 @@ fn main() -> Result<()> {
                         # Mean reprojection distance: {:.2}\n",
                         env!["CARGO_PKG_NAME"],
                         local,
-                        raw_opencv_cal.mean_reprojection_distance_pixels
+                        cal.mean_reprojection_distance_pixels
                     )
                     .as_bytes(),
                 )?;
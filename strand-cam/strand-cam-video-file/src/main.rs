@@ -0,0 +1,12 @@
+use color_eyre::eyre::Result;
+
+lazy_static::lazy_static! {
+    static ref VIDEO_FILE_MODULE: ci2_video_file::WrappedModule = ci2_video_file::new_module().unwrap();
+}
+
+fn main() -> Result<()> {
+    let guard = ci2_video_file::make_singleton_guard(&&*VIDEO_FILE_MODULE)?;
+    let mymod = ci2_async::into_threaded_async(&*VIDEO_FILE_MODULE, &guard);
+    strand_cam::cli_app::cli_main(mymod, env!("CARGO_PKG_NAME"))?;
+    Ok(())
+}
@@ -1389,11 +1389,13 @@ trait HasAvail {
 
 impl HasAvail for ServerState {
     fn available_codecs(&self) -> Vec<CodecSelection> {
-        if !self.cuda_devices.is_empty() && self.is_nvenc_functioning {
+        let mut result = if !self.cuda_devices.is_empty() && self.is_nvenc_functioning {
             vec![CodecSelection::H264Nvenc, CodecSelection::H264OpenH264]
         } else {
             vec![CodecSelection::H264OpenH264]
-        }
+        };
+        result.extend([CodecSelection::H264LessAvcMono12, CodecSelection::Av1Rav1e]);
+        result
     }
 }
 